@@ -0,0 +1,388 @@
+//! Conformance fixtures: real games, and TAS runs of them, which tests can check the decompiler and emulator against.
+//!
+//! The fixtures themselves aren't checked in. `fixtures.toml` lists where to download each one and its SHA-256,
+//! and `cargo run -p conformance -- fetch` downloads them into `conformance/fixtures`, or wherever the
+//! `OPENGMK_FIXTURES` environment variable points. Tests look fixtures up with `games()`, `replays()` or `locate()`,
+//! and skip whatever hasn't been downloaded, so the test suite still passes without any of them.
+//! Tests which should run over every game use `all_games()`, which always includes the games in `synthetic`.
+
+pub mod synthetic;
+
+pub use gm8exe::sha256;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::{Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// An entry in the manifest.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// Unique name which tests refer to the fixture by.
+    pub name: String,
+    /// File name it's stored under in the fixtures directory.
+    pub file: String,
+    /// Where to download it from.
+    pub url: String,
+    /// SHA-256 of the file, in lowercase hex.
+    pub sha256: String,
+    /// For replays, the name of the game fixture it's a run of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game: Option<String>,
+    /// Why it's alright to redistribute, such as the licence or the author's permission.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// The whole manifest, which is a list of games and a list of .gmtas replays.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default, rename = "game", skip_serializing_if = "Vec::is_empty")]
+    pub games: Vec<Entry>,
+    #[serde(default, rename = "replay", skip_serializing_if = "Vec::is_empty")]
+    pub replays: Vec<Entry>,
+}
+
+/// A fixture which has been downloaded.
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// The top-level numbers from a JSON report written by `gm8emulator --coverage-json`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct CoverageSummary {
+    pub functions_called: u64,
+    pub unsupported_functions: u64,
+    pub unsupported_calls: u64,
+}
+
+impl CoverageSummary {
+    /// Reads the summary out of a report, ignoring everything else in it.
+    pub fn parse(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+}
+
+/// The checked-in manifest.
+pub fn manifest_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures.toml")
+}
+
+/// Where fixtures are downloaded to.
+pub fn fixtures_dir() -> PathBuf {
+    match env::var_os("OPENGMK_FIXTURES") {
+        Some(dir) => dir.into(),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures"),
+    }
+}
+
+/// Where the `coverage` command keeps each game's latest report.
+pub fn coverage_dir() -> PathBuf {
+    fixtures_dir().join("coverage")
+}
+
+/// Where the `verify` command keeps the final savestate and output of each replay, and the hash it expects the
+/// final savestate to have.
+pub fn verify_dir() -> PathBuf {
+    fixtures_dir().join("verify")
+}
+
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+impl Manifest {
+    /// Parses and validates a manifest.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let manifest: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Loads the checked-in manifest.
+    pub fn load() -> Result<Self, String> {
+        let path = manifest_path();
+        let text = fs::read_to_string(&path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Checks everything the schema can't: names and files are unique, files are plain file names,
+    /// hashes are well-formed, and every replay belongs to a game in the manifest.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        let mut files = HashSet::new();
+        for entry in self.entries() {
+            let name = &entry.name;
+            if name.is_empty() || !names.insert(name.as_str()) {
+                return Err(format!("fixture name \"{}\" is empty or used more than once", name))
+            }
+            if entry.file.is_empty()
+                || entry.file.starts_with('.')
+                || entry.file.contains(&['/', '\\', ':'][..])
+                || !files.insert(entry.file.to_ascii_lowercase())
+            {
+                return Err(format!("fixture {} has an invalid or duplicate file name \"{}\"", name, entry.file))
+            }
+            if !entry.url.starts_with("https://") && !entry.url.starts_with("http://") {
+                return Err(format!("fixture {} has a URL which isn't http(s): \"{}\"", name, entry.url))
+            }
+            if !is_sha256(&entry.sha256) {
+                return Err(format!("fixture {} has an invalid sha256 \"{}\"", name, entry.sha256))
+            }
+        }
+        if let Some(entry) = self.games.iter().find(|x| x.game.is_some()) {
+            return Err(format!("game fixture {} can't have a \"game\" field", entry.name))
+        }
+        for entry in self.replays.iter() {
+            match &entry.game {
+                Some(game) if self.games.iter().any(|x| &x.name == game) => (),
+                Some(game) => return Err(format!("replay fixture {} is for unknown game \"{}\"", entry.name, game)),
+                None => return Err(format!("replay fixture {} doesn't say which game it's for", entry.name)),
+            }
+        }
+        Ok(())
+    }
+
+    /// All games and replays.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.games.iter().chain(self.replays.iter())
+    }
+
+    /// Finds an entry by name.
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries().find(|x| x.name == name)
+    }
+}
+
+impl Entry {
+    /// Where this is, or would be, in the fixtures directory.
+    pub fn path(&self) -> PathBuf {
+        fixtures_dir().join(&self.file)
+    }
+}
+
+fn available(entries: &[Entry]) -> Vec<Fixture> {
+    let mut found = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        if path.is_file() {
+            found.push(Fixture { name: entry.name.clone(), path });
+        } else {
+            eprintln!("skipping fixture {}: not downloaded (run `cargo run -p conformance -- fetch`)", entry.name);
+        }
+    }
+    found
+}
+
+/// All the game fixtures which have been downloaded. Missing ones are mentioned on stderr and left out.
+/// Panics if the manifest is invalid, since that's a mistake in the repository rather than a missing download.
+pub fn games() -> Vec<Fixture> {
+    available(&Manifest::load().unwrap().games)
+}
+
+/// All the replay fixtures which have been downloaded, like `games()`.
+pub fn replays() -> Vec<Fixture> {
+    available(&Manifest::load().unwrap().replays)
+}
+
+/// Every game to test against, as (name, exe): the synthetic games first, then each downloaded game fixture.
+/// This is never empty, so a test which loops over it always checks something.
+pub fn all_games() -> Vec<(String, Vec<u8>)> {
+    let mut all = vec![("synthetic-gm80".to_string(), synthetic::exe())];
+    for game in games() {
+        let exe = fs::read(&game.path).unwrap_or_else(|e| panic!("couldn't read {}: {}", game.path.display(), e));
+        all.push((game.name, exe));
+    }
+    all
+}
+
+/// Finds a single fixture by name, returning None if it hasn't been downloaded.
+/// Panics if the manifest doesn't have a fixture with that name.
+pub fn locate(name: &str) -> Option<PathBuf> {
+    let manifest = Manifest::load().unwrap();
+    let entry = manifest.get(name).unwrap_or_else(|| panic!("no fixture called {} in the manifest", name));
+    available(std::slice::from_ref(entry)).pop().map(|x| x.path)
+}
+
+/// Compares the SHA-256 of a replay's final savestate with the one in `expected`, which is written by the first run.
+/// Returns whether this was the first run, or an error saying what changed if the hashes are different.
+pub fn check_state(hash: &str, expected: &Path) -> Result<bool, String> {
+    match fs::read_to_string(expected) {
+        Ok(old) if old.trim() == hash => Ok(false),
+        Ok(old) => Err(format!(
+            "the final state is different (sha256 {}, expected {}), delete {} if that's intended",
+            hash,
+            old.trim(),
+            expected.display()
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => fs::write(expected, format!("{}\n", hash))
+            .map(|()| true)
+            .map_err(|e| format!("couldn't write {}: {}", expected.display(), e)),
+        Err(e) => Err(format!("couldn't read {}: {}", expected.display(), e)),
+    }
+}
+
+/// Calls `f` on each item on up to `jobs` threads at once, passing each result to `done` as soon as it's ready.
+pub fn parallel<T: Send, R: Send>(items: Vec<T>, jobs: usize, f: impl Fn(T) -> R + Sync, mut done: impl FnMut(R)) {
+    let queue = Mutex::new(items.into_iter());
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            let sender = sender.clone();
+            let (queue, f) = (&queue, &f);
+            scope.spawn(move || {
+                // the lock has to be released before f runs, hence the separate statement
+                loop {
+                    let item = queue.lock().unwrap().next();
+                    match item {
+                        Some(item) => sender.send(f(item)).unwrap(),
+                        None => break,
+                    }
+                }
+            });
+        }
+        drop(sender);
+        receiver.iter().for_each(&mut done);
+    });
+}
+
+/// Runs a command with its output going to the file at `log`, and kills it if it's still running after `timeout`.
+/// Returns None if it had to be killed.
+pub fn run_with_timeout(command: &mut Command, log: &Path, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let log = File::create(log)?;
+    let mut child = command.stdin(Stdio::null()).stdout(log.try_clone()?).stderr(log).spawn()?;
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status))
+        }
+        if start.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None)
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_state, parallel, run_with_timeout, CoverageSummary, Manifest};
+    use std::{
+        process::Command,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    const HASH: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn game(name: &str, file: &str) -> String {
+        let url = format!("https://example.com/{}", file);
+        format!("[[game]]\nname = \"{}\"\nfile = \"{}\"\nurl = \"{}\"\nsha256 = \"{}\"\n", name, file, url, HASH)
+    }
+
+    #[test]
+    fn checked_in_manifest_is_valid() {
+        Manifest::load().unwrap();
+    }
+
+    #[test]
+    fn validation() {
+        let replay = format!(
+            "[[replay]]\nname = \"run\"\nfile = \"run.gmtas\"\nurl = \"https://example.com/run.gmtas\"\n\
+             sha256 = \"{}\"\ngame = \"a\"\n",
+            HASH
+        );
+        let manifest = Manifest::parse(&(game("a", "a.exe") + &replay)).unwrap();
+        assert_eq!(manifest.games.len(), 1);
+        assert_eq!(manifest.get("run").unwrap().game.as_deref(), Some("a"));
+        assert!(Manifest::parse("").unwrap().entries().next().is_none());
+
+        // duplicate names or files, paths, bad hashes, orphaned replays, unknown fields
+        assert!(Manifest::parse(&(game("a", "a.exe") + &game("a", "b.exe"))).is_err());
+        assert!(Manifest::parse(&(game("a", "a.exe") + &game("b", "A.EXE"))).is_err());
+        assert!(Manifest::parse(&game("a", "../a.exe")).is_err());
+        assert!(Manifest::parse(&game("a", "dir/a.exe")).is_err());
+        assert!(Manifest::parse(&game("a", "a.exe").replace(HASH, &HASH.to_uppercase())).is_err());
+        assert!(Manifest::parse(&game("a", "a.exe").replace("https", "ftp")).is_err());
+        assert!(Manifest::parse(&replay).is_err());
+        assert!(Manifest::parse(&(game("a", "a.exe") + "size = 5\n")).is_err());
+    }
+
+    #[test]
+    fn coverage_summary() {
+        let json = "{\n  \"version\": 1,\n  \"functions_called\": 120,\n  \"unsupported_functions\": 3,\n  \
+                    \"unsupported_calls\": 14,\n  \"parse_errors\": 0,\n  \"functions\": [],\n  \"errors\": []\n}\n";
+        let summary = CoverageSummary { functions_called: 120, unsupported_functions: 3, unsupported_calls: 14 };
+        assert_eq!(CoverageSummary::parse(json), Some(summary));
+        assert_eq!(CoverageSummary::parse(&json.replace("14", "x")), None);
+        assert_eq!(CoverageSummary::parse("{}"), None);
+    }
+
+    #[test]
+    fn parallel_jobs() {
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let mut results = Vec::new();
+        parallel(
+            (0..20).collect(),
+            3,
+            |i: u32| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+                i * 2
+            },
+            |x| results.push(x),
+        );
+        results.sort_unstable();
+        assert_eq!(results, (0..20).map(|i| i * 2).collect::<Vec<_>>());
+        assert!((1..=3).contains(&most.load(Ordering::SeqCst)));
+    }
+
+    #[test]
+    fn expected_states() {
+        let expected = std::env::temp_dir().join(format!("opengmk_conformance_{}.sha256", std::process::id()));
+        let _ = std::fs::remove_file(&expected);
+        assert_eq!(check_state(HASH, &expected), Ok(true));
+        assert_eq!(check_state(HASH, &expected), Ok(false));
+        let other = HASH.replace('a', "b");
+        let error = check_state(&other, &expected).unwrap_err();
+        assert!(error.contains(&other) && error.contains(HASH), "{}", error);
+        // a different state doesn't replace the expected one
+        assert_eq!(check_state(HASH, &expected), Ok(false));
+        std::fs::remove_file(&expected).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeouts() {
+        let log = std::env::temp_dir().join(format!("opengmk_conformance_{}.log", std::process::id()));
+        let run = |script: &str| {
+            run_with_timeout(Command::new("sh").args(["-c", script]), &log, Duration::from_millis(200)).unwrap()
+        };
+        assert_eq!(run("echo hello; exit 3").and_then(|x| x.code()), Some(3));
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "hello\n");
+        assert!(run("sleep 10").is_none());
+        std::fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let manifest = Manifest::parse(&(game("a", "a.exe") + &game("b", "b.exe"))).unwrap();
+        let text = toml::to_string(&manifest).unwrap();
+        assert_eq!(Manifest::parse(&text).unwrap(), manifest);
+    }
+}
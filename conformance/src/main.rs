@@ -0,0 +1,297 @@
+use conformance::{
+    check_state, coverage_dir, fixtures_dir, games, manifest_path, parallel, run_with_timeout, sha256, verify_dir,
+    CoverageSummary, Entry, Fixture, Manifest,
+};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    process::{self, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn usage(opts: &getopts::Options) -> String {
+    opts.usage(
+        "Usage: cargo run -p conformance -- COMMAND [options]\n\n\
+         Commands:\n    \
+         fetch [NAME...]   download fixtures (all of them if no names are given) and verify them\n    \
+         check             validate the manifest and verify any fixtures which have been downloaded\n    \
+         add FILE          hash a local file and add it to the manifest, as a replay if it's a .gmtas\n    \
+         coverage EMU      run the emulator EMU with --coverage on every downloaded game and compare to the last run\n    \
+         verify EMU        play every downloaded replay in EMU, several at once, checking each ends as before",
+    )
+}
+
+// Hashes a file, returning None if it doesn't exist.
+fn hash_file(path: &Path) -> Result<Option<String>, String> {
+    match File::open(path) {
+        Ok(file) => sha256::hash(file).map(Some).map_err(|e| format!("couldn't read {}: {}", path.display(), e)),
+        Err(_) => Ok(None),
+    }
+}
+
+// Downloads a fixture unless it's already there, then checks its hash.
+fn fetch(entry: &Entry) -> Result<(), String> {
+    let path = entry.path();
+    if hash_file(&path)?.as_ref() == Some(&entry.sha256) {
+        println!("{}: already downloaded", entry.name);
+        return Ok(())
+    }
+
+    println!("{}: downloading {}", entry.name, entry.url);
+    let mut part = path.clone().into_os_string();
+    part.push(".part");
+    let status = Command::new("curl")
+        .args(["--fail", "--location", "--silent", "--show-error", "--output"])
+        .arg(&part)
+        .arg(&entry.url)
+        .status()
+        .map_err(|e| format!("couldn't run curl, which is needed for downloading: {}", e))?;
+    if !status.success() {
+        let _ = fs::remove_file(&part);
+        return Err(format!("download failed ({})", status))
+    }
+
+    let hash = hash_file(part.as_ref())?.unwrap_or_default();
+    if hash != entry.sha256 {
+        let _ = fs::remove_file(&part);
+        return Err(format!("downloaded file has sha256 {}, but the manifest says {}", hash, entry.sha256))
+    }
+    fs::rename(&part, &path).map_err(|e| format!("couldn't move download into place: {}", e))
+}
+
+// Adds a local file to the manifest and copies it into the fixtures directory.
+fn add(file: &Path, name: String, url: String, game: Option<String>, note: Option<String>) -> Result<(), String> {
+    let mut manifest = Manifest::load()?;
+    let entry = Entry {
+        name,
+        file: file.file_name().and_then(|x| x.to_str()).ok_or("the file needs a UTF-8 file name")?.into(),
+        url,
+        sha256: hash_file(file)?.ok_or_else(|| format!("{} doesn't exist", file.display()))?,
+        game,
+        note,
+    };
+    let is_replay = entry.file.to_ascii_lowercase().ends_with(".gmtas");
+    let single = if is_replay {
+        manifest.replays.push(entry.clone());
+        Manifest { replays: vec![entry.clone()], ..Default::default() }
+    } else {
+        manifest.games.push(entry.clone());
+        Manifest { games: vec![entry.clone()], ..Default::default() }
+    };
+    manifest.validate()?;
+
+    // Append to the manifest rather than rewriting it, so that comments and formatting are kept
+    let text = toml::to_string(&single).map_err(|e| e.to_string())?;
+    let path = manifest_path();
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| write!(f, "\n{}", text))
+        .map_err(|e| format!("couldn't write {}: {}", path.display(), e))?;
+
+    // the file may already be in the fixtures directory, under a path which is spelled differently
+    let dest = entry.path();
+    let same_file = match (fs::canonicalize(file), fs::canonicalize(&dest)) {
+        (Ok(file), Ok(dest)) => file == dest,
+        _ => false,
+    };
+    if !same_file {
+        fs::copy(file, &dest).map_err(|e| format!("couldn't copy to {}: {}", dest.display(), e))?;
+    }
+    println!("added {} {} ({})", if is_replay { "replay" } else { "game" }, entry.name, entry.sha256);
+    Ok(())
+}
+
+// Runs the emulator's coverage report on a game, returning the new summary and the one from the last run, if any.
+fn coverage(emulator: &Path, game: &Fixture) -> Result<(CoverageSummary, Option<CoverageSummary>), String> {
+    let report = coverage_dir().join(format!("{}.json", game.name));
+    let previous = fs::read_to_string(&report).ok().and_then(|x| CoverageSummary::parse(&x));
+    let output = Command::new(emulator)
+        .arg("--coverage")
+        .arg("--coverage-json")
+        .arg(&report)
+        .arg(&game.path)
+        .output()
+        .map_err(|e| format!("couldn't run {}: {}", emulator.display(), e))?;
+    if !output.status.success() {
+        return Err(format!("emulator failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+    let json = fs::read_to_string(&report).map_err(|e| format!("couldn't read {}: {}", report.display(), e))?;
+    let summary =
+        CoverageSummary::parse(&json).ok_or_else(|| format!("{} isn't a coverage report", report.display()))?;
+    Ok((summary, previous))
+}
+
+// Formats a number with how much it's changed since the last run.
+fn trend(now: u64, before: Option<u64>) -> String {
+    match before {
+        Some(before) if before != now => format!("{} (was {})", now, before),
+        _ => now.to_string(),
+    }
+}
+
+// Plays a replay to the end headless and saves the final state, then checks it's the same as on the first run.
+// Returns whether this was the first run, in which case the state has nothing to be compared to.
+fn verify(emulator: &Path, replay: &Entry, game: &Entry, timeout: Duration) -> Result<bool, String> {
+    // names are unique, where the names of the replay files without their extensions might not be
+    let output = |extension: &str| verify_dir().join(format!("{}.{}", replay.name, extension));
+    let (bin, log) = (output("bin"), output("log"));
+    let _ = fs::remove_file(&bin);
+    let mut command = Command::new(emulator);
+    command.arg("--headless").arg("-f").arg(replay.path()).arg("-o").arg(&bin).arg(game.path());
+    match run_with_timeout(&mut command, &log, timeout) {
+        Ok(Some(status)) if status.success() => (),
+        Ok(Some(status)) => return Err(format!("emulator failed ({}), see {}", status, log.display())),
+        Ok(None) => return Err(format!("timed out after {}s, see {}", timeout.as_secs(), log.display())),
+        Err(e) => return Err(format!("couldn't run {}: {}", emulator.display(), e)),
+    }
+    let state = hash_file(&bin)?.ok_or_else(|| format!("no savestate was written, see {}", log.display()))?;
+    check_state(&state, &output("sha256"))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut opts = getopts::Options::new();
+    opts.optflag("h", "help", "print this help message")
+        .optopt("n", "name", "name of the fixture being added (default: the file name)", "NAME")
+        .optopt("u", "url", "where the fixture being added can be downloaded from", "URL")
+        .optopt("g", "game", "which game fixture the replay being added is for", "NAME")
+        .optopt("", "note", "why the fixture being added can be redistributed", "TEXT")
+        .optopt("j", "jobs", "how many replays verify plays at once (default: one per CPU)", "N")
+        .optopt("", "timeout", "seconds verify gives each replay before stopping it (default 600)", "SECS");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, usage(&opts));
+            process::exit(1);
+        },
+    };
+    if matches.opt_present("h") || matches.free.is_empty() {
+        println!("{}", usage(&opts));
+        return
+    }
+
+    let result = match (matches.free[0].as_str(), &matches.free[1..]) {
+        ("fetch", names) => Manifest::load().and_then(|manifest| {
+            if let Some(name) = names.iter().find(|x| manifest.get(x).is_none()) {
+                return Err(format!("no fixture called {} in the manifest", name))
+            }
+            fs::create_dir_all(fixtures_dir()).map_err(|e| format!("couldn't create fixtures directory: {}", e))?;
+            let mut failed = 0;
+            for entry in manifest.entries().filter(|x| names.is_empty() || names.contains(&x.name)) {
+                if let Err(e) = fetch(entry) {
+                    eprintln!("{}: {}", entry.name, e);
+                    failed += 1;
+                }
+            }
+            if failed == 0 { Ok(()) } else { Err(format!("{} fixtures couldn't be fetched", failed)) }
+        }),
+        ("check", []) => Manifest::load().and_then(|manifest| {
+            let mut bad = 0;
+            for entry in manifest.entries() {
+                match hash_file(&entry.path())? {
+                    Some(hash) if hash == entry.sha256 => println!("{}: ok", entry.name),
+                    Some(hash) => {
+                        println!("{}: sha256 is {}, but the manifest says {}", entry.name, hash, entry.sha256);
+                        bad += 1;
+                    },
+                    None => println!("{}: not downloaded", entry.name),
+                }
+            }
+            if bad == 0 { Ok(()) } else { Err(format!("{} fixtures don't match the manifest", bad)) }
+        }),
+        ("add", [file]) => {
+            let file = Path::new(file);
+            let name = matches.opt_str("n").or_else(|| file.file_stem().map(|x| x.to_string_lossy().into_owned()));
+            match (name, matches.opt_str("u")) {
+                (Some(name), Some(url)) => fs::create_dir_all(fixtures_dir())
+                    .map_err(|e| format!("couldn't create fixtures directory: {}", e))
+                    .and_then(|()| add(file, name, url, matches.opt_str("g"), matches.opt_str("note"))),
+                _ => Err("add needs a --url, so that other people can download the fixture".into()),
+            }
+        },
+        ("coverage", [emulator]) => fs::create_dir_all(coverage_dir())
+            .map_err(|e| format!("couldn't create coverage directory: {}", e))
+            .and_then(|()| {
+                let mut failed = 0;
+                for game in games() {
+                    match coverage(Path::new(emulator), &game) {
+                        Ok((now, before)) => println!(
+                            "{}: {} of {} functions unsupported, {} calls to them",
+                            game.name,
+                            trend(now.unsupported_functions, before.map(|x| x.unsupported_functions)),
+                            now.functions_called,
+                            trend(now.unsupported_calls, before.map(|x| x.unsupported_calls)),
+                        ),
+                        Err(e) => {
+                            eprintln!("{}: {}", game.name, e);
+                            failed += 1;
+                        },
+                    }
+                }
+                if failed == 0 { Ok(()) } else { Err(format!("{} games couldn't be checked", failed)) }
+            }),
+        ("verify", [emulator]) => Manifest::load().and_then(|manifest| {
+            let jobs = match matches.opt_get::<usize>("j") {
+                Ok(jobs) => jobs.unwrap_or_else(|| thread::available_parallelism().map(|x| x.get()).unwrap_or(1)),
+                Err(e) => return Err(format!("bad --jobs: {}", e)),
+            };
+            let timeout = match matches.opt_get_default("timeout", 600) {
+                Ok(secs) => Duration::from_secs(secs),
+                Err(e) => return Err(format!("bad --timeout: {}", e)),
+            };
+            fs::create_dir_all(verify_dir()).map_err(|e| format!("couldn't create verify directory: {}", e))?;
+            let mut runs = Vec::new();
+            for replay in manifest.replays.iter() {
+                let game = replay.game.as_deref().and_then(|x| manifest.get(x)).filter(|x| x.path().is_file());
+                match game {
+                    Some(game) if replay.path().is_file() => runs.push((replay, game)),
+                    _ => println!("{}: skipped, the replay or its game hasn't been downloaded", replay.name),
+                }
+            }
+
+            let start = Instant::now();
+            let (mut failed, mut busy) = (0, Duration::ZERO);
+            let count = runs.len();
+            parallel(
+                runs,
+                jobs,
+                |(replay, game)| {
+                    let start = Instant::now();
+                    (replay, verify(Path::new(emulator), replay, game, timeout), start.elapsed())
+                },
+                |(replay, result, time)| {
+                    busy += time;
+                    match result {
+                        Ok(false) => println!("{}: ok ({:.1}s)", replay.name, time.as_secs_f64()),
+                        Ok(true) => println!(
+                            "{}: ok ({:.1}s), first run so its final state has been kept to compare against",
+                            replay.name,
+                            time.as_secs_f64()
+                        ),
+                        Err(e) => {
+                            println!("{}: {}", replay.name, e);
+                            failed += 1;
+                        },
+                    }
+                },
+            );
+            println!(
+                "played {} replays in {:.1}s with {} jobs ({:.1}s if played one at a time)",
+                count,
+                start.elapsed().as_secs_f64(),
+                jobs,
+                busy.as_secs_f64(),
+            );
+            if failed == 0 { Ok(()) } else { Err(format!("{} replays failed", failed)) }
+        }),
+        _ => Err(usage(&opts)),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
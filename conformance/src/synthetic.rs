@@ -0,0 +1,164 @@
+//! Small games built from scratch in the GM8.0 format, so that tests which run over every fixture always have
+//! something to check, even when nothing has been downloaded.
+//!
+//! They're written the way GameMaker 8.0 writes them, encryption and all, but hold only a handful of assets:
+//! a script, an object, a room, and a deleted slot in each of the other asset lists.
+
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::Write;
+
+/// Where the exe says to start looking for the gamedata header.
+const HEADER_START: usize = 0x144AC4;
+
+/// The script in the synthetic game, which is clean as far as `gm8decompiler --scan` is concerned.
+pub const SCRIPT: &str = "var i;\r\nfor (i = 0; i < 10; i += 1) {\r\n    score += i;\r\n}";
+
+/// A GM8.0 exe holding `gamedata()`, with just enough of a PE header and the runner's loading sequence for it
+/// to be recognised. There are no resources, so there's no icon or version info.
+pub fn exe() -> Vec<u8> {
+    let mut exe = vec![0u8; HEADER_START];
+    exe[0..2].copy_from_slice(b"MZ");
+    exe[0x3C..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+    exe[0x80..0x88].copy_from_slice(b"PE\0\0\x4C\x01\x00\x00");
+    exe[0xA49BE..0xA49CB].copy_from_slice(&[0x8B, 0x45, 0xF4, 0xE8, 0x2A, 0xBD, 0xFD, 0xFF, 0x3D, 0x91, 0xD5, 0x12, 0]);
+    exe[0xA49CB..0xA49D1].copy_from_slice(&[0x0F, 0x85, 0x18, 0x01, 0x00, 0x00]);
+    exe[0xA49E2..0xA49EE].copy_from_slice(&[0x8B, 0xC6, 0xE8, 0x07, 0xBD, 0xFD, 0xFF, 0x3D, 0x20, 0x03, 0, 0]);
+    exe[0xA49EE..0xA49F4].copy_from_slice(&[0x0F, 0x85, 0xF5, 0x00, 0x00, 0x00]);
+    exe[0x144AC0..0x144AC4].copy_from_slice(&(HEADER_START as u32).to_le_bytes());
+    exe.extend_from_slice(&gamedata());
+    exe
+}
+
+/// The gamedata on its own, starting with the header, as `gm8exe::reader::from_gamedata` expects.
+pub fn gamedata() -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u32s(&mut data, &[1234321, 800, 0, 0]);
+    put_block(&mut data, &deflate(&settings()));
+    put_string(&mut data, "D3DX8.dll");
+    put_block(&mut data, b"not really a dll");
+
+    // the swap table goes between two lots of garbage, and everything after the length is encrypted with it
+    let swap_table = (0..=255u8).map(|i| i.wrapping_mul(7).wrapping_add(3)).collect::<Vec<_>>();
+    put_u32s(&mut data, &[2, 3]);
+    data.extend_from_slice(&[0x55; 8]);
+    data.extend_from_slice(&swap_table);
+    data.extend_from_slice(&[0xAA; 12]);
+    let assets = assets();
+    put_u32s(&mut data, &[assets.len() as u32]);
+    let pos = data.len();
+    data.extend_from_slice(&assets);
+    encrypt(&mut data, pos, &swap_table);
+    data
+}
+
+// Reverses gm8exe::gamedata::gm80::decrypt: the swaps are made in the opposite order, then each byte is
+// substituted in terms of the encrypted byte before it.
+fn encrypt(data: &mut [u8], pos: usize, swap_table: &[u8]) {
+    for i in pos..data.len() {
+        let b = (i - usize::from(swap_table[(i - pos) & 0xFF])).max(pos);
+        data.swap(i, b);
+    }
+    for i in pos..data.len() {
+        data[i] = swap_table[usize::from(data[i].wrapping_add(data[i - 1]).wrapping_add((i - pos) as u8))];
+    }
+}
+
+fn settings() -> Vec<u8> {
+    let mut settings = Vec::new();
+    // fullscreen through freeze_on_lose_focus, mostly off, with a scaling of -1 and a black clear colour
+    put_u32s(&mut settings, &[0, 0, 0, 1, u32::MAX, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 0, 0, 0]);
+    // no loading bar or custom load image, then transparency, the progress bar and error settings
+    put_u32s(&mut settings, &[0, 0, 0, 255, 1, 1, 0, 0, 0]);
+    settings
+}
+
+fn assets() -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u32s(&mut data, &[0, 1, 4242]); // no garbage, pro flag, game ID
+    put_u32s(&mut data, &[1, 2, 3, 4]); // GUID
+    put_u32s(&mut data, &[700, 0]); // extensions
+    put_u32s(&mut data, &[800, 0]); // triggers
+    put_u32s(&mut data, &[800, 0]); // constants
+    for _ in 0..3 {
+        // sounds, sprites, backgrounds
+        put_list(&mut data, &[None]);
+    }
+    put_list(&mut data, &[None]); // paths
+
+    let mut script = Vec::new();
+    put_string(&mut script, "scr_count");
+    put_u32s(&mut script, &[800]);
+    put_string(&mut script, SCRIPT);
+    put_list(&mut data, &[Some(script), None]);
+
+    put_list(&mut data, &[None]); // fonts
+    put_list(&mut data, &[None]); // timelines
+
+    let mut object = Vec::new();
+    put_string(&mut object, "obj_counter");
+    put_u32s(&mut object, &[430, u32::MAX, 0, 1, 0, 0, u32::MAX, u32::MAX, 11]);
+    put_u32s(&mut object, &[u32::MAX; 12]); // no events
+    put_list(&mut data, &[Some(object)]);
+
+    let mut room = Vec::new();
+    put_string(&mut room, "rm_start");
+    put_u32s(&mut room, &[541]);
+    put_string(&mut room, "synthetic");
+    put_u32s(&mut room, &[640, 480, 30, 0, 0xC0C0C0, 1]);
+    put_string(&mut room, "");
+    put_u32s(&mut room, &[0, 0, 0]); // backgrounds, views enabled, views
+    put_u32s(&mut room, &[1, 32, 64, 0, 100001]); // an instance of obj_counter
+    put_string(&mut room, "");
+    put_u32s(&mut room, &[0]); // tiles
+    put_list(&mut data, &[Some(room)]);
+
+    put_u32s(&mut data, &[100001, 10000000]); // last instance and tile IDs
+    put_u32s(&mut data, &[800, 0]); // included files
+
+    let mut help = Vec::new();
+    put_u32s(&mut help, &[0xFFFFE1, 0]);
+    put_string(&mut help, "Game Information");
+    put_u32s(&mut help, &[u32::MAX, u32::MAX, 600, 400, 1, 1, 0, 1]);
+    put_string(&mut help, "");
+    put_u32s(&mut data, &[800]);
+    put_block(&mut data, &deflate(&help));
+
+    put_u32s(&mut data, &[500, 0]); // library initialization code
+    put_u32s(&mut data, &[700, 1, 0]); // room order
+    data
+}
+
+// A list of assets, each compressed on its own. Deleted ones are stored as a zero, like GameMaker does.
+fn put_list(data: &mut Vec<u8>, assets: &[Option<Vec<u8>>]) {
+    put_u32s(data, &[800, assets.len() as u32]);
+    for asset in assets {
+        let mut block = Vec::new();
+        match asset {
+            Some(asset) => {
+                put_u32s(&mut block, &[1]);
+                block.extend_from_slice(asset);
+            },
+            None => put_u32s(&mut block, &[0]),
+        }
+        put_block(data, &deflate(&block));
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn put_u32s(data: &mut Vec<u8>, values: &[u32]) {
+    values.iter().for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+}
+
+fn put_block(data: &mut Vec<u8>, block: &[u8]) {
+    put_u32s(data, &[block.len() as u32]);
+    data.extend_from_slice(block);
+}
+
+fn put_string(data: &mut Vec<u8>, s: &str) {
+    put_block(data, s.as_bytes());
+}
@@ -0,0 +1,59 @@
+//! Reads the synthetic game, as an exe and as bare gamedata, and checks what comes out.
+
+use conformance::synthetic;
+use gm8exe::{
+    reader::{from_exe, from_exe_with_callback, from_gamedata, ReaderOptions, ReaderProgress},
+    AssetKind, GameVersion,
+};
+
+#[test]
+fn synthetic_game() {
+    let options = ReaderOptions { strict: true, ..Default::default() };
+    let exe = from_exe(synthetic::exe(), None::<fn(&str)>, options).unwrap();
+    let gamedata = from_gamedata(synthetic::gamedata(), None::<fn(&str)>, options).unwrap();
+    for assets in [exe, gamedata].iter() {
+        assert!(matches!(assets.version, GameVersion::GameMaker8_0));
+        assert_eq!(assets.game_id, 4242);
+        assert_eq!(assets.scripts.len(), 2);
+        assert_eq!(assets.scripts[0].as_ref().unwrap().source.0.as_ref(), synthetic::SCRIPT.as_bytes());
+        assert!(assets.scripts[1].is_none());
+        assert_eq!(assets.objects[0].as_ref().unwrap().name.0.as_ref(), b"obj_counter");
+        let room = assets.rooms[0].as_ref().unwrap();
+        assert_eq!((room.instances.len(), room.instances[0].id), (1, 100001));
+        assert!(assets.sprites.iter().all(|x| x.is_none()) && assets.sounds.iter().all(|x| x.is_none()));
+        assert_eq!(assets.room_order, [0]);
+        assert_eq!(assets.help_dialog.caption.0.as_ref(), b"Game Information");
+        assert!(assets.trailing_data.is_none());
+    }
+}
+
+#[test]
+fn synthetic_progress() {
+    let options = ReaderOptions { multithread: false, ..Default::default() };
+    let mut reports = Vec::new();
+    from_exe_with_callback(synthetic::exe(), None::<fn(&str)>, options, |p| reports.push(p)).unwrap();
+
+    // on one thread, each list is counted up in order, including the empty ones
+    let totals = [0, 1, 1, 1, 1, 2, 1, 1, 1, 1, 0];
+    let mut expected = vec![ReaderProgress::Settings];
+    for (&kind, &total) in AssetKind::ALL.iter().zip(totals.iter()) {
+        expected.extend((0..=total).map(|done| ReaderProgress::Assets { kind, done, total }));
+    }
+    expected.push(ReaderProgress::Finishing);
+    assert_eq!(reports, expected);
+}
+
+#[test]
+fn synthetic_trailing_data() {
+    let options = ReaderOptions { strict: true, ..Default::default() };
+    let extra = b"appended by an installer".to_vec();
+    let clean = from_gamedata(synthetic::gamedata(), None::<fn(&str)>, options).unwrap();
+    let exe = from_exe([synthetic::exe(), extra.clone()].concat(), None::<fn(&str)>, options).unwrap();
+    let gamedata = from_gamedata([synthetic::gamedata(), extra.clone()].concat(), None::<fn(&str)>, options).unwrap();
+    for assets in [exe, gamedata].iter() {
+        assert_eq!(assets.trailing_data.as_deref(), Some(extra.as_slice()));
+        // it's not part of the game, so it doesn't change which game this is
+        assert_eq!(assets.gamedata_hash, clean.gamedata_hash);
+        assert_eq!(assets.room_order, [0]);
+    }
+}
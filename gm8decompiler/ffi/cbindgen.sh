@@ -0,0 +1,4 @@
+#!/usr/bin/env bash
+
+# cargo install cbindgen
+cbindgen --config cbindgen.toml --output include/gm8dec.h
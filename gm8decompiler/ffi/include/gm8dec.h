@@ -0,0 +1,171 @@
+#ifndef GM8DEC_H
+#define GM8DEC_H
+
+/* Generated by cbindgen.sh - do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * The call succeeded.
+ */
+#define GM8DEC_OK 0
+
+/**
+ * A required pointer argument was null.
+ */
+#define GM8DEC_ERROR_NULL_ARGUMENT 1
+
+/**
+ * An argument was out of range or not valid UTF-8.
+ */
+#define GM8DEC_ERROR_INVALID_ARGUMENT 2
+
+/**
+ * The requested asset slot is empty (the asset was deleted before the game was compiled).
+ */
+#define GM8DEC_ERROR_NO_ASSET 3
+
+/**
+ * The output file could not be written.
+ */
+#define GM8DEC_ERROR_WRITE 4
+
+/**
+ * Something went horribly wrong inside the decompiler. The handle should not be used any further.
+ */
+#define GM8DEC_ERROR_PANIC 5
+
+#define GM8DEC_ASSET_TRIGGERS 0
+
+#define GM8DEC_ASSET_CONSTANTS 1
+
+#define GM8DEC_ASSET_SOUNDS 2
+
+#define GM8DEC_ASSET_SPRITES 3
+
+#define GM8DEC_ASSET_BACKGROUNDS 4
+
+#define GM8DEC_ASSET_PATHS 5
+
+#define GM8DEC_ASSET_SCRIPTS 6
+
+#define GM8DEC_ASSET_FONTS 7
+
+#define GM8DEC_ASSET_TIMELINES 8
+
+#define GM8DEC_ASSET_OBJECTS 9
+
+#define GM8DEC_ASSET_ROOMS 10
+
+#define GM8DEC_DEOBFUSCATE_AUTO 0
+
+#define GM8DEC_DEOBFUSCATE_ON 1
+
+#define GM8DEC_DEOBFUSCATE_OFF 2
+
+/**
+ * A parsed game, created with `gm8dec_open` and destroyed with `gm8dec_free`.
+ */
+typedef struct Gm8DecHandle Gm8DecHandle;
+
+/**
+ * Called with each progress message while writing a project file. May be null.
+ */
+typedef void (*Gm8DecProgressCallback)(void *user_data, const uint8_t *message, size_t message_len);
+
+/**
+ * Options for `gm8dec_open`. These match the decompiler's command-line flags.
+ */
+typedef struct Gm8DecOpenOptions {
+  /**
+   * Reject games with unexpected asset versions, rather than trying to read them anyway. Off is `--lazy`.
+   */
+  bool strict;
+  /**
+   * Read assets on multiple threads. Off is `--singlethread`.
+   */
+  bool multithread;
+} Gm8DecOpenOptions;
+
+/**
+ * Options for `gm8dec_write_gmk`. These match the decompiler's command-line flags.
+ */
+typedef struct Gm8DecOptions {
+  /**
+   * One of the `GM8DEC_DEOBFUSCATE_*` modes.
+   */
+  uint32_t deobfuscate;
+  /**
+   * Fix broken events instead of preserving them.
+   */
+  bool fix_events;
+  /**
+   * Write assets on multiple threads.
+   */
+  bool multithread;
+} Gm8DecOptions;
+
+/**
+ * Parses a game executable. Returns null on failure, in which case `gm8dec_last_error_message` says why.
+ * The data is copied, so the caller's buffer may be freed as soon as this returns.
+ * `options` may be null to use the same defaults as the command-line decompiler.
+ */
+struct Gm8DecHandle *gm8dec_open(const uint8_t *data,
+                                 size_t len,
+                                 const struct Gm8DecOpenOptions *options);
+
+/**
+ * Frees a handle returned by `gm8dec_open`. Passing null does nothing.
+ */
+void gm8dec_free(struct Gm8DecHandle *handle);
+
+/**
+ * Gets the GameMaker version of the game: either 800 or 810.
+ */
+int32_t gm8dec_version(const struct Gm8DecHandle *handle, uint32_t *out_version);
+
+/**
+ * Gets the number of asset slots of one of the `GM8DEC_ASSET_*` kinds, including empty ones.
+ */
+int32_t gm8dec_asset_count(const struct Gm8DecHandle *handle, uint32_t kind, size_t *out_count);
+
+/**
+ * Gets the name of an asset as UTF-8.
+ * Up to `buf_len` bytes are copied into `buf`, and the full length of the name is stored in `out_len`,
+ * so the buffer can be resized and the call repeated if the name didn't fit.
+ * `buf` may be null to only query the length.
+ */
+int32_t gm8dec_asset_name(const struct Gm8DecHandle *handle,
+                          uint32_t kind,
+                          size_t index,
+                          uint8_t *buf,
+                          size_t buf_len,
+                          size_t *out_len);
+
+/**
+ * Sets the function to be called with progress messages during `gm8dec_write_gmk`, replacing any previous one.
+ * `user_data` is passed back to the callback untouched. Passing a null callback removes it.
+ */
+int32_t gm8dec_set_progress_callback(struct Gm8DecHandle *handle,
+                                     Gm8DecProgressCallback callback,
+                                     void *user_data);
+
+/**
+ * Writes the game out as a GameMaker project file to the given UTF-8 path.
+ * `options` may be null to use the same defaults as the command-line decompiler.
+ */
+int32_t gm8dec_write_gmk(struct Gm8DecHandle *handle,
+                         const uint8_t *path,
+                         size_t path_len,
+                         const struct Gm8DecOptions *options);
+
+/**
+ * Gets the message for the last error on this thread, in the same way as `gm8dec_asset_name`,
+ * except that the full length is returned rather than stored. `buf` may be null to only query the length.
+ */
+size_t gm8dec_last_error_message(uint8_t *buf,
+                                 size_t buf_len);
+
+#endif /* GM8DEC_H */
@@ -0,0 +1,326 @@
+//! C ABI for the decompiler, for frontends which want to run it in-process.
+//!
+//! All strings crossing the boundary are UTF-8 and passed with explicit lengths, never NUL-terminated.
+//! Functions which can fail return one of the `GM8DEC_*` status codes, and a description of the most recent
+//! failure on the calling thread can be retrieved with `gm8dec_last_error_message`.
+//! The header in `include/gm8dec.h` is generated from this file with `cbindgen.sh`.
+
+#![allow(clippy::missing_safety_doc)]
+
+use gm8decompiler::deobfuscate;
+use gm8exe::{reader::ReaderOptions, GameAssets, GameVersion};
+use std::{
+    cell::RefCell,
+    fs,
+    os::raw::c_void,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr, slice, str,
+};
+
+/// The call succeeded.
+pub const GM8DEC_OK: i32 = 0;
+/// A required pointer argument was null.
+pub const GM8DEC_ERROR_NULL_ARGUMENT: i32 = 1;
+/// An argument was out of range or not valid UTF-8.
+pub const GM8DEC_ERROR_INVALID_ARGUMENT: i32 = 2;
+/// The requested asset slot is empty (the asset was deleted before the game was compiled).
+pub const GM8DEC_ERROR_NO_ASSET: i32 = 3;
+/// The output file could not be written.
+pub const GM8DEC_ERROR_WRITE: i32 = 4;
+/// Something went horribly wrong inside the decompiler. The handle should not be used any further.
+pub const GM8DEC_ERROR_PANIC: i32 = 5;
+
+pub const GM8DEC_ASSET_TRIGGERS: u32 = 0;
+pub const GM8DEC_ASSET_CONSTANTS: u32 = 1;
+pub const GM8DEC_ASSET_SOUNDS: u32 = 2;
+pub const GM8DEC_ASSET_SPRITES: u32 = 3;
+pub const GM8DEC_ASSET_BACKGROUNDS: u32 = 4;
+pub const GM8DEC_ASSET_PATHS: u32 = 5;
+pub const GM8DEC_ASSET_SCRIPTS: u32 = 6;
+pub const GM8DEC_ASSET_FONTS: u32 = 7;
+pub const GM8DEC_ASSET_TIMELINES: u32 = 8;
+pub const GM8DEC_ASSET_OBJECTS: u32 = 9;
+pub const GM8DEC_ASSET_ROOMS: u32 = 10;
+
+pub const GM8DEC_DEOBFUSCATE_AUTO: u32 = 0;
+pub const GM8DEC_DEOBFUSCATE_ON: u32 = 1;
+pub const GM8DEC_DEOBFUSCATE_OFF: u32 = 2;
+
+/// Called with each progress message while writing a project file. May be null.
+pub type ProgressCallback = Option<extern "C" fn(user_data: *mut c_void, message: *const u8, message_len: usize)>;
+
+/// A parsed game, created with `gm8dec_open` and destroyed with `gm8dec_free`.
+pub struct Handle {
+    assets: GameAssets,
+    deobfuscated: bool,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+}
+
+/// Options for `gm8dec_open`. These match the decompiler's command-line flags.
+#[repr(C)]
+pub struct OpenOptions {
+    /// Reject games with unexpected asset versions, rather than trying to read them anyway. Off is `--lazy`.
+    pub strict: bool,
+    /// Read assets on multiple threads. Off is `--singlethread`.
+    pub multithread: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self { strict: true, multithread: true }
+    }
+}
+
+/// Options for `gm8dec_write_gmk`. These match the decompiler's command-line flags.
+#[repr(C)]
+pub struct Options {
+    /// One of the `GM8DEC_DEOBFUSCATE_*` modes.
+    pub deobfuscate: u32,
+    /// Fix broken events instead of preserving them.
+    pub fix_events: bool,
+    /// Write assets on multiple threads.
+    pub multithread: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { deobfuscate: GM8DEC_DEOBFUSCATE_AUTO, fix_events: true, multithread: true }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn set_error(code: i32, message: impl Into<String>) -> i32 {
+    LAST_ERROR.with(|e| *e.borrow_mut() = message.into());
+    code
+}
+
+// Runs the body of an FFI function, turning any panic into GM8DEC_ERROR_PANIC rather than unwinding into C.
+fn guard(f: impl FnOnce() -> i32) -> i32 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(code) => code,
+        Err(e) => {
+            let message = match e.downcast_ref::<&str>() {
+                Some(s) => (*s).into(),
+                None => e.downcast_ref::<String>().cloned().unwrap_or_else(|| "unknown panic".into()),
+            };
+            set_error(GM8DEC_ERROR_PANIC, format!("Internal error: {}", message))
+        },
+    }
+}
+
+// Copies as much of `data` as fits into the caller's buffer and returns the full length.
+unsafe fn copy_out(data: &[u8], buf: *mut u8, buf_len: usize) -> usize {
+    if !buf.is_null() {
+        ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len().min(buf_len));
+    }
+    data.len()
+}
+
+fn asset_names(assets: &GameAssets, kind: u32) -> Option<Vec<Option<&[u8]>>> {
+    fn names<T>(list: &[Option<Box<T>>], name: impl Fn(&T) -> &[u8]) -> Vec<Option<&[u8]>> {
+        list.iter().map(|x| x.as_deref().map(&name)).collect()
+    }
+    Some(match kind {
+        GM8DEC_ASSET_TRIGGERS => names(&assets.triggers, |x| &x.name.0),
+        GM8DEC_ASSET_CONSTANTS => assets.constants.iter().map(|x| Some(x.name.0.as_ref())).collect(),
+        GM8DEC_ASSET_SOUNDS => names(&assets.sounds, |x| &x.name.0),
+        GM8DEC_ASSET_SPRITES => names(&assets.sprites, |x| &x.name.0),
+        GM8DEC_ASSET_BACKGROUNDS => names(&assets.backgrounds, |x| &x.name.0),
+        GM8DEC_ASSET_PATHS => names(&assets.paths, |x| &x.name.0),
+        GM8DEC_ASSET_SCRIPTS => names(&assets.scripts, |x| &x.name.0),
+        GM8DEC_ASSET_FONTS => names(&assets.fonts, |x| &x.name.0),
+        GM8DEC_ASSET_TIMELINES => names(&assets.timelines, |x| &x.name.0),
+        GM8DEC_ASSET_OBJECTS => names(&assets.objects, |x| &x.name.0),
+        GM8DEC_ASSET_ROOMS => names(&assets.rooms, |x| &x.name.0),
+        _ => return None,
+    })
+}
+
+/// Parses a game executable. Returns null on failure, in which case `gm8dec_last_error_message` says why.
+/// The data is copied, so the caller's buffer may be freed as soon as this returns.
+/// `options` may be null to use the same defaults as the command-line decompiler.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_open(data: *const u8, len: usize, options: *const OpenOptions) -> *mut Handle {
+    let mut handle = ptr::null_mut();
+    guard(|| {
+        if data.is_null() {
+            return set_error(GM8DEC_ERROR_NULL_ARGUMENT, "No data given")
+        }
+        let exe = slice::from_raw_parts(data, len).to_vec();
+        let default_options = OpenOptions::default();
+        let options = options.as_ref().unwrap_or(&default_options);
+        let options = ReaderOptions { strict: options.strict, multithread: options.multithread, keep_blobs: false };
+        match gm8exe::reader::from_exe(exe, None::<fn(&str)>, options) {
+            Ok(assets) => {
+                let user_data = ptr::null_mut();
+                handle = Box::into_raw(Box::new(Handle { assets, deobfuscated: false, progress: None, user_data }));
+                GM8DEC_OK
+            },
+            Err(e) => set_error(GM8DEC_ERROR_INVALID_ARGUMENT, format!("Reader error: {}", e)),
+        }
+    });
+    handle
+}
+
+/// Frees a handle returned by `gm8dec_open`. Passing null does nothing.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_free(handle: *mut Handle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Gets the GameMaker version of the game: either 800 or 810.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_version(handle: *const Handle, out_version: *mut u32) -> i32 {
+    guard(|| match (handle.as_ref(), out_version.is_null()) {
+        (Some(handle), false) => {
+            *out_version = match handle.assets.version {
+                GameVersion::GameMaker8_0 => 800,
+                GameVersion::GameMaker8_1 => 810,
+            };
+            GM8DEC_OK
+        },
+        _ => set_error(GM8DEC_ERROR_NULL_ARGUMENT, "Null handle or output pointer"),
+    })
+}
+
+/// Gets the number of asset slots of one of the `GM8DEC_ASSET_*` kinds, including empty ones.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_asset_count(handle: *const Handle, kind: u32, out_count: *mut usize) -> i32 {
+    guard(|| {
+        let handle = match (handle.as_ref(), out_count.is_null()) {
+            (Some(handle), false) => handle,
+            _ => return set_error(GM8DEC_ERROR_NULL_ARGUMENT, "Null handle or output pointer"),
+        };
+        match asset_names(&handle.assets, kind) {
+            Some(names) => {
+                *out_count = names.len();
+                GM8DEC_OK
+            },
+            None => set_error(GM8DEC_ERROR_INVALID_ARGUMENT, format!("Unknown asset kind {}", kind)),
+        }
+    })
+}
+
+/// Gets the name of an asset as UTF-8.
+/// Up to `buf_len` bytes are copied into `buf`, and the full length of the name is stored in `out_len`,
+/// so the buffer can be resized and the call repeated if the name didn't fit.
+/// `buf` may be null to only query the length.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_asset_name(
+    handle: *const Handle,
+    kind: u32,
+    index: usize,
+    buf: *mut u8,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> i32 {
+    guard(|| {
+        let handle = match (handle.as_ref(), out_len.is_null()) {
+            (Some(handle), false) => handle,
+            _ => return set_error(GM8DEC_ERROR_NULL_ARGUMENT, "Null handle or output pointer"),
+        };
+        let names = match asset_names(&handle.assets, kind) {
+            Some(names) => names,
+            None => return set_error(GM8DEC_ERROR_INVALID_ARGUMENT, format!("Unknown asset kind {}", kind)),
+        };
+        match names.get(index) {
+            Some(Some(name)) => {
+                *out_len = copy_out(String::from_utf8_lossy(name).as_bytes(), buf, buf_len);
+                GM8DEC_OK
+            },
+            Some(None) => set_error(GM8DEC_ERROR_NO_ASSET, format!("Asset {} of kind {} does not exist", index, kind)),
+            None => set_error(GM8DEC_ERROR_INVALID_ARGUMENT, format!("Asset index {} is out of range", index)),
+        }
+    })
+}
+
+/// Sets the function to be called with progress messages during `gm8dec_write_gmk`, replacing any previous one.
+/// `user_data` is passed back to the callback untouched. Passing a null callback removes it.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_set_progress_callback(
+    handle: *mut Handle,
+    callback: ProgressCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    guard(|| match handle.as_mut() {
+        Some(handle) => {
+            handle.progress = callback;
+            handle.user_data = user_data;
+            GM8DEC_OK
+        },
+        None => set_error(GM8DEC_ERROR_NULL_ARGUMENT, "Null handle"),
+    })
+}
+
+/// Writes the game out as a GameMaker project file to the given UTF-8 path.
+/// `options` may be null to use the same defaults as the command-line decompiler.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_write_gmk(
+    handle: *mut Handle,
+    path: *const u8,
+    path_len: usize,
+    options: *const Options,
+) -> i32 {
+    guard(|| {
+        let handle = match (handle.as_mut(), path.is_null()) {
+            (Some(handle), false) => handle,
+            _ => return set_error(GM8DEC_ERROR_NULL_ARGUMENT, "Null handle or path"),
+        };
+        let path = match str::from_utf8(slice::from_raw_parts(path, path_len)) {
+            Ok(path) => Path::new(path),
+            Err(e) => return set_error(GM8DEC_ERROR_INVALID_ARGUMENT, format!("Path is not valid UTF-8: {}", e)),
+        };
+        let default_options = Options::default();
+        let options = options.as_ref().unwrap_or(&default_options);
+        let deobfuscate = match options.deobfuscate {
+            GM8DEC_DEOBFUSCATE_AUTO => gm8decompiler::looks_obfuscated(&handle.assets),
+            GM8DEC_DEOBFUSCATE_ON => true,
+            GM8DEC_DEOBFUSCATE_OFF => false,
+            x => return set_error(GM8DEC_ERROR_INVALID_ARGUMENT, format!("Unknown deobfuscation mode {}", x)),
+        };
+
+        if options.fix_events {
+            gm8decompiler::fix_events(&mut handle.assets);
+        }
+        // deobfuscation modifies the assets in place, so it can only be done once per handle
+        if deobfuscate && !handle.deobfuscated {
+            deobfuscate::process(&mut handle.assets, &[]);
+            handle.deobfuscated = true;
+        }
+
+        let mut gmk = match fs::File::create(path) {
+            Ok(f) => f,
+            Err(e) => {
+                return set_error(
+                    GM8DEC_ERROR_WRITE,
+                    format!("Failed to create output file '{}': {}", path.display(), e),
+                )
+            },
+        };
+        let (progress, user_data) = (handle.progress, handle.user_data);
+        let meta = gm8decompiler::Metadata::default();
+        let result = gm8decompiler::write_gmk(&mut gmk, &handle.assets, &meta, options.multithread, |msg| {
+            if let Some(callback) = progress {
+                callback(user_data, msg.as_ptr(), msg.len());
+            }
+        });
+        match result {
+            Ok(()) => GM8DEC_OK,
+            Err(e) => set_error(GM8DEC_ERROR_WRITE, e),
+        }
+    })
+}
+
+/// Gets the message for the last error on this thread, in the same way as `gm8dec_asset_name`,
+/// except that the full length is returned rather than stored. `buf` may be null to only query the length.
+#[no_mangle]
+pub unsafe extern "C" fn gm8dec_last_error_message(buf: *mut u8, buf_len: usize) -> usize {
+    LAST_ERROR.with(|e| copy_out(e.borrow().as_bytes(), buf, buf_len))
+}
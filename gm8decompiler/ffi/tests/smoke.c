@@ -0,0 +1,117 @@
+// Exercises the C ABI from C, to make sure the header and the library agree.
+// Built and run by smoke.rs with the path of the synthetic game from the conformance crate, and a path to write
+// a project file to - returns the number of the first failed check, or 0.
+
+#include "../include/gm8dec.h"
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define CHECK(n, cond) \
+    if (!(cond)) { \
+        fprintf(stderr, "check %d failed: %s\n", n, #cond); \
+        return n; \
+    }
+
+static void count_messages(void *user_data, const uint8_t *message, size_t message_len) {
+    (void)message;
+    if (message_len > 0) {
+        *(int *)user_data += 1;
+    }
+}
+
+// Reads a whole file into a buffer which the caller frees, or returns NULL.
+static uint8_t *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        return NULL;
+    }
+    uint8_t *data = NULL;
+    long size;
+    if (fseek(file, 0, SEEK_END) == 0 && (size = ftell(file)) > 0 && fseek(file, 0, SEEK_SET) == 0) {
+        data = malloc((size_t)size);
+        if (data != NULL && fread(data, 1, (size_t)size, file) != (size_t)size) {
+            free(data);
+            data = NULL;
+        }
+        *len = (size_t)size;
+    }
+    fclose(file);
+    return data;
+}
+
+int main(int argc, char **argv) {
+    CHECK(1, argc == 3);
+
+    // not an exe at all
+    const uint8_t garbage[] = "this is not a game";
+    Gm8DecHandle *handle = gm8dec_open(garbage, sizeof(garbage), NULL);
+    CHECK(2, handle == NULL);
+
+    size_t len = gm8dec_last_error_message(NULL, 0);
+    CHECK(3, len > 0);
+    char message[256] = {0};
+    CHECK(4, gm8dec_last_error_message((uint8_t *)message, sizeof(message) - 1) == len);
+    CHECK(5, strncmp(message, "Reader error", 12) == 0);
+
+    CHECK(6, gm8dec_open(NULL, 0, NULL) == NULL);
+
+    uint32_t version = 0;
+    CHECK(7, gm8dec_version(NULL, &version) == GM8DEC_ERROR_NULL_ARGUMENT);
+    size_t count = 0;
+    CHECK(8, gm8dec_asset_count(NULL, GM8DEC_ASSET_SPRITES, &count) == GM8DEC_ERROR_NULL_ARGUMENT);
+    CHECK(9, gm8dec_set_progress_callback(NULL, NULL, NULL) == GM8DEC_ERROR_NULL_ARGUMENT);
+
+    Gm8DecOptions options = {GM8DEC_DEOBFUSCATE_AUTO, true, true};
+    CHECK(10, gm8dec_write_gmk(NULL, (const uint8_t *)"out.gmk", 7, &options) == GM8DEC_ERROR_NULL_ARGUMENT);
+
+    gm8dec_free(NULL);
+
+    // the synthetic game, which the handle keeps its own copy of
+    size_t exe_len = 0;
+    uint8_t *exe = read_file(argv[1], &exe_len);
+    CHECK(11, exe != NULL);
+    Gm8DecOpenOptions open_options = {true, false};
+    handle = gm8dec_open(exe, exe_len, &open_options);
+    free(exe);
+    CHECK(12, handle != NULL);
+
+    CHECK(13, gm8dec_version(handle, &version) == GM8DEC_OK);
+    CHECK(14, version == 800);
+    CHECK(15, gm8dec_asset_count(handle, GM8DEC_ASSET_SCRIPTS, &count) == GM8DEC_OK && count == 2);
+    CHECK(16, gm8dec_asset_count(handle, GM8DEC_ASSET_OBJECTS, &count) == GM8DEC_OK && count == 1);
+    CHECK(17, gm8dec_asset_count(handle, GM8DEC_ASSET_SPRITES, &count) == GM8DEC_OK && count == 1);
+    CHECK(18, gm8dec_asset_count(handle, GM8DEC_ASSET_CONSTANTS, &count) == GM8DEC_OK && count == 0);
+    CHECK(19, gm8dec_asset_count(handle, 11, &count) == GM8DEC_ERROR_INVALID_ARGUMENT);
+
+    // a buffer which is too small still gets the full length back
+    char name[32] = {0};
+    CHECK(20, gm8dec_asset_name(handle, GM8DEC_ASSET_SCRIPTS, 0, NULL, 0, &len) == GM8DEC_OK && len == 9);
+    CHECK(21, gm8dec_asset_name(handle, GM8DEC_ASSET_SCRIPTS, 0, (uint8_t *)name, 3, &len) == GM8DEC_OK);
+    CHECK(22, len == 9 && strcmp(name, "scr") == 0);
+    CHECK(23, gm8dec_asset_name(handle, GM8DEC_ASSET_SCRIPTS, 0, (uint8_t *)name, sizeof(name), &len) == GM8DEC_OK);
+    CHECK(24, strncmp(name, "scr_count", len) == 0);
+    CHECK(25, gm8dec_asset_name(handle, GM8DEC_ASSET_SCRIPTS, 1, (uint8_t *)name, sizeof(name), &len)
+        == GM8DEC_ERROR_NO_ASSET);
+    CHECK(26, gm8dec_asset_name(handle, GM8DEC_ASSET_SCRIPTS, 2, (uint8_t *)name, sizeof(name), &len)
+        == GM8DEC_ERROR_INVALID_ARGUMENT);
+    CHECK(27, gm8dec_asset_name(handle, GM8DEC_ASSET_OBJECTS, 0, (uint8_t *)name, sizeof(name), &len) == GM8DEC_OK);
+    CHECK(28, len == 11 && strncmp(name, "obj_counter", len) == 0);
+    CHECK(29, gm8dec_asset_name(handle, GM8DEC_ASSET_ROOMS, 0, (uint8_t *)name, sizeof(name), &len) == GM8DEC_OK);
+    CHECK(30, len == 8 && strncmp(name, "rm_start", len) == 0);
+
+    int messages = 0;
+    CHECK(31, gm8dec_set_progress_callback(handle, count_messages, &messages) == GM8DEC_OK);
+    CHECK(32, gm8dec_write_gmk(handle, (const uint8_t *)argv[2], strlen(argv[2]), &options) == GM8DEC_OK);
+    CHECK(33, messages > 0);
+
+    // a project file starts with GameMaker's magic number
+    size_t gmk_len = 0;
+    uint8_t *gmk = read_file(argv[2], &gmk_len);
+    CHECK(34, gmk != NULL && gmk_len > 4);
+    CHECK(35, gmk[0] == 0x91 && gmk[1] == 0xD5 && gmk[2] == 0x12 && gmk[3] == 0x00);
+    free(gmk);
+
+    gm8dec_free(handle);
+    return 0;
+}
@@ -0,0 +1,32 @@
+// Compiles smoke.c against the cdylib with the system C compiler, then runs it on the synthetic game.
+#[cfg(unix)]
+#[test]
+fn c_smoke_test() {
+    use std::{env, fs, path::Path, process::Command};
+
+    // This is target/<profile>/deps/smoke-<hash>. Cargo builds the cdylib into the same directory before running
+    // integration tests, but only copies it up to target/<profile> for `cargo build`, so link against it here.
+    let exe = env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap();
+    let out = lib_dir.join("gm8dec_smoke");
+    let tests_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".into()))
+        .arg(tests_dir.join("smoke.c"))
+        .arg("-o")
+        .arg(&out)
+        .arg("-L")
+        .arg(lib_dir)
+        .arg("-lgm8dec")
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .status()
+        .expect("failed to run C compiler");
+    assert!(status.success(), "failed to compile smoke.c");
+
+    let game = lib_dir.join("gm8dec_smoke.exe");
+    let gmk = lib_dir.join("gm8dec_smoke.gmk");
+    fs::write(&game, conformance::synthetic::exe()).unwrap();
+    let _ = fs::remove_file(&gmk);
+    let status = Command::new(&out).arg(&game).arg(&gmk).status().expect("failed to run smoke test");
+    assert_eq!(status.code(), Some(0));
+}
@@ -0,0 +1,385 @@
+// Batch mode decompiles every game in a directory, writing the projects into a tree which mirrors it.
+//
+// A game which fails doesn't stop the others, and what happened to each one is written to summary.json and
+// summary.csv in the output directory at the end. Games whose project is already there are skipped, unless it
+// has a journal next to it, which means it was interrupted and gets resumed instead. So running the same batch
+// again after stopping it partway only does what's left.
+
+use crate::{gmk_extension, journal};
+use gm8exe::{GameAssets, GameVersion};
+use serde::Serialize;
+use std::{
+    any::Any,
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// One game to decompile.
+pub struct Task {
+    pub input: PathBuf,
+    // Where the project goes, without its extension, which depends on the version of the game.
+    pub output: PathBuf,
+    // Whether an interrupted project was found, which should be carried on with.
+    pub resume: bool,
+    pub multithread: bool,
+}
+
+// What a successfully decompiled game was.
+pub struct Decompiled {
+    pub version: GameVersion,
+    pub assets: AssetCounts,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct AssetCounts {
+    pub sprites: usize,
+    pub sounds: usize,
+    pub backgrounds: usize,
+    pub paths: usize,
+    pub scripts: usize,
+    pub fonts: usize,
+    pub timelines: usize,
+    pub objects: usize,
+    pub rooms: usize,
+}
+
+impl AssetCounts {
+    pub fn of(assets: &GameAssets) -> Self {
+        fn count<T>(list: &[Option<T>]) -> usize {
+            list.iter().filter(|x| x.is_some()).count()
+        }
+        Self {
+            sprites: count(&assets.sprites),
+            sounds: count(&assets.sounds),
+            backgrounds: count(&assets.backgrounds),
+            paths: count(&assets.paths),
+            scripts: count(&assets.scripts),
+            fonts: count(&assets.fonts),
+            timelines: count(&assets.timelines),
+            objects: count(&assets.objects),
+            rooms: count(&assets.rooms),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Decompiled,
+    Skipped,
+    Failed,
+}
+
+// A line of the summary.
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    // The game's path relative to the input directory, always with forward slashes.
+    pub file: String,
+    pub version: Option<&'static str>,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+    // How long it took in seconds.
+    pub duration: f64,
+    pub assets: Option<AssetCounts>,
+}
+
+// What a game's decompile panicked with, for its entry in the summary.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("no message", |x| x).into(),
+    };
+    format!("Panicked: {}", message)
+}
+
+fn short_version(version: GameVersion) -> &'static str {
+    match version {
+        GameVersion::GameMaker8_0 => "8.0",
+        GameVersion::GameMaker8_1 => "8.1",
+    }
+}
+
+// Finds every .exe in a directory and the ones inside it, as paths relative to it, in order.
+pub fn find_games(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, relative: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &path, found)?;
+            } else if path.extension().and_then(|x| x.to_str()).is_some_and(|x| x.eq_ignore_ascii_case("exe")) {
+                found.push(path);
+            }
+        }
+        Ok(())
+    }
+    let mut found = Vec::new();
+    walk(dir, Path::new(""), &mut found)?;
+    found.sort();
+    Ok(found)
+}
+
+// Looks for a project already written for a game, returning its version and whether it was interrupted.
+fn existing_project(output: &Path) -> Option<(GameVersion, bool)> {
+    [GameVersion::GameMaker8_0, GameVersion::GameMaker8_1].iter().find_map(|&version| {
+        let path = output.with_extension(gmk_extension(version));
+        if path.is_file() { Some((version, journal::path_for(&path).exists())) } else { None }
+    })
+}
+
+// Decompiles every game in `input_dir` into `output_dir`, running up to `jobs` at once, and returns the summary.
+// Games which already have a project are skipped unless `force` is set. The threads which a single game would
+// use are split between the jobs, and `decompile` is called on whichever thread a game's job runs on.
+// `progress` is called with each game's entry as soon as it's done. If `decompile` panics, the game is recorded as
+// failed and the others carry on.
+pub fn run<F, P>(
+    input_dir: &Path,
+    output_dir: &Path,
+    jobs: usize,
+    multithread: bool,
+    force: bool,
+    decompile: F,
+    mut progress: P,
+) -> io::Result<Vec<Entry>>
+where
+    F: Fn(&Task) -> Result<Decompiled, String> + Sync,
+    P: FnMut(&Entry),
+{
+    let games = find_games(input_dir)?;
+    let jobs = jobs.max(1).min(games.len().max(1));
+    let threads = thread::available_parallelism().map_or(1, |x| x.get()) / jobs;
+    let multithread = multithread && threads > 1;
+
+    let decompile_one = |relative: &PathBuf| -> Entry {
+        let start = Instant::now();
+        let task =
+            Task { input: input_dir.join(relative), output: output_dir.join(relative), resume: false, multithread };
+        let file = relative.to_string_lossy().replace('\\', "/");
+        let entry = |version: Option<GameVersion>, outcome, error, assets| Entry {
+            file: file.clone(),
+            version: version.map(short_version),
+            outcome,
+            error,
+            duration: (start.elapsed().as_secs_f64() * 1000.0).round() / 1000.0,
+            assets,
+        };
+
+        let resume = match existing_project(&task.output) {
+            Some((version, false)) if !force => return entry(Some(version), Outcome::Skipped, None, None),
+            Some((_, interrupted)) => interrupted && !force,
+            None => false,
+        };
+        let task = Task { resume, ..task };
+        let result = task
+            .output
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| format!("Failed to create output directory: {}", e))
+            .and_then(|()| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads.max(1))
+                    .build()
+                    .map_err(|e| format!("Failed to start threads: {}", e))
+            })
+            .and_then(|pool| {
+                pool.install(|| panic::catch_unwind(AssertUnwindSafe(|| decompile(&task))))
+                    .unwrap_or_else(|payload| Err(panic_message(payload)))
+            });
+        match result {
+            Ok(done) => entry(Some(done.version), Outcome::Decompiled, None, Some(done.assets)),
+            Err(e) => entry(None, Outcome::Failed, Some(e), None),
+        }
+    };
+
+    // each job takes the next game from the queue when it's done with the last
+    let queue = Mutex::new(games.iter());
+    let (sender, receiver) = mpsc::channel();
+    let mut entries = Vec::with_capacity(games.len());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (queue, decompile_one) = (&queue, &decompile_one);
+            scope.spawn(move || {
+                // the lock has to be released before decompiling, hence the separate statement
+                loop {
+                    let game = queue.lock().unwrap().next();
+                    match game {
+                        Some(game) => sender.send(decompile_one(game)).unwrap(),
+                        None => break,
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for entry in receiver.iter() {
+            progress(&entry);
+            entries.push(entry);
+        }
+    });
+    entries.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(entries)
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+// The summary as CSV, with a header line and one line per game.
+pub fn summary_csv(entries: &[Entry]) -> String {
+    let mut csv = String::from(
+        "file,version,outcome,error,duration,sprites,sounds,backgrounds,paths,scripts,fonts,timelines,objects,rooms\n",
+    );
+    for entry in entries {
+        let outcome = match entry.outcome {
+            Outcome::Decompiled => "decompiled",
+            Outcome::Skipped => "skipped",
+            Outcome::Failed => "failed",
+        };
+        let counts = match entry.assets {
+            Some(x) => {
+                [x.sprites, x.sounds, x.backgrounds, x.paths, x.scripts, x.fonts, x.timelines, x.objects, x.rooms]
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+            },
+            None => vec![String::new(); 9],
+        };
+        let fields = [
+            csv_field(&entry.file),
+            entry.version.unwrap_or("").into(),
+            outcome.into(),
+            csv_field(entry.error.as_deref().unwrap_or("")),
+            entry.duration.to_string(),
+        ];
+        csv += &fields.iter().chain(counts.iter()).cloned().collect::<Vec<_>>().join(",");
+        csv.push('\n');
+    }
+    csv
+}
+
+// Writes summary.json and summary.csv into a directory.
+pub fn write_summary(dir: &Path, entries: &[Entry]) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(entries).map_err(io::Error::other)?;
+    fs::write(dir.join("summary.json"), json)?;
+    fs::write(dir.join("summary.csv"), summary_csv(entries))
+}
+
+// How long a whole batch took, along with how many games had each outcome, for printing at the end.
+pub fn totals(entries: &[Entry], elapsed: Duration) -> String {
+    let count = |outcome| entries.iter().filter(|x| x.outcome == outcome).count();
+    format!(
+        "{} decompiled, {} skipped, {} failed in {:.1}s",
+        count(Outcome::Decompiled),
+        count(Outcome::Skipped),
+        count(Outcome::Failed),
+        elapsed.as_secs_f64()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_games, run, summary_csv, write_summary, AssetCounts, Decompiled, Outcome, Task};
+    use crate::{write_gmk, Metadata};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn batch() {
+        let base = std::env::temp_dir().join(format!("gm8decompiler-batch-{}", std::process::id()));
+        let (input, output) = (base.join("games"), base.join("out"));
+        fs::create_dir_all(input.join("nested")).unwrap();
+        fs::write(input.join("good.exe"), b"a valid game").unwrap();
+        fs::write(input.join("nested/Corrupt.EXE"), b"MZ\x90\0 not a game at all").unwrap();
+        fs::write(input.join("readme.txt"), b"not a game").unwrap();
+        assert_eq!(find_games(&input).unwrap(), [Path::new("good.exe"), &Path::new("nested").join("Corrupt.EXE")]);
+
+        // the valid game gets written from a fixture, and the corrupt one goes to the real reader
+        let fixture = crate::tests::fixture_with_gaps();
+        let decompile = |task: &super::Task| {
+            let exe = fs::read(&task.input).unwrap();
+            if exe != b"a valid game" {
+                let options = gm8exe::reader::ReaderOptions::default();
+                gm8exe::reader::from_exe(exe, None::<fn(&str)>, options).map_err(|e| format!("Reader error: {}", e))?;
+            }
+            let mut gmk = Vec::new();
+            write_gmk(&mut gmk, &fixture, &Metadata::default(), task.multithread, |_| ())?;
+            fs::write(task.output.with_extension("gmk"), gmk).unwrap();
+            Ok(Decompiled { version: fixture.version, assets: AssetCounts::of(&fixture) })
+        };
+        let mut progress = 0;
+        let entries = run(&input, &output, 2, true, false, decompile, |_| progress += 1).unwrap();
+        write_summary(&output, &entries).unwrap();
+        assert_eq!(progress, 2);
+        assert!(output.join("good.gmk").is_file());
+        assert!(!output.join("nested").join("Corrupt.gmk").exists());
+
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(output.join("summary.json")).unwrap()).unwrap();
+        let files = json.as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0]["file"], "good.exe");
+        assert_eq!(files[0]["version"], "8.0");
+        assert_eq!(files[0]["outcome"], "decompiled");
+        assert!(files[0]["error"].is_null());
+        assert_eq!(files[0]["assets"]["sprites"], 3);
+        assert_eq!(files[0]["assets"], serde_json::to_value(AssetCounts::of(&fixture)).unwrap());
+        assert_eq!(files[1]["file"], "nested/Corrupt.EXE");
+        assert!(files[1]["version"].is_null());
+        assert_eq!(files[1]["outcome"], "failed");
+        assert!(files[1]["error"].as_str().unwrap().starts_with("Reader error"));
+        assert!(files[1]["assets"].is_null());
+        let csv = fs::read_to_string(output.join("summary.csv")).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(1).unwrap().starts_with("good.exe,8.0,decompiled,,"));
+        assert!(csv.lines().nth(2).unwrap().starts_with("nested/Corrupt.EXE,,failed,Reader error"));
+
+        // finished projects are skipped the next time, interrupted ones are resumed, and --force redoes everything
+        let journal = crate::journal::path_for(&output.join("good.gmk"));
+        let entries = run(&input, &output, 1, false, false, decompile, |_| ()).unwrap();
+        assert_eq!(entries.iter().map(|x| x.outcome).collect::<Vec<_>>(), [Outcome::Skipped, Outcome::Failed]);
+        assert_eq!(entries[0].version, Some("8.0"));
+        fs::write(&journal, "").unwrap();
+        let resumed = |task: &super::Task| if task.resume { decompile(task) } else { Err("not resumed".into()) };
+        let entries = run(&input, &output, 1, false, false, resumed, |_| ()).unwrap();
+        assert_eq!(entries[0].outcome, Outcome::Decompiled);
+        fs::remove_file(&journal).unwrap();
+        let entries = run(&input, &output, 1, false, true, decompile, |_| ()).unwrap();
+        assert_eq!(entries[0].outcome, Outcome::Decompiled);
+        fs::remove_dir_all(&base).unwrap();
+
+        let mut entry = entries[1].clone();
+        entry.error = Some("bad \"data\", at 0".into());
+        assert!(summary_csv(&[entry]).contains(",failed,\"bad \"\"data\"\", at 0\","));
+    }
+
+    #[test]
+    fn panicking_game() {
+        let base = std::env::temp_dir().join(format!("gm8decompiler-batch-panic-{}", std::process::id()));
+        let (input, output) = (base.join("games"), base.join("out"));
+        fs::create_dir_all(&input).unwrap();
+        for name in ["a.exe", "b.exe", "c.exe"] {
+            fs::write(input.join(name), name).unwrap();
+        }
+
+        // the game in the middle panics, which shouldn't take the job it ran on or any other game down with it
+        let fixture = crate::tests::fixture_with_gaps();
+        let decompile = |task: &Task| {
+            if task.input.ends_with("b.exe") {
+                panic!("unexpected data in {}", task.input.display());
+            }
+            Ok(Decompiled { version: fixture.version, assets: AssetCounts::of(&fixture) })
+        };
+        let entries = run(&input, &output, 2, false, false, decompile, |_| ()).unwrap();
+        fs::remove_dir_all(&base).unwrap();
+        let outcomes = entries.iter().map(|x| x.outcome).collect::<Vec<_>>();
+        assert_eq!(outcomes, [Outcome::Decompiled, Outcome::Failed, Outcome::Decompiled]);
+        let error = entries[1].error.as_deref().unwrap();
+        assert!(error.starts_with("Panicked: unexpected data in ") && error.ends_with("b.exe"), "{}", error);
+    }
+}
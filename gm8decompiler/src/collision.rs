@@ -16,6 +16,18 @@ pub enum Shape {
     Diamond = 3,
 }
 
+impl Shape {
+    fn from_u32(shape: u32) -> Option<Self> {
+        match shape {
+            0 => Some(Self::Precise),
+            1 => Some(Self::Rectangle),
+            2 => Some(Self::Disk),
+            3 => Some(Self::Diamond),
+            _ => None,
+        }
+    }
+}
+
 // Resolves an exe-format sprite's collision map to a GmkCollision struct
 // Returns None if the provided list is empty
 pub fn resolve_map(sprite: &Sprite) -> Option<GmkCollision> {
@@ -88,8 +100,13 @@ pub fn resolve_map(sprite: &Sprite) -> Option<GmkCollision> {
         }
     }
 
-    // Decide on shape
-    let (shape, alpha_tolerance) = if all_have_collision {
+    // Decide on shape - late GM8.1 sprites say what it was, otherwise it has to be guessed
+    let (shape, alpha_tolerance) = if let Some(shape) = sprite.collision_shape.and_then(Shape::from_u32) {
+        match shape {
+            Shape::Precise if lowest_alpha_with_col > highest_alpha_no_col => (shape, highest_alpha_no_col),
+            shape => (shape, 0),
+        }
+    } else if all_have_collision {
         (Shape::Rectangle, 0)
     } else if lowest_alpha_with_col > highest_alpha_no_col {
         (Shape::Precise, highest_alpha_no_col)
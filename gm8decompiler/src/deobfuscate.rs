@@ -4,7 +4,7 @@
 // and is really disgusting - read at your own risk.
 // You have been warned.
 
-use crate::mappings;
+use crate::{mappings, rename};
 use gm8exe::{
     asset::{CodeAction, PascalString},
     GameAssets,
@@ -13,20 +13,31 @@ use gml_parser::{
     ast::{self, AST},
     token::Operator,
 };
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     io::Write,
 };
 
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Mode {
     On,
     Off,
     Auto,
 }
 
+// What an asset was called before deobfuscation and what it's called after, as written by --write-name-map.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Renamed {
+    pub kind: String,
+    pub index: usize,
+    pub old: String,
+    pub new: String,
+}
+
 struct DeobfState {
     fields: Vec<Box<[u8]>>,
+    script_names: Vec<Box<[u8]>>,
     constants: HashMap<&'static [u8], f64>,
     vars: HashSet<&'static [u8]>,
 }
@@ -42,10 +53,17 @@ struct ExprWriter<'a, 'b, 'c> {
     group_skip_newline: bool, // overrides writing a newline after a group
 }
 
-pub fn process(assets: &mut GameAssets) {
+// Deobfuscates a game, returning the names given to its assets. Assets in the previous list of names,
+// from deobfuscating an earlier version of the game, get the same names again if their index is the same.
+pub fn process(assets: &mut GameAssets, previous: &[Renamed]) -> Vec<Renamed> {
+    let renamed = new_names(assets, previous);
+    let mut script_names: Vec<Box<[u8]>> = vec![Default::default(); assets.scripts.len()];
+    for x in renamed.iter().filter(|x| x.kind == "script") {
+        script_names[x.index] = x.new.as_bytes().into();
+    }
     let constants = mappings::make_constants_map();
     let vars = mappings::make_kernel_vars_lut();
-    let mut deobfuscator = DeobfState { fields: Vec::new(), constants, vars };
+    let mut deobfuscator = DeobfState { fields: Vec::new(), script_names, constants, vars };
     let assets2 = unsafe { std::mem::transmute::<_, &'static mut GameAssets>(&mut *assets) };
 
     // Helper function for CodeActions
@@ -215,42 +233,60 @@ pub fn process(assets: &mut GameAssets) {
     }
 
     // Mass rename assets
-    for (i, sprite) in assets.sprites.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+    for sprite in assets.sprites.iter_mut().flatten() {
         if sprite.frames.is_empty() {
             sprite.colliders.clear();
             sprite.per_frame_colliders = true;
         }
-        sprite.name = PascalString(format!("sprite{}", i).into_bytes().into());
-    }
-    for (i, sound) in assets.sounds.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        sound.name = PascalString(format!("sound{}", i).into_bytes().into());
-    }
-    for (i, background) in assets.backgrounds.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        background.name = PascalString(format!("background{}", i).into_bytes().into());
-    }
-    for (i, path) in assets.paths.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        path.name = PascalString(format!("path{}", i).into_bytes().into());
-    }
-    for (i, script) in assets.scripts.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        script.name = PascalString(format!("script{}", i).into_bytes().into());
     }
-    for (i, font) in assets.fonts.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        font.name = PascalString(format!("font{}", i).into_bytes().into());
+    for ((kind, index, _), x) in rename::names(assets).into_iter().zip(renamed.iter()) {
+        *rename::name_of(assets, kind, index) = PascalString(x.new.as_bytes().into());
     }
-    for (i, timeline) in assets.timelines.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        timeline.name = PascalString(format!("timeline{}", i).into_bytes().into());
-    }
-    for (i, object) in assets.objects.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        object.name = PascalString(format!("object{}", i).into_bytes().into());
-    }
-    for (i, room) in assets.rooms.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        room.name = PascalString(format!("room{}", i).into_bytes().into());
-    }
-    for (i, trigger) in assets.triggers.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        trigger.constant_name = PascalString(format!("trigger{}", i).into_bytes().into());
-    }
-    for (i, constant) in assets.constants.iter_mut().enumerate() {
-        constant.name = PascalString(format!("constant{}", i).into_bytes().into());
+    renamed
+}
+
+// Picks a name for every asset, like sprite3 or object12. Names from the previous list are used instead
+// where they're valid and don't clash with another asset's name.
+fn new_names(assets: &GameAssets, previous: &[Renamed]) -> Vec<Renamed> {
+    let mut reused = Vec::new();
+    let mut renamed = rename::names(assets)
+        .into_iter()
+        .map(|(kind, index, old)| {
+            let kind = rename::KINDS[kind];
+            let new = previous
+                .iter()
+                .find(|x| x.kind == kind && x.index == index && rename::is_identifier(x.new.as_bytes()))
+                .map(|x| x.new.clone());
+            reused.push(new.is_some());
+            let new = new.unwrap_or_else(|| format!("{}{}", kind, index));
+            Renamed { kind: kind.into(), index, old: String::from_utf8_lossy(&old).into(), new }
+        })
+        .collect::<Vec<_>>();
+
+    // the generated names are all different, so this only has to go back to them until there's no more clashes
+    loop {
+        let mut seen = HashMap::new();
+        let mut clashes = Vec::new();
+        for (i, x) in renamed.iter().enumerate() {
+            match seen.get(x.new.as_str()) {
+                Some(&j) => clashes.push(if reused[i] { i } else { j }),
+                None => {
+                    seen.insert(x.new.as_str(), i);
+                },
+            }
+        }
+        clashes.sort_unstable();
+        clashes.dedup();
+        if clashes.is_empty() {
+            break renamed
+        }
+        for i in clashes {
+            let x = &mut renamed[i];
+            let generated = format!("{}{}", x.kind, x.index);
+            eprintln!("[Warning] Not reusing the name {} for {} {}, since it's already taken", x.new, x.kind, x.index);
+            x.new = generated;
+            reused[i] = false;
+        }
     }
 }
 
@@ -565,7 +601,7 @@ impl<'a, 'b, 'c> ExprWriter<'a, 'b, 'c> {
                     .find(|(_, scr)| &*scr.name.0 == expr.name)
                     .map(|(i, _)| i)
                 {
-                    let _ = write!(self.output, "script{}", idx);
+                    self.output.extend_from_slice(&self.deobf.script_names[idx]);
                 } else {
                     self.output.extend_from_slice(expr.name);
                 }
@@ -793,3 +829,40 @@ fn op_to_str(op: Operator) -> &'static [u8] {
         Operator::Index => panic!("index op passed to op_to_str"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{process, Renamed};
+
+    fn renamed(kind: &str, index: usize, new: &str) -> Renamed {
+        Renamed { kind: kind.into(), index, old: "".into(), new: new.into() }
+    }
+
+    #[test]
+    fn name_map() {
+        let names = process(&mut crate::tests::fixture_with_gaps(), &[]);
+        assert_eq!(names[0], Renamed { kind: "sprite".into(), index: 0, old: "spr0".into(), new: "sprite0".into() });
+        assert!(names.iter().any(|x| x.kind == "script" && x.old == "scr_2" && x.new == "script2"));
+
+        // names are reused by index, unless they aren't valid or another asset has already got them
+        let previous = [
+            renamed("script", 2, "scr_move"),
+            renamed("sprite", 0, "spr_player"),
+            renamed("sprite", 2, "spr_player"),
+            renamed("sound", 0, "object4"),
+            renamed("object", 0, "not a name"),
+        ];
+        let mut assets = crate::tests::fixture_with_gaps();
+        assets.scripts[0].as_mut().unwrap().source = "return scr_2(1);".into();
+        let names = process(&mut assets, &previous);
+        assert_eq!(&*assets.scripts[2].as_ref().unwrap().name.0, b"scr_move");
+        assert_eq!(&*assets.scripts[0].as_ref().unwrap().source.0, b"return scr_move(1);\r\n");
+        assert_eq!(&*assets.sprites[0].as_ref().unwrap().name.0, b"spr_player");
+        assert_eq!(&*assets.sprites[2].as_ref().unwrap().name.0, b"sprite2");
+        assert_eq!(&*assets.sounds[0].as_ref().unwrap().name.0, b"sound0");
+        assert_eq!(&*assets.objects[0].as_ref().unwrap().name.0, b"object0");
+
+        let json = serde_json::to_string(&names).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Renamed>>(&json).unwrap(), names);
+    }
+}
@@ -0,0 +1,266 @@
+// Writes all the GML in a game out as a directory of .gml files, for when only the code is wanted.
+//
+// Scripts, events, timeline moments, trigger conditions and creation code each get a file named after their asset,
+// like scripts/scr_move.gml, objects/obj_player/Step_0.gml and rooms/rm_title/creation.gml.
+// Code is written exactly as it's stored, in the game's own encoding.
+
+use gm8exe::{asset::CodeAction, GameAssets};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// The event types, in the order objects store them.
+const EVENT_NAMES: [&str; 12] = [
+    "Create",
+    "Destroy",
+    "Alarm",
+    "Step",
+    "Collision",
+    "Keyboard",
+    "Mouse",
+    "Other",
+    "Draw",
+    "KeyPress",
+    "KeyRelease",
+    "Trigger",
+];
+
+// Writes the .gml files for a game into a directory, creating it if needed. Returns how many files were written.
+pub fn export(assets: &GameAssets, dir: &Path) -> io::Result<usize> {
+    let files = files(assets);
+    for (path, code) in files.iter() {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, code)?;
+    }
+    Ok(files.len())
+}
+
+// Lists the files to write for a game, as paths relative to the output directory along with their contents.
+pub fn files(assets: &GameAssets) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+
+    let names = unique_names(assets.scripts.iter().map(|x| x.as_ref().map(|x| &*x.name.0)), "script");
+    for (script, name) in assets.scripts.iter().zip(names) {
+        if let (Some(script), Some(name)) = (script, name) {
+            files.push((Path::new("scripts").join(name + ".gml"), script.source.0.to_vec()));
+        }
+    }
+
+    let names = unique_names(assets.objects.iter().map(|x| x.as_ref().map(|x| &*x.name.0)), "object");
+    for (object, name) in assets.objects.iter().zip(names) {
+        if let (Some(object), Some(name)) = (object, name) {
+            for (e1, events) in object.events.iter().enumerate() {
+                for (e2, actions) in events.iter().filter(|(_, x)| !x.is_empty()) {
+                    let event = match EVENT_NAMES.get(e1) {
+                        Some(event) => format!("{}_{}.gml", event, e2),
+                        None => format!("Event{}_{}.gml", e1, e2),
+                    };
+                    files.push((Path::new("objects").join(&name).join(event), actions_code(actions)));
+                }
+            }
+        }
+    }
+
+    let names = unique_names(assets.timelines.iter().map(|x| x.as_ref().map(|x| &*x.name.0)), "timeline");
+    for (timeline, name) in assets.timelines.iter().zip(names) {
+        if let (Some(timeline), Some(name)) = (timeline, name) {
+            for (moment, actions) in timeline.moments.iter().filter(|(_, x)| !x.is_empty()) {
+                let path = Path::new("timelines").join(&name).join(format!("moment_{}.gml", moment));
+                files.push((path, actions_code(actions)));
+            }
+        }
+    }
+
+    let names = unique_names(assets.triggers.iter().map(|x| x.as_ref().map(|x| &*x.name.0)), "trigger");
+    for (trigger, name) in assets.triggers.iter().zip(names) {
+        if let (Some(trigger), Some(name)) = (trigger, name) {
+            files.push((Path::new("triggers").join(name + ".gml"), trigger.condition.0.to_vec()));
+        }
+    }
+
+    // most rooms and instances don't have any creation code, so there's only a file for the ones which do
+    let names = unique_names(assets.rooms.iter().map(|x| x.as_ref().map(|x| &*x.name.0)), "room");
+    for (room, name) in assets.rooms.iter().zip(names) {
+        if let (Some(room), Some(name)) = (room, name) {
+            let dir = Path::new("rooms").join(name);
+            if !room.creation_code.0.is_empty() {
+                files.push((dir.join("creation.gml"), room.creation_code.0.to_vec()));
+            }
+            for instance in room.instances.iter().filter(|x| !x.creation_code.0.is_empty()) {
+                let path = dir.join(format!("instance_{}.gml", instance.id));
+                files.push((path, instance.creation_code.0.to_vec()));
+            }
+        }
+    }
+
+    files
+}
+
+// Puts together the code of an event or timeline moment. If there's more than one action, each starts with
+// a comment saying which it is, and drag and drop actions are summarised in a comment since they aren't GML.
+fn actions_code(actions: &[CodeAction]) -> Vec<u8> {
+    let mut code = Vec::new();
+    for (i, action) in actions.iter().enumerate() {
+        if !code.is_empty() && !code.ends_with(b"\n") {
+            code.extend_from_slice(b"\r\n");
+        }
+        if action.action_kind == 7 {
+            // Execute Code
+            if actions.len() > 1 {
+                code.extend_from_slice(format!("// action {}\r\n", i).as_bytes());
+            }
+            code.extend_from_slice(&action.param_strings[0].0);
+        } else {
+            let summary = format!("// action {}: drag and drop action {} from library {}", i, action.id, action.lib_id);
+            code.extend_from_slice(summary.as_bytes());
+            let params = &action.param_strings[..action.param_count.min(action.param_strings.len())];
+            for (j, param) in params.iter().enumerate() {
+                code.extend_from_slice(if j == 0 { b" (" } else { b", " });
+                // arguments can be whole expressions, which need to stay inside the comment
+                code.extend(param.0.iter().map(|&c| if c == b'\r' || c == b'\n' { b' ' } else { c }));
+            }
+            if !params.is_empty() {
+                code.push(b')');
+            }
+            code.extend_from_slice(b"\r\n");
+        }
+    }
+    code
+}
+
+// Works out a file or directory name for each asset in a list, or None for the deleted ones.
+// Anything besides letters, digits, - and _ becomes _, and assets with no name are called kind_index.
+// Names which end up the same, ignoring case since Windows does, all have their index added on.
+pub(crate) fn unique_names<'a>(names: impl Iterator<Item = Option<&'a [u8]>>, kind: &str) -> Vec<Option<String>> {
+    let names = names
+        .enumerate()
+        .map(|(i, name)| {
+            let name = name?
+                .iter()
+                .map(|&c| if c.is_ascii_alphanumeric() || c == b'-' { c as char } else { '_' })
+                .collect::<String>();
+            Some(if name.is_empty() { format!("{}_{}", kind, i) } else { name })
+        })
+        .collect::<Vec<_>>();
+    let mut counts = HashMap::new();
+    for name in names.iter().flatten() {
+        *counts.entry(name.to_ascii_lowercase()).or_insert(0) += 1;
+    }
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let name = name?;
+            Some(if counts[&name.to_ascii_lowercase()] > 1 { format!("{}_{}", name, i) } else { name })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, files, unique_names};
+    use gm8exe::asset::{room::Instance, trigger::TriggerKind, CodeAction, PascalString, Trigger};
+    use std::path::Path;
+
+    fn action(kind: u32, id: u32, params: &[&str]) -> CodeAction {
+        let mut param_strings: [PascalString; 8] = Default::default();
+        for (param, string) in params.iter().zip(param_strings.iter_mut()) {
+            *string = (*param).into();
+        }
+        CodeAction {
+            id,
+            applies_to: -1,
+            is_condition: false,
+            invert_condition: false,
+            is_relative: false,
+            lib_id: 1,
+            action_kind: kind,
+            execution_type: if kind == 7 { 2 } else { 1 },
+            can_be_relative: 0,
+            applies_to_something: true,
+            fn_name: "".into(),
+            fn_code: "".into(),
+            param_count: params.len(),
+            param_types: [0; 8],
+            param_strings,
+        }
+    }
+
+    #[test]
+    fn names() {
+        let names = [Some(&b"scr_move"[..]), None, Some(b""), Some(b"Move"), Some(b"move"), Some(b"a b/c")];
+        assert_eq!(unique_names(names.iter().copied(), "script"), [
+            Some("scr_move".to_string()),
+            None,
+            Some("script_2".to_string()),
+            Some("Move_3".to_string()),
+            Some("move_4".to_string()),
+            Some("a_b_c".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn lists_files() {
+        let mut assets = crate::tests::fixture_with_gaps();
+        assets.scripts.truncate(2);
+        let object = assets.objects[2].as_mut().unwrap();
+        object.events = (0..12).map(|_| Vec::new()).collect();
+        object.events[3] = vec![(0, vec![action(7, 603, &["x += 1;"])])];
+        object.events[7] = vec![(10, vec![action(1, 203, &["x", "5\r\n+ 1"]), action(7, 603, &["y = 2;"])])];
+        object.events[4] = vec![(4, Vec::new())];
+        assets.timelines[0].as_mut().unwrap().moments = vec![(30, vec![action(7, 603, &["a = 1;"])])];
+        let trigger = Trigger {
+            name: "".into(),
+            condition: "x > 3".into(),
+            moment: TriggerKind::Step,
+            constant_name: "trig".into(),
+        };
+        assets.triggers = vec![None, Some(Box::new(trigger))];
+        let room = assets.rooms[4].as_mut().unwrap();
+        room.creation_code = "global.lives = 3;".into();
+        room.instances = [(100001, ""), (100002, "image_speed = 0;")]
+            .iter()
+            .map(|&(id, code)| Instance {
+                x: 0,
+                y: 0,
+                object: 0,
+                id,
+                creation_code: code.into(),
+                xscale: 1.0,
+                yscale: 1.0,
+                blend: u32::MAX,
+                angle: 0.0,
+            })
+            .collect();
+
+        let files = files(&assets);
+        let listed = files
+            .iter()
+            .map(|(path, code)| (path.to_str().unwrap().replace('\\', "/"), String::from_utf8(code.clone()).unwrap()))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("scripts/scr_0.gml", "return argument0 * 0;"),
+            ("scripts/scr_1.gml", "return argument0 * 1;"),
+            ("objects/obj2/Step_0.gml", "x += 1;"),
+            (
+                "objects/obj2/Other_10.gml",
+                "// action 0: drag and drop action 203 from library 1 (x, 5  + 1)\r\n// action 1\r\ny = 2;",
+            ),
+            ("timelines/tl0/moment_30.gml", "a = 1;"),
+            ("triggers/trigger_1.gml", "x > 3"),
+            ("rooms/rm4/creation.gml", "global.lives = 3;"),
+            ("rooms/rm4/instance_100002.gml", "image_speed = 0;"),
+        ];
+        assert_eq!(listed, expected.iter().map(|&(x, y)| (x.to_string(), y.to_string())).collect::<Vec<_>>());
+
+        let dir = std::env::temp_dir().join(format!("gm8decompiler-export-{}", std::process::id()));
+        assert_eq!(export(&assets, &dir).unwrap(), expected.len());
+        assert_eq!(std::fs::read(dir.join(Path::new("objects/obj2/Step_0.gml"))).unwrap(), b"x += 1;");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
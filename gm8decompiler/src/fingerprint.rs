@@ -0,0 +1,101 @@
+// Identifies a game by the data its assets are stored as, rather than by the whole exe,
+// so the same game can be recognised however it's been packed or protected.
+//
+// The format is plain text, and must not change without changing the version in the header,
+// since people keep databases of these:
+//
+//     gm8decompiler fingerprint v2
+//     <kind> <index> <sha256>
+//     ...
+//     content <sha256>
+//
+// Every asset which is stored as its own compressed block gets a line, listed by kind in the order
+// they're stored in the exe (see AssetKind::ALL) and then by index. Deleted assets are listed too,
+// since their slots are still stored. Hashes are of each block's data after it's been decrypted and
+// decompressed, in lowercase hex, so recompressing a game at another zlib level doesn't change them.
+// A block which doesn't decompress is hashed as it's stored. (v1 hashed every block as it's stored.)
+//
+// The content hash is the SHA-256 of all the asset lines, each with a trailing "\n".
+
+use flate2::read::ZlibDecoder;
+use gm8exe::{sha256, AssetKind, GameAssets};
+use std::io::Read;
+
+const HEADER: &str = "gm8decompiler fingerprint v2";
+
+// Writes the fingerprint of a game. This is None unless the game was read with ReaderOptions::keep_blobs.
+pub fn fingerprint(assets: &GameAssets) -> Option<String> {
+    let blobs = assets.blobs.as_ref()?;
+    let mut lines = String::new();
+    for kind in AssetKind::ALL.iter().copied() {
+        for (i, blob) in blobs[kind as usize].iter().enumerate() {
+            lines += &format!("{} {} {}\n", kind.name(), i, hash(&inflate(blob).unwrap_or_else(|| blob.to_vec())));
+        }
+    }
+    Some(format!("{}\n{}content {}\n", HEADER, lines, hash(lines.as_bytes())))
+}
+
+fn inflate(blob: &[u8]) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(blob).read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+fn hash(data: &[u8]) -> String {
+    sha256::hash(data).expect("reading from a slice can't fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fingerprint;
+    use flate2::{write::ZlibEncoder, Compression};
+    use gm8exe::{AssetKind, GameAssets};
+    use std::io::Write;
+
+    fn deflate(data: &[u8], level: u32) -> Box<[u8]> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap().into()
+    }
+
+    // If this fails, every fingerprint anyone has recorded has changed. Don't just update it - bump the version.
+    #[test]
+    fn stable_format() {
+        let mut assets: GameAssets = crate::tests::fixture();
+        assert_eq!(fingerprint(&assets), None);
+
+        // the same data compressed at any level, apart from the included file, which isn't zlib data at all
+        let mut expected = None;
+        for &level in &[0, 6, 9] {
+            let mut blobs: gm8exe::AssetBlobs = Default::default();
+            blobs[AssetKind::Sprite as usize] = vec![deflate(b"spr0", level), deflate(b"", level)];
+            blobs[AssetKind::Script as usize] = vec![deflate(b"scr0", level)];
+            blobs[AssetKind::IncludedFile as usize] = vec![b"file0".to_vec().into()];
+            assets.blobs = Some(blobs);
+            let printed = fingerprint(&assets).unwrap();
+            assert_eq!(expected.get_or_insert_with(|| printed.clone()), &printed, "level {}", level);
+        }
+        assert_eq!(
+            expected.unwrap(),
+            "gm8decompiler fingerprint v2\n\
+             sprite 0 9d5a99ab65c0848520831d9dd1217b8e12f7ac3a8f5c14758964f625e0c2dd68\n\
+             sprite 1 e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n\
+             script 0 cdbb6480a03e26a8864767802212459a03d815ab91dc28e9dda28ccf027198d6\n\
+             included_file 0 56f3fd843f7ae959a8409e0ae7c067a0e862a6faa7a22bad147ee90ee5992bd7\n\
+             content 46c56b72c6dc9b6b839371e3cde575ace5c7d42b0181b65d827cbff6f2327328\n",
+        );
+    }
+
+    // The same game read again, or read on one thread, has to come out the same
+    #[test]
+    fn repeatable() {
+        for (name, exe) in conformance::all_games() {
+            let read = |multithread| {
+                let options = gm8exe::reader::ReaderOptions { multithread, keep_blobs: true, ..Default::default() };
+                let assets = gm8exe::reader::from_exe(exe.clone(), None::<fn(&str)>, options).unwrap();
+                fingerprint(&assets).unwrap()
+            };
+            assert!(read(true) == read(false), "fingerprint for {} isn't repeatable", name);
+        }
+    }
+}
@@ -7,7 +7,13 @@ use gm8exe::{
     GameAssets, GameVersion,
 };
 use rayon::prelude::*;
-use std::{convert::TryInto, io, u32};
+use std::{
+    convert::TryInto,
+    io,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+    u32,
+};
 
 pub trait WriteBuffer: io::Write {
     fn write_buffer(&mut self, buf: &[u8]) -> io::Result<usize> {
@@ -34,27 +40,79 @@ where
     Ok(())
 }
 
-// Write a timestamp (currently writes 0, which correlates to 1899-01-01)
+// A "last changed" time to write for the settings and each asset, since the exe doesn't store any.
+// GameMaker stores these as Delphi TDateTime values: the number of days since 1899-12-30, as a double.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Timestamp {
+    #[default]
+    Zero,        // 1899-12-30, which is what has always been written
+    Unix(f64),   // seconds since 1970-01-01
+    Serial(f64), // a raw TDateTime
+}
+
+impl Timestamp {
+    // The current time, from the system clock.
+    pub fn now() -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        Timestamp::Unix(now)
+    }
+
+    // The TDateTime value which actually gets written.
+    pub fn to_serial(self) -> f64 {
+        // 1970-01-01 is day 25569 in TDateTime
+        match self {
+            Timestamp::Zero => 0.0,
+            Timestamp::Unix(secs) => secs / 86400.0 + 25569.0,
+            Timestamp::Serial(days) => days,
+        }
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    // Parses "zero", "now", "unix:<seconds>" or "gm-serial:<days>".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_f64 = |x: &str| x.parse::<f64>().ok().filter(|x| x.is_finite());
+        let timestamp = match s.split_once(':') {
+            None if s == "zero" => Some(Timestamp::Zero),
+            None if s == "now" => Some(Timestamp::now()),
+            Some(("unix", secs)) => parse_f64(secs).map(Timestamp::Unix),
+            Some(("gm-serial", days)) => parse_f64(days).map(Timestamp::Serial),
+            _ => None,
+        };
+        timestamp.ok_or_else(|| {
+            format!("invalid timestamp '{}' (expected zero, now, unix:<secs> or gm-serial:<days>)", s)
+        })
+    }
+}
+
+// Writes a timestamp
 #[inline]
-pub fn write_timestamp<W>(writer: &mut W) -> io::Result<()>
+pub fn write_timestamp<W>(writer: &mut W, timestamp: Timestamp) -> io::Result<()>
 where
     W: io::Write,
 {
-    writer.write_u64::<LE>(0)
+    writer.write_f64::<LE>(timestamp.to_serial())
 }
 
 // Writes a settings block to GMK
+#[allow(clippy::too_many_arguments)]
 pub fn write_settings<W>(
     writer: &mut W,
     settings: &Settings,
     ico_file: Option<Vec<u8>>,
     version: GameVersion,
+    author: &PascalString,
+    company: &PascalString,
+    timestamp: Timestamp,
+    compression: Compression,
 ) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_u32::<LE>(800)?;
-    let mut enc = ZlibWriter::new();
+    let mut enc = ZlibWriter::with_compression(compression);
     enc.write_u32::<LE>(settings.fullscreen as u32)?;
     enc.write_u32::<LE>(settings.interpolate_pixels as u32)?;
     enc.write_u32::<LE>(settings.dont_draw_border as u32)?;
@@ -91,7 +149,7 @@ where
         match &settings.backdata {
             Some(data) => {
                 enc.write_u32::<LE>(1)?;
-                let mut backdata_enc = ZlibWriter::new();
+                let mut backdata_enc = ZlibWriter::with_compression(compression);
                 backdata_enc.write_buffer(&data)?;
                 backdata_enc.finish(&mut enc)?;
             },
@@ -103,7 +161,7 @@ where
         match &settings.frontdata {
             Some(data) => {
                 enc.write_u32::<LE>(1)?;
-                let mut frontdata_enc = ZlibWriter::new();
+                let mut frontdata_enc = ZlibWriter::with_compression(compression);
                 frontdata_enc.write_buffer(&data)?;
                 frontdata_enc.finish(&mut enc)?;
             },
@@ -120,7 +178,7 @@ where
             // we need to write two redundant "true"s here.
             enc.write_u32::<LE>(1)?;
             enc.write_u32::<LE>(1)?;
-            let mut ci_enc = ZlibWriter::new();
+            let mut ci_enc = ZlibWriter::with_compression(compression);
             ci_enc.write_buffer(&data)?;
             ci_enc.finish(&mut enc)?;
         },
@@ -150,9 +208,9 @@ where
         )?,
     };
 
-    enc.write_pas_string(&"decompiler clan :police_car: :police_car: :police_car:".into())?; // author
+    enc.write_pas_string(author)?; // author
     enc.write_pas_string(&"".into())?; // version string
-    write_timestamp(&mut enc)?; // timestamp
+    write_timestamp(&mut enc, timestamp)?; // timestamp
     enc.write_pas_string(&"".into())?; // information
 
     // TODO: extract all this stuff from .rsrc in gm8x
@@ -160,11 +218,11 @@ where
     enc.write_u32::<LE>(0)?; // minor version
     enc.write_u32::<LE>(0)?; // release version
     enc.write_u32::<LE>(0)?; // build version
-    enc.write_pas_string(&"".into())?; // company
+    enc.write_pas_string(company)?; // company
     enc.write_pas_string(&"".into())?; // product
     enc.write_pas_string(&"".into())?; // copyright info
     enc.write_pas_string(&"".into())?; // description
-    write_timestamp(&mut enc)?; // timestamp
+    write_timestamp(&mut enc, timestamp)?; // timestamp
 
     enc.finish(writer)?;
 
@@ -177,12 +235,14 @@ pub fn write_asset_list<W, T, F>(
     list: &[Option<Box<T>>],
     write_fn: F,
     version: GameVersion,
+    timestamp: Timestamp,
+    compression: Compression,
     multithread: bool,
 ) -> io::Result<()>
 where
     T: Send + Sync,
     W: io::Write,
-    F: Fn(&mut ZlibEncoder<Vec<u8>>, &T, GameVersion) -> io::Result<()> + Send + Sync,
+    F: Fn(&mut ZlibEncoder<Vec<u8>>, &T, GameVersion, Timestamp) -> io::Result<()> + Send + Sync,
 {
     writer.write_u32::<LE>(800)?;
     writer.write_u32::<LE>(list.len() as u32)?;
@@ -190,11 +250,11 @@ where
     if multithread {
         list.par_iter()
             .map(|asset| {
-                let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+                let mut enc = ZlibEncoder::new(Vec::new(), compression);
                 match asset {
                     Some(asset) => {
                         enc.write_u32::<LE>(true as u32)?;
-                        write_fn(&mut enc, asset, version)?;
+                        write_fn(&mut enc, asset, version, timestamp)?;
                     },
                     None => {
                         enc.write_u32::<LE>(false as u32)?;
@@ -211,11 +271,11 @@ where
             })
     } else {
         for asset in list {
-            let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+            let mut enc = ZlibEncoder::new(Vec::new(), compression);
             match asset {
                 Some(asset) => {
                     enc.write_u32::<LE>(true as u32)?;
-                    write_fn(&mut enc, asset, version)?;
+                    write_fn(&mut enc, asset, version, timestamp)?;
                 },
                 None => {
                     enc.write_u32::<LE>(false as u32)?;
@@ -230,7 +290,12 @@ where
 }
 
 // Writes a trigger (uncompressed data)
-pub fn write_trigger<W>(writer: &mut W, trigger: &asset::Trigger, _version: GameVersion) -> io::Result<()>
+pub fn write_trigger<W>(
+    writer: &mut W,
+    trigger: &asset::Trigger,
+    _version: GameVersion,
+    _timestamp: Timestamp,
+) -> io::Result<()>
 where
     W: io::Write,
 {
@@ -244,7 +309,7 @@ where
 
 // Writes a list of constants
 // This isn't compatible with write_asset_list because constants have a different, simpler format than most assets.
-pub fn write_constants<W>(writer: &mut W, constants: &[asset::Constant]) -> io::Result<()>
+pub fn write_constants<W>(writer: &mut W, constants: &[asset::Constant], timestamp: Timestamp) -> io::Result<()>
 where
     W: io::Write,
 {
@@ -254,17 +319,22 @@ where
         writer.write_pas_string(&constant.name)?;
         writer.write_pas_string(&constant.expression)?;
     }
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     Ok(())
 }
 
 // Writes a Sound (uncompressed data)
-pub fn write_sound<W>(writer: &mut W, sound: &asset::Sound, _version: GameVersion) -> io::Result<()>
+pub fn write_sound<W>(
+    writer: &mut W,
+    sound: &asset::Sound,
+    _version: GameVersion,
+    timestamp: Timestamp,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&sound.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(800)?;
     writer.write_u32::<LE>(sound.kind as u32)?;
     writer.write_pas_string(&sound.extension)?;
@@ -294,13 +364,18 @@ where
 }
 
 // Writes a Sprite (uncompressed data)
-pub fn write_sprite<W>(writer: &mut W, sprite: &asset::Sprite, _version: GameVersion) -> io::Result<()>
+pub fn write_sprite<W>(
+    writer: &mut W,
+    sprite: &asset::Sprite,
+    _version: GameVersion,
+    timestamp: Timestamp,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     let gmk_collision = collision::resolve_map(sprite);
     writer.write_pas_string(&sprite.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(800)?;
     writer.write_i32::<LE>(sprite.origin_x)?;
     writer.write_i32::<LE>(sprite.origin_y)?;
@@ -342,12 +417,17 @@ where
 }
 
 // Writes a Background (uncompressed data)
-pub fn write_background<W>(writer: &mut W, background: &asset::Background, _version: GameVersion) -> io::Result<()>
+pub fn write_background<W>(
+    writer: &mut W,
+    background: &asset::Background,
+    _version: GameVersion,
+    timestamp: Timestamp,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&background.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(710)?;
 
     // Tileset info isn't in exe - not sure if there's a consistent way to reverse it...
@@ -374,12 +454,12 @@ where
 }
 
 // Writes a Path (uncompressed data)
-pub fn write_path<W>(writer: &mut W, path: &asset::Path, _version: GameVersion) -> io::Result<()>
+pub fn write_path<W>(writer: &mut W, path: &asset::Path, _version: GameVersion, timestamp: Timestamp) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&path.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(530)?;
     writer.write_u32::<LE>(path.connection as u32)?;
     writer.write_u32::<LE>(path.closed as u32)?;
@@ -397,24 +477,29 @@ where
 }
 
 // Writes a Script (uncompressed data)
-pub fn write_script<W>(writer: &mut W, script: &asset::Script, _version: GameVersion) -> io::Result<()>
+pub fn write_script<W>(
+    writer: &mut W,
+    script: &asset::Script,
+    _version: GameVersion,
+    timestamp: Timestamp,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&script.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(800)?;
     writer.write_pas_string(&script.source)?;
     Ok(())
 }
 
 // Writes a Font (uncompressed data)
-pub fn write_font<W>(writer: &mut W, font: &asset::Font, version: GameVersion) -> io::Result<()>
+pub fn write_font<W>(writer: &mut W, font: &asset::Font, version: GameVersion, timestamp: Timestamp) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&font.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(800)?;
     writer.write_pas_string(&font.sys_name)?;
     writer.write_u32::<LE>(font.size)?;
@@ -462,12 +547,17 @@ where
 }
 
 // Writes a Timeline (uncompressed data)
-pub fn write_timeline<W>(writer: &mut W, timeline: &asset::Timeline, _version: GameVersion) -> io::Result<()>
+pub fn write_timeline<W>(
+    writer: &mut W,
+    timeline: &asset::Timeline,
+    _version: GameVersion,
+    timestamp: Timestamp,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&timeline.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(500)?;
     writer.write_u32::<LE>(timeline.moments.len() as u32)?;
     for (moment, actions) in &timeline.moments {
@@ -482,12 +572,17 @@ where
 }
 
 // Writes an Object (uncompressed data)
-pub fn write_object<W>(writer: &mut W, object: &asset::Object, _version: GameVersion) -> io::Result<()>
+pub fn write_object<W>(
+    writer: &mut W,
+    object: &asset::Object,
+    _version: GameVersion,
+    timestamp: Timestamp,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&object.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(430)?;
     writer.write_i32::<LE>(object.sprite_index)?;
     writer.write_u32::<LE>(object.solid as u32)?;
@@ -512,12 +607,12 @@ where
 }
 
 // Writes an Room (uncompressed data)
-pub fn write_room<W>(writer: &mut W, room: &asset::Room, _: GameVersion) -> io::Result<()>
+pub fn write_room<W>(writer: &mut W, room: &asset::Room, _: GameVersion, timestamp: Timestamp) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_pas_string(&room.name)?;
-    write_timestamp(writer)?;
+    write_timestamp(writer, timestamp)?;
     writer.write_u32::<LE>(541)?;
     writer.write_pas_string(&room.caption)?;
     writer.write_u32::<LE>(room.width)?;
@@ -620,15 +715,20 @@ where
 
 // Write included files to gmk
 // Note: not compatible with write_asset_list because included files can't not exist
-pub fn write_included_files<W>(writer: &mut W, files: &[asset::IncludedFile]) -> io::Result<()>
+pub fn write_included_files<W>(
+    writer: &mut W,
+    files: &[asset::IncludedFile],
+    timestamp: Timestamp,
+    compression: Compression,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_u32::<LE>(800)?;
     writer.write_u32::<LE>(files.len() as u32)?;
     for file in files {
-        let mut enc = ZlibWriter::new();
-        write_timestamp(&mut enc)?;
+        let mut enc = ZlibWriter::with_compression(compression);
+        write_timestamp(&mut enc, timestamp)?;
         enc.write_u32::<LE>(800)?;
         enc.write_pas_string(&file.file_name)?;
         enc.write_pas_string(&file.source_path)?;
@@ -679,13 +779,18 @@ where
 }
 
 // Write game information (help dialog) block to GMK
-pub fn write_game_information<W>(writer: &mut W, info: &GameHelpDialog) -> io::Result<()>
+pub fn write_game_information<W>(
+    writer: &mut W,
+    info: &GameHelpDialog,
+    timestamp: Timestamp,
+    compression: Compression,
+) -> io::Result<()>
 where
     W: io::Write,
 {
     writer.write_u32::<LE>(800)?; // TODO: why is this hardcoded?? come on adam
     // maybe others are too ?
-    let mut enc = ZlibWriter::new();
+    let mut enc = ZlibWriter::with_compression(compression);
     enc.write_u32::<LE>(info.bg_colour.into())?;
     enc.write_u32::<LE>(info.new_window as u32)?;
     enc.write_pas_string(&info.caption)?;
@@ -697,7 +802,7 @@ where
     enc.write_u32::<LE>(info.resizable as u32)?;
     enc.write_u32::<LE>(info.window_on_top as u32)?;
     enc.write_u32::<LE>(info.freeze_game as u32)?;
-    write_timestamp(&mut enc)?;
+    write_timestamp(&mut enc, timestamp)?;
     enc.write_pas_string(&info.info)?;
     enc.finish(writer)?;
     Ok(())
@@ -808,14 +913,25 @@ where
     for (i, object) in enumerate_existing(&assets.objects) {
         write_rt_asset(writer, &object.name, 1, i as u32)?;
     }
-    write_rt_heading(writer, "Rooms", 4, count_existing(&assets.rooms))?;
-    for room_id in &assets.room_order {
-        if let Some(Some(room)) = assets.rooms.get(*room_id as usize) {
-            write_rt_asset(writer, &room.name, 4, *room_id as u32)?;
-        } else {
-            println!("WARNING: non-existent room id {} referenced in Room Order; skipping it", *room_id);
+    // Rooms go in room order, like in GM8's own tree. Entries which don't point to a room, or which repeat an
+    // earlier one, are left out, and the heading's count has to agree with what's actually written.
+    let room_order = &assets.room_order;
+    let is_listed = |i: usize, room_id: i32| {
+        room_id >= 0
+            && matches!(assets.rooms.get(room_id as usize), Some(Some(_)))
+            && !room_order[..i].contains(&room_id)
+    };
+    let count = room_order.iter().enumerate().filter(|&(i, &room_id)| is_listed(i, room_id)).count();
+    write_rt_heading(writer, "Rooms", 4, count)?;
+    for (i, &room_id) in room_order.iter().enumerate() {
+        match assets.rooms.get(room_id as usize) {
+            Some(Some(room)) if is_listed(i, room_id) => write_rt_asset(writer, &room.name, 4, room_id as u32)?,
+            _ => println!("WARNING: invalid or repeated room id {} in Room Order; skipping it", room_id),
         }
     }
+    for (i, _) in enumerate_existing(&assets.rooms).filter(|&(i, _)| !room_order.contains(&(i as i32))) {
+        println!("WARNING: room {} isn't in Room Order, so it won't be in the resource tree", i);
+    }
     write_rt_asset(writer, &"Game Information".into(), 10, 0)?;
     write_rt_asset(writer, &"Global Game Settings".into(), 11, 0)?;
     write_rt_asset(writer, &"Extension Packages".into(), 13, 0)?;
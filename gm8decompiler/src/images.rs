@@ -0,0 +1,173 @@
+// Writes a game's sprites and backgrounds out as PNG files, for ripping graphics without opening the project.
+//
+// Every sprite frame gets its own file, like sprites/spr_player/0.png, and backgrounds are like backgrounds/bg_sky.png.
+// With strips turned on, each sprite is instead one horizontal strip named like sprites/spr_player_strip4.png,
+// which is the name GameMaker looks for when creating a sprite from a strip.
+
+use crate::export::unique_names;
+use gm8exe::{asset::sprite::Frame, GameAssets};
+use image::{codecs::png::PngEncoder, ColorType};
+use rayon::prelude::*;
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// An image to be written, with its pixels in BGRA order like they're stored in the game.
+struct Image<'a> {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    pixels: Cow<'a, [u8]>,
+}
+
+// Writes the PNG files for a game into a directory, creating it if needed. Returns how many files were written.
+// Encoding is the slow part, so with multithread on, images are encoded and written in parallel.
+pub fn export_images(assets: &GameAssets, dir: &Path, strips: bool, multithread: bool) -> io::Result<usize> {
+    let images = images(assets, strips);
+    let write = |image: &Image| {
+        let path = dir.join(&image.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, png(image)?)
+    };
+    if multithread {
+        images.par_iter().try_for_each(write)?;
+    } else {
+        images.iter().try_for_each(write)?;
+    }
+    Ok(images.len())
+}
+
+// Lists the images to write for a game, as paths relative to the output directory.
+// Frames and backgrounds with no pixels, or the wrong number of them, are left out.
+fn images(assets: &GameAssets, strips: bool) -> Vec<Image<'_>> {
+    let mut images = Vec::new();
+
+    let names = unique_names(assets.sprites.iter().map(|x| x.as_ref().map(|x| &*x.name.0)), "sprite");
+    for (sprite, name) in assets.sprites.iter().zip(names) {
+        if let (Some(sprite), Some(name)) = (sprite, name) {
+            if strips {
+                let path = Path::new("sprites").join(format!("{}_strip{}.png", name, sprite.frames.len()));
+                if let Some((width, height, pixels)) = strip(&sprite.frames) {
+                    images.push(Image { path, width, height, pixels: pixels.into() });
+                }
+            } else {
+                for (i, frame) in sprite.frames.iter().enumerate().filter(|(_, x)| has_pixels(x)) {
+                    images.push(Image {
+                        path: Path::new("sprites").join(&name).join(format!("{}.png", i)),
+                        width: frame.width,
+                        height: frame.height,
+                        pixels: (&*frame.data).into(),
+                    });
+                }
+            }
+        }
+    }
+
+    let names = unique_names(assets.backgrounds.iter().map(|x| x.as_ref().map(|x| &*x.name.0)), "background");
+    for (background, name) in assets.backgrounds.iter().zip(names) {
+        if let (Some(background), Some(name)) = (background, name) {
+            if let Some(data) = &background.data {
+                let (width, height) = (background.width, background.height);
+                if width > 0 && height > 0 && data.len() == width as usize * height as usize * 4 {
+                    let path = Path::new("backgrounds").join(name + ".png");
+                    images.push(Image { path, width, height, pixels: (&**data).into() });
+                }
+            }
+        }
+    }
+
+    images
+}
+
+fn has_pixels(frame: &Frame) -> bool {
+    frame.width > 0 && frame.height > 0 && frame.data.len() == frame.width as usize * frame.height as usize * 4
+}
+
+// Puts a sprite's frames side by side, each in a space the size of the biggest frame.
+// Frames which are smaller are in the top left of their space, and ones with no pixels are left transparent.
+fn strip(frames: &[Frame]) -> Option<(u32, u32, Vec<u8>)> {
+    let width = frames.iter().filter(|x| has_pixels(x)).map(|x| x.width as usize).max()?;
+    let height = frames.iter().filter(|x| has_pixels(x)).map(|x| x.height as usize).max()?;
+    let stride = width * 4 * frames.len();
+    let mut pixels = vec![0; stride * height];
+    for (i, frame) in frames.iter().enumerate().filter(|(_, x)| has_pixels(x)) {
+        for (y, row) in frame.data.chunks_exact(frame.width as usize * 4).enumerate() {
+            let start = y * stride + i * width * 4;
+            pixels[start..start + row.len()].copy_from_slice(row);
+        }
+    }
+    Some(((width * frames.len()) as u32, height as u32, pixels))
+}
+
+// Encodes an image as a PNG, which needs it to be in RGBA order.
+fn png(image: &Image) -> io::Result<Vec<u8>> {
+    let mut rgba = image.pixels.to_vec();
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).encode(&rgba, image.width, image.height, ColorType::Rgba8).map_err(io::Error::other)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export_images, images, png};
+    use gm8exe::asset::sprite::Frame;
+    use std::path::Path;
+
+    // A frame filled with one BGRA colour.
+    fn frame(width: u32, height: u32, bgra: [u8; 4]) -> Frame {
+        let data = bgra.iter().copied().cycle().take(width as usize * height as usize * 4).collect();
+        Frame { width, height, data }
+    }
+
+    #[test]
+    fn lists_images() {
+        let mut assets = crate::tests::fixture_with_gaps();
+        let red = [0, 0, 255, 255];
+        let blue = [255, 0, 0, 128];
+        assets.sprites[0].as_mut().unwrap().frames = vec![frame(2, 1, red), frame(0, 0, red), frame(1, 2, blue)];
+        assets.sprites[2].as_mut().unwrap().frames = vec![frame(1, 1, blue)];
+        let background = assets.backgrounds[2].as_mut().unwrap();
+        background.width = 3;
+        background.height = 1;
+        background.data = Some(frame(3, 1, red).data);
+
+        let paths = |strips| {
+            images(&assets, strips)
+                .iter()
+                .map(|x| (x.path.to_str().unwrap().replace('\\', "/"), x.width, x.height))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(false), [
+            ("sprites/spr0/0.png".to_string(), 2, 1),
+            ("sprites/spr0/2.png".to_string(), 1, 2),
+            ("sprites/spr2/0.png".to_string(), 1, 1),
+            ("backgrounds/bg2.png".to_string(), 3, 1),
+        ]);
+        assert_eq!(paths(true), [
+            ("sprites/spr0_strip3.png".to_string(), 6, 2),
+            ("sprites/spr2_strip1.png".to_string(), 1, 1),
+            ("backgrounds/bg2.png".to_string(), 3, 1),
+        ]);
+
+        // each frame starts two pixels along from the last, with the space around smaller ones left transparent
+        let strip = images(&assets, true).remove(0);
+        let decoded = image::load_from_memory(&png(&strip).unwrap()).unwrap().to_rgba8();
+        let pixels = decoded.pixels().map(|x| x.0).collect::<Vec<_>>();
+        let (r, b, none) = ([255, 0, 0, 255], [0, 0, 255, 128], [0; 4]);
+        assert_eq!(pixels, [[r, r, none, none, b, none], [none, none, none, none, b, none]].concat());
+
+        let dir = std::env::temp_dir().join(format!("gm8decompiler-images-{}", std::process::id()));
+        assert_eq!(export_images(&assets, &dir, false, true).unwrap(), 4);
+        let background = image::open(dir.join(Path::new("backgrounds/bg2.png"))).unwrap().to_rgba8();
+        assert_eq!(background.dimensions(), (3, 1));
+        assert!(background.pixels().all(|x| x.0 == r));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+// A journal is a small text file kept next to the output while it's being written.
+// It records which blocks of the project file are complete and where each one ends,
+// so a decompilation which got interrupted can carry on from the last complete block with --resume.
+//
+// The journal also holds a fingerprint of the input and settings, so it's only used if resuming
+// would produce exactly the same file, and the timestamp which was used, in case it was "now".
+
+use crate::gmk::Timestamp;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::Hasher,
+    io,
+    path::{Path, PathBuf},
+};
+
+const HEADER: &str = "gm8decompiler journal v1";
+
+pub struct Journal {
+    path: PathBuf,
+    fingerprint: u64,
+    timestamp: Timestamp,
+    blocks: Vec<(u64, u64)>,
+}
+
+// Fingerprints the contents of the input file and any others which go into the output, like name maps,
+// along with a description of any settings which affect the output.
+// This doesn't need to be stable between builds - a mismatch only means starting from scratch.
+pub fn fingerprint(inputs: &[&[u8]], settings: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for input in inputs {
+        // the length goes first, so moving bytes from the end of one file to the start of the next still counts
+        hasher.write_u64(input.len() as u64);
+        hasher.write(input);
+    }
+    hasher.write(settings.as_bytes());
+    hasher.finish()
+}
+
+// The path of the journal for a given output file.
+pub fn path_for(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".journal");
+    path.into()
+}
+
+impl Journal {
+    // Starts a new journal, overwriting any old one.
+    pub fn create(path: PathBuf, fingerprint: u64, timestamp: Timestamp) -> io::Result<Self> {
+        let journal = Self { path, fingerprint, timestamp, blocks: Vec::new() };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    // Loads a journal, if there is one and it was written for the same fingerprint.
+    pub fn load(path: PathBuf, fingerprint: u64) -> Option<Self> {
+        let text = fs::read_to_string(&path).ok()?;
+        if !text.ends_with('\n') {
+            return None
+        }
+        let mut lines = text.lines();
+        if lines.next()? != HEADER {
+            return None
+        }
+        let hex = |x: Option<&str>| x.and_then(|x| u64::from_str_radix(x, 16).ok());
+        if hex(lines.next()?.strip_prefix("fingerprint "))? != fingerprint {
+            return None
+        }
+        let timestamp = Timestamp::Serial(f64::from_bits(hex(lines.next()?.strip_prefix("timestamp "))?));
+
+        // Each block has to start where the last one ended, otherwise something's wrong
+        let mut blocks = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let mut fields = line.strip_prefix("block ")?.split(' ').map(|x| x.parse::<u64>().ok());
+            match (fields.next()??, fields.next()??, fields.next()??, fields.next()) {
+                (index, start, end, None)
+                    if index == i as u64 && start == blocks.last().map_or(0, |&(_, end)| end) && end >= start =>
+                {
+                    blocks.push((start, end))
+                },
+                _ => return None,
+            }
+        }
+        Some(Self { path, fingerprint, timestamp, blocks })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut text = format!(
+            "{}\nfingerprint {:016x}\ntimestamp {:016x}\n",
+            HEADER,
+            self.fingerprint,
+            self.timestamp.to_serial().to_bits()
+        );
+        for (i, (start, end)) in self.blocks.iter().enumerate() {
+            text += &format!("block {} {} {}\n", i, start, end);
+        }
+        // Write it somewhere else first, so the journal is never left half-written
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, text)?;
+        fs::rename(&temp, &self.path)
+    }
+
+    // The timestamp the project is being written with.
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    // How many blocks have been written completely.
+    pub fn completed(&self) -> usize {
+        self.blocks.len()
+    }
+
+    // Where the last complete block ends, which is where writing should carry on from.
+    pub fn end(&self) -> u64 {
+        self.blocks.last().map_or(0, |&(_, end)| end)
+    }
+
+    // Records that the next block has been written, and ends at the given position.
+    pub fn record(&mut self, end: u64) -> io::Result<()> {
+        let start = self.end();
+        if end < start {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "block ends before it starts"))
+        }
+        self.blocks.push((start, end));
+        self.save()
+    }
+
+    // Checks that all `block_count` blocks are in the journal and exactly fill a file of the given size,
+    // and if so, deletes the journal as it's no longer needed.
+    pub fn finish(self, block_count: usize, file_size: u64) -> Result<(), String> {
+        if self.blocks.len() != block_count || self.end() != file_size {
+            return Err(format!(
+                "Output doesn't match the journal: expected {} blocks ending at {}, got {} blocks ending at {}",
+                block_count,
+                file_size,
+                self.blocks.len(),
+                self.end(),
+            ))
+        }
+        fs::remove_file(&self.path).map_err(|e| format!("Failed to remove journal: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fingerprint, path_for, Journal};
+    use crate::gmk::Timestamp;
+    use std::{env, fs, path::Path};
+
+    #[test]
+    fn round_trip() {
+        let path = path_for(&env::temp_dir().join(format!("gm8decompiler_journal_{}.gmk", std::process::id())));
+        assert!(path.to_string_lossy().ends_with(".gmk.journal"));
+        let print = fingerprint(&[b"MZ game", b"{}"], "settings");
+        assert_ne!(print, fingerprint(&[b"MZ game", b"{}"], "other settings"));
+        assert_ne!(print, fingerprint(&[b"MZ game", b"{ }"], "settings"));
+        assert_ne!(print, fingerprint(&[b"MZ game{", b"}"], "settings"));
+
+        let mut journal = Journal::create(path.clone(), print, Timestamp::Unix(946684800.5)).unwrap();
+        journal.record(100).unwrap();
+        journal.record(250).unwrap();
+        assert!(journal.record(200).is_err());
+
+        assert!(Journal::load(path.clone(), print + 1).is_none());
+        let journal = Journal::load(path.clone(), print).unwrap();
+        assert_eq!(journal.completed(), 2);
+        assert_eq!(journal.end(), 250);
+        assert_eq!(journal.timestamp().to_serial(), Timestamp::Unix(946684800.5).to_serial());
+
+        // a journal that's been cut off or tampered with isn't used
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(&path, text.replace("block 1 100", "block 1 90")).unwrap();
+        assert!(Journal::load(path.clone(), print).is_none());
+        fs::write(&path, &text[..text.len() - 3]).unwrap();
+        assert!(Journal::load(path.clone(), print).is_none());
+
+        fs::write(&path, &text).unwrap();
+        assert!(Journal::load(path.clone(), print).unwrap().finish(3, 250).is_err());
+        assert!(Journal::load(path.clone(), print).unwrap().finish(2, 200).is_err());
+        Journal::load(path.clone(), print).unwrap().finish(2, 250).unwrap();
+        assert!(!Path::new(&path).exists());
+    }
+}
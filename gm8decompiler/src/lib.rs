@@ -0,0 +1,854 @@
+use flate2::Compression;
+use gm8exe::{
+    asset::{CodeAction, PascalString},
+    GameAssets, GameVersion,
+};
+use std::io;
+
+pub mod batch;
+pub mod collision;
+pub mod deobfuscate;
+pub mod export;
+pub mod fingerprint;
+pub mod gmk;
+pub mod images;
+pub mod journal;
+pub mod mappings;
+pub mod rename;
+pub mod scan;
+pub mod validate;
+pub mod zlib;
+
+// Guesses whether a game has been obfuscated, for deobfuscate::Mode::Auto.
+// Obfuscators tend to blank out asset names, which GameMaker itself never allows.
+pub fn looks_obfuscated(assets: &GameAssets) -> bool {
+    assets.backgrounds.iter().flatten().any(|s| s.name.0.is_empty())
+        || assets.fonts.iter().flatten().any(|s| s.name.0.is_empty())
+        || assets.objects.iter().flatten().any(|s| s.name.0.is_empty())
+        || assets.paths.iter().flatten().any(|s| s.name.0.is_empty())
+        || assets.rooms.iter().flatten().any(|s| s.name.0.is_empty())
+        || assets.sounds.iter().flatten().any(|s| s.name.0.is_empty())
+        || assets.sprites.iter().flatten().any(|s| s.name.0.is_empty())
+        || assets.timelines.iter().flatten().any(|s| s.name.0.is_empty())
+}
+
+// Fixes up broken events in objects and timelines so GameMaker will load them.
+pub fn fix_events(assets: &mut GameAssets) {
+    fn fix_event(ev: &mut CodeAction) {
+        // So far the only broken event type I know of is custom Execute Code actions.
+        // We can fix these by changing the act id and lib id to be a default Execute Code action instead.
+        if ev.action_kind == 7 && ev.execution_type == 2 {
+            // 7 = code block param, 2 = code execution
+            ev.id = 603;
+            ev.lib_id = 1;
+        }
+    }
+
+    assets
+        .objects
+        .iter_mut()
+        .flatten()
+        .flat_map(|x| x.events.iter_mut().flatten())
+        .flat_map(|(_, x)| x.iter_mut())
+        .for_each(fix_event);
+
+    assets
+        .timelines
+        .iter_mut()
+        .flatten()
+        .flat_map(|x| x.moments.iter_mut().flat_map(|(_, x)| x.iter_mut()))
+        .for_each(fix_event);
+}
+
+// The file extension GameMaker expects for a project of this version.
+pub fn gmk_extension(version: GameVersion) -> &'static str {
+    match version {
+        GameVersion::GameMaker8_0 => "gmk",
+        GameVersion::GameMaker8_1 => "gm81",
+    }
+}
+
+// The name of a GameMaker version, for messages.
+pub fn version_name(version: GameVersion) -> &'static str {
+    match version {
+        GameVersion::GameMaker8_0 => "GameMaker 8.0",
+        GameVersion::GameMaker8_1 => "GameMaker 8.1",
+    }
+}
+
+// Changes which version of GameMaker a project will be written for, so it can be opened in the other one.
+// The assets are stored the same way in both, and the header, settings and fonts are written in whichever format
+// assets.version says, so all this does is fill in or drop what only 8.1 has. Returns a note for each setting
+// which can't be carried over to 8.0.
+pub fn convert(assets: &mut GameAssets, version: GameVersion) -> Vec<String> {
+    let mut notes = Vec::new();
+    match (assets.version, version) {
+        (GameVersion::GameMaker8_0, GameVersion::GameMaker8_1) => {
+            // 8.0 has no antialiasing setting, so fonts whose pixel maps show they were antialiased are set to
+            // the highest level, or 8.1 would render them again without it
+            for font in assets.fonts.iter_mut().flatten() {
+                if font.pixel_map.iter().any(|&x| x != 0 && x != 255) {
+                    font.aa_level = 3;
+                }
+            }
+        },
+        (GameVersion::GameMaker8_1, GameVersion::GameMaker8_0) => {
+            // these are what reading an 8.0 game gives, since it's how 8.0 behaves
+            let settings = &mut assets.settings;
+            if !settings.force_cpu_render {
+                notes.push("8.0 always forces software vertex processing, so that setting is lost".into());
+                settings.force_cpu_render = true;
+            }
+            if settings.error_on_uninitialized_args {
+                notes.push("8.0 doesn't check for uninitialized arguments, so that setting is lost".into());
+                settings.error_on_uninitialized_args = false;
+            }
+            for font in assets.fonts.iter_mut().flatten() {
+                if font.aa_level != 0 || font.charset != 0 {
+                    notes.push(format!(
+                        "8.0 fonts don't have an antialiasing level or character set, so font '{}' loses them",
+                        String::from_utf8_lossy(&font.name.0),
+                    ));
+                    font.aa_level = 0;
+                    font.charset = 0;
+                }
+            }
+        },
+        _ => (),
+    }
+    assets.version = version;
+    notes
+}
+
+// Project metadata which isn't in the exe, so the decompiler has to make it up.
+pub struct Metadata {
+    pub author: String,
+    pub company: String,
+    pub timestamp: gmk::Timestamp,
+    // How hard to compress each zlib block. Compression::none() still writes zlib streams, just with stored blocks.
+    pub compression: Compression,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            author: "decompiler clan :police_car: :police_car: :police_car:".into(),
+            company: String::new(),
+            timestamp: gmk::Timestamp::default(),
+            compression: Compression::default(),
+        }
+    }
+}
+
+// The blocks which make up a project file, in the order they're written.
+pub const GMK_BLOCKS: &[&str] = &[
+    "header",
+    "settings",
+    "triggers",
+    "constants",
+    "sounds",
+    "sprites",
+    "backgrounds",
+    "paths",
+    "scripts",
+    "fonts",
+    "timelines",
+    "objects",
+    "rooms",
+    "room editor metadata",
+    "included files",
+    "extensions",
+    "game information",
+    "library initialization code",
+    "room order",
+    "resource tree",
+];
+
+// Writes an entire project file, reporting progress to the given callback as it goes.
+pub fn write_gmk<W, F>(
+    gmk: &mut W,
+    assets: &GameAssets,
+    meta: &Metadata,
+    multithread: bool,
+    progress: F,
+) -> Result<(), String>
+where
+    W: io::Write,
+    F: FnMut(&str),
+{
+    write_gmk_blocks(gmk, assets, meta, multithread, 0, progress, |_, _| Ok(()))
+}
+
+// Writes a project file starting from the given block of GMK_BLOCKS, for carrying on after earlier ones were
+// already written. `written` is called with each block's index as soon as the whole block has been written.
+pub fn write_gmk_blocks<W, F, G>(
+    gmk: &mut W,
+    assets: &GameAssets,
+    meta: &Metadata,
+    multithread: bool,
+    first_block: usize,
+    mut progress: F,
+    mut written: G,
+) -> Result<(), String>
+where
+    W: io::Write,
+    F: FnMut(&str),
+    G: FnMut(&mut W, usize) -> Result<(), String>,
+{
+    for block in first_block..GMK_BLOCKS.len() {
+        write_gmk_block(gmk, assets, meta, multithread, block, &mut progress)?;
+        written(gmk, block)?;
+    }
+    Ok(())
+}
+
+fn write_gmk_block<W, F>(
+    gmk: &mut W,
+    assets: &GameAssets,
+    meta: &Metadata,
+    multithread: bool,
+    block: usize,
+    progress: &mut F,
+) -> Result<(), String>
+where
+    W: io::Write,
+    F: FnMut(&str),
+{
+    let ext = gmk_extension(assets.version);
+    let timestamp = meta.timestamp;
+    let compression = meta.compression;
+
+    match block {
+        0 => {
+            progress(&format!("Writing {} header...", ext));
+            gmk::write_header(gmk, assets.version, assets.game_id, assets.guid)
+                .map_err(|e| format!("Failed to write header: {}", e))
+        },
+        1 => {
+            progress(&format!("Writing {} settings...", ext));
+            let author = PascalString::from(meta.author.as_str());
+            let company = PascalString::from(meta.company.as_str());
+            gmk::write_settings(
+                gmk,
+                &assets.settings,
+                assets.ico_file_raw.clone(),
+                assets.version,
+                &author,
+                &company,
+                timestamp,
+                compression,
+            )
+            .map_err(|e| format!("Failed to write settings block: {}", e))
+        },
+        2 => {
+            progress(&format!("Writing {} triggers...", assets.triggers.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.triggers,
+                gmk::write_trigger,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write triggers: {}", e))?;
+            gmk::write_timestamp(gmk, timestamp).map_err(|e| format!("Failed to write timestamp: {}", e))
+        },
+        3 => {
+            progress(&format!("Writing {} constants...", assets.constants.len()));
+            gmk::write_constants(gmk, &assets.constants, timestamp)
+                .map_err(|e| format!("Failed to write constants: {}", e))
+        },
+        4 => {
+            progress(&format!("Writing {} sounds...", assets.sounds.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.sounds,
+                gmk::write_sound,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write sounds: {}", e))
+        },
+        5 => {
+            progress(&format!("Writing {} sprites...", assets.sprites.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.sprites,
+                gmk::write_sprite,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write sprites: {}", e))
+        },
+        6 => {
+            progress(&format!("Writing {} backgrounds...", assets.backgrounds.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.backgrounds,
+                gmk::write_background,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write backgrounds: {}", e))
+        },
+        7 => {
+            progress(&format!("Writing {} paths...", assets.paths.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.paths,
+                gmk::write_path,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write paths: {}", e))
+        },
+        8 => {
+            progress(&format!("Writing {} scripts...", assets.scripts.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.scripts,
+                gmk::write_script,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write scripts: {}", e))
+        },
+        9 => {
+            progress(&format!("Writing {} fonts...", assets.fonts.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.fonts,
+                gmk::write_font,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write fonts: {}", e))
+        },
+        10 => {
+            progress(&format!("Writing {} timelines...", assets.timelines.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.timelines,
+                gmk::write_timeline,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write timelines: {}", e))
+        },
+        11 => {
+            progress(&format!("Writing {} objects...", assets.objects.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.objects,
+                gmk::write_object,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write objects: {}", e))
+        },
+        12 => {
+            progress(&format!("Writing {} rooms...", assets.rooms.len()));
+            gmk::write_asset_list(
+                gmk,
+                &assets.rooms,
+                gmk::write_room,
+                assets.version,
+                timestamp,
+                compression,
+                multithread,
+            )
+            .map_err(|e| format!("Failed to write rooms: {}", e))
+        },
+        13 => {
+            progress(&format!(
+                "Writing room editor metadata... (last instance: {}, last tile: {})",
+                assets.last_instance_id, assets.last_tile_id
+            ));
+            gmk::write_room_editor_meta(gmk, assets.last_instance_id, assets.last_tile_id)
+                .map_err(|e| format!("Failed to write room editor metadata: {}", e))
+        },
+        14 => {
+            progress(&format!("Writing {} included files...", assets.included_files.len()));
+            gmk::write_included_files(gmk, &assets.included_files, timestamp, compression)
+                .map_err(|e| format!("Failed to write included files: {}", e))
+        },
+        15 => {
+            progress(&format!("Writing {} extensions...", assets.extensions.len()));
+            gmk::write_extensions(gmk, &assets.extensions).map_err(|e| format!("Failed to write extensions: {}", e))
+        },
+        16 => {
+            progress("Writing game information...");
+            gmk::write_game_information(gmk, &assets.help_dialog, timestamp, compression)
+                .map_err(|e| format!("Failed to write game information: {}", e))
+        },
+        17 => {
+            progress(&format!("Writing {} library initialization strings...", assets.library_init_strings.len()));
+            gmk::write_library_init_code(gmk, &assets.library_init_strings)
+                .map_err(|e| format!("Failed to write library initialization code: {}", e))
+        },
+        18 => {
+            progress(&format!("Writing room order ({} rooms)...", assets.room_order.len()));
+            gmk::write_room_order(gmk, &assets.room_order).map_err(|e| format!("Failed to write room order: {}", e))
+        },
+        19 => {
+            progress("Writing resource tree...");
+            gmk::write_resource_tree(gmk, assets).map_err(|e| format!("Failed to write resource tree: {}", e))
+        },
+        _ => unreachable!("there are only {} blocks", GMK_BLOCKS.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        collision::{self, Shape},
+        gmk::{self, Timestamp},
+        convert, write_gmk, write_gmk_blocks, Metadata, GMK_BLOCKS,
+    };
+    use byteorder::{ReadBytesExt, LE};
+    use gm8exe::{
+        asset::{
+            path::{ConnectionKind, Point},
+            sound::{SoundFX, SoundKind},
+            sprite::{CollisionMap, Frame},
+            Asset, Background, Constant, Font, Object, Path, Room, Script, Sound, Sprite, Timeline,
+        },
+        Colour, GameAssets, GameVersion,
+    };
+    use std::io::Read;
+
+    // A few scripts and a path, with the settings and game information of the synthetic game.
+    pub(crate) fn fixture() -> GameAssets {
+        let synthetic = gm8exe::reader::from_gamedata(
+            conformance::synthetic::gamedata(),
+            None::<fn(&str)>,
+            gm8exe::reader::ReaderOptions::default(),
+        )
+        .unwrap();
+        let scripts = (0..20)
+            .map(|i| {
+                Some(Box::new(Script {
+                    name: format!("scr_{}", i).as_str().into(),
+                    source: format!("return argument0 * {};", i).as_str().into(),
+                }))
+            })
+            .collect();
+        let paths = vec![
+            Some(Box::new(Path {
+                name: "path0".into(),
+                connection: ConnectionKind::SmoothCurve,
+                precision: 4,
+                closed: true,
+                points: vec![Point { x: 0.0, y: 0.0, speed: 100.0 }, Point { x: 32.0, y: 16.5, speed: 50.0 }],
+            })),
+            None,
+        ];
+        GameAssets {
+            triggers: Vec::new(),
+            constants: vec![Constant { name: "SPEED".into(), expression: "4".into() }],
+            extensions: Vec::new(),
+            sprites: Vec::new(),
+            sounds: Vec::new(),
+            backgrounds: Vec::new(),
+            paths,
+            scripts,
+            fonts: Vec::new(),
+            timelines: Vec::new(),
+            objects: Vec::new(),
+            rooms: Vec::new(),
+            included_files: Vec::new(),
+            version: GameVersion::GameMaker8_0,
+            dx_dll: Vec::new(),
+            ico_file_raw: None,
+            exe_metadata: None,
+            help_dialog: synthetic.help_dialog,
+            last_instance_id: 100000,
+            last_tile_id: 10000000,
+            library_init_strings: Vec::new(),
+            room_order: Vec::new(),
+            trailing_data: None,
+            settings: synthetic.settings,
+            game_id: 1234,
+            guid: [1, 2, 3, 4],
+            gamedata_hash: 0,
+            blobs: None,
+        }
+    }
+
+    // Every kind of asset in the resource tree, with deleted ones in between and at the end,
+    // and a room order which isn't in index order and has some bad entries.
+    pub(crate) fn fixture_with_gaps() -> GameAssets {
+        fn gaps<T>(make: impl Fn(String) -> T, prefix: &str) -> Vec<Option<Box<T>>> {
+            (0..6).map(|i| if i % 2 == 0 { Some(Box::new(make(format!("{}{}", prefix, i)))) } else { None }).collect()
+        }
+        let mut assets = fixture();
+        assets.sprites = gaps(
+            |name| Sprite {
+                name: name.as_str().into(),
+                origin_x: 0,
+                origin_y: 0,
+                frames: Vec::new(),
+                colliders: Vec::new(),
+                per_frame_colliders: false,
+                collision_shape: None,
+            },
+            "spr",
+        );
+        assets.sounds = gaps(
+            |name| Sound {
+                name: name.as_str().into(),
+                source: "".into(),
+                extension: "".into(),
+                data: None,
+                kind: SoundKind::Normal,
+                volume: 1.0,
+                pan: 0.0,
+                preload: true,
+                fx: SoundFX { chorus: false, echo: false, flanger: false, gargle: false, reverb: false },
+            },
+            "snd",
+        );
+        assets.backgrounds =
+            gaps(|name| Background { name: name.as_str().into(), width: 0, height: 0, data: None }, "bg");
+        assets.paths.extend(vec![None, None]);
+        assets.scripts[3] = None;
+        assets.scripts.push(None);
+        assets.fonts = gaps(
+            |name| Font {
+                name: name.as_str().into(),
+                sys_name: "Arial".into(),
+                size: 12,
+                bold: false,
+                italic: false,
+                range_start: 32,
+                range_end: 127,
+                charset: 0,
+                aa_level: 0,
+                dmap: Box::new([0; 0x600]),
+                map_width: 0,
+                map_height: 0,
+                pixel_map: Box::new([]),
+            },
+            "fnt",
+        );
+        assets.timelines = gaps(|name| Timeline { name: name.as_str().into(), moments: Vec::new() }, "tl");
+        assets.objects = gaps(
+            |name| Object {
+                name: name.as_str().into(),
+                sprite_index: -1,
+                solid: false,
+                visible: true,
+                depth: 0,
+                persistent: false,
+                parent_index: -1,
+                mask_index: -1,
+                events: Vec::new(),
+            },
+            "obj",
+        );
+        assets.rooms = gaps(
+            |name| Room {
+                name: name.as_str().into(),
+                caption: "".into(),
+                width: 640,
+                height: 480,
+                speed: 30,
+                persistent: false,
+                bg_colour: Colour::new(0, 0, 0, 0),
+                clear_screen: true,
+                clear_region: true,
+                creation_code: "".into(),
+                backgrounds: Vec::new(),
+                views_enabled: false,
+                views: Vec::new(),
+                instances: Vec::new(),
+                tiles: Vec::new(),
+            },
+            "rm",
+        );
+        assets.room_order = vec![4, 0, 4, 1, 9, -1, 2];
+        assets
+    }
+
+    // Reads a resource tree back like GameMaker would, making sure every node has as many children as it says
+    // and that nothing's left over. Returns the (kind, index, name) of every asset in it, in order.
+    fn read_tree(mut data: &[u8]) -> Vec<(u32, u32, String)> {
+        fn read_node(data: &mut &[u8], assets: &mut Vec<(u32, u32, String)>) {
+            let status = data.read_u32::<LE>().unwrap();
+            let group = data.read_u32::<LE>().unwrap();
+            let index = data.read_u32::<LE>().unwrap();
+            let mut name = vec![0; data.read_u32::<LE>().unwrap() as usize];
+            data.read_exact(&mut name).unwrap();
+            let children = data.read_u32::<LE>().unwrap();
+            if status == 3 {
+                assets.push((group, index, String::from_utf8(name).unwrap()));
+            }
+            for _ in 0..children {
+                read_node(data, assets);
+            }
+        }
+        let mut assets = Vec::new();
+        for _ in 0..12 {
+            read_node(&mut data, &mut assets);
+        }
+        assert!(data.is_empty(), "{} bytes left over after the resource tree", data.len());
+        assets
+    }
+
+    #[test]
+    fn resource_tree_with_deleted_assets() {
+        let assets = fixture_with_gaps();
+        let mut tree = Vec::new();
+        gmk::write_resource_tree(&mut tree, &assets).unwrap();
+        let nodes = read_tree(&tree);
+        let of_kind = |kind| nodes.iter().filter(|x| x.0 == kind).map(|x| (x.1, x.2.as_str())).collect::<Vec<_>>();
+        assert_eq!(of_kind(2), [(0, "spr0"), (2, "spr2"), (4, "spr4")]);
+        assert_eq!(of_kind(3), [(0, "snd0"), (2, "snd2"), (4, "snd4")]);
+        assert_eq!(of_kind(6), [(0, "bg0"), (2, "bg2"), (4, "bg4")]);
+        assert_eq!(of_kind(8), [(0, "path0")]);
+        assert_eq!(of_kind(7).len(), 19);
+        assert!(of_kind(7).iter().all(|&(i, _)| i != 3));
+        assert_eq!(of_kind(9), [(0, "fnt0"), (2, "fnt2"), (4, "fnt4")]);
+        assert_eq!(of_kind(12), [(0, "tl0"), (2, "tl2"), (4, "tl4")]);
+        assert_eq!(of_kind(1), [(0, "obj0"), (2, "obj2"), (4, "obj4")]);
+        assert_eq!(of_kind(4), [(4, "rm4"), (0, "rm0"), (2, "rm2")]);
+
+        // and the whole file still writes
+        let mut output = Vec::new();
+        write_gmk(&mut output, &assets, &Metadata::default(), true, |_| ()).unwrap();
+    }
+
+    #[test]
+    fn reproducible_output() {
+        let assets = fixture();
+        let meta = Metadata { author: "someone".into(), timestamp: "zero".parse().unwrap(), ..Default::default() };
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        write_gmk(&mut first, &assets, &meta, true, |_| ()).unwrap();
+        write_gmk(&mut second, &assets, &meta, false, |_| ()).unwrap();
+        assert_eq!(first, second);
+
+        // and for the fixture games
+        for (name, exe) in conformance::all_games() {
+            let options = gm8exe::reader::ReaderOptions { multithread: true, ..Default::default() };
+            let assets = gm8exe::reader::from_exe(exe, None::<fn(&str)>, options).unwrap();
+            let (mut first, mut second) = (Vec::new(), Vec::new());
+            write_gmk(&mut first, &assets, &meta, true, |_| ()).unwrap();
+            write_gmk(&mut second, &assets, &meta, false, |_| ()).unwrap();
+            assert!(first == second, "output for {} isn't reproducible", name);
+        }
+    }
+
+    #[test]
+    fn author_and_company() {
+        let assets = fixture();
+        let meta = Metadata {
+            author: "An Author".into(),
+            company: "A Company".into(),
+            compression: flate2::Compression::none(),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_gmk(&mut output, &assets, &meta, false, |_| ()).unwrap();
+        // the company comes after the version numbers, which are always 1.0.0.0
+        let mut company = [1u32, 0, 0, 0, 9].iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+        company.extend_from_slice(b"A Company");
+        let contains = |needle: &[u8]| output.windows(needle.len()).any(|x| x == needle);
+        assert!(contains(b"\x09\0\0\0An Author"));
+        assert!(contains(&company));
+    }
+
+    #[test]
+    fn uncompressed_output() {
+        let assets = fixture();
+        let meta = Metadata { compression: flate2::Compression::none(), ..Default::default() };
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        write_gmk(&mut first, &assets, &meta, true, |_| ()).unwrap();
+        write_gmk(&mut second, &assets, &meta, false, |_| ()).unwrap();
+        assert_eq!(first, second);
+
+        let mut compressed = Vec::new();
+        write_gmk(&mut compressed, &assets, &Metadata::default(), true, |_| ()).unwrap();
+        assert!(first.len() > compressed.len());
+    }
+
+    #[test]
+    fn trailing_data() {
+        for (name, exe) in conformance::all_games() {
+            let options = gm8exe::reader::ReaderOptions::default();
+            let before = gm8exe::reader::from_exe(exe.clone(), None::<fn(&str)>, options).unwrap().trailing_data;
+            let before = before.map_or(0, |x| x.len());
+
+            // antidec decrypts backwards from the end of the file, so adding anything breaks those games,
+            // the same as it does in GM8
+            let mut appended = exe;
+            appended.extend_from_slice(&[0xAB; 64]);
+            if let Ok(assets) = gm8exe::reader::from_exe(appended, None::<fn(&str)>, options) {
+                let after = assets.trailing_data.map_or(0, |x| x.len());
+                assert_eq!(after, before + 64, "wrong amount of trailing data in {}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn reader_progress() {
+        use gm8exe::{reader::ReaderProgress, AssetKind};
+        for (name, exe) in conformance::all_games() {
+            for &multithread in &[false, true] {
+                let options = gm8exe::reader::ReaderOptions { multithread, keep_blobs: true, ..Default::default() };
+                let mut reports = Vec::new();
+                let assets =
+                    gm8exe::reader::from_exe_with_callback(exe.clone(), None::<fn(&str)>, options, |p| reports.push(p))
+                        .unwrap();
+                assert_eq!(reports.first(), Some(&ReaderProgress::Settings));
+                assert_eq!(reports.last(), Some(&ReaderProgress::Finishing));
+                for kind in AssetKind::ALL.iter().copied() {
+                    let total = assets.blobs.as_ref().unwrap()[kind as usize].len();
+                    let counts = reports
+                        .iter()
+                        .filter_map(|p| match *p {
+                            ReaderProgress::Assets { kind: k, done, total: t } if k == kind => Some((done, t)),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    assert_eq!(counts, (0..=total).map(|done| (done, total)).collect::<Vec<_>>(), "{}", name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resume_after_interruption() {
+        let assets = fixture();
+        let meta = Metadata::default();
+        let mut expected = Vec::new();
+        write_gmk(&mut expected, &assets, &meta, false, |_| ()).unwrap();
+
+        // stop right after the sprites block, as if the process was killed partway through the next one
+        let sprites = GMK_BLOCKS.iter().position(|&x| x == "sprites").unwrap();
+        let mut ends = Vec::new();
+        let mut output = Vec::new();
+        let result = write_gmk_blocks(
+            &mut output,
+            &assets,
+            &meta,
+            true,
+            0,
+            |_| (),
+            |gmk, block| {
+                ends.push(gmk.len());
+                if block == sprites { Err("killed".into()) } else { Ok(()) }
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(ends.len(), sprites + 1);
+        output.extend_from_slice(b"half of the backgrounds block");
+
+        output.truncate(*ends.last().unwrap());
+        write_gmk_blocks(&mut output, &assets, &meta, true, ends.len(), |_| (), |_, _| Ok(())).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn stored_collision_shape() {
+        let sprite = Sprite {
+            name: "spr_ball".into(),
+            origin_x: 1,
+            origin_y: 1,
+            frames: vec![Frame { width: 2, height: 2, data: vec![0xFF; 16].into_boxed_slice() }],
+            colliders: vec![CollisionMap {
+                width: 2,
+                height: 2,
+                bbox_left: 0,
+                bbox_right: 1,
+                bbox_top: 0,
+                bbox_bottom: 1,
+                data: vec![true; 4].into_boxed_slice(),
+            }],
+            per_frame_colliders: false,
+            collision_shape: Some(Shape::Disk as u32),
+        };
+        let mut exe = Vec::new();
+        sprite.serialize_exe(&mut exe, GameVersion::GameMaker8_1).unwrap();
+        let sprite = Sprite::deserialize_exe(exe.as_slice(), GameVersion::GameMaker8_1, true).unwrap();
+        assert_eq!(sprite.collision_shape, Some(Shape::Disk as u32));
+
+        // a disk this small fills its whole box, so it would be guessed as a rectangle if the sprite didn't say
+        assert!(matches!(collision::resolve_map(&sprite).unwrap().shape, Shape::Disk));
+        let sprite = Sprite { collision_shape: None, ..sprite };
+        assert!(matches!(collision::resolve_map(&sprite).unwrap().shape, Shape::Rectangle));
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(Timestamp::Zero.to_serial(), 0.0);
+        // 2000-01-01 00:00:00 is 36526.0 as a TDateTime, and sub-second precision must survive
+        assert_eq!("unix:946684800".parse::<Timestamp>().unwrap().to_serial(), 36526.0);
+        assert_eq!("unix:946684800.5".parse::<Timestamp>().unwrap().to_serial(), 36526.0 + 0.5 / 86400.0);
+        assert_eq!("gm-serial:36526.25".parse::<Timestamp>().unwrap().to_serial(), 36526.25);
+        assert!("unix:".parse::<Timestamp>().is_err());
+        assert!("yesterday".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn version_conversion() {
+        // the header version and the 14th dword of the settings, which 8.1 packs the render setting into
+        fn versions(assets: &GameAssets) -> (u32, u32) {
+            let mut gmk = Vec::new();
+            write_gmk(&mut gmk, assets, &Metadata::default(), false, |_| ()).unwrap();
+            let mut data = &gmk[4..];
+            let version = data.read_u32::<LE>().unwrap();
+            data = &data[20..];
+            assert_eq!(data.read_u32::<LE>().unwrap(), 800);
+            let len = data.read_u32::<LE>().unwrap() as usize;
+            let mut settings = Vec::new();
+            flate2::read::ZlibDecoder::new(&data[..len]).read_to_end(&mut settings).unwrap();
+            (version, (&settings[13 * 4..]).read_u32::<LE>().unwrap())
+        }
+
+        let mut assets = fixture_with_gaps();
+        assets.settings.vsync = true;
+        // the settings only 8.1 has, which 8.0 can only store as their defaults
+        assets.settings.force_cpu_render = false;
+        assets.settings.error_on_uninitialized_args = true;
+        assets.fonts[0].as_mut().unwrap().pixel_map = Box::new([0, 128, 255]);
+        assets.fonts[2].as_mut().unwrap().pixel_map = Box::new([0, 255]);
+        assert_eq!(versions(&assets), (800, 1));
+
+        assert!(convert(&mut assets, GameVersion::GameMaker8_1).is_empty());
+        assert!(matches!(assets.version, GameVersion::GameMaker8_1));
+        assert_eq!(assets.fonts[0].as_ref().unwrap().aa_level, 3);
+        assert_eq!(assets.fonts[2].as_ref().unwrap().aa_level, 0);
+        assert_eq!(versions(&assets), (810, 1));
+
+        // going back loses what 8.0 can't store
+        let notes = convert(&mut assets, GameVersion::GameMaker8_0);
+        assert_eq!(notes.len(), 3);
+        assert!(notes[2].contains("'fnt0'"));
+        assert!(matches!(assets.version, GameVersion::GameMaker8_0));
+        assert!(assets.settings.force_cpu_render);
+        assert!(!assets.settings.error_on_uninitialized_args);
+        assert_eq!(assets.fonts[0].as_ref().unwrap().aa_level, 0);
+        assert_eq!(versions(&assets), (800, 1));
+        assert!(convert(&mut assets, GameVersion::GameMaker8_1).is_empty());
+        assert_eq!(versions(&assets), (810, 0x81));
+    }
+}
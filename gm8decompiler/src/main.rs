@@ -1,16 +1,19 @@
+use flate2::Compression;
+use gm8decompiler::{
+    batch::{self, AssetCounts, Decompiled, Outcome},
+    deobfuscate,
+    journal::Journal,
+    GMK_BLOCKS,
+};
 use gm8exe::GameVersion;
 use std::{
     env, fs,
+    io::{self, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     process,
+    time::Instant,
 };
 
-pub mod collision;
-pub mod deobfuscate;
-pub mod gmk;
-pub mod mappings;
-pub mod zlib;
-
 static INFO_STRING: &str = concat!(
     "GM8Decompiler v",
     env!("CARGO_PKG_VERSION"),
@@ -49,8 +52,6 @@ fn is_cmd(_argv_0: &str) -> bool {
 fn pause(_tip: bool) {}
 
 fn main() {
-    println!("{}", INFO_STRING);
-
     let args: Vec<String> = env::args().collect();
     assert!(!args.is_empty());
     let process_path = args[0].as_str();
@@ -62,12 +63,40 @@ fn main() {
         .optflag("l", "lazy", "disable various data integrity checks")
         .optflag("v", "verbose", "enable verbose logging for decompilation")
         .optopt("d", "deobfuscate", "set deobfuscation mode auto/on/off (default=auto)", "")
+        .optopt("", "write-name-map", "write the names given to assets by the deobfuscator to a JSON file", "FILE")
+        .optopt("", "read-name-map", "reuse the deobfuscated names from a file written by --write-name-map", "FILE")
         .optflag("p", "preserve", "preserve broken events (instead of trying to fix them)")
         .optflag("s", "singlethread", "decompile gamedata synchronously (lower RAM usage)")
-        .optopt("o", "output", "specify output filename", "FILE");
+        .optopt("o", "output", "specify output filename", "FILE")
+        .optopt("t", "timestamp", "set asset timestamps to zero/now/unix:<secs>/gm-serial:<days>", "")
+        .optopt("a", "author", "set the author stored in the game settings", "NAME")
+        .optopt("", "company", "set the company stored in the game settings", "NAME")
+        .optflag("", "deterministic", "store data uncompressed and refuse -t now, for comparing outputs")
+        .optopt("", "convert-to", "write a project for GameMaker 8.0 or 8.1, whichever the game was made with", "")
+        .optopt("", "rename-map", "rename assets using a tab-separated file of kind, asset and new name", "FILE")
+        .optflag("c", "scan", "scan for code typical of malware droppers")
+        .optflag("", "strip-flagged", "comment out code flagged by --scan in the output")
+        .optflag("", "validate-gml", "check that all GML parses, reporting where it doesn't")
+        .optflag("", "fix-strings", "repair unclosed strings and NUL bytes found by --validate-gml")
+        .optopt("", "export-gml", "write all scripts and event code to a directory of .gml files", "DIR")
+        .optopt("", "export-images", "write all sprites and backgrounds to a directory of .png files", "DIR")
+        .optflag("", "strips", "with --export-images, write each sprite as one horizontal strip")
+        .optopt("", "dump-trailing", "write any data found after the gamedata to a file", "FILE")
+        .optflag("r", "resume", "carry on writing an output file which was interrupted")
+        .optopt("", "batch", "decompile every .exe in a directory and the ones inside it", "DIR")
+        .optopt("", "jobs", "with --batch, how many games to decompile at once (default=1)", "N")
+        .optflag("", "force", "with --batch, decompile games again even if their output already exists")
+        .optflag("f", "fingerprint", "print hashes of every asset for identifying the game, instead of decompiling");
 
     // parse command line arguments
-    let matches = match opts.parse(&args[1..]) {
+    let parsed = opts.parse(&args[1..]);
+    // a fingerprint is the only thing that goes to stdout, so it can be piped straight into something else
+    if parsed.as_ref().map_or(false, |x| x.opt_present("f")) {
+        eprintln!("{}", INFO_STRING);
+    } else {
+        println!("{}", INFO_STRING);
+    }
+    let matches = match parsed {
         Ok(matches) => matches,
         Err(err) => {
             use getopts::Fail::*;
@@ -86,21 +115,56 @@ fn main() {
     };
 
     // print help message if requested OR no input files
-    if matches.opt_present("h") || matches.free.is_empty() {
+    let batch_dir = matches.opt_str("batch");
+    if matches.opt_present("h") || (matches.free.is_empty() && batch_dir.is_none()) {
         // If the getopts2 usage generator didn't suck this much,
         // I wouldn't have to resort to this.
         // TODO: Get a better argument parser in general.
         println!(
-            "Usage: {} FILENAME [options]
+            "Usage: {0} FILENAME [options]
+       {0} --batch DIR [options]
 
 Options:
     -h, --help                print this help message
     -l, --lazy                disable various data integrity checks
     -v, --verbose             enable verbose logging for decompilation
     -d, --deobfuscate <mode>  set deobfuscation mode auto/on/off (defaults to auto)
+    --write-name-map <file>   write the names given to assets by the deobfuscator to a JSON file, along with
+                              their original names
+    --read-name-map <file>    give assets the same names as in a file written by --write-name-map, for any at the
+                              same index, so the output of an updated game is easy to compare with the old one
     -p, --preserve            preserve broken events (instead of trying to fix them)
     -s, --singlethread        decompile gamedata synchronously (lower RAM usage)
-    -o, --output <file>       specify output filename",
+    -o, --output <file>       specify output filename
+    -t, --timestamp <time>    set asset timestamps to zero/now/unix:<secs>/gm-serial:<days> (defaults to zero)
+    -a, --author <name>       set the author stored in the game settings
+    --company <name>          set the company stored in the game settings (defaults to none)
+    --deterministic           store zlib blocks uncompressed and refuse '-t now', so the same game always gives
+                              the same output, which diffs usefully between builds
+    --convert-to <version>    write a project for GameMaker 8.0 or 8.1, instead of the version the game was made with
+    --rename-map <file>       rename assets and the references to them in code, using a file with a line like
+                              sprite<tab>3<tab>spr_player for each asset
+    -c, --scan                scan for code typical of malware droppers (running programs from temp, etc.)
+    --strip-flagged           comment out any code flagged by --scan in the output
+    --validate-gml            check that all GML parses before writing, reporting the asset and line of any errors
+    --fix-strings             repair unclosed strings and NUL bytes found by --validate-gml, logging each change
+    --export-gml <dir>        write all scripts and event code to a directory of .gml files, as well as the output
+                              file if -o is given
+    --export-images <dir>     write every sprite frame and background to a directory of .png files, as well as the
+                              output file if -o is given
+    --strips                  with --export-images, write each sprite as one horizontal strip instead of a file
+                              per frame
+    --dump-trailing <file>    write any data found after the end of the gamedata to a file, as well as the output
+                              file if -o is given
+    -r, --resume              carry on writing an output file which was interrupted, if nothing has changed
+                              (progress is only kept track of with this or --batch, so the first run needs it too)
+    --batch <dir>             decompile every .exe in a directory and the ones inside it, into the same tree of
+                              directories under -o if given, carrying on past any which fail and writing
+                              summary.json and summary.csv at the end (exits with 1 if any failed)
+    --jobs <n>                with --batch, how many games to decompile at once, sharing the threads between them
+    --force                   with --batch, decompile games again even if their output already exists, instead of
+                              skipping them (interrupted ones are always resumed unless this is given)
+    -f, --fingerprint         print hashes of every asset for identifying the game, instead of decompiling it",
             process_path
         );
         if should_pause {
@@ -109,20 +173,22 @@ Options:
         process::exit(0); // once the user RTFM they can run it again
     }
 
-    // print error message if multiple inputs were provided
-    if matches.free.len() > 1 {
-        eprintln!(
-            concat!("Unexpected input: {}\n", "Tip: Only one input gamefile is expected at a time!",),
-            matches.free[1]
-        );
+    // print error message if multiple inputs were provided, or any alongside --batch
+    let unexpected = if batch_dir.is_some() { matches.free.first() } else { matches.free.get(1) };
+    if let Some(unexpected) = unexpected {
+        let tip = if batch_dir.is_some() {
+            "Tip: --batch decompiles the games in its directory, so no input gamefile is expected!"
+        } else {
+            "Tip: Only one input gamefile is expected at a time!"
+        };
+        eprintln!("Unexpected input: {}\n{}", unexpected, tip);
         if should_pause {
             pause(true);
         }
         process::exit(1);
     }
 
-    // extract flags & input path
-    let input = &matches.free[0];
+    // extract flags
     let lazy = matches.opt_present("l");
     let singlethread = matches.opt_present("s");
     let verbose = matches.opt_present("v");
@@ -135,12 +201,88 @@ Options:
             process::exit(1);
         },
     };
+    let convert_to = match matches.opt_str("convert-to").as_deref() {
+        Some("8.0") => Some(GameVersion::GameMaker8_0),
+        Some("8.1") => Some(GameVersion::GameMaker8_1),
+        None => None,
+        Some(x) => {
+            eprintln!("Invalid version to convert to: {} (valid versions are 8.0/8.1)", x);
+            process::exit(1);
+        },
+    };
     let out_path = matches.opt_str("o");
+    let rename_map = matches.opt_str("rename-map");
+    let write_name_map = matches.opt_str("write-name-map");
+    let read_name_map = matches.opt_str("read-name-map");
     let preserve = matches.opt_present("p");
+    let strip_flagged = matches.opt_present("strip-flagged");
+    let scan = strip_flagged || matches.opt_present("c");
+    let fix_strings = matches.opt_present("fix-strings");
+    let validate = fix_strings || matches.opt_present("validate-gml");
+    let export_dir = matches.opt_str("export-gml");
+    let image_dir = matches.opt_str("export-images");
+    let strips = matches.opt_present("strips");
+    let dump_trailing = matches.opt_str("dump-trailing");
+    let resume = matches.opt_present("r");
+    let print_fingerprint = matches.opt_present("f");
+    let jobs = match matches.opt_get_default("jobs", 1usize) {
+        Ok(jobs) if jobs > 0 => jobs,
+        Ok(_) => {
+            eprintln!("Invalid number of jobs: must be at least 1");
+            process::exit(1);
+        },
+        Err(e) => {
+            eprintln!("Invalid number of jobs: {}", e);
+            process::exit(1);
+        },
+    };
+    let force = matches.opt_present("force");
+    let mut meta = gm8decompiler::Metadata::default();
+    let deterministic = matches.opt_present("deterministic");
+    if let Some(timestamp) = matches.opt_str("t") {
+        if deterministic && timestamp == "now" {
+            eprintln!("--deterministic can't be used with '-t now'");
+            process::exit(1);
+        }
+        meta.timestamp = match timestamp.parse() {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("Invalid timestamp setting: {}", e);
+                process::exit(1);
+            },
+        };
+    }
+    if let Some(author) = matches.opt_str("a") {
+        meta.author = author;
+    }
+    if let Some(company) = matches.opt_str("company") {
+        meta.company = company;
+    }
+    if deterministic {
+        meta.compression = Compression::none();
+    }
     // no_pause extracted before help
 
+    if print_fingerprint {
+        let input = match (&batch_dir, matches.free.first()) {
+            (None, Some(input)) => Path::new(input),
+            _ => {
+                eprintln!("--fingerprint can't be used with --batch");
+                process::exit(1);
+            },
+        };
+        if let Err(e) = fingerprint(input, !lazy, !singlethread) {
+            eprintln!("Error parsing gamedata:\n{}", e);
+            process::exit(1);
+        }
+        return
+    }
+
     // print flags for confirmation
-    println!("Input file: {}", input);
+    match &batch_dir {
+        Some(dir) => println!("Batch mode ON: will decompile every .exe in '{}', {} at a time", dir, jobs),
+        None => println!("Input file: {}", matches.free[0]),
+    }
     if lazy {
         println!("Lazy mode ON: data integrity checking disabled");
     }
@@ -152,17 +294,143 @@ Options:
         deobfuscate::Mode::Off => println!("Deobfuscation OFF: will ignore obfuscation"),
         _ => (),
     }
+    if let Some(path) = &write_name_map {
+        println!("Name map ON: deobfuscated names will be written to '{}'", path);
+    }
+    if let Some(path) = &read_name_map {
+        println!("Name map ON: deobfuscated names will be reused from '{}'", path);
+    }
     if singlethread {
         println!("Single-threaded mode ON: process will not start new threads (slow)");
     }
     if let Some(path) = &out_path {
         println!("Specified output path: {}", path);
     }
+    if let Some(version) = convert_to {
+        println!("Convert mode ON: project will be written for {}", gm8decompiler::version_name(version));
+    }
+    if let Some(path) = &rename_map {
+        println!("Rename mode ON: assets will be renamed using '{}'", path);
+    }
     if preserve {
         println!("Preserve mode ON: broken events will be preserved and will not be fixed");
     }
+    if strip_flagged {
+        println!("Strip mode ON: suspicious code will be commented out");
+    } else if scan {
+        println!("Scan mode ON: suspicious code will be reported");
+    }
+    if fix_strings {
+        println!("Fix strings mode ON: unclosed strings and NUL bytes in GML will be repaired");
+    } else if validate {
+        println!("Validate mode ON: GML which doesn't parse will be reported");
+    }
+    if let Some(dir) = &export_dir {
+        println!("Export mode ON: GML will be written to '{}'", dir);
+    }
+    if let Some(dir) = &image_dir {
+        if strips {
+            println!("Image export ON: sprites will be written to '{}' as strips, along with backgrounds", dir);
+        } else {
+            println!("Image export ON: sprites and backgrounds will be written to '{}'", dir);
+        }
+    } else if strips {
+        println!("***WARNING*** --strips has no effect without --export-images");
+    }
+    if let Some(path) = &dump_trailing {
+        println!("Trailing data dump ON: anything after the gamedata will be written to '{}'", path);
+    }
+    if deterministic {
+        println!("Deterministic mode ON: zlib blocks will be stored uncompressed, so outputs can be diffed");
+    }
+    if resume {
+        println!("Resume mode ON: will carry on from where the last attempt stopped, if possible");
+    }
+    if force && batch_dir.is_some() {
+        println!("Force mode ON: games will be decompiled again even if their output already exists");
+    } else if batch_dir.is_none() && (force || matches.opt_present("jobs")) {
+        println!("***WARNING*** --jobs and --force have no effect without --batch");
+    }
+
+    if let Some(dir) = batch_dir {
+        // these are all about one particular game, so they don't make sense for a whole directory of them
+        let per_game = [
+            ("--write-name-map", write_name_map.is_some()),
+            ("--read-name-map", read_name_map.is_some()),
+            ("--rename-map", rename_map.is_some()),
+            ("--export-gml", export_dir.is_some()),
+            ("--export-images", image_dir.is_some()),
+            ("--dump-trailing", dump_trailing.is_some()),
+        ];
+        if let Some((option, _)) = per_game.iter().find(|(_, present)| *present) {
+            eprintln!("{} can't be used with --batch", option);
+            process::exit(1);
+        }
+        let input_dir = Path::new(&dir);
+        if !input_dir.is_dir() {
+            eprintln!("Input directory '{}' does not exist.", dir);
+            process::exit(1);
+        }
+        let output_dir = out_path.map_or_else(|| input_dir.to_path_buf(), PathBuf::from);
+        if let Err(e) = fs::create_dir_all(&output_dir) {
+            eprintln!("Failed to create output directory '{}': {}", output_dir.display(), e);
+            process::exit(1);
+        }
+
+        let start = Instant::now();
+        let decompile_game = |task: &batch::Task| {
+            decompile(
+                &task.input,
+                None,
+                &task.output,
+                !lazy,
+                task.multithread,
+                verbose,
+                deobfuscate,
+                !preserve,
+                convert_to,
+                None,
+                None,
+                None,
+                scan,
+                strip_flagged,
+                validate,
+                fix_strings,
+                None,
+                None,
+                strips,
+                None,
+                task.resume,
+                true,
+                &meta,
+            )
+        };
+        let progress = |entry: &batch::Entry| match (&entry.outcome, &entry.error) {
+            (Outcome::Failed, Some(e)) => println!("[Batch] Failed {}: {}", entry.file, e),
+            (Outcome::Skipped, _) => println!("[Batch] Skipped {}, its output already exists", entry.file),
+            _ => println!("[Batch] Decompiled {} in {:.1}s", entry.file, entry.duration),
+        };
+        let entries = match batch::run(input_dir, &output_dir, jobs, !singlethread, force, decompile_game, progress) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read input directory '{}': {}", dir, e);
+                process::exit(1);
+            },
+        };
+        if let Err(e) = batch::write_summary(&output_dir, &entries) {
+            eprintln!("Failed to write summary to '{}': {}", output_dir.display(), e);
+            process::exit(1);
+        }
+        println!("Batch finished: {}", batch::totals(&entries, start.elapsed()));
+        println!("Summary written to '{}'", output_dir.join("summary.json").display());
+        if should_pause {
+            pause(false);
+        }
+        process::exit(if entries.iter().any(|x| x.outcome == Outcome::Failed) { 1 } else { 0 });
+    }
 
     // resolve input path
+    let input = &matches.free[0];
     let input_path = Path::new(input);
     if !input_path.is_file() {
         eprintln!("Input file '{}' does not exist.", input);
@@ -170,7 +438,32 @@ Options:
     }
 
     // allow decompile to handle the rest of main
-    if let Err(e) = decompile(input_path, out_path, !lazy, !singlethread, verbose, deobfuscate, !preserve) {
+    let result = decompile(
+        input_path,
+        out_path,
+        input_path,
+        !lazy,
+        !singlethread,
+        verbose,
+        deobfuscate,
+        !preserve,
+        convert_to,
+        write_name_map,
+        read_name_map,
+        rename_map,
+        scan,
+        strip_flagged,
+        validate,
+        fix_strings,
+        export_dir,
+        image_dir,
+        strips,
+        dump_trailing,
+        resume,
+        resume,
+        &meta,
+    );
+    if let Err(e) = result {
         eprintln!("Error parsing gamedata:\n{}", e);
         process::exit(1);
     }
@@ -180,77 +473,200 @@ Options:
     }
 }
 
+// Prints the fingerprint of a game and nothing else
+fn fingerprint(in_path: &Path, strict: bool, multithread: bool) -> Result<(), String> {
+    let file = fs::read(&in_path).map_err(|e| format!("Failed to read '{}': {}", in_path.display(), e))?;
+    let options = gm8exe::reader::ReaderOptions { strict, multithread, keep_blobs: true };
+    let assets = if file.starts_with(b"MZ") {
+        gm8exe::reader::from_exe(file, None::<fn(&str)>, options)
+    } else {
+        eprintln!("No exe header, so reading the file as standalone gamedata");
+        gm8exe::reader::from_gamedata(file, None::<fn(&str)>, options)
+    }
+    .map_err(|e| format!("Reader error: {}", e))?;
+    print!("{}", gm8decompiler::fingerprint::fingerprint(&assets).expect("the reader was asked to keep blobs"));
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn decompile(
     in_path: &Path,
     out_path: Option<String>,
+    default_out_path: &Path,
     strict: bool,
     multithread: bool,
     verbose: bool,
     deobf_mode: deobfuscate::Mode,
     fix_events: bool,
-) -> Result<(), String> {
+    convert_to: Option<GameVersion>,
+    write_name_map: Option<String>,
+    read_name_map: Option<String>,
+    rename_map: Option<String>,
+    scan: bool,
+    strip_flagged: bool,
+    validate: bool,
+    fix_strings: bool,
+    export_dir: Option<String>,
+    image_dir: Option<String>,
+    strips: bool,
+    dump_trailing: Option<String>,
+    resume: bool,
+    keep_journal: bool,
+    meta: &gm8decompiler::Metadata,
+) -> Result<Decompiled, String> {
     // slurp in file contents
     let file = fs::read(&in_path).map_err(|e| format!("Failed to read '{}': {}", in_path.display(), e))?;
 
+    let name_map = match &read_name_map {
+        Some(path) => Some(fs::read(path).map_err(|e| format!("Failed to read '{}': {}", path, e))?),
+        None => None,
+    };
+    let previous_names = match (&read_name_map, &name_map) {
+        (Some(path), Some(text)) => serde_json::from_slice::<Vec<deobfuscate::Renamed>>(text)
+            .map_err(|e| format!("Invalid name map '{}': {}", path, e))?,
+        _ => Vec::new(),
+    };
+    let rename_map = match rename_map {
+        Some(path) => Some(fs::read_to_string(&path).map_err(|e| format!("Failed to read '{}': {}", path, e))?),
+        None => None,
+    };
+    let renames = match &rename_map {
+        Some(text) => gm8decompiler::rename::parse_map(text).map_err(|e| format!("Invalid rename map: {}", e))?,
+        None => Vec::new(),
+    };
+
+    // remember what's being decompiled and how, so an interrupted output file is only resumed if nothing changed -
+    // the name maps are part of that, and it's what's in them that counts rather than where they are
+    let settings = format!(
+        "{:?} {} {} {} {:?} {:?} {:?} {} {} {}",
+        deobf_mode,
+        fix_events,
+        strip_flagged,
+        fix_strings,
+        meta.author,
+        meta.company,
+        convert_to,
+        meta.compression.level(),
+        name_map.is_some(),
+        rename_map.is_some(),
+    );
+    let fingerprint = gm8decompiler::journal::fingerprint(
+        &[&file, name_map.as_deref().unwrap_or_default(), rename_map.as_deref().unwrap_or_default().as_bytes()],
+        &settings,
+    );
+
     // parse (entire) gamedata
     let logger = if verbose { Some(|msg: &str| println!("{}", msg)) } else { None };
-    let mut assets = gm8exe::reader::from_exe(file, logger, strict, multithread) // huge call
-        .map_err(|e| format!("Reader error: {}", e))?;
+    let options = gm8exe::reader::ReaderOptions { strict, multithread, ..Default::default() };
+    // the verbose log already says what's being read, so this only shows a count for each kind of asset without it
+    let progress = |p| {
+        if let gm8exe::reader::ReaderProgress::Assets { kind, done, total } = p {
+            if !verbose && total > 0 && (done % 16 == 0 || done == total) {
+                print!("\rReading {}s... {}/{}", kind.name(), done, total);
+                if done == total {
+                    println!();
+                }
+                let _ = io::stdout().flush();
+            }
+        }
+    };
+
+    // anything without an exe header is taken to be gamedata which has been cut out of its exe
+    let mut assets = if file.starts_with(b"MZ") {
+        gm8exe::reader::from_exe_with_callback(file, logger, options, progress) // huge call
+    } else {
+        println!("No exe header, so reading the file as standalone gamedata");
+        gm8exe::reader::from_gamedata_with_callback(file, logger, options, progress)
+    }
+    .map_err(|e| format!("Reader error: {}", e))?;
 
     println!("Successfully parsed game!");
+    let decompiled = Decompiled { version: assets.version, assets: AssetCounts::of(&assets) };
+
+    if let Some(info) = assets.exe_metadata.as_ref().and_then(|x| x.version_info.as_ref()) {
+        for &(key, label) in &[("ProductName", "Product"), ("CompanyName", "Company"), ("FileVersion", "Version")] {
+            if let Some(value) = info.string(key).filter(|x| !x.is_empty()) {
+                println!("{}: {}", label, value);
+            }
+        }
+    }
+
+    if let Some(data) = &assets.trailing_data {
+        let start = data.iter().take(16).map(|x| format!("{:02X}", x)).collect::<Vec<_>>().join(" ");
+        println!("Found {} bytes of data after the gamedata, starting with {}", data.len(), start);
+        if strict && data.len() > 1024 * 1024 {
+            println!("***WARNING*** That's a lot of trailing data, so part of the game may have been left unread");
+        }
+    }
+    if let Some(path) = &dump_trailing {
+        match &assets.trailing_data {
+            Some(data) => {
+                fs::write(path, data).map_err(|e| format!("Failed to write trailing data to '{}': {}", path, e))?;
+                println!("Successfully wrote trailing data to '{}'", path);
+            },
+            None => println!("There's no trailing data to write to '{}'", path),
+        }
+    }
 
     //Do we want to deobfuscate, yes or no?
     let deobfuscate = match deobf_mode {
         deobfuscate::Mode::On => true,
         deobfuscate::Mode::Off => false,
-        deobfuscate::Mode::Auto => {
-            assets.backgrounds.iter().flatten().any(|s| s.name.0.is_empty())
-                || assets.fonts.iter().flatten().any(|s| s.name.0.is_empty())
-                || assets.objects.iter().flatten().any(|s| s.name.0.is_empty())
-                || assets.paths.iter().flatten().any(|s| s.name.0.is_empty())
-                || assets.rooms.iter().flatten().any(|s| s.name.0.is_empty())
-                || assets.sounds.iter().flatten().any(|s| s.name.0.is_empty())
-                || assets.sprites.iter().flatten().any(|s| s.name.0.is_empty())
-                || assets.timelines.iter().flatten().any(|s| s.name.0.is_empty())
-        },
+        deobfuscate::Mode::Auto => gm8decompiler::looks_obfuscated(&assets),
     };
     if deobf_mode == deobfuscate::Mode::Auto && deobfuscate {
         println!("Note: GMK looks obfuscated, so de-obfuscation has been enabled by default");
         println!(" -- you can turn this off with '-d off'");
     }
 
-    fn fix_event(ev: &mut gm8exe::asset::CodeAction) {
-        // So far the only broken event type I know of is custom Execute Code actions.
-        // We can fix these by changing the act id and lib id to be a default Execute Code action instead.
-        if ev.action_kind == 7 && ev.execution_type == 2 {
-            // 7 = code block param, 2 = code execution
-            ev.id = 603;
-            ev.lib_id = 1;
+    if let Some(version) = convert_to {
+        for note in gm8decompiler::convert(&mut assets, version) {
+            println!("[Convert] {}", note);
         }
     }
 
     if fix_events {
-        assets
-            .objects
-            .iter_mut()
-            .flatten()
-            .flat_map(|x| x.events.iter_mut().flatten())
-            .flat_map(|(_, x)| x.iter_mut())
-            .for_each(|ev| fix_event(ev));
-
-        assets
-            .timelines
-            .iter_mut()
-            .flatten()
-            .flat_map(|x| x.moments.iter_mut().flat_map(|(_, x)| x.iter_mut()))
-            .for_each(|ev| fix_event(ev));
+        gm8decompiler::fix_events(&mut assets);
     }
 
+    // this goes before anything else parses the code, so fixed strings are seen by --scan and the deobfuscator
+    if validate {
+        let report = gm8decompiler::validate::validate(&mut assets, fix_strings);
+        for fix in report.fixes.iter() {
+            println!("[Fix] {} line {}: {}", fix.location, fix.line, fix.description);
+        }
+        for problem in report.problems.iter() {
+            println!("[GML] {} line {}: {}", problem.location, problem.line, problem.message);
+        }
+        match (report.problems.len(), fix_strings) {
+            (0, _) => println!("Validation found no problems with GML"),
+            (n, true) => println!("***WARNING*** Validation found {} problem(s) which couldn't be repaired", n),
+            (n, false) => println!(
+                "***WARNING*** Validation found {} problem(s); GameMaker may refuse to load the output \
+                 (--fix-strings can repair unclosed strings and NUL bytes)",
+                n
+            ),
+        }
+    }
+
+    if scan {
+        let findings = gm8decompiler::scan::scan(&mut assets, strip_flagged);
+        for finding in findings.iter() {
+            println!("[Scan] {}: {}", finding.location, finding.reason);
+        }
+        match (findings.len(), strip_flagged) {
+            (0, _) => println!("Scan found nothing suspicious"),
+            (n, true) => println!("***WARNING*** Scan flagged {} location(s); flagged code has been commented out", n),
+            (n, false) => println!("***WARNING*** Scan flagged {} location(s); use --strip-flagged to disable them", n),
+        }
+    }
+
+    // with --export-gml, --export-images or --dump-trailing, the project file is only written if it was asked for
+    let write_project =
+        out_path.is_some() || (export_dir.is_none() && image_dir.is_none() && dump_trailing.is_none());
+
     // warn user if they specified .gmk for 8.0 or .gm81 for 8.0
-    let out_expected_ext = match assets.version {
-        GameVersion::GameMaker8_0 => "gmk",
-        GameVersion::GameMaker8_1 => "gm81",
-    };
+    let out_expected_ext = gm8decompiler::gmk_extension(assets.version);
     let out_path = match out_path {
         Some(p) => {
             let path = PathBuf::from(p);
@@ -260,15 +676,13 @@ fn decompile(
                     println!(
                         concat!(
                             "***WARNING*** You've specified an output file '{}'",
-                            "a .{} file, for a {} game.\nYou should use '-o {}.{}' instead, ",
-                            "otherwise you won't be able to load the file with GameMaker.",
+                            "a .{} file, for a {} project.\nYou should use '-o {}.{}' instead, ",
+                            "otherwise you won't be able to load the file with GameMaker, ",
+                            "or --convert-to to write the project for the other version.",
                         ),
                         path.display(),
                         extension,
-                        match assets.version {
-                            GameVersion::GameMaker8_0 => "GameMaker 8.0",
-                            GameVersion::GameMaker8_1 => "GameMaker 8.1",
-                        },
+                        gm8decompiler::version_name(assets.version),
                         path.file_stem().and_then(|oss| oss.to_str()).unwrap_or("filename"),
                         out_expected_ext,
                     );
@@ -278,100 +692,120 @@ fn decompile(
             path
         },
         None => {
-            let mut path = PathBuf::from(in_path);
+            // next to the input, or in the matching place in the output directory in batch mode
+            let mut path = default_out_path.to_path_buf();
             path.set_extension(out_expected_ext);
             path
         },
     };
 
     if deobfuscate {
-        deobfuscate::process(&mut assets);
+        let names = deobfuscate::process(&mut assets, &previous_names);
+        if let Some(path) = write_name_map {
+            let json = serde_json::to_string_pretty(&names).map_err(|e| format!("Failed to write name map: {}", e))?;
+            fs::write(&path, json).map_err(|e| format!("Failed to write name map to '{}': {}", path, e))?;
+            println!("Wrote the names of {} asset(s) to '{}'", names.len(), path);
+        }
+    } else if write_name_map.is_some() || read_name_map.is_some() {
+        println!("[Warning] Name maps are only used when deobfuscating, so they've been ignored");
+    }
+    if !renames.is_empty() {
+        let warnings = gm8decompiler::rename::apply(&mut assets, &renames)
+            .map_err(|e| format!("Failed to rename assets: {}", e))?;
+        for warning in warnings {
+            println!("[Warning] {}", warning);
+        }
+        println!("Renamed assets using the rename map");
     }
 
-    let mut gmk = fs::File::create(&out_path)
-        .map_err(|e| format!("Failed to create output file '{}': {}", out_path.display(), e))?;
-
-    println!("Writing {} header...", out_expected_ext);
-    gmk::write_header(&mut gmk, assets.version, assets.game_id, assets.guid)
-        .map_err(|e| format!("Failed to write header: {}", e))?;
-
-    println!("Writing {} settings...", out_expected_ext);
-    let ico_file = assets.ico_file_raw.take();
-    gmk::write_settings(&mut gmk, &assets.settings, ico_file, assets.version)
-        .map_err(|e| format!("Failed to write settings block: {}", e))?;
-
-    println!("Writing {} triggers...", assets.triggers.len());
-    gmk::write_asset_list(&mut gmk, &assets.triggers, gmk::write_trigger, assets.version, multithread)
-        .map_err(|e| format!("Failed to write triggers: {}", e))?;
-
-    gmk::write_timestamp(&mut gmk).map_err(|e| format!("Failed to write timestamp: {}", e))?;
-
-    println!("Writing {} constants...", assets.constants.len());
-    gmk::write_constants(&mut gmk, &assets.constants).map_err(|e| format!("Failed to write constants: {}", e))?;
-
-    println!("Writing {} sounds...", assets.sounds.len());
-    gmk::write_asset_list(&mut gmk, &assets.sounds, gmk::write_sound, assets.version, multithread)
-        .map_err(|e| format!("Failed to write sounds: {}", e))?;
-
-    println!("Writing {} sprites...", assets.sprites.len());
-    gmk::write_asset_list(&mut gmk, &assets.sprites, gmk::write_sprite, assets.version, multithread)
-        .map_err(|e| format!("Failed to write sprites: {}", e))?;
-
-    println!("Writing {} backgrounds...", assets.backgrounds.len());
-    gmk::write_asset_list(&mut gmk, &assets.backgrounds, gmk::write_background, assets.version, multithread)
-        .map_err(|e| format!("Failed to write backgrounds: {}", e))?;
-
-    println!("Writing {} paths...", assets.paths.len());
-    gmk::write_asset_list(&mut gmk, &assets.paths, gmk::write_path, assets.version, multithread)
-        .map_err(|e| format!("Failed to write paths: {}", e))?;
-
-    println!("Writing {} scripts...", assets.scripts.len());
-    gmk::write_asset_list(&mut gmk, &assets.scripts, gmk::write_script, assets.version, multithread)
-        .map_err(|e| format!("Failed to write scripts: {}", e))?;
-
-    println!("Writing {} fonts...", assets.fonts.len());
-    gmk::write_asset_list(&mut gmk, &assets.fonts, gmk::write_font, assets.version, multithread)
-        .map_err(|e| format!("Failed to write fonts: {}", e))?;
-
-    println!("Writing {} timelines...", assets.timelines.len());
-    gmk::write_asset_list(&mut gmk, &assets.timelines, gmk::write_timeline, assets.version, multithread)
-        .map_err(|e| format!("Failed to write timelines: {}", e))?;
-
-    println!("Writing {} objects...", assets.objects.len());
-    gmk::write_asset_list(&mut gmk, &assets.objects, gmk::write_object, assets.version, multithread)
-        .map_err(|e| format!("Failed to write objects: {}", e))?;
-
-    println!("Writing {} rooms...", assets.rooms.len());
-    gmk::write_asset_list(&mut gmk, &assets.rooms, gmk::write_room, assets.version, multithread)
-        .map_err(|e| format!("Failed to write rooms: {}", e))?;
-
-    println!(
-        "Writing room editor metadata... (last instance: {}, last tile: {})",
-        assets.last_instance_id, assets.last_tile_id
-    );
-    gmk::write_room_editor_meta(&mut gmk, assets.last_instance_id, assets.last_tile_id)
-        .map_err(|e| format!("Failed to write room editor metadata: {}", e))?;
-
-    println!("Writing {} included files...", assets.included_files.len());
-    gmk::write_included_files(&mut gmk, &assets.included_files)
-        .map_err(|e| format!("Failed to write included files: {}", e))?;
-
-    println!("Writing {} extensions...", assets.extensions.len());
-    gmk::write_extensions(&mut gmk, &assets.extensions).map_err(|e| format!("Failed to write extensions: {}", e))?;
-
-    println!("Writing game information...");
-    gmk::write_game_information(&mut gmk, &assets.help_dialog)
-        .map_err(|e| format!("Failed to write game information: {}", e))?;
-
-    println!("Writing {} library initialization strings...", assets.library_init_strings.len());
-    gmk::write_library_init_code(&mut gmk, &assets.library_init_strings)
-        .map_err(|e| format!("Failed to write library initialization code: {}", e))?;
+    if let Some(dir) = export_dir {
+        let count = gm8decompiler::export::export(&assets, Path::new(&dir))
+            .map_err(|e| format!("Failed to export GML to '{}': {}", dir, e))?;
+        println!("Successfully exported {} GML file(s) to '{}'", count, dir);
+    }
+    if let Some(dir) = image_dir {
+        let count = gm8decompiler::images::export_images(&assets, Path::new(&dir), strips, multithread)
+            .map_err(|e| format!("Failed to export images to '{}': {}", dir, e))?;
+        println!("Successfully exported {} image(s) to '{}'", count, dir);
+    }
+    if !write_project {
+        return Ok(decompiled)
+    }
 
-    println!("Writing room order ({} rooms)...", assets.room_order.len());
-    gmk::write_room_order(&mut gmk, &assets.room_order).map_err(|e| format!("Failed to write room order: {}", e))?;
+    // keeping a journal means syncing the output after every block, so it's only done if it might get resumed
+    let journal_path = gm8decompiler::journal::path_for(&out_path);
+    let resumable = if resume {
+        Journal::load(journal_path.clone(), fingerprint)
+            .filter(|journal| fs::metadata(&out_path).ok().filter(|m| m.len() >= journal.end()).is_some())
+    } else {
+        None
+    };
+    let (mut gmk, mut journal) = match resumable {
+        Some(journal) => {
+            println!("Resuming: {} of {} blocks were already written", journal.completed(), GMK_BLOCKS.len());
+            let mut gmk = fs::OpenOptions::new()
+                .write(true)
+                .open(&out_path)
+                .map_err(|e| format!("Failed to open output file '{}': {}", out_path.display(), e))?;
+            // anything past the last complete block is left over from the one which was interrupted
+            gmk.set_len(journal.end())
+                .and_then(|()| gmk.seek(SeekFrom::End(0)))
+                .map_err(|e| format!("Failed to rewind output file: {}", e))?;
+            (gmk, Some(journal))
+        },
+        None => {
+            if resume {
+                println!("Nothing to resume (output has changed or wasn't interrupted), starting from the beginning");
+            }
+            let gmk = fs::File::create(&out_path)
+                .map_err(|e| format!("Failed to create output file '{}': {}", out_path.display(), e))?;
+            let journal = if keep_journal {
+                Some(
+                    Journal::create(journal_path, fingerprint, meta.timestamp)
+                        .map_err(|e| format!("Failed to create journal: {}", e))?,
+                )
+            } else {
+                // one left over from an earlier attempt would no longer match the output
+                let _ = fs::remove_file(&journal_path);
+                None
+            };
+            (gmk, journal)
+        },
+    };
 
-    println!("Writing resource tree...");
-    gmk::write_resource_tree(&mut gmk, &assets).map_err(|e| format!("Failed to write resource tree: {}", e))?;
+    // use the same timestamp as the first attempt, in case it was "now"
+    let meta = gm8decompiler::Metadata {
+        author: meta.author.clone(),
+        company: meta.company.clone(),
+        timestamp: journal.as_ref().map_or(meta.timestamp, Journal::timestamp),
+        compression: meta.compression,
+    };
+    gm8decompiler::write_gmk_blocks(
+        &mut gmk,
+        &assets,
+        &meta,
+        multithread,
+        journal.as_ref().map_or(0, Journal::completed),
+        |msg| println!("{}", msg),
+        |gmk, _| match &mut journal {
+            Some(journal) => {
+                // the block has to actually be on disk before the journal says it is
+                let end = gmk.stream_position().map_err(|e| format!("Failed to get output position: {}", e))?;
+                gmk.sync_data().map_err(|e| format!("Failed to flush output file: {}", e))?;
+                journal.record(end).map_err(|e| format!("Failed to update journal: {}", e))
+            },
+            None => Ok(()),
+        },
+    )
+    .map_err(|e| match &journal {
+        Some(_) => format!("{}\nRun again with --resume to carry on from where this stopped.", e),
+        None => e,
+    })?;
+
+    if let Some(journal) = journal {
+        let size = gmk.metadata().map_err(|e| format!("Failed to check output file: {}", e))?.len();
+        journal.finish(GMK_BLOCKS.len(), size)?;
+    }
 
     println!(
         "Successfully written {} to '{}'",
@@ -379,5 +813,5 @@ fn decompile(
         out_path.file_name().and_then(|oss| oss.to_str()).unwrap_or("<INVALID UTF-8>"),
     );
 
-    Ok(())
+    Ok(decompiled)
 }
@@ -0,0 +1,331 @@
+// Renames assets from a map file, for when better names than the generated ones are known.
+//
+// The map is tab-separated, one asset per line: its kind, its index or current name, and the new name, like
+//     sprite	3	spr_player
+//     object	object12	obj_enemy
+// Blank lines and lines starting with # are skipped. References to the old names in code, action arguments,
+// trigger conditions and constants are renamed too. This goes through the GML lexer, so strings, comments and
+// anything after a . are left alone, and the rest of the code keeps its formatting.
+
+use crate::scan::code_of;
+use gm8exe::{
+    asset::{CodeAction, PascalString},
+    GameAssets,
+};
+use gml_parser::{lexer::Lexer, token::Token};
+use std::collections::HashMap;
+
+// The kinds of asset which can be renamed, as written in the map.
+pub(crate) const KINDS: [&str; 11] =
+    ["sprite", "sound", "background", "path", "script", "font", "timeline", "object", "room", "trigger", "constant"];
+
+// One line of a map file.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub line: usize,
+    pub kind: String,
+    pub asset: String,
+    pub name: String,
+}
+
+// Reads a map file. Lines which don't have three fields are an error, since the file's probably in the wrong format.
+pub fn parse_map(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue
+        }
+        match line.split('\t').map(str::trim).collect::<Vec<_>>().as_slice() {
+            [kind, asset, name] => entries.push(Entry {
+                line: i + 1,
+                kind: kind.to_ascii_lowercase(),
+                asset: asset.to_string(),
+                name: name.to_string(),
+            }),
+            _ => return Err(format!("line {} should be a kind, an asset and a new name, with tabs between", i + 1)),
+        }
+    }
+    Ok(entries)
+}
+
+// Applies a map to a game. Returns a warning for each entry which doesn't match an asset,
+// or an error if any asset would end up with the same name as another one.
+pub fn apply(assets: &mut GameAssets, entries: &[Entry]) -> Result<Vec<String>, String> {
+    let mut warnings = Vec::new();
+
+    // every name in the game, as (kind, index, name), since they all share one namespace
+    let old_names = names(assets);
+    let mut names = old_names.clone();
+    let mut renamed = HashMap::new();
+    for entry in entries {
+        let kind = match KINDS.iter().position(|&x| x == entry.kind) {
+            Some(kind) => kind,
+            None => {
+                warnings.push(format!("line {}: there's no kind of asset called '{}'", entry.line, entry.kind));
+                continue
+            },
+        };
+        let found = match entry.asset.parse::<usize>() {
+            Ok(index) => names.iter().position(|x| x.0 == kind && x.1 == index),
+            Err(_) => names.iter().position(|x| x.0 == kind && &*x.2 == entry.asset.as_bytes()),
+        };
+        let position = match found {
+            Some(position) => position,
+            None => {
+                warnings.push(format!("line {}: there's no {} {}", entry.line, entry.kind, entry.asset));
+                continue
+            },
+        };
+        if !is_identifier(entry.name.as_bytes()) {
+            return Err(format!("line {}: '{}' isn't a valid name for an asset", entry.line, entry.name))
+        }
+        renamed.insert(position, names[position].2.clone());
+        names[position].2 = entry.name.as_bytes().into();
+    }
+
+    // renaming something to a name that's still in use would make code referring to either of them ambiguous
+    let mut taken: HashMap<&[u8], usize> = HashMap::new();
+    for (position, (_, _, name)) in names.iter().enumerate() {
+        if let Some(&other) = taken.get(&name[..]) {
+            if renamed.contains_key(&position) || renamed.contains_key(&other) {
+                let (first, second) = if renamed.contains_key(&other) { (other, position) } else { (position, other) };
+                return Err(format!(
+                    "can't rename {} to '{}', since {} is also called that",
+                    describe(&names, &renamed, first),
+                    String::from_utf8_lossy(name),
+                    describe(&names, &renamed, second),
+                ))
+            }
+        }
+        taken.entry(name).or_insert(position);
+    }
+
+    // an old name shared by more than one asset can't be renamed in code, as there's no telling which is meant
+    let mut replacements: HashMap<Box<[u8]>, Box<[u8]>> = HashMap::new();
+    for (&position, old) in renamed.iter() {
+        let shared = old_names.iter().filter(|x| x.2 == *old).count() > 1;
+        if shared {
+            warnings.push(format!(
+                "{} shares its old name with another asset, so references to it in code weren't renamed",
+                describe(&names, &renamed, position),
+            ));
+        } else if !old.is_empty() {
+            replacements.insert(old.clone(), names[position].2.clone());
+        }
+    }
+
+    for (position, (kind, index, name)) in names.into_iter().enumerate() {
+        if renamed.contains_key(&position) {
+            *name_of(assets, kind, index) = PascalString(name);
+        }
+    }
+    if !replacements.is_empty() {
+        for code in sources(assets) {
+            if let Some(new) = rename_identifiers(&code.0, &replacements) {
+                *code = PascalString(new.into());
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+// Renames identifiers in a piece of GML, or returns None if there weren't any to rename.
+// Identifiers right after a . are fields of an instance rather than assets, so they're left alone.
+pub fn rename_identifiers(code: &[u8], replacements: &HashMap<Box<[u8]>, Box<[u8]>>) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut copied = 0;
+    let mut after_period = false;
+    for token in Lexer::new(code) {
+        if let Token::Identifier(ident) = token {
+            if let Some(new) = replacements.get(ident).filter(|_| !after_period) {
+                let start = ident.as_ptr() as usize - code.as_ptr() as usize;
+                output.extend_from_slice(&code[copied..start]);
+                output.extend_from_slice(new);
+                copied = start + ident.len();
+            }
+        }
+        after_period = matches!(token, Token::Separator(gml_parser::token::Separator::Period));
+    }
+    if copied == 0 {
+        return None
+    }
+    output.extend_from_slice(&code[copied..]);
+    Some(output)
+}
+
+pub(crate) fn is_identifier(name: &[u8]) -> bool {
+    matches!(name.first(), Some(c) if c.is_ascii_alphabetic() || *c == b'_')
+        && name.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_')
+}
+
+// Names an asset for a message, like "sprite 3 (spr_player)", using its old name if it's being renamed.
+fn describe(names: &[(usize, usize, Box<[u8]>)], renamed: &HashMap<usize, Box<[u8]>>, position: usize) -> String {
+    let (kind, index, name) = &names[position];
+    let name = renamed.get(&position).unwrap_or(name);
+    format!("{} {} ({})", KINDS[*kind], index, String::from_utf8_lossy(name))
+}
+
+// Lists the names of all the assets, by their position in KINDS and their index.
+pub(crate) fn names(assets: &GameAssets) -> Vec<(usize, usize, Box<[u8]>)> {
+    fn add<T>(names: &mut Vec<(usize, usize, Box<[u8]>)>, kind: usize, list: &[Option<Box<T>>], f: fn(&T) -> &[u8]) {
+        names.extend(list.iter().enumerate().filter_map(|(i, x)| x.as_ref().map(|x| (kind, i, f(x).into()))));
+    }
+    let mut names = Vec::new();
+    add(&mut names, 0, &assets.sprites, |x| &x.name.0);
+    add(&mut names, 1, &assets.sounds, |x| &x.name.0);
+    add(&mut names, 2, &assets.backgrounds, |x| &x.name.0);
+    add(&mut names, 3, &assets.paths, |x| &x.name.0);
+    add(&mut names, 4, &assets.scripts, |x| &x.name.0);
+    add(&mut names, 5, &assets.fonts, |x| &x.name.0);
+    add(&mut names, 6, &assets.timelines, |x| &x.name.0);
+    add(&mut names, 7, &assets.objects, |x| &x.name.0);
+    add(&mut names, 8, &assets.rooms, |x| &x.name.0);
+    add(&mut names, 9, &assets.triggers, |x| &x.constant_name.0);
+    names.extend(assets.constants.iter().enumerate().map(|(i, x)| (10, i, x.name.0.clone())));
+    names
+}
+
+pub(crate) fn name_of(assets: &mut GameAssets, kind: usize, index: usize) -> &mut PascalString {
+    fn get<T>(list: &mut [Option<Box<T>>], index: usize) -> &mut T {
+        list[index].as_mut().expect("only existing assets are renamed")
+    }
+    match kind {
+        0 => &mut get(&mut assets.sprites, index).name,
+        1 => &mut get(&mut assets.sounds, index).name,
+        2 => &mut get(&mut assets.backgrounds, index).name,
+        3 => &mut get(&mut assets.paths, index).name,
+        4 => &mut get(&mut assets.scripts, index).name,
+        5 => &mut get(&mut assets.fonts, index).name,
+        6 => &mut get(&mut assets.timelines, index).name,
+        7 => &mut get(&mut assets.objects, index).name,
+        8 => &mut get(&mut assets.rooms, index).name,
+        9 => &mut get(&mut assets.triggers, index).constant_name,
+        _ => &mut assets.constants[index].name,
+    }
+}
+
+// Finds every piece of GML which could refer to an asset by name.
+fn sources(assets: &mut GameAssets) -> Vec<&mut PascalString> {
+    // the arguments of drag and drop actions which are expressions, the same ones the deobfuscator goes through
+    fn arguments(action: &mut CodeAction) -> Vec<&mut PascalString> {
+        match action.action_kind {
+            0 => action
+                .param_strings
+                .iter_mut()
+                .zip(action.param_types.iter())
+                .take(action.param_count)
+                .filter(|(_, &ty)| ty == 0)
+                .map(|(x, _)| x)
+                .collect(),
+            5 => action.param_strings.iter_mut().take(1).collect(),
+            6 => action.param_strings.iter_mut().take(2).collect(),
+            _ => code_of(action).into_iter().collect(),
+        }
+    }
+
+    let mut sources = Vec::new();
+    sources.extend(assets.scripts.iter_mut().flatten().map(|x| &mut x.source));
+    for timeline in assets.timelines.iter_mut().flatten() {
+        sources.extend(timeline.moments.iter_mut().flat_map(|(_, x)| x.iter_mut()).flat_map(arguments));
+    }
+    for object in assets.objects.iter_mut().flatten() {
+        let actions = object.events.iter_mut().flatten().flat_map(|(_, x)| x.iter_mut());
+        sources.extend(actions.flat_map(arguments));
+    }
+    for room in assets.rooms.iter_mut().flatten() {
+        sources.push(&mut room.creation_code);
+        sources.extend(room.instances.iter_mut().map(|x| &mut x.creation_code));
+    }
+    sources.extend(assets.triggers.iter_mut().flatten().map(|x| &mut x.condition));
+    sources.extend(assets.constants.iter_mut().map(|x| &mut x.expression));
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, parse_map, rename_identifiers, Entry};
+    use gm8exe::asset::{CodeAction, PascalString};
+    use std::collections::HashMap;
+
+    fn action(kind: u32, params: &[&str]) -> CodeAction {
+        let mut param_strings: [PascalString; 8] = Default::default();
+        for (param, string) in params.iter().zip(param_strings.iter_mut()) {
+            *string = (*param).into();
+        }
+        CodeAction {
+            id: if kind == 7 { 603 } else { 101 },
+            applies_to: -1,
+            is_condition: false,
+            invert_condition: false,
+            is_relative: false,
+            lib_id: 1,
+            action_kind: kind,
+            execution_type: if kind == 7 { 2 } else { 1 },
+            can_be_relative: 0,
+            applies_to_something: true,
+            fn_name: "".into(),
+            fn_code: "".into(),
+            param_count: params.len(),
+            param_types: [0; 8],
+            param_strings,
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        let mut replacements: HashMap<Box<[u8]>, Box<[u8]>> = HashMap::new();
+        replacements.insert((*b"spr0").into(), (*b"spr_player").into());
+        replacements.insert((*b"x").into(), (*b"nope").into());
+        let code = b"sprite_index = spr0; // spr0\r\nname = \"spr0\"; other.spr0 = spr0.x;";
+        let renamed = rename_identifiers(code, &replacements).unwrap();
+        assert_eq!(
+            String::from_utf8(renamed).unwrap(),
+            "sprite_index = spr_player; // spr0\r\nname = \"spr0\"; other.spr0 = spr_player.x;"
+        );
+        assert_eq!(rename_identifiers(b"a = 1;", &replacements), None);
+    }
+
+    #[test]
+    fn map_files() {
+        let map = "# kind\tasset\tname\r\nsprite\t0\tspr_player\r\n\r\nOBJECT\tobj2\tobj_enemy\r\n";
+        assert_eq!(parse_map(map).unwrap(), [
+            Entry { line: 2, kind: "sprite".into(), asset: "0".into(), name: "spr_player".into() },
+            Entry { line: 4, kind: "object".into(), asset: "obj2".into(), name: "obj_enemy".into() },
+        ]);
+        assert!(parse_map("sprite 0 spr_player").unwrap_err().starts_with("line 1"));
+    }
+
+    #[test]
+    fn renames_assets_and_code() {
+        let mut assets = crate::tests::fixture_with_gaps();
+        assets.scripts[0].as_mut().unwrap().source = "instance_create(0, 0, obj2); return scr_1(spr0);".into();
+        let object = assets.objects[0].as_mut().unwrap();
+        object.events = vec![vec![(0, vec![action(7, &["sprite_index = spr0;"]), action(0, &["obj2", "spr0"])])]];
+        let map = "sprite\tspr0\tspr_player\nobject\t2\tobj_enemy\nscript\t1\tscr_move\nsound\t1\tsnd\n";
+        let map = parse_map(map).unwrap();
+        let warnings = apply(&mut assets, &map).unwrap();
+        assert_eq!(warnings, ["line 4: there's no sound 1"]);
+
+        assert_eq!(&*assets.sprites[0].as_ref().unwrap().name.0, b"spr_player");
+        assert_eq!(&*assets.objects[2].as_ref().unwrap().name.0, b"obj_enemy");
+        assert_eq!(&*assets.scripts[1].as_ref().unwrap().name.0, b"scr_move");
+        assert_eq!(
+            &*assets.scripts[0].as_ref().unwrap().source.0,
+            b"instance_create(0, 0, obj_enemy); return scr_move(spr_player);"
+        );
+        let actions = &assets.objects[0].as_ref().unwrap().events[0][0].1;
+        assert_eq!(&*actions[0].param_strings[0].0, b"sprite_index = spr_player;");
+        assert_eq!(&*actions[1].param_strings[0].0, b"obj_enemy");
+        assert_eq!(&*actions[1].param_strings[1].0, b"spr_player");
+
+        // swapping two names is fine, but taking one which is still in use isn't
+        let map = parse_map("sprite\tspr_player\tspr2\nsprite\t2\tspr_player\n").unwrap();
+        assert!(apply(&mut assets, &map).unwrap().is_empty());
+        assert_eq!(&*assets.sprites[2].as_ref().unwrap().name.0, b"spr_player");
+        let error = apply(&mut assets, &parse_map("object\tobj0\tspr2\n").unwrap()).unwrap_err();
+        assert_eq!(error, "can't rename object 0 (obj0) to 'spr2', since sprite 0 (spr2) is also called that");
+        assert!(apply(&mut assets, &parse_map("object\tobj0\t2fast\n").unwrap()).is_err());
+        assert_eq!(&*assets.objects[0].as_ref().unwrap().name.0, b"obj0");
+    }
+}
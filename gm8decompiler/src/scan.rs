@@ -0,0 +1,290 @@
+// Looks for the tell-tale signs of malware droppers dressed up as games:
+// running programs out of the temp folder, adding themselves to Windows startup, and so on.
+//
+// Only the parsed GML is inspected, so this can't see through strings which are built up at runtime.
+// That's fine - the point is to catch the copy-pasted droppers, while never flagging an ordinary game.
+
+use gm8exe::{
+    asset::{included_file::ExportSetting, CodeAction, PascalString},
+    GameAssets,
+};
+use gml_parser::ast::{self, Expr, AST};
+
+// Something suspicious, and where it was found.
+pub struct Finding {
+    pub location: String,
+    pub reason: &'static str,
+}
+
+// Checks all the code and included files in a game. If `strip` is set, flagged code is commented out.
+pub fn scan(assets: &mut GameAssets, strip: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut runs_programs = false;
+    let mut check = |location: String, code: &mut PascalString| {
+        let (reason, executes) = inspect(&code.0);
+        runs_programs |= executes;
+        if let Some(reason) = reason {
+            findings.push(Finding { location, reason });
+            if strip {
+                *code = PascalString(comment_out(&code.0, reason).into());
+            }
+        }
+    };
+
+    for (i, script) in assets.scripts.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&script.name.0).into_owned();
+        check(format!("script {} ({})", i, name), &mut script.source);
+    }
+
+    for (i, timeline) in assets.timelines.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&timeline.name.0).into_owned();
+        for (moment, actions) in timeline.moments.iter_mut() {
+            for (j, code) in actions.iter_mut().enumerate().filter_map(|(j, x)| code_of(x).map(|x| (j, x))) {
+                check(format!("timeline {} ({}) moment {} action {}", i, name, moment, j), code);
+            }
+        }
+    }
+
+    for (i, object) in assets.objects.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&object.name.0).into_owned();
+        for (e1, events) in object.events.iter_mut().enumerate() {
+            for (e2, actions) in events.iter_mut() {
+                for (j, code) in actions.iter_mut().enumerate().filter_map(|(j, x)| code_of(x).map(|x| (j, x))) {
+                    check(format!("object {} ({}) event {},{} action {}", i, name, e1, e2, j), code);
+                }
+            }
+        }
+    }
+
+    for (i, room) in assets.rooms.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&room.name.0).into_owned();
+        check(format!("creation code for room {} ({})", i, name), &mut room.creation_code);
+        for instance in room.instances.iter_mut() {
+            let location = format!("creation code for instance {} in room {} ({})", instance.id, i, name);
+            check(location, &mut instance.creation_code);
+        }
+    }
+
+    // An executable on its own is fine, but one which gets exported and then run by the game is how droppers work
+    for (i, file) in assets.included_files.iter().enumerate() {
+        let name = String::from_utf8_lossy(&file.file_name.0).to_ascii_lowercase();
+        let reason = match &file.export_settings {
+            ExportSetting::CustomFolder(folder) if is_startup_folder(&folder.0) => {
+                Some("included file is exported into the Windows startup folder")
+            },
+            ExportSetting::NoExport => None,
+            _ if runs_programs && EXECUTABLE_EXTENSIONS.iter().any(|ext| name.ends_with(ext)) => {
+                Some("executable included file is exported, and the game runs programs")
+            },
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            findings.push(Finding { location: format!("included file {} ({})", i, name), reason });
+        }
+    }
+
+    findings
+}
+
+const EXECUTABLE_EXTENSIONS: &[&str] = &[".exe", ".scr", ".com", ".bat", ".cmd", ".vbs", ".js", ".ps1"];
+const TEMP_FOLDERS: &[&str] = &["%temp%", "\\temp\\", "%appdata%", "\\appdata\\"];
+const SCRIPT_HOSTS: &[&str] = &[".vbs", ".scr", ".ps1", "wscript", "powershell"];
+
+// Gets the code of an Execute Code action, which is the only kind which can be commented out.
+pub(crate) fn code_of(action: &mut CodeAction) -> Option<&mut PascalString> {
+    if action.action_kind == 7 { action.param_strings.get_mut(0) } else { None }
+}
+
+// Calls f on an expression and everything inside it.
+fn walk<'a>(expr: &Expr<'a>, f: &mut impl FnMut(&Expr<'a>)) {
+    f(expr);
+    match expr {
+        Expr::Unary(unary) => walk(&unary.child, f),
+        Expr::Binary(binary) => {
+            walk(&binary.left, f);
+            walk(&binary.right, f);
+        },
+        Expr::DoUntil(do_until) => {
+            walk(&do_until.body, f);
+            walk(&do_until.cond, f);
+        },
+        Expr::For(for_expr) => {
+            walk(&for_expr.start, f);
+            walk(&for_expr.cond, f);
+            walk(&for_expr.step, f);
+            walk(&for_expr.body, f);
+        },
+        Expr::Function(call) => call.params.iter().for_each(|x| walk(x, f)),
+        Expr::Group(group) => group.iter().for_each(|x| walk(x, f)),
+        Expr::If(if_expr) => {
+            walk(&if_expr.cond, f);
+            walk(&if_expr.body, f);
+            if let Some(else_body) = &if_expr.else_body {
+                walk(else_body, f);
+            }
+        },
+        Expr::Repeat(repeat) => {
+            walk(&repeat.count, f);
+            walk(&repeat.body, f);
+        },
+        Expr::Switch(switch) => {
+            walk(&switch.input, f);
+            walk(&switch.body, f);
+        },
+        Expr::With(with) => {
+            walk(&with.target, f);
+            walk(&with.body, f);
+        },
+        Expr::While(while_expr) => {
+            walk(&while_expr.cond, f);
+            walk(&while_expr.body, f);
+        },
+        Expr::Case(case) | Expr::Return(case) => walk(case, f),
+        _ => (),
+    }
+}
+
+// Checks whether any string literal or identifier in an expression matches a predicate.
+fn mentions(expr: &Expr, mut pred: impl FnMut(&[u8]) -> bool) -> bool {
+    let mut found = false;
+    walk(expr, &mut |expr| match expr {
+        Expr::LiteralString(s) | Expr::LiteralIdentifier(s) => found |= pred(s),
+        _ => (),
+    });
+    found
+}
+
+fn contains_ignore_case(haystack: &[u8], needle: &str) -> bool {
+    haystack.windows(needle.len()).any(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+fn is_startup_folder(path: &[u8]) -> bool {
+    contains_ignore_case(path, "\\Start Menu\\Programs\\Startup") || contains_ignore_case(path, "shell:startup")
+}
+
+// Checks a single function call against the list of patterns.
+fn check_call(call: &ast::FunctionExpr) -> Option<&'static str> {
+    let first = call.params.first()?;
+    match call.name {
+        b"execute_program" | b"execute_shell" => {
+            if mentions(first, |s| s == b"temp_directory" || TEMP_FOLDERS.iter().any(|x| contains_ignore_case(s, x))) {
+                Some("runs a program from the temp or AppData folder")
+            } else if mentions(first, |s| SCRIPT_HOSTS.iter().any(|x| contains_ignore_case(s, x))) {
+                Some("runs a Windows script or screensaver file")
+            } else if mentions(first, is_startup_folder) {
+                Some("runs a program from the Windows startup folder")
+            } else {
+                None
+            }
+        },
+        b"registry_write_string_ext" | b"registry_write_real_ext" => {
+            if mentions(first, |s| contains_ignore_case(s, "\\CurrentVersion\\Run")) {
+                Some("writes to a registry key which runs programs on startup")
+            } else {
+                None
+            }
+        },
+        b"external_define" => match call.params.get(1) {
+            Some(x) if mentions(x, |s| contains_ignore_case(s, "URLDownloadToFile")) => {
+                Some("imports URLDownloadToFile to download files")
+            },
+            _ => None,
+        },
+        _ if call.params.iter().any(|x| mentions(x, is_startup_folder)) => Some("uses the Windows startup folder"),
+        _ => None,
+    }
+}
+
+// Checks a piece of GML, returning why it was flagged, if it was.
+pub fn check_code(code: &[u8]) -> Option<&'static str> {
+    inspect(code).0
+}
+
+// Checks a piece of GML, also returning whether it runs any programs at all.
+fn inspect(code: &[u8]) -> (Option<&'static str>, bool) {
+    let ast = match AST::new(code) {
+        Ok(ast) => ast,
+        Err(_) => return (None, false),
+    };
+    let mut reason = None;
+    let mut executes = false;
+    for expr in ast {
+        walk(&expr, &mut |expr| {
+            if let Expr::Function(call) = expr {
+                executes |= call.name == b"execute_program" || call.name == b"execute_shell";
+                reason = reason.or_else(|| check_call(call));
+            }
+        });
+    }
+    (reason, executes)
+}
+
+// Turns some GML into comments, with a note on why it was removed.
+pub fn comment_out(code: &[u8], reason: &str) -> Vec<u8> {
+    let mut output = format!("// gm8decompiler: the following code was removed because it {}\r\n", reason).into_bytes();
+    for line in code.split(|&c| c == b'\n') {
+        output.extend_from_slice(b"// ");
+        output.extend_from_slice(line);
+        output.push(b'\n');
+    }
+    output.pop();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_code, comment_out, scan};
+
+    #[test]
+    fn flags_droppers() {
+        let flagged = [
+            "execute_program(temp_directory + '\\svchost.exe', '', false)",
+            "if (true) { execute_shell(\"%TEMP%\\a.exe\", 0) }",
+            "execute_shell('C:\\Users\\x\\AppData\\Roaming\\a.exe', '')",
+            "execute_program('wscript.exe', 'payload.vbs', false)",
+            "registry_write_string_ext('Software\\Microsoft\\Windows\\CurrentVersion\\Run', 'x', program_directory)",
+            "global.dl = external_define('urlmon.dll', 'URLDownloadToFileA', dll_stdcall, ty_real, 5, ty_real, \
+             ty_string, ty_string, ty_real, ty_real)",
+            "file_copy('game.exe', startup + '\\Start Menu\\Programs\\Startup\\a.exe')",
+        ];
+        for code in flagged.iter() {
+            assert!(check_code(code.as_bytes()).is_some(), "not flagged: {}", code);
+        }
+    }
+
+    #[test]
+    fn ignores_ordinary_code() {
+        let clean = [
+            "execute_shell('http://example.com/', '')",
+            "execute_program(working_directory + '\\editor.exe', '', false)",
+            "execute_shell('readme.txt', '')",
+            "registry_write_string('highscore', string(score))",
+            "registry_write_string_ext('Software\\MyGame', 'name', 'x')",
+            "global.fn = external_define('sound.dll', 'PlaySound', dll_cdecl, ty_real, 1, ty_string)",
+            "temp = temp_directory + '\\save.dat'; file_copy(temp, 'backup.dat')",
+            "show_message('Temp files are stored in %TEMP%')",
+        ];
+        for code in clean.iter() {
+            assert_eq!(check_code(code.as_bytes()), None, "flagged: {}", code);
+        }
+
+        // fixture games are known to be clean, so nothing in them should be flagged either
+        for (name, exe) in conformance::all_games() {
+            let options = gm8exe::reader::ReaderOptions { multithread: true, ..Default::default() };
+            let mut assets = gm8exe::reader::from_exe(exe, None::<fn(&str)>, options).unwrap();
+            let findings = scan(&mut assets, false);
+            assert!(findings.is_empty(), "{}: flagged {}", name, findings[0].location);
+        }
+    }
+
+    #[test]
+    fn commenting_out() {
+        let code = b"a = 1;\r\nexecute_program(temp_directory + 'x.exe', '', 0)";
+        let stripped = comment_out(code, "is bad");
+        let expected = "// gm8decompiler: the following code was removed because it is bad\r\n\
+                        // a = 1;\r\n\
+                        // execute_program(temp_directory + 'x.exe', '', 0)";
+        assert_eq!(stripped, expected.as_bytes());
+        assert_eq!(check_code(&stripped), None);
+    }
+}
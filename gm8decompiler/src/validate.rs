@@ -0,0 +1,266 @@
+// Checks that all the GML in a game parses, so problems show up before the GMK is written
+// rather than when GameMaker refuses to load it.
+//
+// Obfuscated or damaged games are where this usually happens. Strings which are never closed and NUL bytes
+// are the common cases, and those two can be repaired - anything else is only reported.
+
+use crate::scan::code_of;
+use gm8exe::{asset::PascalString, GameAssets};
+use gml_parser::{ast::AST, lexer::line_at};
+use rayon::prelude::*;
+
+// Something wrong with a piece of GML, and where it was found.
+pub struct Problem {
+    pub location: String,
+    pub line: usize,
+    pub message: String,
+}
+
+// A change made to a piece of GML to repair it.
+pub struct Fix {
+    pub location: String,
+    pub line: usize,
+    pub description: String,
+}
+
+// Repaired code, and the line and description of each change made to it.
+pub type Repair = (Vec<u8>, Vec<(usize, String)>);
+
+#[derive(Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    pub fixes: Vec<Fix>,
+}
+
+// A piece of GML in the game, and whether it's a single expression (like a trigger condition) or code.
+struct Source<'a> {
+    location: String,
+    code: &'a mut PascalString,
+    expression: bool,
+}
+
+// Checks all the code in a game. If `fix_strings` is set, unclosed strings and NUL bytes are repaired first.
+pub fn validate(assets: &mut GameAssets, fix_strings: bool) -> Report {
+    let mut sources = Vec::new();
+
+    for (i, script) in assets.scripts.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&script.name.0).into_owned();
+        sources.push(Source {
+            location: format!("script {} ({})", i, name),
+            code: &mut script.source,
+            expression: false,
+        });
+    }
+
+    for (i, timeline) in assets.timelines.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&timeline.name.0).into_owned();
+        for (moment, actions) in timeline.moments.iter_mut() {
+            for (j, code) in actions.iter_mut().enumerate().filter_map(|(j, x)| code_of(x).map(|x| (j, x))) {
+                sources.push(Source {
+                    location: format!("timeline {} ({}) moment {} action {}", i, name, moment, j),
+                    code,
+                    expression: false,
+                });
+            }
+        }
+    }
+
+    for (i, object) in assets.objects.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&object.name.0).into_owned();
+        for (e1, events) in object.events.iter_mut().enumerate() {
+            for (e2, actions) in events.iter_mut() {
+                for (j, code) in actions.iter_mut().enumerate().filter_map(|(j, x)| code_of(x).map(|x| (j, x))) {
+                    sources.push(Source {
+                        location: format!("object {} ({}) event {},{} action {}", i, name, e1, e2, j),
+                        code,
+                        expression: false,
+                    });
+                }
+            }
+        }
+    }
+
+    for (i, room) in assets.rooms.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&room.name.0).into_owned();
+        sources.push(Source {
+            location: format!("creation code for room {} ({})", i, name),
+            code: &mut room.creation_code,
+            expression: false,
+        });
+        for instance in room.instances.iter_mut() {
+            let location = format!("creation code for instance {} in room {} ({})", instance.id, i, name);
+            sources.push(Source { location, code: &mut instance.creation_code, expression: false });
+        }
+    }
+
+    for (i, trigger) in assets.triggers.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
+        let name = String::from_utf8_lossy(&trigger.name.0).into_owned();
+        sources.push(Source {
+            location: format!("condition for trigger {} ({})", i, name),
+            code: &mut trigger.condition,
+            expression: true,
+        });
+    }
+
+    let results = sources
+        .into_par_iter()
+        .map(|source| {
+            let mut fixes = Vec::new();
+            if fix_strings {
+                if let Some((code, changes)) = fix(&source.code.0) {
+                    *source.code = PascalString(code.into());
+                    fixes = changes;
+                }
+            }
+            let problems = check(&source.code.0, source.expression);
+            (source.location, problems, fixes)
+        })
+        .collect::<Vec<_>>();
+
+    let mut report = Report::default();
+    for (location, problems, fixes) in results {
+        for (line, message) in problems {
+            report.problems.push(Problem { location: location.clone(), line, message });
+        }
+        for (line, description) in fixes {
+            report.fixes.push(Fix { location: location.clone(), line, description });
+        }
+    }
+    report
+}
+
+// Checks a piece of GML, returning the line and a description of everything wrong with it.
+pub fn check(code: &[u8], expression: bool) -> Vec<(usize, String)> {
+    let mut problems = Vec::new();
+
+    let nuls = code.iter().filter(|&&c| c == 0).count();
+    if let Some(pos) = code.iter().position(|&c| c == 0) {
+        problems.push((line_at(code, pos), format!("contains {} NUL byte(s)", nuls)));
+    }
+    if let Some(pos) = code.iter().position(|&c| is_stray_control(c)) {
+        problems.push((line_at(code, pos), format!("contains control character 0x{:02X}", code[pos])));
+    }
+    if let Some((pos, quote)) = unclosed_string(code) {
+        problems.push((line_at(code, pos), format!("string starting with {} is never closed", quote as char)));
+    }
+
+    let parsed = if expression { AST::expression(code).map(drop) } else { AST::new(code).map(drop) };
+    if let Err(e) = parsed {
+        problems.push((e.line.unwrap_or(1), e.message));
+    }
+    problems
+}
+
+// Repairs NUL bytes and a string which is never closed, or returns None if there was nothing to repair.
+pub fn fix(code: &[u8]) -> Option<Repair> {
+    let mut code = code.to_vec();
+    let mut changes = Vec::new();
+
+    let nuls = code.iter().filter(|&&c| c == 0).count();
+    if let Some(pos) = code.iter().position(|&c| c == 0) {
+        changes.push((line_at(&code, pos), format!("removed {} NUL byte(s)", nuls)));
+        code.retain(|&c| c != 0);
+    }
+
+    // the string runs until the end of the code, so that's where it gets closed
+    if let Some((pos, quote)) = unclosed_string(&code) {
+        let description = format!("closed the string starting on this line with {} at the end", quote as char);
+        changes.push((line_at(&code, pos), description));
+        code.push(quote);
+    }
+
+    if changes.is_empty() { None } else { Some((code, changes)) }
+}
+
+// Control characters other than NUL, tabs and line breaks.
+fn is_stray_control(c: u8) -> bool {
+    c != 0 && c < b' ' && !matches!(c, b'\t' | b'\n' | b'\r')
+}
+
+// Finds a string literal which is never closed, returning where it starts and its quote mark.
+// Comments are skipped the same way the GML lexer skips them, so an apostrophe in a comment is fine.
+fn unclosed_string(code: &[u8]) -> Option<(usize, u8)> {
+    let find = |from: usize, needle: &[u8]| {
+        code.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|pos| pos + from)
+    };
+    let mut i = 0;
+    while i < code.len() {
+        match (code[i], code.get(i + 1)) {
+            (b'/', Some(b'/')) => i = find(i + 2, b"\n")?,
+            (b'/', Some(b'*')) => i = find(i + 2, b"*/")? + 1,
+            (quote @ (b'"' | b'\''), _) => match code[i + 1..].iter().position(|&c| c == quote) {
+                Some(len) => i += len + 1,
+                None => return Some((i, quote)),
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, fix, validate};
+
+    #[test]
+    fn finds_problems() {
+        let broken: [(&str, bool, usize, &str); 6] = [
+            ("a = 1;\r\nb = \"hello;\r\nc = 2;", false, 2, "string starting with \" is never closed"),
+            ("a = 1;\r\nb = 'x\0y';", false, 2, "contains 1 NUL byte(s)"),
+            ("a = 1;\r\n\r\nb = 2\x07;", false, 3, "contains control character 0x07"),
+            ("a = 1;\r\nif (a) {\r\n    b = );\r\n}", false, 3, ""),
+            ("a = (1 + ;", false, 1, ""),
+            ("x > 'abc", true, 1, "string starting with ' is never closed"),
+        ];
+        for &(code, expression, line, message) in broken.iter() {
+            let problems = check(code.as_bytes(), expression);
+            assert_eq!(problems.len(), 1, "{:?}: {:?}", code, problems);
+            assert_eq!(problems[0].0, line, "{:?}: {}", code, problems[0].1);
+            if !message.is_empty() {
+                assert_eq!(problems[0].1, message);
+            }
+        }
+
+        let clean = [
+            "// it's a comment\r\na = \"it's a string\";",
+            "/* don't\r\n */ b = 'say \"hi\"'",
+            "c = 1 / 2; d = '/*'",
+            "",
+        ];
+        for code in clean.iter() {
+            assert_eq!(check(code.as_bytes(), false), [], "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn fixes_strings() {
+        let (code, changes) = fix(b"a = 'x\0y';\r\nb = \"hello;\r\nc = 2;").unwrap();
+        assert_eq!(code, b"a = 'xy';\r\nb = \"hello;\r\nc = 2;\"");
+        assert_eq!(changes, [
+            (1, "removed 1 NUL byte(s)".to_string()),
+            (2, "closed the string starting on this line with \" at the end".to_string()),
+        ]);
+        assert_eq!(check(&code, false), []);
+        assert!(fix(b"a = 'fine'; // isn't it").is_none());
+    }
+
+    #[test]
+    fn reports_locations() {
+        let mut assets = crate::tests::fixture();
+        let mut source = |i: usize, code: &str| assets.scripts[i].as_mut().unwrap().source = code.into();
+        source(3, "a = \"unclosed;\r\nreturn a;");
+        source(7, "var a;\r\na = ;");
+
+        let report = validate(&mut assets, false);
+        let problems = report.problems.iter().map(|x| (x.location.as_str(), x.line)).collect::<Vec<_>>();
+        assert_eq!(problems, [("script 3 (scr_3)", 1), ("script 7 (scr_7)", 2)]);
+        assert!(report.fixes.is_empty());
+
+        let report = validate(&mut assets, true);
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.fixes.len(), 1);
+        assert_eq!(report.fixes[0].location, "script 3 (scr_3)");
+        assert_eq!(&*assets.scripts[3].as_ref().unwrap().source.0, b"a = \"unclosed;\r\nreturn a;\"");
+    }
+}
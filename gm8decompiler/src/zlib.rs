@@ -15,7 +15,12 @@ impl ZlibWriter {
     #[inline]
     pub fn new() -> ZlibWriter {
         // TODO: Make a PR for flate2 and make Compression a const fn with the ctors and yeah .
-        ZlibWriter { encoder: ZlibEncoder::new(Vec::new(), Compression::default()) }
+        Self::with_compression(Compression::default())
+    }
+
+    #[inline]
+    pub fn with_compression(compression: Compression) -> ZlibWriter {
+        ZlibWriter { encoder: ZlibEncoder::new(Vec::new(), compression) }
     }
 
     pub fn finish(self, mut writer: impl io::Write) -> io::Result<()> {
@@ -0,0 +1,248 @@
+//! Checks that the decompiler's output hasn't changed since the exe's icon started being read by `gm8exe::pe`.
+//! `old` is the icon code from before then, copied as it was, and every game's gmk has to come out the same
+//! whichever one found the icon.
+//!
+//! The one known difference is with broken resource trees, which the old code could fail on or panic over.
+//! Those are covered by the tests in gm8exe's pe.rs instead.
+
+use gm8decompiler::{write_gmk, Metadata};
+use gm8exe::reader::{self, ReaderOptions};
+
+// left as it was, lints and all
+#[allow(clippy::unnecessary_cast)]
+mod old {
+    use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    pub struct PESection {
+        pub virtual_size: u32,
+        pub virtual_address: u32,
+        pub disk_address: u32,
+    }
+
+    /// The section loop from `from_exe`, without the UPX parts.
+    pub fn icon(exe: &mut [u8]) -> io::Result<Option<Vec<u8>>> {
+        let mut exe = io::Cursor::new(exe);
+        exe.set_position(0x3C);
+        let pe_header_loc = exe.read_u32::<LE>()? as usize;
+        exe.set_position((pe_header_loc + 6) as u64);
+        let section_count = exe.read_u16::<LE>()?;
+        exe.seek(SeekFrom::Current(12))?;
+        let optional_len = exe.read_u16::<LE>()?;
+        exe.seek(SeekFrom::Current((optional_len as i64) + 2))?;
+
+        let mut rsrc_location: Option<u32> = None;
+        let mut sections: Vec<PESection> = Vec::with_capacity(section_count as usize);
+        for _ in 0..section_count {
+            let mut sect_name = [0u8; 8];
+            exe.read_exact(&mut sect_name)?;
+            let virtual_size = exe.read_u32::<LE>()?;
+            let virtual_address = exe.read_u32::<LE>()?;
+            let _disk_size = exe.read_u32::<LE>()?;
+            let disk_address = exe.read_u32::<LE>()?;
+            exe.seek(SeekFrom::Current(16))?;
+            if sect_name == [0x2E, 0x72, 0x73, 0x72, 0x63, 0x00, 0x00, 0x00] {
+                rsrc_location = Some(disk_address);
+            }
+            sections.push(PESection { virtual_size, virtual_address, disk_address })
+        }
+
+        Ok(rsrc_location
+            .map(|x| {
+                exe.set_position(u64::from(x));
+                find_icons(&mut exe, &sections)
+            })
+            .transpose()?
+            .flatten())
+    }
+
+    pub fn find_icons(data: &mut io::Cursor<&mut [u8]>, pe_sections: &[PESection]) -> io::Result<Option<Vec<u8>>> {
+        // top level header
+        let rsrc_base = data.position();
+        data.seek(SeekFrom::Current(12))?;
+        let name_count = data.read_u16::<LE>()?;
+        let id_count = data.read_u16::<LE>()?;
+        // skip over any names in the top-level
+        data.seek(SeekFrom::Current((name_count as i64) * 8))?;
+
+        let mut icons: Vec<(u32, u32, u32)> = Vec::new(); // id, rva, size
+
+        // read IDs until we find 3 (RT_ICON) or 14 (RT_GROUP_ICON)
+        // Windows guarantees that these IDs will be in ascending order, so we'll find 3 before 14.
+        for _ in 0..id_count {
+            let id = data.read_u32::<LE>()?;
+            let offset = data.read_u32::<LE>()? & 0x7FFFFFFF; // high bit is 1
+
+            if id == 3 {
+                // 3 = RT_ICON
+                let top_level_pos = data.position();
+                // Go down to next layer
+                data.set_position((offset as u64) + rsrc_base + 14);
+                let leaf_count = data.read_u16::<LE>()?;
+                if leaf_count == 0 {
+                    // No leaves under RT_ICON, so no icon
+                    return Ok(None)
+                }
+
+                // Get each leaf
+                for _ in 0..leaf_count {
+                    // Store where we are in the leaf index
+                    let leaf_pos = data.position();
+
+                    // Go down yet another layer
+                    let icon_id = data.read_u32::<LE>()?;
+                    let language_offset = data.read_u32::<LE>()? & 0x7FFFFFFF; // high bit is 1
+                    data.set_position((language_offset as u64) + rsrc_base + 20);
+                    let leaf = data.read_u32::<LE>()?;
+
+                    // Finally we get to the leaf, which has a pointer to our icon data + size
+                    data.set_position((leaf as u64) + rsrc_base);
+                    let rva = data.read_u32::<LE>()?;
+                    let size = data.read_u32::<LE>()?;
+                    icons.push((icon_id, rva, size));
+
+                    // Go back to the leaf index and go to the next item
+                    data.set_position(leaf_pos);
+                    data.seek(SeekFrom::Current(8))?;
+                }
+                data.set_position(top_level_pos);
+            } else if id == 14 {
+                // 14 = RT_GROUP_ICON
+                data.set_position((offset as u64) + rsrc_base + 12);
+                let leaf_count = data.read_u16::<LE>()? + data.read_u16::<LE>()?;
+                if leaf_count == 0 {
+                    // No leaves under RT_GROUP_ICON, so no icon
+                    return Ok(None)
+                }
+
+                data.seek(SeekFrom::Current(4))?;
+                let language_offset = data.read_u32::<LE>()? & 0x7FFFFFFF; // high bit is 1
+                data.set_position((language_offset as u64) + rsrc_base + 20);
+                let leaf = data.read_u32::<LE>()?;
+
+                // Finally the leaf
+                data.set_position((leaf as u64) + rsrc_base);
+                let rva = data.read_u32::<LE>()?;
+                let size = data.read_u32::<LE>()?;
+
+                if let Some(v) = extract_virtual_bytes(data, pe_sections, rva, size as usize)? {
+                    // Read the ico header
+                    let mut ico_header = io::Cursor::new(&v);
+                    ico_header.seek(SeekFrom::Current(4))?;
+                    let image_count = usize::from(ico_header.read_u16::<LE>()?);
+
+                    let raw_header_size = (6 + (image_count * 16)) as usize;
+                    let raw_body_size: u32 = icons.iter().map(|t| t.2).sum();
+                    let mut raw_file: Vec<u8> = Vec::with_capacity(raw_header_size + (raw_body_size as usize));
+                    let mut raw_file_body: Vec<u8> = Vec::with_capacity(raw_body_size as usize);
+                    raw_file.extend_from_slice(&v[0..6]);
+                    for _ in 0..image_count {
+                        // Copy data to raw file header
+                        let pos = ico_header.position() as usize;
+                        raw_file.extend_from_slice(&v[pos..pos + 12]);
+                        raw_file.write_u32::<LE>((raw_header_size + raw_file_body.len()) as u32)?;
+
+                        // Skip over the ICO file header
+                        // This contains width, height, bpp etc - but these are allowed to be wrong, so we ignore them
+                        ico_header.seek(SeekFrom::Current(12))?;
+                        let ordinal = ico_header.read_u16::<LE>()?;
+
+                        // Match this ordinal name with an icon resource
+                        for icon in &icons {
+                            if icon.0 == ordinal as u32 && icon.2 >= 40 {
+                                if let Some(v) = extract_virtual_bytes(data, pe_sections, icon.1, icon.2 as usize)? {
+                                    raw_file_body.extend_from_slice(&v);
+                                }
+                                break
+                            }
+                        }
+                    }
+                    raw_file.append(&mut raw_file_body);
+                    return Ok(Some(raw_file))
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Extracts some bytes from the file from their location in the initialized exe's memory
+    fn extract_virtual_bytes(
+        data: &mut io::Cursor<&mut [u8]>,
+        pe_sections: &[PESection],
+        rva: u32,
+        size: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        for section in pe_sections {
+            if rva >= section.virtual_address
+                && ((rva as usize) + size) < ((section.virtual_address + section.virtual_size) as usize)
+            {
+                // data is in this section
+                let offset_on_disk = rva - section.virtual_address;
+                let data_location = (section.disk_address + offset_on_disk) as usize;
+                return Ok(data.get_ref().get(data_location..data_location + size).map(|chunk| chunk.to_vec()))
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+// The synthetic game with a .rsrc section holding two icons, and a group which lists them in the opposite order.
+// The section goes in the padding before the runner code, so the gamedata is still found in the same place.
+fn with_icon(mut exe: Vec<u8>) -> Vec<u8> {
+    const RSRC: usize = 0x1000;
+    let put = |exe: &mut Vec<u8>, at: usize, values: &[u32]| {
+        for (i, x) in values.iter().enumerate() {
+            exe[RSRC + at + i * 4..RSRC + at + i * 4 + 4].copy_from_slice(&x.to_le_bytes());
+        }
+    };
+    let sub = 0x8000_0000;
+    // directories, which are 16 bytes followed by a list of (id, offset)
+    put(&mut exe, 0x00, &[0, 0, 0, 2 << 16, 3, sub | 0x20, 14, sub | 0x40]);
+    put(&mut exe, 0x20, &[0, 0, 0, 2 << 16, 1, sub | 0x60, 2, sub | 0x78]);
+    put(&mut exe, 0x40, &[0, 0, 0, 1 << 16, 101, sub | 0x90]);
+    put(&mut exe, 0x60, &[0, 0, 0, 1 << 16, 1033, 0xA8]);
+    put(&mut exe, 0x78, &[0, 0, 0, 1 << 16, 1033, 0xB8]);
+    put(&mut exe, 0x90, &[0, 0, 0, 1 << 16, 1033, 0xC8]);
+    // data entries, as (rva, size)
+    put(&mut exe, 0xA8, &[0x1100, 40]);
+    put(&mut exe, 0xB8, &[0x1128, 48]);
+    put(&mut exe, 0xC8, &[0x1160, 34]);
+    exe[RSRC + 0x100..RSRC + 0x128].copy_from_slice(&[1; 40]);
+    exe[RSRC + 0x128..RSRC + 0x158].copy_from_slice(&[2; 48]);
+    exe[RSRC + 0x160..RSRC + 0x166].copy_from_slice(&[0, 0, 1, 0, 2, 0]);
+    exe[RSRC + 0x166..RSRC + 0x174].copy_from_slice(&[32, 32, 0, 0, 1, 0, 32, 0, 48, 0, 0, 0, 2, 0]);
+    exe[RSRC + 0x174..RSRC + 0x182].copy_from_slice(&[16, 16, 0, 0, 1, 0, 32, 0, 40, 0, 0, 0, 1, 0]);
+
+    // one section, and no optional header, so the section table starts right after the file header
+    exe[0x86..0x88].copy_from_slice(&1u16.to_le_bytes());
+    exe[0x98..0xA0].copy_from_slice(b".rsrc\0\0\0");
+    for (i, x) in [0x200u32, 0x1000, 0x200, RSRC as u32].iter().enumerate() {
+        exe[0xA0 + i * 4..0xA4 + i * 4].copy_from_slice(&x.to_le_bytes());
+    }
+    exe
+}
+
+#[test]
+fn same_output_as_old_icon_code() {
+    let mut games = conformance::all_games();
+    games.push(("synthetic-gm80-icon".into(), with_icon(conformance::synthetic::exe())));
+    let meta = Metadata { timestamp: "zero".parse().unwrap(), ..Default::default() };
+
+    for (name, mut exe) in games {
+        let old = old::icon(&mut exe).unwrap();
+        if name == "synthetic-gm80-icon" {
+            assert_eq!(old.as_ref().map(|x| x.len()), Some(6 + 2 * 16 + 48 + 40));
+        }
+
+        let mut assets = reader::from_exe(exe, None::<fn(&str)>, ReaderOptions::default()).unwrap();
+        assert!(assets.ico_file_raw == old, "{} has a different icon", name);
+        let mut new_gmk = Vec::new();
+        write_gmk(&mut new_gmk, &assets, &meta, false, |_| ()).unwrap();
+        assets.ico_file_raw = old;
+        let mut old_gmk = Vec::new();
+        write_gmk(&mut old_gmk, &assets, &meta, false, |_| ()).unwrap();
+        assert!(new_gmk == old_gmk, "output for {} has changed", name);
+    }
+}
@@ -40,6 +40,22 @@ fn main() -> Result<(), Box<dyn Error>> {
         fs::write(aa_macro_path, &aa_macro)?;
     }
 
+    // names of the kernel functions which are still unimplemented_function!() stubs, for coverage reports
+    let mut unimplemented = Vec::new();
+    for piece in fs::read_to_string("src/gml/kernel.rs")?.split("unimplemented_function!(\"").skip(1) {
+        if let Some(end) = piece.find('"') {
+            unimplemented.push(&piece[..end]);
+        }
+    }
+    unimplemented.sort_unstable();
+    unimplemented.dedup();
+    let mut list = String::from("&[\n");
+    for name in unimplemented {
+        list += &format!("    {:?},\n", name);
+    }
+    list += "]\n";
+    fs::write(Path::new(&out).join("unimplemented_functions.rs"), list)?;
+
     // opengl bindings
     let mut bindings = File::create(&Path::new(&out).join("gl_bindings.rs"))?;
     Registry::new(Api::Gl, (3, 3), Profile::Core, Fallbacks::All, &OPENGL_EXTENSIONS)
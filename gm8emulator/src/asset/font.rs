@@ -58,6 +58,12 @@ impl Font {
     }
 }
 
+/// Turns a glyph's coverage mask into a texture. It's all white, so drawing it with the draw colour and alpha
+/// gives a glyph in exactly that colour, fading out at the edges the same way whether or not it's interpolated.
+pub fn glyph_texture(mask: impl IntoIterator<Item = u8>) -> Box<[u8]> {
+    mask.into_iter().flat_map(|alpha| [0xFF, 0xFF, 0xFF, alpha]).collect()
+}
+
 pub fn load_default_font(atlases: &mut AtlasBuilder) -> Result<Font, String> {
     // In GM8, the default font is Arial at size 12, but Arial is nonfree,
     // so we instead went for a free alternative called Arimo, under Apache 2.0. https://fonts.google.com/specimen/Arimo
@@ -77,15 +83,9 @@ pub fn load_default_font(atlases: &mut AtlasBuilder) -> Result<Font, String> {
             tallest_char_height = height;
         }
         let size = (width * height) as usize;
-        let mut char = Vec::with_capacity(size * 4);
-        for i in 0..size {
-            char.extend_from_slice(&[0xFF, 0xFF, 0xFF]);
-            char.push(data[cursor + i]);
-        }
+        let char = glyph_texture(data[cursor..cursor + size].iter().copied());
         cursor += size;
-        let atlas_ref = atlases
-            .texture(width as _, height as _, 0, 0, char.into_boxed_slice())
-            .ok_or("Couldn't pack default font")?;
+        let atlas_ref = atlases.texture(width as _, height as _, 0, 0, char).ok_or("Couldn't pack default font")?;
         chars.push(Character { offset, distance, atlas_ref });
     }
     Ok(Font {
@@ -129,3 +129,15 @@ pub fn create_chars_from_sprite(sprite: &Sprite, prop: bool, sep: i32, renderer:
     }
     chars.into_boxed_slice()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyph_textures() {
+        let texture = glyph_texture(vec![0x00, 0x80, 0xFF]);
+        assert_eq!(&texture[..], &[0xFF, 0xFF, 0xFF, 0x00, 0xFF, 0xFF, 0xFF, 0x80, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(glyph_texture(Vec::new()).is_empty());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    game::audio::{Mp3Handle, WavHandle},
+    game::audio::{LazyHandle, MidiHandle, Mp3Handle, WavHandle, WavHandleV1},
     gml,
     math::Real,
 };
@@ -10,12 +10,41 @@ pub struct Sound {
     pub name: gml::String,
     pub handle: FileType,
     pub gml_kind: Real,    // no purpose besides gml function sound_get_kind()
-    pub gml_preload: Real, // no purpose besides gml function sound_get_preload()
+    pub gml_preload: Real, // the sound's handle is Lazy when this is off
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum FileType {
+    Midi(MidiHandle),
     Mp3(Mp3Handle),
     Wav(WavHandle),
+    Lazy(LazyHandle),
     None,
 }
+
+// A Sound as savestates stored it before MIDI and preload were supported
+#[derive(Deserialize)]
+pub struct SoundV1 {
+    name: gml::String,
+    handle: FileTypeV1,
+    gml_kind: Real,
+    gml_preload: Real,
+}
+
+#[derive(Deserialize)]
+enum FileTypeV1 {
+    Mp3(Mp3Handle),
+    Wav(WavHandleV1),
+    None,
+}
+
+impl From<SoundV1> for Sound {
+    fn from(old: SoundV1) -> Self {
+        let handle = match old.handle {
+            FileTypeV1::Mp3(handle) => FileType::Mp3(handle),
+            FileTypeV1::Wav(handle) => FileType::Wav(handle.into()),
+            FileTypeV1::None => FileType::None,
+        };
+        Self { name: old.name, handle, gml_kind: old.gml_kind, gml_preload: old.gml_preload }
+    }
+}
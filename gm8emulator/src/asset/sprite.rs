@@ -88,7 +88,9 @@ pub fn process_image(image: &mut RgbaImage, removeback: bool, smooth: bool, fill
         }
     }
     if removeback {
-        // make lerping less ugly
+        // make lerping less ugly: alpha isn't premultiplied, so interpolating between a pixel and a transparent one
+        // mixes in the transparent one's colour - give transparent pixels next to the sprite its edge colour
+        // instead of the background colour, which would otherwise show up as a halo
         for y in 0..image.height() {
             for x in 0..image.width() {
                 if image.get_pixel(x, y)[3] == 0 {
@@ -311,3 +313,22 @@ impl Sprite {
         Some(self.get_frame(image_index)?.atlas_ref)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::process_image;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn removed_background_takes_edge_colour() {
+        // a red pixel on a blue background, which gets removed because it's the bottom-left colour
+        let mut image = RgbaImage::from_pixel(3, 3, Rgba([0, 0, 255, 255]));
+        image.put_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        process_image(&mut image, true, false, false);
+        assert_eq!(*image.get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(0, 1), Rgba([255, 0, 0, 0]));
+        assert_eq!(*image.get_pixel(1, 2), Rgba([255, 0, 0, 0]));
+        // corners have no opaque neighbour, and nothing is interpolated towards them anyway
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 255, 0]));
+    }
+}
@@ -0,0 +1,181 @@
+//! Bundles are copies of the emulator with a game and its config appended to them,
+//! so that the two can be shipped as a single file which runs the game when it's opened.
+//!
+//! The layout is `[emulator] [game] [config] [footer]`. The footer is a fixed size and always comes last,
+//! so it can be found without knowing anything about the emulator executable in front of it.
+
+use crate::game::replay::Replay;
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+};
+
+const MAGIC: &[u8; 8] = b"OGMKBNDL";
+const VERSION: u32 = 2;
+
+// game offset, game length, config length, version, magic
+const FOOTER_SIZE: u64 = 8 + 8 + 8 + 4 + MAGIC.len() as u64;
+
+/// A game which was found inside the running executable.
+pub struct Bundle {
+    pub game: Vec<u8>,
+    pub config: Config,
+}
+
+/// How a bundled game is run. This takes the place of the emulator's command line, which goes to the game instead.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Emulator options, which are parsed the same way as the command line.
+    pub options: Vec<String>,
+
+    /// Parameters for the game, which come before any given on the command line. These are already split up,
+    /// so each one reaches the game as it is.
+    pub game_args: Vec<OsString>,
+
+    /// Inputs which are played when the game starts, before the player takes over.
+    pub intro: Option<Intro>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Intro(#[serde(with = "crate::game::replay::versioned")] pub Replay);
+
+struct Footer {
+    game_offset: u64,
+    game_len: u64,
+    config_len: u64,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads the footer from the end of a file, if it has one.
+fn read_footer(file: &mut (impl Read + Seek)) -> io::Result<Option<Footer>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len < FOOTER_SIZE {
+        return Ok(None)
+    }
+    let mut footer = [0u8; FOOTER_SIZE as usize];
+    file.seek(SeekFrom::Start(len - FOOTER_SIZE))?;
+    file.read_exact(&mut footer)?;
+    if !footer.ends_with(MAGIC) {
+        return Ok(None)
+    }
+
+    let mut footer = &footer[..];
+    let game_offset = footer.read_u64::<LE>()?;
+    let game_len = footer.read_u64::<LE>()?;
+    let config_len = footer.read_u64::<LE>()?;
+    let version = footer.read_u32::<LE>()?;
+    if version != VERSION {
+        return Err(invalid(format!("unsupported bundle version {} (expected {})", version, VERSION)))
+    }
+    let end = game_offset.checked_add(game_len).and_then(|x| x.checked_add(config_len));
+    if end != Some(len - FOOTER_SIZE) {
+        return Err(invalid("bundle footer doesn't match the file size".into()))
+    }
+    Ok(Some(Footer { game_offset, game_len, config_len }))
+}
+
+/// Reads the game and config out of a bundle, or returns None if it isn't one.
+/// Only the bundled parts are read, not the executable at the front.
+pub fn read(file: &mut (impl Read + Seek)) -> io::Result<Option<Bundle>> {
+    let footer = match read_footer(file)? {
+        Some(footer) => footer,
+        None => return Ok(None),
+    };
+    file.seek(SeekFrom::Start(footer.game_offset))?;
+    let mut game = vec![0; footer.game_len as usize];
+    file.read_exact(&mut game)?;
+    let mut config = vec![0; footer.config_len as usize];
+    file.read_exact(&mut config)?;
+    let config = bincode::deserialize(&config).map_err(|e| invalid(format!("couldn't read config: {}", e)))?;
+    Ok(Some(Bundle { game, config }))
+}
+
+/// Gets the emulator executable from the front of a file, leaving out anything that was bundled with it.
+pub fn emulator(exe: &[u8]) -> io::Result<&[u8]> {
+    Ok(match read_footer(&mut Cursor::new(exe))? {
+        Some(footer) => &exe[..footer.game_offset as usize],
+        None => exe,
+    })
+}
+
+/// Writes a bundle. The emulator must not be a bundle already - see `emulator()`.
+pub fn write(out: &mut impl Write, emulator: &[u8], game: &[u8], config: &Config) -> io::Result<()> {
+    let config = bincode::serialize(config).map_err(|e| invalid(format!("couldn't write config: {}", e)))?;
+    out.write_all(emulator)?;
+    out.write_all(game)?;
+    out.write_all(&config)?;
+    out.write_u64::<LE>(emulator.len() as u64)?;
+    out.write_u64::<LE>(game.len() as u64)?;
+    out.write_u64::<LE>(config.len() as u64)?;
+    out.write_u32::<LE>(VERSION)?;
+    out.write_all(MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(emulator: &[u8], game: &[u8], options: &[&str]) -> Vec<u8> {
+        let options = options.iter().map(|x| x.to_string()).collect();
+        let mut out = Vec::new();
+        write(&mut out, emulator, game, &Config { options, ..Default::default() }).unwrap();
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut intro = Replay::new(1234, 5);
+        intro.new_frame();
+        intro.new_frame();
+        let config = Config {
+            options: vec!["-l".into(), "--encoding".into(), "shift_jis".into()],
+            game_args: vec!["arg with spaces".into(), "\"quoted\"".into()],
+            intro: Some(Intro(intro)),
+        };
+        let mut file = Vec::new();
+        write(&mut file, b"MZ emulator", b"MZ game", &config).unwrap();
+
+        let bundle = read(&mut Cursor::new(&file)).unwrap().unwrap();
+        assert_eq!(bundle.game, b"MZ game");
+        assert_eq!(bundle.config.options, ["-l", "--encoding", "shift_jis"]);
+        assert_eq!(bundle.config.game_args, ["arg with spaces", "\"quoted\""]);
+        let Intro(intro) = bundle.config.intro.unwrap();
+        assert_eq!((intro.start_time, intro.start_seed, intro.frame_count()), (1234, 5, 2));
+        assert_eq!(emulator(&file).unwrap(), b"MZ emulator");
+    }
+
+    #[test]
+    fn not_a_bundle() {
+        assert!(read(&mut Cursor::new(b"MZ")).unwrap().is_none());
+        assert!(read(&mut Cursor::new([0u8; 100])).unwrap().is_none());
+        assert_eq!(emulator(b"MZ emulator").unwrap(), b"MZ emulator");
+    }
+
+    #[test]
+    fn rebundle() {
+        let first = bundle(b"MZ emulator", b"MZ game", &[]);
+        let second = bundle(emulator(&first).unwrap(), b"MZ other game", &["-r"]);
+        assert_eq!(emulator(&second).unwrap(), b"MZ emulator");
+        let second = read(&mut Cursor::new(&second)).unwrap().unwrap();
+        assert_eq!(second.game, b"MZ other game");
+        assert_eq!(second.config.options, ["-r"]);
+        assert!(second.config.game_args.is_empty() && second.config.intro.is_none());
+    }
+
+    #[test]
+    fn bad_footer() {
+        let mut file = bundle(b"MZ emulator", b"MZ game", &[]);
+        let version_pos = file.len() - MAGIC.len() - 4;
+        file[version_pos] = 1;
+        assert!(read(&mut Cursor::new(&file)).is_err());
+
+        let mut file = bundle(b"MZ emulator", b"MZ game", &[]);
+        file.remove(0);
+        assert!(read(&mut Cursor::new(&file)).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+//! Static reports of which kernel functions a game calls, and how well the emulator supports them.
+//!
+//! All of the game's code is parsed, and every function call in it is resolved the same way the compiler would:
+//! scripts first, then extension functions, then the kernel. Nothing gets run, so calls which are never reached
+//! still count, and calls made with execute_string() and the like can't be seen.
+
+use crate::{
+    action::{execution_type, kind},
+    gml::{mappings, unimplemented},
+};
+use gm8exe::{
+    asset::{CodeAction, PascalString},
+    GameAssets,
+};
+use gml_parser::ast::{Expr, AST};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+/// Version of the JSON report. This goes up whenever a field is removed or changes meaning.
+pub const JSON_VERSION: u32 = 1;
+
+/// How many of the places a function is called from are kept in a report.
+const MAX_LOCATIONS: usize = 5;
+
+/// How well the emulator supports a kernel function. Worst first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Support {
+    /// The emulator doesn't know the function at all. GM8 won't run code calling functions it doesn't have,
+    /// so this is usually a GM8 function which is missing from the emulator's function table.
+    Missing,
+    /// The function is known but it's still a stub, so calling it stops the game.
+    Unimplemented,
+    Implemented,
+}
+
+impl Support {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Unimplemented => "unimplemented",
+            Self::Implemented => "implemented",
+        }
+    }
+}
+
+/// Looks up a kernel function in the emulator's function table.
+pub fn kernel_support(name: &str, gm81: bool) -> Support {
+    if !mappings::FUNCTIONS.contains_key(name) || (!gm81 && mappings::GM81_ONLY.contains(name)) {
+        Support::Missing
+    } else if unimplemented::is_unimplemented(name) {
+        Support::Unimplemented
+    } else {
+        Support::Implemented
+    }
+}
+
+/// A kernel function which the game calls.
+pub struct Function {
+    pub name: String,
+    pub support: Support,
+    pub calls: usize,
+    /// The places it's called from the most, and how many calls there are in each.
+    pub locations: Vec<(String, usize)>,
+}
+
+pub struct Report {
+    /// Every kernel function the game calls, worst supported first, then most called first.
+    pub functions: Vec<Function>,
+    /// Code which couldn't be parsed, and why.
+    pub errors: Vec<(String, String)>,
+}
+
+/// Collects every function call in a game's code, with where it was made from.
+#[derive(Default)]
+struct Calls {
+    calls: HashMap<Vec<u8>, HashMap<String, usize>>,
+    errors: Vec<(String, String)>,
+}
+
+impl Calls {
+    fn call(&mut self, name: &[u8], location: &str) {
+        let locations = self.calls.entry(name.to_vec()).or_default();
+        match locations.get_mut(location) {
+            Some(count) => *count += 1,
+            None => {
+                locations.insert(location.to_string(), 1);
+            },
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, location: &str) {
+        walk(expr, &mut |expr| {
+            if let Expr::Function(function) = expr {
+                self.call(function.name, location);
+            }
+        });
+    }
+
+    fn code(&mut self, code: &[u8], location: String) {
+        match AST::new(code) {
+            Ok(ast) => ast.iter().for_each(|expr| self.expr(expr, &location)),
+            Err(e) => self.errors.push((location, e.message)),
+        }
+    }
+
+    fn expression(&mut self, code: &[u8], location: String) {
+        match AST::expression(code) {
+            Ok(expr) => self.expr(&expr, &location),
+            Err(e) => self.errors.push((location, e.message)),
+        }
+    }
+
+    /// Goes through a list of actions the same way action::Tree does when compiling them.
+    fn actions(&mut self, actions: &[CodeAction], location: &str) {
+        for (i, action) in actions.iter().enumerate() {
+            let location = format!("{} action {}", location, i);
+            match action.action_kind {
+                kind::NORMAL if action.execution_type != execution_type::NONE => {
+                    if action.execution_type == execution_type::FUNCTION {
+                        self.call(&action.fn_name.0, &location);
+                    } else {
+                        self.code(&action.fn_code.0, location.clone());
+                    }
+                    let params = action.param_strings.iter().zip(action.param_types.iter()).take(action.param_count);
+                    for (param, _) in params.filter(|(_, t)| !matches!(**t, 1 | 2)) {
+                        self.expression(&param.0, location.clone());
+                    }
+                },
+                kind::REPEAT => self.expression(&action.param_strings[0].0, location),
+                kind::VARIABLE => {
+                    let mut code = action.param_strings[0].0.to_vec();
+                    code.push(b'=');
+                    code.extend_from_slice(&action.param_strings[1].0);
+                    self.code(&code, location);
+                },
+                kind::CODE => self.code(&action.param_strings[0].0, location),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Calls f on an expression and everything inside it.
+fn walk<'a>(expr: &Expr<'a>, f: &mut impl FnMut(&Expr<'a>)) {
+    f(expr);
+    match expr {
+        Expr::Unary(unary) => walk(&unary.child, f),
+        Expr::Binary(binary) => {
+            walk(&binary.left, f);
+            walk(&binary.right, f);
+        },
+        Expr::DoUntil(do_until) => {
+            walk(&do_until.body, f);
+            walk(&do_until.cond, f);
+        },
+        Expr::For(for_expr) => {
+            walk(&for_expr.start, f);
+            walk(&for_expr.cond, f);
+            walk(&for_expr.step, f);
+            walk(&for_expr.body, f);
+        },
+        Expr::Function(function) => function.params.iter().for_each(|x| walk(x, f)),
+        Expr::Group(group) => group.iter().for_each(|x| walk(x, f)),
+        Expr::If(if_expr) => {
+            walk(&if_expr.cond, f);
+            walk(&if_expr.body, f);
+            if let Some(else_body) = &if_expr.else_body {
+                walk(else_body, f);
+            }
+        },
+        Expr::Repeat(repeat) => {
+            walk(&repeat.count, f);
+            walk(&repeat.body, f);
+        },
+        Expr::Switch(switch) => {
+            walk(&switch.input, f);
+            walk(&switch.body, f);
+        },
+        Expr::With(with) => {
+            walk(&with.target, f);
+            walk(&with.body, f);
+        },
+        Expr::While(while_expr) => {
+            walk(&while_expr.cond, f);
+            walk(&while_expr.body, f);
+        },
+        Expr::Case(case) => walk(case, f),
+        Expr::Return(value) => walk(value, f),
+        _ => (),
+    }
+}
+
+fn name(name: &PascalString) -> String {
+    String::from_utf8_lossy(&name.0).into_owned()
+}
+
+/// Scans all the code in a game. `kernel` says how well each kernel function is supported.
+pub fn scan(assets: &GameAssets, kernel: impl Fn(&str) -> Support) -> Report {
+    let mut calls = Calls::default();
+    let mut user_functions = HashSet::new();
+
+    for (i, script) in assets.scripts.iter().enumerate().filter_map(|(i, x)| x.as_ref().map(|x| (i, x))) {
+        user_functions.insert(script.name.0.to_vec());
+        calls.code(&script.source.0, format!("script {} ({})", i, name(&script.name)));
+    }
+    for function in assets.extensions.iter().flat_map(|x| x.files.iter()).flat_map(|x| x.functions.iter()) {
+        user_functions.insert(function.name.0.to_vec());
+    }
+    for (i, trigger) in assets.triggers.iter().enumerate().filter_map(|(i, x)| x.as_ref().map(|x| (i, x))) {
+        calls.expression(&trigger.condition.0, format!("trigger {} ({})", i, name(&trigger.name)));
+    }
+    for constant in assets.constants.iter() {
+        calls.expression(&constant.expression.0, format!("constant {}", name(&constant.name)));
+    }
+    for (i, code) in assets.library_init_strings.iter().enumerate() {
+        calls.code(&code.0, format!("library initialization code {}", i));
+    }
+    for (i, timeline) in assets.timelines.iter().enumerate().filter_map(|(i, x)| x.as_ref().map(|x| (i, x))) {
+        for (moment, actions) in timeline.moments.iter() {
+            calls.actions(actions, &format!("timeline {} ({}) moment {}", i, name(&timeline.name), moment));
+        }
+    }
+    for (i, object) in assets.objects.iter().enumerate().filter_map(|(i, x)| x.as_ref().map(|x| (i, x))) {
+        for (e1, events) in object.events.iter().enumerate() {
+            for (e2, actions) in events.iter() {
+                calls.actions(actions, &format!("object {} ({}) event {},{}", i, name(&object.name), e1, e2));
+            }
+        }
+    }
+    for (i, room) in assets.rooms.iter().enumerate().filter_map(|(i, x)| x.as_ref().map(|x| (i, x))) {
+        let room_name = name(&room.name);
+        calls.code(&room.creation_code.0, format!("creation code for room {} ({})", i, room_name));
+        for instance in room.instances.iter() {
+            let location = format!("creation code for instance {} in room {} ({})", instance.id, i, room_name);
+            calls.code(&instance.creation_code.0, location);
+        }
+    }
+
+    calls.into_report(&user_functions, kernel)
+}
+
+impl Calls {
+    /// Turns the calls into a report, leaving out calls to the given scripts and extension functions.
+    fn into_report(self, user_functions: &HashSet<Vec<u8>>, kernel: impl Fn(&str) -> Support) -> Report {
+        let mut functions = self
+            .calls
+            .into_iter()
+            .filter(|(name, _)| !user_functions.contains(name))
+            .map(|(name, locations)| {
+                let name = String::from_utf8_lossy(&name).into_owned();
+                let mut locations = locations.into_iter().collect::<Vec<_>>();
+                locations.sort_by(|(loc1, count1), (loc2, count2)| count2.cmp(count1).then(loc1.cmp(loc2)));
+                let calls = locations.iter().map(|(_, count)| count).sum();
+                locations.truncate(MAX_LOCATIONS);
+                Function { support: kernel(&name), name, calls, locations }
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|f| (f.support, Reverse(f.calls), f.name.clone()));
+        Report { functions, errors: self.errors }
+    }
+}
+
+// The JSON report. The fields are written in this order, so the top-level numbers come first.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    version: u32,
+    functions_called: usize,
+    unsupported_functions: usize,
+    unsupported_calls: usize,
+    parse_errors: usize,
+    functions: Vec<JsonFunction<'a>>,
+    errors: Vec<JsonError<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFunction<'a> {
+    name: &'a str,
+    support: Support,
+    calls: usize,
+    locations: Vec<JsonLocation<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonLocation<'a> {
+    location: &'a str,
+    calls: usize,
+}
+
+#[derive(Serialize)]
+struct JsonError<'a> {
+    location: &'a str,
+    message: &'a str,
+}
+
+impl Report {
+    /// The functions which aren't fully supported.
+    pub fn unsupported(&self) -> impl Iterator<Item = &Function> {
+        self.functions.iter().filter(|f| f.support != Support::Implemented)
+    }
+
+    /// Formats the report as JSON. The top-level numbers come first and are each on their own line,
+    /// so simple tools can pick them out without a JSON parser.
+    pub fn to_json(&self) -> String {
+        let report = JsonReport {
+            version: JSON_VERSION,
+            functions_called: self.functions.len(),
+            unsupported_functions: self.unsupported().count(),
+            unsupported_calls: self.unsupported().map(|f| f.calls).sum(),
+            parse_errors: self.errors.len(),
+            functions: self
+                .functions
+                .iter()
+                .map(|f| JsonFunction {
+                    name: &f.name,
+                    support: f.support,
+                    calls: f.calls,
+                    locations: f
+                        .locations
+                        .iter()
+                        .map(|(location, calls)| JsonLocation { location, calls: *calls })
+                        .collect(),
+                })
+                .collect(),
+            errors: self.errors.iter().map(|(location, message)| JsonError { location, message }).collect(),
+        };
+        serde_json::to_string_pretty(&report).expect("a report is always valid JSON") + "\n"
+    }
+
+    /// Formats the report as a table for people to read.
+    pub fn to_table(&self) -> String {
+        let mut table = String::new();
+        let unsupported = self.unsupported().collect::<Vec<_>>();
+        if unsupported.is_empty() {
+            writeln!(table, "all {} functions this game calls are implemented", self.functions.len()).unwrap();
+        } else {
+            writeln!(
+                table,
+                "{} of the {} functions this game calls aren't fully supported:\n",
+                unsupported.len(),
+                self.functions.len(),
+            )
+            .unwrap();
+            writeln!(table, "{:<14} {:>6}  function", "support", "calls").unwrap();
+            for function in unsupported {
+                writeln!(table, "{:<14} {:>6}  {}", function.support.name(), function.calls, function.name).unwrap();
+                for (location, calls) in function.locations.iter() {
+                    writeln!(table, "{:<14} {:>6}    in {}", "", calls, location).unwrap();
+                }
+            }
+        }
+        if !self.errors.is_empty() {
+            writeln!(table, "\n{} pieces of code couldn't be parsed, so weren't checked:", self.errors.len()).unwrap();
+            for (location, message) in self.errors.iter() {
+                writeln!(table, "  {}: {}", location, message).unwrap();
+            }
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(action_kind: u32, execution_type: u32, fn_name: &str, params: &[(u32, &str)]) -> CodeAction {
+        let mut param_types = [0; 8];
+        let mut param_strings: [PascalString; 8] = Default::default();
+        for (i, (kind, param)) in params.iter().enumerate() {
+            param_types[i] = *kind;
+            param_strings[i] = (*param).into();
+        }
+        CodeAction {
+            id: 0,
+            applies_to: -1,
+            is_condition: false,
+            invert_condition: false,
+            is_relative: false,
+            lib_id: 1,
+            action_kind,
+            execution_type,
+            can_be_relative: 0,
+            applies_to_something: true,
+            fn_name: fn_name.into(),
+            fn_code: "".into(),
+            param_count: params.len(),
+            param_types,
+            param_strings,
+        }
+    }
+
+    fn kernel(name: &str) -> Support {
+        match name {
+            "splash_show_web" => Support::Unimplemented,
+            "not_a_function" => Support::Missing,
+            _ => Support::Implemented,
+        }
+    }
+
+    #[test]
+    fn calls_in_actions() {
+        let mut calls = Calls::default();
+        let actions = [
+            action(kind::NORMAL, execution_type::FUNCTION, "action_move", &[
+                (0, "random(4)"),
+                (1, "splash_show_web()"),
+            ]),
+            action(kind::REPEAT, execution_type::NONE, "", &[(0, "irandom(3)")]),
+            action(kind::VARIABLE, execution_type::NONE, "", &[(1, "x"), (0, "random(4) + not_a_function()")]),
+            action(kind::CODE, execution_type::CODE, "", &[(1, "if a with b splash_show_web(1) else c = scr(d(1))")]),
+            action(kind::CODE, execution_type::CODE, "", &[(1, "if (")]),
+        ];
+        calls.actions(&actions, "object 2 (obj_test) event 3,0");
+        let user_functions = std::iter::once(b"scr".to_vec()).collect();
+        let report = calls.into_report(&user_functions, kernel);
+
+        let functions = report.functions.iter().map(|f| (f.name.as_str(), f.support, f.calls)).collect::<Vec<_>>();
+        assert_eq!(functions, [
+            ("not_a_function", Support::Missing, 1),
+            ("splash_show_web", Support::Unimplemented, 1),
+            ("random", Support::Implemented, 2),
+            ("action_move", Support::Implemented, 1),
+            ("d", Support::Implemented, 1),
+            ("irandom", Support::Implemented, 1),
+        ]);
+        assert_eq!(report.functions[1].locations, [("object 2 (obj_test) event 3,0 action 3".to_string(), 1)]);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].0, "object 2 (obj_test) event 3,0 action 4");
+        assert_eq!(report.unsupported().count(), 2);
+    }
+
+    #[test]
+    fn json() {
+        let mut calls = Calls::default();
+        for i in 0..10 {
+            calls.code(b"splash_show_web(); splash_show_web()", format!("script {} (\"quoted\"\\)", i));
+        }
+        let text = calls.into_report(&HashSet::new(), kernel).to_json();
+        assert!(
+            text.starts_with("{\n  \"version\": 1,\n  \"functions_called\": 1,\n  \"unsupported_functions\": 1,\n")
+        );
+        assert!(text.contains("\n  \"unsupported_calls\": 20,\n"));
+
+        let json = serde_json::from_str::<serde_json::Value>(&text).unwrap();
+        assert_eq!(json["parse_errors"], 0);
+        assert_eq!(json["errors"], serde_json::json!([]));
+        let function = &json["functions"][0];
+        assert_eq!(function["name"], "splash_show_web");
+        assert_eq!(function["support"], "unimplemented");
+        assert_eq!(function["calls"], 20);
+        assert_eq!(function["locations"].as_array().unwrap().len(), MAX_LOCATIONS);
+        assert_eq!(function["locations"][0], serde_json::json!({ "location": "script 0 (\"quoted\"\\)", "calls": 2 }));
+    }
+}
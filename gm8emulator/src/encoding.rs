@@ -0,0 +1,210 @@
+//! Picking the code page that GM8.0 games' text is in. GM8.0 stores text as bytes in whatever the ANSI code page was
+//! on the machine it was made on, and the runner reads it back with the ANSI code page of the machine it runs on,
+//! so nothing in the game says which one it is. GM8.1 games always use UTF-8 and aren't affected.
+
+use encoding_rs::{
+    Encoding, BIG5, EUC_KR, GBK, IBM866, SHIFT_JIS, WINDOWS_1250, WINDOWS_1251, WINDOWS_1252, WINDOWS_1253,
+    WINDOWS_1254, WINDOWS_1255, WINDOWS_1256, WINDOWS_1257, WINDOWS_1258, WINDOWS_874,
+};
+use gm8exe::GameAssets;
+
+/// What's used when recording or replaying, so TASes play back the same on every machine, and on systems with no
+/// ANSI code page. It's always been the default, since Japanese games are the most common ones with non-ASCII text.
+pub const FALLBACK: &Encoding = SHIFT_JIS;
+
+/// Labels suggested when an unknown one is given.
+const COMMON_LABELS: &[&str] = &["shift_jis", "windows-1252", "windows-1251", "gbk", "euc-kr", "big5"];
+
+/// Looks up an encoding by any of its WHATWG labels, such as "shift_jis" or "windows-1252".
+pub fn from_label(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("unknown encoding '{}' (try one of: auto, {})", label, COMMON_LABELS.join(", ")))
+}
+
+/// The encoding for a Windows code page number, as returned by GetACP.
+pub fn from_code_page(code_page: u32) -> Option<&'static Encoding> {
+    Some(match code_page {
+        874 => WINDOWS_874,
+        866 => IBM866,
+        932 => SHIFT_JIS,
+        936 => GBK,
+        949 => EUC_KR,
+        950 => BIG5,
+        1250 => WINDOWS_1250,
+        1251 => WINDOWS_1251,
+        1252 => WINDOWS_1252,
+        1253 => WINDOWS_1253,
+        1254 => WINDOWS_1254,
+        1255 => WINDOWS_1255,
+        1256 => WINDOWS_1256,
+        1257 => WINDOWS_1257,
+        1258 => WINDOWS_1258,
+        _ => return None,
+    })
+}
+
+/// The system's ANSI code page, which is what the real runner would use. Falls back to `FALLBACK` elsewhere.
+pub fn system() -> &'static Encoding {
+    ansi_code_page().and_then(from_code_page).unwrap_or(FALLBACK)
+}
+
+#[cfg(target_os = "windows")]
+fn ansi_code_page() -> Option<u32> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetACP() -> u32;
+    }
+    Some(unsafe { GetACP() })
+}
+
+#[cfg(not(target_os = "windows"))]
+fn ansi_code_page() -> Option<u32> {
+    None
+}
+
+/// Guesses which encoding a game's text is in from its scripts, code, room captions and constants.
+/// Returns None if it's all ASCII, so there's nothing to go on, or if none of the encodings tried fit.
+pub fn guess(assets: &GameAssets) -> Option<&'static Encoding> {
+    let mut strings: Vec<&[u8]> = Vec::new();
+    strings.extend(assets.scripts.iter().flatten().map(|x| x.source.0.as_ref()));
+    strings.extend(assets.constants.iter().map(|x| x.expression.0.as_ref()));
+    for room in assets.rooms.iter().flatten() {
+        strings.push(room.caption.0.as_ref());
+        strings.push(room.creation_code.0.as_ref());
+        strings.extend(room.instances.iter().map(|x| x.creation_code.0.as_ref()));
+    }
+    for object in assets.objects.iter().flatten() {
+        for (_, actions) in object.events.iter().flatten() {
+            strings.extend(actions.iter().flat_map(|x| x.param_strings.iter().map(|x| x.0.as_ref())));
+        }
+    }
+    guess_from(strings)
+}
+
+/// Guesses an encoding from some strings. For an encoding to be picked, every string has to decode without errors,
+/// and most of the non-ASCII characters have to look like they belong together in that encoding's language.
+/// Multi-byte encodings will decode short bits of text in other encodings as nonsense, but over a whole game's worth
+/// of text there's almost always a byte that doesn't fit.
+pub fn guess_from<'a>(strings: impl IntoIterator<Item = &'a [u8]>) -> Option<&'static Encoding> {
+    // in order of preference when more than one fits equally well
+    let candidates: [(&'static Encoding, Fits); 5] = [
+        (SHIFT_JIS, |text, i| is_kana(text[i]) || is_cjk(text[i])),
+        (EUC_KR, |text, i| is_common_korean(text[i])),
+        (GBK, |text, i| is_common_chinese(text[i])),
+        // Cyrillic words are spelt entirely in non-ASCII letters
+        (WINDOWS_1251, |text, i| is_cyrillic(text[i]) && neighbours(text, i).any(is_cyrillic)),
+        // whereas most letters in Western European words are ASCII, with the odd accented one among them
+        (WINDOWS_1252, |text, i| is_accented(text[i]) && neighbours(text, i).any(|c| c.is_ascii_alphabetic())),
+    ];
+
+    let strings = strings.into_iter().filter(|x| !x.is_ascii()).collect::<Vec<_>>();
+    let mut best = None;
+    let mut best_score = 0.5;
+    for &(encoding, fits) in &candidates {
+        let (mut total, mut matched, mut kana) = (0, 0, false);
+        let decoded = strings.iter().all(|bytes| {
+            let text = match encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+                Some(text) => text.chars().collect::<Vec<_>>(),
+                None => return false,
+            };
+            for i in (0..text.len()).filter(|&i| !text[i].is_ascii()) {
+                total += 1;
+                matched += usize::from(fits(&text, i));
+                kana |= is_kana(text[i]);
+            }
+            true
+        });
+        // Japanese text almost always has some kana in it, which Chinese doesn't
+        if !decoded || total == 0 || (encoding == SHIFT_JIS && !kana) {
+            continue
+        }
+        let score = matched as f64 / total as f64;
+        if score > best_score {
+            best = Some(encoding);
+            best_score = score;
+        }
+    }
+    best
+}
+
+type Fits = fn(&[char], usize) -> bool;
+
+// Only the common characters are counted for Korean and Chinese, as the rest are where text in other encodings
+// ends up: an accented letter followed by an ASCII one, or a pair of Cyrillic letters, are both rare characters.
+
+// The 2350 Hangul syllables in KS X 1001, which are the ones used in practice.
+fn is_common_korean(c: char) -> bool {
+    match *EUC_KR.encode(c.encode_utf8(&mut [0; 4])).0 {
+        [lead, trail] => matches!(lead, 0xB0..=0xC8) && trail >= 0xA1,
+        _ => false,
+    }
+}
+
+// Punctuation and the 3755 level 1 characters of GB2312, which make up almost all Chinese text.
+fn is_common_chinese(c: char) -> bool {
+    match *GBK.encode(c.encode_utf8(&mut [0; 4])).0 {
+        [lead, trail] => matches!(lead, 0xA1..=0xA9 | 0xB0..=0xD7) && trail >= 0xA1,
+        _ => false,
+    }
+}
+
+fn neighbours(text: &[char], i: usize) -> impl Iterator<Item = char> + '_ {
+    i.checked_sub(1).and_then(|i| text.get(i)).into_iter().chain(text.get(i + 1)).copied()
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}')
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3000}'..='\u{303F}' | '\u{FF01}'..='\u{FF5E}')
+}
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}')
+}
+
+fn is_accented(c: char) -> bool {
+    matches!(c, '\u{00C0}'..='\u{00FF}') && c != '\u{00D7}' && c != '\u{00F7}'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(encoding: &'static Encoding, text: &str) -> Vec<u8> {
+        let (bytes, _, bad) = encoding.encode(text);
+        assert!(!bad);
+        bytes.into_owned()
+    }
+
+    fn guess_text(encoding: &'static Encoding, lines: &[&str]) -> Option<&'static Encoding> {
+        let strings = lines.iter().map(|x| encode(encoding, x)).collect::<Vec<_>>();
+        guess_from(strings.iter().map(|x| x.as_slice()))
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(from_label("windows-1252").unwrap(), WINDOWS_1252);
+        assert_eq!(from_label(" Shift_JIS ").unwrap(), SHIFT_JIS);
+        assert_eq!(from_label("cp1251").unwrap(), WINDOWS_1251);
+        let error = from_label("klingon").unwrap_err();
+        assert!(error.contains("'klingon'") && error.contains("windows-1252"));
+        assert_eq!(from_code_page(932), Some(SHIFT_JIS));
+        assert_eq!(from_code_page(1252), Some(WINDOWS_1252));
+        assert_eq!(from_code_page(65001), None);
+    }
+
+    #[test]
+    fn guesses() {
+        assert_eq!(guess_from(vec![&b"show_message(\"hi\")"[..]]), None);
+        assert_eq!(guess_text(SHIFT_JIS, &["show_message(\"ゲームオーバー\")", "caption = \"体力\""]), Some(SHIFT_JIS));
+        assert_eq!(guess_text(WINDOWS_1251, &["show_message(\"Игра окончена\")", "// жизни"]), Some(WINDOWS_1251));
+        assert_eq!(guess_text(WINDOWS_1252, &["show_message(\"Spiel für zwei\")", "// über"]), Some(WINDOWS_1252));
+        assert_eq!(guess_text(GBK, &["show_message(\"游戏结束了\")", "// 生命 值"]), Some(GBK));
+        assert_eq!(guess_text(EUC_KR, &["show_message(\"게임 오버\")"]), Some(EUC_KR));
+        // these would all decode as Chinese
+        assert_eq!(guess_text(WINDOWS_1251, &["Привет мир, как дела"]), Some(WINDOWS_1251));
+        assert_eq!(guess_text(WINDOWS_1252, &["Vous êtes mort, à bientôt"]), Some(WINDOWS_1252));
+    }
+}
@@ -1,18 +1,32 @@
+pub mod atlasdump;
 pub mod audio;
+pub mod autosave;
 pub mod background;
+pub mod cmdline;
 pub mod draw;
+pub mod error;
 pub mod events;
 pub mod external;
+pub mod filecapture;
+pub mod framedump;
+pub mod gamelog;
 pub mod gm_save;
+pub mod icon;
 pub mod includedfile;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod model;
 pub mod movement;
+pub mod pacing;
 pub mod particle;
 pub mod pathfinding;
 pub mod recording;
 pub mod replay;
 pub mod savestate;
 pub mod surface;
+pub mod tempdir;
+#[cfg(test)]
+pub mod testing;
 pub mod transition;
 pub mod view;
 
@@ -25,21 +39,26 @@ use crate::{
     action::Tree,
     asset::{
         self,
-        font::{Character, Font},
+        font::{self, Character, Font},
         path::{self, Path},
         room::{self, Room},
+        sound::SoundV1,
         sprite::{Collider, Frame, Sprite},
         trigger::{self, Trigger},
         Object, Script, Sound, Timeline,
     },
-    game::gm_save::GMSave,
+    game::{
+        error::{ExitReason, LaunchError, RuntimeError},
+        gm_save::GMSave,
+        pacing::FramePacer,
+    },
     gml::{self, ds, ev, file, rand::Random, runtime::Instruction, Compiler, Context},
     handleman::{HandleArray, HandleList},
     input::{self, Input},
-    instance::{DummyFieldHolder, Instance, InstanceState},
+    instance::{advance_image, DummyFieldHolder, Instance, InstanceState},
     instancelist::{InstanceList, TileList},
     math::Real,
-    render::{atlas::AtlasBuilder, Renderer, RendererOptions, Scaling},
+    render::{atlas::{AtlasBuilder, SharedTextures}, Backend, Renderer, RendererOptions, Scaling},
     tile,
     types::{Colour, ID},
     util,
@@ -62,6 +81,7 @@ use std::{
     cell::{Cell, RefCell},
     collections::{BTreeMap, HashMap, HashSet, VecDeque},
     convert::TryFrom,
+    ffi::OsString,
     fs::File,
     io::Write,
     path::PathBuf,
@@ -93,6 +113,7 @@ pub struct Game {
 
     pub last_instance_id: ID,
     pub last_tile_id: ID,
+    pub destroying: Vec<usize>, // instances whose destroy event is running, so destroying them again does nothing
     pub particles: particle::Manager,
 
     pub room: RoomState,
@@ -149,11 +170,14 @@ pub struct Game {
     pub health: Real,             // default 100.0
     pub health_capt: gml::String, // default "Health: "
     pub health_capt_d: bool,      // display in caption?
+    pub lives_ran_out: bool,      // no more lives event due after this step's step event
+    pub health_ran_out: bool,     // no more health event due after this step's step event
 
     pub error_occurred: bool,
     pub error_last: gml::String,
 
     pub game_id: i32,
+    pub gamedata_hash: u64, // identifies the exe that replays are recorded against
     pub program_directory: gml::String,
     pub temp_directory: gml::String,
     pub included_files: Vec<IncludedFile>,
@@ -162,25 +186,39 @@ pub struct Game {
     pub open_file: Option<file::TextHandle>,       // for legacy file functions from GM <= 5.1
     pub file_finder: Option<Box<dyn Iterator<Item = PathBuf>>>,
     pub spoofed_time_nanos: Option<u128>, // use this instead of real time if this is set
-    pub parameters: Vec<String>,
+    pub parameters: Vec<gml::String>,
     pub encoding: &'static Encoding,
 
     pub esc_close_game: bool,
+    pub f5_save_f6_load: bool,
+    pub f9_screenshot: bool,
+    pub treat_close_as_esc: bool,
+    pub close_button_pending: bool, // the close button was clicked and the close button event hasn't run yet
 
     pub play_type: PlayType,
     pub stored_events: VecDeque<replay::Event>,
     pub frame_limiter: bool, // whether to limit FPS of gameplay by room_speed
+    pub file_store: Option<filecapture::FileStore>, // if set, savestates capture the temp directory
+    pub game_log: gamelog::GameLog, // show_debug_message output, for the console and --game-log
+    pub debug_mode: bool, // what debug_mode reads as, which is true if the game is run as if from GM's debug mode
+    pub frame_dumper: Option<framedump::FrameDumper>, // if set, replays dump frames with --dump-frames
+    pub frame_time_graph: Option<pacing::FrameTimeGraph>, // if set, drawn over the game with --frame-times
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<metrics::Server>, // if set, a snapshot gets published after every frame
 
     pub audio: audio::AudioManager,
 
     // winit windowing
-    pub window: Window,
+    /// The game's window, or None when running headless.
+    pub window: Option<Window>,
     pub window_border: bool,
     pub window_caption: String,
     pub window_cursor_gml: i32,
     pub window_icons: bool,
     pub window_inner_size: (u32, u32),
     pub window_offset_spoof: (i32, i32),
+    // Whether Windows would scale the game's window on high DPI displays, which it does unless the exe's
+    // manifest says it's DPI-aware
     pub window_is_logical_dpi: bool,
     pub window_sizeable: bool,
     pub window_visible: bool,
@@ -254,6 +292,40 @@ pub struct Assets {
     pub sprites: Vec<Option<Box<Sprite>>>,
     pub timelines: Vec<Option<Box<Timeline>>>,
     pub triggers: Vec<Option<Box<Trigger>>>,
+    pub shared_textures: SharedTextures,
+}
+
+// Assets as savestates stored them before sprite frames shared textures, and before MIDI and preload
+#[derive(Deserialize)]
+pub struct AssetsV1 {
+    backgrounds: Vec<Option<Box<asset::Background>>>,
+    fonts: Vec<Option<Box<Font>>>,
+    objects: Vec<Option<Box<Object>>>,
+    paths: Vec<Option<Box<Path>>>,
+    rooms: Vec<Option<Box<Room>>>,
+    scripts: Vec<Option<Box<Script>>>,
+    sounds: Vec<Option<Box<SoundV1>>>,
+    sprites: Vec<Option<Box<Sprite>>>,
+    timelines: Vec<Option<Box<Timeline>>>,
+    triggers: Vec<Option<Box<Trigger>>>,
+}
+
+impl From<AssetsV1> for Assets {
+    fn from(old: AssetsV1) -> Self {
+        Self {
+            backgrounds: old.backgrounds,
+            fonts: old.fonts,
+            objects: old.objects,
+            paths: old.paths,
+            rooms: old.rooms,
+            scripts: old.scripts,
+            sounds: old.sounds.into_iter().map(|x| x.map(|x| Box::new((*x).into()))).collect(),
+            sprites: old.sprites,
+            timelines: old.timelines,
+            triggers: old.triggers,
+            shared_textures: SharedTextures::default(),
+        }
+    }
 }
 
 impl From<PascalString> for gml::String {
@@ -262,12 +334,18 @@ impl From<PascalString> for gml::String {
     }
 }
 
+/// The file the built-in F5 and F6 keys save to and load from, in the working directory.
+const SAVE_FILE_NAME: &str = "save.sav";
+
 macro_rules! handle_scene_change {
     ($self:ident) => {{
         match $self.scene_change {
             Some(SceneChange::Room(id)) => $self.load_room(id)?,
             Some(SceneChange::Restart) => $self.restart()?,
-            Some(SceneChange::End) => return Ok($self.run_game_end_events()?),
+            Some(SceneChange::End) => {
+                $self.run_game_end_events()?;
+                return Ok(ExitReason::GameEnd)
+            },
             Some(SceneChange::Load(ref mut path)) => {
                 let path = std::mem::take(path);
                 $self.load_gm_save(path)?
@@ -277,32 +355,33 @@ macro_rules! handle_scene_change {
     }};
 }
 
+/// The size to give the window for the game to be the given size. Windows scales the game up on high DPI displays,
+/// unless it's DPI-aware, so then the size is in logical pixels rather than physical ones.
+pub fn window_size(logical_dpi: bool, width: u32, height: u32) -> Size {
+    if logical_dpi { Size::Logical(width.into(), height.into()) } else { Size::Physical(width, height) }
+}
+
 impl Game {
     pub fn launch(
         assets: gm8exe::GameAssets,
         file_path: PathBuf,
-        game_arguments: Vec<String>,
+        game_arguments: Vec<OsString>,
         temp_dir: Option<PathBuf>,
         encoding: &'static Encoding,
         frame_limiter: bool,
         play_type: PlayType,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
+        headless: bool,
+        logical_dpi: bool,
+    ) -> Result<Self, LaunchError> {
         // Parse file path
         let mut file_path2 = file_path.clone();
         file_path2.pop();
         // Game Maker doesn't change working directory on load but doing it anyway makes life easier
         std::env::set_current_dir(&file_path2)?;
-        let mut param_string: &str = &file_path.to_string_lossy();
-        let mut program_directory: &str = &file_path2.to_string_lossy();
-
-        if cfg!(target_os = "windows") {
-            param_string = param_string.trim_start_matches("\\\\?\\");
-            program_directory = program_directory.trim_start_matches("\\\\?\\");
-        }
-        // TODO: store these as gml::String probably?
         eprintln!(
             "launching game\n  > param_string: \"{}\"\n  > program_directory: \"{}\"",
-            param_string, program_directory
+            file_path.display(),
+            file_path2.display()
         );
 
         // Improve framepacing on Windows
@@ -320,10 +399,12 @@ impl Game {
         // Destructure assets
         let gm8exe::GameAssets {
             game_id,
+            gamedata_hash,
             backgrounds,
             constants,
             extensions,
             fonts,
+            ico_file_raw,
             included_files,
             last_instance_id,
             last_tile_id,
@@ -347,14 +428,21 @@ impl Game {
             gm8exe::GameVersion::GameMaker8_1 => Version::GameMaker8_1,
         };
 
+        // Paths and parameters are kept as OS strings until here, then converted to whatever the game would've seen
+        let param_encoding = match gm_version {
+            Version::GameMaker8_0 => encoding,
+            Version::GameMaker8_1 => encoding_rs::UTF_8,
+        };
+        let program_directory = gml::String::from(cmdline::encode_path(&file_path2, param_encoding));
+        let parameters = cmdline::parameters(&file_path, &game_arguments, param_encoding)
+            .into_iter()
+            .map(gml::String::from)
+            .collect();
+
         // If there are no rooms, you can't build a GM8 game. Fatal error.
         // We need a lot of the initialization info from the first room,
         // the window size, and title, etc. is based on it.
-        let room1_id = *room_order.first().ok_or("Room order is empty")?;
-        let room1 = match rooms.get(room1_id as usize) {
-            Some(Some(r)) => r,
-            _ => return Err("First room does not exist".into()),
-        };
+        let (room1_id, room1) = error::first_room(&room_order, &rooms)?;
         let room1_width = room1.width;
         let room1_height = room1.height;
         let room1_speed = room1.speed;
@@ -378,43 +466,33 @@ impl Game {
             Some(path) => path,
             None => {
                 // read path from tempdir.txt or if that's not possible get std::env::temp_dir()
-                let mut dir = if let Some(path) =
+                let dir = if let Some(path) =
                     std::fs::read("tempdir.txt").ok().and_then(decode_str_maybe).map(|path| PathBuf::from(path))
                 {
                     path
                 } else {
                     std::env::temp_dir()
                 };
-                // closure to make a gm_ttt folder within a given path
-                let mut make_temp_dir = |path: &mut PathBuf| {
-                    let mut folder = "gm_ttt_".to_string();
-                    folder += &rand.next_int(99999).to_string();
-                    path.push(&folder);
-                    while path.exists() {
-                        path.pop();
-                        folder.truncate(7); // length of "gm_ttt_"
-                        folder += &rand.next_int(99999).to_string();
-                        path.push(&folder);
-                    }
-                    std::fs::create_dir_all(path)
-                };
-                // try making folders
-                if let Err(e) = make_temp_dir(&mut dir) {
-                    eprintln!("Could not create temp folder in {:?}: {}", dir, e);
-                    // GM8 would try C:\temp but let's skip that
-                    match std::env::current_dir().map(|x| {
-                        dir = x;
-                        make_temp_dir(&mut dir)
-                    }) {
-                        Ok(_) => eprintln!("Using game directory instead."),
-                        Err(e) => {
-                            eprintln!("Could not use game directory either: {}", e);
-                            eprintln!("Trying to run anyway. If this game uses the temp folder, it will likely crash.");
-                            dir = PathBuf::new();
-                        },
-                    }
+                match tempdir::create_in(&dir, &mut rand) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        eprintln!("Could not create temp folder in {:?}: {}", dir, e);
+                        // GM8 would try C:\temp but let's skip that
+                        match std::env::current_dir().and_then(|x| tempdir::create_in(&x, &mut rand)) {
+                            Ok(path) => {
+                                eprintln!("Using game directory instead.");
+                                path
+                            },
+                            Err(e) => {
+                                eprintln!("Could not use game directory either: {}", e);
+                                eprintln!(
+                                    "Trying to run anyway. If this game uses the temp folder, it will likely crash."
+                                );
+                                PathBuf::new()
+                            },
+                        }
+                    },
                 }
-                dir
             },
         };
 
@@ -446,14 +524,14 @@ impl Game {
                     free_after_export: i.free_memory,
                     remove_at_end: i.remove_at_end,
                 };
-                i.export(temp_directory.clone(), program_directory.to_string().into())?;
+                i.export(temp_directory.clone(), file_path2.clone())?;
                 Ok(i)
             })
             .collect::<Result<Vec<_>, std::io::Error>>()
             .expect("failed to extract included files");
 
         // Set up a GML compiler
-        let mut compiler = Compiler::new();
+        let mut compiler = Compiler::new(gm_version == Version::GameMaker8_1);
         compiler.reserve_scripts(scripts.iter().flatten().count());
         compiler.reserve_constants(
             backgrounds.iter().flatten().count()
@@ -543,32 +621,49 @@ impl Game {
         let (width, height) = options.size;
         let window_border = !settings.dont_draw_border;
         let window_icons = !settings.dont_show_buttons;
-        let window = Window::builder()
-            .visible(false)
-            .inner_size(Size::Physical(width.into(), height.into()))
-            .borderless(!window_border && play_type != PlayType::Record)
-            .title(room1_caption.to_owned())
-            .resizable(match play_type {
-                PlayType::Normal => settings.allow_resize,
-                PlayType::Record => true,
-                PlayType::Replay => false,
-            })
-            .controls(if play_type == PlayType::Record {
-                Some(Controls::enabled())
-            } else if window_icons {
-                Some(Controls::new(settings.allow_resize, settings.allow_resize, true))
-            } else {
-                None
-            })
-            .build()
-            .expect("oh no");
+        let window = if headless {
+            None
+        } else {
+            let window = Window::builder()
+                .visible(false)
+                .inner_size(window_size(logical_dpi, width.into(), height.into()))
+                .borderless(!window_border && play_type != PlayType::Record)
+                .title(room1_caption.to_owned())
+                .resizable(match play_type {
+                    PlayType::Normal => settings.allow_resize,
+                    PlayType::Record => true,
+                    PlayType::Replay => false,
+                })
+                .controls(if play_type == PlayType::Record {
+                    Some(Controls::enabled())
+                } else if window_icons {
+                    Some(Controls::new(settings.allow_resize, settings.allow_resize, true))
+                } else {
+                    None
+                })
+                .build()
+                .expect("oh no");
+            if let Some(ico) = &ico_file_raw {
+                icon::set_window_icon(&window, ico);
+            }
+            Some(window)
+        };
 
         // Set up audio manager
-        let mut audio = audio::AudioManager::new(play_type != PlayType::Record);
+        let mut audio = if headless {
+            audio::AudioManager::without_output()
+        } else {
+            audio::AudioManager::new(play_type != PlayType::Record)
+        };
 
         // TODO: specific flags here (make wb mutable)
 
-        let mut renderer = Renderer::new((), &options, &window, settings.clear_colour.into())?;
+        let backend = match &window {
+            Some(window) => Backend::OpenGL(window),
+            None => Backend::Headless,
+        };
+        let mut renderer =
+            Renderer::new(backend, &options, settings.clear_colour.into()).map_err(LaunchError::Renderer)?;
 
         let mut atlases = AtlasBuilder::new(renderer.max_texture_size() as _);
 
@@ -582,7 +677,7 @@ impl Game {
 
         let particle_shapes = particle::load_shapes(&mut atlases);
 
-        let default_font = asset::font::load_default_font(&mut atlases)?;
+        let default_font = asset::font::load_default_font(&mut atlases).map_err(LaunchError::Renderer)?;
 
         let mut externals = external::ExternalManager::new(false).unwrap();
 
@@ -695,7 +790,11 @@ impl Game {
                                     } else {
                                         &file.contents[start..]
                                     };
-                                    extension_functions.push(Some(ExtensionFunction::Gml(compiler.compile(fn_code)?)));
+                                    let compiled = compiler.compile(fn_code).map_err(|e| LaunchError::Compile {
+                                        location: format!("extension function {}", function.name),
+                                        error: e.to_string(),
+                                    })?;
+                                    extension_functions.push(Some(ExtensionFunction::Gml(compiled)));
                                 },
                                 None => {
                                     println!(
@@ -730,6 +829,43 @@ impl Game {
                     use gm8exe::asset::sound::SoundKind;
                     let handle = match b.data {
                         Some(data) => match b.extension.0.as_ref() {
+                            b".mp3" if !b.preload => match audio.add_lazy_mp3(data, sound_id as i32) {
+                                Some(x) => FileType::Lazy(x),
+                                None => {
+                                    println!(
+                                        "WARNING: invalid mp3 data in sound '{}'",
+                                        String::from_utf8_lossy(b.name.0.as_ref())
+                                    );
+                                    FileType::None
+                                },
+                            },
+                            b".wav" if !b.preload => match audio.add_lazy_wav(
+                                data,
+                                sound_id as i32,
+                                b.volume,
+                                b.kind == SoundKind::ThreeDimensional,
+                                b.kind == SoundKind::Multimedia,
+                            ) {
+                                Some(x) => FileType::Lazy(x),
+                                None => {
+                                    println!(
+                                        "WARNING: invalid wav data in sound '{}'",
+                                        String::from_utf8_lossy(b.name.0.as_ref())
+                                    );
+                                    FileType::None
+                                },
+                            },
+                            // MIDI files are tiny once parsed, so they're loaded up front even with preload off
+                            b".mid" | b".midi" => match audio.add_midi(data, sound_id as i32) {
+                                Some(x) => FileType::Midi(x),
+                                None => {
+                                    println!(
+                                        "WARNING: invalid midi data in sound '{}'",
+                                        String::from_utf8_lossy(b.name.0.as_ref())
+                                    );
+                                    FileType::None
+                                },
+                            },
                             b".mp3" => match audio.add_mp3(data, sound_id as i32) {
                                 Some(x) => FileType::Mp3(x),
                                 None => {
@@ -770,6 +906,8 @@ impl Game {
             })
             .collect::<Vec<_>>();
 
+        // lots of sprites have identical frames, so those are only packed once
+        let mut shared_textures = SharedTextures::default();
         let sprites = sprites
             .into_iter()
             .map(|o| {
@@ -791,7 +929,14 @@ impl Game {
                                     width: f.width,
                                     height: f.height,
                                     atlas_ref: atlases
-                                        .texture(f.width as _, f.height as _, origin_x, origin_y, f.data)
+                                        .texture_shared(
+                                            f.width as _,
+                                            f.height as _,
+                                            origin_x,
+                                            origin_y,
+                                            f.data,
+                                            &mut shared_textures,
+                                        )
                                         .ok_or(())?,
                                 })
                             })
@@ -864,23 +1009,15 @@ impl Game {
                             if tallest_char_height < char_blob[3] {
                                 tallest_char_height = char_blob[3];
                             }
-                            let mut data: Vec<u8> = Vec::with_capacity((char_blob[2] * char_blob[3] * 4) as usize);
-                            for y in 0..char_blob[3] {
-                                for x in 0..char_blob[2] {
-                                    data.push(0xFF);
-                                    data.push(0xFF);
-                                    data.push(0xFF);
-                                    data.push(
-                                        b.pixel_map[((y + char_blob[1]) * b.map_width + x + char_blob[0]) as usize],
-                                    );
-                                }
-                            }
+                            let (left, top, width, height) = (char_blob[0], char_blob[1], char_blob[2], char_blob[3]);
+                            let (pixel_map, map_width) = (&b.pixel_map, b.map_width);
+                            let data = font::glyph_texture((0..height).flat_map(|y| {
+                                (0..width).map(move |x| pixel_map[((y + top) * map_width + x + left) as usize])
+                            }));
                             Ok(Character {
                                 offset: char_blob[4] as _,
                                 distance: char_blob[5] as _,
-                                atlas_ref: atlases
-                                    .texture(char_blob[2] as _, char_blob[3] as _, 0, 0, data.into_boxed_slice())
-                                    .ok_or(())?,
+                                atlas_ref: atlases.texture(width as _, height as _, 0, 0, data).ok_or(())?,
                             })
                         })
                         .collect::<Result<Box<_>, ()>>()?;
@@ -938,7 +1075,12 @@ impl Game {
                 t.map(|b| {
                     let condition = match compiler.compile(&b.condition.0) {
                         Ok(s) => s,
-                        Err(e) => return Err(format!("Compiler error in trigger {}: {}", b.name, e)),
+                        Err(e) => {
+                            return Err(LaunchError::Compile {
+                                location: format!("trigger {}", b.name),
+                                error: e.to_string(),
+                            })
+                        },
                     };
                     Ok(Box::new(Trigger { name: b.name.into(), condition, moment: b.moment.into() }))
                 })
@@ -952,7 +1094,12 @@ impl Game {
                 t.map(|b| {
                     let compiled = match compiler.compile(&b.source.0) {
                         Ok(s) => s,
-                        Err(e) => return Err(format!("Compiler error in script {}: {}", b.name, e)),
+                        Err(e) => {
+                            return Err(LaunchError::Compile {
+                                location: format!("script {}", b.name),
+                                error: e.to_string(),
+                            })
+                        },
                     };
                     Ok(Box::new(Script { name: b.name.into(), source: b.source.into(), compiled }))
                 })
@@ -971,7 +1118,10 @@ impl Game {
                                 moments.insert(*moment as i32, Rc::new(RefCell::new(t)));
                             },
                             Err(e) => {
-                                return Err(format!("Compiler error in timeline {} moment {}: {}", b.name, moment, e))
+                                return Err(LaunchError::Compile {
+                                    location: format!("timeline {} moment {}", b.name, moment),
+                                    error: e,
+                                })
                             },
                         };
                     }
@@ -998,10 +1148,10 @@ impl Game {
                                 map.insert(*sub, match Tree::from_list(actions, &mut compiler) {
                                     Ok(t) => Rc::new(RefCell::new(t)),
                                     Err(e) => {
-                                        return Err(format!(
-                                            "Compiler error in object {} event {},{}: {}",
-                                            b.name, i, sub, e
-                                        ))
+                                        return Err(LaunchError::Compile {
+                                            location: format!("object {} event {},{}", b.name, i, sub),
+                                            error: e,
+                                        })
                                     },
                                 });
                             }
@@ -1039,11 +1189,7 @@ impl Game {
                         objects.get_asset_mut(i as _).unwrap().parents.borrow_mut().insert(parent_index);
                         parent_index = next_parent_index;
                     } else {
-                        return Err(format!(
-                            "Invalid parent tree for object {}: non-existent object: {}",
-                            i, parent_index
-                        )
-                        .into())
+                        return Err(LaunchError::InvalidParent { object: i as _, parent: parent_index })
                     }
                 }
             }
@@ -1178,10 +1324,9 @@ impl Game {
         Self::fill_event_holders(&mut event_holders, &objects);
 
         // Make list of objects with custom draw events
-        let custom_draw_objects =
-            event_holders[ev::DRAW].iter().flat_map(|(_, x)| x.borrow().iter().copied().collect::<Vec<_>>()).collect();
+        let custom_draw_objects = Self::draw_event_objects(&event_holders);
 
-        renderer.push_atlases(atlases)?;
+        renderer.push_atlases(atlases).map_err(LaunchError::Renderer)?;
 
         let mut game = Self {
             compiler,
@@ -1197,7 +1342,19 @@ impl Game {
             externals,
             surface_fix: false,
             input: Input::new(),
-            assets: Assets { backgrounds, fonts, objects, paths, rooms, scripts, sprites, sounds, timelines, triggers },
+            assets: Assets {
+                backgrounds,
+                fonts,
+                objects,
+                paths,
+                rooms,
+                scripts,
+                sprites,
+                sounds,
+                timelines,
+                triggers,
+                shared_textures,
+            },
             event_holders,
             custom_draw_objects,
             particles: particle::Manager::new(particle_shapes),
@@ -1246,6 +1403,7 @@ impl Game {
             auto_draw: true,
             last_instance_id,
             last_tile_id,
+            destroying: Vec::new(),
             uninit_fields_are_zero: settings.zero_uninitialized_vars,
             uninit_args_are_zero: !settings.error_on_uninitialized_args,
             swap_creation_events: settings.swap_creation_events,
@@ -1261,7 +1419,8 @@ impl Game {
             health: Real::from(100.0),
             health_capt: "Health: ".to_string().into(),
             game_id: game_id as i32,
-            program_directory: program_directory.into(),
+            gamedata_hash,
+            program_directory,
             temp_directory: "".into(),
             included_files,
             gm_version,
@@ -1272,13 +1431,19 @@ impl Game {
             frame_limiter,
             fps: 0,
             frame_counter: 0,
-            parameters: game_arguments,
+            parameters,
             encoding,
             esc_close_game: settings.esc_close_game,
+            f5_save_f6_load: settings.f5_save_f6_load,
+            f9_screenshot: settings.f9_screenshot,
+            treat_close_as_esc: settings.treat_close_as_esc,
+            close_button_pending: false,
             score_capt_d: true,
             has_set_show_score: false,
             lives_capt_d: false,
             health_capt_d: false,
+            lives_ran_out: false,
+            health_ran_out: false,
             error_occurred: false,
             error_last: "".to_string().into(),
             audio,
@@ -1289,6 +1454,13 @@ impl Game {
             scaling,
             play_type,
             stored_events: VecDeque::new(),
+            file_store: None,
+            game_log: Default::default(),
+            debug_mode: false,
+            frame_dumper: None,
+            frame_time_graph: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
 
             // load_room sets this
             unscaled_width: 0,
@@ -1298,7 +1470,7 @@ impl Game {
             window_caption: room1_caption.clone(),
             window_cursor_gml: gml::mappings::constants::CR_DEFAULT as _,
             window_inner_size: (width, height),
-            window_is_logical_dpi: false,
+            window_is_logical_dpi: logical_dpi,
             window_offset_spoof: (0, 0),
             window_sizeable: settings.allow_resize,
             window_visible: true,
@@ -1310,7 +1482,11 @@ impl Game {
         for extension in extensions {
             for file in extension.files {
                 for constant in file.consts {
-                    let expr = game.compiler.compile_expression(&constant.value.0)?;
+                    let expr =
+                        game.compiler.compile_expression(&constant.value.0).map_err(|e| LaunchError::Compile {
+                            location: format!("extension constant {}", constant.name),
+                            error: e.to_string(),
+                        })?;
                     let dummy_instance = game
                         .room
                         .instance_list
@@ -1323,7 +1499,10 @@ impl Game {
         }
 
         for c in &constants {
-            let expr = game.compiler.compile_expression(&c.expression.0)?;
+            let expr = game
+                .compiler
+                .compile_expression(&c.expression.0)
+                .map_err(|e| LaunchError::Compile { location: format!("constant {}", c.name), error: e.to_string() })?;
             let dummy_instance = game
                 .room
                 .instance_list
@@ -1338,7 +1517,9 @@ impl Game {
         game.globals.vars.clear();
         game.globalvars.clear();
 
-        game.window.set_visible(true);
+        if let Some(window) = &game.window {
+            window.set_visible(true);
+        }
 
         Ok(game)
     }
@@ -1355,10 +1536,12 @@ impl Game {
         Self::fill_event_holders(&mut self.event_holders, &self.assets.objects);
 
         // Make list of objects with custom draw events
-        self.custom_draw_objects = self.event_holders[ev::DRAW]
-            .iter()
-            .flat_map(|(_, x)| x.borrow().iter().copied().collect::<Vec<_>>())
-            .collect();
+        self.custom_draw_objects = Self::draw_event_objects(&self.event_holders);
+    }
+
+    /// Objects which have a draw event, either their own or one inherited from a parent, even if it's empty.
+    fn draw_event_objects(event_holders: &[IndexMap<u32, Rc<RefCell<Vec<ID>>>>]) -> HashSet<ID> {
+        event_holders[ev::DRAW].iter().flat_map(|(_, x)| x.borrow().iter().copied().collect::<Vec<_>>()).collect()
     }
 
     fn fill_event_holders(
@@ -1437,7 +1620,9 @@ impl Game {
             };
             if self.play_type != PlayType::Record {
                 self.window_inner_size = (width, height);
-                self.window.set_inner_size(Size::Physical(width, height));
+                if let Some(window) = &self.window {
+                    window.set_inner_size(window_size(self.window_is_logical_dpi, width, height));
+                }
             }
         }
     }
@@ -1459,7 +1644,7 @@ impl Game {
         }
     }
 
-    pub fn load_room(&mut self, room_id: i32) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn load_room(&mut self, room_id: i32) -> Result<(), RuntimeError> {
         let (room, room_state, is_stored) = if let Some(room) = self.assets.rooms.get_asset(room_id) {
             if let Some(p) = self.stored_rooms.iter().position(|x| x.id == room_id) {
                 (room.clone(), self.stored_rooms.remove(p), true)
@@ -1491,6 +1676,10 @@ impl Game {
         // Update this early so the other events run
         self.scene_change = None;
 
+        // A no more lives or health event that was due in the old room doesn't run in the new one
+        self.lives_ran_out = false;
+        self.health_ran_out = false;
+
         // Initialize room transition surface
         let transition_kind = self.transition_kind;
         let (trans_surf_old, trans_surf_new) = if self.get_transition(transition_kind).is_some() {
@@ -1548,7 +1737,7 @@ impl Game {
                     .map(yh)
                     .unwrap_or(room_state.height as i32);
                 if x_max < 0 || y_max < 0 {
-                    return Err(format!("Bad room width/height {},{} loading room {}", x_max, y_max, room_id).into())
+                    return Err(RuntimeError::InvalidRoomSize { room: room_id, width: x_max, height: y_max })
                 }
                 (x_max, y_max)
             }
@@ -1582,7 +1771,12 @@ impl Game {
                     // Get object
                     let object = match self.assets.objects.get_asset(instance.object) {
                         Some(o) => o.as_ref(),
-                        _ => return Err(format!("Instance of invalid Object in room {}", room.name).into()),
+                        _ => {
+                            return Err(RuntimeError::InvalidObjectInRoom {
+                                room: room.name.to_string(),
+                                object: instance.object,
+                            })
+                        },
                     };
 
                     // Add instance to list
@@ -1623,7 +1817,7 @@ impl Game {
                 // Run this instance's room creation code
                 let mut new_context = Context::with_single_instance(*handle);
                 new_context.event_object = instance.object;
-                self.execute(&instance.creation.clone()?, &mut new_context)?;
+                self.execute(&instance.creation.clone().map_err(RuntimeError::RoomCode)?, &mut new_context)?;
 
                 if !self.swap_creation_events {
                     // Run create event for this instance
@@ -1645,7 +1839,7 @@ impl Game {
                 .instance_list
                 .insert_dummy(Instance::new_dummy(self.assets.objects.get_asset(0).map(|x| x.as_ref())));
             let mut new_context = Context::with_single_instance(dummy_instance);
-            self.execute(&room.creation_code?, &mut new_context)?;
+            self.execute(&room.creation_code.map_err(RuntimeError::RoomCode)?, &mut new_context)?;
             self.room.instance_list.remove_dummy(dummy_instance);
         }
 
@@ -1730,7 +1924,7 @@ impl Game {
     }
 
     /// Restarts the game in the same half-baked way GM8 does, including running all relevant events.
-    pub fn restart(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn restart(&mut self) -> Result<(), RuntimeError> {
         // Room end, game end events
         self.run_game_end_events()?;
 
@@ -1741,11 +1935,24 @@ impl Game {
         self.game_start = true;
 
         // Go to first room
-        self.room.id = self.room_order.first().copied().ok_or("Empty room order during Game::restart()")?;
+        self.room.id = self.room_order.first().copied().ok_or(RuntimeError::EmptyRoomOrder)?;
         self.init()
     }
 
-    pub fn load_gm_save(&mut self, path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn save_gm_save(&self, path: PathBuf) -> gml::Result<()> {
+        let save = GMSave::from_game(self);
+        let mut file = std::fs::File::create(path)
+            .map(std::io::BufWriter::new)
+            .map_err(|e| gml::Error::FunctionError("game_save".into(), format!("{}", e)))?;
+        // write magic number (0x21c in GM8)
+        file.write(&[0x1d, 0x02, 0x00, 0x00])
+            .map_err(|e| gml::Error::FunctionError("game_save".into(), format!("{}", e)))?;
+        bincode::serialize_into(&mut file, &save)
+            .map_err(|e| gml::Error::FunctionError("game_save".into(), format!("{}", e)))?;
+        file.flush().map_err(|e| gml::Error::FunctionError("game_save".into(), e.to_string()))
+    }
+
+    pub fn load_gm_save(&mut self, path: PathBuf) -> Result<(), RuntimeError> {
         use std::io::Read;
         self.input.keyboard_clear_all();
         self.input.mouse_clear_all();
@@ -1755,10 +1962,9 @@ impl Game {
         let mut magnum = [0u8; 4];
         file.read(&mut magnum).map_err(|e| gml::Error::FunctionError("game_load".into(), format!("{}", e)))?;
         if magnum != [0x1d, 0x02, 0x00, 0x00] {
-            return Err(Box::new(gml::Error::FunctionError(
-                "game_load".into(),
-                "tried to load wrong version of save file".into(),
-            )))
+            return Err(
+                gml::Error::FunctionError("game_load".into(), "tried to load wrong version of save file".into()).into()
+            )
         }
         let save: GMSave = bincode::deserialize_from(file)
             .map_err(|e| gml::Error::FunctionError("game_load".into(), format!("{}", e)))?;
@@ -1767,19 +1973,43 @@ impl Game {
         Ok(Default::default())
     }
 
+    /// How much memory the game's sounds are taking up.
+    pub fn audio_memory(&self) -> audio::AudioMemory {
+        self.audio.memory(self.assets.sounds.iter().flatten().map(|x| &x.handle))
+    }
+
     /// Runs a frame loop and draws the screen. Exits immediately, without waiting for any FPS limitation.
     pub fn frame(&mut self) -> gml::Result<()> {
+        self.rand.log_frame();
+        self.game_log.next_frame();
+        let current_time = self.spoofed_time_nanos.unwrap_or_else(gml::datetime::now_as_nanos);
+        self.audio.check_output(current_time);
+        self.audio.start_pending(self.assets.sounds.iter().flatten().map(|x| &x.handle), current_time);
+
+        // GM8 handles the close button between frames, so its event runs before anything else in the frame
+        if self.close_button_pending {
+            self.close_button_pending = false;
+            self.run_object_event(ev::OTHER, 30, None)?; // close button event
+            if self.scene_change.is_some() {
+                return Ok(())
+            }
+        }
+
         if self.esc_close_game && self.input.keyboard_lastkey() == input::Button::Escape as u8 {
             self.scene_change = Some(SceneChange::End);
             return Ok(())
         }
 
-        // Update xprevious and yprevious for all instances
+        // The built-in save key doesn't take the keypress away from the game, so its own F5 events still run after
+        if self.f5_save_f6_load && self.input.keyboard_check_pressed(input::Button::F5 as u8) {
+            self.save_gm_save(SAVE_FILE_NAME.into())?;
+        }
+        self.check_screenshot_key();
+
+        // Update xprevious and yprevious for all instances, before anything gets a chance to move them
         let mut iter = self.room.instance_list.iter_by_drawing();
         while let Some(instance) = iter.next(&self.room.instance_list).map(|x| self.room.instance_list.get(x)) {
-            instance.xprevious.set(instance.x.get());
-            instance.yprevious.set(instance.y.get());
-            instance.path_positionprevious.set(instance.path_position.get().clamp(0.into(), 1.into()));
+            movement::save_previous(instance);
         }
 
         // Begin step trigger events
@@ -1888,7 +2118,13 @@ impl Game {
             return Ok(())
         }
 
-        // Movement: apply friction, gravity, and hspeed/vspeed
+        // No more lives and no more health events
+        self.run_ran_out_events()?;
+        if self.scene_change.is_some() {
+            return Ok(())
+        }
+
+        // Movement: apply friction and gravity to everything first, then hspeed/vspeed and paths per instance
         self.process_speeds();
         let mut iter = self.room.instance_list.iter_by_drawing();
         while let Some(handle) = iter.next(&self.room.instance_list) {
@@ -1941,49 +2177,81 @@ impl Game {
         let mut iter = self.room.instance_list.iter_by_drawing();
         while let Some(handle) = iter.next(&self.room.instance_list) {
             let instance = self.room.instance_list.get(handle);
-            let new_index = instance.image_index.get() + instance.image_speed.get();
+            let frame_count = self.assets.sprites.get_asset(instance.sprite_index.get()).map(|x| x.frames.len());
+            let (new_index, ended) = advance_image(instance.image_index.get(), instance.image_speed.get(), frame_count);
             instance.image_index.set(new_index);
-            if let Some(sprite) = self.assets.sprites.get_asset(instance.sprite_index.get()) {
-                let frame_count = sprite.frames.len() as f64;
-                if new_index.into_inner() >= frame_count {
-                    instance.image_index.set(new_index - Real::from(frame_count));
-                    self.run_instance_event(ev::OTHER, 7, handle, handle, None)?; // animation end event
-                }
+            if ended {
+                self.run_instance_event(ev::OTHER, 7, handle, handle, None)?; // animation end event
             }
         }
         self.cursor_sprite_frame += 1;
 
+        // The built-in load key waits until the end of the frame, so the game's own F6 events have seen it by then
+        if self.f5_save_f6_load
+            && self.input.keyboard_check_pressed(input::Button::F6 as u8)
+            && std::path::Path::new(SAVE_FILE_NAME).is_file()
+        {
+            self.scene_change = Some(SceneChange::Load(SAVE_FILE_NAME.into()));
+        }
+
         // Clear inputs for this frame
         self.input.step();
 
         Ok(())
     }
 
+    /// Clicks the window's close button. If the game treats it as Esc, that's a press and release of Esc.
+    /// Otherwise the close button event will run at the start of the next frame, and it's up to that to end the game.
+    pub fn press_close_button(&mut self) {
+        if self.treat_close_as_esc {
+            self.input.button_press(input::Button::Escape as u8, true);
+            self.input.button_release(input::Button::Escape as u8, true);
+        } else {
+            self.close_button_pending = true;
+        }
+    }
+
     pub fn process_window_events(&mut self) {
         self.input.mouse_step();
-        self.window.swap_events();
+        let window = match &mut self.window {
+            Some(window) => window,
+            None => return,
+        };
+        window.swap_events();
         match self.play_type {
             PlayType::Normal => {
-                for event in self.window.events() {
+                let mut close_pressed = false;
+                for event in window.events() {
                     match event {
                         Event::KeyboardDown(key) => self.input.button_press(input::ramen2vk(*key), true),
                         Event::KeyboardUp(key) => self.input.button_release(input::ramen2vk(*key), true),
                         Event::MouseMove((point, scale)) => {
                             let (x, y) = point.as_physical(*scale);
                             if let (Ok(x), Ok(y)) = (i32::try_from(x), i32::try_from(y)) {
-                                self.input.mouse_move_to((x, y));
+                                let position = self.window_to_screen(x, y);
+                                self.input.mouse_move_to(position);
                             }
                         },
                         Event::MouseDown(button) => self.input.mouse_press(input::ramen2mb(*button), true),
                         Event::MouseUp(button) => self.input.mouse_release(input::ramen2mb(*button), true),
                         Event::MouseWheel(x) => self.input.mouse_scroll(*x),
                         Event::Resize((size, scale)) => self.window_inner_size = size.as_physical(*scale),
-                        Event::CloseRequest(_) => self.close_requested = true,
+                        Event::CloseRequest(_) => close_pressed = true,
                         _ => (),
                     }
                 }
+                if close_pressed {
+                    self.press_close_button();
+                }
+            },
+            _ => {
+                // the game's inputs come from elsewhere, so closing the window just means the user wants out
+                for event in window.events() {
+                    if let Event::CloseRequest(_) = event {
+                        self.close_requested = true;
+                    }
+                }
             },
-            _ => (),
         }
     }
 
@@ -2013,14 +2281,16 @@ impl Game {
     }
 
     /// Starts the game, loading the first room. Does not need to be called immediately before loading a savestate.
-    pub fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn init(&mut self) -> Result<(), RuntimeError> {
         // Library initialization code
         for i in 0..self.library_init_strings.len() {
             let dummy_instance = self
                 .room
                 .instance_list
                 .insert_dummy(Instance::new_dummy(self.assets.objects.get_asset(0).map(|x| x.as_ref())));
-            let instructions = self.compiler.compile(&self.library_init_strings[i])?;
+            let instructions = self.compiler.compile(&self.library_init_strings[i]).map_err(|e| {
+                RuntimeError::Compile { location: format!("library initialization code {}", i), error: e.to_string() }
+            })?;
             self.execute(&instructions, &mut Context::with_single_instance(dummy_instance))?;
             self.room.instance_list.remove_dummy(dummy_instance);
         }
@@ -2060,10 +2330,11 @@ impl Game {
     }
 
     // Plays the game normally
-    pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn run(&mut self) -> Result<ExitReason, RuntimeError> {
         self.init()?;
         handle_scene_change!(self);
 
+        let mut pacer = FramePacer::default();
         let mut time_now = Instant::now();
         let mut time_last = time_now;
         loop {
@@ -2071,14 +2342,10 @@ impl Game {
 
             self.frame()?;
             handle_scene_change!(self);
-
-            // Exit if the window was closed by the user, such as by pressing 'X'
-            if self.close_requested {
-                break Ok(self.run_game_end_events()?)
-            }
+            #[cfg(feature = "metrics")]
+            self.publish_metrics();
 
             // frame limiter
-            let diff = Instant::now().duration_since(time_now);
             let duration = Duration::new(0, 1_000_000_000u32 / self.room.speed);
             if let Some(t) = self.spoofed_time_nanos.as_mut() {
                 *t += duration.as_nanos();
@@ -2093,18 +2360,39 @@ impl Game {
             }
             self.frame_counter += 1;
 
-            if let (Some(time), true) = (duration.checked_sub(diff), self.frame_limiter) {
-                gml::datetime::sleep(time);
-                time_now += duration;
+            time_now = if self.frame_limiter {
+                pacer.wait(&self.renderer, self.window.as_ref(), time_now, duration, self.room.speed)
             } else {
-                time_now = Instant::now();
+                Instant::now()
+            };
+            if let Some(graph) = &mut self.frame_time_graph {
+                graph.tick();
+                self.renderer.set_overlay(graph.rects(duration));
             }
         }
     }
 
+    /// Identifies this game, so replays can tell if they're played against a different one than they were made with.
+    pub fn game_info(&self) -> replay::GameInfo {
+        replay::GameInfo { game_id: self.game_id, gamedata_hash: self.gamedata_hash }
+    }
+
     // Replays some recorded inputs to the game
-    pub fn replay(mut self, replay: Replay, output_bin: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    // If it was launched for normal play, the replay is an intro (like a bundled game's), and the player takes over
+    // once it runs out
+    pub fn replay(&mut self, replay: Replay, output_bin: Option<PathBuf>) -> Result<ExitReason, RuntimeError> {
         let mut frame_count: usize = 0;
+        let intro = self.play_type == PlayType::Normal;
+        if intro {
+            self.play_type = PlayType::Replay;
+        }
+        if let Some(recorded) = replay.game.filter(|&x| x != self.game_info()) {
+            eprintln!(
+                "warning: this replay was recorded against a different exe (game ID {}, gamedata hash {:016x}) \
+                 than this one (game ID {}, gamedata hash {:016x}), so it may not play back the same",
+                recorded.game_id, recorded.gamedata_hash, self.game_id, self.gamedata_hash,
+            );
+        }
         self.rand.set_seed(replay.start_seed);
         self.spoofed_time_nanos = Some(replay.start_time);
 
@@ -2120,18 +2408,16 @@ impl Game {
         self.init()?;
         handle_scene_change!(self);
 
+        let mut pacer = FramePacer::default();
         let mut time_now = Instant::now();
         loop {
-            self.window.swap_events();
-            self.input.mouse_step();
+            self.process_window_events();
             if let Some(frame) = replay.get_frame(frame_count) {
                 if !self.stored_events.is_empty() {
-                    return Err(format!(
-                        "ERROR: {} stored events remaining at beginning of frame {}; aborting",
-                        self.stored_events.len(),
-                        frame_count,
-                    )
-                    .into())
+                    return Err(RuntimeError::StoredEventsRemaining {
+                        count: self.stored_events.len(),
+                        frame: frame_count,
+                    })
                 }
 
                 for ev in frame.events.iter() {
@@ -2155,28 +2441,44 @@ impl Game {
                         replay::Input::MouseRelease(b) => self.input.mouse_release(*b as i8, true),
                         replay::Input::MouseWheelUp => self.input.mouse_scroll_up(),
                         replay::Input::MouseWheelDown => self.input.mouse_scroll_down(),
+                        replay::Input::CloseButton => self.press_close_button(),
                     }
                 }
             } else if let Some(bin) = &output_bin {
                 let render_state = self.renderer.state();
-                match SaveState::from(&mut self, replay.clone(), render_state)
+                match SaveState::from(self, replay.clone(), render_state)
                     .save_to_file(bin, &mut savestate::Buffer::new())
                 {
-                    Ok(()) => break Ok(()),
-                    Err(e) => break Err(format!("Error saving to {:?}: {:?}", output_bin, e).into()),
+                    Ok(()) => break Ok(ExitReason::ReplaySaved),
+                    Err(e) => break Err(RuntimeError::ReplaySave { path: bin.clone(), error: format!("{:?}", e) }),
                 }
+            } else if self.window.is_none() {
+                // nobody can watch what happens after the inputs run out when there's no window
+                break Ok(ExitReason::ReplayFinished)
+            } else if intro && self.play_type == PlayType::Replay {
+                // the clock carries on from the replay's, so the game doesn't see time jump
+                self.play_type = PlayType::Normal;
             }
 
             self.frame()?;
             handle_scene_change!(self);
+            #[cfg(feature = "metrics")]
+            self.publish_metrics();
+
+            if self.frame_dumper.as_ref().map_or(false, |x| x.is_due(frame_count)) {
+                let image = self.screen_image();
+                if let Some(dumper) = &self.frame_dumper {
+                    dumper.dump(frame_count, image);
+                }
+            }
 
-            // exit if X pressed or game_end() invoked
+            // exit if X pressed
             if self.close_requested {
-                break Ok(self.run_game_end_events()?)
+                self.run_game_end_events()?;
+                break Ok(ExitReason::WindowClosed)
             }
 
             // frame limiter
-            let diff = Instant::now().duration_since(time_now);
             let duration = Duration::new(0, 1_000_000_000u32 / self.room.speed);
             if let Some(t) = self.spoofed_time_nanos.as_mut() {
                 *t += duration.as_nanos();
@@ -2188,17 +2490,28 @@ impl Game {
             }
             self.frame_counter += 1;
 
-            if let (Some(time), true) = (duration.checked_sub(diff), self.frame_limiter) {
-                gml::datetime::sleep(time);
-                time_now += duration;
+            time_now = if self.frame_limiter {
+                pacer.wait(&self.renderer, self.window.as_ref(), time_now, duration, self.room.speed)
             } else {
-                time_now = Instant::now();
+                Instant::now()
+            };
+            if let Some(graph) = &mut self.frame_time_graph {
+                graph.tick();
+                self.renderer.set_overlay(graph.rects(duration));
             }
 
             frame_count += 1;
         }
     }
 
+    // Converts a point in the window to a point on the game's screen, undoing window scaling and black bars.
+    // The input state only ever holds screen positions, so replays don't depend on the size of the window.
+    pub fn window_to_screen(&self, x: i32, y: i32) -> (i32, i32) {
+        let fb_size = (self.unscaled_width as i32, self.unscaled_height as i32);
+        let window_size = (self.window_inner_size.0 as i32, self.window_inner_size.1 as i32);
+        self.scaling.window_to_screen((x, y), fb_size, window_size)
+    }
+
     // Gets the mouse position in room coordinates
     pub fn get_mouse_in_room(&self) -> (i32, i32) {
         let (x, y) = (self.input.mouse_x(), self.input.mouse_y());
@@ -2697,29 +3010,7 @@ impl Game {
             let x2 = x2.round().to_i32();
             let y2 = y2.round().to_i32();
 
-            // Set up the iterator
-            let iter_vert = (x2 - x1).abs() < (y2 - y1).abs();
-            let point_count = (if iter_vert { y2 - y1 } else { x2 - x1 }) + 1;
-            // If iterating vertically, make sure we're going top to bottom
-            let (x1, y1, x2, y2) = if iter_vert && y2 < y1 { (x2, y2, x1, y1) } else { (x1, y1, x2, y2) };
-            // Helper function for getting points on the line
-            let get_point = |i: i32| {
-                // Avoid dividing by zero
-                if point_count == 1 {
-                    return (Real::from(x1), Real::from(y1))
-                }
-                if iter_vert {
-                    let slope = Real::from(x2 - x1) / Real::from(y2 - y1);
-                    (Real::from(x1) + Real::from(i) * slope, Real::from(y1 + i))
-                } else {
-                    let slope = Real::from(y2 - y1) / Real::from(x2 - x1);
-                    (Real::from(x1 + i), Real::from(y1) + Real::from(i) * slope)
-                }
-            };
-
-            for i in 0..point_count {
-                let (mut x, mut y) = get_point(i);
-
+            for (mut x, mut y) in util::line_points(x1, y1, x2, y2) {
                 // Transform point to be relative to collider
                 x -= inst_x.into();
                 y -= inst_y.into();
@@ -2769,6 +3060,36 @@ impl Game {
         None
     }
 
+    // Runs a collision check with an instance temporarily moved to the given position, as place_free etc. do.
+    // GM8 doesn't round the position itself here, only the corners of the resulting bbox (see update_bbox).
+    // The instance's position and bbox are put back exactly as they were afterwards, so nothing needs recalculating.
+    pub fn check_collision_at<T>(&self, inst: usize, x: Real, y: Real, check: impl FnOnce(&Self) -> T) -> T {
+        let instance = self.room.instance_list.get(inst);
+        let old_pos = (instance.x.get(), instance.y.get());
+        let old_bbox = (
+            instance.bbox_left.get(),
+            instance.bbox_right.get(),
+            instance.bbox_top.get(),
+            instance.bbox_bottom.get(),
+            instance.bbox_is_stale.get(),
+        );
+        instance.x.set(x);
+        instance.y.set(y);
+        instance.bbox_is_stale.set(true);
+
+        let result = check(self);
+
+        let instance = self.room.instance_list.get(inst);
+        instance.x.set(old_pos.0);
+        instance.y.set(old_pos.1);
+        instance.bbox_left.set(old_bbox.0);
+        instance.bbox_right.set(old_bbox.1);
+        instance.bbox_top.set(old_bbox.2);
+        instance.bbox_bottom.set(old_bbox.3);
+        instance.bbox_is_stale.set(old_bbox.4);
+        result
+    }
+
     /// Finds an instance that matches the predicate.
     /// `object_id` can be -3 for `all`, an object ID, or an instance ID.
     /// The predicate should take an instance handle as an argument, and return true if it matches.
@@ -2826,3 +3147,23 @@ impl<T> GetAsset<T> for Vec<Option<T>> {
         self.get_mut(usize::try_from(index).ok()?)?.as_mut()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::testing::{self, set_event};
+
+    #[test]
+    fn swap_creation_events() {
+        // the room's instance has creation code which adds a 2 to the score, and its create event adds a 1
+        for &(swap, score) in &[(false, 21), (true, 12)] {
+            let mut assets = testing::synthetic_assets();
+            assets.settings.swap_creation_events = swap;
+            assets.rooms[0].as_mut().unwrap().instances[0].creation_code = "score = score * 10 + 2".into();
+            let mut game = testing::launch(assets).unwrap();
+            set_event(&mut game, 0, ev::CREATE, 0, "score = score * 10 + 1");
+            game.init().unwrap();
+            assert_eq!(game.score, score, "swap_creation_events = {}", swap);
+        }
+    }
+}
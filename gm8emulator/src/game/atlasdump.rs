@@ -0,0 +1,142 @@
+//! Writes out every texture page along with an index of which assets are drawn from where on them,
+//! for tracking down bleeding edges and wrong texture coordinates.
+//!
+//! The output is one `page<N>.png` per texture page and an `atlas.json` listing each asset's texture.
+//! Assets are looked up when the dump is made, so anything created at runtime is included too.
+
+use crate::{game::Game, render::atlas::AtlasRef};
+use serde::Serialize;
+use std::{fs, path::Path};
+
+/// Where one asset's texture is.
+pub struct Entry {
+    pub atlas_ref: AtlasRef,
+    pub page: u32,
+    pub bounds: (i32, i32, i32, i32),
+    pub owner: String,
+}
+
+/// A texture page which was written to disk.
+#[derive(Serialize)]
+pub struct Page {
+    pub file: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize)]
+struct Index<'a> {
+    pages: &'a [Option<Page>],
+    textures: Vec<Texture<'a>>,
+}
+
+#[derive(Serialize)]
+struct Texture<'a> {
+    id: i32,
+    page: u32,
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    owner: &'a str,
+}
+
+/// Builds the JSON index. Pages which don't exist any more are written as null so that indices still line up.
+pub fn index_json(pages: &[Option<Page>], entries: &[Entry]) -> String {
+    let textures = entries
+        .iter()
+        .map(|entry| {
+            let (x, y, w, h) = entry.bounds;
+            Texture { id: entry.atlas_ref.0, page: entry.page, x, y, w, h, owner: &entry.owner }
+        })
+        .collect();
+    serde_json::to_string_pretty(&Index { pages, textures }).expect("couldn't serialize the atlas index") + "\n"
+}
+
+impl Game {
+    /// Finds the texture of every sprite frame, background, font character and surface.
+    pub fn atlas_entries(&self) -> Vec<Entry> {
+        let mut owners: Vec<(AtlasRef, String)> = Vec::new();
+        for (i, sprite) in self.assets.sprites.iter().enumerate() {
+            if let Some(sprite) = sprite {
+                let name = self.decode_str(sprite.name.as_ref());
+                for (j, frame) in sprite.frames.iter().enumerate() {
+                    owners.push((frame.atlas_ref, format!("sprite {} ({}) frame {}", i, name, j)));
+                }
+            }
+        }
+        for (i, background) in self.assets.backgrounds.iter().enumerate() {
+            if let Some(background) = background {
+                if let Some(atlas_ref) = background.atlas_ref {
+                    let name = self.decode_str(background.name.as_ref());
+                    owners.push((atlas_ref, format!("background {} ({})", i, name)));
+                }
+            }
+        }
+        for (i, font) in self.assets.fonts.iter().enumerate() {
+            if let Some(font) = font {
+                let name = self.decode_str(font.name.as_ref());
+                for (j, character) in font.chars.iter().enumerate() {
+                    let code = usize::from(font.first) + j;
+                    owners.push((character.atlas_ref, format!("font {} ({}) character {}", i, name, code)));
+                }
+            }
+        }
+        for (i, surface) in self.surfaces.iter().enumerate() {
+            if let Some(surface) = surface {
+                owners.push((surface.atlas_ref, format!("surface {}", i)));
+            }
+        }
+
+        owners
+            .into_iter()
+            .filter_map(|(atlas_ref, owner)| {
+                let rect = self.renderer.get_rect(atlas_ref)?;
+                Some(Entry { atlas_ref, page: rect.page(), bounds: rect.bounds(), owner })
+            })
+            .collect()
+    }
+
+    /// Writes every texture page and the index into the given directory, returning how many pages there were.
+    pub fn dump_atlas(&self, dir: &Path) -> Result<usize, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+        let mut pages = Vec::new();
+        for (i, image) in self.renderer.dump_atlas_pages().into_iter().enumerate() {
+            pages.push(match image {
+                Some(image) => {
+                    let file = format!("page{}.png", i);
+                    image.save(dir.join(&file)).map_err(|e| format!("couldn't write {}: {}", file, e))?;
+                    Some(Page { file, width: image.width(), height: image.height() })
+                },
+                None => None,
+            });
+        }
+        let index = index_json(&pages, &self.atlas_entries());
+        fs::write(dir.join("atlas.json"), index).map_err(|e| format!("couldn't write atlas.json: {}", e))?;
+        Ok(pages.iter().flatten().count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index() {
+        let pages = [Some(Page { file: "page0.png".into(), width: 256, height: 128 }), None];
+        let owner = "sprite 0 (\"a\\b\")".into();
+        let entries = [Entry { atlas_ref: AtlasRef(3), page: 0, bounds: (16, 0, 32, 24), owner }];
+        let json: serde_json::Value = serde_json::from_str(&index_json(&pages, &entries)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "pages": [{ "file": "page0.png", "width": 256, "height": 128 }, null],
+                "textures": [
+                    { "id": 3, "page": 0, "x": 16, "y": 0, "w": 32, "h": 24, "owner": "sprite 0 (\"a\\b\")" },
+                ],
+            })
+        );
+        let empty: serde_json::Value = serde_json::from_str(&index_json(&[], &[])).unwrap();
+        assert_eq!(empty, serde_json::json!({ "pages": [], "textures": [] }));
+    }
+}
@@ -1,24 +1,30 @@
+mod midi;
 mod mixer;
 mod mp3;
 
+use crate::asset::sound::FileType;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    mem,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc,
     },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 use udon::{
     cycle::Cycle,
     rechanneler::Rechanneler,
     resampler::Resampler,
     session::{Api, Session},
-    source::{ChannelCount, SampleRate, Source},
+    source::{ChannelCount, Sample, SampleRate, Source},
     wav::WavPlayer,
 };
 
 use self::{
+    midi::MidiPlayer,
     mixer::{Mixer, MixerHandle},
     mp3::Mp3Player,
 };
@@ -29,56 +35,512 @@ pub struct Mp3Handle {
     id: i32,
 }
 
+/// A MIDI file, which GM8 always plays as background music like an MP3.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MidiHandle {
+    player: MidiPlayer,
+    id: i32,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct WavHandle {
     player: WavPlayer,
     params: Arc<SoundParams>,
     _use_3d: bool,
     exclusive: bool,
+    size: usize,
+    id: i32,
+}
+
+// A WavHandle as savestates stored it before it kept the file's size
+#[derive(Deserialize)]
+pub struct WavHandleV1 {
+    player: WavPlayer,
+    params: Arc<SoundParams>,
+    _use_3d: bool,
+    exclusive: bool,
+    id: i32,
+}
+
+impl From<WavHandleV1> for WavHandle {
+    fn from(old: WavHandleV1) -> Self {
+        // the file is gone by now, so this sound is left out of the memory figures
+        let WavHandleV1 { player, params, _use_3d, exclusive, id } = old;
+        Self { player, params, _use_3d, exclusive, size: 0, id }
+    }
+}
+
+/// A sound with preload turned off. Only its file is kept until it's first played, when it gets decoded on another
+/// thread. It starts at the next frame boundary, so how long decoding takes can't change anything the game sees.
+/// The file is checked when it's added, so an invalid one is turned away just like it would be with preload on.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LazyHandle {
+    file: Arc<[u8]>,
+    kind: LazyKind,
     id: i32,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+enum LazyKind {
+    Mp3,
+    Wav { params: Arc<SoundParams>, use_3d: bool, exclusive: bool },
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SoundParams {
     pub volume: AtomicU32,
 }
 
+/// Keeps track of when each sound finishes, worked out from when it started and how long it is.
+/// This never asks the mixer, so sound_isplaying is the same with or without audio output, and in replays.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Playback {
+    // None means looping
+    end_times: HashMap<i32, Option<u128>>,
+    multimedia_end: Option<(i32, Option<u128>)>,
+    /// Sounds with preload turned off which have been played since the last frame boundary.
+    /// Their lengths aren't known until they're decoded, so until then they count as playing.
+    pending: Vec<PendingPlay>,
+}
+
+/// A sound with preload turned off which was played, kept by its id so savestates don't copy its file.
+#[derive(Clone, Serialize, Deserialize)]
+struct PendingPlay {
+    id: i32,
+    start_time: u128,
+    looping: bool,
+    exclusive: bool,
+}
+
+impl Playback {
+    fn play(&mut self, id: i32, start_time: u128, end_time: u128, exclusive: bool) {
+        self.prune(start_time);
+        if exclusive {
+            self.multimedia_end = Some((id, Some(end_time)));
+            self.pending.retain(|x| !x.exclusive);
+        } else if self.end_times.get(&id) != Some(&None) {
+            self.end_times.insert(id, Some(end_time));
+        }
+    }
+
+    fn play_looping(&mut self, id: i32, exclusive: bool) {
+        if exclusive {
+            self.multimedia_end = Some((id, None));
+            self.pending.retain(|x| !x.exclusive);
+        } else {
+            self.end_times.insert(id, None);
+        }
+    }
+
+    /// Plays a sound with preload turned off once it's been decoded, which happens at the next frame boundary.
+    fn defer(&mut self, play: PendingPlay) {
+        if play.exclusive {
+            self.multimedia_end = None;
+            self.pending.retain(|x| !x.exclusive);
+        }
+        self.pending.push(play);
+    }
+
+    fn stop(&mut self, id: i32) {
+        self.end_times.remove(&id);
+        if self.multimedia_end.map(|(x, _)| x) == Some(id) {
+            self.multimedia_end = None;
+        }
+        self.pending.retain(|x| x.id != id);
+    }
+
+    fn stop_all(&mut self) {
+        self.end_times.clear();
+        self.multimedia_end = None;
+        self.pending.clear();
+    }
+
+    fn is_playing(&self, id: i32, current_time: u128) -> bool {
+        let playing = |end_time: Option<u128>| end_time.map(|x| x > current_time).unwrap_or(true);
+        self.multimedia_end.map(|(x, end_time)| x == id && playing(end_time)).unwrap_or(false)
+            || self.end_times.get(&id).map(|&end_time| playing(end_time)).unwrap_or(false)
+            || self.pending.iter().any(|x| x.id == id)
+    }
+
+    /// Forgets about sounds which have finished, so they don't pile up in savestates.
+    fn prune(&mut self, current_time: u128) {
+        self.end_times.retain(|_, end_time| end_time.map(|x| x > current_time).unwrap_or(true));
+        if let Some((_, Some(end_time))) = self.multimedia_end {
+            if end_time <= current_time {
+                self.multimedia_end = None;
+            }
+        }
+    }
+}
+
+/// How long to wait between attempts at reopening the output device after it stops working.
+const REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a sound with preload turned off has to go unplayed before it can be evicted, in nanoseconds of game time.
+const EVICT_AFTER: u128 = 5 * 60 * 1_000_000_000;
+
+/// Somewhere for the mixer to play to. This is udon, except in tests.
+trait Backend {
+    /// The names of the output devices which are available right now.
+    fn devices(&mut self) -> Vec<String>;
+
+    /// Opens the output device with the given name, or whichever one is the default right now if it's None or
+    /// isn't available. Returns None if there's no device at all.
+    fn open(&mut self, device: Option<&str>) -> Option<Output>;
+}
+
+/// An output device which is ready to play.
+struct Output {
+    sample_rate: SampleRate,
+    channel_count: ChannelCount,
+    /// Plays the mixer, only returning once the device has stopped working.
+    play: Box<dyn FnOnce(Mixer) + Send>,
+}
+
+struct Udon;
+
+impl Backend for Udon {
+    fn devices(&mut self) -> Vec<String> {
+        Session::new(Api::Wasapi)
+            .ok()
+            .and_then(|session| session.output_devices().ok())
+            .map(|devices| devices.iter().map(|x| x.name().to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    fn open(&mut self, device: Option<&str>) -> Option<Output> {
+        let session = Session::new(Api::Wasapi).ok()?;
+        let named = device.and_then(|name| session.output_devices().ok()?.into_iter().find(|x| x.name() == name));
+        let device = match named {
+            Some(device) => device,
+            None => session.default_output_device().ok()?,
+        };
+        Some(Output {
+            sample_rate: device.sample_rate(),
+            channel_count: device.channel_count(),
+            play: Box::new(move |mixer| {
+                if let Ok(stream) = session.open_output_stream(device) {
+                    let _ = stream.play(mixer);
+                }
+            }),
+        })
+    }
+}
+
+/// For when there's no audio device at all, such as when running headless.
+struct NoOutput;
+
+impl Backend for NoOutput {
+    fn devices(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn open(&mut self, _device: Option<&str>) -> Option<Output> {
+        None
+    }
+}
+
+/// The mixer currently playing on an output device.
+struct MixerOutput {
+    handle: MixerHandle,
+    channel_count: ChannelCount,
+    sample_rate: SampleRate,
+    /// Set once the device stops working, such as when headphones get unplugged.
+    lost: Arc<AtomicBool>,
+}
+
+/// Sets a flag when dropped, so it gets set even if the output thread panics.
+struct SetOnDrop(Arc<AtomicBool>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+impl MixerOutput {
+    /// Adds a sound to the mixer, starting `elapsed` nanoseconds in.
+    fn add(
+        &self,
+        player: impl Source + Send + 'static,
+        params: Option<Arc<SoundParams>>,
+        id: i32,
+        looping: bool,
+        elapsed: u128,
+    ) {
+        let source = Rechanneler::new(Resampler::new(player, self.sample_rate), self.channel_count);
+        let rate: u32 = self.sample_rate.into();
+        let channels: u16 = self.channel_count.into();
+        let skip = elapsed * u128::from(rate) / 1_000_000_000 * u128::from(channels);
+        if looping {
+            self.send(Cycle::new(source), params, id, skip);
+        } else {
+            self.send(source, params, id, skip);
+        }
+    }
+
+    fn send(&self, mut source: impl Source + Send + 'static, params: Option<Arc<SoundParams>>, id: i32, skip: u128) {
+        let mut buffer = [0.0 as Sample; 4096];
+        let mut remaining = skip;
+        while remaining > 0 {
+            let count = remaining.min(buffer.len() as u128) as usize;
+            if source.write_samples(&mut buffer[..count]) < count {
+                return
+            }
+            remaining -= count as u128;
+        }
+        let _ = match params {
+            Some(params) => self.handle.add(source, params, id),
+            None => self.handle.add_exclusive(source, id),
+        };
+    }
+}
+
+#[derive(Clone)]
+enum SoundHandle {
+    Midi(MidiHandle),
+    Mp3(Mp3Handle),
+    Wav(WavHandle),
+}
+
+impl SoundHandle {
+    fn length(&self) -> u128 {
+        match self {
+            SoundHandle::Midi(handle) => handle.length(),
+            SoundHandle::Mp3(handle) => handle.length(),
+            SoundHandle::Wav(handle) => handle.length(),
+        }
+    }
+
+    fn memory(&self) -> usize {
+        match self {
+            SoundHandle::Midi(handle) => handle.player.memory(),
+            SoundHandle::Mp3(handle) => handle.player.memory(),
+            SoundHandle::Wav(handle) => handle.size,
+        }
+    }
+}
+
+/// A sound with preload turned off which has been decoded, kept until it's evicted or discarded.
+struct DecodedSound {
+    file: Arc<[u8]>,
+    id: i32,
+    handle: SoundHandle,
+    last_played: u128,
+}
+
+/// How much memory sounds are taking up, in bytes.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct AudioMemory {
+    /// Sounds with preload turned on, which are all decoded at startup.
+    pub preloaded: usize,
+    /// The files of sounds with preload turned off, which are kept whether they're decoded or not.
+    pub compressed: usize,
+    /// Sounds with preload turned off which are currently decoded.
+    pub decoded: usize,
+    pub decoded_count: usize,
+}
+
+impl AudioMemory {
+    pub fn total(&self) -> usize {
+        self.preloaded + self.compressed + self.decoded
+    }
+}
+
+/// A sound which was sent to the mixer, kept so it can be sent to a new one if the output device changes,
+/// or started again at the right position when a savestate is loaded.
+#[derive(Clone)]
+struct ActiveSound {
+    handle: SoundHandle,
+    start_time: u128,
+    length: u128,
+    looping: bool,
+}
+
+impl ActiveSound {
+    fn id(&self) -> i32 {
+        match &self.handle {
+            SoundHandle::Midi(handle) => handle.id,
+            SoundHandle::Mp3(handle) => handle.id,
+            SoundHandle::Wav(handle) => handle.id,
+        }
+    }
+
+    fn saved(&self) -> SavedSound {
+        SavedSound { id: self.id(), start_time: self.start_time, length: self.length, looping: self.looping }
+    }
+}
+
+/// What savestates keep of an `ActiveSound`. The handle is taken from the game's sounds when the savestate is
+/// loaded, so the decoded sound isn't saved a second time.
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedSound {
+    id: i32,
+    start_time: u128,
+    length: u128,
+    looping: bool,
+}
+
+/// How far into a sound playback should be by `current_time`, or None if it's finished.
+fn resume_position(start_time: u128, length: u128, looping: bool, current_time: u128) -> Option<u128> {
+    let elapsed = current_time.saturating_sub(start_time);
+    if looping {
+        Some(if length == 0 { 0 } else { elapsed % length })
+    } else if elapsed < length {
+        Some(elapsed)
+    } else {
+        None
+    }
+}
+
 pub struct AudioManager {
-    mixer_handle: MixerHandle,
-    mixer_channel_count: ChannelCount,
-    mixer_sample_rate: SampleRate,
+    backend: Box<dyn Backend>,
+    /// The output device chosen with --audio-device or in record mode, or None for the default one.
+    device: Option<String>,
+    output: Option<MixerOutput>,
+    last_reopen: Instant,
     do_output: bool,
     global_volume: Arc<AtomicU32>,
-    end_times: HashMap<i32, Option<u128>>,
-    multimedia_end: Option<(i32, Option<u128>)>,
+    playback: Playback,
+    /// What's been sent to the mixer. None of this is visible to the game, which only sees `playback`.
+    active: Vec<ActiveSound>,
+    active_exclusive: Option<ActiveSound>,
+    decoded: Vec<DecodedSound>,
+    /// Sounds being decoded on other threads, which get waited for at the next frame boundary if they're needed.
+    decoding: Vec<(LazyHandle, JoinHandle<Option<SoundHandle>>)>,
+    /// Once decoded sounds take up more than this many bytes, ones which haven't been played for a while get evicted.
+    memory_budget: Option<usize>,
 }
 
 impl AudioManager {
     pub fn new(do_output: bool) -> Self {
-        // TODO: not all these unwraps
-        let session = Session::new(Api::Wasapi).unwrap();
-        let device = session.default_output_device().unwrap();
-        let sample_rate = device.sample_rate();
-        let channel_count = device.channel_count();
-        let global_volume = Arc::new(AtomicU32::from(1.0f32.to_bits()));
-        let (mixer, mixer_handle) = Mixer::new(sample_rate, channel_count, global_volume.clone());
+        let manager = Self::with_backend(do_output, Box::new(Udon));
+        if manager.output.is_none() {
+            eprintln!("Warning: no audio output device, sounds won't be heard until one is available");
+        }
+        manager
+    }
 
-        std::thread::spawn(move || {
-            let stream = session.open_output_stream(device).unwrap();
-            stream.play(mixer).unwrap();
-        });
+    /// Makes an audio manager which never plays anything, but still keeps track of what the game thinks is playing.
+    pub fn without_output() -> Self {
+        Self::with_backend(false, Box::new(NoOutput))
+    }
 
+    fn with_backend(do_output: bool, mut backend: Box<dyn Backend>) -> Self {
+        let global_volume = Arc::new(AtomicU32::from(1.0f32.to_bits()));
+        let output = Self::open_output(backend.as_mut(), None, &global_volume);
         Self {
-            mixer_handle,
-            mixer_channel_count: channel_count,
-            mixer_sample_rate: sample_rate,
+            backend,
+            device: None,
+            output,
+            last_reopen: Instant::now(),
             do_output,
             global_volume,
-            end_times: HashMap::new(),
-            multimedia_end: None,
+            playback: Playback::default(),
+            active: Vec::new(),
+            active_exclusive: None,
+            decoded: Vec::new(),
+            decoding: Vec::new(),
+            memory_budget: None,
+        }
+    }
+
+    /// Starts a new mixer playing on the given output device, or the current default one.
+    fn open_output(
+        backend: &mut dyn Backend,
+        device: Option<&str>,
+        global_volume: &Arc<AtomicU32>,
+    ) -> Option<MixerOutput> {
+        let output = backend.open(device)?;
+        let (mixer, handle) = Mixer::new(output.sample_rate, output.channel_count, global_volume.clone());
+        let lost = Arc::new(AtomicBool::new(false));
+        let guard = SetOnDrop(lost.clone());
+        let play = output.play;
+        thread::spawn(move || {
+            let _guard = guard;
+            play(mixer);
+        });
+        Some(MixerOutput { handle, channel_count: output.channel_count, sample_rate: output.sample_rate, lost })
+    }
+
+    /// The names of the output devices which are available right now.
+    pub fn devices(&mut self) -> Vec<String> {
+        self.backend.devices()
+    }
+
+    /// The output device that was chosen, or None if it's the default one.
+    pub fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
+    /// Moves playback to another output device, or the default one if it's None. Like when the device is lost,
+    /// whatever was playing carries on from about where it should be, and nothing the game can see changes.
+    pub fn set_device(&mut self, device: Option<String>, current_time: u128) {
+        if let Some(output) = self.output.take() {
+            let _ = output.handle.stop_all();
+        }
+        self.device = device;
+        self.reopen(current_time);
+    }
+
+    /// If the output device has stopped working, tries to open it again, or the current default one, and restarts
+    /// whatever was playing at about the position it should be at. This doesn't change anything the game can see.
+    pub fn check_output(&mut self, current_time: u128) {
+        let lost = self.output.as_ref().is_none_or(|x| x.lost.load(Ordering::Acquire));
+        if lost && self.last_reopen.elapsed() >= REOPEN_INTERVAL {
+            self.reopen(current_time);
+        }
+    }
+
+    fn reopen(&mut self, current_time: u128) {
+        self.last_reopen = Instant::now();
+        self.output = Self::open_output(self.backend.as_mut(), self.device.as_deref(), &self.global_volume);
+        if self.output.is_some() {
+            let sounds = self.active_exclusive.iter().chain(self.active.iter()).cloned().collect::<Vec<_>>();
+            for sound in sounds {
+                if let Some(elapsed) = resume_position(sound.start_time, sound.length, sound.looping, current_time) {
+                    self.start(&sound, elapsed);
+                }
+            }
+        }
+    }
+
+    /// Sends a sound to the mixer, starting `elapsed` nanoseconds in.
+    fn start(&self, sound: &ActiveSound, elapsed: u128) {
+        if let Some(output) = self.output.as_ref().filter(|_| self.do_output) {
+            match &sound.handle {
+                SoundHandle::Midi(handle) => output.add(handle.player.clone(), None, handle.id, sound.looping, elapsed),
+                SoundHandle::Mp3(handle) => output.add(handle.player.clone(), None, handle.id, sound.looping, elapsed),
+                SoundHandle::Wav(handle) => {
+                    let params = if handle.exclusive { None } else { Some(handle.params.clone()) };
+                    output.add(handle.player.clone(), params, handle.id, sound.looping, elapsed)
+                },
+            }
+        }
+    }
+
+    /// Keeps track of a sound and sends it to the mixer.
+    fn play(&mut self, handle: SoundHandle, start_time: u128, length: u128, looping: bool, exclusive: bool) {
+        let sound = ActiveSound { handle, start_time, length, looping };
+        self.start(&sound, 0);
+        self.track(sound, exclusive);
+    }
+
+    /// Keeps track of a sound which has been sent to the mixer.
+    fn track(&mut self, sound: ActiveSound, exclusive: bool) {
+        let start_time = sound.start_time;
+        self.active.retain(|x| resume_position(x.start_time, x.length, x.looping, start_time).is_some());
+        if exclusive {
+            self.active_exclusive = Some(sound);
+        } else {
+            self.active.push(sound);
         }
     }
 
+    pub fn add_midi(&mut self, file: Box<[u8]>, sound_id: i32) -> Option<MidiHandle> {
+        MidiPlayer::new(&file).map(|player| MidiHandle { player, id: sound_id }).ok()
+    }
+
     pub fn add_mp3(&mut self, file: Box<[u8]>, sound_id: i32) -> Option<Mp3Handle> {
         Mp3Player::new(file).map(|player| Mp3Handle { player, id: sound_id }).ok()
     }
@@ -91,126 +553,231 @@ impl AudioManager {
         use_3d: bool,
         exclusive: bool,
     ) -> Option<WavHandle> {
+        let size = file.len();
         WavPlayer::new(file)
             .map(|player| WavHandle {
                 player,
                 params: Arc::new(SoundParams { volume: AtomicU32::new(make_volume(volume).to_bits()) }),
                 _use_3d: use_3d,
                 exclusive,
+                size,
                 id: sound_id,
             })
             .ok()
     }
 
-    pub fn play_mp3(&mut self, handle: &Mp3Handle, start_time: u128) {
-        let end_time = length_to_ns(
-            handle.player.length(),
-            handle.player.sample_rate().into(),
-            1, // mp3 length() already takes channels into account
-        ) + start_time;
-        self.multimedia_end = Some((handle.id, Some(end_time)));
-        if self.do_output {
-            let _ = self.mixer_handle.add_exclusive(
-                Rechanneler::new(
-                    Resampler::new(handle.player.clone(), self.mixer_sample_rate),
-                    self.mixer_channel_count,
-                ),
-                handle.id,
-            );
+    pub fn add_lazy_mp3(&mut self, file: Box<[u8]>, sound_id: i32) -> Option<LazyHandle> {
+        Mp3Player::is_valid(&file).then(|| LazyHandle { file: file.into(), kind: LazyKind::Mp3, id: sound_id })
+    }
+
+    pub fn add_lazy_wav(
+        &mut self,
+        file: Box<[u8]>,
+        sound_id: i32,
+        volume: f64,
+        use_3d: bool,
+        exclusive: bool,
+    ) -> Option<LazyHandle> {
+        WavPlayer::new(file.clone()).ok()?;
+        let params = Arc::new(SoundParams { volume: AtomicU32::new(make_volume(volume).to_bits()) });
+        Some(LazyHandle { file: file.into(), kind: LazyKind::Wav { params, use_3d, exclusive }, id: sound_id })
+    }
+
+    /// Starts decoding a sound with preload turned off on another thread, unless it's already decoded.
+    pub fn prepare(&mut self, handle: &LazyHandle) {
+        if self.decoded.iter().any(|x| handle.is_file(x.id, &x.file))
+            || self.decoding.iter().any(|(x, _)| handle.is_file(x.id, &x.file))
+        {
+            return
         }
+        let lazy = handle.clone();
+        self.decoding.push((handle.clone(), thread::spawn(move || lazy.decode())));
     }
 
-    pub fn play_wav(&mut self, handle: &WavHandle, start_time: u128) {
-        let end_time = length_to_ns(
-            handle.player.length(),
-            handle.player.sample_rate().into(),
-            handle.player.channel_count().into(),
-        ) + start_time;
-        if handle.exclusive {
-            self.multimedia_end = Some((handle.id, Some(end_time)));
-        } else if self.end_times.get(&handle.id) != Some(&None) {
-            self.end_times.insert(handle.id, Some(end_time));
-        }
-
-        if self.do_output {
-            if handle.exclusive {
-                let _ = self.mixer_handle.add_exclusive(
-                    Rechanneler::new(
-                        Resampler::new(handle.player.clone(), self.mixer_sample_rate),
-                        self.mixer_channel_count,
-                    ),
-                    handle.id,
-                );
+    /// Throws away the decoded version of a sound with preload turned off, if there is one.
+    pub fn discard(&mut self, id: i32) {
+        self.decoded.retain(|x| x.id != id);
+        self.decoding.retain(|(x, _)| x.id != id);
+    }
+
+    /// Evicts decoded sounds which haven't been played for five minutes whenever they take up more than `budget` bytes.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+    }
+
+    pub fn play_lazy(&mut self, handle: &LazyHandle, start_time: u128) {
+        self.prepare(handle);
+        self.playback.defer(PendingPlay { id: handle.id, start_time, looping: false, exclusive: handle.exclusive() });
+    }
+
+    pub fn loop_lazy(&mut self, handle: &LazyHandle, start_time: u128) {
+        self.prepare(handle);
+        self.playback.defer(PendingPlay { id: handle.id, start_time, looping: true, exclusive: handle.exclusive() });
+    }
+
+    /// Starts any sounds with preload turned off which were played during the last frame, waiting for them to be
+    /// decoded if they aren't yet. They play as if they'd started when they were played, not now, and this always
+    /// happens between frames, so it doesn't matter how long decoding takes or whether it happened before.
+    /// `sounds` are the game's sounds, which the handles are looked up in.
+    pub fn start_pending<'a>(&mut self, sounds: impl IntoIterator<Item = &'a FileType>, current_time: u128) {
+        let pending = mem::take(&mut self.playback.pending);
+        let sounds = if pending.is_empty() { HashMap::new() } else { sounds_by_id(sounds) };
+        for pending in pending {
+            let handle = match sounds.get(&pending.id) {
+                Some(FileType::Lazy(lazy)) => match self.decoded_handle(lazy, current_time) {
+                    Some(handle) => handle,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let (id, start_time, length) = (pending.id, pending.start_time, handle.length());
+            let exclusive = pending.exclusive;
+            if pending.looping {
+                self.playback.play_looping(id, exclusive);
             } else {
-                let _ = self.mixer_handle.add(
-                    Rechanneler::new(
-                        Resampler::new(handle.player.clone(), self.mixer_sample_rate),
-                        self.mixer_channel_count,
-                    ),
-                    handle.params.clone(),
-                    handle.id,
-                );
+                self.playback.play(id, start_time, start_time + length, exclusive);
+            }
+            let sound = ActiveSound { handle, start_time, length, looping: pending.looping };
+            if let Some(elapsed) = resume_position(start_time, length, pending.looping, current_time) {
+                self.start(&sound, elapsed);
             }
+            self.track(sound, exclusive);
         }
+        self.evict(current_time);
     }
 
-    pub fn loop_mp3(&mut self, handle: &Mp3Handle) {
-        self.multimedia_end = Some((handle.id, None));
-        if self.do_output {
-            let _ = self.mixer_handle.add_exclusive(
-                Cycle::new(Rechanneler::new(
-                    Resampler::new(handle.player.clone(), self.mixer_sample_rate),
-                    self.mixer_channel_count,
-                )),
-                handle.id,
-            );
+    /// Gets the decoded version of a sound with preload turned off, decoding it now if that isn't already happening.
+    fn decoded_handle(&mut self, lazy: &LazyHandle, current_time: u128) -> Option<SoundHandle> {
+        let mut handle = match self.decoded.iter_mut().find(|x| lazy.is_file(x.id, &x.file)) {
+            Some(decoded) => {
+                decoded.last_played = current_time;
+                decoded.handle.clone()
+            },
+            None => {
+                let handle = match self.decoding.iter().position(|(x, _)| lazy.is_file(x.id, &x.file)) {
+                    Some(i) => self.decoding.remove(i).1.join().ok().flatten(),
+                    None => lazy.decode(),
+                };
+                let handle = match handle {
+                    Some(handle) => handle,
+                    None => {
+                        let kind = if matches!(lazy.kind, LazyKind::Mp3) { "mp3" } else { "wav" };
+                        println!("WARNING: invalid {} data in sound {}", kind, lazy.id);
+                        return None
+                    },
+                };
+                self.decoded.retain(|x| x.id != lazy.id);
+                self.decoded.push(DecodedSound {
+                    file: lazy.file.clone(),
+                    id: lazy.id,
+                    handle: handle.clone(),
+                    last_played: current_time,
+                });
+                handle
+            },
+        };
+        // sound_volume changes the handle the game has, which might not be the one this was decoded from
+        if let (SoundHandle::Wav(handle), LazyKind::Wav { params, .. }) = (&mut handle, &lazy.kind) {
+            handle.params = params.clone();
         }
+        Some(handle)
     }
 
-    pub fn loop_wav(&mut self, handle: &WavHandle) {
-        if handle.exclusive {
-            self.multimedia_end = Some((handle.id, None));
-        } else {
-            self.end_times.insert(handle.id, None);
-        }
-
-        if self.do_output {
-            if handle.exclusive {
-                let _ = self.mixer_handle.add_exclusive(
-                    Cycle::new(Rechanneler::new(
-                        Resampler::new(handle.player.clone(), self.mixer_sample_rate),
-                        self.mixer_channel_count,
-                    )),
-                    handle.id,
-                );
-            } else {
-                let _ = self.mixer_handle.add(
-                    Cycle::new(Rechanneler::new(
-                        Resampler::new(handle.player.clone(), self.mixer_sample_rate),
-                        self.mixer_channel_count,
-                    )),
-                    handle.params.clone(),
-                    handle.id,
-                );
+    /// If decoded sounds are over the memory budget, throws away the ones which have gone unplayed the longest
+    /// until they aren't, as long as they've gone unplayed for long enough and aren't still playing.
+    fn evict(&mut self, current_time: u128) {
+        let budget = match self.memory_budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        let mut total = self.decoded.iter().map(|x| x.handle.memory()).sum::<usize>();
+        if total <= budget {
+            return
+        }
+        let active = self
+            .active_exclusive
+            .iter()
+            .chain(self.active.iter())
+            .filter(|x| resume_position(x.start_time, x.length, x.looping, current_time).is_some())
+            .map(ActiveSound::id)
+            .collect::<Vec<_>>();
+        self.decoded.sort_by_key(|x| x.last_played);
+        self.decoded.retain(|x| {
+            if total <= budget || current_time.saturating_sub(x.last_played) < EVICT_AFTER || active.contains(&x.id) {
+                return true
+            }
+            total -= x.handle.memory();
+            false
+        });
+    }
+
+    /// Adds up how much memory the given sounds are taking up.
+    pub fn memory<'a>(&self, sounds: impl IntoIterator<Item = &'a FileType>) -> AudioMemory {
+        let mut memory = AudioMemory::default();
+        for sound in sounds {
+            match sound {
+                FileType::Midi(handle) => memory.preloaded += handle.player.memory(),
+                FileType::Mp3(handle) => memory.preloaded += handle.player.memory(),
+                FileType::Wav(handle) => memory.preloaded += handle.size,
+                FileType::Lazy(handle) => memory.compressed += handle.file.len(),
+                FileType::None => (),
             }
         }
+        memory.decoded = self.decoded.iter().map(|x| x.handle.memory()).sum();
+        memory.decoded_count = self.decoded.len();
+        memory
+    }
+
+    pub fn play_midi(&mut self, handle: &MidiHandle, start_time: u128) {
+        let length = handle.length();
+        self.playback.play(handle.id, start_time, start_time + length, true);
+        self.play(SoundHandle::Midi(handle.clone()), start_time, length, false, true);
+    }
+
+    pub fn play_mp3(&mut self, handle: &Mp3Handle, start_time: u128) {
+        let length = handle.length();
+        self.playback.play(handle.id, start_time, start_time + length, true);
+        self.play(SoundHandle::Mp3(handle.clone()), start_time, length, false, true);
+    }
+
+    pub fn play_wav(&mut self, handle: &WavHandle, start_time: u128) {
+        let length = handle.length();
+        self.playback.play(handle.id, start_time, start_time + length, handle.exclusive);
+        self.play(SoundHandle::Wav(handle.clone()), start_time, length, false, handle.exclusive);
+    }
+
+    pub fn loop_midi(&mut self, handle: &MidiHandle, start_time: u128) {
+        self.playback.play_looping(handle.id, true);
+        self.play(SoundHandle::Midi(handle.clone()), start_time, handle.length(), true, true);
+    }
+
+    pub fn loop_mp3(&mut self, handle: &Mp3Handle, start_time: u128) {
+        self.playback.play_looping(handle.id, true);
+        self.play(SoundHandle::Mp3(handle.clone()), start_time, handle.length(), true, true);
+    }
+
+    pub fn loop_wav(&mut self, handle: &WavHandle, start_time: u128) {
+        self.playback.play_looping(handle.id, handle.exclusive);
+        self.play(SoundHandle::Wav(handle.clone()), start_time, handle.length(), true, handle.exclusive);
     }
 
     pub fn stop_sound(&mut self, id: i32) {
-        self.end_times.remove(&id);
-        if self.multimedia_end.map(|(x, _)| x) == Some(id) {
-            self.multimedia_end = None;
+        self.playback.stop(id);
+        self.active.retain(|x| x.id() != id);
+        if self.active_exclusive.as_ref().map(ActiveSound::id) == Some(id) {
+            self.active_exclusive = None;
         }
-        if self.do_output {
-            let _ = self.mixer_handle.stop(id);
+        if let Some(output) = self.output.as_ref().filter(|_| self.do_output) {
+            let _ = output.handle.stop(id);
         }
     }
 
     pub fn stop_all(&mut self) {
-        self.end_times.clear();
-        self.multimedia_end = None;
-        if self.do_output {
-            let _ = self.mixer_handle.stop_all();
+        self.playback.stop_all();
+        self.active.clear();
+        self.active_exclusive = None;
+        if let Some(output) = self.output.as_ref().filter(|_| self.do_output) {
+            let _ = output.handle.stop_all();
         }
     }
 
@@ -219,35 +786,115 @@ impl AudioManager {
     }
 
     pub fn sound_playing(&self, sound_id: i32, current_time: u128) -> bool {
-        self.mp3_playing(sound_id, current_time) || self.wav_playing(sound_id, current_time)
+        self.playback.is_playing(sound_id, current_time)
     }
 
-    fn mp3_playing(&self, sound_id: i32, current_time: u128) -> bool {
-        self.multimedia_end
-            .map(|(id, end_time)| id == sound_id && end_time.map(|x| x > current_time).unwrap_or(true))
-            .unwrap_or(false)
+    pub fn state(&self) -> AudioState {
+        // the mixer reads the volume from our Arc, so savestates get a copy of the value rather than sharing it
+        let global_volume = Arc::new(AtomicU32::new(self.global_volume.load(Ordering::Acquire)));
+        AudioState {
+            global_volume,
+            playback: self.playback.clone(),
+            active: self.active.iter().map(ActiveSound::saved).collect(),
+            active_exclusive: self.active_exclusive.as_ref().map(ActiveSound::saved),
+        }
     }
 
-    fn wav_playing(&self, sound_id: i32, current_time: u128) -> bool {
-        match self.end_times.get(&sound_id) {
-            Some(&Some(x)) => x > current_time,
-            Some(None) => true,
-            None => false,
+    /// Loads a savestate, restarting whatever was playing in it at the position it should be at by `current_time`.
+    /// `sounds` are the game's sounds from the same savestate, which the restored sounds get their handles from.
+    pub fn set_state<'a>(
+        &mut self,
+        state: AudioState,
+        sounds: impl IntoIterator<Item = &'a FileType>,
+        current_time: u128,
+    ) {
+        self.global_volume.store(state.global_volume.load(Ordering::Acquire), Ordering::Release);
+        self.playback = state.playback;
+        if let Some(output) = self.output.as_ref().filter(|_| self.do_output) {
+            let _ = output.handle.stop_all();
+        }
+
+        let sounds = sounds_by_id(sounds);
+        let playing = |x: &SavedSound| resume_position(x.start_time, x.length, x.looping, current_time).is_some();
+        self.active = state
+            .active
+            .iter()
+            .filter(|x| playing(x))
+            .filter_map(|x| self.restore(x, sounds.get(&x.id)?, current_time))
+            .collect();
+        self.active_exclusive =
+            state.active_exclusive.filter(playing).and_then(|x| self.restore(&x, sounds.get(&x.id)?, current_time));
+        let sounds = self.active_exclusive.iter().chain(self.active.iter()).cloned().collect::<Vec<_>>();
+        for sound in sounds {
+            if let Some(elapsed) = resume_position(sound.start_time, sound.length, sound.looping, current_time) {
+                self.start(&sound, elapsed);
+            }
         }
     }
 
-    pub fn state(&self) -> AudioState {
-        AudioState {
-            global_volume: self.global_volume.clone(),
-            end_times: self.end_times.clone(),
-            multimedia_end: self.multimedia_end,
+    /// Gives a sound from a savestate back its handle, which the game's handles share their volume with.
+    fn restore(&mut self, saved: &SavedSound, sound: &FileType, current_time: u128) -> Option<ActiveSound> {
+        let handle = match sound {
+            FileType::Midi(handle) => SoundHandle::Midi(handle.clone()),
+            FileType::Mp3(handle) => SoundHandle::Mp3(handle.clone()),
+            FileType::Wav(handle) => SoundHandle::Wav(handle.clone()),
+            FileType::Lazy(handle) => self.decoded_handle(handle, current_time)?,
+            FileType::None => return None,
+        };
+        Some(ActiveSound { handle, start_time: saved.start_time, length: saved.length, looping: saved.looping })
+    }
+}
+
+impl MidiHandle {
+    fn length(&self) -> u128 {
+        length_to_ns(self.player.length(), midi::SAMPLE_RATE, 1)
+    }
+}
+
+impl Mp3Handle {
+    fn length(&self) -> u128 {
+        // mp3 length() already takes channels into account
+        length_to_ns(self.player.length(), self.player.sample_rate().into(), 1)
+    }
+}
+
+impl LazyHandle {
+    pub fn set_volume(&self, vol: f64) {
+        if let LazyKind::Wav { params, .. } = &self.kind {
+            params.volume.store(make_volume(vol).to_bits(), Ordering::Release);
         }
     }
 
-    pub fn set_state(&mut self, state: AudioState) {
-        self.global_volume = state.global_volume;
-        self.end_times = state.end_times;
-        self.multimedia_end = state.multimedia_end;
+    fn exclusive(&self) -> bool {
+        match &self.kind {
+            LazyKind::Mp3 => true,
+            LazyKind::Wav { exclusive, .. } => *exclusive,
+        }
+    }
+
+    /// Whether this is the given sound, with the same file. Savestates make copies of files, so this compares them.
+    fn is_file(&self, id: i32, file: &Arc<[u8]>) -> bool {
+        self.id == id && (Arc::ptr_eq(&self.file, file) || self.file == *file)
+    }
+
+    fn decode(&self) -> Option<SoundHandle> {
+        match &self.kind {
+            LazyKind::Mp3 => {
+                Mp3Player::new(&self.file[..]).ok().map(|player| SoundHandle::Mp3(Mp3Handle { player, id: self.id }))
+            },
+            LazyKind::Wav { params, use_3d, exclusive } => {
+                WavPlayer::new(Box::<[u8]>::from(&self.file[..])).ok().map(|player| {
+                    SoundHandle::Wav(WavHandle {
+                        player,
+                        params: params.clone(),
+                        _use_3d: *use_3d,
+                        exclusive: *exclusive,
+                        size: self.file.len(),
+                        id: self.id,
+                    })
+                })
+            },
+        }
     }
 }
 
@@ -255,15 +902,49 @@ impl WavHandle {
     pub fn set_volume(&self, vol: f64) {
         self.params.volume.store(make_volume(vol).to_bits(), Ordering::Release);
     }
+
+    fn length(&self) -> u128 {
+        length_to_ns(self.player.length(), self.player.sample_rate().into(), self.player.channel_count().into())
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct AudioState {
+    global_volume: Arc<AtomicU32>,
+    playback: Playback,
+    active: Vec<SavedSound>,
+    active_exclusive: Option<SavedSound>,
+}
+
+// An AudioState as savestates stored it before sounds were saved and resumed
+#[derive(Deserialize)]
+pub struct AudioStateV1 {
     global_volume: Arc<AtomicU32>,
     end_times: HashMap<i32, Option<u128>>,
     multimedia_end: Option<(i32, Option<u128>)>,
 }
 
+impl From<AudioStateV1> for AudioState {
+    fn from(old: AudioStateV1) -> Self {
+        let AudioStateV1 { global_volume, end_times, multimedia_end } = old;
+        let playback = Playback { end_times, multimedia_end, pending: Vec::new() };
+        Self { global_volume, playback, active: Vec::new(), active_exclusive: None }
+    }
+}
+
+fn sounds_by_id<'a>(sounds: impl IntoIterator<Item = &'a FileType>) -> HashMap<i32, &'a FileType> {
+    sounds
+        .into_iter()
+        .filter_map(|sound| match sound {
+            FileType::Midi(handle) => Some((handle.id, sound)),
+            FileType::Mp3(handle) => Some((handle.id, sound)),
+            FileType::Wav(handle) => Some((handle.id, sound)),
+            FileType::Lazy(handle) => Some((handle.id, sound)),
+            FileType::None => None,
+        })
+        .collect()
+}
+
 fn length_to_ns(sample_count: usize, sample_rate: u32, channels: u16) -> u128 {
     (sample_count as u128 * 1_000_000_000) / (u128::from(sample_rate) * u128::from(channels))
 }
@@ -274,3 +955,351 @@ fn length_to_ns(sample_count: usize, sample_rate: u32, channels: u16) -> u128 {
 fn make_volume(vol: f64) -> f32 {
     1000.0f64.powf(vol.clamp(0.0, 1.0) - 1.0) as f32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        length_to_ns, resume_position, ActiveSound, AudioManager, Backend, ChannelCount, FileType, Mixer, NoOutput,
+        Output, Playback, SampleRate, SoundHandle, Source, EVICT_AFTER, REOPEN_INTERVAL,
+    };
+    use std::{
+        sync::{atomic::Ordering, mpsc, Arc},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// A silent wav file, mono and 16-bit at 22050Hz.
+    fn wav(samples: u32) -> Box<[u8]> {
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(36 + samples * 2).to_le_bytes());
+        file.extend_from_slice(b"WAVEfmt ");
+        file.extend_from_slice(&16u32.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        file.extend_from_slice(&1u16.to_le_bytes()); // channels
+        file.extend_from_slice(&22050u32.to_le_bytes());
+        file.extend_from_slice(&44100u32.to_le_bytes()); // bytes per second
+        file.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+        file.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&(samples * 2).to_le_bytes());
+        file.resize(file.len() + samples as usize * 2, 0);
+        file.into_boxed_slice()
+    }
+
+    /// Like `wav`, but every sample is the same non-zero value, so it can be told apart from silence.
+    fn tone(samples: u32) -> Box<[u8]> {
+        let mut file = wav(samples);
+        file[44..].chunks_mut(2).for_each(|x| x.copy_from_slice(&0x2000i16.to_le_bytes()));
+        file
+    }
+
+    /// An output device opened by `MockBackend`. Dropping it unplugs the device.
+    struct MockDevice {
+        name: Option<String>,
+        mixer: Mixer,
+        _plugged_in: mpsc::Sender<()>,
+    }
+
+    impl MockDevice {
+        /// Plays the mixer for about ten seconds, and returns how many of the samples weren't silent.
+        fn audible_samples(&mut self) -> usize {
+            let mut buffer = [0.0; 2205];
+            (0..100)
+                .map(|_| {
+                    self.mixer.write_samples(&mut buffer);
+                    buffer.iter().filter(|x| **x != 0.0).count()
+                })
+                .sum()
+        }
+    }
+
+    /// Sends every device it opens to the test instead of playing it. They're mono at 22050Hz, the same as `wav`.
+    struct MockBackend(mpsc::Sender<MockDevice>);
+
+    impl Backend for MockBackend {
+        fn devices(&mut self) -> Vec<String> {
+            vec!["Speakers".into(), "Headphones".into()]
+        }
+
+        fn open(&mut self, device: Option<&str>) -> Option<Output> {
+            let devices = self.0.clone();
+            let name = device.map(String::from);
+            Some(Output {
+                sample_rate: SampleRate::new(22050).unwrap(),
+                channel_count: ChannelCount::new(1).unwrap(),
+                play: Box::new(move |mixer| {
+                    let (plugged_in, unplugged) = mpsc::channel();
+                    let _ = devices.send(MockDevice { name, mixer, _plugged_in: plugged_in });
+                    let _ = unplugged.recv();
+                }),
+            })
+        }
+    }
+
+    fn with_mock_backend() -> (AudioManager, impl Fn() -> Option<MockDevice>) {
+        let (sender, receiver) = mpsc::channel();
+        let audio = AudioManager::with_backend(true, Box::new(MockBackend(sender)));
+        (audio, move || receiver.recv_timeout(Duration::from_millis(500)).ok())
+    }
+
+    /// Waits for the output thread to notice its device has gone, then lets `check_output` try again straight away.
+    fn wait_until_lost(audio: &mut AudioManager) {
+        let lost = audio.output.as_ref().unwrap().lost.clone();
+        let start = Instant::now();
+        while !lost.load(Ordering::Acquire) {
+            assert!(start.elapsed() < Duration::from_secs(10), "the output thread didn't finish");
+            thread::sleep(Duration::from_millis(1));
+        }
+        audio.last_reopen = Instant::now() - REOPEN_INTERVAL;
+    }
+
+    #[test]
+    fn finished_sounds() {
+        let mut playback = Playback::default();
+        // one second of 22050Hz stereo, started at 1s
+        let end = 1_000_000_000 + length_to_ns(44100, 22050, 2);
+        playback.play(1, 1_000_000_000, end, false);
+        assert!(playback.is_playing(1, end - 1));
+        assert!(!playback.is_playing(1, end));
+        assert!(!playback.is_playing(2, 0));
+
+        // finished sounds get forgotten the next time anything plays
+        playback.play(2, end, end + 100, false);
+        assert!(!playback.end_times.contains_key(&1));
+        assert!(playback.is_playing(2, end));
+    }
+
+    #[test]
+    fn looping_sounds() {
+        let mut playback = Playback::default();
+        playback.play_looping(1, false);
+        assert!(playback.is_playing(1, u128::MAX));
+
+        // playing a looping sound again doesn't stop it looping
+        playback.play(1, 0, 100, false);
+        assert!(playback.is_playing(1, 200));
+        playback.stop(1);
+        assert!(!playback.is_playing(1, 0));
+    }
+
+    #[test]
+    fn multimedia_sounds() {
+        let mut playback = Playback::default();
+        // only one exclusive sound plays at a time
+        playback.play(1, 0, 100, true);
+        playback.play_looping(2, true);
+        assert!(!playback.is_playing(1, 50));
+        assert!(playback.is_playing(2, 50));
+
+        playback.play(3, 0, 100, false);
+        playback.stop_all();
+        assert!(!playback.is_playing(2, 50));
+        assert!(!playback.is_playing(3, 50));
+    }
+
+    #[test]
+    fn resume_positions() {
+        // a one second sound started at 2s, checked at 2.5s and 3s
+        assert_eq!(resume_position(2_000_000_000, 1_000_000_000, false, 2_500_000_000), Some(500_000_000));
+        assert_eq!(resume_position(2_000_000_000, 1_000_000_000, false, 3_000_000_000), None);
+        // looping sounds carry on from wherever they'd have got to in the current loop
+        assert_eq!(resume_position(2_000_000_000, 1_000_000_000, true, 5_250_000_000), Some(250_000_000));
+        assert_eq!(resume_position(2_000_000_000, 0, true, 5_250_000_000), Some(0));
+        // the clock can go backwards when a savestate is loaded
+        assert_eq!(resume_position(2_000_000_000, 1_000_000_000, false, 1_000_000_000), Some(0));
+    }
+
+    #[test]
+    fn global_volume_in_savestates() {
+        let mut audio = AudioManager::with_backend(false, Box::new(NoOutput));
+        let mixer_volume = audio.global_volume.clone();
+        let volume = || f32::from_bits(mixer_volume.load(Ordering::Acquire));
+        audio.set_global_volume(2.0);
+        assert_eq!(volume(), 1.0);
+        audio.set_global_volume(-1.0);
+        assert_eq!(volume(), 0.001);
+
+        // changing the volume after saving doesn't change the savestate, and loading it changes what the mixer uses
+        let state = audio.state();
+        audio.set_global_volume(1.0);
+        audio.set_state(state, std::iter::empty(), 0);
+        assert_eq!(volume(), 0.001);
+        audio.set_global_volume(1.0);
+        assert_eq!(volume(), 1.0);
+    }
+
+    #[test]
+    fn deferred_first_play() {
+        let mut audio = AudioManager::with_backend(false, Box::new(NoOutput));
+        // half a second each, played at 1s, one with preload turned off
+        let preloaded = audio.add_wav(wav(11025), 1, 1.0, false, false).unwrap();
+        let lazy = audio.add_lazy_wav(wav(11025), 2, 1.0, false, false).unwrap();
+        let sounds = [FileType::Wav(preloaded.clone()), FileType::Lazy(lazy.clone())];
+        audio.play_wav(&preloaded, 1_000_000_000);
+        audio.play_lazy(&lazy, 1_000_000_000);
+        let state = audio.state();
+        assert!(bincode::serialize(&state).unwrap().len() < 200);
+
+        // until the next frame boundary it counts as playing, then it ends when it would have if it were preloaded
+        assert!(audio.sound_playing(2, 1_000_000_000));
+        audio.start_pending(&sounds, 1_020_000_000);
+        for &time in &[1_020_000_000, 1_499_999_999, 1_500_000_000] {
+            assert_eq!(audio.sound_playing(2, time), audio.sound_playing(1, time));
+        }
+
+        // loading a savestate from before the frame boundary gets the same result, without a decode in progress
+        let mut loaded = AudioManager::with_backend(false, Box::new(NoOutput));
+        loaded.set_state(state, std::iter::empty(), 1_020_000_000);
+        loaded.start_pending(&sounds, 1_040_000_000);
+        assert!(loaded.sound_playing(2, 1_499_999_999));
+        assert!(!loaded.sound_playing(2, 1_500_000_000));
+
+        // stopping it before it starts means it never does
+        audio.play_lazy(&lazy, 2_000_000_000);
+        audio.stop_sound(2);
+        assert!(!audio.sound_playing(2, 2_000_000_000));
+        audio.start_pending(&sounds, 2_020_000_000);
+        assert!(!audio.sound_playing(2, 2_020_000_000));
+    }
+
+    #[test]
+    fn invalid_sounds() {
+        // an invalid file is turned away whether preload is on or not, so it never counts as playing
+        let mut audio = AudioManager::with_backend(false, Box::new(NoOutput));
+        let file = || b"RIFF but not really".to_vec().into_boxed_slice();
+        assert!(audio.add_wav(file(), 1, 1.0, false, false).is_none());
+        assert!(audio.add_lazy_wav(file(), 1, 1.0, false, false).is_none());
+        assert!(audio.add_lazy_wav(wav(100), 1, 1.0, false, false).is_some());
+        assert!(audio.add_lazy_mp3(file(), 2).is_none());
+    }
+
+    #[test]
+    fn eviction() {
+        let mut audio = AudioManager::with_backend(false, Box::new(NoOutput));
+        let lazy = audio.add_lazy_wav(wav(22050), 1, 1.0, false, false).unwrap();
+        let looping = audio.add_lazy_wav(wav(22050), 2, 1.0, false, false).unwrap();
+        let sounds = [FileType::Lazy(lazy.clone()), FileType::Lazy(looping.clone())];
+        let size = lazy.file.len();
+        audio.set_memory_budget(Some(0));
+        audio.play_lazy(&lazy, 0);
+        audio.loop_lazy(&looping, 0);
+        audio.start_pending(&sounds, 0);
+        let memory = audio.memory(&sounds);
+        assert_eq!((memory.compressed, memory.decoded, memory.decoded_count), (size * 2, size * 2, 2));
+
+        // sounds get evicted once they've gone unplayed for long enough, unless they're still playing
+        audio.start_pending(&sounds, EVICT_AFTER - 1);
+        assert_eq!(audio.memory(&sounds).decoded_count, 2);
+        audio.start_pending(&sounds, EVICT_AFTER);
+        assert_eq!(audio.memory(&sounds).decoded, size);
+
+        // an evicted sound gets decoded again the next time it's played, and plays the same as the first time
+        audio.play_lazy(&lazy, EVICT_AFTER);
+        assert!(audio.sound_playing(1, EVICT_AFTER));
+        audio.start_pending(&sounds, EVICT_AFTER);
+        assert!(audio.sound_playing(1, EVICT_AFTER + 999_999_999));
+        assert!(!audio.sound_playing(1, EVICT_AFTER + 1_000_000_000));
+        assert_eq!(audio.memory(&sounds).decoded_count, 2);
+
+        // without a budget nothing gets evicted
+        audio.set_memory_budget(None);
+        audio.start_pending(&sounds, EVICT_AFTER * 3);
+        assert_eq!(audio.memory(&sounds).decoded_count, 2);
+    }
+
+    #[test]
+    fn savestate_positions() {
+        let mut audio = AudioManager::with_backend(false, Box::new(NoOutput));
+        // both a second long and played at 0, one of them looping
+        let once = audio.add_wav(wav(22050), 1, 1.0, false, false).unwrap();
+        let looping = audio.add_wav(wav(22050), 2, 1.0, false, false).unwrap();
+        audio.play_wav(&once, 0);
+        audio.loop_wav(&looping, 0);
+        let state = bincode::serialize(&audio.state()).unwrap();
+        audio.stop_all();
+        // only where the sounds are up to gets saved, not the sounds themselves
+        assert!(state.len() < 200, "{}", state.len());
+
+        // loading halfway through restarts both, and the game's handles still set the volume of the restored sounds
+        let sounds = [FileType::Wav(once), FileType::Wav(looping.clone())];
+        audio.set_state(bincode::deserialize(&state).unwrap(), &sounds, 500_000_000);
+        assert_eq!(audio.active.iter().map(ActiveSound::id).collect::<Vec<_>>(), [1, 2]);
+        let params = audio.active.iter().find_map(|x| match &x.handle {
+            SoundHandle::Wav(handle) if handle.id == 2 => Some(handle.params.clone()),
+            _ => None,
+        });
+        assert!(Arc::ptr_eq(&params.unwrap(), &looping.params));
+
+        // loading after the first one would have finished leaves it stopped
+        audio.set_state(bincode::deserialize(&state).unwrap(), &sounds, 1_500_000_000);
+        assert_eq!(audio.active.iter().map(ActiveSound::id).collect::<Vec<_>>(), [2]);
+        assert!(!audio.sound_playing(1, 1_500_000_000));
+        assert!(audio.sound_playing(2, 1_500_000_000));
+    }
+
+    #[test]
+    fn lost_device() {
+        let (mut audio, next_device) = with_mock_backend();
+        let mut device = next_device().unwrap();
+        // both a second long and played at 0, and one of them stopped
+        let once = audio.add_wav(tone(22050), 1, 1.0, false, false).unwrap();
+        let stopped = audio.add_wav(tone(22050), 2, 1.0, false, false).unwrap();
+        audio.play_wav(&once, 0);
+        audio.play_wav(&stopped, 0);
+        audio.stop_sound(2);
+        assert_eq!(device.audible_samples(), 22050);
+        let state = bincode::serialize(&audio.state()).unwrap();
+
+        // nothing gets reopened while the device is working
+        audio.last_reopen = Instant::now() - REOPEN_INTERVAL;
+        audio.check_output(500_000_000);
+        assert!(next_device().is_none());
+
+        // once it's unplugged, the default device gets a new mixer, and the sound carries on from halfway through
+        drop(device);
+        wait_until_lost(&mut audio);
+        audio.check_output(500_000_000);
+        let mut device = next_device().unwrap();
+        assert_eq!(device.name, None);
+        let audible = device.audible_samples();
+        assert!((11000..=11050).contains(&audible), "{}", audible);
+
+        // the game can't tell any of that happened
+        assert_eq!(bincode::serialize(&audio.state()).unwrap(), state);
+        assert!(audio.sound_playing(1, 999_999_999));
+        assert!(!audio.sound_playing(1, 1_000_000_000));
+        assert!(!audio.sound_playing(2, 500_000_000));
+
+        // it doesn't try again straight away if a device goes as soon as it's opened
+        drop(device);
+        wait_until_lost(&mut audio);
+        audio.last_reopen = Instant::now();
+        audio.check_output(600_000_000);
+        assert!(next_device().is_none());
+    }
+
+    #[test]
+    fn chosen_device() {
+        let (mut audio, next_device) = with_mock_backend();
+        let mut speakers = next_device().unwrap();
+        let once = audio.add_wav(tone(22050), 1, 1.0, false, false).unwrap();
+        audio.play_wav(&once, 0);
+        assert_eq!(audio.devices(), ["Speakers", "Headphones"]);
+        assert_eq!(audio.device(), None);
+
+        // switching devices moves the sound over at about where it should be, and the old device goes quiet
+        audio.set_device(Some("Headphones".into()), 250_000_000);
+        let mut headphones = next_device().unwrap();
+        assert_eq!(headphones.name.as_deref(), Some("Headphones"));
+        assert_eq!(audio.device(), Some("Headphones"));
+        assert_eq!(speakers.audible_samples(), 0);
+        let audible = headphones.audible_samples();
+        assert!((16500..=16550).contains(&audible), "{}", audible);
+
+        // when the chosen device is lost, it's the one that gets opened again
+        drop(headphones);
+        wait_until_lost(&mut audio);
+        audio.check_output(500_000_000);
+        assert_eq!(next_device().unwrap().name.as_deref(), Some("Headphones"));
+    }
+}
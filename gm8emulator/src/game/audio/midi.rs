@@ -0,0 +1,525 @@
+//! A small software synthesizer for MIDI background music.
+//!
+//! GM8 plays MIDI files through DirectMusic and the General MIDI set that comes with Windows, which can't be shipped
+//! with the emulator, so instruments are approximated with plain waveforms chosen by instrument family. Event timing
+//! follows the file exactly, including tempo changes, so a song lasts as long as it does in GM8.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use udon::source::{ChannelCount, Sample, SampleRate, Source};
+
+/// The sample rate everything is synthesized at. The mixer resamples it to whatever the output device wants.
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// How many samples a note takes to fade in after it starts and to fade out after it's released.
+const ATTACK: u32 = SAMPLE_RATE / 200;
+const RELEASE: u32 = SAMPLE_RATE / 20;
+
+/// How many samples a drum hit takes to die away.
+const DRUM_DECAY: u32 = SAMPLE_RATE / 6;
+
+/// The oldest note gets cut off when this many are playing at once.
+const MAX_VOICES: usize = 48;
+
+/// Channel 10, counting from 1, is always drums in General MIDI.
+const DRUM_CHANNEL: u8 = 9;
+
+/// How loud a single note at full velocity and volume is, leaving room for chords.
+const NOTE_AMPLITUDE: f32 = 0.15;
+
+/// The default tempo of a MIDI file, in microseconds per quarter note, which is 120 beats per minute.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidFile,
+    NoTracks,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Event {
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    NoteOff { channel: u8, key: u8 },
+    Program { channel: u8, program: u8 },
+    Volume { channel: u8, volume: u8 },
+    AllNotesOff { channel: u8 },
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum Waveform {
+    Sine,
+    Triangle,
+    Square,
+    Sawtooth,
+    Noise,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Voice {
+    channel: u8,
+    key: u8,
+    waveform: Waveform,
+    /// How far through the current cycle the voice is, from 0 to 1.
+    phase: f32,
+    /// How far `phase` moves each sample.
+    step: f32,
+    amplitude: f32,
+    age: u32,
+    /// How many samples ago the note was released, if it has been.
+    released: Option<u32>,
+    /// State of the random number generator used for drums.
+    noise: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MidiPlayer {
+    /// Every event in the file along with the sample it happens on, in order.
+    events: Arc<[(u64, Event)]>,
+    length: usize, // Number of samples up to the end of the longest track
+    position: u64,
+    next_event: usize,
+    programs: [u8; 16],
+    volumes: [u8; 16],
+    voices: Vec<Voice>,
+}
+
+impl MidiPlayer {
+    pub fn new(file: &[u8]) -> Result<Self, Error> {
+        let (events, length) = parse(file)?;
+        Ok(Self {
+            events: events.into(),
+            length: length as usize,
+            position: 0,
+            next_event: 0,
+            programs: [0; 16],
+            volumes: [100; 16],
+            voices: Vec::new(),
+        })
+    }
+
+    /// The number of samples which will be played out. Divide by `SAMPLE_RATE` to get length in seconds.
+    #[inline(always)]
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// How many bytes this is taking up, which is the parsed events rather than the file itself.
+    pub fn memory(&self) -> usize {
+        self.events.len() * std::mem::size_of::<(u64, Event)>()
+    }
+
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::NoteOn { channel, key, velocity: 0 } => self.release(channel, key),
+            Event::NoteOn { channel, key, velocity } => {
+                // striking a key that's still sounding restarts it
+                self.voices.retain(|v| v.channel != channel || v.key != key || v.released.is_some());
+                if self.voices.len() >= MAX_VOICES {
+                    self.voices.remove(0);
+                }
+                let waveform = if channel == DRUM_CHANNEL {
+                    Waveform::Noise
+                } else {
+                    waveform(self.programs[usize::from(channel)])
+                };
+                let frequency = 440.0 * 2f32.powf((f32::from(key) - 69.0) / 12.0);
+                let volume = f32::from(self.volumes[usize::from(channel)]) / 127.0;
+                self.voices.push(Voice {
+                    channel,
+                    key,
+                    waveform,
+                    phase: 0.0,
+                    step: frequency / SAMPLE_RATE as f32,
+                    amplitude: NOTE_AMPLITUDE * volume * f32::from(velocity) / 127.0,
+                    age: 0,
+                    released: None,
+                    noise: u32::from(key) * 0x9E37 + 1,
+                });
+            },
+            Event::NoteOff { channel, key } => self.release(channel, key),
+            Event::Program { channel, program } => self.programs[usize::from(channel)] = program,
+            Event::Volume { channel, volume } => self.volumes[usize::from(channel)] = volume,
+            Event::AllNotesOff { channel } => {
+                for voice in self.voices.iter_mut().filter(|v| v.channel == channel && v.released.is_none()) {
+                    voice.released = Some(0);
+                }
+            },
+        }
+    }
+
+    fn release(&mut self, channel: u8, key: u8) {
+        for voice in self.voices.iter_mut() {
+            if voice.channel == channel && voice.key == key && voice.released.is_none() {
+                voice.released = Some(0);
+            }
+        }
+    }
+
+    fn next_sample(&mut self) -> Sample {
+        let mut output = 0.0;
+        for voice in self.voices.iter_mut() {
+            output += voice.next_sample();
+        }
+        self.voices.retain(Voice::is_sounding);
+        output
+    }
+}
+
+impl Voice {
+    fn next_sample(&mut self) -> f32 {
+        let wave = match self.waveform {
+            Waveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    0.6
+                } else {
+                    -0.6
+                }
+            },
+            Waveform::Sawtooth => 0.8 * (2.0 * self.phase - 1.0),
+            Waveform::Noise => {
+                self.noise = self.noise.wrapping_mul(1664525).wrapping_add(1013904223);
+                (self.noise >> 16) as f32 / 32768.0 - 1.0
+            },
+        };
+        let envelope = match (self.waveform, self.released) {
+            (Waveform::Noise, _) => 1.0 - self.age as f32 / DRUM_DECAY as f32,
+            (_, Some(released)) => 1.0 - released as f32 / RELEASE as f32,
+            (_, None) => (self.age as f32 / ATTACK as f32).min(1.0),
+        };
+        self.phase = (self.phase + self.step).fract();
+        self.age = self.age.saturating_add(1);
+        if let Some(released) = &mut self.released {
+            *released += 1;
+        }
+        wave * envelope.max(0.0) * self.amplitude
+    }
+
+    fn is_sounding(&self) -> bool {
+        match (self.waveform, self.released) {
+            (Waveform::Noise, _) => self.age < DRUM_DECAY,
+            (_, Some(released)) => released < RELEASE,
+            (_, None) => true,
+        }
+    }
+}
+
+impl Source for MidiPlayer {
+    #[inline(always)]
+    fn channel_count(&self) -> ChannelCount {
+        ChannelCount::new(1).unwrap()
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> SampleRate {
+        SampleRate::new(SAMPLE_RATE).unwrap()
+    }
+
+    fn write_samples(&mut self, buffer: &mut [Sample]) -> usize {
+        let mut samples_written = 0usize;
+        for sample in buffer.iter_mut() {
+            if self.position >= self.length as u64 {
+                break
+            }
+            while let Some(&(time, event)) = self.events.get(self.next_event) {
+                if time > self.position {
+                    break
+                }
+                self.apply(event);
+                self.next_event += 1;
+            }
+            *sample = self.next_sample();
+            self.position += 1;
+            samples_written += 1;
+        }
+        samples_written
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+        self.next_event = 0;
+        self.programs = [0; 16];
+        self.volumes = [100; 16];
+        self.voices.clear();
+    }
+}
+
+/// Picks a waveform for a General MIDI program by which family of instruments it's in.
+fn waveform(program: u8) -> Waveform {
+    match program / 8 {
+        0 | 3 | 4 => Waveform::Triangle, // piano, guitar, bass
+        1 | 9 | 11 => Waveform::Sine,    // chromatic percussion, pipe, synth pad
+        2 | 8 | 10 => Waveform::Square,  // organ, reed, synth lead
+        5..=7 => Waveform::Sawtooth,     // strings, ensemble, brass
+        _ => Waveform::Triangle,
+    }
+}
+
+/// Reads a standard MIDI file, or an RMID file containing one, into its events and the sample each one happens on,
+/// along with the number of samples up to the end of the longest track.
+fn parse(file: &[u8]) -> Result<(Vec<(u64, Event)>, u64), Error> {
+    let mut reader = Reader { data: rmid_data(file).unwrap_or(file), pos: 0 };
+    if reader.bytes(4)? != b"MThd" {
+        return Err(Error::InvalidFile)
+    }
+    let mut header = Reader { data: reader.chunk()?, pos: 0 };
+    let (format, track_count, division) = (header.u16()?, header.u16()?, header.u16()?);
+    if format > 2 || division == 0 {
+        return Err(Error::InvalidFile)
+    }
+
+    // (tick, track, event) - tempo changes go in here too, since they affect every track
+    let mut timeline = Vec::new();
+    let mut end_tick = 0u64;
+    let mut tracks = 0u16;
+    while tracks < track_count && reader.pos < reader.data.len() {
+        let id = reader.bytes(4)?;
+        let chunk = reader.chunk()?;
+        if id == b"MTrk" {
+            end_tick = end_tick.max(read_track(chunk, tracks, &mut timeline)?);
+            tracks += 1;
+        }
+    }
+    if tracks == 0 {
+        return Err(Error::NoTracks)
+    }
+    // stable, so events on the same tick stay in track order and then file order
+    timeline.sort_by_key(|&(tick, track, _)| (tick, track));
+
+    let ticks_to_samples = |ticks: u64, tempo: u32| -> u64 {
+        if division & 0x8000 == 0 {
+            // ticks per quarter note
+            (u128::from(ticks) * u128::from(tempo) * u128::from(SAMPLE_RATE)
+                / (u128::from(division) * 1_000_000)) as u64
+        } else {
+            // SMPTE frames per second and ticks per frame, where tempo doesn't matter
+            let fps = u64::from(((division >> 8) as i8).wrapping_neg() as u8);
+            let ticks_per_frame = u64::from(division & 0xFF);
+            ticks * u64::from(SAMPLE_RATE) / (fps * ticks_per_frame).max(1)
+        }
+    };
+
+    let mut events = Vec::with_capacity(timeline.len());
+    let (mut tempo, mut base_tick, mut base_sample) = (DEFAULT_TEMPO, 0u64, 0u64);
+    for (tick, _, event) in timeline {
+        let sample = base_sample + ticks_to_samples(tick - base_tick, tempo);
+        match event {
+            TrackEvent::Tempo(new_tempo) => {
+                tempo = new_tempo;
+                base_tick = tick;
+                base_sample = sample;
+            },
+            TrackEvent::Event(event) => events.push((sample, event)),
+        }
+    }
+    let length = base_sample + ticks_to_samples(end_tick.saturating_sub(base_tick), tempo);
+    Ok((events, length))
+}
+
+enum TrackEvent {
+    Event(Event),
+    Tempo(u32),
+}
+
+/// Reads the events in one track, returning the tick its End of Track event is on.
+fn read_track(data: &[u8], track: u16, timeline: &mut Vec<(u64, u16, TrackEvent)>) -> Result<u64, Error> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut tick = 0u64;
+    let mut running_status = None;
+    while reader.pos < reader.data.len() {
+        tick += u64::from(reader.var_len()?);
+        let mut status = reader.u8()?;
+        if status < 0x80 {
+            // running status, so this is actually the first data byte
+            reader.pos -= 1;
+            status = running_status.ok_or(Error::InvalidFile)?;
+        }
+        let channel = status & 0x0F;
+        let event = match status & 0xF0 {
+            0x80 => {
+                running_status = Some(status);
+                let (key, _) = (reader.u8()?, reader.u8()?);
+                Some(TrackEvent::Event(Event::NoteOff { channel, key }))
+            },
+            0x90 => {
+                running_status = Some(status);
+                let (key, velocity) = (reader.u8()?, reader.u8()?);
+                Some(TrackEvent::Event(Event::NoteOn { channel, key, velocity }))
+            },
+            0xB0 => {
+                running_status = Some(status);
+                match (reader.u8()?, reader.u8()?) {
+                    (7, volume) => Some(TrackEvent::Event(Event::Volume { channel, volume })),
+                    (120, _) | (123, _) => Some(TrackEvent::Event(Event::AllNotesOff { channel })),
+                    _ => None,
+                }
+            },
+            0xC0 => {
+                running_status = Some(status);
+                let program = reader.u8()?;
+                Some(TrackEvent::Event(Event::Program { channel, program }))
+            },
+            0xA0 | 0xE0 => {
+                // aftertouch and pitch bend
+                running_status = Some(status);
+                reader.bytes(2)?;
+                None
+            },
+            0xD0 => {
+                // channel pressure
+                running_status = Some(status);
+                reader.u8()?;
+                None
+            },
+            _ => match status {
+                0xFF => {
+                    let kind = reader.u8()?;
+                    let len = reader.var_len()? as usize;
+                    let data = reader.bytes(len)?;
+                    match (kind, data) {
+                        (0x2F, _) => return Ok(tick),
+                        (0x51, &[a, b, c]) => Some(TrackEvent::Tempo(u32::from_be_bytes([0, a, b, c]).max(1))),
+                        _ => None,
+                    }
+                },
+                0xF0 | 0xF7 => {
+                    let len = reader.var_len()? as usize;
+                    reader.bytes(len)?;
+                    None
+                },
+                _ => return Err(Error::InvalidFile),
+            },
+        };
+        if let Some(event) = event {
+            timeline.push((tick, track, event));
+        }
+    }
+    // missing End of Track, so the track ends at its last event
+    Ok(tick)
+}
+
+/// Gets the standard MIDI file out of an RMID file, which is one wrapped in a RIFF chunk.
+fn rmid_data(file: &[u8]) -> Option<&[u8]> {
+    if file.get(..4)? != b"RIFF" || file.get(8..12)? != b"RMID" {
+        return None
+    }
+    let mut reader = Reader { data: file, pos: 12 };
+    while reader.pos < reader.data.len() {
+        let id = reader.bytes(4).ok()?;
+        let size = reader.bytes(4).ok()?;
+        let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        let data = reader.bytes(size).ok()?;
+        if id == b"data" {
+            return Some(data)
+        }
+        reader.pos += size % 2;
+    }
+    None
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(count).ok_or(Error::InvalidFile)?);
+        let bytes = bytes.ok_or(Error::InvalidFile)?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a chunk's big-endian length and then the chunk itself.
+    fn chunk(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.bytes(4)?;
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        self.bytes(len.min(self.data.len() - self.pos))
+    }
+
+    /// Reads a variable-length quantity, which is up to four bytes of seven bits each, most significant first.
+    fn var_len(&mut self) -> Result<u32, Error> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value)
+            }
+        }
+        Err(Error::InvalidFile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, MidiPlayer, SAMPLE_RATE};
+    use udon::source::Source;
+
+    /// A format 1 file at 96 ticks per quarter note with the given tracks, each of which gets an End of Track added.
+    fn midi(tracks: &[&[u8]]) -> Vec<u8> {
+        let mut file = b"MThd".to_vec();
+        file.extend_from_slice(&6u32.to_be_bytes());
+        file.extend_from_slice(&1u16.to_be_bytes());
+        file.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        file.extend_from_slice(&96u16.to_be_bytes());
+        for track in tracks {
+            file.extend_from_slice(b"MTrk");
+            file.extend_from_slice(&(track.len() as u32 + 4).to_be_bytes());
+            file.extend_from_slice(track);
+            file.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+        }
+        file
+    }
+
+    #[test]
+    fn tempo_changes() {
+        let file = midi(&[
+            // one quarter note at 120bpm, then switch to 60bpm and put an empty text event a quarter note later
+            &[
+                0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 500000us per quarter note
+                0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 1000000us per quarter note
+                0x60, 0xFF, 0x01, 0x00,
+            ],
+            // program change, then a note held for two quarter notes using running status for the note off
+            &[0x00, 0xC0, 0x10, 0x00, 0x90, 0x45, 0x40, 0x81, 0x40, 0x45, 0x00],
+        ]);
+        let player = MidiPlayer::new(&file).unwrap();
+        // half a second at 120bpm and a second at 60bpm
+        assert_eq!(player.length(), SAMPLE_RATE as usize * 3 / 2);
+        assert_eq!(&player.events[..], &[
+            (0, Event::Program { channel: 0, program: 0x10 }),
+            (0, Event::NoteOn { channel: 0, key: 0x45, velocity: 0x40 }),
+            (u64::from(SAMPLE_RATE) * 3 / 2, Event::NoteOn { channel: 0, key: 0x45, velocity: 0 }),
+        ]);
+    }
+
+    #[test]
+    fn plays_to_the_end() {
+        let file = midi(&[&[0x00, 0x90, 0x3C, 0x7F, 0x60, 0x80, 0x3C, 0x00]]);
+        let mut player = MidiPlayer::new(&file).unwrap();
+        let mut buffer = vec![0.0; player.length() + 100];
+        assert_eq!(player.write_samples(&mut buffer), player.length());
+        assert!(buffer[..player.length()].iter().any(|&x| x != 0.0));
+        assert_eq!(player.write_samples(&mut buffer), 0);
+        player.reset();
+        assert_eq!(player.write_samples(&mut buffer), player.length());
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(MidiPlayer::new(b"RIFF").is_err());
+        assert!(MidiPlayer::new(&midi(&[])).is_err());
+        assert!(MidiPlayer::new(&midi(&[&[0x00, 0x3C]])).is_err());
+    }
+}
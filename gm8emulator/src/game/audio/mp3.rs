@@ -80,12 +80,22 @@ impl Mp3Player {
         }
     }
 
+    /// Whether `new` would accept this file, without going through all of it to work out how long it is.
+    pub fn is_valid(file: &[u8]) -> bool {
+        details(Decoder::new(file)).map_or(false, |(channels, sample_rate)| channels != 0 && sample_rate != 0)
+    }
+
     /// The number of samples which will actually be played out. Divide by sample rate to get length in seconds.
     #[inline(always)]
     pub fn length(&self) -> usize {
         self.length
     }
 
+    /// How many bytes this is taking up, which is the file and a frame's worth of decoded samples.
+    pub fn memory(&self) -> usize {
+        self.file.len() + std::mem::size_of_val(&*self.buffer)
+    }
+
     fn flush(&mut self, output: &mut [Sample]) -> usize {
         // get the biggest slice that can be copied directly into `output`
         let mut buffer = &self.buffer[self.buffer_off..self.buffer_off + self.buffer_len];
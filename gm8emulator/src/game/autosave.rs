@@ -0,0 +1,250 @@
+use crate::{
+    game::replay::{self, Replay},
+    locale::trf,
+};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Name of the newest autosave in a project directory. Backups are named autosave.1.gmtas, autosave.2.gmtas etc.
+pub const AUTOSAVE_NAME: &str = "autosave.gmtas";
+
+/// How often record mode should autosave the movie.
+#[derive(Clone, Copy, Debug)]
+pub struct AutosaveConfig {
+    /// Seconds between autosaves, or 0 to not autosave on a timer.
+    pub interval: u64,
+    /// Re-records between autosaves, or 0 to not autosave on re-records.
+    pub rerecords: u64,
+    /// How many older autosaves to keep around.
+    pub backups: usize,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        Self { interval: 60, rerecords: 25, backups: 5 }
+    }
+}
+
+/// Writes a file by writing to a temporary file next to it and renaming it over the original.
+/// If the process dies part way through, the original file is left as it was.
+pub fn write_atomic(path: &Path, write: impl FnOnce(&mut File) -> io::Result<()>) -> io::Result<()> {
+    let mut temp_name = path.file_name().map(OsString::from).unwrap_or_default();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let mut file = File::create(&temp_path)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)
+}
+
+/// Keeps an autosave of the movie being recorded in a project directory.
+/// Compressing and writing happen on a background thread, so saving only costs the frame loop a serialize.
+pub struct Autosaver {
+    config: AutosaveConfig,
+    last_save: Instant,
+    last_rerecords: u64,
+    last_data: Vec<u8>,
+    sender: Option<mpsc::Sender<Vec<u8>>>,
+    error_sender: mpsc::Sender<String>,
+    errors: mpsc::Receiver<String>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Autosaver {
+    pub fn new(project_path: &Path, config: AutosaveConfig, rerecords: u64) -> Self {
+        let path = project_path.join(AUTOSAVE_NAME);
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        let (error_sender, errors) = mpsc::channel();
+        let thread_errors = error_sender.clone();
+        let thread = thread::spawn(move || {
+            while let Ok(mut data) = receiver.recv() {
+                // if saves have piled up, only the newest one matters
+                while let Ok(newer) = receiver.try_recv() {
+                    data = newer;
+                }
+                let result = rotate_backups(&path, config.backups)
+                    .map_err(replay::WriteError::IOErr)
+                    .and_then(|()| Replay::write_serialized(&data, &path));
+                if let Err(e) = result {
+                    let _ = thread_errors.send(trf("error_write_file", &[&AUTOSAVE_NAME, &format!("{:?}", e)]));
+                }
+            }
+        });
+        Self {
+            config,
+            last_save: Instant::now(),
+            last_rerecords: rerecords,
+            last_data: Vec::new(),
+            sender: Some(sender),
+            error_sender,
+            errors,
+            thread: Some(thread),
+        }
+    }
+
+    /// Autosaves if enough time or re-records have passed since the last autosave.
+    pub fn tick(&mut self, replay: &Replay, rerecords: u64) {
+        let interval =
+            self.config.interval != 0 && self.last_save.elapsed() >= Duration::from_secs(self.config.interval);
+        let rerecorded = self.config.rerecords != 0 && rerecords >= self.last_rerecords + self.config.rerecords;
+        if interval || rerecorded {
+            self.save(replay, rerecords);
+        }
+    }
+
+    /// Autosaves right away, unless the movie hasn't changed since the last autosave.
+    pub fn save(&mut self, replay: &Replay, rerecords: u64) {
+        self.last_save = Instant::now();
+        self.last_rerecords = rerecords;
+        match replay.serialize() {
+            Ok(data) => {
+                if data != self.last_data {
+                    self.last_data = data.clone();
+                    if let Some(sender) = &self.sender {
+                        let _ = sender.send(data);
+                    }
+                }
+            },
+            Err(e) => {
+                let _ = self.error_sender.send(trf("error_serialize_file", &[&AUTOSAVE_NAME, &format!("{:?}", e)]));
+            },
+        }
+    }
+
+    /// Returns an error message if a background write has failed since this was last called.
+    pub fn take_error(&self) -> Option<String> {
+        self.errors.try_recv().ok()
+    }
+}
+
+impl Drop for Autosaver {
+    /// Waits for any pending write to finish. This doesn't autosave, so dropping an Autosaver is like being killed.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// An autosave which has more of the movie in it than the project's quicksave.
+pub struct Recovery {
+    pub path: PathBuf,
+    pub frames: usize,
+}
+
+/// Looks for an autosave which was written after the given quicksave and has more frames than it.
+/// Backups are checked too, newest first, in case the latest autosave can't be read.
+pub fn find_recovery(project_path: &Path, quicksave: &Path, quicksave_frames: usize) -> Option<Recovery> {
+    let saved_at = fs::metadata(quicksave).and_then(|m| m.modified()).ok();
+    let newest = project_path.join(AUTOSAVE_NAME);
+    let backups = (1..).map(|i| backup_path(&newest, i));
+    for path in std::iter::once(newest.clone()).chain(backups) {
+        let modified = match fs::metadata(&path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            // the newest autosave is briefly missing while backups are being rotated
+            Err(_) if path == newest => continue,
+            Err(_) => break,
+        };
+        if saved_at.map(|t| modified <= t).unwrap_or(false) {
+            return None
+        }
+        if let Ok(replay) = Replay::from_file(&path) {
+            return if replay.frame_count() > quicksave_frames {
+                Some(Recovery { path, frames: replay.frame_count() })
+            } else {
+                None
+            }
+        }
+    }
+    None
+}
+
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    path.with_extension(format!("{}.gmtas", index))
+}
+
+/// Shifts autosave.gmtas to autosave.1.gmtas, autosave.1.gmtas to autosave.2.gmtas and so on, dropping the oldest.
+fn rotate_backups(path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 || !path.exists() {
+        return Ok(())
+    }
+    for i in (1..keep).rev() {
+        let from = backup_path(path, i);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, i + 1))?;
+        }
+    }
+    fs::rename(path, backup_path(path, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("opengmk_autosave_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn add_frames(replay: &mut Replay, count: usize) {
+        for _ in 0..count {
+            replay.new_frame();
+        }
+    }
+
+    #[test]
+    fn killed_between_autosaves() {
+        let project = project("kill");
+        let quicksave = project.join("save1.bin");
+        let config = AutosaveConfig { interval: 0, rerecords: 2, backups: 3 };
+        let mut autosaver = Autosaver::new(&project, config, 0);
+        let mut replay = Replay::new(0, 0);
+        add_frames(&mut replay, 10);
+        autosaver.tick(&replay, 1);
+        autosaver.tick(&replay, 2);
+        add_frames(&mut replay, 10);
+        autosaver.tick(&replay, 3);
+
+        // the emulator dies here with 20 frames recorded, and leaves a half-written file behind
+        drop(autosaver);
+        fs::write(project.join("autosave.gmtas.tmp"), b"junk").unwrap();
+
+        let recovery = find_recovery(&project, &quicksave, 0).unwrap();
+        assert_eq!(recovery.frames, 10);
+        assert!(find_recovery(&project, &quicksave, 10).is_none());
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn backups() {
+        let project = project("backups");
+        let config = AutosaveConfig { interval: 0, rerecords: 0, backups: 2 };
+        let mut replay = Replay::new(0, 0);
+        for _ in 0..4 {
+            add_frames(&mut replay, 1);
+            Autosaver::new(&project, config, 0).save(&replay, 0);
+        }
+        let frames = |name: &str| Replay::from_file(&project.join(name)).unwrap().frame_count();
+        assert_eq!(frames("autosave.gmtas"), 4);
+        assert_eq!(frames("autosave.1.gmtas"), 3);
+        assert_eq!(frames("autosave.2.gmtas"), 2);
+        assert!(!project.join("autosave.3.gmtas").exists());
+
+        // if the newest autosave is unreadable, the newest backup is used instead
+        fs::write(project.join("autosave.gmtas"), b"junk").unwrap();
+        assert_eq!(find_recovery(&project, &project.join("save1.bin"), 0).unwrap().frames, 3);
+        fs::remove_dir_all(&project).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use encoding_rs::Encoding;
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+/// Converts a string to the given code page. Characters it can't represent become '?', as with the ANSI Windows APIs.
+fn encode_lossy(s: &str, encoding: &'static Encoding) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut buf = [0u8; 4];
+    for c in s.chars() {
+        let (bytes, _, unmappable) = encoding.encode(c.encode_utf8(&mut buf));
+        if unmappable {
+            out.push(b'?');
+        } else {
+            out.extend_from_slice(&bytes);
+        }
+    }
+    out
+}
+
+/// Converts an OS string to the bytes a game would have seen, in the given code page.
+/// On Unix, a string which isn't UTF-8 is assumed to already be in the game's code page - this is usually the case
+/// for Shift-JIS filenames which were extracted from an archive as-is.
+pub fn encode_os_str(s: &OsStr, encoding: &'static Encoding) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        match s.to_str() {
+            Some(s) => encode_lossy(s, encoding),
+            None => s.as_bytes().to_vec(),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        encode_lossy(&s.to_string_lossy(), encoding)
+    }
+}
+
+/// The same as `encode_os_str`, but also removes the `\\?\` prefix which canonicalize() adds on Windows.
+pub fn encode_path(path: &Path, encoding: &'static Encoding) -> Vec<u8> {
+    if cfg!(target_os = "windows") {
+        encode_lossy(path.to_string_lossy().trim_start_matches("\\\\?\\"), encoding)
+    } else {
+        encode_os_str(path.as_os_str(), encoding)
+    }
+}
+
+/// Splits a command line into parameters exactly like Delphi's ParamStr, which is what GM8 uses.
+/// Parameters are separated by whitespace (any byte up to and including space), and double quotes can be used
+/// anywhere in a parameter to include whitespace - the quotes themselves are removed. There are no escapes.
+pub fn split(cmdline: &[u8]) -> Vec<Vec<u8>> {
+    let mut params = Vec::new();
+    let mut pos = 0;
+    loop {
+        // skip whitespace, and also any empty quotes at the start of a parameter
+        loop {
+            while cmdline.get(pos).filter(|&&c| c != 0 && c <= b' ').is_some() {
+                pos += 1;
+            }
+            if cmdline.get(pos) == Some(&b'"') && cmdline.get(pos + 1) == Some(&b'"') {
+                pos += 2;
+            } else {
+                break
+            }
+        }
+
+        let mut param = Vec::new();
+        while let Some(&c) = cmdline.get(pos).filter(|&&c| c > b' ') {
+            pos += 1;
+            if c == b'"' {
+                while let Some(&c) = cmdline.get(pos).filter(|&&c| c != 0 && c != b'"') {
+                    param.push(c);
+                    pos += 1;
+                }
+                if cmdline.get(pos) == Some(&b'"') {
+                    pos += 1;
+                }
+            } else {
+                param.push(c);
+            }
+        }
+
+        // ParamStr stops at the first empty parameter, which is also the end of the string
+        if param.is_empty() {
+            break params
+        }
+        params.push(param);
+    }
+}
+
+/// Splits one argument given to the emulator into the parameters the game would have got from it on its command line.
+/// Quotes and whitespace are ASCII, so this works the same before converting to the game's code page as after.
+pub fn split_os_str(s: &OsStr) -> Vec<OsString> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        split(s.as_bytes()).into_iter().map(OsString::from_vec).collect()
+    }
+    #[cfg(not(unix))]
+    {
+        split(s.to_string_lossy().as_bytes())
+            .into_iter()
+            .map(|p| OsString::from(String::from_utf8_lossy(&p).into_owned()))
+            .collect()
+    }
+}
+
+/// Builds the list of parameters the game sees: parameter 0 is the full path to the game,
+/// and the rest are the given ones, which have already been split up.
+pub fn parameters(game_path: &Path, args: &[OsString], encoding: &'static Encoding) -> Vec<Vec<u8>> {
+    let mut params = vec![encode_path(game_path, encoding)];
+    params.extend(args.iter().map(|arg| encode_os_str(arg, encoding)));
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn split_str(s: &str) -> Vec<String> {
+        split(s.as_bytes()).into_iter().map(|p| String::from_utf8(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn split_like_paramstr() {
+        assert_eq!(split_str("a b  c"), ["a", "b", "c"]);
+        assert_eq!(split_str("  \"with space\" x"), ["with space", "x"]);
+        assert_eq!(split_str("a\"b c\"d e"), ["ab cd", "e"]);
+        assert_eq!(split_str("\"\" first"), ["first"]);
+        assert_eq!(split_str("\"unterminated quote"), ["unterminated quote"]);
+        assert!(split_str("").is_empty());
+        assert!(split_str(" \t ").is_empty());
+    }
+
+    #[test]
+    fn parameter_round_trip() {
+        let game = PathBuf::from("/some dir/game.exe");
+        let args = split_os_str(OsStr::new("-level \"my save.sav\" x"));
+        let params = parameters(&game, &args, encoding_rs::WINDOWS_1252);
+        // parameter_count doesn't include parameter 0
+        assert_eq!(params.len() - 1, 3);
+        if cfg!(not(target_os = "windows")) {
+            assert_eq!(params[0], b"/some dir/game.exe");
+        }
+        assert_eq!(params[1], b"-level");
+        assert_eq!(params[2], b"my save.sav");
+        assert_eq!(params[3], b"x");
+
+        // parameters which were already split up, like a bundled game's own command line, are passed as they are
+        let args = [OsString::from("my save.sav"), OsString::from("\"quoted\"")];
+        let params = parameters(&game, &args, encoding_rs::WINDOWS_1252);
+        assert_eq!(params[1..], [&b"my save.sav"[..], b"\"quoted\""]);
+    }
+
+    #[test]
+    fn encode_to_code_page() {
+        let sjis = b"\x83\x51\x81\x5B\x83\x80.exe"; // ゲーム.exe
+        assert_eq!(encode_os_str(OsStr::new("ゲーム.exe"), encoding_rs::SHIFT_JIS), sjis);
+        // characters outside the code page are replaced, not turned into HTML entities
+        assert_eq!(encode_os_str(OsStr::new("ゲ.exe"), encoding_rs::WINDOWS_1252), b"?.exe");
+    }
+
+    #[test]
+    fn shift_jis_filename() {
+        let sjis = b"\x83\x51\x81\x5B\x83\x80.exe"; // ゲーム.exe
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("opengmk_cmdline_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // the same name as UTF-8 works everywhere, and raw Shift-JIS works on Unix filesystems which accept it
+        let mut names = vec![OsString::from("ゲーム.exe")];
+        #[cfg(unix)]
+        names.push(<OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(sjis).to_os_string());
+        for name in names {
+            let path = dir.join(&name);
+            if fs::write(&path, b"MZ").is_err() {
+                // the filesystem can't represent this name, so there's nothing to test
+                continue
+            }
+            let path = path.canonicalize().unwrap();
+            let encoded = encode_path(&path, encoding_rs::SHIFT_JIS);
+            assert!(encoded.ends_with(sjis));
+            assert!(!encoded.starts_with(b"\\\\?\\"));
+            fs::remove_file(&path).unwrap();
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
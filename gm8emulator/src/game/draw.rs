@@ -1,10 +1,16 @@
 use crate::{
-    asset::{self, font, Font},
+    asset::{
+        self,
+        font::{self, Character},
+        Font,
+    },
     game::{Game, GetAsset, PlayType, Version},
     gml,
     math::Real,
+    types::ID,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum Halign {
@@ -20,6 +26,30 @@ pub enum Valign {
     Bottom,
 }
 
+/// What the draw pass does with an instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawKind {
+    /// Invisible instances are skipped entirely, so their draw event doesn't run either.
+    Hidden,
+    /// The object has a draw event, its own or a parent's, which runs instead of the default draw.
+    /// This is the case even if the event is empty, which some games use to hide an instance's sprite.
+    Event,
+    /// Otherwise the instance's sprite is drawn with its image_* variables, if it has a sprite.
+    Default,
+}
+
+impl DrawKind {
+    pub fn of(visible: bool, object_index: ID, draw_event_objects: &HashSet<ID>) -> Self {
+        if !visible {
+            Self::Hidden
+        } else if draw_event_objects.contains(&object_index) {
+            Self::Event
+        } else {
+            Self::Default
+        }
+    }
+}
+
 struct LineIterator<'a> {
     text: Vec<u8>,
     pos: usize,
@@ -29,6 +59,101 @@ struct LineIterator<'a> {
     word_width: i32,
 }
 
+/// A character placed by `layout_text`, relative to where the text is drawn from, before scaling and rotation.
+#[derive(Clone, Copy)]
+struct PlacedChar {
+    character: Character,
+    x: i32,
+    y: i32,
+    /// How far along its line the character starts, and how wide the line is, for draw_text_color's gradient
+    line_x: i32,
+    line_width: i32,
+}
+
+/// Splits text into lines, along with how wide each one is.
+fn collect_lines(mut iter: LineIterator) -> Vec<(Vec<u8>, i32)> {
+    let mut lines = Vec::new();
+    while let Some(line) = iter.next() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// How much room some lines of text take up.
+fn text_size(lines: &[(Vec<u8>, i32)], line_height: i32) -> (i32, i32) {
+    (lines.iter().map(|(_, width)| *width).fold(0, i32::max), lines.len() as i32 * line_height)
+}
+
+/// Places each character of some lines of text around an anchor at (0, 0) according to the alignment.
+/// string_width() and friends use the same line widths, so right-aligned text ends exactly where they say it will.
+fn layout_text(
+    lines: &[(Vec<u8>, i32)],
+    font: &Font,
+    line_height: i32,
+    halign: Halign,
+    valign: Valign,
+) -> Vec<PlacedChar> {
+    let height = text_size(lines, line_height).1;
+    let mut cursor_y = match valign {
+        Valign::Top => 0,
+        Valign::Middle => -(height / 2),
+        Valign::Bottom => -height,
+    };
+    let mut chars = Vec::new();
+    for (line, width) in lines {
+        let left_offset = match halign {
+            Halign::Left => 0,
+            Halign::Middle => -(width / 2),
+            Halign::Right => -width,
+        };
+        let mut cursor_x = left_offset;
+        for c in line.iter().copied() {
+            match font.get_char(c) {
+                Some(character) => {
+                    chars.push(PlacedChar {
+                        character,
+                        x: character.distance + cursor_x,
+                        y: cursor_y,
+                        line_x: cursor_x - left_offset,
+                        line_width: *width,
+                    });
+                    cursor_x += character.offset;
+                },
+                None => {
+                    // Space if it isn't in the font
+                    if let Some(character) = font.get_char(font.first) {
+                        cursor_x += character.offset;
+                    }
+                },
+            }
+        }
+        cursor_y += line_height;
+    }
+    chars
+}
+
+/// Where a point `(x, y)` away from the text's anchor ends up once the text is scaled, then rotated by `angle`.
+fn transform_offset(x: Real, y: Real, xscale: Real, yscale: Real, angle: Real) -> (Real, Real) {
+    let sin = angle.to_radians().sin();
+    let cos = angle.to_radians().cos();
+    (x * xscale * cos + y * yscale * sin, y * yscale * cos - x * xscale * sin)
+}
+
+/// How much of a health bar is filled for an amount out of 100. GM8 clamps the amount to between 0 and 100 first.
+pub fn healthbar_fill(amount: Real) -> f64 {
+    f64::from(amount).clamp(0.0, 100.0) / 100.0
+}
+
+/// The colour of a health bar `fill` of the way full, going from `min_colour` when empty to `max_colour` when full.
+/// Unlike merge_color, each channel is rounded down.
+pub fn healthbar_colour(fill: f64, min_colour: i32, max_colour: i32) -> i32 {
+    let channel = |shift: i32| {
+        let (min, max) = (f64::from((min_colour >> shift) & 0xFF), f64::from((max_colour >> shift) & 0xFF));
+        ((fill * max + (1.0 - fill) * min) as i32) << shift
+    };
+    channel(0) | channel(8) | channel(16)
+}
+
 impl<'a> LineIterator<'a> {
     fn next(&mut self) -> Option<(Vec<u8>, i32)> {
         if self.pos >= self.text.len() {
@@ -223,8 +348,8 @@ impl Game {
 
         // Apply room caption
         let title = self.get_window_title();
-        if self.play_type != PlayType::Record {
-            self.window.set_title(title.as_ref());
+        if let (Some(window), false) = (&self.window, self.play_type == PlayType::Record) {
+            window.set_title(title.as_ref());
         }
 
         Ok(())
@@ -274,18 +399,18 @@ impl Game {
 
         fn draw_instance(game: &mut Game, idx: usize) -> gml::Result<()> {
             let instance = game.room.instance_list.get(idx);
-            if instance.visible.get() {
+            let kind = DrawKind::of(instance.visible.get(), instance.object_index.get(), &game.custom_draw_objects);
+            if kind != DrawKind::Hidden {
                 game.renderer.set_depth(instance.depth.get().into_inner() as f32);
-                if game.custom_draw_objects.contains(&instance.object_index.get()) {
-                    // Custom draw event
-                    game.run_instance_event(gml::ev::DRAW, 0, idx, idx, None)
-                } else {
-                    // Default draw action
+            }
+            match kind {
+                DrawKind::Hidden => Ok(()),
+                DrawKind::Event => game.run_instance_event(gml::ev::DRAW, 0, idx, idx, None),
+                DrawKind::Default => {
+                    // Instances without a sprite draw nothing rather than erroring
                     let _ = game.draw_instance_default(idx);
                     Ok(())
-                }
-            } else {
-                Ok(())
+                },
             }
         }
 
@@ -467,17 +592,7 @@ impl Game {
             None => font.tallest_char_height as i32,
         };
 
-        let mut width = 0;
-        let mut line_count = 0;
-        let mut iter = self.split_string(string, max_width, font);
-        while let Some((_, current_w)) = iter.next() {
-            if width < current_w {
-                width = current_w;
-            }
-            line_count += 1;
-        }
-
-        (width, line_count * line_height)
+        text_size(&collect_lines(self.split_string(string, max_width, font)), line_height)
     }
 
     /// Draws a string to the screen at the given coordinates.
@@ -498,20 +613,14 @@ impl Game {
     ) {
         let font = self.assets.fonts.get_asset(self.draw_font_id).map(|x| x.as_ref()).unwrap_or(&self.default_font);
 
-        let sin = angle.to_radians().sin();
-        let cos = angle.to_radians().cos();
-
         // Figure out what the height of a line is if one wasn't specified
         let line_height = match line_height {
             Some(h) => h,
             None => font.tallest_char_height as i32,
         };
 
-        let mut cursor_y = match self.draw_valign {
-            Valign::Top => 0,
-            Valign::Middle => -(self.get_string_size(string.clone(), Some(line_height), max_width).1 / 2),
-            Valign::Bottom => -self.get_string_size(string.clone(), Some(line_height), max_width).1,
-        };
+        let lines = collect_lines(self.split_string(string, max_width, font));
+        let chars = layout_text(&lines, font, line_height, self.draw_halign, self.draw_valign);
 
         fn lerp_col(c1: i32, c2: i32, ratio: f64) -> i32 {
             ((f64::from(c1 & 0xff) * (1.0 - ratio) + f64::from(c2 & 0xff) * ratio) as i32 & 0xff)
@@ -519,63 +628,191 @@ impl Game {
                 + ((f64::from(c1 & 0xff0000) * (1.0 - ratio) + f64::from(c2 & 0xff0000) * ratio) as i32 & 0xff0000)
         }
 
-        let mut iter = self.split_string(string, max_width, font);
-        while let Some((line, width)) = iter.next() {
-            let left_offset = match self.draw_halign {
-                Halign::Left => 0,
-                Halign::Middle => -(width as i32 / 2),
-                Halign::Right => -width as i32,
-            };
-            let mut cursor_x = left_offset;
-
-            for c in line.iter().copied() {
-                let character = match font.get_char(c) {
-                    Some(character) => character,
-                    None => {
-                        // Space if it isn't in the font
-                        if let Some(character) = font.get_char(font.first) {
-                            cursor_x += character.offset as i32;
-                        }
-                        continue
-                    },
-                };
-
-                let xdiff = Real::from(character.distance as i32 + cursor_x);
-                let ydiff = Real::from(cursor_y);
-
-                let (xdiff, ydiff) =
-                    (xdiff * xscale * cos + ydiff * yscale * sin, ydiff * yscale * cos - xdiff * xscale * sin);
-
-                match colours {
-                    Some((c1, c2, c3, c4)) => self.renderer.draw_sprite_colour(
-                        character.atlas_ref,
-                        (x + xdiff).into(),
-                        (y + ydiff).into(),
-                        xscale.into(),
-                        yscale.into(),
-                        angle.into(),
-                        lerp_col(c1, c2, f64::from(cursor_x - left_offset) / f64::from(width)),
-                        lerp_col(c1, c2, f64::from(cursor_x - left_offset + character.offset) / f64::from(width)),
-                        lerp_col(c4, c3, f64::from(cursor_x - left_offset + character.offset) / f64::from(width)),
-                        lerp_col(c4, c3, f64::from(cursor_x - left_offset) / f64::from(width)),
-                        alpha.into(),
-                    ),
-                    None => self.renderer.draw_sprite(
-                        character.atlas_ref,
-                        (x + xdiff).into(),
-                        (y + ydiff).into(),
-                        xscale.into(),
-                        yscale.into(),
-                        angle.into(),
-                        u32::from(self.draw_colour) as i32,
-                        alpha.into(),
-                    ),
-                }
+        // GM8 blends the colours per character rather than per pixel
+        for placed in chars {
+            let (xdiff, ydiff) = transform_offset(placed.x.into(), placed.y.into(), xscale, yscale, angle);
+            let left = f64::from(placed.line_x) / f64::from(placed.line_width);
+            let right = f64::from(placed.line_x + placed.character.offset) / f64::from(placed.line_width);
+            match colours {
+                Some((c1, c2, c3, c4)) => self.renderer.draw_sprite_colour(
+                    placed.character.atlas_ref,
+                    (x + xdiff).into(),
+                    (y + ydiff).into(),
+                    xscale.into(),
+                    yscale.into(),
+                    angle.into(),
+                    lerp_col(c1, c2, left),
+                    lerp_col(c1, c2, right),
+                    lerp_col(c4, c3, right),
+                    lerp_col(c4, c3, left),
+                    alpha.into(),
+                ),
+                None => self.renderer.draw_sprite(
+                    placed.character.atlas_ref,
+                    (x + xdiff).into(),
+                    (y + ydiff).into(),
+                    xscale.into(),
+                    yscale.into(),
+                    angle.into(),
+                    u32::from(self.draw_colour) as i32,
+                    alpha.into(),
+                ),
+            }
+        }
+    }
+}
 
-                cursor_x += character.offset as i32;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{action::Tree, asset::Object, game::testing, gml::Compiler, render::atlas::AtlasRef};
+    use indexmap::IndexMap;
+    use std::{cell::RefCell, rc::Rc};
+
+    // An object with an empty draw event if `draw` is set, and with the given parent
+    fn object(draw: bool, parent_index: ID) -> Option<Box<Object>> {
+        let mut object = Object {
+            name: "".into(),
+            solid: false,
+            visible: true,
+            persistent: false,
+            depth: 0,
+            sprite_index: 0,
+            mask_index: -1,
+            parent_index,
+            events: Default::default(),
+            children: Default::default(),
+            parents: Default::default(),
+        };
+        if draw {
+            let tree = Tree::from_list(&[], &mut Compiler::new(false)).unwrap();
+            object.events[gml::ev::DRAW].insert(0, Rc::new(RefCell::new(tree)));
+        }
+        Some(Box::new(object))
+    }
+
+    #[test]
+    fn draw_event_dispatch() {
+        // 0: no draw event, 1: empty draw event, 2: child of 1, 3: grandchild of 1, 4: child of 0
+        let objects = vec![object(false, -1), object(true, -1), object(false, 1), object(false, 2), object(false, 0)];
+        for (i, object) in objects.iter().enumerate() {
+            let mut parent = Some(i as ID);
+            while let Some(id) = parent {
+                let ancestor = objects[id as usize].as_ref().unwrap();
+                ancestor.children.borrow_mut().insert(i as ID);
+                parent = Some(ancestor.parent_index).filter(|&x| x >= 0);
             }
+            object.as_ref().unwrap().parents.borrow_mut().insert(i as ID);
+        }
+        let mut event_holders: [IndexMap<u32, Rc<RefCell<Vec<ID>>>>; 12] = Default::default();
+        Game::fill_event_holders(&mut event_holders, &objects);
+        let draw_event_objects = Game::draw_event_objects(&event_holders);
+
+        let kinds = (0..5).map(|i| DrawKind::of(true, i, &draw_event_objects)).collect::<Vec<_>>();
+        assert_eq!(kinds, [DrawKind::Default, DrawKind::Event, DrawKind::Event, DrawKind::Event, DrawKind::Default]);
+        assert!((0..5).all(|i| DrawKind::of(false, i, &draw_event_objects) == DrawKind::Hidden));
+    }
+
+    // A font with just 'a', 10 pixels wide and drawn 1 pixel in, and 'b', 6 pixels wide
+    fn font() -> Font {
+        let char = |offset, distance| Character { offset, distance, atlas_ref: AtlasRef(0) };
+        Font {
+            name: "".into(),
+            sys_name: "".into(),
+            charset: 0,
+            size: 12,
+            bold: false,
+            italic: false,
+            first: b'a',
+            last: b'b',
+            tallest_char_height: 20,
+            chars: vec![char(10, 1), char(6, 0)].into_boxed_slice(),
+            own_graphics: false,
+        }
+    }
+
+    fn lines(text: &[u8], font: &Font) -> Vec<(Vec<u8>, i32)> {
+        let text = text.to_vec();
+        collect_lines(LineIterator { text, pos: 0, font, max_width: None, word_buf: Vec::new(), word_width: 0 })
+    }
+
+    #[test]
+    fn text_anchors() {
+        let font = font();
+        let lines = lines(b"ab#a", &font);
+        assert_eq!(text_size(&lines, 20), (16, 40));
+        for &(halign, left1, left2) in &[(Halign::Left, 0, 0), (Halign::Middle, -8, -5), (Halign::Right, -16, -10)] {
+            for &(valign, top) in &[(Valign::Top, 0), (Valign::Middle, -20), (Valign::Bottom, -40)] {
+                let chars = layout_text(&lines, &font, 20, halign, valign);
+                let positions = chars.iter().map(|c| (c.x, c.y)).collect::<Vec<_>>();
+                assert_eq!(positions, [(left1 + 1, top), (left1 + 10, top), (left2 + 1, top + 20)]);
+            }
+        }
+
+        // right-aligned text ends exactly at the anchor, which is what HUDs using string_width() rely on
+        let chars = layout_text(&lines, &font, 20, Halign::Right, Valign::Top);
+        assert_eq!(chars[1].x - chars[1].character.distance + chars[1].character.offset, 0);
+        assert_eq!((chars[1].line_x, chars[1].line_width), (10, 16));
+    }
+
+    #[test]
+    fn text_missing_characters() {
+        // characters which aren't in the font take up as much room as the first character in it
+        let font = font();
+        let lines = lines(b"a?a", &font);
+        assert_eq!(text_size(&lines, 20), (30, 20));
+        let chars = layout_text(&lines, &font, 20, Halign::Left, Valign::Top);
+        assert_eq!(chars.iter().map(|c| c.x).collect::<Vec<_>>(), [1, 21]);
+    }
+
+    #[test]
+    fn text_transform() {
+        let offset = |x: i32, y: i32, scale: f64, angle: f64| {
+            let (x, y) = transform_offset(x.into(), y.into(), scale.into(), scale.into(), angle.into());
+            (x.into_inner().round(), y.into_inner().round())
+        };
+        assert_eq!(offset(3, 4, 2.0, 0.0), (6.0, 8.0));
+        // rotation is anticlockwise on screen, where y points down
+        assert_eq!(offset(10, 0, 1.0, 90.0), (0.0, -10.0));
+        assert_eq!(offset(0, 10, 1.0, 90.0), (10.0, 0.0));
+        assert_eq!(offset(10, 5, 2.0, 180.0), (-20.0, -10.0));
+    }
+
+    #[test]
+    fn healthbar_gradient() {
+        // c_red to c_lime, as most games use it
+        let (red, lime) = (0x0000FF, 0x00FF00);
+        let colour = |amount: f64| healthbar_colour(healthbar_fill(amount.into()), red, lime);
+        assert_eq!(colour(0.0), red);
+        assert_eq!(colour(100.0), lime);
+        assert_eq!(colour(50.0), 0x007F7F);
+        assert_eq!(colour(25.0), 0x003FBF);
+        // out of range amounts draw an empty or full bar rather than wrapping around
+        assert_eq!((colour(-30.0), colour(250.0)), (red, lime));
+        assert_eq!((healthbar_fill((-30.0).into()), healthbar_fill(250.0.into())), (0.0, 1.0));
+
+        // every channel is blended, and the high byte of a colour is ignored
+        assert_eq!(healthbar_colour(0.5, 0x00204060, 0x7F604020), 0x404040);
+    }
 
-            cursor_y += line_height;
+    #[test]
+    fn automatic_draw_presents() {
+        let mut game = testing::synthetic_game();
+        // automatic drawing goes off on frame 30, back on on frame 60 and so on, and the game redraws once itself
+        let step = "score += 1; if (score mod 30 == 0) set_automatic_draw(score mod 60 == 0); \
+                    if (score == 45) screen_redraw()";
+        testing::set_event(&mut game, 0, gml::ev::STEP, 0, step);
+        let start = game.renderer.present_count();
+        let mut presents = Vec::new();
+        for _ in 0..120 {
+            game.frame().unwrap();
+            presents.push(game.renderer.present_count() - start);
         }
+        // frames 1 to 29 present, as do frame 45's redraw, frames 60 to 89 and frame 120
+        assert_eq!(presents[28], 29);
+        assert_eq!(presents[29], 29);
+        assert_eq!(presents[58], 30);
+        assert_eq!(presents[89], 60);
+        assert_eq!(presents[119], 61);
     }
 }
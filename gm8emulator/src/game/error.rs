@@ -0,0 +1,279 @@
+//! Errors from launching and running a game, and the reasons a game can stop normally.
+//!
+//! These are all Serialize so that anything driving the emulator from outside can tell them apart
+//! without having to pick apart the message. Errors raised by GML itself are gml::Error, which keeps
+//! track of which object event and script it came from.
+
+use crate::{gml, types::ID};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, path::PathBuf};
+
+/// Why a game stopped running, when it wasn't because of an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitReason {
+    /// The user closed the window.
+    WindowClosed,
+
+    /// The game called game_end() or an equivalent action.
+    GameEnd,
+
+    /// A replay reached its last frame and the final state was saved.
+    ReplaySaved,
+
+    /// A replay ran headless and reached its last frame with nowhere to save the final state.
+    ReplayFinished,
+}
+
+/// Something which stopped a game from starting.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum LaunchError {
+    /// Setting up the game's files or directories failed.
+    Io(#[serde(with = "io_error")] io::Error),
+
+    EmptyRoomOrder,
+    MissingFirstRoom(ID),
+
+    /// Some GML didn't compile. The location is something like "script scr_init" or "object obj_player event 0,0".
+    Compile {
+        location: String,
+        error: String,
+    },
+
+    /// An object's parent tree refers to an object that doesn't exist.
+    InvalidParent {
+        object: ID,
+        parent: ID,
+    },
+
+    /// The renderer couldn't be set up, or textures couldn't be uploaded to it.
+    Renderer(String),
+
+    /// An error raised while evaluating the game's constants.
+    Gml(gml::Error),
+}
+
+/// Something which stopped a running game, other than the game ending normally.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum RuntimeError {
+    /// An error raised while executing GML.
+    Gml(gml::Error),
+
+    /// GML didn't compile, outside of the assets which get compiled on launch.
+    Compile {
+        location: String,
+        error: String,
+    },
+
+    /// Room or instance creation code which failed to compile on launch and has now been run.
+    RoomCode(String),
+
+    InvalidRoomSize {
+        room: ID,
+        width: i32,
+        height: i32,
+    },
+    InvalidObjectInRoom {
+        room: String,
+        object: ID,
+    },
+    EmptyRoomOrder,
+
+    /// A replay's frame started with events left over from the previous one, so it's desynced.
+    StoredEventsRemaining {
+        count: usize,
+        frame: usize,
+    },
+
+    /// A replay finished but its final state couldn't be saved.
+    ReplaySave {
+        path: PathBuf,
+        error: String,
+    },
+}
+
+impl std::error::Error for LaunchError {}
+impl fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::EmptyRoomOrder => write!(f, "room order is empty"),
+            Self::MissingFirstRoom(id) => write!(f, "first room ({}) does not exist", id),
+            Self::Compile { location, error } => write!(f, "compiler error in {}: {}", location, error),
+            Self::InvalidParent { object, parent } => {
+                write!(f, "invalid parent tree for object {}: non-existent object {}", object, parent)
+            },
+            Self::Renderer(e) => write!(f, "renderer error: {}", e),
+            Self::Gml(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Gml(e) => write!(f, "{}", e),
+            Self::Compile { location, error } => write!(f, "compiler error in {}: {}", location, error),
+            Self::RoomCode(e) => write!(f, "{}", e),
+            Self::InvalidRoomSize { room, width, height } => {
+                write!(f, "bad room width/height {},{} loading room {}", width, height, room)
+            },
+            Self::InvalidObjectInRoom { room, object } => {
+                write!(f, "instance of non-existent object {} in room {}", object, room)
+            },
+            Self::EmptyRoomOrder => write!(f, "room order is empty"),
+            Self::StoredEventsRemaining { count, frame } => {
+                write!(f, "{} stored events remaining at beginning of frame {}", count, frame)
+            },
+            Self::ReplaySave { path, error } => write!(f, "error saving to {}: {}", path.display(), error),
+        }
+    }
+}
+
+impl From<io::Error> for LaunchError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<gml::Error> for LaunchError {
+    fn from(e: gml::Error) -> Self {
+        Self::Gml(e)
+    }
+}
+
+impl From<gml::Error> for RuntimeError {
+    fn from(e: gml::Error) -> Self {
+        Self::Gml(e)
+    }
+}
+
+/// Finds the first room, which the game window's initial settings come from.
+pub fn first_room<'a, T>(room_order: &[ID], rooms: &'a [Option<T>]) -> Result<(ID, &'a T), LaunchError> {
+    let &id = room_order.first().ok_or(LaunchError::EmptyRoomOrder)?;
+    match rooms.get(id as usize) {
+        Some(Some(room)) => Ok((id, room)),
+        _ => Err(LaunchError::MissingFirstRoom(id)),
+    }
+}
+
+/// io::Error isn't Serialize, so it's sent as its kind and message.
+mod io_error {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::io;
+
+    #[derive(Serialize, Deserialize)]
+    struct IoError {
+        kind: String,
+        message: String,
+    }
+
+    pub fn serialize<S: Serializer>(e: &io::Error, serializer: S) -> Result<S::Ok, S::Error> {
+        IoError { kind: format!("{:?}", e.kind()), message: e.to_string() }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<io::Error, D::Error> {
+        let e = IoError::deserialize(deserializer)?;
+        Ok(io::Error::other(e.message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset,
+        game::testing::{self, set_event},
+        gml::runtime::Error,
+    };
+
+    #[test]
+    fn missing_first_room() {
+        let rooms = [None, Some("rm_title")];
+        assert!(matches!(first_room(&[], &rooms), Err(LaunchError::EmptyRoomOrder)));
+        assert!(matches!(first_room(&[0, 1], &rooms), Err(LaunchError::MissingFirstRoom(0))));
+        assert!(matches!(first_room(&[5], &rooms), Err(LaunchError::MissingFirstRoom(5))));
+        assert!(matches!(first_room(&[1, 0], &rooms), Ok((1, &"rm_title"))));
+    }
+
+    #[test]
+    fn gml_error_context() {
+        let error = Error::NonexistentAsset(asset::Type::Sprite, 12).in_script("scr_draw_hud").in_event(
+            "obj_hud",
+            gml::ev::DRAW,
+            0,
+        );
+        match &error {
+            Error::InEvent { object, event_type, event_number, error } => {
+                assert_eq!((object.as_str(), *event_type, *event_number), ("obj_hud", gml::ev::DRAW, 0));
+                assert!(matches!(**error, Error::InScript { ref script, .. } if script == "scr_draw_hud"));
+            },
+            e => panic!("expected an event error, got {:?}", e),
+        }
+        assert!(matches!(error.root(), Error::NonexistentAsset(asset::Type::Sprite, 12)));
+
+        // Events running other events (like instance_create) keep the whole chain
+        let error = error.in_event("obj_controller", gml::ev::STEP, 0);
+        assert!(matches!(error.root(), Error::NonexistentAsset(..)));
+        assert_eq!(
+            RuntimeError::from(error).to_string(),
+            "nonexistent asset id 12 (sprite)\n\
+             in script scr_draw_hud\n\
+             in object obj_hud event 8,0\n\
+             in object obj_controller event 3,0",
+        );
+    }
+
+    #[test]
+    fn launch_errors() {
+        let mut assets = testing::synthetic_assets();
+        assets.room_order.clear();
+        assert!(matches!(testing::launch(assets), Err(LaunchError::EmptyRoomOrder)));
+
+        let mut assets = testing::synthetic_assets();
+        assets.scripts[0].as_mut().unwrap().source = "score +=".into();
+        match testing::launch(assets) {
+            Err(LaunchError::Compile { location, .. }) => assert_eq!(location, "script scr_count"),
+            Err(e) => panic!("expected a compile error, got {:?}", e),
+            Ok(_) => panic!("a script that doesn't compile was launched"),
+        }
+
+        let mut assets = testing::synthetic_assets();
+        assets.objects[0].as_mut().unwrap().parent_index = 7;
+        assert!(matches!(testing::launch(assets), Err(LaunchError::InvalidParent { object: 0, parent: 7 })));
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let mut assets = testing::synthetic_assets();
+        assets.library_init_strings.push("score +=".into());
+        let mut game = testing::launch(assets).unwrap();
+        match game.init() {
+            Err(RuntimeError::Compile { location, .. }) => assert_eq!(location, "library initialization code 0"),
+            r => panic!("expected a compile error, got {:?}", r),
+        }
+
+        // an error in a script called from an event says where it came from
+        let mut assets = testing::synthetic_assets();
+        assets.scripts[0].as_mut().unwrap().source = "instance_change(99, false)".into();
+        let mut game = testing::launch(assets).unwrap();
+        game.init().unwrap();
+        set_event(&mut game, 0, gml::ev::STEP, 0, "scr_count()");
+        let error = game.frame().unwrap_err();
+        match &error {
+            Error::InEvent { object, event_type: gml::ev::STEP, event_number: 0, error } => {
+                assert_eq!(object, "obj_counter");
+                assert!(matches!(**error, Error::InScript { ref script, .. } if script == "scr_count"));
+            },
+            e => panic!("expected an event error, got {:?}", e),
+        }
+        assert!(matches!(error.root(), Error::NonexistentAsset(asset::Type::Object, 99)));
+
+        assert!(matches!(game.load_room(5), Err(RuntimeError::Gml(Error::NonexistentAsset(asset::Type::Room, 5)))));
+        game.assets.objects[0] = None;
+        match game.load_room(0) {
+            Err(RuntimeError::InvalidObjectInRoom { room, object: 0 }) => assert_eq!(room, "rm_start"),
+            r => panic!("expected an invalid object error, got {:?}", r),
+        }
+    }
+}
@@ -1,16 +1,75 @@
 use crate::{
-    asset::trigger::TriggerTime,
-    game::{Game, GetAsset},
+    asset::{trigger::TriggerTime, Sprite},
+    game::{movement, Game, GetAsset},
     gml,
     input::MouseButton,
     instance::Instance,
+    math::Real,
     types::ID,
 };
 use std::convert::TryFrom;
 
+/// Whether lives or health going from `old` to `new` makes the no more lives or no more health event due.
+/// That only happens when it goes from above 0 to 0 or below, so lives starting at -1 doesn't count.
+fn ran_out<T: PartialOrd + Default>(old: T, new: T) -> bool {
+    old > T::default() && new <= T::default()
+}
+
+/// Counts an alarm down by a step, returning whether its event should run now.
+/// As in GM8, only alarms at 0 or above count down, and the event runs when one gets to 0, so it reads as 0 during
+/// its own event and turns off (-1) on the next step. That means setting an alarm to 0 turns it off without running
+/// the event, and other negative numbers stay where they are.
+pub fn tick_alarm(alarm: &mut i32) -> bool {
+    if *alarm >= 0 {
+        *alarm -= 1;
+        *alarm == 0
+    } else {
+        false
+    }
+}
+
+/// Whether an instance is entirely outside a rectangle, as in the Outside Room and Outside View events.
+/// If it has a mask, its bounding box has to be strictly past one of the edges. Since the bbox is inclusive and
+/// `x2` is the first pixel outside the room, that means GM8 fires this one pixel later on the right and bottom.
+/// Without a mask, its x/y point is used instead, and it's outside as soon as it's past an edge at all.
+/// The bbox is brought up to date first, so movement earlier in the step is taken into account.
+pub fn outside_rect(instance: &Instance, mask: Option<&Sprite>, (x1, y1, x2, y2): (i32, i32, i32, i32)) -> bool {
+    if mask.is_some() {
+        instance.update_bbox(mask);
+        instance.bbox_right.get() < x1
+            || instance.bbox_bottom.get() < y1
+            || instance.bbox_left.get() > x2
+            || instance.bbox_top.get() > y2
+    } else {
+        let (x, y) = (f64::from(instance.x.get()), f64::from(instance.y.get()));
+        (x.floor() as i32) < x1 || (y.floor() as i32) < y1 || (x.ceil() as i32) > x2 || (y.ceil() as i32) > y2
+    }
+}
+
+/// Whether an instance is even partly outside a rectangle, as in the Intersect Boundary and Intersect View events.
+/// Like `outside_rect`, a bbox edge has to be strictly past the rectangle's edge. Instances without a mask only
+/// have a point, so for them this is the same as `outside_rect`.
+pub fn intersects_rect_edge(
+    instance: &Instance,
+    mask: Option<&Sprite>,
+    (x1, y1, x2, y2): (i32, i32, i32, i32),
+) -> bool {
+    if mask.is_some() {
+        instance.update_bbox(mask);
+        instance.bbox_left.get() < x1
+            || instance.bbox_top.get() < y1
+            || instance.bbox_right.get() > x2
+            || instance.bbox_bottom.get() > y2
+    } else {
+        outside_rect(instance, mask, (x1, y1, x2, y2))
+    }
+}
+
 impl Game {
     /// Runs an event for all objects which hold the given event.
     /// If no "other" instance is provided, "self" will be used as "other". This is what GM8 tends to do.
+    /// Each object's instances are gathered when it gets its turn, so like in GM8, an instance created by an object
+    /// earlier in the list does get the event, while one created by its own object or a later one doesn't.
     pub fn run_object_event(&mut self, event_id: usize, event_sub: u32, other: Option<usize>) -> gml::Result<()> {
         let holders = match self.event_holders.get(event_id).and_then(|x| x.get(&event_sub)) {
             Some(e) => e.clone(),
@@ -36,6 +95,33 @@ impl Game {
         Ok(())
     }
 
+    /// Sets lives, making the no more lives event due if they've just dropped to 0 or below.
+    pub fn set_lives(&mut self, lives: i32) {
+        self.lives_ran_out |= ran_out(self.lives, lives);
+        self.lives = lives;
+    }
+
+    /// Sets health, making the no more health event due if it's just dropped to 0 or below.
+    pub fn set_health(&mut self, health: Real) {
+        self.health_ran_out |= ran_out(self.health, health);
+        self.health = health;
+    }
+
+    /// Runs the no more lives and no more health events if they're due. GM8 only checks for these once per step,
+    /// straight after the step event, so they never run in the middle of whatever changed lives or health.
+    pub fn run_ran_out_events(&mut self) -> gml::Result<()> {
+        if std::mem::take(&mut self.lives_ran_out) {
+            self.run_other_event(6)?;
+            if self.scene_change.is_some() {
+                return Ok(())
+            }
+        }
+        if std::mem::take(&mut self.health_ran_out) {
+            self.run_other_event(9)?;
+        }
+        Ok(())
+    }
+
     /// Runs an event for a given instance. Does nothing if that instance doesn't have the specified event.
     pub fn run_instance_event(
         &mut self,
@@ -78,7 +164,10 @@ impl Game {
                 }
             };
 
-            self.execute_tree(event, instance, other, event_id, event_sub as _, object_id)
+            self.execute_tree(event, instance, other, event_id, event_sub as _, object_id).map_err(|e| {
+                let object = self.assets.objects.get_asset(object_id).map(|o| o.name.to_string()).unwrap_or_default();
+                e.in_event(object, event_id, event_sub)
+            })
         } else {
             Ok(())
         }
@@ -150,14 +239,7 @@ impl Game {
                     while let Some(handle) = iter.next(&self.room.instance_list) {
                         // Check if this has the alarm set
                         let instance = self.room.instance_list.get(handle);
-                        let run_event = match instance.alarms.borrow_mut().get_mut(&alarm_id) {
-                            Some(alarm) if *alarm >= 0 => {
-                                // Decrement it, run the event if it hit 0
-                                *alarm -= 1;
-                                *alarm == 0
-                            },
-                            _ => false,
-                        };
+                        let run_event = instance.alarms.borrow_mut().get_mut(&alarm_id).is_some_and(tick_alarm);
                         if run_event {
                             self.run_instance_event(gml::ev::ALARMS, alarm_id, handle, handle, None)?;
                         }
@@ -420,157 +502,37 @@ impl Game {
     }
 
     /// Runs all outside room, intersect boundary, and outside/intersect view events.
+    /// These aren't edge-triggered: they run every step for as long as the condition holds.
     pub fn run_bound_events(&mut self) -> gml::Result<()> {
-        fn instance_outside_rect(i: &Instance, x1: i32, y1: i32, x2: i32, y2: i32) -> bool {
-            i.bbox_right.get() < x1 || i.bbox_bottom.get() < y1 || i.bbox_left.get() > x2 || i.bbox_top.get() > y2
-        }
-
-        fn point_outside_rect(x: f64, y: f64, x1: i32, y1: i32, x2: i32, y2: i32) -> bool {
-            (x.floor() as i32) < x1 || (y.floor() as i32) < y1 || (x.ceil() as i32) > x2 || (y.ceil() as i32 > y2)
-        }
-
-        fn instance_intersect_rect(i: &Instance, x1: i32, y1: i32, x2: i32, y2: i32) -> bool {
-            i.bbox_left.get() < x1 || i.bbox_top.get() < y1 || i.bbox_right.get() > x2 || i.bbox_bottom.get() > y2
-        }
-
-        // Outside room events
-        if let Some(holders) = self.event_holders.get(gml::ev::OTHER).and_then(|x| x.get(&0)) {
-            let holders = holders.clone();
-            let mut position = 0;
-            while let Some(&object_id) = holders.borrow().get(position) {
-                let mut iter = self.room.instance_list.iter_by_object(object_id);
-                while let Some(handle) = iter.next(&self.room.instance_list) {
-                    let instance = self.room.instance_list.get(handle);
-                    let mask = self.get_instance_mask_sprite(handle);
-
-                    let outside = if mask.is_some() {
-                        instance.update_bbox(mask);
-                        instance_outside_rect(instance, 0, 0, self.room.width, self.room.height)
-                    } else {
-                        point_outside_rect(
-                            instance.x.get().into(),
-                            instance.y.get().into(),
-                            0,
-                            0,
-                            self.room.width,
-                            self.room.height,
-                        )
-                    };
-                    if outside {
-                        self.run_instance_event(gml::ev::OTHER, 0, handle, handle, None)?;
-                    }
-                }
-                position += 1;
-            }
-        }
-
-        // Intersect room boundary events
-        if let Some(holders) = self.event_holders.get(gml::ev::OTHER).and_then(|x| x.get(&1)) {
-            let holders = holders.clone();
-            let mut position = 0;
-            while let Some(&object_id) = holders.borrow().get(position) {
-                let mut iter = self.room.instance_list.iter_by_object(object_id);
-                while let Some(handle) = iter.next(&self.room.instance_list) {
-                    let instance = self.room.instance_list.get(handle);
-                    let mask = self.get_instance_mask_sprite(handle);
-
-                    let intersect = if mask.is_some() {
-                        instance.update_bbox(mask);
-                        instance_intersect_rect(instance, 0, 0, self.room.width, self.room.height)
-                    } else {
-                        point_outside_rect(
-                            instance.x.get().into(),
-                            instance.y.get().into(),
-                            0,
-                            0,
-                            self.room.width,
-                            self.room.height,
-                        )
-                    };
-                    if intersect {
-                        self.run_instance_event(gml::ev::OTHER, 1, handle, handle, None)?;
-                    }
-                }
-                position += 1;
-            }
-        }
-
         let view_count = self.room.views.len().min(8);
 
-        // Outside view events
-        for i in 0..view_count {
-            let event_number = (40 + i) as u32;
+        // Outside room, then intersect boundary, then outside each view, then intersect each view
+        let checks = [(0, None, false), (1, None, true)]
+            .iter()
+            .copied()
+            .chain((0..view_count).map(|i| (40 + i as u32, Some(i), false)))
+            .chain((0..view_count).map(|i| (50 + i as u32, Some(i), true)))
+            .collect::<Vec<_>>();
+        for (event_number, view, intersect) in checks {
             if let Some(holders) = self.event_holders.get(gml::ev::OTHER).and_then(|x| x.get(&event_number)) {
                 let holders = holders.clone();
                 let mut position = 0;
                 while let Some(&object_id) = holders.borrow().get(position) {
                     let mut iter = self.room.instance_list.iter_by_object(object_id);
                     while let Some(handle) = iter.next(&self.room.instance_list) {
-                        let instance = self.room.instance_list.get(handle);
-                        let mask = self.get_instance_mask_sprite(handle);
-                        let view = &self.room.views[i];
-
-                        let outside = if mask.is_some() {
-                            instance.update_bbox(mask);
-                            instance_outside_rect(
-                                instance,
-                                view.source_x,
-                                view.source_y,
-                                view.source_x + view.source_w,
-                                view.source_y + view.source_h,
-                            )
-                        } else {
-                            point_outside_rect(
-                                instance.x.get().into(),
-                                instance.y.get().into(),
-                                view.source_x,
-                                view.source_y,
-                                view.source_x + view.source_w,
-                                view.source_y + view.source_h,
-                            )
+                        // Events can move views or change the room size, so this is looked up for every instance
+                        let rect = match view.and_then(|i| self.room.views.get(i)) {
+                            Some(v) => (v.source_x, v.source_y, v.source_x + v.source_w, v.source_y + v.source_h),
+                            None => (0, 0, self.room.width, self.room.height),
                         };
-                        if outside {
-                            self.run_instance_event(gml::ev::OTHER, event_number, handle, handle, None)?;
-                        }
-                    }
-                    position += 1;
-                }
-            }
-        }
-
-        // Intersect view events
-        for i in 0..view_count {
-            let event_number = (50 + i) as u32;
-            if let Some(holders) = self.event_holders.get(gml::ev::OTHER).and_then(|x| x.get(&event_number)) {
-                let holders = holders.clone();
-                let mut position = 0;
-                while let Some(&object_id) = holders.borrow().get(position) {
-                    let mut iter = self.room.instance_list.iter_by_object(object_id);
-                    while let Some(handle) = iter.next(&self.room.instance_list) {
                         let instance = self.room.instance_list.get(handle);
                         let mask = self.get_instance_mask_sprite(handle);
-                        let view = &self.room.views[i];
-
-                        let intersect = if mask.is_some() {
-                            instance.update_bbox(mask);
-                            instance_intersect_rect(
-                                instance,
-                                view.source_x,
-                                view.source_y,
-                                view.source_x + view.source_w,
-                                view.source_y + view.source_h,
-                            )
+                        let fires = if intersect {
+                            intersects_rect_edge(instance, mask, rect)
                         } else {
-                            point_outside_rect(
-                                instance.x.get().into(),
-                                instance.y.get().into(),
-                                view.source_x,
-                                view.source_y,
-                                view.source_x + view.source_w,
-                                view.source_y + view.source_h,
-                            )
+                            outside_rect(instance, mask, rect)
                         };
-                        if intersect {
+                        if fires {
                             self.run_instance_event(gml::ev::OTHER, event_number, handle, handle, None)?;
                         }
                     }
@@ -603,12 +565,8 @@ impl Game {
                             let inst1 = self.room.instance_list.get(instance);
                             let inst2 = self.room.instance_list.get(target);
                             if inst1.solid.get() || inst2.solid.get() {
-                                for inst in [inst1, inst2] {
-                                    inst.x.set(inst.xprevious.get());
-                                    inst.y.set(inst.yprevious.get());
-                                    inst.bbox_is_stale.set(true);
-                                    inst.path_position.set(inst.path_positionprevious.get());
-                                }
+                                movement::revert_to_previous(inst1);
+                                movement::revert_to_previous(inst2);
                             }
 
                             // Run both collision events
@@ -624,14 +582,8 @@ impl Game {
 
                                 // If they're still colliding, move them back again
                                 if inst1.is_active() && inst2.is_active() && self.check_collision(instance, target) {
-                                    inst1.x.set(inst1.xprevious.get());
-                                    inst1.y.set(inst1.yprevious.get());
-                                    inst1.bbox_is_stale.set(true);
-                                    inst1.path_position.set(inst1.path_positionprevious.get());
-                                    inst2.x.set(inst2.xprevious.get());
-                                    inst2.y.set(inst2.yprevious.get());
-                                    inst2.bbox_is_stale.set(true);
-                                    inst2.path_position.set(inst2.path_positionprevious.get());
+                                    movement::revert_to_previous(inst1);
+                                    movement::revert_to_previous(inst2);
                                 }
                             }
 
@@ -650,3 +602,109 @@ impl Game {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::testing::{self, set_event, sprite},
+        math::Real,
+    };
+
+    // Moves an instance right across a 100x100 room at a sub-pixel speed, like apply_speeds would,
+    // and returns the steps on which the intersect boundary and outside room events would run.
+    fn fire_steps(mask: Option<&Sprite>, x: f64, speed: f64, steps: usize) -> (Vec<usize>, Vec<usize>) {
+        let room = (0, 0, 100, 100);
+        let instance = Instance::new_dummy(None);
+        instance.x.set(Real::from(x));
+        instance.y.set(Real::from(50.0));
+        let (mut intersect, mut outside) = (Vec::new(), Vec::new());
+        for step in 0..steps {
+            instance.x.set(instance.x.get() + Real::from(speed));
+            instance.bbox_is_stale.set(true);
+            if intersects_rect_edge(&instance, mask, room) {
+                intersect.push(step);
+            }
+            if outside_rect(&instance, mask, room) {
+                outside.push(step);
+            }
+        }
+        (intersect, outside)
+    }
+
+    // Sets an alarm to `start`, then counts it down, returning which steps the event would run on
+    // and what it reads as afterwards
+    fn alarm_steps(start: i32, steps: usize) -> (Vec<usize>, i32) {
+        let mut alarm = start;
+        let fired = (0..steps).filter(|_| tick_alarm(&mut alarm)).collect();
+        (fired, alarm)
+    }
+
+    #[test]
+    fn alarms() {
+        assert_eq!(alarm_steps(3, 5), (vec![2], -1));
+        assert_eq!(alarm_steps(1, 1), (vec![0], 0));
+        assert_eq!(alarm_steps(0, 5), (vec![], -1));
+        assert_eq!(alarm_steps(-1, 5), (vec![], -1));
+        assert_eq!(alarm_steps(-5, 5), (vec![], -5));
+    }
+
+    #[test]
+    fn running_out() {
+        // lives as a game might set them over a few steps, and whether the event is due after each one
+        let lives = [-1, 3, 2, 0, -1, 0, 1, 0];
+        let due = lives.windows(2).map(|x| ran_out(x[0], x[1])).collect::<Vec<_>>();
+        assert_eq!(due, [false, false, true, false, false, false, true]);
+
+        assert!(ran_out(Real::from(0.5), Real::from(-20.0)));
+        assert!(!ran_out(Real::from(0.0), Real::from(-20.0)));
+        assert!(!ran_out(Real::from(100.0), Real::from(0.01)));
+    }
+
+    #[test]
+    fn running_out_in_game() {
+        let mut game = testing::synthetic_game();
+        let step = "lives = 0; score += 1; if (health == 50) { health = 0; room_restart() }";
+        set_event(&mut game, 0, gml::ev::STEP, 0, step);
+        set_event(&mut game, 0, gml::ev::OTHER, 6, "score *= 10");
+        set_event(&mut game, 0, gml::ev::OTHER, 9, "score += 1000");
+        game.lives = 3;
+
+        // the no more lives event runs once the whole step event is done, and not again while lives stay at 0
+        game.frame().unwrap();
+        assert_eq!(game.score, 10);
+        game.frame().unwrap();
+        assert_eq!(game.score, 11);
+
+        // health runs out in the same step as the room restarts, so the no more health event never gets to run
+        game.health = Real::from(50.0);
+        game.frame().unwrap();
+        assert_eq!(game.score, 12);
+        game.load_room(game.room.id).unwrap();
+        game.frame().unwrap();
+        assert_eq!(game.score, 13);
+    }
+
+    #[test]
+    fn bbox_edges() {
+        // bbox_right = round(x + 15) first passes 100 at x = 85.6, and bbox_left passes it at x = 100.8,
+        // and both keep firing from then on
+        let (intersect, outside) = fire_steps(Some(&sprite()), 80.0, 0.4, 60);
+        assert_eq!(intersect, (13..60).collect::<Vec<_>>());
+        assert_eq!(outside, (51..60).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn point_edges() {
+        // without a mask the origin is used, and x = 100 itself is still inside
+        let (intersect, outside) = fire_steps(None, 98.0, 0.5, 10);
+        assert_eq!(outside, (4..10).collect::<Vec<_>>());
+        assert_eq!(intersect, outside);
+
+        let instance = Instance::new_dummy(None);
+        instance.x.set(Real::from(-0.1));
+        assert!(outside_rect(&instance, None, (0, 0, 100, 100)));
+        instance.x.set(Real::from(0.0));
+        assert!(!outside_rect(&instance, None, (0, 0, 100, 100)));
+    }
+}
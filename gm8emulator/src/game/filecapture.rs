@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Files larger than this are never captured, since restoring them on every load would be unbearably slow.
+pub const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// If a single capture is larger than this in total, a warning will be printed.
+pub const WARN_TOTAL_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A content-addressed store for the files a game writes during record mode.
+/// Every distinct file content is only written to the store once, no matter how many savestates reference it.
+pub struct FileStore {
+    store_path: PathBuf,
+    directories: Vec<PathBuf>,
+    excluded: Vec<PathBuf>,
+    max_file_size: u64,
+    // When the store was created, and which files each directory had then, so that restoring never deletes a file
+    // which wasn't written while capturing.
+    started: SystemTime,
+    existing: Vec<HashSet<PathBuf>>,
+}
+
+/// A snapshot of the contents of the captured directories, as stored in a savestate.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    // One list of (relative path, blob name) pairs per captured directory, in the same order as the FileStore's.
+    // Files which were too large to capture have no blob, and are left alone when restoring.
+    directories: Vec<Vec<(PathBuf, Option<String>)>>,
+}
+
+impl FileStore {
+    /// Creates a FileStore which keeps its blobs in `project_path/files` and captures the given directories.
+    /// Anything in `excluded`, and the project itself, is never captured or restored, even inside those directories -
+    /// so the game's folder can be captured for its save files without taking the exe and the savestates with it.
+    pub fn new(project_path: &Path, directories: Vec<PathBuf>, excluded: Vec<PathBuf>) -> io::Result<Self> {
+        let mut store_path = project_path.to_path_buf();
+        store_path.push("files");
+        fs::create_dir_all(&store_path)?;
+        let canonical = |path: PathBuf| path.canonicalize().unwrap_or(path);
+        let directories = directories.into_iter().map(canonical).collect::<Vec<_>>();
+        let excluded = excluded.into_iter().chain(Some(project_path.to_path_buf())).map(canonical).collect::<Vec<_>>();
+        let started = SystemTime::now();
+        let mut existing = Vec::with_capacity(directories.len());
+        for dir in &directories {
+            let mut paths = Vec::new();
+            if dir.is_dir() {
+                list_files(dir, Path::new(""), &excluded, &mut paths)?;
+            }
+            existing.push(paths.into_iter().collect());
+        }
+        Ok(Self { store_path, directories, excluded, max_file_size: MAX_FILE_SIZE, started, existing })
+    }
+
+    /// Takes a snapshot of all the captured directories, adding any new file contents to the store.
+    pub fn capture(&self) -> io::Result<FileSnapshot> {
+        let mut total_size = 0;
+        let mut directories = Vec::with_capacity(self.directories.len());
+        for dir in &self.directories {
+            let mut paths = Vec::new();
+            if dir.is_dir() {
+                list_files(dir, Path::new(""), &self.excluded, &mut paths)?;
+            }
+            let mut files = Vec::with_capacity(paths.len());
+            for path in paths {
+                let full_path = dir.join(&path);
+                let len = fs::metadata(&full_path)?.len();
+                if len > self.max_file_size {
+                    eprintln!("warning: not capturing {:?} as it is too large ({} bytes)", full_path, len);
+                    files.push((path, None));
+                    continue
+                }
+                let data = fs::read(&full_path)?;
+                let blob = blob_name(&data);
+                let blob_path = self.store_path.join(&blob);
+                if !blob_path.exists() {
+                    fs::write(&blob_path, &data)?;
+                }
+                total_size += len;
+                files.push((path, Some(blob)));
+            }
+            directories.push(files);
+        }
+        if total_size > WARN_TOTAL_SIZE {
+            eprintln!(
+                "warning: captured {} MiB of files in this savestate; saving and loading may be slow",
+                total_size / (1024 * 1024)
+            );
+        }
+        Ok(FileSnapshot { directories })
+    }
+
+    /// Restores the captured directories to the state they were in when the snapshot was taken.
+    /// Files which didn't exist at that time are deleted, as long as they were written since the store was created.
+    /// Files which haven't changed, or which were too large to capture, are left alone.
+    pub fn restore(&self, snapshot: &FileSnapshot) -> io::Result<()> {
+        let dirs = self.directories.iter().zip(&self.existing);
+        for ((dir, existing), files) in dirs.zip(snapshot.directories.iter()) {
+            let mut wanted = files.iter().cloned().collect::<HashMap<_, _>>();
+            if dir.is_dir() {
+                let mut current = Vec::new();
+                list_files(dir, Path::new(""), &self.excluded, &mut current)?;
+                for path in current {
+                    let full_path = dir.join(&path);
+                    match wanted.get(&path) {
+                        Some(Some(blob)) if *blob != blob_name(&fs::read(&full_path)?) => fs::remove_file(&full_path)?,
+                        Some(_) => {
+                            wanted.remove(&path);
+                        },
+                        None => {
+                            let modified = fs::metadata(&full_path)?.modified()?;
+                            if !existing.contains(&path) || modified >= self.started {
+                                fs::remove_file(&full_path)?;
+                            }
+                        },
+                    }
+                }
+            }
+            for (path, blob) in wanted.into_iter().filter_map(|(path, blob)| Some((path, blob?))) {
+                let path = dir.join(path);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(self.store_path.join(blob), path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recursively lists all the files in a directory, relative to the given root, leaving out any excluded ones.
+fn list_files(root: &Path, relative: &Path, excluded: &[PathBuf], files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if excluded.contains(&root.join(&path)) {
+            continue
+        }
+        if entry.metadata()?.is_dir() {
+            list_files(root, &path, excluded, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Gets the name a blob with the given content is stored under, from its FNV-1a hash and its length.
+fn blob_name(data: &[u8]) -> String {
+    format!("{:016x}-{}", gm8exe::reader::fnv1a(data), data.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileStore;
+    use std::fs;
+
+    #[test]
+    fn capture_restore() {
+        let mut project = std::env::temp_dir();
+        project.push(format!("opengmk_filecapture_{}", std::process::id()));
+        let temp = project.join("gm_ttt_0");
+        fs::create_dir_all(temp.join("sub")).unwrap();
+        fs::write(temp.join("save.ini"), b"[a]\nb=1\n").unwrap();
+        fs::write(temp.join("sub").join("data.bin"), b"data").unwrap();
+
+        let store = FileStore::new(&project, vec![temp.clone()], Vec::new()).unwrap();
+        let snapshot = store.capture().unwrap();
+        let blob_count = fs::read_dir(project.join("files")).unwrap().count();
+
+        // the same contents captured again must not grow the store
+        store.capture().unwrap();
+        assert_eq!(fs::read_dir(project.join("files")).unwrap().count(), blob_count);
+
+        fs::write(temp.join("save.ini"), b"[a]\nb=2\n").unwrap();
+        fs::write(temp.join("new.txt"), b"from the future").unwrap();
+        fs::remove_file(temp.join("sub").join("data.bin")).unwrap();
+        store.restore(&snapshot).unwrap();
+
+        assert_eq!(fs::read(temp.join("save.ini")).unwrap(), b"[a]\nb=1\n");
+        assert_eq!(fs::read(temp.join("sub").join("data.bin")).unwrap(), b"data");
+        assert!(!temp.join("new.txt").exists());
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn untouched_files() {
+        let mut project = std::env::temp_dir();
+        project.push(format!("opengmk_filecapture_untouched_{}", std::process::id()));
+        let dir = project.join("saves");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("notes.txt"), b"the user's").unwrap();
+
+        let mut store = FileStore::new(&project, vec![dir.clone()], Vec::new()).unwrap();
+        store.max_file_size = 4;
+        // a file from before capturing started, which isn't there when the snapshot is taken
+        fs::rename(dir.join("notes.txt"), project.join("notes.txt")).unwrap();
+        fs::write(dir.join("big.bin"), b"too large").unwrap();
+        let snapshot = store.capture().unwrap();
+
+        fs::rename(project.join("notes.txt"), dir.join("notes.txt")).unwrap();
+        fs::write(dir.join("big.bin"), b"still too large").unwrap();
+        fs::write(dir.join("new.txt"), b"from the future").unwrap();
+        store.restore(&snapshot).unwrap();
+
+        assert_eq!(fs::read(dir.join("notes.txt")).unwrap(), b"the user's");
+        assert_eq!(fs::read(dir.join("big.bin")).unwrap(), b"still too large");
+        assert!(!dir.join("new.txt").exists());
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn excluded() {
+        // the game's folder, with the game's saves, the exe and the project all in it
+        let mut game = std::env::temp_dir();
+        game.push(format!("opengmk_filecapture_excluded_{}", std::process::id()));
+        let project = game.join("projects").join("tas");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(game.join("game.exe"), b"MZ").unwrap();
+        fs::write(game.join("save1"), b"old").unwrap();
+
+        let store = FileStore::new(&project, vec![game.clone()], vec![game.join("game.exe")]).unwrap();
+        let snapshot = store.capture().unwrap();
+        fs::write(game.join("save1"), b"new").unwrap();
+        fs::write(game.join("game.exe"), b"MZ patched").unwrap();
+        fs::write(project.join("save.bin"), b"a savestate").unwrap();
+        store.restore(&snapshot).unwrap();
+
+        assert_eq!(fs::read(game.join("save1")).unwrap(), b"old");
+        assert_eq!(fs::read(game.join("game.exe")).unwrap(), b"MZ patched");
+        assert!(project.join("save.bin").exists());
+
+        fs::remove_dir_all(&game).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Saves what's on screen to image files: every so often during a replay with `--dump-frames`, so a run can be
+//! checked without watching it, and when F9 is pressed in games that let it take a screenshot.
+
+use crate::{
+    asset,
+    game::{Game, PlayType},
+    gml::file,
+    input,
+};
+use image::RgbaImage;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+/// How many frames apart dumps are if `--dump-frames` isn't given a number.
+pub const DEFAULT_INTERVAL: usize = 60;
+
+/// How many frames can be waiting to be written before dumping another one waits for the oldest to be done,
+/// so that a replay running much faster than PNGs can be encoded doesn't keep every frame in memory.
+const QUEUE_LENGTH: usize = 4;
+
+/// Parses the argument to `--dump-frames`, which is a directory optionally followed by `:N` to dump every N frames.
+/// Anything after the last colon that isn't a number is taken to be part of the directory, like `C:\frames`.
+pub fn parse_arg(arg: &str) -> Result<(PathBuf, usize), String> {
+    let (dir, interval) = match arg.rfind(':') {
+        Some(i) if i + 1 < arg.len() && arg[i + 1..].bytes().all(|x| x.is_ascii_digit()) => {
+            let interval = arg[i + 1..].parse::<usize>().map_err(|e| format!("bad interval: {}", e))?;
+            (&arg[..i], interval)
+        },
+        _ => (arg, DEFAULT_INTERVAL),
+    };
+    if dir.is_empty() {
+        Err("no directory given".into())
+    } else if interval == 0 {
+        Err("the interval must be at least 1 frame".into())
+    } else {
+        Ok((PathBuf::from(dir), interval))
+    }
+}
+
+/// Writes a numbered PNG of the screen every `interval` frames of a replay.
+/// Encoding and writing happen on a background thread, so dumping doesn't hold up the frame loop unless that falls
+/// behind by more than `QUEUE_LENGTH` frames.
+pub struct FrameDumper {
+    dir: PathBuf,
+    interval: usize,
+    sender: Option<mpsc::SyncSender<(PathBuf, RgbaImage)>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl FrameDumper {
+    /// Creates a dumper which writes into `dir`, creating it first if it doesn't exist.
+    pub fn new(dir: PathBuf, interval: usize) -> Result<Self, String> {
+        fs::create_dir_all(&dir).map_err(|e| format!("couldn't create '{}': {}", dir.display(), e))?;
+        let (sender, receiver) = mpsc::sync_channel::<(PathBuf, RgbaImage)>(QUEUE_LENGTH);
+        let thread = thread::spawn(move || {
+            while let Ok((path, image)) = receiver.recv() {
+                if let Err(e) = file::save_image(&path, image) {
+                    eprintln!("failed to write frame '{}': {}", path.display(), e);
+                }
+            }
+        });
+        Ok(Self { dir, interval, sender: Some(sender), thread: Some(thread) })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether the given frame of the replay should be dumped. The first frame always is.
+    pub fn is_due(&self, frame: usize) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    /// Queues an image of a frame to be written as `frame<N>.png`, padded so the files sort in order.
+    /// If the queue is full, this waits until there's room.
+    pub fn dump(&self, frame: usize, image: RgbaImage) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send((self.dir.join(frame_file_name(frame)), image));
+        }
+    }
+}
+
+impl Drop for FrameDumper {
+    /// Waits for every queued frame to be written.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn frame_file_name(frame: usize) -> String {
+    format!("frame{:06}.png", frame)
+}
+
+/// The name of the `index`th F9 screenshot. GM8 calls them screenshot000.bmp, screenshot001.bmp and so on,
+/// using the first name that isn't already taken.
+fn screenshot_name(index: usize) -> String {
+    format!("screenshot{:03}.bmp", index)
+}
+
+impl Game {
+    /// Reads the screen back as an opaque image, as screen_save and screenshots save it.
+    pub fn screen_image(&mut self) -> RgbaImage {
+        self.renderer.flush_queue();
+        let (width, height) = (self.unscaled_width, self.unscaled_height);
+        let rgba = self.renderer.get_pixels(0, 0, width as _, height as _);
+        let mut image = RgbaImage::from_vec(width, height, rgba.into()).unwrap();
+        asset::sprite::process_image(&mut image, false, false, true);
+        image
+    }
+
+    /// Saves a screenshot if F9 has just been pressed and the game's settings allow it. As in GM8, the game still
+    /// gets the keypress afterwards. Nothing is saved in record mode, where the screen is only drawn for the UI,
+    /// or when running headless, where it's never drawn at all.
+    pub fn check_screenshot_key(&mut self) {
+        if !self.f9_screenshot
+            || self.play_type == PlayType::Record
+            || !self.input.keyboard_check_pressed(input::Button::F9 as u8)
+        {
+            return
+        }
+        if self.window.is_none() {
+            eprintln!("warning: not saving a screenshot, since nothing is drawn when headless");
+            return
+        }
+        let mut index = 0;
+        while Path::new(&screenshot_name(index)).exists() {
+            index += 1;
+        }
+        let path = screenshot_name(index);
+        let image = self.screen_image();
+        if let Err(e) = file::save_image(&path, image) {
+            eprintln!("failed to save screenshot '{}': {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args() {
+        assert_eq!(parse_arg("frames"), Ok((PathBuf::from("frames"), DEFAULT_INTERVAL)));
+        assert_eq!(parse_arg("frames:30"), Ok((PathBuf::from("frames"), 30)));
+        assert_eq!(parse_arg("out/run 1:1"), Ok((PathBuf::from("out/run 1"), 1)));
+        // a colon that isn't followed by a number is part of the path
+        assert_eq!(parse_arg(r"C:\frames"), Ok((PathBuf::from(r"C:\frames"), DEFAULT_INTERVAL)));
+        assert_eq!(parse_arg(r"C:\frames:10"), Ok((PathBuf::from(r"C:\frames"), 10)));
+        assert_eq!(parse_arg("frames:"), Ok((PathBuf::from("frames:"), DEFAULT_INTERVAL)));
+        assert!(parse_arg("frames:0").is_err());
+        assert!(parse_arg(":5").is_err());
+        assert!(parse_arg("").is_err());
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(frame_file_name(0), "frame000000.png");
+        assert_eq!(frame_file_name(3600), "frame003600.png");
+        assert_eq!(screenshot_name(7), "screenshot007.bmp");
+        let mut frames = [120, 60, 1200].iter().map(|&x| frame_file_name(x)).collect::<Vec<_>>();
+        frames.sort();
+        assert_eq!(frames, ["frame000060.png", "frame000120.png", "frame001200.png"]);
+    }
+
+    #[test]
+    fn writes_in_background() {
+        let dir = std::env::temp_dir().join(format!("opengmk_framedump_{}", std::process::id()));
+        let dumper = FrameDumper::new(dir.join("nested"), 2).unwrap();
+        let frames = (0..5).filter(|&x| dumper.is_due(x)).collect::<Vec<_>>();
+        assert_eq!(frames, [0, 2, 4]);
+        for frame in frames {
+            dumper.dump(frame, RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255])));
+        }
+        // more than the queue holds, so some of these have to wait for the thread to catch up
+        for frame in (6..).step_by(2).take(QUEUE_LENGTH * 3) {
+            dumper.dump(frame, RgbaImage::from_pixel(64, 64, image::Rgba([0, 0, 255, 255])));
+        }
+        drop(dumper);
+        let mut written = fs::read_dir(dir.join("nested"))
+            .unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        written.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written.len(), 3 + QUEUE_LENGTH * 3);
+        assert_eq!(written[..3], ["frame000000.png", "frame000002.png", "frame000004.png"]);
+        assert_eq!(written.last().unwrap(), &frame_file_name(4 + QUEUE_LENGTH * 6));
+    }
+}
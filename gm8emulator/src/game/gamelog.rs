@@ -0,0 +1,195 @@
+//! The game's own debug output from show_debug_message, kept separate from the emulator's logging.
+//!
+//! Messages are numbered by the frame they were printed on the same way as the RNG log: anything printed while
+//! the game is starting up is on frame 0, and each call to Game::frame moves on to the next one.
+
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// How many messages are kept for the console if no other limit is given.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Serialize)]
+pub struct Message {
+    pub frame: u64,
+    #[serde(serialize_with = "seconds")]
+    pub time: Duration,
+    pub text: String,
+}
+
+fn seconds<S: serde::Serializer>(time: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(time.as_secs_f64())
+}
+
+impl Message {
+    /// Formats the message as one line for the console or the log file.
+    pub fn to_line(&self) -> String {
+        format!("[{} {:.3}s] {}", self.frame, self.time.as_secs_f64(), self.text)
+    }
+}
+
+pub struct GameLog {
+    messages: VecDeque<Message>,
+    capacity: usize,
+    frame: u64,
+    changes: u64,
+    start: Instant,
+    file: Option<BufWriter<File>>,
+}
+
+impl Default for GameLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl GameLog {
+    /// Creates a log which keeps the last `capacity` messages.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            messages: VecDeque::with_capacity(capacity.min(DEFAULT_CAPACITY)),
+            capacity,
+            frame: 0,
+            changes: 0,
+            start: Instant::now(),
+            file: None,
+        }
+    }
+
+    /// Also writes every message to the file at `path` from now on, replacing anything already in it.
+    pub fn tee_to(&mut self, path: &Path) -> io::Result<()> {
+        self.file = Some(BufWriter::new(File::create(path)?));
+        Ok(())
+    }
+
+    /// Moves the log on to the next frame, writing out anything still buffered for the file.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+        if let Some(file) = &mut self.file {
+            if let Err(e) = file.flush() {
+                eprintln!("failed to write game log: {}", e);
+                self.file = None;
+            }
+        }
+    }
+
+    /// The frame new messages are numbered with.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Carries on numbering from the given frame, such as after a savestate was loaded. The messages already in
+    /// the log are left alone, since they're still what the game printed.
+    pub fn set_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+
+    /// How many times the messages have changed, so that a copy of them only has to be updated when this has.
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// Adds a message, dropping the oldest one if the log is full.
+    pub fn push(&mut self, text: String) {
+        let message = Message { frame: self.frame, time: self.start.elapsed(), text };
+        if let Some(file) = &mut self.file {
+            if let Err(e) = writeln!(file, "{}", message.to_line()) {
+                eprintln!("failed to write game log: {}", e);
+                self.file = None;
+            }
+        }
+        if self.capacity == 0 {
+            return
+        }
+        self.changes += 1;
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+
+    /// The messages still in the log, oldest first.
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+
+    /// Empties the console. Doesn't affect the log file.
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.changes += 1;
+    }
+
+    /// Formats the messages still in the log as a JSON array, oldest first, with their times in seconds.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.messages).expect("couldn't serialize the game log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GameLog;
+    use std::fs;
+
+    #[test]
+    fn frames_and_capacity() {
+        let mut log = GameLog::new(3);
+        log.push("startup".into());
+        log.next_frame();
+        log.push("a".into());
+        log.push("b".into());
+        log.next_frame();
+        log.next_frame();
+        log.push("c".into());
+        let kept = log.messages().map(|m| (m.frame, m.text.as_str())).collect::<Vec<_>>();
+        assert_eq!(kept, [(1, "a"), (1, "b"), (3, "c")]);
+        log.clear();
+        assert_eq!(log.messages().count(), 0);
+        assert_eq!(log.changes(), 5);
+
+        // a savestate from frame 10 was loaded
+        log.set_frame(10);
+        log.push("d".into());
+        log.next_frame();
+        log.push("e".into());
+        let kept = log.messages().map(|m| (m.frame, m.text.as_str())).collect::<Vec<_>>();
+        assert_eq!(kept, [(10, "d"), (11, "e")]);
+    }
+
+    #[test]
+    fn json() {
+        let mut log = GameLog::new(2);
+        assert_eq!(log.to_json(), "[]");
+        log.push("hello \"world\"".into());
+        log.next_frame();
+        log.push("second".into());
+        let json: serde_json::Value = serde_json::from_str(&log.to_json()).unwrap();
+        let messages = json.as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!((&messages[0]["frame"], &messages[0]["text"]), (&0.into(), &"hello \"world\"".into()));
+        assert_eq!((&messages[1]["frame"], &messages[1]["text"]), (&1.into(), &"second".into()));
+        assert!(messages.iter().all(|m| m["time"].as_f64().unwrap() >= 0.0));
+    }
+
+    #[test]
+    fn tee_keeps_everything() {
+        let path = std::env::temp_dir().join(format!("opengmk_gamelog_{}.txt", std::process::id()));
+        let mut log = GameLog::new(1);
+        log.tee_to(&path).unwrap();
+        log.push("first".into());
+        log.next_frame();
+        log.push("second".into());
+        drop(log);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("[0 ") && lines[0].ends_with("] first"));
+        assert!(lines[1].starts_with("[1 ") && lines[1].ends_with("] second"));
+    }
+}
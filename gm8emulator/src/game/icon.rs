@@ -0,0 +1,170 @@
+//! The game's window icon, taken from the .ico the reader pulls out of the exe's resources.
+//!
+//! Icons from protected or hand-edited exes are sometimes truncated or otherwise broken, so everything here
+//! skips what it can't read rather than failing, and the window just keeps its default icon if nothing's usable.
+
+use ramen::window::Window;
+use std::convert::TryInto;
+
+/// One image in an .ico file.
+#[derive(Clone, Copy, Debug)]
+pub struct IconImage<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub bit_count: u16,
+    /// The image itself, either a DIB without its file header or a whole PNG.
+    pub data: &'a [u8],
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// Reads the directory of an .ico file, leaving out any images whose data isn't all there.
+pub fn parse(ico: &[u8]) -> Vec<IconImage<'_>> {
+    let count = match (u16_at(ico, 0), u16_at(ico, 2), u16_at(ico, 4)) {
+        (Some(0), Some(1), Some(count)) => count,
+        _ => return Vec::new(),
+    };
+    (0..usize::from(count))
+        .filter_map(|i| {
+            let entry = ico.get(6 + i * 16..6 + (i + 1) * 16)?;
+            let size = u32_at(entry, 8)? as usize;
+            let offset = u32_at(entry, 12)? as usize;
+            let data = ico.get(offset..offset.checked_add(size)?)?;
+            let bit_count = match u16_at(entry, 6)? {
+                // Lots of icons leave this blank, so it has to come from the image
+                0 if data.starts_with(b"\x89PNG") => 32,
+                0 => u16_at(data, 14)?,
+                n => n,
+            };
+            // 0 stands for 256 in the directory
+            let dimension = |x: u8| if x == 0 { 256 } else { u32::from(x) };
+            Some(IconImage { width: dimension(entry[0]), height: dimension(entry[1]), bit_count, data })
+        })
+        .collect()
+}
+
+/// Picks the image to use at a given size, the way Windows does: the smallest one at least that big,
+/// or failing that the biggest one, with ties going to whichever has the most colours.
+pub fn choose<'a>(images: &[IconImage<'a>], width: u32, height: u32) -> Option<IconImage<'a>> {
+    let fits = |x: &&IconImage| x.width >= width && x.height >= height;
+    match images.iter().filter(fits).min_by_key(|x| (x.width * x.height, u16::MAX - x.bit_count)) {
+        Some(image) => Some(*image),
+        None => images.iter().max_by_key(|x| (x.width * x.height, x.bit_count)).copied(),
+    }
+}
+
+/// Sets the window's big (alt-tab) and small (title bar and taskbar) icons from an .ico file.
+#[cfg(target_os = "windows")]
+pub fn set_window_icon(window: &Window, ico: &[u8]) {
+    use ramen::platform::win32::WindowExt as _;
+    use std::os::raw::{c_int, c_void};
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn CreateIconFromResourceEx(
+            presbits: *const u8,
+            dw_res_size: u32,
+            f_icon: c_int,
+            dw_ver: u32,
+            cx_desired: c_int,
+            cy_desired: c_int,
+            flags: u32,
+        ) -> *mut c_void;
+        fn GetSystemMetrics(n_index: c_int) -> c_int;
+        fn SendMessageW(hwnd: *mut c_void, msg: u32, wparam: usize, lparam: isize) -> isize;
+    }
+    const WM_SETICON: u32 = 0x0080;
+    const ICON_SMALL: usize = 0;
+    const ICON_BIG: usize = 1;
+    const SM_CXICON: c_int = 11;
+    const SM_CYICON: c_int = 12;
+    const SM_CXSMICON: c_int = 49;
+    const SM_CYSMICON: c_int = 50;
+
+    let images = parse(ico);
+    for &(kind, metric_x, metric_y) in &[(ICON_BIG, SM_CXICON, SM_CYICON), (ICON_SMALL, SM_CXSMICON, SM_CYSMICON)] {
+        unsafe {
+            let (width, height) = (GetSystemMetrics(metric_x), GetSystemMetrics(metric_y));
+            if let Some(image) = choose(&images, width as u32, height as u32) {
+                let data = image.data;
+                let icon = CreateIconFromResourceEx(data.as_ptr(), data.len() as u32, 1, 0x00030000, width, height, 0);
+                if !icon.is_null() {
+                    SendMessageW(window.hwnd() as _, WM_SETICON, kind, icon as isize);
+                }
+            }
+        }
+    }
+}
+
+// TODO: Others!
+#[cfg(not(target_os = "windows"))]
+pub fn set_window_icon(_window: &Window, _ico: &[u8]) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds an .ico with the given (width, height, bit count in the directory, bit count in the DIB) images.
+    // A width of 256 is stored as 0, and a DIB bit count of 0 makes that image a PNG instead.
+    fn ico(images: &[(u32, u32, u16, u16)]) -> Vec<u8> {
+        let mut header = vec![0, 0, 1, 0];
+        header.extend_from_slice(&(images.len() as u16).to_le_bytes());
+        let mut data = Vec::new();
+        for &(width, height, dir_bits, dib_bits) in images {
+            let image = if dib_bits == 0 {
+                b"\x89PNG\r\n\x1a\n....".to_vec()
+            } else {
+                let mut dib = vec![0; 40];
+                dib[0..4].copy_from_slice(&40u32.to_le_bytes());
+                dib[14..16].copy_from_slice(&dib_bits.to_le_bytes());
+                dib
+            };
+            let offset = 6 + images.len() * 16 + data.len();
+            header.extend_from_slice(&[width as u8, height as u8, 0, 0, 1, 0]);
+            header.extend_from_slice(&dir_bits.to_le_bytes());
+            header.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(offset as u32).to_le_bytes());
+            data.extend(image);
+        }
+        header.extend(data);
+        header
+    }
+
+    fn size(image: Option<IconImage>) -> Option<(u32, u32, u16)> {
+        image.map(|x| (x.width, x.height, x.bit_count))
+    }
+
+    #[test]
+    fn choose_sizes() {
+        let file = ico(&[(16, 16, 4, 4), (32, 32, 8, 8), (32, 32, 0, 32), (48, 48, 32, 32), (256, 256, 0, 0)]);
+        let images = parse(&file);
+        assert_eq!(images.len(), 5);
+        assert_eq!(size(choose(&images, 16, 16)), Some((16, 16, 4)));
+        assert_eq!(size(choose(&images, 20, 20)), Some((32, 32, 32)));
+        assert_eq!(size(choose(&images, 32, 32)), Some((32, 32, 32)));
+        assert_eq!(size(choose(&images, 64, 64)), Some((256, 256, 32)));
+        assert_eq!(size(choose(&images, 512, 512)), Some((256, 256, 32)));
+        assert!(images[4].data.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn broken_icons() {
+        let file = ico(&[(16, 16, 4, 4), (32, 32, 32, 32)]);
+        // cut off partway through the second image
+        let images = parse(&file[..file.len() - 10]);
+        assert_eq!(images.len(), 1);
+        assert_eq!(size(choose(&images, 32, 32)), Some((16, 16, 4)));
+
+        // cut off in the directory, or not an icon at all
+        assert!(parse(&file[..20]).is_empty());
+        assert!(parse(b"MZ\x90\0").is_empty());
+        assert!(parse(&[]).is_empty());
+        assert_eq!(size(choose(&[], 32, 32)), None);
+    }
+}
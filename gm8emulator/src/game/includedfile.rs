@@ -20,19 +20,28 @@ pub enum ExportSetting {
 }
 
 impl IncludedFile {
+    /// Exports the file when the game starts, if it's set to be exported.
     pub fn export(&mut self, temp_directory: PathBuf, program_directory: PathBuf) -> std::io::Result<()> {
-        if self.data.is_some() {
-            if let Some(mut export_path) = match self.export_settings.clone() {
-                ExportSetting::NoExport => None,
-                ExportSetting::TempFolder => Some(temp_directory),
-                ExportSetting::GameFolder => Some(program_directory),
-                ExportSetting::CustomFolder(dir) => Some(dir.clone().into()),
-            } {
-                export_path.push(&self.name);
-                self.export_to(&export_path)?;
-            }
+        match self.export_directory(temp_directory, program_directory) {
+            Some(dir) => self.export_to(&dir.join(&self.name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Exports the file for export_include_file. Unlike at startup, this also works for a file that's set not to be
+    /// exported, which then goes in the game's working folder.
+    pub fn export_on_request(&mut self, temp_directory: PathBuf, program_directory: PathBuf) -> std::io::Result<()> {
+        let dir = self.export_directory(temp_directory, program_directory.clone()).unwrap_or(program_directory);
+        self.export_to(&dir.join(&self.name))
+    }
+
+    fn export_directory(&self, temp_directory: PathBuf, program_directory: PathBuf) -> Option<PathBuf> {
+        match &self.export_settings {
+            ExportSetting::NoExport => None,
+            ExportSetting::TempFolder => Some(temp_directory),
+            ExportSetting::GameFolder => Some(program_directory),
+            ExportSetting::CustomFolder(dir) => Some(dir.into()),
         }
-        Ok(())
     }
 
     pub fn export_to(&mut self, path: &Path) -> std::io::Result<()> {
@@ -47,3 +56,56 @@ impl IncludedFile {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn export_settings() {
+        let mut root = std::env::temp_dir();
+        root.push(format!("opengmk_includedfile_{}", std::process::id()));
+        let (temp, program) = (root.join("temp"), root.join("game"));
+        fs::create_dir_all(&temp).unwrap();
+        fs::create_dir_all(&program).unwrap();
+        let file = |name: &str, export_settings| IncludedFile {
+            name: name.into(),
+            data: Some(name.as_bytes().into()),
+            export_settings,
+            overwrite: false,
+            free_after_export: false,
+            remove_at_end: false,
+        };
+
+        let mut temp_file = file("temp.txt", ExportSetting::TempFolder);
+        temp_file.export(temp.clone(), program.clone()).unwrap();
+        assert_eq!(fs::read(temp.join("temp.txt")).unwrap(), b"temp.txt");
+
+        // not exported at startup, but export_include_file puts it in the working folder
+        let mut hidden = file("hidden.txt", ExportSetting::NoExport);
+        hidden.export(temp.clone(), program.clone()).unwrap();
+        assert!(!program.join("hidden.txt").exists() && !temp.join("hidden.txt").exists());
+        hidden.export_on_request(temp.clone(), program.clone()).unwrap();
+        assert_eq!(fs::read(program.join("hidden.txt")).unwrap(), b"hidden.txt");
+
+        // only overwritten if it's set to be
+        fs::write(program.join("hidden.txt"), b"changed").unwrap();
+        hidden.export_on_request(temp.clone(), program.clone()).unwrap();
+        assert_eq!(fs::read(program.join("hidden.txt")).unwrap(), b"changed");
+        hidden.overwrite = true;
+        hidden.export_on_request(temp.clone(), program.clone()).unwrap();
+        assert_eq!(fs::read(program.join("hidden.txt")).unwrap(), b"hidden.txt");
+
+        // nothing left to export once it's been freed or discarded
+        let mut freed = file("freed.txt", ExportSetting::GameFolder);
+        freed.free_after_export = true;
+        freed.export(temp.clone(), program.clone()).unwrap();
+        assert!(freed.data.is_none());
+        fs::remove_file(program.join("freed.txt")).unwrap();
+        freed.export_on_request(temp.clone(), program.clone()).unwrap();
+        assert!(!program.join("freed.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
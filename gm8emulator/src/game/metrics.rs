@@ -0,0 +1,399 @@
+//! A tiny HTTP server which shows what's going on in the game, for stream overlays and the like.
+//!
+//! Every request gets the latest snapshot as JSON: how many frames the game has run, the room name, fps, and the
+//! values of some watch expressions. The game renders a new snapshot at the end of every frame and swaps it in, so a
+//! request only ever holds the lock long enough to clone a pointer, and never has to wait for a frame to finish.
+//!
+//! `/game_log` gets the show_debug_message output still in the game log instead, as a JSON array.
+//!
+//! This is only built with the `metrics` feature.
+
+use crate::{
+    game::{gamelog::GameLog, Game, GetAsset},
+    gml::{
+        mappings,
+        runtime::{ArrayAccessor, InstanceIdentifier, Node},
+        Compiler, Context, Function, InstanceVariable, Value,
+    },
+    instance::Instance,
+};
+use serde_json::json;
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// How long a client gets to send its request or read the response before it's dropped.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The longest request line or header we bother reading. Anything longer is cut off.
+const MAX_LINE: u64 = 8192;
+
+/// The most headers read from one request before answering it anyway.
+const MAX_HEADERS: usize = 100;
+
+/// Parses the argument to --metrics-port. A port number on its own means localhost only,
+/// anything else has to be a full address such as 0.0.0.0:8080.
+pub fn parse_address(address: &str) -> Result<SocketAddr, String> {
+    match address.parse::<u16>() {
+        Ok(port) => Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)),
+        Err(_) => address.parse().map_err(|_| format!("'{}' is neither a port number nor an address", address)),
+    }
+}
+
+/// A compiled watch expression.
+pub struct Watch {
+    source: String,
+    node: Node,
+}
+
+impl Watch {
+    /// Compiles a watch expression, refusing anything which could change the game's state when it's evaluated,
+    /// since that would make replays desync just from having the server running.
+    pub fn compile(compiler: &mut Compiler, source: &str) -> Result<Self, String> {
+        let node = compiler.compile_expression(source.as_bytes()).map_err(|e| e.to_string())?;
+        check_read_only(&node)?;
+        Ok(Self { source: source.into(), node })
+    }
+}
+
+fn check_read_only(node: &Node) -> Result<(), String> {
+    let check_accessor = |array: &ArrayAccessor, owner: &InstanceIdentifier| -> Result<(), String> {
+        match array {
+            ArrayAccessor::None => (),
+            ArrayAccessor::Single(index) => check_read_only(index)?,
+            ArrayAccessor::Double(index1, index2) => {
+                check_read_only(index1)?;
+                check_read_only(index2)?;
+            },
+        }
+        match owner {
+            InstanceIdentifier::Expression(owner) => check_read_only(owner),
+            _ => Ok(()),
+        }
+    };
+    match node {
+        Node::Literal { .. } | Node::Constant { .. } => Ok(()),
+        Node::Function { args, function_id } => match mappings::FUNCTIONS.index(*function_id) {
+            // these only read the game's state, if they look at it at all
+            Some((_, Function::Pure(_))) | Some((_, Function::Constant(_))) => {
+                args.iter().try_for_each(check_read_only)
+            },
+            Some((name, _)) => Err(format!("{} might change the game's state, so it can't be watched", name)),
+            None => Err(format!("unknown function {}", function_id)),
+        },
+        Node::Script { .. } => Err("scripts might change the game's state, so they can't be watched".into()),
+        Node::ExtensionFunction { .. } => {
+            Err("extension functions might change the game's state, so they can't be watched".into())
+        },
+        Node::Field { accessor } => check_accessor(&accessor.array, &accessor.owner),
+        Node::Variable { accessor } => match accessor.var {
+            InstanceVariable::CurrentTime => Err("reading current_time moves the spoofed clock along".into()),
+            _ => check_accessor(&accessor.array, &accessor.owner),
+        },
+        Node::Binary { left, right, .. } => {
+            check_read_only(left)?;
+            check_read_only(right)
+        },
+        Node::Unary { child, .. } => check_read_only(child),
+        Node::RuntimeError { error } => Err(error.to_string()),
+    }
+}
+
+/// What a watch expression came out as on the last frame.
+pub enum Reading {
+    Real(f64),
+    Str(String),
+    Error(String),
+}
+
+/// Everything the server reports about one frame.
+#[derive(Default)]
+pub struct Snapshot {
+    /// How many frames the game has run, numbered the same way as in the game log.
+    pub frame: u64,
+    pub room: String,
+    pub fps: u32,
+    pub watches: Vec<(String, Reading)>,
+}
+
+impl Snapshot {
+    /// Formats the snapshot as JSON. Watches which errored are null in "watches" and have their message in "errors".
+    /// Reals which JSON can't represent, such as infinity, are null too.
+    pub fn to_json(&self) -> String {
+        let mut watches = serde_json::Map::new();
+        let mut errors = serde_json::Map::new();
+        for (source, reading) in &self.watches {
+            let value = match reading {
+                Reading::Real(x) => json!(x),
+                Reading::Str(s) => json!(s),
+                Reading::Error(e) => {
+                    errors.insert(source.clone(), json!(e));
+                    serde_json::Value::Null
+                },
+            };
+            watches.insert(source.clone(), value);
+        }
+        json!({
+            "frame": self.frame,
+            "room": self.room,
+            "fps": self.fps,
+            "watches": watches,
+            "errors": errors,
+        })
+        .to_string()
+    }
+}
+
+pub struct Server {
+    shared: Arc<Shared>,
+    watches: Vec<Watch>,
+    address: SocketAddr,
+    log_changes: Option<u64>,
+}
+
+// What requests are answered with, each swapped out whole when it changes
+struct Shared {
+    metrics: Mutex<Arc<str>>,
+    game_log: Mutex<Arc<str>>,
+}
+
+impl Server {
+    /// Starts listening on the given address, serving requests on a thread of its own.
+    pub fn start(address: SocketAddr, watches: Vec<Watch>) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            metrics: Mutex::new(Snapshot::default().to_json().into()),
+            game_log: Mutex::new("[]".into()),
+        });
+        let server_shared = shared.clone();
+        thread::Builder::new().name("metrics".into()).spawn(move || {
+            for stream in listener.incoming() {
+                // a client hanging up or sending garbage is its own problem
+                let _ = stream.and_then(|stream| respond(stream, &server_shared));
+            }
+        })?;
+        Ok(Self { shared, watches, address, log_changes: None })
+    }
+
+    /// The address the server ended up listening on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Replaces the snapshot that requests get from now on.
+    pub fn publish(&self, snapshot: &Snapshot) {
+        let json: Arc<str> = snapshot.to_json().into();
+        *self.shared.metrics.lock().unwrap() = json;
+    }
+
+    /// Replaces the game log that requests get from now on, if it's changed since it was last published.
+    pub fn publish_log(&mut self, log: &GameLog) {
+        if self.log_changes != Some(log.changes()) {
+            let json: Arc<str> = log.to_json().into();
+            *self.shared.game_log.lock().unwrap() = json;
+            self.log_changes = Some(log.changes());
+        }
+    }
+}
+
+fn respond(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.by_ref().take(MAX_LINE).read_line(&mut request)?;
+    for _ in 0..MAX_HEADERS {
+        let mut header = String::new();
+        if reader.by_ref().take(MAX_LINE).read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break
+        }
+    }
+
+    let mut words = request.split_whitespace();
+    let method = words.next().unwrap_or_default();
+    let path = words.next().unwrap_or_default().split('?').next().unwrap_or_default();
+    let (status, body) = match (method, path) {
+        ("GET", "/") | ("GET", "/metrics") | ("HEAD", "/") | ("HEAD", "/metrics") => {
+            ("200 OK", Some(shared.metrics.lock().unwrap().clone()))
+        },
+        ("GET", "/game_log") | ("HEAD", "/game_log") => ("200 OK", Some(shared.game_log.lock().unwrap().clone())),
+        ("OPTIONS", _) => ("204 No Content", None),
+        ("GET", _) | ("HEAD", _) => ("404 Not Found", None),
+        _ => ("405 Method Not Allowed", None),
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: GET, HEAD, OPTIONS\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n",
+        status
+    );
+    let body = body.as_deref().unwrap_or_default();
+    if !body.is_empty() {
+        response.push_str("Content-Type: application/json; charset=utf-8\r\n");
+    }
+    write!(response, "Content-Length: {}\r\n\r\n", body.len()).unwrap();
+    if method != "HEAD" {
+        response.push_str(body);
+    }
+    (&stream).write_all(response.as_bytes())
+}
+
+impl Game {
+    /// Starts the metrics server with the given watch expressions. Returns the address it's listening on.
+    pub fn start_metrics(&mut self, address: SocketAddr, watches: &[String]) -> Result<SocketAddr, String> {
+        let watches = watches
+            .iter()
+            .map(|source| Watch::compile(&mut self.compiler, source).map_err(|e| format!("watch '{}': {}", source, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let server = Server::start(address, watches).map_err(|e| format!("couldn't listen on {}: {}", address, e))?;
+        let address = server.address();
+        self.metrics = Some(server);
+        Ok(address)
+    }
+
+    /// Evaluates the watch expressions and publishes a new snapshot, if the metrics server is running.
+    /// This should be called at the end of every frame.
+    pub fn publish_metrics(&mut self) {
+        if let Some(mut metrics) = self.metrics.take() {
+            let dummy_instance = self
+                .room
+                .instance_list
+                .insert_dummy(Instance::new_dummy(self.assets.objects.get_asset(0).map(|x| x.as_ref())));
+            let mut context = Context::with_single_instance(dummy_instance);
+            let watches = metrics
+                .watches
+                .iter()
+                .map(|watch| {
+                    let reading = match self.eval(&watch.node, &mut context) {
+                        Ok(Value::Real(x)) => Reading::Real(x.into()),
+                        Ok(Value::Str(s)) => Reading::Str(self.decode_str(s.as_ref()).into_owned()),
+                        Err(e) => Reading::Error(e.to_string()),
+                    };
+                    (watch.source.clone(), reading)
+                })
+                .collect();
+            self.room.instance_list.remove_dummy(dummy_instance);
+
+            let room = match self.assets.rooms.get_asset(self.room.id) {
+                Some(room) => self.decode_str(room.name.as_ref()).into_owned(),
+                None => String::new(),
+            };
+            metrics.publish(&Snapshot { frame: self.game_log.frame(), room, fps: self.fps, watches });
+            metrics.publish_log(&self.game_log);
+            self.metrics = Some(metrics);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(parse_address("8080"), Ok("127.0.0.1:8080".parse().unwrap()));
+        assert_eq!(parse_address("0.0.0.0:1234"), Ok("0.0.0.0:1234".parse().unwrap()));
+        assert!(parse_address("localhost").is_err());
+        assert!(parse_address("70000").is_err());
+    }
+
+    #[test]
+    fn read_only_watches() {
+        let mut compiler = Compiler::new(false);
+        for source in &["global.deaths", "x + y * 2", "string(score) + \"pts\"", "ds_list_size(global.list[3])"] {
+            assert!(Watch::compile(&mut compiler, source).is_ok(), "{} should be allowed", source);
+        }
+        for source in &["random(10)", "current_time", "instance_create(0, 0, 0)", "global.a[irandom(2)]", "x +"] {
+            assert!(Watch::compile(&mut compiler, source).is_err(), "{} shouldn't be allowed", source);
+        }
+    }
+
+    fn parse(json: &str) -> serde_json::Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn snapshot_json() {
+        let snapshot = Snapshot {
+            frame: 120,
+            room: "rm_\"stage\" 1".into(),
+            fps: 50,
+            watches: vec![
+                ("global.deaths".into(), Reading::Real(3.0)),
+                ("x".into(), Reading::Real(103.5)),
+                ("1/0".into(), Reading::Real(f64::INFINITY)),
+                ("room_caption".into(), Reading::Str("I Wanna\nBe".into())),
+                ("obj_player.x".into(), Reading::Error("nope".into())),
+            ],
+        };
+        assert_eq!(
+            parse(&snapshot.to_json()),
+            json!({
+                "frame": 120,
+                "room": "rm_\"stage\" 1",
+                "fps": 50,
+                "watches": {
+                    "global.deaths": 3.0,
+                    "x": 103.5,
+                    "1/0": null,
+                    "room_caption": "I Wanna\nBe",
+                    "obj_player.x": null,
+                },
+                "errors": { "obj_player.x": "nope" },
+            })
+        );
+        assert_eq!(
+            parse(&Snapshot::default().to_json()),
+            json!({ "frame": 0, "room": "", "fps": 0, "watches": {}, "errors": {} })
+        );
+    }
+
+    #[test]
+    fn serves_latest_snapshot() {
+        let mut server = Server::start(parse_address("0").unwrap(), Vec::new()).unwrap();
+        let address = server.address();
+        assert!(address.ip().is_loopback());
+
+        let response = get(address, "GET /metrics HTTP/1.1\r\nHost: localhost\r\nOrigin: null\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\nAccess-Control-Allow-Origin: *\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{}", Snapshot::default().to_json())));
+
+        let snapshot = Snapshot { frame: 7, room: "rm_test".into(), fps: 30, watches: Vec::new() };
+        server.publish(&snapshot);
+        let response = get(address, "GET /?t=1 HTTP/1.1\r\n\r\n");
+        let json = snapshot.to_json();
+        assert!(response.contains(&format!("\r\nContent-Length: {}\r\n", json.len())));
+        assert!(response.ends_with(&format!("\r\n\r\n{}", json)));
+
+        assert!(get(address, "GET /game_log HTTP/1.1\r\n\r\n").ends_with("\r\n\r\n[]"));
+        let mut log = GameLog::new(10);
+        log.push("hello".into());
+        server.publish_log(&log);
+        let response = get(address, "GET /game_log HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{}", log.to_json())));
+
+        assert!(get(address, "HEAD / HTTP/1.1\r\n\r\n").ends_with("\r\n\r\n"));
+        assert!(get(address, "OPTIONS /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(get(address, "GET /favicon.ico HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(get(address, "POST / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
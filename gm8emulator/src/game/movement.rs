@@ -1,149 +1,229 @@
 use crate::{
+    asset::Path,
     game::{Game, GetAsset},
+    gml::rand::{Random, Site},
+    instance::Instance,
     math::Real,
     util,
 };
 
-impl Game {
-    /// Processes movement (friction, gravity, speed/direction) for all instances
-    pub fn process_speeds(&mut self) {
-        let mut iter = self.room.instance_list.iter_by_drawing();
-        while let Some(instance) = iter.next(&self.room.instance_list).map(|i| self.room.instance_list.get(i)) {
-            let friction = instance.friction.get();
-            if friction != Real::from(0.0) {
-                // "Subtract" friction from speed towards 0
-                let speed = instance.speed.get();
-                if speed >= Real::from(0.0) {
-                    if friction > speed {
-                        instance.set_speed(Real::from(0.0));
-                    } else {
-                        instance.set_speed(speed - friction);
-                    }
-                } else {
-                    if friction > -speed {
-                        instance.set_speed(Real::from(0.0));
-                    } else {
-                        instance.set_speed(speed + friction);
-                    }
-                }
+/// Picks one of the nine Move Fixed buttons, given the string the action stores them in (such as "000000101").
+/// Like GM8, this draws irandom(8) until it lands on a selected button, so every attempt consumes exactly one value
+/// from the RNG. If no buttons are selected the RNG isn't touched at all.
+pub fn move_fixed_choice(buttons: &[u8], rand: &mut Random) -> Option<usize> {
+    if buttons.len() == 9 && buttons.contains(&b'1') {
+        loop {
+            let index = rand.next_int(8, Site::MoveFixed) as usize;
+            if buttons[index] == b'1' {
+                break Some(index)
             }
+        }
+    } else {
+        None
+    }
+}
 
-            let gravity = instance.gravity.get();
-            if gravity != Real::from(0.0) {
-                // Apply gravity in gravity_direction to hspeed and vspeed
-                let gravity_direction = instance.gravity_direction.get().to_radians();
-                instance.set_hvspeed(
-                    instance.hspeed.get() + (gravity_direction.cos() * gravity),
-                    instance.vspeed.get() - (gravity_direction.sin() * gravity),
-                );
+/// Gets the speed and direction a Move Fixed button stands for. The buttons are laid out like a numpad,
+/// starting from the bottom-left, and the middle one means "stop".
+pub fn move_fixed_motion(button: usize, speed: Real) -> (Real, Real) {
+    match button {
+        0 => (speed, Real::from(225.0)),
+        1 => (speed, Real::from(270.0)),
+        2 => (speed, Real::from(315.0)),
+        3 => (speed, Real::from(180.0)),
+        5 => (speed, Real::from(0.0)),
+        6 => (speed, Real::from(135.0)),
+        7 => (speed, Real::from(90.0)),
+        8 => (speed, Real::from(45.0)),
+        _ => (Real::from(0.0), Real::from(0.0)),
+    }
+}
+
+/// Wraps one coordinate for move_wrap, given the room's size along that axis. Like GM8 this looks at the
+/// instance's origin rather than its bbox: it wraps once the origin is more than `margin` past either edge
+/// (landing on the other side by the same amount), and being exactly `margin` past doesn't count yet.
+pub fn wrap(position: Real, size: i32, margin: Real) -> Option<Real> {
+    let size = Real::from(size);
+    // Both sides are checked against the original position, so with a very negative margin the second one wins
+    let mut wrapped = None;
+    if position < -margin {
+        wrapped = Some(size + position + Real::from(2) * margin);
+    }
+    if position > size + margin {
+        wrapped = Some(position - size - Real::from(2) * margin);
+    }
+    wrapped
+}
+
+/// Applies one step's worth of friction and then gravity to an instance's speed.
+pub fn apply_friction_gravity(instance: &Instance) {
+    let friction = instance.friction.get();
+    if friction != Real::from(0.0) {
+        // "Subtract" friction from speed towards 0
+        let speed = instance.speed.get();
+        if speed >= Real::from(0.0) {
+            if friction > speed {
+                instance.set_speed(Real::from(0.0));
+            } else {
+                instance.set_speed(speed - friction);
+            }
+        } else {
+            if friction > -speed {
+                instance.set_speed(Real::from(0.0));
+            } else {
+                instance.set_speed(speed + friction);
             }
         }
     }
 
-    // Returns true if path end event should be called
-    pub fn apply_speeds(&self, handle: usize) -> bool {
-        let instance = self.room.instance_list.get(handle);
-        // Apply hspeed and vspeed to x and y
-        let hspeed = instance.hspeed.get();
-        let vspeed = instance.vspeed.get();
-        if hspeed != Real::from(0.0) || vspeed != Real::from(0.0) {
-            instance.x.set(instance.x.get() + hspeed);
-            instance.y.set(instance.y.get() + vspeed);
-            instance.bbox_is_stale.set(true);
-        }
+    let gravity = instance.gravity.get();
+    if gravity != Real::from(0.0) {
+        // Apply gravity in gravity_direction to hspeed and vspeed
+        let gravity_direction = instance.gravity_direction.get().to_radians();
+        instance.set_hvspeed(
+            instance.hspeed.get() + (gravity_direction.cos() * gravity),
+            instance.vspeed.get() - (gravity_direction.sin() * gravity),
+        );
+    }
+}
 
-        // Advance paths
-        let mut run_event = false;
-        if let Some(path) = self.assets.paths.get_asset(instance.path_index.get()) {
-            if path.length != 0.into() && instance.path_speed.get() != 0.into() {
-                // Prepare this for later
-                let angle = instance.path_orientation.get().to_radians();
-
-                // Calculate how much offset (0-1) we want to add to the instance's path position
-                let point_speed = path.get_point(instance.path_position.get()).speed;
-                let offset = instance.path_speed.get() * (point_speed / Real::from(100.0))
-                    / (path.length * instance.path_scale.get());
-
-                // Work out what the new position should be
-                let new_position = instance.path_position.get() + offset;
-                if new_position <= Real::from(0.0) || new_position >= Real::from(1.0) {
-                    // Path end
-                    let reversed = new_position < Real::from(0.0);
-                    let opposite_position =
-                        if reversed { new_position + Real::from(1.0) } else { new_position - Real::from(1.0) };
-                    match instance.path_endaction.get() {
-                        1 => {
-                            // Continue from start
-                            instance.path_position.set(opposite_position);
-                        },
-                        2 => {
-                            // Continue from end
-                            let path_start_pos = if reversed { Real::from(1.0) } else { Real::from(0.0) };
-                            let path_end_pos = if reversed { Real::from(0.0) } else { Real::from(1.0) };
-
-                            instance.path_position.set(opposite_position);
-                            let start_point = path.get_point(path_start_pos);
-                            let end_point = path.get_point(path_end_pos);
-                            let mut size_h = end_point.x - start_point.x;
-                            let mut size_v = end_point.y - start_point.y;
-                            util::rotate_around_center(
-                                size_h.as_mut_ref(),
-                                size_v.as_mut_ref(),
-                                angle.sin().into(),
-                                angle.cos().into(),
-                            );
-                            instance.path_xstart.set(instance.path_xstart.get() + size_h * instance.path_scale.get());
-                            instance.path_ystart.set(instance.path_ystart.get() + size_v * instance.path_scale.get());
-                        },
-                        3 => {
-                            // Reverse
-                            instance.path_position.set(Real::from(1.0) - (opposite_position));
-                            instance.path_speed.set(if reversed {
-                                instance.path_speed.get().abs()
-                            } else {
-                                -instance.path_speed.get().abs()
-                            });
-                        },
-                        _ => {
-                            // Stop
-                            instance.path_position.set(1.into());
-                            instance.path_index.set(-1);
-                        },
-                    }
+/// Remembers where an instance was at the start of the step, before anything has had a chance to move it.
+/// This is what the solid collision revert goes back to, so it has to happen before the begin step event:
+/// anything that moves the instance afterwards (step events, mp_potential_step, speed, paths) gets undone together.
+pub fn save_previous(instance: &Instance) {
+    instance.xprevious.set(instance.x.get());
+    instance.yprevious.set(instance.y.get());
+    instance.path_positionprevious.set(instance.path_position.get().clamp(0.into(), 1.into()));
+}
 
-                    // Set flag to run path end event
-                    run_event = true;
-                } else {
-                    // Normally update path_position
-                    instance.path_position.set(new_position);
-                }
+/// Puts an instance back where it was at the start of the step, for when it's touching something solid.
+pub fn revert_to_previous(instance: &Instance) {
+    instance.x.set(instance.xprevious.get());
+    instance.y.set(instance.yprevious.get());
+    instance.bbox_is_stale.set(true);
+    instance.path_position.set(instance.path_positionprevious.get());
+}
+
+/// Adds hspeed and vspeed to an instance's position.
+pub fn apply_motion(instance: &Instance) {
+    let hspeed = instance.hspeed.get();
+    let vspeed = instance.vspeed.get();
+    if hspeed != Real::from(0.0) || vspeed != Real::from(0.0) {
+        instance.x.set(instance.x.get() + hspeed);
+        instance.y.set(instance.y.get() + vspeed);
+        instance.bbox_is_stale.set(true);
+    }
+}
+
+/// Moves an instance one step along the given path. This runs after apply_motion: the instance is pointed towards
+/// the new point on the path and its speed is set to 0, so the path is the only thing moving it.
+/// Returns true if the path end event should be run.
+pub fn advance_path(instance: &Instance, path: &Path) -> bool {
+    if path.length == 0.into() || instance.path_speed.get() == 0.into() {
+        return false
+    }
+
+    let mut run_event = false;
+
+    // Prepare this for later
+    let angle = instance.path_orientation.get().to_radians();
 
-                // Figure out the new coordinates for this instance based on its path_position and path vars
-                let mut point = path.get_point(instance.path_position.get());
-                point.x -= path.start.x;
-                point.y -= path.start.y;
-                point.x *= instance.path_scale.get();
-                point.y *= instance.path_scale.get();
+    // Calculate how much offset (0-1) we want to add to the instance's path position
+    let point_speed = path.get_point(instance.path_position.get()).speed;
+    let offset =
+        instance.path_speed.get() * (point_speed / Real::from(100.0)) / (path.length * instance.path_scale.get());
+
+    // Work out what the new position should be
+    let new_position = instance.path_position.get() + offset;
+    if new_position <= Real::from(0.0) || new_position >= Real::from(1.0) {
+        // Path end
+        let reversed = new_position < Real::from(0.0);
+        let opposite_position = if reversed { new_position + Real::from(1.0) } else { new_position - Real::from(1.0) };
+        match instance.path_endaction.get() {
+            1 => {
+                // Continue from start
+                instance.path_position.set(opposite_position);
+            },
+            2 => {
+                // Continue from end
+                let path_start_pos = if reversed { Real::from(1.0) } else { Real::from(0.0) };
+                let path_end_pos = if reversed { Real::from(0.0) } else { Real::from(1.0) };
+
+                instance.path_position.set(opposite_position);
+                let start_point = path.get_point(path_start_pos);
+                let end_point = path.get_point(path_end_pos);
+                let mut size_h = end_point.x - start_point.x;
+                let mut size_v = end_point.y - start_point.y;
                 util::rotate_around_center(
-                    point.x.as_mut_ref(),
-                    point.y.as_mut_ref(),
+                    size_h.as_mut_ref(),
+                    size_v.as_mut_ref(),
                     angle.sin().into(),
                     angle.cos().into(),
                 );
+                instance.path_xstart.set(instance.path_xstart.get() + size_h * instance.path_scale.get());
+                instance.path_ystart.set(instance.path_ystart.get() + size_v * instance.path_scale.get());
+            },
+            3 => {
+                // Reverse
+                instance.path_position.set(Real::from(1.0) - (opposite_position));
+                instance.path_speed.set(if reversed {
+                    instance.path_speed.get().abs()
+                } else {
+                    -instance.path_speed.get().abs()
+                });
+            },
+            _ => {
+                // Stop
+                instance.path_position.set(1.into());
+                instance.path_index.set(-1);
+            },
+        }
 
-                // Update the instance's x, y and direction
-                let new_x = point.x + instance.path_xstart.get();
-                let new_y = point.y + instance.path_ystart.get();
-                instance.set_direction((instance.y.get() - new_y).arctan2(new_x - instance.x.get()).to_degrees());
-                instance.set_speed(0.into());
-                instance.x.set(new_x);
-                instance.y.set(new_y);
-                instance.bbox_is_stale.set(true);
-            }
+        // Set flag to run path end event
+        run_event = true;
+    } else {
+        // Normally update path_position
+        instance.path_position.set(new_position);
+    }
+
+    // Figure out the new coordinates for this instance based on its path_position and path vars
+    let mut point = path.get_point(instance.path_position.get());
+    point.x -= path.start.x;
+    point.y -= path.start.y;
+    point.x *= instance.path_scale.get();
+    point.y *= instance.path_scale.get();
+    util::rotate_around_center(point.x.as_mut_ref(), point.y.as_mut_ref(), angle.sin().into(), angle.cos().into());
+
+    // Update the instance's x, y and direction
+    let new_x = point.x + instance.path_xstart.get();
+    let new_y = point.y + instance.path_ystart.get();
+    instance.set_direction((instance.y.get() - new_y).arctan2(new_x - instance.x.get()).to_degrees());
+    instance.set_speed(0.into());
+    instance.x.set(new_x);
+    instance.y.set(new_y);
+    instance.bbox_is_stale.set(true);
+
+    run_event
+}
+
+impl Game {
+    /// Processes movement (friction, gravity, speed/direction) for all instances
+    pub fn process_speeds(&mut self) {
+        let mut iter = self.room.instance_list.iter_by_drawing();
+        while let Some(instance) = iter.next(&self.room.instance_list).map(|i| self.room.instance_list.get(i)) {
+            apply_friction_gravity(instance);
         }
+    }
 
-        // Run path end event
-        run_event
+    /// Moves an instance by its speed and then along its path, in that order.
+    /// Returns true if the path end event should be called.
+    pub fn apply_speeds(&self, handle: usize) -> bool {
+        let instance = self.room.instance_list.get(handle);
+        apply_motion(instance);
+        match self.assets.paths.get_asset(instance.path_index.get()) {
+            Some(path) => advance_path(instance, path),
+            None => false,
+        }
     }
 
     /// "bounces" the instance against any instances or only solid ones, depending on solid_only
@@ -241,3 +321,249 @@ impl Game {
         instance.bbox_is_stale.set(true);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::pathfinding::{potential_step, PotentialStepSettings};
+
+    fn assert_close(actual: Real, expected: f64) {
+        assert!((f64::from(actual) - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    // Runs a step event which does Move Fixed every 10 steps, then friction, gravity and speed like a real step.
+    // Returns the instance's position at the end of every 10 steps, and the RNG so its state can be checked.
+    fn trace(buttons: &[u8], relative: bool, seed: i32) -> (Vec<(Real, Real)>, Random) {
+        let mut rand = Random::with_seed(seed);
+        let instance = Instance::new_dummy(None);
+        instance.friction.set(Real::from(0.1));
+        instance.gravity.set(Real::from(0.25));
+        instance.gravity_direction.set(Real::from(270.0));
+        let mut positions = Vec::new();
+        for step in 0..100 {
+            if step % 10 == 0 {
+                if let Some(button) = move_fixed_choice(buttons, &mut rand) {
+                    let (speed, direction) = move_fixed_motion(button, Real::from(6.0));
+                    if relative {
+                        instance.motion_add(direction, speed);
+                    } else {
+                        instance.set_speed_direction(speed, direction);
+                    }
+                }
+            }
+            apply_friction_gravity(&instance);
+            instance.x.set(instance.x.get() + instance.hspeed.get());
+            instance.y.set(instance.y.get() + instance.vspeed.get());
+            if step % 10 == 9 {
+                positions.push((instance.x.get(), instance.y.get()));
+            }
+        }
+        (positions, rand)
+    }
+
+    fn assert_trace(actual: &[(Real, Real)], expected: &[(f64, f64)]) {
+        assert_eq!(actual.len(), expected.len());
+        for (&(x, y), &(expected_x, expected_y)) in actual.iter().zip(expected) {
+            assert_close(x, expected_x);
+            assert_close(y, expected_y);
+        }
+    }
+
+    #[test]
+    fn move_fixed_rng_usage() {
+        let mut rand = Random::with_seed(1);
+        assert_eq!(move_fixed_choice(b"000000000", &mut rand), None);
+        assert_eq!(rand.seed(), 1);
+
+        // a single button still draws until it's hit, one RNG value per attempt
+        let mut expected = Random::with_seed(1);
+        while expected.next_int(8, Site::MoveFixed) != 7 {}
+        assert_eq!(move_fixed_choice(b"000000010", &mut rand), Some(7));
+        assert_eq!(rand.seed(), expected.seed());
+    }
+
+    #[test]
+    fn motion_add_is_vector_addition() {
+        let instance = Instance::new_dummy(None);
+        instance.set_speed_direction(Real::from(3.0), Real::from(0.0));
+        instance.motion_add(Real::from(90.0), Real::from(4.0));
+        assert_close(instance.hspeed.get(), 3.0);
+        assert_close(instance.vspeed.get(), -4.0);
+        assert_close(instance.speed.get(), 5.0);
+        instance.motion_add(Real::from(180.0), Real::from(3.0));
+        assert_close(instance.direction.get(), 90.0);
+        assert_close(instance.speed.get(), 4.0);
+    }
+
+    #[test]
+    fn move_fixed_golden_trace() {
+        let (positions, rand) = trace(b"101000101", false, 12345);
+        assert_trace(&positions, &[
+            (38.8676, 51.9969),
+            (0.0, 103.9937),
+            (-38.8676, 155.9906),
+            (-77.7352, 207.9875),
+            (-39.5795, 182.7334),
+            (-1.4238, 157.4793),
+            (37.4438, 209.4761),
+            (76.3114, 261.4730),
+            (38.1557, 236.2189),
+            (-0.7119, 288.2158),
+        ]);
+        assert_eq!(rand.seed(), 223020403);
+
+        let (positions, rand) = trace(b"111101111", true, -8);
+        assert_trace(&positions, &[
+            (-38.1557, -25.2541),
+            (-126.9703, -23.0102),
+            (-207.3296, 60.2046),
+            (-223.6546, 159.7293),
+            (-279.7758, 317.0826),
+            (-274.5744, 489.6943),
+            (-228.7993, 635.1575),
+            (-185.4479, 855.9324),
+            (-102.7480, 1049.8315),
+            (-65.4677, 1216.8011),
+        ]);
+        assert_eq!(rand.seed(), -1251588084);
+    }
+
+    #[test]
+    fn wrap_margins() {
+        let wrap = |x: f64, margin: f64| wrap(Real::from(x), 100, Real::from(margin)).map(f64::from);
+        assert_eq!(wrap(102.0, 2.0), None);
+        assert_eq!(wrap(-2.0, 2.0), None);
+        assert_eq!(wrap(50.0, -60.0), Some(70.0));
+        assert_close(Real::from(wrap(102.5, 2.0).unwrap()), -1.5);
+        assert_close(Real::from(wrap(-2.5, 2.0).unwrap()), 101.5);
+        assert_close(Real::from(wrap(-1.0, -16.0).unwrap()), 67.0);
+
+        // a bullet moving at a sub-pixel speed wraps on the first step its origin is more than the margin out
+        let mut x = Real::from(95.0);
+        let mut wrapped_at = None;
+        for step in 0..30 {
+            x += Real::from(0.4);
+            if let Some(new_x) = super::wrap(x, 100, Real::from(2.0)) {
+                x = new_x;
+                wrapped_at.get_or_insert(step);
+            }
+        }
+        assert_eq!(wrapped_at, Some(17));
+        assert_close(x, 3.0);
+    }
+
+    // A straight path from (0, 0) to (100, 0), taking 4 steps at a path_speed of 25
+    fn straight_path() -> Path {
+        let point = |x: f64| crate::asset::path::Point { x: x.into(), y: 0.into(), speed: 100.into() };
+        let mut path = Path {
+            name: "path".into(),
+            points: vec![point(0.0), point(100.0)],
+            control_nodes: Vec::new(),
+            length: 0.into(),
+            curve: false,
+            closed: false,
+            precision: 4,
+            start: Default::default(),
+            end: Default::default(),
+        };
+        path.update();
+        path
+    }
+
+    // Runs one step's movement like Game::frame does: xprevious, the step event, friction/gravity, speed and path,
+    // then the solid collision revert against a "wall" (re-applying speed once, and reverting again if still inside).
+    // Returns whether the path end event would have run.
+    fn step(
+        instance: &Instance,
+        path: &Path,
+        step_event: impl Fn(&Instance),
+        wall: impl Fn(&Instance) -> bool,
+    ) -> bool {
+        let apply_speeds = || {
+            apply_motion(instance);
+            instance.path_index.get() >= 0 && advance_path(instance, path)
+        };
+        save_previous(instance);
+        step_event(instance);
+        apply_friction_gravity(instance);
+        let path_end = apply_speeds();
+        if wall(instance) {
+            revert_to_previous(instance);
+            apply_speeds();
+            if wall(instance) {
+                revert_to_previous(instance);
+            }
+        }
+        path_end
+    }
+
+    #[test]
+    fn path_resets_speed() {
+        let path = straight_path();
+        let instance = Instance::new_dummy(None);
+        instance.gravity.set(Real::from(0.5));
+        instance.gravity_direction.set(Real::from(270.0));
+        instance.path_index.set(0);
+        instance.path_speed.set(Real::from(25.0));
+        let floor = |instance: &Instance| instance.y.get() >= Real::from(10.0);
+
+        // the path sets speed to 0 every step, so gravity never gets to build up while it runs
+        let mut trace = Vec::new();
+        for _ in 0..4 {
+            let path_end = step(&instance, &path, |_| (), floor);
+            trace.push((instance.x.get(), instance.y.get()));
+            assert_close(instance.speed.get(), 0.0);
+            assert_close(instance.vspeed.get(), 0.0);
+            assert_eq!(path_end, instance.path_index.get() == -1);
+        }
+        assert_trace(&trace, &[(25.0, 0.0), (50.0, 0.0), (75.0, 0.0), (100.0, 0.0)]);
+        assert_eq!(instance.path_index.get(), -1);
+
+        // then it starts falling from rest, until it lands on the floor and gets stuck above it
+        let mut trace = Vec::new();
+        for _ in 0..7 {
+            step(&instance, &path, |_| (), floor);
+            trace.push((instance.x.get(), instance.y.get()));
+        }
+        assert_trace(&trace, &[
+            (100.0, 0.5),
+            (100.0, 1.5),
+            (100.0, 3.0),
+            (100.0, 5.0),
+            (100.0, 7.5),
+            (100.0, 7.5),
+            (100.0, 7.5),
+        ]);
+        assert_close(instance.direction.get(), 270.0);
+    }
+
+    #[test]
+    fn solid_revert_undoes_whole_step() {
+        // hitting a wall while on a path puts path_position back too, so the instance stays where it was
+        let path = straight_path();
+        let instance = Instance::new_dummy(None);
+        instance.path_index.set(0);
+        instance.path_speed.set(Real::from(25.0));
+        let wall = |instance: &Instance| instance.x.get() >= Real::from(60.0);
+        for _ in 0..4 {
+            assert!(!step(&instance, &path, |_| (), wall));
+        }
+        assert_close(instance.x.get(), 50.0);
+        assert_close(instance.path_position.get(), 0.5);
+
+        // mp_potential_step moves during the step event, so it gets reverted along with speed,
+        // but only speed is applied again afterwards
+        let instance = Instance::new_dummy(None);
+        instance.set_hspeed(Real::from(5.0));
+        let settings = PotentialStepSettings::default();
+        let potential_step = |instance: &Instance| {
+            potential_step(Real::from(100.0), Real::from(0.0), Real::from(10.0), &settings, instance, || false);
+        };
+        let wall = |instance: &Instance| instance.x.get() >= Real::from(25.0);
+        step(&instance, &path, potential_step, wall);
+        assert_close(instance.x.get(), 15.0);
+        step(&instance, &path, potential_step, wall);
+        assert_close(instance.x.get(), 20.0);
+        assert_close(instance.xprevious.get(), 15.0);
+    }
+}
@@ -0,0 +1,312 @@
+//! Frame pacing for normal play and replays, and a graph of how it's going.
+//!
+//! This only decides when the next frame starts in real time. Nothing here touches the spoofed clock,
+//! so how a game sees time passing is the same however the frames end up being paced.
+
+use crate::{
+    render::{OverlayRect, Renderer},
+    types::Colour,
+};
+use ramen::window::Window;
+use std::{
+    collections::VecDeque,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The most time we'll spend spinning at the end of a frame instead of sleeping.
+const MAX_OVERSHOOT: Duration = Duration::from_millis(16);
+
+/// How many frames the frame time graph shows, each as a bar two pixels wide.
+const GRAPH_FRAMES: usize = 120;
+
+/// How tall a bar in the frame time graph is for each millisecond the frame took.
+const GRAPH_PIXELS_PER_MS: f64 = 3.0;
+
+/// The tallest a bar in the frame time graph gets, so one long hitch doesn't cover the game.
+const GRAPH_HEIGHT: i32 = 150;
+
+pub struct FramePacer {
+    /// The display the window was on last time, and its refresh rate if it could be found out.
+    display: Option<(usize, Option<u32>)>,
+
+    /// How much later than asked for thread::sleep has been waking up.
+    overshoot: Duration,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self { display: None, overshoot: Duration::from_millis(1) }
+    }
+}
+
+impl FramePacer {
+    /// Waits for the end of a frame which started at `start` and should last `duration`, and returns when the next one
+    /// starts. If vsync is on and it can keep frames at the room speed by itself, this lets it, by setting the swap
+    /// interval so that presenting the next frame blocks until it's due.
+    pub fn wait(
+        &mut self,
+        renderer: &Renderer,
+        window: Option<&Window>,
+        start: Instant,
+        duration: Duration,
+        room_speed: u32,
+    ) -> Instant {
+        if renderer.get_vsync() {
+            let refresh_rate = window.and_then(|window| self.refresh_rate(window));
+            if let Some(interval) = refresh_rate.and_then(|rate| vsync_interval(rate, room_speed)) {
+                if renderer.get_swap_interval() != interval {
+                    renderer.set_swap_interval(interval);
+                }
+                return Instant::now()
+            } else if renderer.get_swap_interval() != 1 {
+                renderer.set_swap_interval(1);
+            }
+        }
+
+        let end = start + duration;
+        if Instant::now() < end {
+            self.sleep_until(end);
+            end
+        } else {
+            Instant::now()
+        }
+    }
+
+    /// The refresh rate of the display the window is on, which is only looked up again when it moves to another one.
+    fn refresh_rate(&mut self, window: &Window) -> Option<u32> {
+        let display = display(window);
+        match self.display {
+            Some((last, rate)) if last == display => rate,
+            _ => {
+                let rate = refresh_rate(display);
+                self.display = Some((display, rate));
+                rate
+            },
+        }
+    }
+
+    /// Sleeps until just before the deadline, leaving enough time for sleep waking up late, and spins for the rest.
+    fn sleep_until(&mut self, deadline: Instant) {
+        let now = Instant::now();
+        match deadline.saturating_duration_since(now).checked_sub(self.overshoot) {
+            Some(sleep_time) if sleep_time > Duration::ZERO => {
+                thread::sleep(sleep_time);
+                let woke = Instant::now().saturating_duration_since(now + sleep_time);
+                self.overshoot = update_overshoot(self.overshoot, woke);
+            },
+            // Not sleeping at all means never finding out if the estimate's come down, so let it drift down anyway
+            _ => self.overshoot = update_overshoot(self.overshoot, Duration::ZERO),
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// The swap interval which makes vsync run at the room speed, if there is one.
+pub fn vsync_interval(refresh_rate: u32, room_speed: u32) -> Option<u32> {
+    match refresh_rate.checked_rem(room_speed) {
+        Some(0) => Some(refresh_rate / room_speed),
+        _ => None,
+    }
+}
+
+/// Updates the estimate of how late sleeping wakes up, given how late it just woke.
+/// Waking later than expected makes a frame late, so the estimate goes straight up, but it only comes down gradually.
+pub fn update_overshoot(estimate: Duration, woke: Duration) -> Duration {
+    if woke > estimate { woke.min(MAX_OVERSHOOT) } else { estimate - (estimate - woke) / 8 }
+}
+
+/// A graph of how long the last few frames really took, which --frame-times draws over the bottom left corner of
+/// the window. The line across it is how long a frame should take at the room speed.
+pub struct FrameTimeGraph {
+    last: Instant,
+    times: VecDeque<Duration>,
+}
+
+impl Default for FrameTimeGraph {
+    fn default() -> Self {
+        Self { last: Instant::now(), times: VecDeque::with_capacity(GRAPH_FRAMES) }
+    }
+}
+
+impl FrameTimeGraph {
+    /// Records that a frame has just ended.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        self.push(now.saturating_duration_since(self.last));
+        self.last = now;
+    }
+
+    fn push(&mut self, time: Duration) {
+        if self.times.len() == GRAPH_FRAMES {
+            self.times.pop_front();
+        }
+        self.times.push_back(time);
+    }
+
+    /// The rectangles to draw the graph with, given how long each frame should take. Frames which took about
+    /// that long are green, ones which took up to twice as long are yellow, and any slower are red.
+    pub fn rects(&self, target: Duration) -> Vec<OverlayRect> {
+        let height = |time: Duration| {
+            ((time.as_secs_f64() * 1000.0 * GRAPH_PIXELS_PER_MS).round() as i32).clamp(1, GRAPH_HEIGHT)
+        };
+        let width = GRAPH_FRAMES as i32 * 2;
+        let mut rects = Vec::with_capacity(self.times.len() + 2);
+        rects.push(OverlayRect { x: 0, y: 0, w: width, h: GRAPH_HEIGHT, colour: Colour::new(0.1, 0.1, 0.1) });
+        for (i, &time) in self.times.iter().enumerate() {
+            let colour = if time <= target + target / 10 {
+                Colour::new(0.2, 0.8, 0.2)
+            } else if time <= target * 2 {
+                Colour::new(0.9, 0.8, 0.1)
+            } else {
+                Colour::new(0.9, 0.2, 0.2)
+            };
+            rects.push(OverlayRect { x: i as i32 * 2, y: 0, w: 2, h: height(time), colour });
+        }
+        rects.push(OverlayRect { x: 0, y: height(target), w: width, h: 1, colour: Colour::new(1.0, 1.0, 1.0) });
+        rects
+    }
+}
+
+/// Identifies the display the window is mostly on.
+#[cfg(target_os = "windows")]
+fn display(window: &Window) -> usize {
+    use ramen::platform::win32::WindowExt as _;
+    use std::os::raw::c_void;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn MonitorFromWindow(hwnd: *mut c_void, flags: u32) -> *mut c_void;
+    }
+    const MONITOR_DEFAULTTONEAREST: u32 = 2;
+
+    unsafe { MonitorFromWindow(window.hwnd() as _, MONITOR_DEFAULTTONEAREST) as usize }
+}
+
+/// The refresh rate of a display.
+#[cfg(target_os = "windows")]
+fn refresh_rate(display: usize) -> Option<u32> {
+    use std::{
+        mem,
+        os::raw::{c_int, c_void},
+        ptr,
+    };
+
+    #[repr(C)]
+    struct MonitorInfoExW {
+        size: u32,
+        monitor: [i32; 4],
+        work: [i32; 4],
+        flags: u32,
+        device: [u16; 32],
+    }
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetMonitorInfoW(monitor: *mut c_void, info: *mut MonitorInfoExW) -> c_int;
+    }
+    #[link(name = "gdi32")]
+    extern "system" {
+        fn CreateDCW(driver: *const u16, device: *const u16, port: *const u16, mode: *const c_void) -> *mut c_void;
+        fn GetDeviceCaps(hdc: *mut c_void, index: c_int) -> c_int;
+        fn DeleteDC(hdc: *mut c_void) -> c_int;
+    }
+    const VREFRESH: c_int = 116;
+
+    unsafe {
+        let size = mem::size_of::<MonitorInfoExW>() as u32;
+        let mut info = MonitorInfoExW { size, monitor: [0; 4], work: [0; 4], flags: 0, device: [0; 32] };
+        if display == 0 || GetMonitorInfoW(display as _, &mut info) == 0 {
+            return None
+        }
+        // the device name is something like \\.\DISPLAY2, which gets a DC for the whole of that display
+        let dc = CreateDCW(info.device.as_ptr(), ptr::null(), ptr::null(), ptr::null());
+        if dc.is_null() {
+            return None
+        }
+        let rate = GetDeviceCaps(dc, VREFRESH);
+        DeleteDC(dc);
+        // 0 and 1 both mean the hardware's default, whatever that is
+        if rate > 1 { Some(rate as u32) } else { None }
+    }
+}
+
+// TODO: Others!
+#[cfg(not(target_os = "windows"))]
+fn display(_window: &Window) -> usize {
+    0
+}
+
+#[cfg(not(target_os = "windows"))]
+fn refresh_rate(_display: usize) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vsync_intervals() {
+        assert_eq!(vsync_interval(60, 60), Some(1));
+        assert_eq!(vsync_interval(120, 60), Some(2));
+        assert_eq!(vsync_interval(144, 48), Some(3));
+        assert_eq!(vsync_interval(240, 30), Some(8));
+        // these have to fall back to the frame limiter
+        assert_eq!(vsync_interval(144, 60), None);
+        assert_eq!(vsync_interval(59, 60), None);
+        assert_eq!(vsync_interval(60, 50), None);
+        assert_eq!(vsync_interval(60, 0), None);
+    }
+
+    #[test]
+    fn overshoot_estimate() {
+        let ms = Duration::from_millis;
+        let mut estimate = ms(1);
+        // a coarse timer makes it go straight up, but not past the limit
+        estimate = update_overshoot(estimate, ms(15));
+        assert_eq!(estimate, ms(15));
+        assert_eq!(update_overshoot(estimate, ms(40)), MAX_OVERSHOOT);
+
+        // and then it comes back down over a few frames
+        for _ in 0..20 {
+            let next = update_overshoot(estimate, ms(1));
+            assert!(next < estimate && next > ms(1));
+            estimate = next;
+        }
+        assert!(estimate < ms(2));
+        for _ in 0..200 {
+            estimate = update_overshoot(estimate, Duration::ZERO);
+        }
+        assert!(estimate < Duration::from_micros(10));
+    }
+
+    #[test]
+    fn frame_time_graph() {
+        let ms = Duration::from_millis;
+        let mut graph = FrameTimeGraph::default();
+        for time in [ms(20), ms(21), ms(30), ms(50), ms(500)] {
+            graph.push(time);
+        }
+        let rects = graph.rects(ms(20));
+        assert_eq!(rects.len(), 7);
+        let (background, bars, line) = (rects[0], &rects[1..6], rects[6]);
+        assert_eq!((background.w, background.h), (GRAPH_FRAMES as i32 * 2, GRAPH_HEIGHT));
+        assert_eq!((line.y, line.w, line.h), (60, GRAPH_FRAMES as i32 * 2, 1));
+        let bars_at = bars.iter().map(|x| (x.x, x.h)).collect::<Vec<_>>();
+        assert_eq!(bars_at, [(0, 60), (2, 63), (4, 90), (6, 150), (8, 150)]);
+        let colours = bars.iter().map(|x| x.colour.as_rgb()).collect::<Vec<_>>();
+        assert_eq!(colours[0], colours[1]);
+        assert_eq!(colours[3], colours[4]);
+        assert!(colours[1] != colours[2] && colours[2] != colours[3]);
+
+        // only the most recent frames are kept
+        for _ in 0..GRAPH_FRAMES {
+            graph.push(ms(1));
+        }
+        let rects = graph.rects(ms(20));
+        assert_eq!(rects.len(), GRAPH_FRAMES + 2);
+        assert!(rects[1..=GRAPH_FRAMES].iter().all(|x| x.h == 3));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     game::{Assets, GetAsset},
-    gml::rand::Random,
+    gml::rand::{Random, Site},
     math::Real,
     render::{
         atlas::{AtlasBuilder, AtlasRef},
@@ -524,8 +524,8 @@ impl Manager {
                         pt.life_min = (Real::from(25) / fps_mod).round().to_i32();
                         pt.life_max = pt.life_min;
                         for _ in 0..6 {
-                            let dx = rand.next_int(9) - 5;
-                            let dy = rand.next_int(9) - 5;
+                            let dx = rand.next_int(9, Site::ParticleEffect) - 5;
+                            let dy = rand.next_int(9, Site::ParticleEffect) - 5;
                             self.system_create_particles(system, x + dx.into(), y + dy.into(), id, Some(col), 1, rand);
                         }
                     },
@@ -535,8 +535,8 @@ impl Manager {
                         pt.life_min = (Real::from(30) / fps_mod).round().to_i32();
                         pt.life_max = pt.life_min;
                         for _ in 0..11 {
-                            let dx = rand.next_int(29) - 15;
-                            let dy = rand.next_int(29) - 15;
+                            let dx = rand.next_int(29, Site::ParticleEffect) - 15;
+                            let dy = rand.next_int(29, Site::ParticleEffect) - 15;
                             self.system_create_particles(system, x + dx.into(), y + dy.into(), id, Some(col), 1, rand);
                         }
                     },
@@ -546,8 +546,8 @@ impl Manager {
                         pt.life_min = (Real::from(50) / fps_mod).round().to_i32();
                         pt.life_max = pt.life_min;
                         for _ in 0..16 {
-                            let dx = rand.next_int(59) - 30;
-                            let dy = rand.next_int(59) - 30;
+                            let dx = rand.next_int(59, Site::ParticleEffect) - 30;
+                            let dy = rand.next_int(59, Site::ParticleEffect) - 30;
                             self.system_create_particles(system, x + dx.into(), y + dy.into(), id, Some(col), 1, rand);
                         }
                     },
@@ -577,8 +577,8 @@ impl Manager {
                         pt.life_min = (Real::from(25) / fps_mod).round().to_i32();
                         pt.life_max = pt.life_min;
                         for _ in 0..6 {
-                            let dx = rand.next_int(9) - 5;
-                            let dy = rand.next_int(9) - 5;
+                            let dx = rand.next_int(9, Site::ParticleEffect) - 5;
+                            let dy = rand.next_int(9, Site::ParticleEffect) - 5;
                             self.system_create_particles(system, x + dx.into(), y + dy.into(), id, Some(col), 1, rand);
                         }
                     },
@@ -590,8 +590,8 @@ impl Manager {
                         pt.life_min = (Real::from(30) / fps_mod).round().to_i32();
                         pt.life_max = pt.life_min;
                         for _ in 0..11 {
-                            let dx = rand.next_int(29) - 15;
-                            let dy = rand.next_int(29) - 15;
+                            let dx = rand.next_int(29, Site::ParticleEffect) - 15;
+                            let dy = rand.next_int(29, Site::ParticleEffect) - 15;
                             self.system_create_particles(system, x + dx.into(), y + dy.into(), id, Some(col), 1, rand);
                         }
                     },
@@ -603,8 +603,8 @@ impl Manager {
                         pt.life_min = (Real::from(50) / fps_mod).round().to_i32();
                         pt.life_max = pt.life_min;
                         for _ in 0..16 {
-                            let dx = rand.next_int(59) - 30;
-                            let dy = rand.next_int(59) - 30;
+                            let dx = rand.next_int(59, Site::ParticleEffect) - 30;
+                            let dy = rand.next_int(59, Site::ParticleEffect) - 30;
                             self.system_create_particles(system, x + dx.into(), y + dy.into(), id, Some(col), 1, rand);
                         }
                     },
@@ -736,8 +736,8 @@ impl Manager {
                 for _ in 0..number {
                     self.system_create_particles(
                         system,
-                        Real::from(rand.next(1.2)) * room_width.into(),
-                        Real::from(rand.next_int(19) - 30),
+                        Real::from(rand.next(1.2, Site::ParticleEffect)) * room_width.into(),
+                        Real::from(rand.next_int(19, Site::ParticleEffect) - 30),
                         id,
                         Some(col),
                         1,
@@ -779,8 +779,8 @@ impl Manager {
                 for _ in 0..number {
                     self.system_create_particles(
                         system,
-                        Real::from(rand.next(1.2)) * room_width.into() - 60.into(),
-                        Real::from(rand.next_int(19) - 30),
+                        Real::from(rand.next(1.2, Site::ParticleEffect)) * room_width.into() - 60.into(),
+                        Real::from(rand.next_int(19, Site::ParticleEffect) - 30),
                         id,
                         Some(col),
                         1,
@@ -1005,11 +1005,11 @@ impl Distribution {
     fn range(&self, rand: &mut Random, min: Real, max: Real) -> Real {
         if min < max {
             match self {
-                Distribution::Linear => Real::from(rand.next((max - min).into())) + min,
+                Distribution::Linear => Real::from(rand.next((max - min).into(), Site::ParticleRange)) + min,
                 Distribution::Gaussian => {
                     let x = loop {
-                        let x = Real::from(rand.next(6.0) - 3.0);
-                        if (-x * x * Real::from(0.5)).exp() > Real::from(rand.next(1.0)) {
+                        let x = Real::from(rand.next(6.0, Site::ParticleRange) - 3.0);
+                        if (-x * x * Real::from(0.5)).exp() > Real::from(rand.next(1.0, Site::ParticleRange)) {
                             break x
                         }
                     };
@@ -1017,8 +1017,8 @@ impl Distribution {
                 },
                 Distribution::InvGaussian => {
                     let mut x = loop {
-                        let x = Real::from(rand.next(6.0) - 3.0);
-                        if (-x * x * Real::from(0.5)).exp() > Real::from(rand.next(1.0)) {
+                        let x = Real::from(rand.next(6.0, Site::ParticleRange) - 3.0);
+                        if (-x * x * Real::from(0.5)).exp() > Real::from(rand.next(1.0, Site::ParticleRange)) {
                             break x
                         }
                     };
@@ -1202,14 +1202,14 @@ impl System {
             if let Some(ptype) = types.get_asset(particle.ptype) {
                 if particle.timer >= particle.lifetime {
                     let mut number = ptype.death_number;
-                    if number < 0 && rand.next_int((-number) as u32) == 0 {
+                    if number < 0 && rand.next_int((-number) as u32, Site::ParticleCount) == 0 {
                         number = 1;
                     }
                     self.create_particles(x, y, ptype.death_type, None, number, rand, types);
                 } else {
                     // particle is alive
                     let mut number = ptype.step_number;
-                    if number < 0 && rand.next_int((-number) as u32) == 0 {
+                    if number < 0 && rand.next_int((-number) as u32, Site::ParticleCount) == 0 {
                         number = 1;
                     }
                     self.create_particles(x, y, ptype.step_type, None, number, rand, types);
@@ -1241,10 +1241,10 @@ impl Particle {
             let mut subimage = 0;
             if let ParticleGraphic::Sprite { sprite: _, animat: _, stretch: _, random } = ptype.graphic {
                 if random {
-                    subimage = rand.next_int(10000);
+                    subimage = rand.next_int(10000, Site::ParticleSubimage);
                 }
             }
-            let random_start = rand.next_int(100000);
+            let random_start = rand.next_int(100000, Site::ParticleSubimage);
             Some(Self {
                 ptype: ptype_id,
                 timer: 0,
@@ -1306,7 +1306,7 @@ impl Particle {
                 let out_b = ((b + m) * Real::from(255.0)).round().to_i32();
                 out_r | (out_g << 8) | (out_b << 16)
             },
-            ParticleColour::Mix(c1, c2) => colour_lerp(*c1, *c2, rand.next(1.0).into()),
+            ParticleColour::Mix(c1, c2) => colour_lerp(*c1, *c2, rand.next(1.0, Site::ParticleColour).into()),
         }
     }
 
@@ -1603,7 +1603,7 @@ impl Emitter {
         types: &dyn GetAsset<Box<ParticleType>>,
     ) {
         let number = if number < 0 {
-            if rand.next_int(-number as u32) == 0 { 1 } else { return }
+            if rand.next_int(-number as u32, Site::ParticleCount) == 0 { 1 } else { return }
         } else {
             number
         };
@@ -1612,10 +1612,10 @@ impl Emitter {
                 let mut xspawn = self.distribution.range(rand, Real::from(0.0), Real::from(1.0));
                 let mut yspawn = self.distribution.range(rand, Real::from(0.0), Real::from(1.0));
                 if self.distribution == Distribution::InvGaussian && self.shape != Shape::Line {
-                    if rand.next(1.0) >= 0.5 {
-                        yspawn = rand.next(1.0).into();
+                    if rand.next(1.0, Site::ParticleRange) >= 0.5 {
+                        yspawn = rand.next(1.0, Site::ParticleRange).into();
                     } else {
-                        xspawn = rand.next(1.0).into();
+                        xspawn = rand.next(1.0, Site::ParticleRange).into();
                     }
                 }
                 if self.shape.contains(
@@ -96,6 +96,8 @@ pub fn linear_step(x: Real, y: Real, step_size: Real, instance: &Instance, coll:
 
 /// Performs a step towards the given destination. When a wall is reached, it will try to go around it.
 /// Returns true if the goal was reached, and false otherwise.
+/// This moves the instance directly, the same as setting x and y in a step event would, so the step still goes on to
+/// apply speed and paths on top of it and a solid collision reverts all of it back to xprevious/yprevious.
 pub fn potential_step(
     x: Real,
     y: Real,
@@ -158,7 +160,7 @@ pub fn potential_step(
             }
         }
         if !res && settings.rotate_on_spot {
-            instance.direction.set(instance.direction.get() + settings.max_rotation);
+            instance.direction.set((instance.direction.get() + settings.max_rotation).rem_euclid(360.into()));
         }
         res
     } else {
@@ -1,12 +1,15 @@
 use crate::{
     game::{
+        autosave::{self, AutosaveConfig, Autosaver},
+        error::RuntimeError,
         replay::{self, Replay},
         savestate::{self, SaveState},
         Game, SceneChange,
     },
-    gml::rand::Random,
+    gml::{self, rand::Random, Value},
     imgui, input,
     instance::Field,
+    locale::{self, tr, trf},
     render::{atlas::AtlasRef, PrimitiveType, Renderer, RendererState},
     types::Colour,
 };
@@ -18,7 +21,8 @@ use serde::{Deserialize, Serialize};
 use std::{
     convert::TryFrom,
     fs::File,
-    path::PathBuf,
+    io, iter,
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
@@ -86,41 +90,41 @@ impl KeyState {
         let open = if !frame.window_focused() {
             false
         } else if self.is_held() {
-            if frame.menu_item("(Keep Held)") {
+            if frame.menu_item(tr("key_keep_held")) {
                 *self = KeyState::Held;
                 false
-            } else if frame.menu_item("Release") {
+            } else if frame.menu_item(tr("key_release")) {
                 *self = KeyState::HeldWillRelease;
                 false
-            } else if frame.menu_item("Release, Press") {
+            } else if frame.menu_item(tr("key_release_press")) {
                 *self = KeyState::HeldWillDouble;
                 false
-            } else if frame.menu_item("Release, Press, Release") {
+            } else if frame.menu_item(tr("key_release_press_release")) {
                 *self = KeyState::HeldWillTriple;
                 false
-            } else if frame.menu_item("Tap Every Frame") {
+            } else if frame.menu_item(tr("key_tap_every_frame")) {
                 *self = KeyState::HeldDoubleEveryFrame;
                 false
             } else {
                 true
             }
         } else {
-            if frame.menu_item("(Keep Neutral)") {
+            if frame.menu_item(tr("key_keep_neutral")) {
                 *self = KeyState::Neutral;
                 false
-            } else if frame.menu_item("Press") {
+            } else if frame.menu_item(tr("key_press")) {
                 *self = KeyState::NeutralWillPress;
                 false
-            } else if frame.menu_item("Press, Release") {
+            } else if frame.menu_item(tr("key_press_release")) {
                 *self = KeyState::NeutralWillDouble;
                 false
-            } else if frame.menu_item("Press, Release, Press") {
+            } else if frame.menu_item(tr("key_press_release_press")) {
                 *self = KeyState::NeutralWillTriple;
                 false
-            } else if frame.menu_item("Tap Every Frame") {
+            } else if frame.menu_item(tr("key_tap_every_frame")) {
                 *self = KeyState::NeutralDoubleEveryFrame;
                 false
-            } else if frame.menu_item("Cactus-Release") {
+            } else if frame.menu_item(tr("key_cactus_release")) {
                 *self = KeyState::NeutralWillCactus;
                 false
             } else {
@@ -133,17 +137,17 @@ impl KeyState {
 
     fn repr(&self) -> &'static str {
         match self {
-            Self::Neutral => "Neutral",
-            Self::NeutralWillPress => "Neutral; will press",
-            Self::NeutralWillDouble => "Neutral; will press and release",
-            Self::NeutralWillTriple => "Neutral; will press, release, press",
-            Self::NeutralDoubleEveryFrame => "Neutral; will tap on each frame",
-            Self::NeutralWillCactus => "Neutral; will cactus-release",
-            Self::Held => "Held",
-            Self::HeldWillRelease => "Held; will release",
-            Self::HeldWillDouble => "Held; will release and press",
-            Self::HeldWillTriple => "Held; will release, press, release",
-            Self::HeldDoubleEveryFrame => "Held; will tap on each frame",
+            Self::Neutral => tr("state_neutral"),
+            Self::NeutralWillPress => tr("state_neutral_will_press"),
+            Self::NeutralWillDouble => tr("state_neutral_will_double"),
+            Self::NeutralWillTriple => tr("state_neutral_will_triple"),
+            Self::NeutralDoubleEveryFrame => tr("state_neutral_every_frame"),
+            Self::NeutralWillCactus => tr("state_neutral_will_cactus"),
+            Self::Held => tr("state_held"),
+            Self::HeldWillRelease => tr("state_held_will_release"),
+            Self::HeldWillDouble => tr("state_held_will_double"),
+            Self::HeldWillTriple => tr("state_held_will_triple"),
+            Self::HeldDoubleEveryFrame => tr("state_held_every_frame"),
         }
     }
 }
@@ -153,6 +157,7 @@ enum ContextMenu {
     MouseButton { pos: imgui::Vec2<f32>, button: i8 },
     Instances { pos: imgui::Vec2<f32>, options: Vec<(String, i32)> },
     Seed { pos: imgui::Vec2<f32> },
+    AudioDevice { pos: imgui::Vec2<f32>, devices: Vec<String> },
 }
 
 #[derive(Deserialize, Serialize)]
@@ -172,8 +177,14 @@ struct ProjectConfig {
     quicksave_slot: usize,
 }
 
+impl ProjectConfig {
+    fn save(&self, path: &Path) -> io::Result<()> {
+        autosave::write_atomic(path, |f| bincode::serialize_into(f, self).map_err(io::Error::other))
+    }
+}
+
 impl Game {
-    pub fn record(&mut self, project_path: PathBuf) {
+    pub fn record(&mut self, project_path: PathBuf, autosave_config: AutosaveConfig) {
         let mut save_buffer = savestate::Buffer::new();
         let mut startup_successful = true;
 
@@ -200,12 +211,13 @@ impl Game {
                 },
             }
         } else {
-            bincode::serialize_into(File::create(&config_path).expect("Couldn't write project.cfg"), &default_config)
-                .expect("Couldn't serialize project.cfg");
+            default_config.save(&config_path).expect("Couldn't write project.cfg");
             default_config
         };
 
         let mut replay = Replay::new(self.spoofed_time_nanos.unwrap_or(0), self.rand.seed());
+        replay.game = Some(self.game_info());
+        replay.rerecords = config.rerecords;
 
         let mut context = imgui::Context::new();
         context.make_current();
@@ -221,6 +233,17 @@ impl Game {
         }
         io.set_display_size(imgui::Vec2(f32::from(config.ui_width), f32::from(config.ui_height)));
 
+        // imgui's default font only has Latin characters, so others are merged in for the UI language and game text
+        let scripts = locale::scripts(locale::language(), self.encoding);
+        let font_files = scripts.iter().filter_map(|&x| Some((x, locale::font_file(x)?))).collect::<Vec<_>>();
+        for script in scripts.iter().filter(|x| !font_files.iter().any(|(y, _)| y == *x)) {
+            println!("Warning: couldn't find a font with {:?} characters, so some text won't show up", script);
+        }
+        let font_files = font_files.iter().map(|(script, path)| (*script, path.as_path())).collect::<Vec<_>>();
+        for path in io.load_fonts(&font_files) {
+            println!("Warning: couldn't load font {}, so some text won't show up", path.display());
+        }
+
         // TODO probably don't store these textures in the same places as the game textures
         let imgui::FontData { data: fdata, size: (fwidth, fheight) } = io.font_data();
         let mut font = self
@@ -307,42 +330,61 @@ impl Game {
         let mut game_running = true; // false indicates the game closed or crashed, and so advancing is not allowed
         let mut err_string: Option<String> = None;
 
-        let mut frame_text = String::from("Frame: 0");
-        let mut seed_text = format!("Seed: {}", self.rand.seed());
-        let mut rerecord_text = format!("Re-record count: {}", config.rerecords);
-        let save_text = (0..16).map(|i| format!("Save {}", i + 1)).collect::<Vec<_>>();
-        let load_text = (0..16).map(|i| format!("Load {}", i + 1)).collect::<Vec<_>>();
-        let select_text = (0..16).map(|i| format!("Select###Select{}", i + 1)).collect::<Vec<_>>();
+        let mut frame_text = trf("control_frame", &[&0]);
+        let mut seed_text = trf("control_seed", &[&self.rand.seed()]);
+        let mut rerecord_text = trf("control_rerecords", &[&config.rerecords]);
+        let save_text =
+            (0..16).map(|i| format!("{}###Save{}", trf("savestate_save", &[&(i + 1)]), i + 1)).collect::<Vec<_>>();
+        let load_text =
+            (0..16).map(|i| format!("{}###Load{}", trf("savestate_load", &[&(i + 1)]), i + 1)).collect::<Vec<_>>();
+        let select_text = (0..16).map(|i| format!("{}###Select{}", tr("savestate_select"), i + 1)).collect::<Vec<_>>();
         let mut context_menu: Option<ContextMenu> = None;
+
+        // Room navigator and instance list. Anything which runs game code waits for the next frame to be advanced.
+        let room_names = {
+            use crate::game::GetAsset;
+            self.room_order
+                .iter()
+                .map(|&id| match self.assets.rooms.get_asset(id) {
+                    Some(room) => format!("{} ({})", room.name.decode(self.encoding), id),
+                    None => format!("{} ({})", tr("deleted_room"), id),
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut pending_navigation: Vec<Navigation> = Vec::new();
+        let mut instance_filter = [0u8; 64];
+        let mut selected_instance: Option<i32> = None;
+        let mut variable_name = [0u8; 64];
+        let mut variable_value = [0u8; 256];
+        let mut run_destroy_event = true;
+        let mut click_close_button = false; // whether the next frame should have the window's close button clicked
+        let mut assisted = false; // true once the navigator has changed the game, so the replay won't match
         let mut savestate;
         let mut renderer_state;
 
         if !save_paths[config.quicksave_slot].exists() {
             if let Err(e) = match self.init() {
                 Ok(()) => match self.scene_change {
-                    Some(SceneChange::Room(id)) => self.load_room(id),
-                    Some(SceneChange::Restart) => self.restart(),
+                    Some(SceneChange::Room(id)) => self.load_room(id).map_err(|e| e.to_string()),
+                    Some(SceneChange::Restart) => self.restart().map_err(|e| e.to_string()),
                     Some(SceneChange::End) => {
                         startup_successful = false;
                         match self.run_game_end_events() {
-                            Ok(()) => Err("(Fatal) Game ended during startup".into()),
-                            Err(e) => {
-                                Err(format!("(Fatal) Game ended during startup, then crashed during Game End: {}", e)
-                                    .into())
-                            },
+                            Ok(()) => Err(tr("error_startup_ended").into()),
+                            Err(e) => Err(trf("error_startup_ended_crashed", &[&e])),
                         }
                     },
                     Some(SceneChange::Load(ref mut path)) => {
                         let path = std::mem::take(path);
-                        self.load_gm_save(path)
+                        self.load_gm_save(path).map_err(|e| e.to_string())
                     },
                     None => Ok(()),
                 },
-                Err(e) => Err(e),
+                Err(e) => Err(e.to_string()),
             } {
                 game_running = false;
                 startup_successful = false;
-                err_string = Some(format!("(Fatal) Game crashed during startup: {}", e));
+                err_string = Some(trf("error_startup_crashed", &[&e]));
             }
             for ev in self.stored_events.iter() {
                 replay.startup_events.push(ev.clone());
@@ -355,20 +397,18 @@ impl Game {
             savestate = SaveState::from(self, replay.clone(), renderer_state.clone());
 
             if let Err(err) = savestate.save_to_file(&save_paths[config.quicksave_slot], &mut save_buffer) {
-                err_string = Some(format!(
-                    concat!(
-                        "Warning: failed to create {:?} (it has still been saved in memory)\n\n",
-                        "Error message: {:?}",
-                    ),
-                    save_paths[config.quicksave_slot].file_name(),
-                    err,
-                ));
+                err_string = Some(trf("error_create_quicksave", &[
+                    &format!("{:?}", save_paths[config.quicksave_slot].file_name().unwrap_or_default()),
+                    &format!("{:?}", err),
+                ]));
             }
         } else {
             match SaveState::from_file(&save_paths[config.quicksave_slot], &mut save_buffer) {
                 Ok(state) => {
                     let (rep, ren) = state.clone().load_into(self);
                     replay = rep;
+                    replay.rerecords = config.rerecords;
+                    replay.game = Some(self.game_info());
                     renderer_state = ren;
 
                     for (i, state) in keyboard_state.iter_mut().enumerate() {
@@ -381,8 +421,8 @@ impl Game {
                             if self.input.mouse_check_button(i as i8 + 1) { KeyState::Held } else { KeyState::Neutral };
                     }
 
-                    frame_text = format!("Frame: {}", replay.frame_count());
-                    seed_text = format!("Seed: {}", self.rand.seed());
+                    frame_text = trf("control_frame", &[&replay.frame_count()]);
+                    seed_text = trf("control_seed", &[&self.rand.seed()]);
                     self.renderer.resize_framebuffer(config.ui_width.into(), config.ui_height.into(), false);
                     self.renderer.set_state(&ui_renderer_state);
                     savestate = state;
@@ -390,7 +430,7 @@ impl Game {
                 Err(e) => {
                     // Just to initialize renderer_state and keep the compiler happy, this won't be used...
                     renderer_state = ui_renderer_state.clone();
-                    err_string = Some(format!("(Fatal) Error loading quicksave file: {:?}", e));
+                    err_string = Some(trf("error_load_quicksave", &[&format!("{:?}", e)]));
                     savestate = SaveState::from(self, replay.clone(), renderer_state.clone());
                     startup_successful = false;
                     game_running = false;
@@ -398,7 +438,24 @@ impl Game {
             }
         }
 
-        self.window.set_inner_size(Size::Physical(config.ui_width.into(), config.ui_height.into()));
+        if startup_successful && err_string.is_none() {
+            let quicksave = &save_paths[config.quicksave_slot];
+            if let Some(recovery) = autosave::find_recovery(&project_path, quicksave, replay.frame_count()) {
+                err_string = Some(trf("recovery_available", &[
+                    &format!("{:?}", recovery.path.file_name().unwrap_or_default()),
+                    &format!("{:?}", quicksave.file_name().unwrap_or_default()),
+                    &recovery.frames,
+                    &replay.frame_count(),
+                    &format!("{:?}", recovery.path),
+                    &format!("{:?}", quicksave),
+                ]));
+            }
+        }
+        let mut autosaver = Autosaver::new(&project_path, autosave_config, config.rerecords);
+
+        if let Some(window) = &self.window {
+            window.set_inner_size(Size::Physical(config.ui_width.into(), config.ui_height.into()));
+        }
 
         for (i, state) in keyboard_state.iter_mut().enumerate() {
             if self.input.keyboard_check_direct(i as u8) {
@@ -414,6 +471,8 @@ impl Game {
         let mut instance_reports: Vec<(i32, Option<InstanceReport>)> =
             config.watched_ids.iter().map(|id| (*id, InstanceReport::new(&*self, *id))).collect();
         let mut instance_images: Vec<AtlasRef> = Vec::new();
+        let mut atlas_browser: Option<AtlasBrowser> = None;
+        let mut atlas_images: Vec<AtlasRef> = Vec::new();
         let mut new_rand: Option<Random> = None;
         let mut callback_data; // Putting this outside the loop makes sure it never goes out of scope
 
@@ -429,8 +488,10 @@ impl Game {
             io.set_mouse_wheel(0.0);
 
             // poll window events
-            self.window.swap_events();
-            for event in self.window.events() {
+            if let Some(window) = &mut self.window {
+                window.swap_events();
+            }
+            for event in self.window.iter().flat_map(|x| x.events()) {
                 match event {
                     ev @ Event::KeyboardDown(key) | ev @ Event::KeyboardUp(key) => {
                         setting_mouse_pos = false;
@@ -471,7 +532,10 @@ impl Game {
             }
 
             // present imgui
-            let fps_text = format!("FPS: {}", io.framerate().round());
+            let fps_text = trf("control_fps", &[&io.framerate().round()]);
+            let audio_memory = self.audio_memory();
+            let audio_text =
+                trf("control_audio_memory", &[&(audio_memory.total() / 1024), &(audio_memory.decoded / 1024)]);
             let win_frame_height = context.frame_height();
             let win_border_size = context.window_border_size();
             let win_padding = context.window_padding();
@@ -549,7 +613,7 @@ impl Game {
                                 let id = instance.id.get();
                                 let description = match self.assets.objects.get_asset(instance.object_index.get()) {
                                     Some(obj) => format!("{} ({})", obj.name, id.to_string()),
-                                    None => format!("<deleted object> ({})", id.to_string()),
+                                    None => format!("{} ({})", tr("deleted_object"), id.to_string()),
                                 };
                                 options.push((description, id));
                             }
@@ -568,8 +632,9 @@ impl Game {
 
             // Control window
             frame.setup_next_window(imgui::Vec2(8.0, 8.0), None, None);
-            frame.begin_window("Control", None, true, false, None);
-            if (frame.button("Advance (Space)", imgui::Vec2(165.0, 20.0), None)
+            frame.begin_window(&format!("{}###Control", tr("window_control")), None, true, false, None);
+            frame.checkbox(tr("control_click_close"), &mut click_close_button);
+            if (frame.button(tr("control_advance"), imgui::Vec2(165.0, 20.0), None)
                 || frame.key_pressed(input::ramen2vk(Key::Space)))
                 && game_running
                 && err_string.is_none()
@@ -666,6 +731,12 @@ impl Game {
                     }
                 }
 
+                if click_close_button {
+                    click_close_button = false;
+                    frame.inputs.push(replay::Input::CloseButton);
+                    self.press_close_button();
+                }
+
                 if let Some((x, y)) = new_mouse_pos {
                     frame.mouse_x = x;
                     frame.mouse_y = y;
@@ -691,20 +762,25 @@ impl Game {
                     self.unscaled_height as _,
                 );
                 self.renderer.draw_stored(0, 0, w, h);
-                if let Err(e) = match self.frame() {
-                    Ok(()) => match self.scene_change {
-                        Some(SceneChange::Room(id)) => self.load_room(id),
-                        Some(SceneChange::Restart) => self.restart(),
-                        Some(SceneChange::End) => self.restart(),
-                        Some(SceneChange::Load(ref mut path)) => {
-                            let path = std::mem::take(path);
-                            self.load_gm_save(path)
+                if !pending_navigation.is_empty() {
+                    assisted = true;
+                }
+                if let Err(e) =
+                    pending_navigation.drain(..).try_for_each(|nav| nav.apply(self)).and_then(|()| match self.frame() {
+                        Ok(()) => match self.scene_change {
+                            Some(SceneChange::Room(id)) => self.load_room(id),
+                            Some(SceneChange::Restart) => self.restart(),
+                            Some(SceneChange::End) => self.restart(),
+                            Some(SceneChange::Load(ref mut path)) => {
+                                let path = std::mem::take(path);
+                                self.load_gm_save(path)
+                            },
+                            None => Ok(()),
                         },
-                        None => Ok(()),
-                    },
-                    Err(e) => Err(e.into()),
-                } {
-                    err_string = Some(format!("Game crashed: {}\n\nPlease load a savestate.", e));
+                        Err(e) => Err(e.into()),
+                    })
+                {
+                    err_string = Some(trf("error_crashed", &[&e]));
                     game_running = false;
                 }
 
@@ -729,8 +805,8 @@ impl Game {
                 }
                 self.frame_counter += 1;
 
-                frame_text = format!("Frame: {}", replay.frame_count());
-                seed_text = format!("Seed: {}", self.rand.seed());
+                frame_text = trf("control_frame", &[&replay.frame_count()]);
+                seed_text = trf("control_seed", &[&self.rand.seed()]);
 
                 self.renderer.resize_framebuffer(config.ui_width.into(), config.ui_height.into(), true);
                 self.renderer.set_view(
@@ -755,25 +831,19 @@ impl Game {
                     config.watched_ids.iter().map(|id| (*id, InstanceReport::new(&*self, *id))).collect();
             }
 
-            if (frame.button("Quick Save (Q)", imgui::Vec2(165.0, 20.0), None)
+            if (frame.button(tr("control_quick_save"), imgui::Vec2(165.0, 20.0), None)
                 || frame.key_pressed(input::ramen2vk(Key::Q)))
                 && game_running
                 && err_string.is_none()
             {
                 savestate = SaveState::from(self, replay.clone(), renderer_state.clone());
                 if let Err(err) = savestate.save_to_file(&save_paths[config.quicksave_slot], &mut save_buffer) {
-                    err_string = Some(format!(
-                        concat!(
-                            "Warning: failed to save quicksave.bin (it has still been saved in memory)\n\n",
-                            "Error message: {:?}",
-                        ),
-                        err,
-                    ));
+                    err_string = Some(trf("error_save_quicksave", &[&format!("{:?}", err)]));
                 }
                 context_menu = None;
             }
 
-            if frame.button("Load Quicksave (W)", imgui::Vec2(165.0, 20.0), None)
+            if frame.button(tr("control_load_quicksave"), imgui::Vec2(165.0, 20.0), None)
                 || frame.key_pressed(input::ramen2vk(Key::W))
             {
                 if startup_successful {
@@ -793,32 +863,36 @@ impl Game {
                             if self.input.mouse_check_button(i as i8 + 1) { KeyState::Held } else { KeyState::Neutral };
                     }
 
-                    frame_text = format!("Frame: {}", replay.frame_count());
-                    seed_text = format!("Seed: {}", self.rand.seed());
+                    frame_text = trf("control_frame", &[&replay.frame_count()]);
+                    seed_text = trf("control_seed", &[&self.rand.seed()]);
                     context_menu = None;
                     new_rand = None;
                     new_mouse_pos = None;
+                    pending_navigation.clear();
                     instance_reports =
                         config.watched_ids.iter().map(|id| (*id, InstanceReport::new(&*self, *id))).collect();
                     config.rerecords += 1;
-                    rerecord_text = format!("Re-record count: {}", config.rerecords);
-                    let _ = File::create(&config_path).map(|f| bincode::serialize_into(f, &config));
+                    replay.rerecords = config.rerecords;
+                    replay.game = Some(self.game_info());
+                    rerecord_text = trf("control_rerecords", &[&config.rerecords]);
+                    let _ = config.save(&config_path);
                 }
             }
 
-            if frame.button("Export to .gmtas", imgui::Vec2(165.0, 20.0), None) {
+            if frame.button(tr("control_export"), imgui::Vec2(165.0, 20.0), None) {
                 let mut filepath = project_path.clone();
                 filepath.push("save.gmtas");
                 match replay.to_file(&filepath) {
+                    Ok(()) if assisted => err_string = Some(tr("warning_assisted_export").into()),
                     Ok(()) => (),
                     Err(replay::WriteError::IOErr(err)) => {
-                        err_string = Some(format!("Failed to write save.gmtas: {}", err))
+                        err_string = Some(trf("error_write_file", &[&"save.gmtas", &err]))
                     },
                     Err(replay::WriteError::CompressErr(err)) => {
-                        err_string = Some(format!("Failed to compress save.gmtas: {}", err))
+                        err_string = Some(trf("error_compress_file", &[&"save.gmtas", &err]))
                     },
                     Err(replay::WriteError::SerializeErr(err)) => {
-                        err_string = Some(format!("Failed to serialize save.gmtas: {}", err))
+                        err_string = Some(trf("error_serialize_file", &[&"save.gmtas", &err]))
                     },
                 }
             }
@@ -831,36 +905,52 @@ impl Game {
             }
             frame.text(&rerecord_text);
             frame.text(&fps_text);
+            frame.text(&audio_text);
+            if assisted {
+                frame.coloured_text(tr("control_assisted"), Colour::new(1.0, 0.5, 0.5));
+            }
 
-            let keyboard_label = if config.full_keyboard {
-                "Simple Keyboard###KeyboardLayout"
-            } else {
-                "Full Keyboard###KeyboardLayout"
-            };
-            if frame.button(keyboard_label, imgui::Vec2(165.0, 20.0), None) {
+            let keyboard_label =
+                if config.full_keyboard { tr("control_simple_keyboard") } else { tr("control_full_keyboard") };
+            if frame.button(&format!("{}###KeyboardLayout", keyboard_label), imgui::Vec2(165.0, 20.0), None) {
                 config.full_keyboard = !config.full_keyboard;
-                let _ = File::create(&config_path).map(|f| bincode::serialize_into(f, &config));
+                let _ = config.save(&config_path);
             }
 
             let input_label = match config.input_mode {
-                InputMode::Direct => "Switch to mouse input###InputMethod",
-                InputMode::Mouse => "Switch to direct input###InputMethod",
+                InputMode::Direct => tr("control_mouse_input"),
+                InputMode::Mouse => tr("control_direct_input"),
             };
-            if frame.button(input_label, imgui::Vec2(165.0, 20.0), None) {
+            if frame.button(&format!("{}###InputMethod", input_label), imgui::Vec2(165.0, 20.0), None) {
                 config.input_mode = match config.input_mode {
                     InputMode::Mouse => InputMode::Direct,
                     InputMode::Direct => InputMode::Mouse,
                 }
             }
 
+            if frame.button(tr("control_dump_atlas"), imgui::Vec2(165.0, 20.0), None) {
+                let mut dir = project_path.clone();
+                dir.push("atlas");
+                match self.dump_atlas(&dir) {
+                    Ok(count) => println!("Wrote {} texture pages to {}", count, dir.display()),
+                    Err(err) => println!("Warning: couldn't dump texture pages: {}", err),
+                }
+            }
+            if frame.button(tr("control_atlas_browser"), imgui::Vec2(165.0, 20.0), None) {
+                atlas_browser.get_or_insert(AtlasBrowser { page: 0, zoom: 1.0, offset: imgui::Vec2(0.0, 0.0) });
+            }
+            if frame.button(tr("control_audio_device"), imgui::Vec2(165.0, 20.0), None) {
+                context_menu = Some(ContextMenu::AudioDevice { pos: frame.mouse_pos(), devices: self.audio.devices() });
+            }
+
             if frame.button(">", imgui::Vec2(18.0, 18.0), Some(imgui::Vec2(160.0, 138.0))) {
                 if let Some(rand) = &mut new_rand {
                     rand.cycle();
-                    seed_text = format!("Seed: {}*", rand.seed());
+                    seed_text = trf("control_seed_changed", &[&rand.seed()]);
                 } else {
                     let mut rand = self.rand.clone();
                     rand.cycle();
-                    seed_text = format!("Seed: {}*", rand.seed());
+                    seed_text = trf("control_seed_changed", &[&rand.seed()]);
                     new_rand = Some(rand);
                 }
             }
@@ -871,7 +961,7 @@ impl Game {
 
             // Savestates window
             frame.setup_next_window(imgui::Vec2(306.0, 8.0), Some(imgui::Vec2(225.0, 330.0)), None);
-            frame.begin_window("Savestates", None, true, false, None);
+            frame.begin_window(&format!("{}###Savestates", tr("window_savestates")), None, true, false, None);
             let rect_size = imgui::Vec2(frame.window_size().0, 24.0);
             let pos = frame.window_position() + frame.content_position() - imgui::Vec2(8.0, 8.0);
             for i in 0..8 {
@@ -906,13 +996,13 @@ impl Game {
                     {
                         Ok(()) => (),
                         Err(savestate::WriteError::IOErr(err)) => {
-                            err_string = Some(format!("Failed to write savestate #{}: {}", i, err))
+                            err_string = Some(trf("error_write_savestate", &[&i, &err]))
                         },
                         Err(savestate::WriteError::CompressErr(err)) => {
-                            err_string = Some(format!("Failed to compress savestate #{}: {}", i, err))
+                            err_string = Some(trf("error_compress_savestate", &[&i, &err]))
                         },
                         Err(savestate::WriteError::SerializeErr(err)) => {
-                            err_string = Some(format!("Failed to serialize savestate #{}: {}", i, err))
+                            err_string = Some(trf("error_serialize_savestate", &[&i, &err]))
                         },
                     }
                 }
@@ -945,28 +1035,29 @@ impl Game {
                                     };
                                 }
 
-                                frame_text = format!("Frame: {}", replay.frame_count());
-                                seed_text = format!("Seed: {}", self.rand.seed());
+                                frame_text = trf("control_frame", &[&replay.frame_count()]);
+                                seed_text = trf("control_seed", &[&self.rand.seed()]);
                                 context_menu = None;
                                 new_rand = None;
                                 new_mouse_pos = None;
+                                pending_navigation.clear();
                                 err_string = None;
                                 game_running = true;
                                 config.rerecords += 1;
-                                rerecord_text = format!("Re-record count: {}", config.rerecords);
-                                let _ = File::create(&config_path).map(|f| bincode::serialize_into(f, &config));
+                                replay.rerecords = config.rerecords;
+                                replay.game = Some(self.game_info());
+                                rerecord_text = trf("control_rerecords", &[&config.rerecords]);
+                                let _ = config.save(&config_path);
                             },
                             Err(err) => {
                                 let filename = save_paths[i].to_string_lossy();
                                 err_string = Some(match err {
-                                    savestate::ReadError::IOErr(err) => {
-                                        format!("Error reading {}:\n\n{}", filename, err)
-                                    },
+                                    savestate::ReadError::IOErr(err) => trf("error_read_savestate", &[&filename, &err]),
                                     savestate::ReadError::DecompressErr(err) => {
-                                        format!("Error decompressing {}:\n\n{}", filename, err)
+                                        trf("error_decompress_savestate", &[&filename, &err])
                                     },
                                     savestate::ReadError::DeserializeErr(err) => {
-                                        format!("Error deserializing {}:\n\n{}", filename, err)
+                                        trf("error_deserialize_savestate", &[&filename, &err])
                                     },
                                 });
                             },
@@ -983,7 +1074,7 @@ impl Game {
                             Ok(state) => {
                                 savestate = state;
                                 config.quicksave_slot = i;
-                                let _ = File::create(&config_path).map(|f| bincode::serialize_into(f, &config));
+                                let _ = config.save(&config_path);
                             },
                             Err(e) => {
                                 println!(
@@ -1112,7 +1203,7 @@ impl Game {
                     Some(imgui::Vec2(917.0, 362.0)),
                     Some(imgui::Vec2(440.0, 200.0)),
                 );
-                frame.begin_window("Keyboard###FullKeyboard", None, true, false, None);
+                frame.begin_window(&format!("{}###FullKeyboard", tr("window_keyboard")), None, true, false, None);
                 if !frame.window_collapsed() {
                     frame.rect(
                         imgui::Vec2(0.0, win_frame_height) + frame.window_position(),
@@ -1370,7 +1461,7 @@ impl Game {
                     Some(imgui::Vec2(365.0, 192.0)),
                     Some(imgui::Vec2(201.0, 122.0)),
                 );
-                frame.begin_window("Keyboard###SimpleKeyboard", None, true, false, None);
+                frame.begin_window(&format!("{}###SimpleKeyboard", tr("window_keyboard")), None, true, false, None);
                 if !frame.window_collapsed() {
                     frame.rect(
                         imgui::Vec2(0.0, win_frame_height) + frame.window_position(),
@@ -1424,7 +1515,13 @@ impl Game {
 
             // Mouse input window
             frame.setup_next_window(imgui::Vec2(2.0, 210.0), None, None);
-            frame.begin_window("Mouse", Some(imgui::Vec2(300.0, 138.0)), false, false, None);
+            frame.begin_window(
+                &format!("{}###Mouse", tr("window_mouse")),
+                Some(imgui::Vec2(300.0, 138.0)),
+                false,
+                false,
+                None,
+            );
             if !frame.window_collapsed() {
                 frame.rect(
                     imgui::Vec2(0.0, win_frame_height) + frame.window_position(),
@@ -1434,14 +1531,14 @@ impl Game {
                 );
 
                 let button_size = imgui::Vec2(40.0, 40.0);
-                kb_btn!("Left", button_size, 4.0, 65.0, mouse 0);
-                kb_btn!("Middle", button_size, 48.0, 65.0, mouse 2);
-                kb_btn!("Right", button_size, 92.0, 65.0, mouse 1);
-                if frame.button("Set Mouse", imgui::Vec2(150.0, 20.0), Some(imgui::Vec2(150.0, 50.0))) {
+                kb_btn!(tr("mouse_left"), button_size, 4.0, 65.0, mouse 0);
+                kb_btn!(tr("mouse_middle"), button_size, 48.0, 65.0, mouse 2);
+                kb_btn!(tr("mouse_right"), button_size, 92.0, 65.0, mouse 1);
+                if frame.button(tr("mouse_set"), imgui::Vec2(150.0, 20.0), Some(imgui::Vec2(150.0, 50.0))) {
                     if game_running {
                         setting_mouse_pos = true;
                     } else {
-                        err_string = Some("The game is not running. Please load a savestate.".into());
+                        err_string = Some(tr("error_not_running").into());
                     }
                 }
 
@@ -1470,32 +1567,38 @@ impl Game {
             instance_images.reserve(config.watched_ids.len());
             config.watched_ids.retain(|id| {
                 let mut open = true;
-                frame.begin_window(&format!("Instance {}", id), None, true, false, Some(&mut open));
+                frame.begin_window(
+                    &format!("{}###Instance{}", trf("window_instance", &[id]), id),
+                    None,
+                    true,
+                    false,
+                    Some(&mut open),
+                );
                 if let Some((_, Some(report))) = instance_reports.iter().find(|(i, _)| i == id) {
                     frame.text(&report.object_name);
                     frame.text(&report.id);
                     frame.text("");
-                    if frame.begin_tree_node("General Variables") {
+                    if frame.begin_tree_node(tr("instance_general")) {
                         report.general_vars.iter().for_each(|s| frame.text(s));
                         frame.pop_tree_node();
                     }
-                    if frame.begin_tree_node("Physics Variables") {
+                    if frame.begin_tree_node(tr("instance_physics")) {
                         report.physics_vars.iter().for_each(|s| frame.text(s));
                         frame.pop_tree_node();
                     }
-                    if frame.begin_tree_node("Image Variables") {
+                    if frame.begin_tree_node(tr("instance_image")) {
                         report.image_vars.iter().for_each(|s| frame.text(s));
                         frame.pop_tree_node();
                     }
-                    if frame.begin_tree_node("Timeline Variables") {
+                    if frame.begin_tree_node(tr("instance_timeline")) {
                         report.timeline_vars.iter().for_each(|s| frame.text(s));
                         frame.pop_tree_node();
                     }
-                    if frame.begin_tree_node("Alarms") {
+                    if frame.begin_tree_node(tr("instance_alarms")) {
                         report.alarms.iter().for_each(|s| frame.text(s));
                         frame.pop_tree_node();
                     }
-                    if frame.begin_tree_node("Fields") {
+                    if frame.begin_tree_node(tr("instance_fields")) {
                         report.fields.iter().for_each(|f| match f {
                             ReportField::Single(s) => frame.text(s),
                             ReportField::Array(label, array) => {
@@ -1542,7 +1645,7 @@ impl Game {
                         }
                     }
                 } else {
-                    frame.text_centered("<deleted instance>", imgui::Vec2(160.0, 35.0));
+                    frame.text_centered(tr("instance_deleted"), imgui::Vec2(160.0, 35.0));
                 }
                 frame.end();
                 open
@@ -1551,8 +1654,195 @@ impl Game {
             if config.watched_ids.len() != previous_len {
                 instance_reports =
                     config.watched_ids.iter().map(|id| (*id, InstanceReport::new(&*self, *id))).collect();
-                let _ = File::create(&config_path).map(|f| bincode::serialize_into(f, &config));
+                let _ = config.save(&config_path);
+            }
+
+            // Atlas browser
+            atlas_images.clear();
+            if let Some(browser) = &mut atlas_browser {
+                let mut open = true;
+                frame.setup_next_window(imgui::Vec2(236.0, 8.0), Some(imgui::Vec2(512.0, 512.0)), None);
+                frame.begin_window(
+                    &format!("{}###AtlasBrowser", tr("window_atlas")),
+                    None,
+                    true,
+                    false,
+                    Some(&mut open),
+                );
+                let entries = self.atlas_entries();
+                let page_count = entries.iter().map(|entry| entry.page + 1).max().unwrap_or(0);
+                if frame.button("<", imgui::Vec2(18.0, 18.0), None) && browser.page > 0 {
+                    browser.page -= 1;
+                    browser.offset = imgui::Vec2(0.0, 0.0);
+                }
+                frame.same_line();
+                if frame.button(">", imgui::Vec2(18.0, 18.0), None) && browser.page + 1 < page_count {
+                    browser.page += 1;
+                    browser.offset = imgui::Vec2(0.0, 0.0);
+                }
+                frame.same_line();
+                frame.text(&trf("atlas_page", &[&(browser.page + 1), &page_count, &(browser.zoom * 100.0).round()]));
+
+                // The rest of the window is the page itself, which is dragged around and zoomed with the mouse
+                let origin = frame.cursor_position();
+                let imgui::Vec2(view_w, view_h) = frame.available_size();
+                let view_max = origin + imgui::Vec2(view_w.max(1.0), view_h.max(1.0));
+                frame.invisible_button("##AtlasView", view_max - origin, None);
+                let view_hovered = frame.item_hovered();
+                if frame.item_active() {
+                    browser.offset = browser.offset + frame.take_mouse_drag();
+                }
+                if view_hovered && frame.mouse_wheel() != 0.0 {
+                    // Zoom in on whatever's under the mouse
+                    let old_zoom = browser.zoom;
+                    let new_zoom = if frame.mouse_wheel() > 0.0 { old_zoom * 2.0 } else { old_zoom / 2.0 };
+                    browser.zoom = new_zoom.clamp(0.125, 16.0);
+                    let imgui::Vec2(mouse_x, mouse_y) = frame.mouse_pos() - origin;
+                    let scale = browser.zoom / old_zoom;
+                    browser.offset = imgui::Vec2(
+                        mouse_x - (mouse_x - browser.offset.0) * scale,
+                        mouse_y - (mouse_y - browser.offset.1) * scale,
+                    );
+                }
+
+                let zoom = browser.zoom;
+                let page_origin = origin + browser.offset;
+                let page_entries = entries.iter().filter(|entry| entry.page == browser.page).collect::<Vec<_>>();
+                let page_w = page_entries.iter().map(|entry| entry.bounds.0 + entry.bounds.2).max().unwrap_or(0);
+                let page_h = page_entries.iter().map(|entry| entry.bounds.1 + entry.bounds.3).max().unwrap_or(0);
+                let imgui::Vec2(mouse_x, mouse_y) = frame.mouse_pos();
+                let mut hovered_owner = None;
+                atlas_images.reserve(page_entries.len());
+                frame.push_clip_rect(origin, view_max);
+                frame.rect(
+                    page_origin,
+                    page_origin + imgui::Vec2(page_w as f32 * zoom, page_h as f32 * zoom),
+                    Colour::new(0.0, 0.0, 0.0),
+                    160,
+                );
+                for entry in page_entries {
+                    let (x, y, w, h) = entry.bounds;
+                    let min = page_origin + imgui::Vec2(x as f32 * zoom, y as f32 * zoom);
+                    let max = min + imgui::Vec2(w as f32 * zoom, h as f32 * zoom);
+                    let i = atlas_images.len();
+                    atlas_images.push(entry.atlas_ref);
+                    unsafe {
+                        cimgui_sys::ImDrawList_AddImage(
+                            cimgui_sys::igGetWindowDrawList(),
+                            atlas_images.as_mut_ptr().add(i) as _,
+                            min.into(),
+                            max.into(),
+                            cimgui_sys::ImVec2 { x: 0.0, y: 0.0 },
+                            cimgui_sys::ImVec2 { x: 1.0, y: 1.0 },
+                            0xFFFFFFFF,
+                        );
+                    }
+                    if view_hovered && mouse_x >= min.0 && mouse_x < max.0 && mouse_y >= min.1 && mouse_y < max.1 {
+                        frame.rect_outline(min, max, Colour::new(1.0, 1.0, 0.0), 255);
+                        hovered_owner = Some(&entry.owner);
+                    } else {
+                        frame.rect_outline(min, max, Colour::new(0.0, 1.0, 1.0), 96);
+                    }
+                }
+                frame.pop_clip_rect();
+                if let Some(owner) = hovered_owner {
+                    frame.tooltip(owner);
+                }
+                frame.end();
+                if !open {
+                    atlas_browser = None;
+                }
+            }
+
+            // Room navigator
+            frame.setup_next_window(imgui::Vec2(8.0, 400.0), Some(imgui::Vec2(220.0, 200.0)), None);
+            frame.begin_window(&format!("{}###Rooms", tr("window_rooms")), None, true, false, None);
+            for (&id, name) in self.room_order.iter().zip(room_names.iter()) {
+                let pending = pending_navigation.iter().any(|nav| matches!(nav, Navigation::Room(i) if *i == id));
+                if frame.selectable(name, id == self.room.id || pending) && game_running {
+                    pending_navigation.retain(|nav| !matches!(nav, Navigation::Room(_)));
+                    pending_navigation.push(Navigation::Room(id));
+                }
             }
+            frame.end();
+
+            // Instance list
+            frame.setup_next_window(imgui::Vec2(236.0, 400.0), Some(imgui::Vec2(300.0, 300.0)), None);
+            frame.begin_window(&format!("{}###Instances", tr("window_instances")), None, true, false, None);
+            frame.input_text(tr("instances_filter"), &mut instance_filter, false);
+            let filter = buffer_str(&instance_filter);
+            let mut iter = self.room.instance_list.iter_by_drawing();
+            while let Some(handle) = iter.next(&self.room.instance_list) {
+                use crate::game::GetAsset;
+                let instance = self.room.instance_list.get(handle);
+                let id = instance.id.get();
+                let object_name = match self.assets.objects.get_asset(instance.object_index.get()) {
+                    Some(obj) => obj.name.decode(self.encoding),
+                    None => tr("deleted_object").into(),
+                };
+                if !id.to_string().contains(filter) && !object_name.contains(filter) {
+                    continue
+                }
+                let label = format!(
+                    "{} {} ({:.2}, {:.2})###Instance{}",
+                    id,
+                    object_name,
+                    instance.x.get(),
+                    instance.y.get(),
+                    id,
+                );
+                if frame.selectable(&label, selected_instance == Some(id)) {
+                    selected_instance = Some(id);
+                    if !config.watched_ids.contains(&id) {
+                        config.watched_ids.push(id);
+                        instance_reports.push((id, InstanceReport::new(&*self, id)));
+                        let _ = config.save(&config_path);
+                    }
+                }
+            }
+            if let Some(handle) = selected_instance.and_then(|id| self.room.instance_list.get_by_instid(id)) {
+                let id = self.room.instance_list.get(handle).id.get();
+                frame.text("");
+                frame.text(&trf("instances_selected", &[&id]));
+                frame.input_text(tr("instances_variable"), &mut variable_name, false);
+                frame.input_text(tr("instances_value"), &mut variable_value, false);
+                if frame.button(tr("instances_set"), imgui::Vec2(80.0, 20.0), None) && game_running {
+                    match parse_variable(buffer_str(&variable_name)).zip(parse_value(buffer_str(&variable_value))) {
+                        Some(((name, index), value)) => match set_variable(self, handle, name, index, value) {
+                            Ok(()) => {
+                                assisted = true;
+                                instance_reports = config
+                                    .watched_ids
+                                    .iter()
+                                    .map(|id| (*id, InstanceReport::new(&*self, *id)))
+                                    .collect();
+                            },
+                            Err(e) => err_string = Some(trf("error_set_variable", &[&e])),
+                        },
+                        None => err_string = Some(tr("error_variable_syntax").into()),
+                    }
+                }
+                if frame.button(tr("instances_destroy"), imgui::Vec2(80.0, 20.0), None) && game_running {
+                    pending_navigation.push(Navigation::Destroy { id, run_event: run_destroy_event });
+                }
+                frame.same_line();
+                frame.checkbox(tr("instances_run_destroy_event"), &mut run_destroy_event);
+                if !pending_navigation.is_empty() {
+                    frame.text(tr("instances_pending"));
+                }
+            }
+            frame.end();
+
+            // Game log (show_debug_message output)
+            frame.setup_next_window(imgui::Vec2(544.0, 400.0), Some(imgui::Vec2(360.0, 200.0)), None);
+            frame.begin_window(&format!("{}###GameLog", tr("window_game_log")), None, true, false, None);
+            if frame.button(tr("game_log_clear"), imgui::Vec2(80.0, 20.0), None) {
+                self.game_log.clear();
+            }
+            for message in self.game_log.messages() {
+                frame.text(&message.to_line());
+            }
+            frame.end();
 
             // Context menu windows (aka right-click menus)
             match &context_menu {
@@ -1578,7 +1868,7 @@ impl Game {
                                 if !config.watched_ids.contains(id) {
                                     config.watched_ids.push(*id);
                                     instance_reports.push((*id, InstanceReport::new(&*self, *id)));
-                                    let _ = File::create(&config_path).map(|f| bincode::serialize_into(f, &config));
+                                    let _ = config.save(&config_path);
                                 }
                                 context_menu = None;
                                 break
@@ -1593,21 +1883,21 @@ impl Game {
                         context_menu = None;
                     } else {
                         let count;
-                        if new_rand.is_some() && frame.menu_item("Reset") {
+                        if new_rand.is_some() && frame.menu_item(tr("seed_reset")) {
                             count = None;
                             context_menu = None;
                             new_rand = None;
-                            seed_text = format!("Seed: {}", self.rand.seed());
-                        } else if frame.menu_item("+1 RNG call") {
+                            seed_text = trf("control_seed", &[&self.rand.seed()]);
+                        } else if frame.menu_item(tr("seed_one_call")) {
                             count = Some(1);
                             context_menu = None;
-                        } else if frame.menu_item("+5 RNG calls") {
+                        } else if frame.menu_item(&trf("seed_calls", &[&5])) {
                             count = Some(5);
                             context_menu = None;
-                        } else if frame.menu_item("+10 RNG calls") {
+                        } else if frame.menu_item(&trf("seed_calls", &[&10])) {
                             count = Some(10);
                             context_menu = None;
-                        } else if frame.menu_item("+50 RNG calls") {
+                        } else if frame.menu_item(&trf("seed_calls", &[&50])) {
                             count = Some(50);
                             context_menu = None;
                         } else {
@@ -1618,22 +1908,48 @@ impl Game {
                                 for _ in 0..count {
                                     rand.cycle();
                                 }
-                                seed_text = format!("Seed: {}*", rand.seed());
+                                seed_text = trf("control_seed_changed", &[&rand.seed()]);
                             } else {
                                 let mut rand = self.rand.clone();
                                 for _ in 0..count {
                                     rand.cycle();
                                 }
-                                seed_text = format!("Seed: {}*", rand.seed());
+                                seed_text = trf("control_seed_changed", &[&rand.seed()]);
                                 new_rand = Some(rand);
                             }
                         }
                     }
                     frame.end();
                 },
+                Some(ContextMenu::AudioDevice { pos, devices }) => {
+                    frame.begin_context_menu(*pos);
+                    if !frame.window_focused() {
+                        context_menu = None;
+                    } else {
+                        let current = self.audio.device().map(String::from);
+                        for device in iter::once(None).chain(devices.iter().cloned().map(Some)) {
+                            let name = device.as_deref().unwrap_or(tr("audio_device_default"));
+                            let label =
+                                if device == current { trf("audio_device_current", &[&name]) } else { name.into() };
+                            if frame.menu_item(&label) {
+                                let current_time = self.spoofed_time_nanos.unwrap_or_else(gml::datetime::now_as_nanos);
+                                self.audio.set_device(device, current_time);
+                                context_menu = None;
+                                break
+                            }
+                        }
+                    }
+                    frame.end();
+                },
                 None => (),
             }
 
+            // Autosave the movie if it's due
+            autosaver.tick(&replay, config.rerecords);
+            if err_string.is_none() {
+                err_string = autosaver.take_error();
+            }
+
             // Show error/info message if there is one
             if let Some(err) = &err_string {
                 if !frame.popup(err) {
@@ -1715,7 +2031,10 @@ impl Game {
             context.io().set_delta_time(time_start.elapsed().as_micros() as f32 / 1000000.0);
         }
 
-        let _ = File::create(&config_path).map(|f| bincode::serialize_into(f, &config));
+        if startup_successful {
+            autosaver.save(&replay, config.rerecords);
+        }
+        let _ = config.save(&config_path);
     }
 }
 
@@ -1730,6 +2049,13 @@ struct InstanceReport {
     fields: Vec<ReportField>,
 }
 
+/// Which texture page the atlas browser is showing, and how it's been moved and zoomed.
+struct AtlasBrowser {
+    page: u32,
+    zoom: f32,
+    offset: imgui::Vec2<f32>,
+}
+
 enum ReportField {
     Single(String),
     Array(String, Vec<String>),
@@ -1747,7 +2073,7 @@ impl InstanceReport {
                 .objects
                 .get_asset(instance.object_index.get())
                 .map(|x| x.name.decode(game.encoding))
-                .unwrap_or("<deleted object>".into());
+                .unwrap_or(tr("deleted_object").into());
 
             Some(Self {
                 object_name: object_name.clone().into(),
@@ -1784,7 +2110,7 @@ impl InstanceReport {
                             .sprites
                             .get_asset(instance.sprite_index.get())
                             .map(|x| x.name.decode(game.encoding))
-                            .unwrap_or("<deleted sprite>".into()),
+                            .unwrap_or(tr("deleted_sprite").into()),
                     ),
                     format!(
                         "mask_index: {} ({})",
@@ -1793,7 +2119,7 @@ impl InstanceReport {
                             .sprites
                             .get_asset(instance.mask_index.get())
                             .map(|x| x.name.decode(game.encoding))
-                            .unwrap_or("<same as sprite>".into()),
+                            .unwrap_or(tr("same_as_sprite").into()),
                     ),
                     format!("image_index: {:.4}", instance.image_index.get()),
                     format!("image_speed: {:.4}", instance.image_speed.get()),
@@ -1813,7 +2139,7 @@ impl InstanceReport {
                             .timelines
                             .get_asset(instance.timeline_index.get())
                             .map(|x| x.name.decode(game.encoding))
-                            .unwrap_or("<deleted timeline>".into()),
+                            .unwrap_or(tr("deleted_timeline").into()),
                     ),
                     format!("timeline_running: {}", instance.timeline_running.get()),
                     format!("timeline_speed: {:.4}", instance.timeline_speed.get()),
@@ -1843,6 +2169,81 @@ impl InstanceReport {
     }
 }
 
+/// Something done from the navigator which runs game code, so it's held back until the next frame is advanced.
+enum Navigation {
+    Room(i32),
+    Destroy { id: i32, run_event: bool },
+}
+
+impl Navigation {
+    fn apply(&self, game: &mut Game) -> Result<(), RuntimeError> {
+        match *self {
+            Self::Room(id) => game.load_room(id),
+            Self::Destroy { id, run_event } => {
+                // Same as instance_destroy(), except the destroy event can be skipped
+                if let Some(handle) = game.room.instance_list.get_by_instid(id) {
+                    game.destroy_instance(handle, run_event)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Sets one of an instance's variables from the navigator. Builtin variables are set the same way GML sets them,
+/// so for example setting speed updates hspeed and vspeed.
+fn set_variable(game: &mut Game, handle: usize, name: &str, index: u32, value: Value) -> gml::Result<()> {
+    match game.compiler.builtin_variable(name.as_bytes()) {
+        Some(var) => game.set_instance_var(handle, &var, index, value, &mut gml::Context::with_single_instance(handle)),
+        None => {
+            let field_id = game.compiler.get_field_id(name.as_bytes());
+            let mut fields = game.room.instance_list.get(handle).fields.borrow_mut();
+            if let Some(field) = fields.get_mut(&field_id) {
+                field.set(index, value)
+            } else {
+                fields.insert(field_id, Field::new(index, value));
+            }
+            Ok(())
+        },
+    }
+}
+
+/// Splits a variable typed into the navigator into its name and array index, like `hp` or `path[3]`.
+fn parse_variable(text: &str) -> Option<(&str, u32)> {
+    let text = text.trim();
+    let (name, index) = match text.strip_suffix(']').and_then(|t| t.split_once('[')) {
+        Some((name, index)) => (name.trim_end(), index.trim().parse().ok()?),
+        None => (text, 0),
+    };
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => (),
+        _ => return None,
+    }
+    if chars.all(|c| c == '_' || c.is_ascii_alphanumeric()) { Some((name, index)) } else { None }
+}
+
+/// Reads a value typed into the navigator, which is either a number or a string in single or double quotes.
+fn parse_value(text: &str) -> Option<Value> {
+    let text = text.trim();
+    for quote in &['"', '\''] {
+        if let Some(s) = text.strip_prefix(*quote).and_then(|t| t.strip_suffix(*quote)) {
+            return Some(s.into())
+        }
+    }
+    text.parse::<f64>().ok().filter(|x| x.is_finite()).map(Value::from)
+}
+
+/// The text in a nul-terminated buffer edited by imgui.
+/// If a multi-byte character got cut off at the end of the buffer, the rest of the text is still returned.
+fn buffer_str(buf: &[u8]) -> &str {
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8(&buf[..e.valid_up_to()]).unwrap_or_default(),
+    }
+}
+
 // Draws the coloured rectangle according to the current state of the button.
 // Doesn't render any text on it.
 fn draw_keystate(frame: &mut imgui::Frame, state: &KeyState, position: imgui::Vec2<f32>, size: imgui::Vec2<f32>) {
@@ -1886,3 +2287,31 @@ fn draw_keystate(frame: &mut imgui::Frame, state: &KeyState, position: imgui::Ve
     }
     frame.rect_outline(position + wpos, position + size + wpos, Colour::new(0.4, 0.4, 0.65), u8::MAX);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Real;
+
+    #[test]
+    fn navigator_input() {
+        assert_eq!(parse_variable("hp"), Some(("hp", 0)));
+        assert_eq!(parse_variable(" path[3] "), Some(("path", 3)));
+        assert_eq!(parse_variable("_a1 [ 12 ]"), Some(("_a1", 12)));
+        assert_eq!(parse_variable("1up"), None);
+        assert_eq!(parse_variable("a[-1]"), None);
+        assert_eq!(parse_variable("a.b"), None);
+        assert_eq!(parse_variable(""), None);
+
+        assert!(matches!(parse_value(" -2.5 "), Some(Value::Real(x)) if x == Real::from(-2.5)));
+        assert!(matches!(parse_value("\"hi\""), Some(Value::Str(s)) if s.as_ref() == b"hi"));
+        assert!(matches!(parse_value("'say \"hi\"'"), Some(Value::Str(s)) if s.as_ref() == b"say \"hi\""));
+        assert!(parse_value("hi").is_none());
+        assert!(parse_value("inf").is_none());
+
+        assert_eq!(buffer_str(b"obj_player\0\0\0"), "obj_player");
+        assert_eq!(buffer_str(&[0; 4]), "");
+        assert_eq!(buffer_str("ｐｌａｙｅｒ".as_bytes()), "ｐｌａｙｅｒ");
+        assert_eq!(buffer_str(&"名前".as_bytes()[..5]), "名");
+    }
+}
@@ -1,13 +1,17 @@
-use crate::gml::Value;
+use crate::{game::autosave, gml::Value};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use lzzzz::lz4;
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::{File, OpenOptions},
+    fs::File,
     io::{self, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+mod text;
+
+pub use text::TextError;
+
 // Represents an entire replay (TAS) file
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Replay {
@@ -23,8 +27,53 @@ pub struct Replay {
 
     // List of frames in this replay.
     frames: Vec<Frame>,
+
+    // The game this replay was recorded against. None in replays from before this was noted down.
+    pub game: Option<GameInfo>,
+
+    // How many times a savestate was loaded while recording this replay.
+    pub rerecords: u64,
+}
+
+// Identifies the game a replay was recorded against, so playing it against a different one can be warned about
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameInfo {
+    pub game_id: i32,
+    // Hash of the exe's gamedata, which doesn't change if only its icon or version info is changed
+    pub gamedata_hash: u64,
+}
+
+// A summary of a replay, as shown by --replay-info
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub frame_count: usize,
+    pub rerecords: u64,
+    pub game: Option<GameInfo>,
+    // The first and last frames with a key or mouse button pressed or released, the mouse wheel scrolled,
+    // or the close button clicked, or None if there aren't any
+    pub first_input: Option<usize>,
+    pub last_input: Option<usize>,
+}
+
+// Version 1 of the gmtas format, which was the same as a Replay without `game` and `rerecords`
+#[derive(Deserialize)]
+pub struct ReplayV1 {
+    start_time: u128,
+    start_seed: i32,
+    startup_events: Vec<Event>,
+    frames: Vec<Frame>,
+}
+
+impl From<ReplayV1> for Replay {
+    fn from(old: ReplayV1) -> Self {
+        let ReplayV1 { start_time, start_seed, startup_events, frames } = old;
+        Self { start_time, start_seed, startup_events, frames, game: None, rerecords: 0 }
+    }
 }
 
+// The version written at the start of gmtas files. Older versions are still read.
+const GMTAS_VERSION: u32 = 2;
+
 // Associated data for a single frame of playback
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Frame {
@@ -56,6 +105,7 @@ pub enum Input {
     MouseRelease(i8),
     MouseWheelUp,
     MouseWheelDown,
+    CloseButton, // the window's close button was clicked - new variants must go last so old files still load
 }
 
 #[derive(Debug)]
@@ -75,7 +125,7 @@ pub enum WriteError {
 
 impl Replay {
     pub fn new(start_time: u128, start_seed: i32) -> Self {
-        Self { start_time, start_seed, startup_events: Vec::new(), frames: Vec::new() }
+        Self { start_time, start_seed, startup_events: Vec::new(), frames: Vec::new(), game: None, rerecords: 0 }
     }
 
     // Loads a Replay from a gmtas-format file (doesn't check the file extension)
@@ -85,7 +135,7 @@ impl Replay {
         let mut file = File::open(path).map_err(ReadError::IOErr)?;
 
         match file.read_u32::<LE>() {
-            Ok(1) => {
+            Ok(version @ (1 | GMTAS_VERSION)) => {
                 let init_size = file.metadata().map(|m| m.len() as usize + 1).unwrap_or(0);
                 lz4_buf.reserve(init_size);
                 match file.read_to_end(&mut lz4_buf) {
@@ -96,8 +146,12 @@ impl Replay {
                             match lz4::decompress(block, bin_buf.as_mut_slice()) {
                                 Ok(len) => {
                                     unsafe { bin_buf.set_len(len) };
-                                    bincode::deserialize::<'_, Self>(bin_buf.as_slice())
-                                        .map_err(ReadError::DeserializeErr)
+                                    let replay = if version == 1 {
+                                        bincode::deserialize::<'_, ReplayV1>(bin_buf.as_slice()).map(Self::from)
+                                    } else {
+                                        bincode::deserialize::<'_, Self>(bin_buf.as_slice())
+                                    };
+                                    replay.map_err(ReadError::DeserializeErr)
                                 },
                                 Err(err) => Err(ReadError::DecompressErr(err)),
                             }
@@ -115,23 +169,30 @@ impl Replay {
 
     // Serializes this replay into a file
     pub fn to_file(&self, path: &PathBuf) -> Result<(), WriteError> {
+        Self::write_serialized(&self.serialize()?, path)
+    }
+
+    // Serializes this replay without compressing it, so it can be written out later with `write_serialized`
+    pub fn serialize(&self) -> Result<Vec<u8>, WriteError> {
+        bincode::serialize(self).map_err(WriteError::SerializeErr)
+    }
+
+    // Compresses the output of `serialize` into a gmtas-format file. Doesn't need the Replay, so it can be
+    // called from another thread. The file is replaced atomically, so it's never left half-written.
+    pub fn write_serialized(bin_buf: &[u8], path: &Path) -> Result<(), WriteError> {
         let mut lz4_buf = Vec::new();
-        let mut bin_buf = Vec::new();
-        match bincode::serialize_into(&mut bin_buf, self) {
-            Ok(()) => match lz4::compress_to_vec(bin_buf.as_slice(), lz4_buf.as_mut(), lz4::ACC_LEVEL_DEFAULT) {
-                Ok(_length) => {
-                    match OpenOptions::new().create(true).write(true).truncate(true).open(path).and_then(|mut f| {
-                        f.write_u32::<LE>(1).and_then(|_| {
-                            f.write_u64::<LE>(bin_buf.len() as u64).and_then(|_| f.write_all(lz4_buf.as_slice()))
-                        })
-                    }) {
-                        Ok(()) => Ok(()),
-                        Err(e) => Err(WriteError::IOErr(e)),
-                    }
-                },
-                Err(err) => Err(WriteError::CompressErr(err)),
+        match lz4::compress_to_vec(bin_buf, lz4_buf.as_mut(), lz4::ACC_LEVEL_DEFAULT) {
+            Ok(_length) => {
+                match autosave::write_atomic(path, |f| {
+                    f.write_u32::<LE>(GMTAS_VERSION).and_then(|_| {
+                        f.write_u64::<LE>(bin_buf.len() as u64).and_then(|_| f.write_all(lz4_buf.as_slice()))
+                    })
+                }) {
+                    Ok(()) => Ok(()),
+                    Err(e) => Err(WriteError::IOErr(e)),
+                }
             },
-            Err(err) => Err(WriteError::SerializeErr(err)),
+            Err(err) => Err(WriteError::CompressErr(err)),
         }
     }
 
@@ -163,4 +224,144 @@ impl Replay {
     pub fn frame_count(&self) -> usize {
         self.frames.len()
     }
+
+    // Summarises the replay without playing it
+    pub fn stats(&self) -> Stats {
+        let mut with_input = (0..self.frames.len()).filter(|&i| !self.frames[i].inputs.is_empty());
+        let first_input = with_input.next();
+        Stats {
+            frame_count: self.frames.len(),
+            rerecords: self.rerecords,
+            game: self.game,
+            first_input,
+            last_input: with_input.next_back().or(first_input),
+        }
+    }
+}
+
+// For embedding a Replay in something else, like a savestate, with `#[serde(with = "replay::versioned")]`.
+// The gmtas version is written before it, so that the embedding format doesn't silently change with the replay's.
+pub mod versioned {
+    use super::{Replay, ReplayV1, GMTAS_VERSION};
+    use serde::{de, ser::SerializeTuple, Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(replay: &Replay, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&GMTAS_VERSION)?;
+        tuple.serialize_element(replay)?;
+        tuple.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Replay, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Replay;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a gmtas version followed by a replay")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Replay, A::Error> {
+                let version: u32 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let replay = match version {
+                    1 => seq.next_element::<ReplayV1>()?.map(Replay::from),
+                    GMTAS_VERSION => seq.next_element::<Replay>()?,
+                    _ => return Err(de::Error::custom(format!("unknown replay version {}", version))),
+                };
+                replay.ok_or_else(|| de::Error::invalid_length(1, &self))
+            }
+        }
+
+        deserializer.deserialize_tuple(2, Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_encoding() {
+        // gmtas files store inputs by variant index, so existing variants must keep their numbers
+        let inputs = vec![Input::KeyPress(27), Input::MouseWheelDown, Input::CloseButton];
+        let encoded = bincode::serialize(&inputs).unwrap();
+        assert_eq!(encoded[8..], [0, 0, 0, 0, 27, 5, 0, 0, 0, 6, 0, 0, 0]);
+        let decoded: Vec<Input> = bincode::deserialize(&encoded).unwrap();
+        assert!(matches!(decoded.as_slice(), [Input::KeyPress(27), Input::MouseWheelDown, Input::CloseButton]));
+    }
+
+    #[test]
+    fn stats() {
+        let mut replay = Replay::new(0, 0);
+        for i in 0..5 {
+            let frame = replay.new_frame();
+            if i == 1 || i == 3 {
+                frame.inputs.push(Input::KeyPress(32));
+            }
+        }
+        // events aren't input
+        replay.new_frame().events.push(Event::ShowMessage);
+        replay.rerecords = 7;
+        replay.game = Some(GameInfo { game_id: 5, gamedata_hash: 0xABC });
+        assert_eq!(replay.stats(), Stats {
+            frame_count: 6,
+            rerecords: 7,
+            game: Some(GameInfo { game_id: 5, gamedata_hash: 0xABC }),
+            first_input: Some(1),
+            last_input: Some(3),
+        });
+        replay.frames[3].inputs.clear();
+        assert_eq!((replay.stats().first_input, replay.stats().last_input), (Some(1), Some(1)));
+        assert_eq!((Replay::new(0, 0).stats().first_input, Replay::new(0, 0).stats().last_input), (None, None));
+    }
+
+    #[test]
+    fn old_versions() {
+        let path = std::env::temp_dir().join(format!("opengmk_replay_{}.gmtas", std::process::id()));
+        let mut replay = Replay::new(123, 4);
+        replay.new_frame().inputs.push(Input::MouseWheelUp);
+
+        // a version 1 file is a replay without the fields which have been added since
+        let v1 = bincode::serialize(&(replay.start_time, replay.start_seed, &replay.startup_events, &replay.frames));
+        Replay::write_serialized(&v1.unwrap(), &path).unwrap();
+        let mut file = std::fs::read(&path).unwrap();
+        file[..4].copy_from_slice(&1u32.to_le_bytes());
+        std::fs::write(&path, file).unwrap();
+        let old = Replay::from_file(&path).unwrap();
+        assert_eq!((old.start_time, old.start_seed), (123, 4));
+        assert!(matches!(old.get_frame(0).unwrap().inputs.as_slice(), [Input::MouseWheelUp]));
+        assert_eq!(old.stats(), Stats { game: None, rerecords: 0, ..replay.stats() });
+
+        replay.game = Some(GameInfo { game_id: -1, gamedata_hash: u64::MAX });
+        replay.rerecords = 3;
+        replay.to_file(&path).unwrap();
+        let new = Replay::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(new.stats(), replay.stats());
+    }
+
+    #[test]
+    fn embedded() {
+        #[derive(Serialize, Deserialize)]
+        struct Embedded(#[serde(with = "versioned")] Replay, u8);
+
+        let mut replay = Replay::new(123, 4);
+        replay.new_frame().inputs.push(Input::MouseWheelUp);
+        replay.game = Some(GameInfo { game_id: 7, gamedata_hash: 0xDEF });
+        replay.rerecords = 9;
+        let bin = bincode::serialize(&Embedded(replay.clone(), 42)).unwrap();
+        assert_eq!(bin[..4], GMTAS_VERSION.to_le_bytes());
+        let Embedded(loaded, after) = bincode::deserialize(&bin).unwrap();
+        assert_eq!((loaded.stats(), after), (replay.stats(), 42));
+
+        // one embedded before the game and rerecords were added
+        let v1 = (1u32, (replay.start_time, replay.start_seed, &replay.startup_events, &replay.frames), 42u8);
+        let Embedded(old, after) = bincode::deserialize(&bincode::serialize(&v1).unwrap()).unwrap();
+        assert_eq!((old.stats(), after), (Stats { game: None, rerecords: 0, ..replay.stats() }, 42));
+
+        let unknown = (GMTAS_VERSION + 1, 0u64, 42u8);
+        assert!(bincode::deserialize::<Embedded>(&bincode::serialize(&unknown).unwrap()).is_err());
+    }
 }
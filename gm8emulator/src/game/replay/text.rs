@@ -0,0 +1,425 @@
+//! A plain text version of the replay format, for reviewing changes to a TAS in a diff and editing inputs by hand.
+//!
+//! The first few lines give the start time and seed, the rerecord count, the game it was recorded against (its
+//! game ID and gamedata hash, if known) and any startup events, then each frame gets one line:
+//!
+//! ```text
+//! opengmk replay 1
+//! start_time 1600000000000000000
+//! start_seed 12345
+//! rerecords 3
+//! game 428 9b1c0e4f2d6a7788
+//! startup show_message
+//! # frame 0
+//! 320 240 +vk_right +Z
+//! 320 240 -Z get_string="my name" seed=42
+//! ```
+//!
+//! A frame line is the mouse position followed by what happened that frame, in order. Keys are pressed with `+` and
+//! released with `-`, so a key is held on every line between its `+` and its `-`. Keys are named like GML's `vk_`
+//! constants, or by their letter or digit, and any other key code can be given as `#N`. Mouse buttons are `mb_left`,
+//! `mb_right`, `mb_middle`, `mb_x1` and `mb_x2`. Blank lines and lines starting with `#` are ignored.
+
+use crate::{
+    game::replay::{Event, Frame, GameInfo, Input, Replay},
+    gml::{mappings, Value},
+};
+use std::fmt::{self, Write};
+
+const HEADER: &str = "opengmk replay 1";
+
+// How many frames apart the `# frame N` comments are written, to make it easier to find your way around
+const COMMENT_INTERVAL: usize = 60;
+
+const MOUSE_BUTTONS: [(i8, &str); 5] = [(1, "mb_left"), (2, "mb_right"), (3, "mb_middle"), (4, "mb_x1"), (5, "mb_x2")];
+
+// An error in a replay's text, with the line it's on counting from 1
+#[derive(Debug)]
+pub struct TextError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Replay {
+    // Writes the replay out as text. Reading it back with `from_text` gives exactly the same replay.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", HEADER);
+        let _ = writeln!(out, "start_time {}", self.start_time);
+        let _ = writeln!(out, "start_seed {}", self.start_seed);
+        let _ = writeln!(out, "rerecords {}", self.rerecords);
+        if let Some(game) = self.game {
+            let _ = writeln!(out, "game {} {:016x}", game.game_id, game.gamedata_hash);
+        }
+        for event in &self.startup_events {
+            let _ = writeln!(out, "startup {}", event_text(event));
+        }
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i % COMMENT_INTERVAL == 0 {
+                let _ = writeln!(out, "# frame {}", i);
+            }
+            let _ = write!(out, "{} {}", frame.mouse_x, frame.mouse_y);
+            for input in &frame.inputs {
+                out.push(' ');
+                out += &input_text(input);
+            }
+            for event in &frame.events {
+                out.push(' ');
+                out += &event_text(event);
+            }
+            if let Some(seed) = frame.new_seed {
+                let _ = write!(out, " seed={}", seed);
+            }
+            if let Some(time) = frame.new_time {
+                let _ = write!(out, " time={}", time);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Reads a replay written by `to_text`, or edited by hand since
+    pub fn from_text(text: &str) -> Result<Self, TextError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let error = |line, message: String| TextError { line, message };
+
+        match lines.next() {
+            Some((_, HEADER)) => (),
+            Some((line, _)) => return Err(error(line, format!("expected '{}'", HEADER))),
+            None => return Err(error(1, "the file is empty".into())),
+        }
+        let mut header_value = |key: &str| match lines.next() {
+            Some((line, text)) => match text.strip_prefix(key).map(str::trim) {
+                Some(value) => Ok((line, value)),
+                None => Err(error(line, format!("expected {}", key))),
+            },
+            None => Err(error(text.lines().count(), format!("expected {}", key))),
+        };
+        let (line, start_time) = header_value("start_time")?;
+        let start_time = start_time.parse().map_err(|e| error(line, format!("bad start_time: {}", e)))?;
+        let (line, start_seed) = header_value("start_seed")?;
+        let start_seed = start_seed.parse().map_err(|e| error(line, format!("bad start_seed: {}", e)))?;
+
+        let mut replay = Replay::new(start_time, start_seed);
+        for (line, text) in lines {
+            let (key, value) = text.split_once(' ').unwrap_or((text, ""));
+            if matches!(key, "startup" | "rerecords" | "game") && !replay.frames.is_empty() {
+                return Err(error(line, format!("{} has to come before the first frame", key)))
+            }
+            if key == "startup" {
+                replay.startup_events.push(parse_event(value.trim()).map_err(|e| error(line, e))?);
+            } else if key == "rerecords" {
+                replay.rerecords = value.trim().parse().map_err(|e| error(line, format!("bad rerecords: {}", e)))?;
+            } else if key == "game" {
+                replay.game = Some(parse_game(value).map_err(|e| error(line, e))?);
+            } else {
+                replay.frames.push(parse_frame(text).map_err(|e| error(line, e))?);
+            }
+        }
+        Ok(replay)
+    }
+}
+
+fn parse_game(text: &str) -> Result<GameInfo, String> {
+    match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [game_id, gamedata_hash] => Ok(GameInfo {
+            game_id: game_id.parse().map_err(|e| format!("bad game ID: {}", e))?,
+            gamedata_hash: u64::from_str_radix(gamedata_hash, 16).map_err(|e| format!("bad gamedata hash: {}", e))?,
+        }),
+        _ => Err("expected a game ID and gamedata hash".into()),
+    }
+}
+
+fn key_name(code: u8) -> String {
+    // vk_enter and vk_return are the same key, as are a few others, so the first name alphabetically is used
+    let name = mappings::CONSTANTS
+        .entries()
+        .filter(|(name, value)| name.starts_with("vk_") && **value == f64::from(code))
+        .map(|(name, _)| *name)
+        .filter(|&name| name != "vk_nokey" && name != "vk_anykey")
+        .min();
+    match name {
+        Some(name) => name.into(),
+        None if code.is_ascii_uppercase() || code.is_ascii_digit() => char::from(code).into(),
+        None => format!("#{}", code),
+    }
+}
+
+fn parse_key(name: &str) -> Result<u8, String> {
+    if let Some(code) = name.strip_prefix('#') {
+        return code.parse().map_err(|_| format!("bad key code '{}'", name))
+    }
+    match name.as_bytes() {
+        &[c] if c.is_ascii_alphanumeric() => Ok(c.to_ascii_uppercase()),
+        _ => match mappings::CONSTANTS.get(name) {
+            Some(&code) if name.starts_with("vk_") => Ok(code as u8),
+            _ => Err(format!("unknown key '{}'", name)),
+        },
+    }
+}
+
+fn mouse_button_name(button: i8) -> String {
+    match MOUSE_BUTTONS.iter().find(|(code, _)| *code == button) {
+        Some((_, name)) => (*name).into(),
+        None => format!("mb#{}", button),
+    }
+}
+
+fn parse_mouse_button(name: &str) -> Option<i8> {
+    match name.strip_prefix("mb#") {
+        Some(code) => code.parse().ok(),
+        None => MOUSE_BUTTONS.iter().find(|(_, x)| *x == name).map(|(code, _)| *code),
+    }
+}
+
+fn input_text(input: &Input) -> String {
+    match input {
+        Input::KeyPress(key) => format!("+{}", key_name(*key)),
+        Input::KeyRelease(key) => format!("-{}", key_name(*key)),
+        Input::MousePress(button) => format!("+{}", mouse_button_name(*button)),
+        Input::MouseRelease(button) => format!("-{}", mouse_button_name(*button)),
+        Input::MouseWheelUp => "wheel_up".into(),
+        Input::MouseWheelDown => "wheel_down".into(),
+        Input::CloseButton => "close_button".into(),
+    }
+}
+
+fn parse_input(token: &str) -> Option<Result<Input, String>> {
+    let (pressed, name) = match token.as_bytes().first() {
+        Some(b'+') => (true, &token[1..]),
+        Some(b'-') => (false, &token[1..]),
+        _ => {
+            return match token {
+                "wheel_up" => Some(Ok(Input::MouseWheelUp)),
+                "wheel_down" => Some(Ok(Input::MouseWheelDown)),
+                "close_button" => Some(Ok(Input::CloseButton)),
+                _ => None,
+            }
+        },
+    };
+    Some(match (parse_mouse_button(name), pressed) {
+        (Some(button), true) => Ok(Input::MousePress(button)),
+        (Some(button), false) => Ok(Input::MouseRelease(button)),
+        (None, true) => parse_key(name).map(Input::KeyPress),
+        (None, false) => parse_key(name).map(Input::KeyRelease),
+    })
+}
+
+fn event_text(event: &Event) -> String {
+    match event {
+        Event::GetInteger(value) => format!("get_integer={}", value_text(value)),
+        Event::GetString(value) => format!("get_string={}", value_text(value)),
+        Event::Randomize(seed) => format!("randomize={}", seed),
+        Event::ShowMenu(value) => format!("show_menu={}", value_text(value)),
+        Event::ShowMessage => "show_message".into(),
+        Event::ShowQuestion(value) => format!("show_question={}", value_text(value)),
+    }
+}
+
+fn parse_event(token: &str) -> Result<Event, String> {
+    let (name, value) = match token.find('=') {
+        Some(i) => (&token[..i], Some(&token[i + 1..])),
+        None => (token, None),
+    };
+    let needs_value = || value.ok_or_else(|| format!("{} needs a value", name));
+    Ok(match name {
+        "get_integer" => Event::GetInteger(parse_value(needs_value()?)?),
+        "get_string" => Event::GetString(parse_value(needs_value()?)?),
+        "randomize" => Event::Randomize(needs_value()?.parse().map_err(|_| format!("bad seed in '{}'", token))?),
+        "show_menu" => Event::ShowMenu(parse_value(needs_value()?)?),
+        "show_message" if value.is_none() => Event::ShowMessage,
+        "show_question" => Event::ShowQuestion(parse_value(needs_value()?)?),
+        _ => return Err(format!("unknown event '{}'", token)),
+    })
+}
+
+// Reals are written so they read back as exactly the same number, and strings are quoted, with anything other
+// than printable ASCII escaped as \xNN so that the text doesn't depend on the game's encoding.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Real(real) => format!("{:?}", real.into_inner()),
+        Value::Str(string) => {
+            let mut out = String::from("\"");
+            for &byte in string.as_ref() {
+                match byte {
+                    b'"' => out += "\\\"",
+                    b'\\' => out += "\\\\",
+                    0x20..=0x7E => out.push(char::from(byte)),
+                    _ => {
+                        let _ = write!(out, "\\x{:02X}", byte);
+                    },
+                }
+            }
+            out.push('"');
+            out
+        },
+    }
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    let quoted = match text.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(quoted) => quoted,
+        None => return text.parse::<f64>().map(Value::from).map_err(|_| format!("bad value '{}'", text)),
+    };
+    let mut bytes = Vec::with_capacity(quoted.len());
+    let mut iter = quoted.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue
+        }
+        match iter.next() {
+            Some(b'x') => {
+                let hex = [iter.next(), iter.next()];
+                let digits = hex.iter().flatten().map(|&x| char::from(x)).collect::<String>();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 => bytes.push(byte),
+                    _ => return Err(format!("bad escape in {}", text)),
+                }
+            },
+            Some(escaped @ (b'"' | b'\\')) => bytes.push(escaped),
+            _ => return Err(format!("bad escape in {}", text)),
+        }
+    }
+    Ok(Value::from(bytes))
+}
+
+// Splits a frame line into words, keeping quoted strings together even if they have spaces in them
+fn tokens(line: &str) -> Result<Vec<&str>, String> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let (mut quoted, mut escaped) = (false, false);
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    tokens.push(&line[start..i]);
+                }
+                continue
+            },
+            _ => (),
+        }
+        start.get_or_insert(i);
+    }
+    if quoted {
+        return Err("a quoted string doesn't end".into())
+    }
+    tokens.extend(start.map(|start| &line[start..]));
+    Ok(tokens)
+}
+
+fn parse_frame(line: &str) -> Result<Frame, String> {
+    let tokens = tokens(line)?;
+    let mouse = |i: usize| {
+        let token = tokens.get(i).ok_or("expected the mouse position at the start of the line")?;
+        token.parse::<i32>().map_err(|_| format!("bad mouse position '{}'", token))
+    };
+    let mut frame = Frame {
+        mouse_x: mouse(0)?,
+        mouse_y: mouse(1)?,
+        inputs: Vec::new(),
+        events: Vec::new(),
+        new_seed: None,
+        new_time: None,
+    };
+    for &token in &tokens[2..] {
+        if let Some(input) = parse_input(token) {
+            frame.inputs.push(input?);
+        } else if let Some(seed) = token.strip_prefix("seed=") {
+            frame.new_seed = Some(seed.parse().map_err(|_| format!("bad seed '{}'", token))?);
+        } else if let Some(time) = token.strip_prefix("time=") {
+            frame.new_time = Some(time.parse().map_err(|_| format!("bad time '{}'", token))?);
+        } else {
+            frame.events.push(parse_event(token)?);
+        }
+    }
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A replay using every kind of input and event there is
+    fn replay() -> Replay {
+        let mut replay = Replay::new(1_600_000_000_123_456_789, -77);
+        replay.rerecords = 12;
+        replay.game = Some(GameInfo { game_id: -3, gamedata_hash: 0x00C0_FFEE });
+        replay.startup_events.push(Event::ShowMessage);
+        replay.startup_events.push(Event::GetString("say \"hi\"\\ \x01 caf\u{e9}".into()));
+        let frame = replay.new_frame();
+        frame.mouse_x = 320;
+        frame.mouse_y = -5;
+        frame.inputs.extend(vec![Input::KeyPress(0x27), Input::KeyPress(b'Z'), Input::KeyPress(b'0')]);
+        frame.inputs.extend(vec![Input::KeyPress(13), Input::KeyPress(0xFE), Input::MousePress(1)]);
+        frame.new_seed = Some(42);
+        let frame = replay.new_frame();
+        frame.inputs.extend(vec![Input::KeyRelease(b'Z'), Input::MouseRelease(1), Input::MousePress(5)]);
+        frame.inputs.extend(vec![Input::MousePress(-3), Input::MouseWheelUp, Input::MouseWheelDown]);
+        frame.inputs.push(Input::CloseButton);
+        frame.events.extend(vec![Event::GetInteger(0.1.into()), Event::GetInteger((-0.0).into())]);
+        frame.events.extend(vec![Event::Randomize(i32::MIN), Event::ShowMenu(3.into())]);
+        frame.events.extend(vec![Event::ShowQuestion(1.into()), Event::GetString("".into())]);
+        frame.new_time = Some(u128::MAX);
+        for _ in 0..70 {
+            replay.new_frame();
+        }
+        replay
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = replay();
+        let text = replay.to_text();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0..3], ["opengmk replay 1", "start_time 1600000000123456789", "start_seed -77"]);
+        assert_eq!(lines[3..5], ["rerecords 12", "game -3 0000000000c0ffee"]);
+        assert_eq!(lines[6], r#"startup get_string="say \"hi\"\\ \x01 caf\xC3\xA9""#);
+        assert_eq!(lines[8], "320 -5 +vk_right +Z +0 +vk_enter +#254 +mb_left seed=42");
+        assert!(lines[9].starts_with("320 -5 -Z -mb_left +mb_x2 +mb#-3 wheel_up wheel_down close_button"));
+        assert!(lines[9].ends_with(" get_string=\"\" time=340282366920938463463374607431768211455"));
+        assert_eq!(lines.iter().filter(|x| x.starts_with('#')).count(), 2);
+
+        let read = Replay::from_text(&text).unwrap();
+        assert_eq!(read.serialize().unwrap(), replay.serialize().unwrap());
+        assert_eq!(read.to_text(), text);
+    }
+
+    #[test]
+    fn hand_written() {
+        let text = "opengmk replay 1\n\nstart_time 0\nstart_seed 1\n# a comment\n  0 0 +vk_space +a  \n1 2 -A\n";
+        let replay = Replay::from_text(text).unwrap();
+        assert_eq!(replay.frame_count(), 2);
+        let inputs = &replay.get_frame(0).unwrap().inputs;
+        assert!(matches!(inputs.as_slice(), [Input::KeyPress(32), Input::KeyPress(b'A')]));
+        assert_eq!(replay.get_frame(1).unwrap().mouse_y, 2);
+        assert_eq!((replay.rerecords, replay.game), (0, None));
+
+        let error = |text: &str| Replay::from_text(text).unwrap_err().to_string();
+        let header = "opengmk replay 1\nstart_time 0\nstart_seed 0\n";
+        assert_eq!(error(""), "line 1: the file is empty");
+        assert_eq!(error("opengmk replay 2"), "line 1: expected 'opengmk replay 1'");
+        assert_eq!(error("opengmk replay 1\nstart_seed 0"), "line 2: expected start_time");
+        assert_eq!(error(&format!("{}0 0 +vk_nope", header)), "line 4: unknown key 'vk_nope'");
+        assert_eq!(error(&format!("{}0 0 get_string=\"oops", header)), "line 4: a quoted string doesn't end");
+        assert_eq!(error(&format!("{}0 0 jump", header)), "line 4: unknown event 'jump'");
+        assert!(error(&format!("{}0 0\n\n0", header)).starts_with("line 6: expected the mouse position"));
+        assert!(error(&format!("{}0 0\nstartup show_message", header)).starts_with("line 5: startup has to come"));
+        assert_eq!(error(&format!("{}game 1", header)), "line 4: expected a game ID and gamedata hash");
+        assert!(error(&format!("{}game 1 xyz", header)).starts_with("line 4: bad gamedata hash"));
+        assert!(error(&format!("{}rerecords -1", header)).starts_with("line 4: bad rerecords"));
+    }
+}
@@ -1,30 +1,45 @@
 use crate::{
     game::{
-        audio::AudioState, draw, external, includedfile::IncludedFile, model::Model, particle,
-        pathfinding::PotentialStepSettings, surface::Surface, transition::UserTransition, Assets, Game, Replay,
-        RoomState, Version,
+        audio::{AudioState, AudioStateV1},
+        autosave, draw, external,
+        filecapture::FileSnapshot,
+        includedfile::IncludedFile,
+        model::Model,
+        particle,
+        pathfinding::PotentialStepSettings,
+        replay::ReplayV1,
+        surface::Surface,
+        transition::UserTransition,
+        Assets, AssetsV1, Game, Replay, RoomState, Version,
     },
-    gml::{self, ds, rand::Random, Compiler},
+    gml::{self, compiler::CompilerV1, ds, rand::Random, Compiler},
     handleman::HandleList,
-    input::Input,
+    input::{Input, InputV1},
     instance::DummyFieldHolder,
     math::Real,
-    render::{RendererState, SavedTexture, Scaling},
+    render::{RendererState, SavedTexture, SavedTextureV1, Scaling},
     types::{Colour, ID},
 };
-use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use byteorder::{WriteBytesExt, LE};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use indexmap::IndexMap;
 use lzzzz::lz4;
 use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
-    fs::{File, OpenOptions},
+    convert::TryInto,
+    fs::File,
     io::{self, Read, Write},
     path::PathBuf,
     rc::Rc,
 };
 
+/// Savestate files start with this, then the length of the serialized state, then the state compressed with zlib.
+/// The number goes up whenever the serialized state changes. Older versions are turned away rather than misread.
+/// Files from before this started with the length and were compressed with lz4, and are read as a SaveStateV1.
+const MAGIC: &[u8; 8] = b"OGMKSAV3";
+
 /// Represents a savestate. Very similar to the Game struct, but without things which aren't serialized.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SaveState {
@@ -98,6 +113,8 @@ pub struct SaveState {
     pub health: Real,
     pub health_capt: gml::String,
     pub health_capt_d: bool,
+    pub lives_ran_out: bool,
+    pub health_ran_out: bool,
     pub error_occurred: bool,
     pub error_last: gml::String,
 
@@ -114,18 +131,211 @@ pub struct SaveState {
     window_height: u32,
 
     audio_state: AudioState,
+    files: Option<FileSnapshot>,
 
+    #[serde(with = "crate::game::replay::versioned")]
     replay: Replay,
+    screen: SavedTexture,
+}
+
+// A SaveState as it was stored before the header, with the types it had then
+#[derive(Deserialize)]
+struct SaveStateV1 {
+    compiler: CompilerV1,
+    rand: Random,
+    input: InputV1,
+    assets: AssetsV1,
+    event_holders: [IndexMap<u32, Rc<RefCell<Vec<ID>>>>; 12],
+    custom_draw_objects: HashSet<ID>,
+
+    background_colour: Colour,
+    textures: Vec<Option<SavedTextureV1>>,
+
+    externals: (HashMap<ID, external::state::State>, ID),
+    surface_fix: bool,
+
+    view_current: usize,
+
+    last_instance_id: ID,
+    last_tile_id: ID,
+
+    particles: particle::Manager,
+
+    room: RoomState,
+    stored_rooms: Vec<RoomState>,
+    room_order: Box<[i32]>,
+    user_transitions: HashMap<i32, UserTransition>,
+
+    globals: DummyFieldHolder,
+    globalvars: HashSet<usize>,
+    game_start: bool,
+
+    stacks: HandleList<ds::Stack>,
+    queues: HandleList<ds::Queue>,
+    lists: HandleList<ds::List>,
+    maps: HandleList<ds::Map>,
+    priority_queues: HandleList<ds::Priority>,
+    grids: HandleList<ds::Grid>,
+    ds_precision: Real,
+
+    draw_font_id: ID,
+    draw_colour: Colour,
+    draw_alpha: Real,
+    draw_halign: draw::Halign,
+    draw_valign: draw::Valign,
+    surfaces: Vec<Option<Surface>>,
+    surface_target: Option<i32>,
+    models: Vec<Option<Model>>,
+    model_matrix_stack: Vec<[f32; 16]>,
+    auto_draw: bool,
+    renderer_state: RendererState,
+
+    uninit_fields_are_zero: bool,
+    uninit_args_are_zero: bool,
+
+    potential_step_settings: PotentialStepSettings,
+
+    fps: u32,
+    frame_counter: u32,
+    transition_kind: i32,
+    transition_steps: i32,
+    cursor_sprite: i32,
+    cursor_sprite_frame: u32,
+    score: i32,
+    score_capt: gml::String,
+    score_capt_d: bool,
+    has_set_show_score: bool,
+    lives: i32,
+    lives_capt: gml::String,
+    lives_capt_d: bool,
+    health: Real,
+    health_capt: gml::String,
+    health_capt_d: bool,
+    error_occurred: bool,
+    error_last: gml::String,
+
+    game_id: i32,
+    program_directory: gml::String,
+    included_files: Vec<IncludedFile>,
+    gm_version: Version,
+    spoofed_time_nanos: Option<u128>,
+
+    scaling: Scaling,
+    unscaled_width: u32,
+    unscaled_height: u32,
+    window_width: u32,
+    window_height: u32,
+
+    audio_state: AudioStateV1,
+
+    replay: ReplayV1,
     screenshot: Box<[u8]>,
     zbuffer: Box<[f32]>,
 }
 
+impl From<SaveStateV1> for SaveState {
+    fn from(old: SaveStateV1) -> Self {
+        let gm81 = matches!(old.gm_version, Version::GameMaker8_1);
+        Self {
+            compiler: old.compiler.upgrade(gm81),
+            rand: old.rand,
+            input: old.input.into(),
+            assets: old.assets.into(),
+            event_holders: old.event_holders,
+            custom_draw_objects: old.custom_draw_objects,
+            background_colour: old.background_colour,
+            textures: old.textures.into_iter().map(|x| x.map(SavedTexture::from)).collect(),
+            externals: old.externals,
+            surface_fix: old.surface_fix,
+            view_current: old.view_current,
+            last_instance_id: old.last_instance_id,
+            last_tile_id: old.last_tile_id,
+            particles: old.particles,
+            room: old.room,
+            stored_rooms: old.stored_rooms,
+            room_order: old.room_order,
+            user_transitions: old.user_transitions,
+            globals: old.globals,
+            globalvars: old.globalvars,
+            game_start: old.game_start,
+            stacks: old.stacks,
+            queues: old.queues,
+            lists: old.lists,
+            maps: old.maps,
+            priority_queues: old.priority_queues,
+            grids: old.grids,
+            ds_precision: old.ds_precision,
+            draw_font_id: old.draw_font_id,
+            draw_colour: old.draw_colour,
+            draw_alpha: old.draw_alpha,
+            draw_halign: old.draw_halign,
+            draw_valign: old.draw_valign,
+            surfaces: old.surfaces,
+            surface_target: old.surface_target,
+            models: old.models,
+            model_matrix_stack: old.model_matrix_stack,
+            auto_draw: old.auto_draw,
+            renderer_state: old.renderer_state,
+            uninit_fields_are_zero: old.uninit_fields_are_zero,
+            uninit_args_are_zero: old.uninit_args_are_zero,
+            potential_step_settings: old.potential_step_settings,
+            fps: old.fps,
+            frame_counter: old.frame_counter,
+            transition_kind: old.transition_kind,
+            transition_steps: old.transition_steps,
+            cursor_sprite: old.cursor_sprite,
+            cursor_sprite_frame: old.cursor_sprite_frame,
+            score: old.score,
+            score_capt: old.score_capt,
+            score_capt_d: old.score_capt_d,
+            has_set_show_score: old.has_set_show_score,
+            lives: old.lives,
+            lives_capt: old.lives_capt,
+            lives_capt_d: old.lives_capt_d,
+            health: old.health,
+            health_capt: old.health_capt,
+            health_capt_d: old.health_capt_d,
+            lives_ran_out: false,
+            health_ran_out: false,
+            error_occurred: old.error_occurred,
+            error_last: old.error_last,
+            game_id: old.game_id,
+            program_directory: old.program_directory,
+            included_files: old.included_files,
+            gm_version: old.gm_version,
+            spoofed_time_nanos: old.spoofed_time_nanos,
+            scaling: old.scaling,
+            unscaled_width: old.unscaled_width,
+            unscaled_height: old.unscaled_height,
+            window_width: old.window_width,
+            window_height: old.window_height,
+            audio_state: old.audio_state.into(),
+            files: None,
+            replay: old.replay.into(),
+            screen: SavedTexture::new(
+                old.window_width as i32,
+                old.window_height as i32,
+                old.screenshot,
+                Some(old.zbuffer),
+            ),
+        }
+    }
+}
+
 impl SaveState {
     /// Creates a new SaveState from the given components.
     pub fn from(game: &mut Game, replay: Replay, renderer_state: RendererState) -> Self {
+        // Savestates are only made between frames, never from inside an event, so no destroy event can be running.
+        // Destroyed instances can still be in the room (from a draw event, or if the room changed before the end
+        // of the step), but they're saved with their Deleted state and removed at the end of the next step as usual.
+        assert!(game.destroying.is_empty(), "savestate made while a destroy event was running");
         let (window_width, window_height) = game.renderer.stored_size();
-        let screenshot = game.renderer.stored_pixels();
-        let zbuffer = game.renderer.stored_zbuffer();
+        let screen = SavedTexture::new(
+            window_width as i32,
+            window_height as i32,
+            game.renderer.stored_pixels(),
+            Some(game.renderer.stored_zbuffer()),
+        );
 
         Self {
             compiler: game.compiler.clone(),
@@ -186,6 +396,8 @@ impl SaveState {
             health: game.health.clone(),
             health_capt: game.health_capt.clone(),
             health_capt_d: game.health_capt_d.clone(),
+            lives_ran_out: game.lives_ran_out,
+            health_ran_out: game.health_ran_out,
             error_occurred: game.error_occurred,
             error_last: game.error_last.clone(),
             game_id: game.game_id.clone(),
@@ -199,9 +411,15 @@ impl SaveState {
             window_width,
             window_height,
             audio_state: game.audio.state(),
+            files: game.file_store.as_ref().and_then(|store| match store.capture() {
+                Ok(snapshot) => Some(snapshot),
+                Err(e) => {
+                    eprintln!("warning: failed to capture files for savestate: {}", e);
+                    None
+                },
+            }),
             replay,
-            screenshot,
-            zbuffer,
+            screen,
         }
     }
 
@@ -209,7 +427,8 @@ impl SaveState {
     pub fn load_into(self, game: &mut Game) -> (Replay, RendererState) {
         game.renderer.upload_dynamic_textures(&self.textures);
 
-        game.renderer.set_stored(self.screenshot, self.zbuffer, self.window_width, self.window_height);
+        let (pixels, zbuffer) = self.screen.into_parts();
+        game.renderer.set_stored(pixels, zbuffer.unwrap_or_default(), self.window_width, self.window_height);
 
         let surfaces = self.surfaces;
         if let Some(Some(surf)) = self.surface_target.and_then(|id| surfaces.get(id as usize)) {
@@ -237,6 +456,11 @@ impl SaveState {
         game.compiler = self.compiler;
         game.rand = self.rand;
         game.input = self.input;
+        // Identical sprite frames are given one texture at launch. A state from before that (see SaveStateV1) has
+        // its sprites pointing at where their textures used to be, so the only difference is what gets drawn.
+        if self.assets.shared_textures.frames_saved != game.assets.shared_textures.frames_saved {
+            eprintln!("warning: this savestate is from an older version, so some sprites may be drawn wrong");
+        }
         game.assets = self.assets;
         game.event_holders = self.event_holders;
         game.custom_draw_objects = self.custom_draw_objects;
@@ -288,6 +512,8 @@ impl SaveState {
         game.health = self.health;
         game.health_capt = self.health_capt;
         game.health_capt_d = self.health_capt_d;
+        game.lives_ran_out = self.lives_ran_out;
+        game.health_ran_out = self.health_ran_out;
         game.error_occurred = self.error_occurred;
         game.error_last = self.error_last;
         game.game_id = self.game_id;
@@ -295,10 +521,18 @@ impl SaveState {
         game.included_files = self.included_files;
         game.gm_version = self.gm_version;
         game.spoofed_time_nanos = self.spoofed_time_nanos;
-        game.audio.set_state(self.audio_state);
+        let current_time = game.spoofed_time_nanos.unwrap_or_else(gml::datetime::now_as_nanos);
+        game.audio.set_state(self.audio_state, game.assets.sounds.iter().flatten().map(|x| &x.handle), current_time);
+        if let (Some(store), Some(snapshot)) = (&game.file_store, &self.files) {
+            if let Err(e) = store.restore(snapshot) {
+                eprintln!("warning: failed to restore captured files: {}", e);
+            }
+        }
         game.scaling = self.scaling;
         game.unscaled_width = self.unscaled_width;
         game.unscaled_height = self.unscaled_height;
+        // the log isn't saved, but messages printed from here on are numbered from the frame this was saved on
+        game.game_log.set_frame(self.replay.frame_count() as u64);
         (self.replay, self.renderer_state)
     }
 
@@ -309,84 +543,205 @@ impl SaveState {
 
     /// Loads a SaveState from a file. The format will always match the one used by `save_to_file()`.
     pub fn from_file(path: &PathBuf, buffer: &mut Buffer) -> Result<Self, ReadError> {
-        match File::open(path).map(|f| (f.metadata().map(|m| m.len() as usize + 1).unwrap_or(0), f)) {
-            Ok((init_size, mut file)) => {
-                buffer.lz4_buf.clear();
-                buffer.lz4_buf.reserve(init_size);
-                match file.read_to_end(&mut buffer.lz4_buf) {
-                    Ok(_) => {
-                        match (buffer.lz4_buf.as_slice().read_u64::<LE>().map(|x| x as usize), buffer.lz4_buf.get(8..))
-                        {
-                            (Ok(len), Some(block)) => {
-                                buffer.bin_buf.clear();
-                                buffer.bin_buf.reserve(len);
-                                unsafe { buffer.bin_buf.set_len(len) };
-                                match lz4::decompress(block, buffer.bin_buf.as_mut_slice()) {
-                                    Ok(len) => {
-                                        unsafe { buffer.bin_buf.set_len(len) };
-                                        bincode::deserialize::<'_, SaveState>(buffer.bin_buf.as_slice())
-                                            .map_err(ReadError::DeserializeErr)
-                                    },
-                                    Err(err) => Err(ReadError::DecompressErr(err)),
-                                }
-                            },
-                            (Ok(_), None) => Err(ReadError::IOErr(io::Error::from(io::ErrorKind::UnexpectedEof))),
-                            (Err(err), _) => Err(ReadError::IOErr(err)),
-                        }
-                    },
-                    Err(err) => Err(ReadError::IOErr(err)),
-                }
-            },
-            Err(err) => Err(ReadError::IOErr(err)),
-        }
+        buffer.file_buf.clear();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut buffer.file_buf)).map_err(ReadError::IOErr)?;
+        let state = if decompress(&buffer.file_buf, &mut buffer.bin_buf)? {
+            bincode::deserialize::<'_, SaveStateV1>(buffer.bin_buf.as_slice()).map(Self::from)
+        } else {
+            bincode::deserialize::<'_, SaveState>(buffer.bin_buf.as_slice())
+        };
+        state.map_err(ReadError::DeserializeErr)
     }
 
-    /// Saves a SaveState to a file. The SaveState object is formatted with Serde/bincode and compressed with lz4.
-    /// A Buffer object is needed for the lz4 compression. Ideally, the same buffer should be re-used on each call.
+    /// Saves a SaveState to a file. The SaveState object is formatted with Serde/bincode and compressed with zlib,
+    /// after its textures and the stored screen have been compressed as PNGs.
+    /// A Buffer object is needed for the compression. Ideally, the same buffer should be re-used on each call.
+    /// The file is replaced atomically, so a crash while saving leaves the previous save intact.
     pub fn save_to_file(&self, path: &PathBuf, buffer: &mut Buffer) -> Result<(), WriteError> {
         buffer.bin_buf.clear();
-        buffer.lz4_buf.clear();
-        match bincode::serialize_into(&mut buffer.bin_buf, self) {
-            Ok(()) => {
-                match lz4::compress_to_vec(buffer.bin_buf.as_slice(), buffer.lz4_buf.as_mut(), lz4::ACC_LEVEL_DEFAULT) {
-                    Ok(_length) => {
-                        match OpenOptions::new().create(true).write(true).truncate(true).open(path).and_then(|mut f| {
-                            f.write_u64::<LE>(buffer.bin_buf.len() as u64)
-                                .and_then(|_| f.write_all(buffer.lz4_buf.as_slice()))
-                        }) {
-                            Ok(()) => Ok(()),
-                            Err(e) => Err(WriteError::IOErr(e)),
-                        }
-                    },
-                    Err(err) => Err(WriteError::CompressErr(err)),
-                }
-            },
-            Err(err) => Err(WriteError::SerializeErr(err)),
-        }
+        bincode::serialize_into(&mut buffer.bin_buf, self).map_err(WriteError::SerializeErr)?;
+        compress(&buffer.bin_buf, &mut buffer.file_buf).map_err(WriteError::CompressErr)?;
+        autosave::write_atomic(path, |f| f.write_all(&buffer.file_buf)).map_err(WriteError::IOErr)
+    }
+}
+
+/// Compresses a serialized SaveState into the contents of a savestate file.
+fn compress(bin: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    out.clear();
+    out.extend_from_slice(MAGIC);
+    out.write_u64::<LE>(bin.len() as u64)?;
+    let mut encoder = ZlibEncoder::new(out, Compression::default());
+    encoder.write_all(bin)?;
+    encoder.finish().map(|_| ())
+}
+
+/// Decompresses the contents of a savestate file made by `compress`, or by the lz4 format from before MAGIC.
+/// Returns true if it was the old format, whose contents are a SaveStateV1.
+fn decompress(file: &[u8], out: &mut Vec<u8>) -> Result<bool, ReadError> {
+    let (data, legacy) = match file.strip_prefix(&MAGIC[..]) {
+        Some(data) => (data, false),
+        None if file.starts_with(&MAGIC[..7]) => {
+            let error = io::Error::new(io::ErrorKind::InvalidData, "savestate from an unsupported version");
+            return Err(ReadError::DecompressErr(error))
+        },
+        // an old file starts with its length, which is never anywhere near big enough to look like MAGIC
+        None => (file, true),
+    };
+    let len = match data.get(..8) {
+        Some(len) => u64::from_le_bytes(len.try_into().unwrap()) as usize,
+        None => return Err(ReadError::IOErr(io::Error::from(io::ErrorKind::UnexpectedEof))),
+    };
+    out.clear();
+    if legacy {
+        out.resize(len, 0);
+        let len = lz4::decompress(&data[8..], out).map_err(ReadError::DecompressErr)?;
+        out.truncate(len);
+    } else {
+        out.reserve(len);
+        ZlibDecoder::new(&data[8..]).read_to_end(out).map_err(ReadError::DecompressErr)?;
     }
+    Ok(legacy)
 }
 
 pub struct Buffer {
     bin_buf: Vec<u8>,
-    lz4_buf: Vec<u8>,
+    file_buf: Vec<u8>,
 }
 
 impl Buffer {
     pub fn new() -> Self {
-        Self { bin_buf: Vec::new(), lz4_buf: Vec::new() }
+        Self { bin_buf: Vec::new(), file_buf: Vec::new() }
     }
 }
 
 #[derive(Debug)]
 pub enum ReadError {
     IOErr(io::Error),
-    DecompressErr(lzzzz::Error),
+    DecompressErr(io::Error),
     DeserializeErr(Box<bincode::ErrorKind>),
 }
 
 #[derive(Debug)]
 pub enum WriteError {
     IOErr(io::Error),
-    CompressErr(lzzzz::Error),
+    CompressErr(io::Error),
     SerializeErr(Box<bincode::ErrorKind>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        filecapture::FileStore,
+        replay::Input,
+        testing::{self, run},
+    };
+    use std::fs;
+
+    // The parts of a mid-game savestate which take up the most space, with textures of type T
+    #[derive(Serialize)]
+    struct SyntheticState<T> {
+        textures: Vec<Option<T>>,
+        screen: T,
+        instances: Vec<[f64; 24]>,
+        replay: Replay,
+    }
+
+    // Something like what a game draws: a tiled background, a gradient and some transparent space
+    fn scene(width: usize, height: usize) -> Box<[u8]> {
+        let pixel = |x: usize, y: usize| match x * 3 / width {
+            0 => [(x / 16 * 40 + y / 16 * 70) as u8, 96, 160, 255],
+            1 => [(x % 256) as u8, (y % 256) as u8, 128, 255],
+            _ => [0, 0, 0, 0],
+        };
+        (0..width * height).flat_map(|i| pixel(i % width, i / width)).collect()
+    }
+
+    #[test]
+    fn compressed_size() {
+        let (width, height) = (800, 608);
+        let mut replay = Replay::new(0, 0);
+        for i in 0..20000 {
+            let frame = replay.new_frame();
+            frame.mouse_x = 400 + i / 1000;
+            if i % 13 == 0 {
+                frame.inputs.push(Input::KeyPress(39));
+            }
+        }
+        let instances = (0..500)
+            .map(|i| {
+                let mut fields = [0.0; 24];
+                fields[0] = f64::from(i % 25 * 32);
+                fields[1] = f64::from(i / 25 * 32) + 0.25;
+                fields[2] = f64::from(i % 7);
+                fields
+            })
+            .collect::<Vec<_>>();
+        let surface = (640, 480, scene(640, 480), None::<Box<[f32]>>);
+        let screen = (width as i32, height as i32, scene(width, height), Some(vec![1.0f32; width * height].into()));
+
+        // as it was before: the raw pixels everywhere, compressed with lz4
+        let old = SyntheticState {
+            textures: vec![Some(surface.clone()), None, Some(surface.clone())],
+            screen: screen.clone(),
+            instances: instances.clone(),
+            replay: replay.clone(),
+        };
+        let old_bin = bincode::serialize(&old).unwrap();
+        let mut old_file = (old_bin.len() as u64).to_le_bytes().to_vec();
+        lz4::compress_to_vec(&old_bin, &mut old_file, lz4::ACC_LEVEL_DEFAULT).unwrap();
+
+        // which still loads, as the old layout
+        let mut bin = Vec::new();
+        assert!(decompress(&old_file, &mut bin).unwrap());
+        assert!(bin == old_bin);
+
+        let texture = SavedTexture::new(surface.0, surface.1, surface.2, surface.3);
+        let new = SyntheticState {
+            textures: vec![Some(texture.clone()), None, Some(texture)],
+            screen: SavedTexture::new(screen.0, screen.1, screen.2, screen.3),
+            instances,
+            replay,
+        };
+        let new_bin = bincode::serialize(&new).unwrap();
+        let mut file = Vec::new();
+        compress(&new_bin, &mut file).unwrap();
+        assert!(file.len() * 5 <= old_file.len(), "{} bytes, and {} before", file.len(), old_file.len());
+        assert!(file.len() < 48 * 1024, "{} bytes", file.len());
+        assert!(!decompress(&file, &mut bin).unwrap());
+        assert!(bin == new_bin);
+
+        assert!(matches!(decompress(&file[..4], &mut bin), Err(ReadError::IOErr(_))));
+        assert!(matches!(decompress(&file[..12], &mut bin), Err(ReadError::IOErr(_))));
+        assert!(matches!(decompress(&file[..30], &mut bin), Err(ReadError::DecompressErr(_))));
+        assert!(matches!(decompress(&old_file[..30], &mut bin), Err(ReadError::DecompressErr(_))));
+        let mut other_version = file.clone();
+        other_version[7] = b'2';
+        assert!(matches!(decompress(&other_version, &mut bin), Err(ReadError::DecompressErr(_))));
+    }
+
+    #[test]
+    fn captured_files() {
+        let mut game = testing::synthetic_game();
+        let temp = PathBuf::from(game.decode_str(game.temp_directory.as_ref()).into_owned());
+        let mut project = std::env::temp_dir();
+        project.push(format!("opengmk_savestate_files_{}", std::process::id()));
+        game.file_store = Some(FileStore::new(&project, vec![temp.clone()], Vec::new()).unwrap());
+
+        let write = |n| format!("ini_open(temp_directory + '/save.ini'); ini_write_real('a', 'b', {}); ini_close()", n);
+        run(&mut game, &write(1));
+        let renderer_state = game.renderer.state();
+        let state = SaveState::from(&mut game, Replay::new(0, 0), renderer_state);
+        run(&mut game, &write(2));
+        run(&mut game, "file_text_close(file_text_open_write(temp_directory + '/later.txt'))");
+
+        // the game reads back what was there when the savestate was made, and the newer file is gone
+        state.load_into(&mut game);
+        run(&mut game, "ini_open(temp_directory + '/save.ini'); score = ini_read_real('a', 'b', 0); ini_close()");
+        assert_eq!(game.score, 1);
+        assert!(!temp.join("later.txt").exists());
+
+        fs::remove_dir_all(&project).unwrap();
+        fs::remove_dir_all(&temp).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+//! The temp folder GM8 makes for each run of a game, which `temp_directory` points to.
+//! It's named `gm_ttt_` followed by a random number up to 99999, and it's deleted when the game ends.
+
+use crate::gml::rand::{Random, Site};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// What every temp folder's name starts with. Games sometimes look for this to find their own temp folder.
+pub const PREFIX: &str = "gm_ttt_";
+
+/// Picks a name for a temp folder the same way GM8 does, using up one number from the given RNG.
+pub fn folder_name(rand: &mut Random) -> String {
+    format!("{}{}", PREFIX, rand.next_int(99999, Site::TempFolder))
+}
+
+/// Makes a new temp folder in the given directory, picking names until one isn't taken, and returns its path.
+pub fn create_in(dir: &Path, rand: &mut Random) -> io::Result<PathBuf> {
+    let mut path = dir.join(folder_name(rand));
+    while path.exists() {
+        path = dir.join(folder_name(rand));
+    }
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+/// Finds the temp folder kept in a record mode project, or makes one if there isn't one yet.
+/// It stays between runs, so savestates and replays can rely on it being in the same place.
+pub fn find_or_create_in_project(project: &Path) -> io::Result<PathBuf> {
+    let existing = fs::read_dir(project)?.filter_map(|x| x.ok()).find(|entry| {
+        entry.metadata().map(|x| x.is_dir()).unwrap_or(false)
+            && entry.file_name().to_str().map(|x| x.starts_with(PREFIX)).unwrap_or(false)
+    });
+    match existing {
+        Some(entry) => Ok(entry.path()),
+        None => create_in(project, &mut Random::new()),
+    }
+}
+
+/// Deletes the temp folder and any included files marked to be removed at the end when it's dropped.
+/// This happens when unwinding from a panic too, so a crashing game doesn't leave its temp folder behind.
+#[derive(Default)]
+pub struct Cleanup {
+    pub dir: Option<PathBuf>,
+    pub files: Vec<PathBuf>,
+}
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        for file in &self.files {
+            fs::remove_file(file).ok();
+        }
+        if let Some(dir) = &self.dir {
+            fs::remove_dir_all(dir).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_in, find_or_create_in_project, Cleanup, PREFIX};
+    use crate::gml::rand::Random;
+    use std::{fs, panic};
+
+    #[test]
+    fn names() {
+        let base = std::env::temp_dir().join(format!("opengmk_tempdir_{}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        let paths = [0, 1, -1, 12345678]
+            .iter()
+            .map(|&seed| create_in(&base, &mut Random::with_seed(seed)).unwrap())
+            .collect::<Vec<_>>();
+        for path in &paths {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let number = name.strip_prefix(PREFIX).unwrap();
+            assert!(number.bytes().all(|x| x.is_ascii_digit()), "{}", name);
+            assert_eq!(number.parse::<u32>().unwrap().min(99999).to_string(), number);
+            assert!(path.is_dir());
+        }
+
+        // the same seed picks another name when the first is taken, and a project's folder is found again
+        let again = create_in(&base, &mut Random::with_seed(0)).unwrap();
+        assert_ne!(again, paths[0]);
+        let project = base.join("project");
+        fs::create_dir_all(&project).unwrap();
+        let made = find_or_create_in_project(&project).unwrap();
+        assert_eq!(find_or_create_in_project(&project).unwrap(), made);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn cleanup_after_panic() {
+        let dir = create_in(&std::env::temp_dir(), &mut Random::new()).unwrap();
+        let file = std::env::temp_dir().join(format!("opengmk_included_{}.txt", std::process::id()));
+        fs::write(dir.join("save.ini"), b"[a]\nb=1\n").unwrap();
+        fs::write(&file, b"included").unwrap();
+        let cleanup = Cleanup { dir: Some(dir.clone()), files: vec![file.clone()] };
+        let result = panic::catch_unwind(move || {
+            let _cleanup = cleanup;
+            panic!("the game crashed");
+        });
+        assert!(result.is_err());
+        assert!(!dir.exists());
+        assert!(!file.exists());
+    }
+}
@@ -0,0 +1,108 @@
+//! Helpers for tests which need a whole game running, or assets to run one with.
+
+use crate::{
+    action::Tree,
+    asset::{sprite::Collider, Object, Sprite},
+    game::{error::LaunchError, Game, PlayType},
+    gml::Context,
+    instance::Instance,
+    types::ID,
+};
+use gm8exe::{
+    reader::{self, ReaderOptions},
+    GameAssets,
+};
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+/// Reads `conformance::synthetic`'s game, for tests which need to change something before launching it.
+pub fn synthetic_assets() -> GameAssets {
+    reader::from_gamedata(conformance::synthetic::gamedata(), None::<fn(&str)>, ReaderOptions::default())
+        .expect("couldn't read the synthetic game")
+}
+
+/// Launches a game headless, as if it were the synthetic game's exe in the current directory.
+pub fn launch(assets: GameAssets) -> Result<Game, LaunchError> {
+    // launching changes to the game's directory, so keep that the same as it already is
+    let path = std::env::current_dir().unwrap().join("synthetic.exe");
+    Game::launch(assets, path, Vec::new(), None, encoding_rs::WINDOWS_1252, false, PlayType::Normal, true, false)
+}
+
+/// Launches `conformance::synthetic`'s game headless and loads its only room, which has one obj_counter in it.
+/// obj_counter doesn't have any events, so tests can give it whichever ones they need.
+pub fn synthetic_game() -> Game {
+    let mut game = launch(synthetic_assets()).expect("couldn't launch the synthetic game");
+    game.init().expect("couldn't start the synthetic game");
+    game
+}
+
+/// Adds an object with no sprite or events to the end of the game's objects, and returns its index.
+/// Its parent is `parent`, or none if that's -1.
+pub fn add_object(game: &mut Game, name: &str, parent: ID) -> ID {
+    let id = game.assets.objects.len() as ID;
+    let mut parents = HashSet::new();
+    parents.insert(id);
+    if let Some(Some(parent)) = game.assets.objects.get(parent as usize) {
+        parents.extend(parent.parents.borrow().iter().copied());
+    }
+    for &ancestor in &parents {
+        if let Some(Some(ancestor)) = game.assets.objects.get(ancestor as usize) {
+            ancestor.children.borrow_mut().insert(id);
+        }
+    }
+    game.assets.objects.push(Some(Box::new(Object {
+        name: name.into(),
+        solid: false,
+        visible: true,
+        persistent: false,
+        depth: 0,
+        sprite_index: -1,
+        mask_index: -1,
+        parent_index: parent,
+        events: Default::default(),
+        children: Rc::new(RefCell::new([id].iter().copied().collect())),
+        parents: Rc::new(RefCell::new(parents)),
+    })));
+    game.refresh_event_holders();
+    id
+}
+
+/// Gives an object some code to run for one of its events.
+pub fn set_event(game: &mut Game, object: ID, event: usize, sub: u32, code: &str) {
+    let code = game.compiler.compile(code.as_bytes()).unwrap();
+    game.assets.objects[object as usize].as_mut().unwrap().events[event].insert(sub, Tree::new_from_code(code));
+    game.refresh_event_holders();
+}
+
+/// Runs some GML in the game, as if from the library initialization code.
+pub fn run(game: &mut Game, code: &str) {
+    let code = game.compiler.compile(code.as_bytes()).unwrap();
+    let dummy = game.room.instance_list.insert_dummy(Instance::new_dummy(None));
+    game.execute(&code, &mut Context::with_single_instance(dummy)).unwrap();
+    game.room.instance_list.remove_dummy(dummy);
+}
+
+/// A 16x16 sprite with no frames, a full bbox and collision mask, and its origin in the top-left.
+pub fn sprite() -> Sprite {
+    Sprite {
+        name: "spr_test".into(),
+        frames: Vec::new(),
+        colliders: vec![Collider {
+            width: 16,
+            height: 16,
+            bbox_left: 0,
+            bbox_right: 15,
+            bbox_top: 0,
+            bbox_bottom: 15,
+            data: vec![true; 16 * 16].into_boxed_slice(),
+        }],
+        width: 16,
+        height: 16,
+        origin_x: 0,
+        origin_y: 0,
+        per_frame_colliders: false,
+        bbox_left: 0,
+        bbox_right: 15,
+        bbox_top: 0,
+        bbox_bottom: 15,
+    }
+}
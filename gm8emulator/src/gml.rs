@@ -9,6 +9,7 @@ pub mod network;
 pub mod rand;
 pub mod runtime;
 pub mod string;
+pub mod unimplemented;
 pub mod value;
 
 pub use compiler::Compiler;
@@ -4,12 +4,16 @@ use super::{
         ArrayAccessor, BinaryOperator, FieldAccessor, InstanceIdentifier, Instruction, Node, ReturnType, UnaryOperator,
         VariableAccessor,
     },
-    Value,
+    InstanceVariable, Value,
 };
 use crate::{gml, math::Real};
 use gml_parser::{ast, token::Operator};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, rc::Rc, str};
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    str,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Compiler {
@@ -19,6 +23,9 @@ pub struct Compiler {
     /// Table of user-defined constants to IDs
     user_constant_names: HashMap<Box<[u8]>, usize>,
 
+    /// Names registered by register_runtime_asset, which go away again when the asset is deleted
+    runtime_assets: HashSet<Box<[u8]>>,
+
     /// Table of script names to IDs
     script_names: HashMap<Box<[u8]>, usize>,
 
@@ -27,17 +34,48 @@ pub struct Compiler {
 
     /// Lookup table of unique field names
     fields: Vec<Box<[u8]>>,
+
+    /// Whether the names GM 8.1 added (see mappings::GM81_ONLY) are available
+    gm81: bool,
+}
+
+// A Compiler as savestates stored it before runtime assets were tracked and 8.1's names were gated
+#[derive(Deserialize)]
+pub struct CompilerV1 {
+    constants: HashMap<Box<[u8]>, Value>,
+    user_constant_names: HashMap<Box<[u8]>, usize>,
+    script_names: HashMap<Box<[u8]>, usize>,
+    extension_fn_names: HashMap<Box<[u8]>, usize>,
+    fields: Vec<Box<[u8]>>,
+}
+
+impl CompilerV1 {
+    /// Converts this into a Compiler for a game made in GM 8.1 if `gm81` is true, or GM 8.0 if not.
+    pub fn upgrade(self, gm81: bool) -> Compiler {
+        let Self { constants, user_constant_names, script_names, extension_fn_names, fields } = self;
+        Compiler {
+            constants,
+            user_constant_names,
+            runtime_assets: HashSet::new(),
+            script_names,
+            extension_fn_names,
+            fields,
+            gm81,
+        }
+    }
 }
 
 impl Compiler {
-    /// Create a compiler.
-    pub fn new() -> Self {
+    /// Create a compiler. If `gm81` is false, the builtins which only exist in GM 8.1 aren't recognised.
+    pub fn new(gm81: bool) -> Self {
         Self {
             constants: HashMap::new(),
             user_constant_names: HashMap::new(),
+            runtime_assets: HashSet::new(),
             script_names: HashMap::new(),
             extension_fn_names: HashMap::new(),
             fields: Vec::new(),
+            gm81,
         }
     }
 
@@ -68,6 +106,26 @@ impl Compiler {
         self.constants.entry(name).or_insert(Value::Real(value.into()));
     }
 
+    /// Names an asset created at runtime (such as by sprite_add) and registers the name like an asset from the game,
+    /// so that code compiled afterwards, for example with execute_string, can refer to it. As with any other asset,
+    /// a name that's already taken keeps its old meaning.
+    pub fn register_runtime_asset(&mut self, prefix: &str, index: usize) -> gml::String {
+        let name = format!("{}{}", prefix, index);
+        if !self.constants.contains_key(name.as_bytes()) {
+            self.register_constant(name.as_bytes().into(), index as f64);
+            self.runtime_assets.insert(name.as_bytes().into());
+        }
+        name.into()
+    }
+
+    /// Forgets the name of a deleted asset if it was registered by register_runtime_asset, so code compiled
+    /// afterwards doesn't refer to it. Names of assets from the game are left alone, as they are in GM8.
+    pub fn unregister_runtime_asset(&mut self, name: &[u8]) {
+        if self.runtime_assets.remove(name) {
+            self.constants.remove(name);
+        }
+    }
+
     /// Register a script name and its index. Duplicate script names are ignored.
     pub fn register_script(&mut self, name: Box<[u8]>, index: usize) {
         self.script_names.entry(name).or_insert(index);
@@ -278,7 +336,7 @@ impl Compiler {
                     Node::Literal { value: entry.clone() }
                 } else if let Some(constant_id) = self.user_constant_names.get(*string) {
                     Node::Constant { constant_id: *constant_id }
-                } else if let Some(&v) = str::from_utf8(string).ok().and_then(|n| mappings::CONSTANTS.get(n)) {
+                } else if let Some(&v) = self.builtin_name(string).and_then(|n| mappings::CONSTANTS.get(n)) {
                     Node::Literal { value: Value::Real(Real::from(v)) }
                 } else {
                     self.identifier_to_variable(string, None, ArrayAccessor::None, locals)
@@ -384,10 +442,20 @@ impl Compiler {
                     Node::Script { args, script_id }
                 } else if let Some(id) = self.extension_fn_names.get(function.name).copied() {
                     Node::ExtensionFunction { args, id }
-                } else if let Some(function_id) =
-                    str::from_utf8(function.name).ok().and_then(|n| mappings::FUNCTIONS.get_index(n))
+                } else if let Some((name, function_id)) =
+                    self.builtin_name(function.name).and_then(|n| mappings::FUNCTIONS.get_index(n).map(|id| (n, id)))
                 {
-                    Node::Function { args, function_id }
+                    // GM8 refuses to compile calls with the wrong number of arguments, so they never get as far
+                    // as the function itself
+                    match mappings::ARGUMENT_COUNTS.get(name) {
+                        Some(&(min, _)) if args.len() < min => {
+                            Node::RuntimeError { error: gml::Error::WrongArgumentCount(min, args.len()) }
+                        },
+                        Some(&(_, max)) if args.len() > max => {
+                            Node::RuntimeError { error: gml::Error::WrongArgumentCount(max, args.len()) }
+                        },
+                        _ => Node::Function { args, function_id },
+                    }
                 } else {
                     Node::RuntimeError {
                         error: gml::Error::UnknownFunction(String::from_utf8_lossy(function.name).into()),
@@ -417,6 +485,16 @@ impl Compiler {
         }
     }
 
+    /// Gets an identifier as the name of a builtin, unless it's one which doesn't exist in this GM version.
+    fn builtin_name<'a>(&self, name: &'a [u8]) -> Option<&'a str> {
+        str::from_utf8(name).ok().filter(|n| self.gm81 || !mappings::GM81_ONLY.contains(*n))
+    }
+
+    /// Looks up a builtin instance variable, unless it doesn't exist in this GM version.
+    pub fn builtin_variable(&self, name: &[u8]) -> Option<InstanceVariable> {
+        self.builtin_name(name).and_then(|n| mappings::get_instance_variable_by_name(n.as_bytes())).copied()
+    }
+
     /// Searches for the fieldname id.
     pub fn find_field_id(&self, name: &[u8]) -> Option<usize> {
         self.fields.iter().position(|x| x.as_ref() == name)
@@ -533,8 +611,8 @@ impl Compiler {
             },
         };
 
-        if let Some(var) = mappings::get_instance_variable_by_name(identifier) {
-            Node::Variable { accessor: VariableAccessor { var: *var, array, owner } }
+        if let Some(var) = self.builtin_variable(identifier) {
+            Node::Variable { accessor: VariableAccessor { var, array, owner } }
         } else {
             let index = self.get_field_id(identifier);
             Node::Field { accessor: FieldAccessor { index, array, owner } }
@@ -562,8 +640,8 @@ impl Compiler {
             },
         };
 
-        if let Some(var) = mappings::get_instance_variable_by_name(identifier) {
-            Instruction::SetVariable { accessor: VariableAccessor { var: *var, array, owner }, value }
+        if let Some(var) = self.builtin_variable(identifier) {
+            Instruction::SetVariable { accessor: VariableAccessor { var, array, owner }, value }
         } else {
             let index = self.get_field_id(identifier);
             Instruction::SetField { accessor: FieldAccessor { index, array, owner }, value }
@@ -592,11 +670,11 @@ impl Compiler {
             },
         };
 
-        if let Some(var) = mappings::get_instance_variable_by_name(identifier) {
+        if let Some(var) = self.builtin_variable(identifier) {
             Instruction::SetVariable {
-                accessor: VariableAccessor { var: *var, array: array.clone(), owner: owner.clone() },
+                accessor: VariableAccessor { var, array: array.clone(), owner: owner.clone() },
                 value: Node::Binary {
-                    left: Box::new(Node::Variable { accessor: VariableAccessor { var: *var, array, owner } }),
+                    left: Box::new(Node::Variable { accessor: VariableAccessor { var, array, owner } }),
                     right: Box::new(value),
                     operator,
                     type_unsafe: false,
@@ -650,3 +728,139 @@ impl Compiler {
         self.fields.get(id).map(|s| String::from_utf8_lossy(s).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    // Compiles `a = <expr>` and returns the node being assigned
+    fn assigned(compiler: &mut Compiler, expr: &str) -> Node {
+        let code = compiler.compile(format!("a = {}", expr).as_bytes()).unwrap();
+        match &code[..] {
+            [Instruction::SetField { value, .. }] => value.clone(),
+            _ => panic!("unexpected instructions for {}", expr),
+        }
+    }
+
+    #[test]
+    fn gm81_only_builtins() {
+        let mut gm80 = Compiler::new(false);
+        let mut gm81 = Compiler::new(true);
+
+        let unknown = assigned(&mut gm80, "YoYo_GetPlatform()");
+        assert!(matches!(unknown, Node::RuntimeError { error: gml::Error::UnknownFunction(_) }));
+        assert!(matches!(assigned(&mut gm81, "YoYo_GetPlatform()"), Node::Function { .. }));
+
+        // in 8.0 these are just ordinary fields
+        assert!(matches!(assigned(&mut gm80, "os_browser"), Node::Field { .. }));
+        assert!(matches!(assigned(&mut gm81, "os_browser"), Node::Variable { .. }));
+        assert!(matches!(assigned(&mut gm80, "browser_not_a_browser"), Node::Field { .. }));
+        match assigned(&mut gm81, "browser_not_a_browser") {
+            Node::Literal { value } => assert_eq!(f64::from(value), mappings::constants::BROWSER_NOT_A_BROWSER),
+            _ => panic!("browser_not_a_browser isn't a constant in 8.1"),
+        }
+
+        // everything else is the same in both versions
+        assert!(matches!(assigned(&mut gm80, "instance_number(0)"), Node::Function { .. }));
+        assert!(matches!(assigned(&mut gm80, "c_red"), Node::Literal { .. }));
+    }
+
+    #[test]
+    fn runtime_asset_names() {
+        let mut compiler = Compiler::new(false);
+        compiler.register_constant(b"spr_player".to_vec().into(), 0.0);
+        // an object from the game which happens to have the name sprite_add would give sprite 2
+        compiler.register_constant(b"__newsprite2".to_vec().into(), 7.0);
+
+        let name = compiler.register_runtime_asset("__newsprite", 1);
+        assert_eq!(name.as_ref(), b"__newsprite1");
+        let value_of = |compiler: &mut Compiler, name| match assigned(compiler, name) {
+            Node::Literal { value } => f64::from(value),
+            _ => panic!("{} isn't a constant", name),
+        };
+        assert_eq!(value_of(&mut compiler, "__newsprite1"), 1.0);
+        assert_eq!(compiler.register_runtime_asset("__newsprite", 2).as_ref(), b"__newsprite2");
+        assert_eq!(value_of(&mut compiler, "__newsprite2"), 7.0);
+        assert_eq!(value_of(&mut compiler, "spr_player"), 0.0);
+        assert!(matches!(assigned(&mut compiler, "__newsprite3"), Node::Field { .. }));
+
+        // deleting a runtime asset forgets its name, but not the name of one from the game which it clashed with
+        compiler.unregister_runtime_asset(b"__newsprite1");
+        compiler.unregister_runtime_asset(b"__newsprite2");
+        compiler.unregister_runtime_asset(b"spr_player");
+        assert!(matches!(assigned(&mut compiler, "__newsprite1"), Node::Field { .. }));
+        assert_eq!(value_of(&mut compiler, "__newsprite2"), 7.0);
+        assert_eq!(value_of(&mut compiler, "spr_player"), 0.0);
+    }
+
+    #[test]
+    fn gm81_stub_values() {
+        assert_eq!(f64::from(Game::yoyo_getplatform(&[]).unwrap()), mappings::constants::OS_WIN32);
+        assert_eq!(f64::from(Game::yoyo_getdevice(&[]).unwrap()), mappings::constants::DEVICE_IOS_IPHONE);
+        assert_eq!(gml::String::from(Game::yoyo_getdomain(&[]).unwrap()).as_ref(), b"unknown");
+        assert_eq!(f64::from(Game::yoyo_gettimer(&[]).unwrap()), 0.0);
+        assert_eq!(f64::from(Game::yoyo_openurl(&["http://example.com".into()]).unwrap()), 0.0);
+    }
+
+    #[test]
+    fn builtin_registry() {
+        // phf won't build a map with the same name in it twice, but nothing stops it being in two of them
+        let is_variable = |name| mappings::get_instance_variable_by_name(name).is_some();
+        for (name, _) in mappings::FUNCTIONS.entries() {
+            assert!(!mappings::CONSTANTS.contains_key(name) && !is_variable(name.as_bytes()), "{} is ambiguous", name);
+        }
+        for (i, (name, _)) in mappings::INSTANCE_VARIABLES.iter().enumerate() {
+            assert!(!mappings::CONSTANTS.contains_key(name), "{} is ambiguous", name);
+            assert!(mappings::INSTANCE_VARIABLES[..i].iter().all(|(x, _)| x != name), "{} is listed twice", name);
+        }
+
+        for name in mappings::GM81_ONLY.iter() {
+            assert!(
+                mappings::FUNCTIONS.contains_key(name)
+                    || mappings::CONSTANTS.contains_key(name)
+                    || is_variable(name.as_bytes()),
+                "{} is 8.1-only but doesn't exist",
+                name
+            );
+        }
+
+        for (name, &(min, max)) in mappings::ARGUMENT_COUNTS.entries() {
+            assert!(mappings::FUNCTIONS.contains_key(name), "{} has argument counts but isn't a function", name);
+            assert!(min <= max && max <= 16, "{} has bad argument counts", name);
+        }
+    }
+
+    #[test]
+    fn wrong_argument_counts() {
+        let mut gm80 = Compiler::new(false);
+        let mut gm81 = Compiler::new(true);
+
+        let wrong_count = |node| match node {
+            Node::RuntimeError { error: gml::Error::WrongArgumentCount(expected, got) } => Some((expected, got)),
+            _ => None,
+        };
+        assert_eq!(wrong_count(assigned(&mut gm80, "string_length()")), Some((1, 0)));
+        assert_eq!(wrong_count(assigned(&mut gm80, "string_copy(\"abc\", 1, 2, 3)")), Some((3, 4)));
+        assert_eq!(wrong_count(assigned(&mut gm80, "ds_list_add(0)")), Some((2, 1)));
+        assert!(matches!(assigned(&mut gm80, "string_copy(\"abc\", 1, 2)"), Node::Function { .. }));
+
+        // variadic
+        assert!(matches!(assigned(&mut gm80, "max()"), Node::Function { .. }));
+        assert!(matches!(assigned(&mut gm80, "choose(1, 2, 3, 4, 5)"), Node::Function { .. }));
+
+        // functions which aren't listed yet are left to check for themselves
+        assert!(!mappings::ARGUMENT_COUNTS.contains_key("instance_number"));
+        assert!(matches!(assigned(&mut gm80, "instance_number()"), Node::Function { .. }));
+
+        // and a function that doesn't exist in this version doesn't have any number of arguments
+        assert!(matches!(assigned(&mut gm80, "YoYo_GetPlatform(1)"), Node::RuntimeError {
+            error: gml::Error::UnknownFunction(_)
+        }));
+        assert!(matches!(assigned(&mut gm81, "YoYo_GetPlatform()"), Node::Function { .. }));
+
+        let error = gml::Error::WrongArgumentCount(1, 0);
+        assert_eq!(error.to_string(), "Wrong number of arguments to function or script.");
+        assert_eq!(format!("{:?}", error), "WrongArgumentCount(1, 0)");
+    }
+}
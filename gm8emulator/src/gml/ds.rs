@@ -1,4 +1,10 @@
-use crate::{gml::Value, math::Real};
+use crate::{
+    gml::{
+        rand::{Random, Site},
+        Value,
+    },
+    math::Real,
+};
 use serde::{Deserialize, Serialize};
 use std::{cmp::Ordering, collections, convert::TryInto};
 
@@ -258,6 +264,15 @@ impl Grid {
     }
 }
 
+/// Shuffles a list like ds_list_shuffle: one swap fewer than there are items, each between two indices from the RNG.
+pub fn shuffle(list: &mut [Value], rand: &mut Random) {
+    for _ in 1..list.len() {
+        let id1 = rand.next_int(list.len() as u32 - 1, Site::ListShuffle);
+        let id2 = rand.next_int(list.len() as u32 - 1, Site::ListShuffle);
+        list.swap(id1 as usize, id2 as usize);
+    }
+}
+
 pub fn eq(v1: &Value, v2: &Value, precision: Real) -> bool {
     match (v1, v2) {
         (Value::Real(x), Value::Real(y)) => (*x - *y).abs() <= precision,
@@ -280,3 +295,76 @@ pub fn cmp(v1: &Value, v2: &Value, precision: Real) -> Ordering {
         (Value::Str(_), Value::Real(_)) => Ordering::Greater,
     }
 }
+
+/// The order ds_list_sort puts values in, which is the same as `cmp` except strings are compared ignoring case.
+/// Letters are compared as uppercase, so _ and the other symbols between Z and a come after all of them.
+pub fn sort_cmp(v1: &Value, v2: &Value, precision: Real) -> Ordering {
+    match (v1, v2) {
+        (Value::Str(x), Value::Str(y)) => {
+            let upper = |s: &[u8]| s.iter().map(u8::to_ascii_uppercase).collect::<Vec<_>>();
+            upper(x.as_ref()).cmp(&upper(y.as_ref()))
+        },
+        _ => cmp(v1, v2, precision),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text<'a>(values: impl IntoIterator<Item = &'a Value>) -> Vec<String> {
+        values.into_iter().map(|x| x.to_string()).collect()
+    }
+
+    fn draws(rand: &mut Random) -> Vec<String> {
+        let log = rand.take_log().unwrap().to_text();
+        log.lines().map(|x| x.split(' ').nth(1).unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn shuffle_rng_usage() {
+        let mut list: List = (0..5).map(Value::from).collect();
+        let mut rand = Random::with_seed(1);
+        rand.start_log();
+        shuffle(&mut list, &mut rand);
+        assert_eq!(draws(&mut rand), vec!["ListShuffle"; 8]);
+        assert_eq!(text(&list), ["3", "4", "2", "1", "0"]);
+        assert_eq!(rand.seed(), 1598751577);
+
+        // nothing is drawn for a list with one item
+        let seed = rand.seed();
+        shuffle(&mut list[..1], &mut rand);
+        assert_eq!(rand.seed(), seed);
+    }
+
+    #[test]
+    fn sort_order() {
+        let mut list: List = vec!["b".into(), 2.into(), "B".into(), "_a".into(), 1.into(), "a".into(), "".into()];
+        list.sort_by(|x, y| sort_cmp(x, y, Real::from(0.0)));
+        assert_eq!(text(&list), ["1", "2", "\"\"", "\"a\"", "\"b\"", "\"B\"", "\"_a\""]);
+        list.sort_by(|x, y| sort_cmp(y, x, Real::from(0.0)));
+        assert_eq!(text(&list), ["\"_a\"", "\"b\"", "\"B\"", "\"a\"", "\"\"", "2", "1"]);
+
+        // reals within the precision of each other are equal, so they keep their order too
+        let mut list: List = vec![1.00001.into(), 1.into(), 0.5.into()];
+        list.sort_by(|x, y| sort_cmp(x, y, Real::from(0.001)));
+        assert_eq!(text(&list), ["0.5", "1.00001", "1"]);
+    }
+
+    #[test]
+    fn shuffled_savestate() {
+        let mut list: List = (0..10).map(Value::from).collect();
+        let mut rand = Random::with_seed(-12345);
+        shuffle(&mut list, &mut rand);
+
+        let bytes = bincode::serialize(&(&list, &rand)).unwrap();
+        let (mut loaded_list, mut loaded_rand): (List, Random) = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(text(&loaded_list), text(&list));
+
+        // shuffling again after loading does exactly the same as shuffling again without
+        shuffle(&mut list, &mut rand);
+        shuffle(&mut loaded_list, &mut loaded_rand);
+        assert_eq!(text(&loaded_list), text(&list));
+        assert!(loaded_rand == rand);
+    }
+}
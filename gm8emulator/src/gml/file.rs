@@ -1,4 +1,5 @@
-use image::{codecs::gif::GifDecoder, AnimationDecoder, ImageError, ImageFormat, Pixel, RgbaImage};
+use crate::math::Real;
+use image::{ImageError, ImageFormat, Pixel, Rgba, RgbaImage};
 use std::{
     fs::{File, OpenOptions},
     io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
@@ -48,6 +49,15 @@ impl From<ImageError> for Error {
     }
 }
 
+impl From<gif::DecodingError> for Error {
+    fn from(e: gif::DecodingError) -> Self {
+        match e {
+            gif::DecodingError::Io(e) => Self::IOError(e),
+            e => Self::IOError(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -149,7 +159,8 @@ impl TextHandle {
     }
 
     pub fn write_real(&mut self, real: f64) -> Result<()> {
-        let text = if real.fract() == 0.0 { format!(" {:.0}", real) } else { format!(" {:.6}", real) };
+        let decimals = if real.fract() == 0.0 { 0 } else { 6 };
+        let text = format!(" {}", Real::from(real).to_fixed(decimals));
         self.get_writer()?.write_all(text.as_bytes())?;
         Ok(())
     }
@@ -407,10 +418,7 @@ pub fn load_image(path: &str) -> Result<RgbaImage> {
 
 pub fn load_animation(path: &str, imgnumb: usize) -> Result<Vec<RgbaImage>> {
     if ImageFormat::from_path(path)? == ImageFormat::Gif {
-        GifDecoder::new(BufReader::new(File::open(path)?))?
-            .into_frames()
-            .map(|r| r.map(|f| f.into_buffer()).map_err(Error::from))
-            .collect()
+        load_gif(path)
     } else {
         let image = load_image(path.as_ref())?;
         let sprite_width = image.width() as usize / imgnumb;
@@ -434,6 +442,45 @@ pub fn load_animation(path: &str, imgnumb: usize) -> Result<Vec<RgbaImage>> {
     }
 }
 
+/// Loads every frame of a GIF the way GM8 does, which isn't how a GIF is meant to be displayed.
+/// Disposal methods are ignored: the canvas starts out filled with the background colour, as found in the first
+/// frame's palette, and each frame is drawn over the one before. Transparent pixels are never actually transparent.
+fn load_gif(path: &str) -> Result<Vec<RgbaImage>> {
+    fn colour(palette: &[u8], index: usize) -> Rgba<u8> {
+        match palette.get(index * 3..index * 3 + 3) {
+            Some(rgb) => Rgba([rgb[0], rgb[1], rgb[2], 255]),
+            None => Rgba([0, 0, 0, 255]),
+        }
+    }
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(BufReader::new(File::open(path)?))?;
+    let (width, height) = (u32::from(decoder.width()), u32::from(decoder.height()));
+    let global_palette = decoder.global_palette().map(<[u8]>::to_vec);
+    let bg_index = decoder.bg_color().unwrap_or(0);
+
+    let mut canvas: Option<RgbaImage> = None;
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        let palette = frame.palette.as_deref().or_else(|| global_palette.as_deref()).unwrap_or(&[]);
+        let canvas = canvas.get_or_insert_with(|| RgbaImage::from_pixel(width, height, colour(palette, bg_index)));
+        let frame_width = usize::from(frame.width).max(1);
+        for (i, &index) in frame.buffer.iter().enumerate() {
+            let x = u32::from(frame.left) + (i % frame_width) as u32;
+            let y = u32::from(frame.top) + (i / frame_width) as u32;
+            if Some(index) != frame.transparent && x < width && y < height {
+                canvas.put_pixel(x, y, colour(palette, index.into()));
+            }
+        }
+        frames.push(canvas.clone());
+    }
+    if frames.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "gif has no frames").into())
+    }
+    Ok(frames)
+}
+
 pub fn save_image<P: AsRef<Path>>(path: P, image: RgbaImage) -> Result<()> {
     // save to png if the filename is .png otherwise bmp regardless of filename
     if path.as_ref().extension().and_then(|s| s.to_str()).map(|s| s.eq_ignore_ascii_case("png")).unwrap_or(false) {
@@ -443,3 +490,73 @@ pub fn save_image<P: AsRef<Path>>(path: P, image: RgbaImage) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(format!("opengmk_file_{}_{}", std::process::id(), name));
+        path.into_os_string().into_string().unwrap()
+    }
+
+    #[test]
+    fn strip_frames() {
+        // 7 pixels wide split 3 ways gives 2-pixel frames, and the last column is dropped
+        let path = temp_path("strip.png");
+        let strip = RgbaImage::from_fn(7, 2, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        strip.save(&path).unwrap();
+        let frames = load_animation(&path, 3).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.dimensions(), (2, 2));
+            assert_eq!(frame.get_pixel(0, 0), &Rgba([i as u8 * 2, 0, 0, 255]));
+            assert_eq!(frame.get_pixel(1, 1), &Rgba([i as u8 * 2 + 1, 1, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn gif_frames() {
+        use std::borrow::Cow;
+
+        // background (index 0) is blue, then red, green, and white
+        let palette = [0, 0, 255, 255, 0, 0, 0, 255, 0, 255, 255, 255];
+        let path = temp_path("anim.gif");
+        {
+            let mut encoder = gif::Encoder::new(File::create(&path).unwrap(), 3, 2, &palette).unwrap();
+            // red square in the corner, with one transparent pixel
+            encoder
+                .write_frame(&gif::Frame {
+                    width: 2,
+                    height: 2,
+                    buffer: Cow::Borrowed(&[1, 1, 3, 1]),
+                    transparent: Some(3),
+                    dispose: gif::DisposalMethod::Background,
+                    ..Default::default()
+                })
+                .unwrap();
+            // one green pixel, which should be drawn over the first frame even though it asked to be cleared
+            encoder
+                .write_frame(&gif::Frame {
+                    left: 1,
+                    top: 1,
+                    width: 1,
+                    height: 1,
+                    buffer: Cow::Borrowed(&[2]),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let frames = load_animation(&path, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (blue, red, green) = (Rgba([0, 0, 255, 255]), Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255]));
+        let pixels = |image: &RgbaImage| image.pixels().copied().collect::<Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(pixels(&frames[0]), [red, red, blue, blue, red, blue]);
+        assert_eq!(pixels(&frames[1]), [red, red, blue, blue, green, blue]);
+    }
+}
@@ -5,19 +5,21 @@
 use crate::{
     action, asset,
     game::{
-        draw, external, gm_save::GMSave, model, particle, pathfinding, replay, surface::Surface,
-        transition::UserTransition, view::View, Game, GetAsset, PlayType, SceneChange, Version,
+        draw, external, model, movement, particle, pathfinding, replay, surface::Surface,
+        transition::UserTransition, view::View, window_size, Game, GetAsset, PlayType, SceneChange, Version,
     },
     gml::{
         self,
         datetime::{self, DateTime},
         ds, file,
         mappings::{self, constants as gml_consts},
-        network, Context, Value,
+        network,
+        rand::Site,
+        Context, Value,
     },
     handleman::HandleManager,
     input::MouseButton,
-    instance::{Field, Instance, InstanceState},
+    instance::{Field, Instance, InstanceState, DEFAULT_ALARM},
     math::Real,
     render::{BlendType, Fog, Light, Renderer, Scaling},
     tile::Tile,
@@ -61,6 +63,18 @@ macro_rules! expect_args {
     ($args: expr, [$($x: ident,)*]) => { expect_args!($args, $($x),*) };
 }
 
+/// Body for a GML function which hasn't been implemented yet.
+/// This panics, unless calls to unimplemented functions are being counted, in which case it returns 0.
+macro_rules! unimplemented_function {
+    ($name: literal) => {{
+        if gml::unimplemented::record($name) {
+            Ok(Default::default())
+        } else {
+            unimplemented!(concat!("Called unimplemented kernel function ", $name))
+        }
+    }};
+}
+
 #[rustfmt::skip]
 fn rgb_to_hsv(colour: i32) -> (i32, i32, i32) {
     let (r, g, b) = (Real::from(0xFF & colour), Real::from(0xFF & (colour >> 8)), Real::from(0xFF & (colour >> 16)));
@@ -98,71 +112,73 @@ fn rgb_to_hsv(colour: i32) -> (i32, i32, i32) {
 impl Game {
     pub fn display_get_width(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_get_width")
+        unimplemented_function!("display_get_width")
     }
 
     pub fn display_get_height(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_get_height")
+        unimplemented_function!("display_get_height")
     }
 
     pub fn display_get_colordepth(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_get_colordepth")
+        unimplemented_function!("display_get_colordepth")
     }
 
     pub fn display_get_frequency(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_get_frequency")
+        unimplemented_function!("display_get_frequency")
     }
 
     pub fn display_set_size(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function display_set_size")
+        unimplemented_function!("display_set_size")
     }
 
     pub fn display_set_colordepth(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function display_set_colordepth")
+        unimplemented_function!("display_set_colordepth")
     }
 
     pub fn display_set_frequency(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function display_set_frequency")
+        unimplemented_function!("display_set_frequency")
     }
 
     pub fn display_set_all(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function display_set_all")
+        unimplemented_function!("display_set_all")
     }
 
     pub fn display_test_all(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function display_test_all")
+        unimplemented_function!("display_test_all")
     }
 
     pub fn display_reset(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        unimplemented!("Called unimplemented kernel function display_reset")
+        unimplemented_function!("display_reset")
     }
 
     pub fn display_mouse_get_x(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_mouse_get_x")
+        unimplemented_function!("display_mouse_get_x")
     }
 
     pub fn display_mouse_get_y(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_mouse_get_y")
+        unimplemented_function!("display_mouse_get_y")
     }
 
     pub fn display_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function display_mouse_set")
+        unimplemented_function!("display_mouse_set")
     }
 
     pub fn window_set_visible(&mut self, args: &[Value]) -> gml::Result<Value> {
         let visible = expect_args!(args, [bool])?;
-        self.window.set_visible(visible);
+        if let Some(window) = &self.window {
+            window.set_visible(visible);
+        }
         Ok(Default::default())
     }
 
@@ -188,7 +204,7 @@ impl Game {
         let show_border = expect_args!(args, [bool])?;
         if show_border != self.window_border {
             self.window_border = show_border;
-            if self.play_type != PlayType::Record {
+            if self.play_type != PlayType::Record && self.window.is_some() {
                 // TODO: Borderless
                 unimplemented!()
             }
@@ -205,10 +221,8 @@ impl Game {
         let show_icons = expect_args!(args, [bool])?;
         if show_icons != self.window_icons {
             self.window_icons = show_icons;
-            if self.play_type != PlayType::Record {
-                self.window.set_controls({
-                    if self.window_icons { Some(ramen::window::Controls::enabled()) } else { None }
-                })
+            if let (Some(window), false) = (&self.window, self.play_type == PlayType::Record) {
+                window.set_controls(if self.window_icons { Some(ramen::window::Controls::enabled()) } else { None })
             }
         }
         Ok(Default::default())
@@ -221,20 +235,20 @@ impl Game {
 
     pub fn window_set_stayontop(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function window_set_stayontop")
+        unimplemented_function!("window_set_stayontop")
     }
 
     pub fn window_get_stayontop(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_get_stayontop")
+        unimplemented_function!("window_get_stayontop")
     }
 
     pub fn window_set_sizeable(&mut self, args: &[Value]) -> gml::Result<Value> {
         let sizeable = expect_args!(args, [bool])?;
         if sizeable != self.window_sizeable {
             self.window_sizeable = sizeable;
-            if self.play_type != PlayType::Record {
-                self.window.set_resizable(self.window_sizeable);
+            if let (Some(window), false) = (&self.window, self.play_type == PlayType::Record) {
+                window.set_resizable(self.window_sizeable);
             }
         }
         Ok(Default::default())
@@ -247,8 +261,8 @@ impl Game {
 
     pub fn window_set_caption(&mut self, args: &[Value]) -> gml::Result<Value> {
         let caption = expect_args!(args, [string])?;
-        if self.play_type == PlayType::Record {
-            self.window.set_title(caption.as_ref());
+        if let (Some(window), PlayType::Record) = (&self.window, self.play_type) {
+            window.set_title(caption.as_ref());
         }
         self.window_caption = caption.into_owned();
         Ok(Default::default())
@@ -290,8 +304,8 @@ impl Game {
                 Cursor::Blank
             },
         };
-        if self.play_type == PlayType::Normal {
-            self.window.set_cursor(cursor);
+        if let (Some(window), PlayType::Normal) = (&self.window, self.play_type) {
+            window.set_cursor(cursor);
         }
         self.window_cursor_gml = code;
         Ok(Default::default())
@@ -323,21 +337,23 @@ impl Game {
         let (width, height) = expect_args!(args, [int, int])?;
         if width > 0 && height > 0 {
             self.window_inner_size = (width as u32, height as u32);
-            self.window.execute(|window| {
-                use ramen::monitor::Size;
-                if window.is_dpi_logical() {
-                    unimplemented!();
-                } else {
-                    window.set_inner_size(Size::Physical(width as u32, height as u32));
-                }
-            });
+            let size = window_size(self.window_is_logical_dpi, width as u32, height as u32);
+            if let Some(window) = &self.window {
+                window.execute(|window| {
+                    if window.is_dpi_logical() {
+                        unimplemented!();
+                    } else {
+                        window.set_inner_size(size);
+                    }
+                });
+            }
         }
         Ok(Default::default())
     }
 
     pub fn window_set_rectangle(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function window_set_rectangle")
+        unimplemented_function!("window_set_rectangle")
     }
 
     pub fn window_center(&mut self, _args: &[Value]) -> gml::Result<Value> {
@@ -347,7 +363,7 @@ impl Game {
 
     pub fn window_default(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_default")
+        unimplemented_function!("window_default")
     }
 
     pub fn window_get_x(&self, args: &[Value]) -> gml::Result<Value> {
@@ -375,7 +391,7 @@ impl Game {
         // unscaled_width and unscaled_height will need to be separated into framebuffer size
         // and window region size for this to work
         // probably keep the framebuffer size on the renderer and make a getter?
-        unimplemented!("Called unimplemented kernel function window_set_region_size")
+        unimplemented_function!("window_set_region_size")
     }
 
     pub fn window_get_region_width(&self, args: &[Value]) -> gml::Result<Value> {
@@ -406,7 +422,9 @@ impl Game {
                 (region_w, region_h)
             };
             self.window_inner_size = (width, height);
-            self.window.set_inner_size(ramen::monitor::Size::Physical(width, height));
+            if let Some(window) = &self.window {
+                window.set_inner_size(window_size(self.window_is_logical_dpi, width, height));
+            }
         }
         Ok(Default::default())
     }
@@ -433,37 +451,45 @@ impl Game {
 
     pub fn window_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function window_mouse_set")
+        unimplemented_function!("window_mouse_set")
     }
 
-    pub fn window_view_mouse_get_x(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function window_view_mouse_get_x")
+    pub fn window_view_mouse_get_x(&self, args: &[Value]) -> gml::Result<Value> {
+        let view_id = expect_args!(args, [int])?;
+        let (x, y) = (self.input.mouse_x(), self.input.mouse_y());
+        match self.room.views.get(view_id as usize) {
+            Some(view) if view_id >= 0 => Ok(view.transform_point(x, y).0.into()),
+            _ => Ok(x.into()),
+        }
     }
 
-    pub fn window_view_mouse_get_y(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function window_view_mouse_get_y")
+    pub fn window_view_mouse_get_y(&self, args: &[Value]) -> gml::Result<Value> {
+        let view_id = expect_args!(args, [int])?;
+        let (x, y) = (self.input.mouse_x(), self.input.mouse_y());
+        match self.room.views.get(view_id as usize) {
+            Some(view) if view_id >= 0 => Ok(view.transform_point(x, y).1.into()),
+            _ => Ok(y.into()),
+        }
     }
 
     pub fn window_view_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function window_view_mouse_set")
+        unimplemented_function!("window_view_mouse_set")
     }
 
     pub fn window_views_mouse_get_x(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_views_mouse_get_x")
+        unimplemented_function!("window_views_mouse_get_x")
     }
 
     pub fn window_views_mouse_get_y(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_views_mouse_get_y")
+        unimplemented_function!("window_views_mouse_get_y")
     }
 
     pub fn window_views_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function window_views_mouse_set")
+        unimplemented_function!("window_views_mouse_set")
     }
 
     pub fn set_synchronization(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -495,17 +521,27 @@ impl Game {
 
     pub fn screen_wait_vsync(&self, args: &[Value]) -> gml::Result<Value> {
         expect_args!(args, [])?;
-        self.renderer.wait_vsync();
+        // Nothing gets presented in record mode, and nothing should be throttled with the frame limiter off,
+        // so there's no vertical blank worth waiting for. The wait can't be observed by the game either way.
+        if self.play_type != PlayType::Record && self.frame_limiter {
+            self.renderer.wait_vsync();
+        }
         Ok(Default::default())
     }
 
+    /// Nothing is ever drawn when running headless, so reading pixels back would quietly give the game
+    /// whatever the target was last cleared to. Functions which do that fail instead.
+    fn check_readback(&self, function: &str) -> gml::Result<()> {
+        match self.window {
+            Some(_) => Ok(()),
+            None => Err(gml::Error::FunctionError(function.into(), "can't read back pixels when headless".into())),
+        }
+    }
+
     pub fn screen_save(&mut self, args: &[Value]) -> gml::Result<Value> {
         let fname = expect_args!(args, [string])?;
-        self.renderer.flush_queue();
-        let (width, height) = (self.unscaled_width, self.unscaled_height);
-        let rgba = self.renderer.get_pixels(0, 0, width as _, height as _);
-        let mut image = RgbaImage::from_vec(width, height, rgba.into()).unwrap();
-        asset::sprite::process_image(&mut image, false, false, true);
+        self.check_readback("screen_save")?;
+        let image = self.screen_image();
         match file::save_image(fname.as_ref(), image) {
             Ok(()) => Ok(Default::default()),
             Err(e) => Err(gml::Error::FunctionError("screen_save".into(), e.to_string())),
@@ -514,6 +550,7 @@ impl Game {
 
     pub fn screen_save_part(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (fname, x, y, w, h) = expect_args!(args, [string, int, int, int, int])?;
+        self.check_readback("screen_save_part")?;
         let x = x.max(0);
         let y = y.max(0);
         let w = w.min(self.unscaled_width as i32 - x);
@@ -530,6 +567,7 @@ impl Game {
 
     pub fn draw_getpixel(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (x, y) = expect_args!(args, [int, int])?;
+        self.check_readback("draw_getpixel")?;
         self.renderer.flush_queue();
         let data = self.renderer.get_pixels(x, y, 1, 1);
         Ok(u32::from_le_bytes([data[0], data[1], data[2], 0]).into())
@@ -885,11 +923,8 @@ impl Game {
     pub fn draw_healthbar(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (x1, y1, x2, y2, amount, backcol, mincol, maxcol, direction, showback, showborder) =
             expect_args!(args, [real, real, real, real, real, int, int, int, int, bool, bool])?;
-        let health_ratio = f64::from(amount / Real::from(100.0));
-        let lerp = |min, max| (health_ratio * f64::from(max) + (1.0 - health_ratio) * f64::from(min)) as u8 as i32;
-        let bar_colour = lerp(mincol & 0xff, maxcol & 0xff)
-            | (lerp((mincol >> 8) & 0xff, (maxcol >> 8) & 0xff) << 8)
-            | (lerp((mincol >> 16) & 0xff, (maxcol >> 16) & 0xff) << 16);
+        let health_ratio = draw::healthbar_fill(amount);
+        let bar_colour = draw::healthbar_colour(health_ratio, mincol, maxcol);
         let (x1, y1, x2, y2) = (x1.into_inner(), y1.into_inner(), x2.into_inner(), y2.into_inner());
         if showback {
             self.renderer.draw_rectangle(x1, y1, x2, y2, backcol, self.draw_alpha.into());
@@ -1124,9 +1159,9 @@ impl Game {
         }
     }
 
-    pub fn texture_exists(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function texture_exists")
+    pub fn texture_exists(&self, args: &[Value]) -> gml::Result<Value> {
+        let texid = expect_args!(args, [int])?;
+        Ok(self.renderer.get_texture_from_id(texid).is_some().into())
     }
 
     pub fn texture_set_interpolation(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -1137,7 +1172,7 @@ impl Game {
 
     pub fn texture_set_blending(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function texture_set_blending")
+        unimplemented_function!("texture_set_blending")
     }
 
     pub fn texture_set_repeat(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -1156,14 +1191,14 @@ impl Game {
         Ok(1.into()) // see texture_get_width
     }
 
-    pub fn texture_preload(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function texture_preload")
+    pub fn texture_preload(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let _texid = expect_args!(args, [int])?;
+        Ok(Default::default()) // everything's already in video memory
     }
 
-    pub fn texture_set_priority(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function texture_set_priority")
+    pub fn texture_set_priority(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (_texid, _prio) = expect_args!(args, [int, real])?;
+        Ok(Default::default()) // see texture_preload
     }
 
     pub fn draw_set_font(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -1371,7 +1406,7 @@ impl Game {
 
     pub fn draw_sprite_pos(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 11
-        unimplemented!("Called unimplemented kernel function draw_sprite_pos")
+        unimplemented_function!("draw_sprite_pos")
     }
 
     pub fn draw_sprite_ext(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -2001,7 +2036,7 @@ impl Game {
 
     pub fn tile_find(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function tile_find")
+        unimplemented_function!("tile_find")
     }
 
     pub fn tile_exists(&self, args: &[Value]) -> gml::Result<Value> {
@@ -2021,7 +2056,7 @@ impl Game {
 
     pub fn tile_delete_at(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function tile_delete_at")
+        unimplemented_function!("tile_delete_at")
     }
 
     pub fn tile_layer_hide(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -2360,6 +2395,7 @@ impl Game {
 
     pub fn surface_save(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (surf_id, fname) = expect_args!(args, [int, string])?;
+        self.check_readback("surface_save")?;
         if Some(surf_id) == self.surface_target {
             self.renderer.flush_queue();
         }
@@ -2378,6 +2414,7 @@ impl Game {
 
     pub fn surface_save_part(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (surf_id, fname, x, y, w, h) = expect_args!(args, [int, string, int, int, int, int])?;
+        self.check_readback("surface_save_part")?;
         if Some(surf_id) == self.surface_target {
             self.renderer.flush_queue();
         }
@@ -2401,7 +2438,7 @@ impl Game {
 
     pub fn surface_getpixel(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function surface_getpixel")
+        unimplemented_function!("surface_getpixel")
     }
 
     pub fn surface_copy(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -2423,7 +2460,7 @@ impl Game {
 
     pub fn action_path_old(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function action_path_old")
+        unimplemented_function!("action_path_old")
     }
 
     pub fn action_set_sprite(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -2437,27 +2474,27 @@ impl Game {
 
     pub fn action_draw_font(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function action_draw_font")
+        unimplemented_function!("action_draw_font")
     }
 
     pub fn action_draw_font_old(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function action_draw_font_old")
+        unimplemented_function!("action_draw_font_old")
     }
 
     pub fn action_fill_color(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function action_fill_color")
+        unimplemented_function!("action_fill_color")
     }
 
     pub fn action_line_color(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function action_line_color")
+        unimplemented_function!("action_line_color")
     }
 
     pub fn action_highscore(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function action_highscore")
+        unimplemented_function!("action_highscore")
     }
 
     pub fn action_move(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -2473,32 +2510,10 @@ impl Game {
         }
 
         // Only invoke RNG if at least one of the options is checked, otherwise don't do anything
-        if bytes.contains(&49) {
-            // Call irandom until it lands on a byte that's '1' rather than '0'
-            let offset = loop {
-                let index = self.rand.next_int(8) as usize;
-                if bytes[index] == 49 {
-                    // '1'
-                    break index
-                }
-            };
-
-            // Handle each case separately
-            let (speed, direction): (Real, Real) = match offset {
-                0 => (speed, Real::from(225.0)),
-                1 => (speed, Real::from(270.0)),
-                2 => (speed, Real::from(315.0)),
-                3 => (speed, Real::from(180.0)),
-                4 => (Real::from(0.0), Real::from(0.0)),
-                5 => (speed, Real::from(0.0)),
-                6 => (speed, Real::from(135.0)),
-                7 => (speed, Real::from(90.0)),
-                8 => (speed, Real::from(45.0)),
-                _ => unreachable!(),
-            };
+        if let Some(button) = movement::move_fixed_choice(bytes, &mut self.rand) {
+            let (speed, direction) = movement::move_fixed_motion(button, speed);
             if context.relative {
-                instance.set_hspeed(direction.to_radians().cos() * speed + instance.hspeed.get());
-                instance.set_vspeed(-direction.to_radians().sin() * speed + instance.vspeed.get());
+                instance.motion_add(direction, speed);
             } else {
                 instance.set_speed_direction(speed, direction);
             }
@@ -2511,8 +2526,7 @@ impl Game {
         let (direction, speed) = expect_args!(args, [real, real])?;
         let instance = self.room.instance_list.get(context.this);
         if context.relative {
-            instance.set_hspeed(direction.to_radians().cos() * speed + instance.hspeed.get());
-            instance.set_vspeed(-direction.to_radians().sin() * speed + instance.vspeed.get());
+            instance.motion_add(direction, speed);
         } else {
             instance.set_speed_direction(speed, direction);
         }
@@ -2548,7 +2562,7 @@ impl Game {
             let instance = self.room.instance_list.get(context.this);
             if context.relative {
                 instance.gravity.set(gravity + instance.gravity.get());
-                instance.gravity_direction.set(direction + instance.gravity.get());
+                instance.gravity_direction.set(direction + instance.gravity_direction.get());
             } else {
                 instance.gravity.set(gravity);
                 instance.gravity_direction.set(direction);
@@ -2572,7 +2586,8 @@ impl Game {
     pub fn action_move_point(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (x, y, speed) = expect_args!(args, [real, real, real])?;
         let instance = self.room.instance_list.get(context.this);
-        let speed = if context.relative { instance.speed.get() + speed } else { speed };
+        // Relative only applies to the position here, not the speed
+        let (x, y) = if context.relative { (instance.x.get() + x, instance.y.get() + y) } else { (x, y) };
         let direction = (instance.y.get() - y).arctan2(x - instance.x.get()).to_degrees();
         instance.set_speed_direction(speed, direction);
         Ok(Default::default())
@@ -2615,6 +2630,8 @@ impl Game {
             (Real::from(0.0), Real::from(0.0))
         };
 
+        // The bbox is checked again straight away by the intersect boundary event, so it has to be kept up to date
+        let (x, y) = (instance.x.get(), instance.y.get());
         if horizontal {
             let room_width = Real::from(self.room.width);
             if instance.hspeed.get() > Real::from(0.0) && instance.x.get() > room_width {
@@ -2637,6 +2654,9 @@ impl Game {
                 instance.y.set(instance.y.get() + (room_height + h));
             }
         }
+        if instance.x.get() != x || instance.y.get() != y {
+            instance.bbox_is_stale.set(true);
+        }
         Ok(Default::default())
     }
 
@@ -2785,7 +2805,7 @@ impl Game {
         let object_ids = [obj1, obj2, obj3, obj4];
         if object_ids.iter().any(|&id| self.assets.objects.get_asset(id).is_some()) {
             let (object_id, object) = loop {
-                let i = self.rand.next_int(3) as usize;
+                let i = self.rand.next_int(3, Site::CreateObjectRandom) as usize;
                 if let Some(object) = self.assets.objects.get_asset(object_ids[i]) {
                     break (object_ids[i], object)
                 }
@@ -2897,7 +2917,8 @@ impl Game {
     pub fn action_set_alarm(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (time, alarm) = expect_args!(args, [int, int])?;
         let mut alarms = self.room.instance_list.get(context.this).alarms.borrow_mut();
-        let time = if context.relative { time + alarms.get(&(alarm as u32)).copied().unwrap_or(-1) } else { time };
+        let current = alarms.get(&(alarm as u32)).copied().unwrap_or(DEFAULT_ALARM);
+        let time = if context.relative { time + current } else { time };
         alarms.insert(alarm as u32, time);
         Ok(Default::default())
     }
@@ -2980,37 +3001,37 @@ impl Game {
 
     pub fn action_splash_text(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function action_splash_text")
+        unimplemented_function!("action_splash_text")
     }
 
     pub fn action_splash_image(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function action_splash_image")
+        unimplemented_function!("action_splash_image")
     }
 
     pub fn action_splash_web(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function action_splash_web")
+        unimplemented_function!("action_splash_web")
     }
 
     pub fn action_splash_settings(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function action_splash_settings")
+        unimplemented_function!("action_splash_settings")
     }
 
     pub fn action_replace_sprite(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function action_replace_sprite")
+        unimplemented_function!("action_replace_sprite")
     }
 
     pub fn action_replace_sound(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function action_replace_sound")
+        unimplemented_function!("action_replace_sound")
     }
 
     pub fn action_replace_background(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function action_replace_background")
+        unimplemented_function!("action_replace_background")
     }
 
     pub fn action_if_empty(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -3067,7 +3088,7 @@ impl Game {
 
     pub fn action_if_dice(&mut self, args: &[Value]) -> gml::Result<Value> {
         let bound = expect_args!(args, [real])?;
-        Ok((self.rand.next(bound.into()) < 1.0).into())
+        Ok((self.rand.next(bound.into(), Site::IfDice) < 1.0).into())
     }
 
     pub fn action_if_mouse(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -3116,7 +3137,7 @@ impl Game {
                 ],
                 5,
             );
-            self.execute(&instructions, &mut new_context)?;
+            self.execute(&instructions, &mut new_context).map_err(|e| self.script_error(script_id, e))?;
             Ok(new_context.return_value)
         } else {
             Err(gml::Error::NonexistentAsset(asset::Type::Script, script_id))
@@ -3194,16 +3215,12 @@ impl Game {
 
     pub fn action_highscore_show(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function action_highscore_show")
+        unimplemented_function!("action_highscore_show")
     }
 
     pub fn action_set_life(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let lives = expect_args!(args, [int])?;
-        if context.relative {
-            self.lives += lives;
-        } else {
-            self.lives = lives;
-        }
+        self.set_lives(if context.relative { self.lives + lives } else { lives });
         Ok(Default::default())
     }
 
@@ -3248,15 +3265,7 @@ impl Game {
 
     pub fn action_set_health(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let health = expect_args!(args, [real])?;
-        let old_health = self.health;
-        if context.relative {
-            self.health += health;
-        } else {
-            self.health = health;
-        }
-        if old_health > 0.into() && self.health <= 0.into() {
-            self.run_other_event(9)?;
-        }
+        self.set_health(if context.relative { self.health + health } else { health });
         Ok(Default::default())
     }
 
@@ -3284,7 +3293,7 @@ impl Game {
             y2 += y;
         }
 
-        let health_ratio = f64::from(self.health / Real::from(100.0));
+        let health_ratio = draw::healthbar_fill(self.health);
 
         use mappings::constants;
         let bar_colour = match col {
@@ -3509,13 +3518,15 @@ impl Game {
         } else {
             Cursor::Blank
         };
-        self.window.set_cursor(cursor);
+        if let Some(window) = &self.window {
+            window.set_cursor(cursor);
+        }
         Ok(Default::default())
     }
 
     pub fn action_webpage(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function action_webpage")
+        unimplemented_function!("action_webpage")
     }
 
     pub fn action_draw_sprite(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -3723,22 +3734,22 @@ impl Game {
 
     pub fn random(&mut self, args: &[Value]) -> gml::Result<Value> {
         let bound = expect_args!(args, [real])?;
-        Ok(self.rand.next(bound.into()).into())
+        Ok(self.rand.next(bound.into(), Site::Random).into())
     }
 
     pub fn random_range(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (lower, upper) = expect_args!(args, [real, real])?;
-        Ok((lower.min(upper) + Real::from(self.rand.next((upper - lower).abs().into()))).into())
+        Ok((lower.min(upper) + Real::from(self.rand.next((upper - lower).abs().into(), Site::RandomRange))).into())
     }
 
     pub fn irandom(&mut self, args: &[Value]) -> gml::Result<Value> {
         let bound = expect_args!(args, [int])?;
-        Ok(self.rand.next_int(bound as _).into())
+        Ok(self.rand.next_int(bound as _, Site::Irandom).into())
     }
 
     pub fn irandom_range(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (lower, upper) = expect_args!(args, [int, int])?;
-        Ok((lower.min(upper) + self.rand.next_int((upper - lower).abs() as _)).into())
+        Ok((lower.min(upper) + self.rand.next_int((upper - lower).abs() as _, Site::IrandomRange)).into())
     }
 
     pub fn random_set_seed(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -3927,7 +3938,7 @@ impl Game {
 
     pub fn choose(&mut self, args: &[Value]) -> gml::Result<Value> {
         match args.len().checked_sub(1) {
-            Some(i) => Ok(args[self.rand.next_int(i as _) as usize].clone()),
+            Some(i) => Ok(args[self.rand.next_int(i as _, Site::Choose) as usize].clone()),
             None => Ok(Default::default()),
         }
     }
@@ -3947,9 +3958,9 @@ impl Game {
             r @ Value::Real(_) => Ok(r),
             Value::Str(s) => match self.decode_str(s.as_ref()).trim() {
                 x if x.len() == 0 => Ok(Default::default()),
-                x => match x.parse::<f64>() {
-                    Ok(r) => Ok(r.into()),
-                    Err(e) => Err(gml::Error::FunctionError("real".into(), format!("can't convert {} - {}", s, e))),
+                x => match Real::parse(x) {
+                    Some(r) => Ok(r.into()),
+                    None => Err(gml::Error::FunctionError("real".into(), format!("can't convert {} to a number", s))),
                 },
             },
         })
@@ -3972,7 +3983,7 @@ impl Game {
                     x = Real::from((x / power).round()) * power;
                     dec = 18;
                 }
-                Ok(format!("{num:>width$.prec$}", num = x, prec = dec as usize, width = tot as usize).into())
+                Ok(format!("{:>width$}", x.to_fixed(dec as usize), width = tot as usize).into())
             },
         }
     }
@@ -4235,11 +4246,11 @@ impl Game {
         drop(inst); // le borrow
         let (mut x, mut y) = Default::default();
         for _ in 0..100 {
-            x = Real::from(self.rand.next_int((right - left - 1) as u32) + left);
+            x = Real::from(self.rand.next_int((right - left - 1) as u32, Site::MoveRandom) + left);
             if hsnap > 0 {
                 x = (x / hsnap.into()).floor() * hsnap.into();
             }
-            y = Real::from(self.rand.next_int((bottom - top - 1) as u32) + top);
+            y = Real::from(self.rand.next_int((bottom - top - 1) as u32, Site::MoveRandom) + top);
             if vsnap > 0 {
                 y = (y / vsnap.into()).floor() * vsnap.into();
             }
@@ -4256,71 +4267,27 @@ impl Game {
 
     pub fn place_free(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (x, y) = expect_args!(args, [real, real])?;
-
-        // Set self's position to the new coordinates
-        let instance = self.room.instance_list.get(context.this);
-        let old_x = instance.x.get();
-        let old_y = instance.y.get();
-        instance.x.set(x);
-        instance.y.set(y);
-        instance.bbox_is_stale.set(true);
-
-        // Check collision with any solids
-        let free = self.check_collision_solid(context.this).is_none();
-
-        // Move self back to where it was
-        instance.x.set(old_x);
-        instance.y.set(old_y);
-        instance.bbox_is_stale.set(true);
-
+        // Only solid instances are considered
+        let free =
+            self.check_collision_at(context.this, x, y, |game| game.check_collision_solid(context.this).is_none());
         Ok(free.into())
     }
 
     pub fn place_empty(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (x, y) = expect_args!(args, [real, real])?;
-
-        // Set self's position to the new coordinates
-        let instance = self.room.instance_list.get(context.this);
-        let old_x = instance.x.get();
-        let old_y = instance.y.get();
-        instance.x.set(x);
-        instance.y.set(y);
-        instance.bbox_is_stale.set(true);
-
-        // Check collision with any instance
-        let empty = self.check_collision_any(context.this).is_none();
-
-        // Move self back to where it was
-        instance.x.set(old_x);
-        instance.y.set(old_y);
-        instance.bbox_is_stale.set(true);
-
+        // Every instance is considered, solid or not
+        let empty =
+            self.check_collision_at(context.this, x, y, |game| game.check_collision_any(context.this).is_none());
         Ok(empty.into())
     }
 
     pub fn place_meeting(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (x, y, obj) = expect_args!(args, [real, real, int])?;
-
-        // Set self's position to the new coordinates
-        let instance = self.room.instance_list.get(context.this);
-        let old_x = instance.x.get();
-        let old_y = instance.y.get();
-        instance.x.set(x);
-        instance.y.set(y);
-        instance.bbox_is_stale.set(true);
-
-        // Check collision with target
-        let collision = match obj {
+        let collision = self.check_collision_at(context.this, x, y, |game| match obj {
             gml::SELF => false,
-            gml::OTHER => self.check_collision(context.this, context.other),
-            obj => self.find_instance_with(obj, |handle| self.check_collision(context.this, handle)).is_some(),
-        };
-
-        // Move self back to where it was
-        instance.x.set(old_x);
-        instance.y.set(old_y);
-        instance.bbox_is_stale.set(true);
-
+            gml::OTHER => game.check_collision(context.this, context.other),
+            obj => game.find_instance_with(obj, |handle| game.check_collision(context.this, handle)).is_some(),
+        });
         Ok(collision.into())
     }
 
@@ -4525,25 +4492,14 @@ impl Game {
         let mut update_bbox = false;
 
         if horizontal_wrap {
-            let instance_x = instance.x.get();
-
-            if instance_x < -margin {
-                instance.x.set(Real::from(self.room.width) + instance_x + Real::from(2) * margin);
-                update_bbox = true;
-            }
-            if instance_x > Real::from(self.room.width) + margin {
-                instance.x.set(instance_x - Real::from(self.room.width) - Real::from(2) * margin);
+            if let Some(x) = movement::wrap(instance.x.get(), self.room.width, margin) {
+                instance.x.set(x);
                 update_bbox = true;
             }
         }
         if vertical_wrap {
-            let instance_y = instance.y.get();
-            if instance_y < -margin {
-                instance.y.set(Real::from(self.room.height) + instance_y + Real::from(2) * margin);
-                update_bbox = true;
-            }
-            if instance_y > Real::from(self.room.height) + margin {
-                instance.y.set(instance_y - Real::from(self.room.height) - Real::from(2) * margin);
+            if let Some(y) = movement::wrap(instance.y.get(), self.room.height, margin) {
+                instance.y.set(y);
                 update_bbox = true;
             }
         }
@@ -4561,10 +4517,7 @@ impl Game {
 
     pub fn motion_add(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (direction, speed) = expect_args!(args, [real, real])?;
-        let instance = self.room.instance_list.get(context.this);
-        let hspeed = direction.to_radians().cos() * speed;
-        let vspeed = -direction.to_radians().sin() * speed;
-        instance.set_hvspeed(instance.hspeed.get() + hspeed, instance.vspeed.get() + vspeed);
+        self.room.instance_list.get(context.this).motion_add(direction, speed);
         Ok(Default::default())
     }
 
@@ -4574,55 +4527,12 @@ impl Game {
 
         let sprite = self.get_instance_mask_sprite(context.this);
         instance.update_bbox(sprite);
-
-        let distance_x = if x < instance.bbox_left.get().into() {
-            x - instance.bbox_left.get().into()
-        } else if x > instance.bbox_right.get().into() {
-            x - instance.bbox_right.get().into()
-        } else {
-            0.into()
-        };
-
-        let distance_y = if y < instance.bbox_top.get().into() {
-            y - instance.bbox_top.get().into()
-        } else if y > instance.bbox_bottom.get().into() {
-            y - instance.bbox_bottom.get().into()
-        } else {
-            0.into()
-        };
-
-        Ok(distance_x.into_inner().hypot(distance_y.into_inner()).into())
+        Ok(instance.bbox_distance_to_point(x, y).into())
     }
 
     pub fn distance_to_object(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let object_id = expect_args!(args, [int])?;
 
-        // Helper fn: distance between two instances (with a function name that's really hard to say quickly)
-        fn instance_distance(inst1: &Instance, inst2: &Instance) -> f64 {
-            let distance_x = if inst1.bbox_left.get() > inst2.bbox_right.get() {
-                inst1.bbox_left.get() - inst2.bbox_right.get()
-            } else if inst2.bbox_left.get() > inst1.bbox_right.get() {
-                inst2.bbox_left.get() - inst1.bbox_right.get()
-            } else {
-                0
-            };
-
-            let distance_y = if inst1.bbox_top.get() > inst2.bbox_bottom.get() {
-                inst1.bbox_top.get() - inst2.bbox_bottom.get()
-            } else if inst2.bbox_top.get() > inst1.bbox_bottom.get() {
-                inst2.bbox_top.get() - inst1.bbox_bottom.get()
-            } else {
-                0
-            };
-
-            match (distance_x, distance_y) {
-                (0, 0) => 0.0,
-                (x, 0) => x.into(),
-                (0, y) => y.into(),
-                (x, y) => f64::from(x).hypot(y.into()),
-            }
-        }
-
         let sprite = self.get_instance_mask_sprite(context.this);
         let this = self.room.instance_list.get(context.this);
         this.update_bbox(sprite);
@@ -4633,7 +4543,7 @@ impl Game {
                 let sprite = self.get_instance_mask_sprite(context.other);
                 let other = self.room.instance_list.get(context.other);
                 other.update_bbox(sprite);
-                instance_distance(this, other)
+                this.bbox_distance(other)
             },
             gml::ALL => {
                 let mut closest = 1000000.0; // GML default
@@ -4643,7 +4553,7 @@ impl Game {
                     let sprite = self.get_instance_mask_sprite(other);
                     let other = self.room.instance_list.get(other);
                     other.update_bbox(sprite);
-                    let dist = instance_distance(this, other);
+                    let dist = this.bbox_distance(other);
                     if dist < closest {
                         closest = dist;
                     }
@@ -4658,7 +4568,7 @@ impl Game {
                     let sprite = self.get_instance_mask_sprite(other);
                     let other = self.room.instance_list.get(other);
                     other.update_bbox(sprite);
-                    let dist = instance_distance(this, other);
+                    let dist = this.bbox_distance(other);
                     if dist < closest {
                         closest = dist;
                     }
@@ -4671,7 +4581,7 @@ impl Game {
                         let sprite = self.get_instance_mask_sprite(handle);
                         let other = self.room.instance_list.get(handle);
                         other.update_bbox(sprite);
-                        instance_distance(this, other)
+                        this.bbox_distance(other)
                     },
                     None => 1000000.0, // Again, GML default
                 }
@@ -4767,7 +4677,7 @@ impl Game {
 
     pub fn mp_linear_path_object(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function mp_linear_path_object")
+        unimplemented_function!("mp_linear_path_object")
     }
 
     pub fn mp_potential_settings(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -4802,7 +4712,7 @@ impl Game {
 
     pub fn mp_potential_path(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function mp_potential_path")
+        unimplemented_function!("mp_potential_path")
     }
 
     pub fn mp_potential_step_object(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -4824,7 +4734,7 @@ impl Game {
 
     pub fn mp_potential_path_object(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function mp_potential_path_object")
+        unimplemented_function!("mp_potential_path_object")
     }
 
     pub fn mp_grid_create(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -4959,12 +4869,12 @@ impl Game {
 
     pub fn mp_grid_add_instances(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function mp_grid_add_instances")
+        unimplemented_function!("mp_grid_add_instances")
     }
 
     pub fn mp_grid_path(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 7
-        unimplemented!("Called unimplemented kernel function mp_grid_path")
+        unimplemented_function!("mp_grid_path")
     }
 
     pub fn mp_grid_draw(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -5289,10 +5199,13 @@ impl Game {
 
     pub fn instance_copy(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let run_event = expect_args!(args, [bool])?;
+        // Everything is copied by value, including alarms, timeline state and fields. Any data structure IDs stored
+        // in fields will refer to the same data structures, since they're just numbers.
         let new_instance = self.room.instance_list.get(context.this).clone();
         self.last_instance_id += 1;
         let id = self.last_instance_id;
         new_instance.id.set(id);
+        new_instance.state.set(InstanceState::Active);
         let handle = self.room.instance_list.insert(new_instance);
         if run_event {
             self.run_instance_event(gml::ev::CREATE, 0, handle, handle, None)?;
@@ -5302,9 +5215,14 @@ impl Game {
 
     pub fn instance_change(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (object_id, run_events) = expect_args!(args, [int, bool])?;
+        self.change_instance(context.this, object_id, run_events)?;
+        Ok(Default::default())
+    }
 
+    // Changes an instance into another object, as instance_change and position_change do.
+    fn change_instance(&mut self, handle: usize, object_id: i32, run_events: bool) -> gml::Result<()> {
         if run_events {
-            self.run_instance_event(gml::ev::DESTROY, 0, context.this, context.this, None)?;
+            self.run_instance_event(gml::ev::DESTROY, 0, handle, handle, None)?;
         }
 
         let object = self
@@ -5312,56 +5230,68 @@ impl Game {
             .objects
             .get_asset(object_id)
             .ok_or(gml::Error::NonexistentAsset(asset::Type::Object, object_id))?;
-        let mut new_instance = self.room.instance_list.get(context.this).clone();
+        // Variables, alarms and timeline state are all kept, as are sprite_index and mask_index.
+        // Only the object's flags are taken from the new object. Since the new instance gets inserted straight away,
+        // its events and collision status (e.g. the solid flag) are resolved using the new object from now on.
+        let mut new_instance = self.room.instance_list.get(handle).clone();
         new_instance.object_index.set(object_id);
-        new_instance.sprite_index.set(object.sprite_index);
-        new_instance.mask_index.set(object.mask_index);
         new_instance.depth.set(Real::from(object.depth));
         new_instance.solid.set(object.solid);
         new_instance.visible.set(object.visible);
         new_instance.persistent.set(object.persistent);
         new_instance.parents = object.parents.clone();
-        self.last_instance_id += 1; // This is incremented by GM8 but not used
-
-        let frame_count = if let Some(sprite) = self.assets.sprites.get_asset(object.sprite_index) {
-            sprite.frames.len() as f64
-        } else {
-            0.0
-        };
-        if frame_count <= new_instance.image_index.get().floor().into() {
-            new_instance.image_index.set(Real::from(0.0));
-        }
+        new_instance.state.set(InstanceState::Active);
         new_instance.bbox_is_stale.set(true);
+        self.last_instance_id += 1; // This is incremented by GM8 but not used
 
-        self.room.instance_list.mark_deleted(context.this);
+        self.room.instance_list.mark_deleted(handle);
         let handle = self.room.instance_list.insert(new_instance);
 
         if run_events {
             self.run_instance_event(gml::ev::CREATE, 0, handle, handle, None)?;
         }
 
-        Ok(Default::default())
+        Ok(())
     }
 
     pub fn instance_destroy(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         expect_args!(args, [])?;
-        self.run_instance_event(gml::ev::DESTROY, 0, context.this, context.this, None)?;
-        self.room.instance_list.mark_deleted(context.this);
+        self.destroy_instance(context.this, true)?;
         Ok(Default::default())
     }
 
+    // Destroys an instance, running its destroy event first if asked to. It isn't removed until the end of the step,
+    // so anything still holding its handle (like `other` in a collision) can read its variables until then,
+    // but it's left out of with(), collisions, drawing and looking up by id straight away.
+    // Destroying an instance from inside its own destroy event does nothing, rather than recursing forever.
+    pub fn destroy_instance(&mut self, handle: usize, run_event: bool) -> gml::Result<()> {
+        if self.destroying.contains(&handle) {
+            return Ok(())
+        }
+        if run_event {
+            self.destroying.push(handle);
+            let result = self.run_instance_event(gml::ev::DESTROY, 0, handle, handle, None);
+            self.destroying.pop();
+            result?;
+        }
+        self.room.instance_list.mark_deleted(handle);
+        Ok(())
+    }
+
     pub fn instance_sprite(&mut self, _context: &mut Context, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function instance_sprite")
+        unimplemented_function!("instance_sprite")
     }
 
     pub fn position_empty(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (x, y) = expect_args!(args, [any, any])?;
-        Ok((!self.position_meeting(context, &[gml::ALL.into(), x, y])?.is_truthy()).into())
+        Ok((!self.position_meeting(context, &[x, y, gml::ALL.into()])?.is_truthy()).into())
     }
 
     pub fn position_meeting(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
         let (x, y, object_id) = expect_args!(args, [real, real, int])?;
+        // Unlike the place_* functions, the position_* functions floor the point rather than rounding it
+        let (x, y) = (x.floor(), y.floor());
         let meeting = match object_id {
             gml::SELF => self.check_collision_point(context.this, x, y, true),
             gml::OTHER => self.check_collision_point(context.other, x, y, true),
@@ -5372,19 +5302,33 @@ impl Game {
 
     pub fn position_destroy(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (x, y) = expect_args!(args, [real, real])?;
+        let (x, y) = (x.floor(), y.floor());
         let mut iter = self.room.instance_list.iter_by_drawing();
         while let Some(handle) = iter.next(&self.room.instance_list) {
             if self.check_collision_point(handle, x, y, true) {
-                self.run_instance_event(gml::ev::DESTROY, 0, handle, handle, None)?;
-                self.room.instance_list.mark_deleted(handle);
+                self.destroy_instance(handle, true)?;
             }
         }
         Ok(Default::default())
     }
 
-    pub fn position_change(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function position_change")
+    pub fn position_change(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (x, y, object_id, run_events) = expect_args!(args, [real, real, int, bool])?;
+        let (x, y) = (x.floor(), y.floor());
+        // Find everything first, so the changed instances don't get picked up by the iterator again
+        let mut targets = Vec::new();
+        let mut iter = self.room.instance_list.iter_by_drawing();
+        while let Some(handle) = iter.next(&self.room.instance_list) {
+            if self.check_collision_point(handle, x, y, true) {
+                targets.push(handle);
+            }
+        }
+        for handle in targets {
+            if self.room.instance_list.get(handle).is_active() {
+                self.change_instance(handle, object_id, run_events)?;
+            }
+        }
+        Ok(Default::default())
     }
 
     pub fn instance_deactivate_all(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -5550,6 +5494,9 @@ impl Game {
 
     pub fn room_goto(&mut self, args: &[Value]) -> gml::Result<Value> {
         let target = expect_args!(args, [int])?;
+        if self.assets.rooms.get_asset(target).is_none() {
+            return Err(gml::Error::UnexistingRoom(target))
+        }
         self.scene_change = Some(SceneChange::Room(target));
         Ok(Default::default())
     }
@@ -5567,7 +5514,7 @@ impl Game {
                 self.scene_change = Some(SceneChange::Room(i));
                 Ok(Default::default())
             },
-            None => Err(gml::Error::EndOfRoomOrder),
+            None => Err(gml::Error::PreviousRoomBeforeFirst),
         }
     }
 
@@ -5579,7 +5526,7 @@ impl Game {
                 self.scene_change = Some(SceneChange::Room(i));
                 Ok(Default::default())
             },
-            None => Err(gml::Error::EndOfRoomOrder),
+            None => Err(gml::Error::NextRoomAfterLast),
         }
     }
 
@@ -5632,16 +5579,7 @@ impl Game {
 
     pub fn game_save(&mut self, args: &[Value]) -> gml::Result<Value> {
         let fname = expect_args!(args, [string])?;
-        let save = GMSave::from_game(self);
-        let mut file = std::fs::File::create(fname.as_ref())
-            .map(std::io::BufWriter::new)
-            .map_err(|e| gml::Error::FunctionError("game_save".into(), format!("{}", e)))?;
-        // write magic number (0x21c in GM8)
-        file.write(&[0x1d, 0x02, 0x00, 0x00])
-            .map_err(|e| gml::Error::FunctionError("game_save".into(), format!("{}", e)))?;
-        bincode::serialize_into(&mut file, &save)
-            .map_err(|e| gml::Error::FunctionError("game_save".into(), format!("{}", e)))?;
-        file.flush().map_err(|e| gml::Error::FunctionError("game_save".into(), e.to_string()))?;
+        self.save_gm_save(fname.into_owned().into())?;
         Ok(Default::default())
     }
 
@@ -6181,7 +6119,7 @@ impl Game {
 
     pub fn file_attributes(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function file_attributes")
+        unimplemented_function!("file_attributes")
     }
 
     pub fn filename_name(args: &[Value]) -> gml::Result<Value> {
@@ -6237,9 +6175,8 @@ impl Game {
         let name = expect_args!(args, [bytes])?;
         let temp_directory = self.decode_str(self.temp_directory.as_ref()).into_owned().into();
         let program_directory = self.decode_str(self.program_directory.as_ref()).into_owned().into();
-        if let Some(file) = self.included_files.iter_mut().filter(|i| name.eq_ignore_ascii_case(i.name.as_ref())).next()
-        {
-            match file.export(temp_directory, program_directory) {
+        if let Some(file) = self.included_files.iter_mut().find(|i| name.eq_ignore_ascii_case(i.name.as_ref())) {
+            match file.export_on_request(temp_directory, program_directory) {
                 Ok(()) => Ok(Default::default()),
                 Err(e) => Err(gml::Error::FunctionError("export_include_file".into(), e.to_string())),
             }
@@ -6250,8 +6187,7 @@ impl Game {
 
     pub fn export_include_file_location(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (name, path) = expect_args!(args, [bytes, string])?;
-        if let Some(file) = self.included_files.iter_mut().filter(|i| name.eq_ignore_ascii_case(i.name.as_ref())).next()
-        {
+        if let Some(file) = self.included_files.iter_mut().find(|i| name.eq_ignore_ascii_case(i.name.as_ref())) {
             let path_ref: &str = path.as_ref();
             match file.export_to(path_ref.as_ref()) {
                 Ok(()) => Ok(Default::default()),
@@ -6267,8 +6203,7 @@ impl Game {
 
     pub fn discard_include_file(&mut self, args: &[Value]) -> gml::Result<Value> {
         let name = expect_args!(args, [bytes])?;
-        if let Some(file) = self.included_files.iter_mut().filter(|i| name.eq_ignore_ascii_case(i.name.as_ref())).next()
-        {
+        if let Some(file) = self.included_files.iter_mut().find(|i| name.eq_ignore_ascii_case(i.name.as_ref())) {
             file.data = None;
             Ok(Default::default())
         } else {
@@ -6335,7 +6270,7 @@ impl Game {
 
     pub fn execute_shell(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function execute_shell")
+        unimplemented_function!("execute_shell")
     }
 
     pub fn parameter_count(&self, args: &[Value]) -> gml::Result<Value> {
@@ -6348,10 +6283,9 @@ impl Game {
         let param_index = expect_args!(args, [int])?;
         if param_index >= 0 {
             Ok(match self.parameters.get(param_index as usize) {
-                Some(a) => a.clone(),
-                None => "".to_string(),
-            }
-            .into())
+                Some(a) => a.clone().into(),
+                None => "".into(),
+            })
         } else {
             Ok("".into())
         }
@@ -6368,57 +6302,57 @@ impl Game {
 
     pub fn registry_write_string(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function registry_write_string")
+        unimplemented_function!("registry_write_string")
     }
 
     pub fn registry_write_real(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function registry_write_real")
+        unimplemented_function!("registry_write_real")
     }
 
     pub fn registry_read_string(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function registry_read_string")
+        unimplemented_function!("registry_read_string")
     }
 
     pub fn registry_read_real(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function registry_read_real")
+        unimplemented_function!("registry_read_real")
     }
 
     pub fn registry_exists(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function registry_exists")
+        unimplemented_function!("registry_exists")
     }
 
     pub fn registry_write_string_ext(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function registry_write_string_ext")
+        unimplemented_function!("registry_write_string_ext")
     }
 
     pub fn registry_write_real_ext(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function registry_write_real_ext")
+        unimplemented_function!("registry_write_real_ext")
     }
 
     pub fn registry_read_string_ext(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function registry_read_string_ext")
+        unimplemented_function!("registry_read_string_ext")
     }
 
     pub fn registry_read_real_ext(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function registry_read_real_ext")
+        unimplemented_function!("registry_read_real_ext")
     }
 
     pub fn registry_exists_ext(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function registry_exists_ext")
+        unimplemented_function!("registry_exists_ext")
     }
 
     pub fn registry_set_root(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function registry_set_root")
+        unimplemented_function!("registry_set_root")
     }
 
     pub fn ini_open(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -6472,10 +6406,7 @@ impl Game {
         let (section, key, default) = expect_args!(args, [string, string, real])?;
         match self.open_ini.as_ref() {
             Some((ini, _)) => match ini.section(Some(section.as_ref())).and_then(|s| s.get(key)) {
-                Some(val) => match val.parse::<f64>() {
-                    Ok(x) => Ok(x.into()),
-                    Err(_) => Ok(Default::default()),
-                },
+                Some(val) => Ok(Real::parse(val.trim()).unwrap_or_default().into()),
                 None => Ok(default.into()),
             },
             None => Err(gml::Error::FunctionError(
@@ -6585,122 +6516,122 @@ impl Game {
 
     pub fn splash_set_caption(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_caption")
+        unimplemented_function!("splash_set_caption")
     }
 
     pub fn splash_set_fullscreen(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_fullscreen")
+        unimplemented_function!("splash_set_fullscreen")
     }
 
     pub fn splash_set_border(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_border")
+        unimplemented_function!("splash_set_border")
     }
 
     pub fn splash_set_size(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function splash_set_size")
+        unimplemented_function!("splash_set_size")
     }
 
     pub fn splash_set_position(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function splash_set_position")
+        unimplemented_function!("splash_set_position")
     }
 
     pub fn splash_set_adapt(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_adapt")
+        unimplemented_function!("splash_set_adapt")
     }
 
     pub fn splash_set_top(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_top")
+        unimplemented_function!("splash_set_top")
     }
 
     pub fn splash_set_color(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_color")
+        unimplemented_function!("splash_set_color")
     }
 
     pub fn splash_set_main(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_main")
+        unimplemented_function!("splash_set_main")
     }
 
     pub fn splash_set_scale(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_scale")
+        unimplemented_function!("splash_set_scale")
     }
 
     pub fn splash_set_cursor(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_cursor")
+        unimplemented_function!("splash_set_cursor")
     }
 
     pub fn splash_set_interrupt(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_interrupt")
+        unimplemented_function!("splash_set_interrupt")
     }
 
     pub fn splash_set_stop_key(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_stop_key")
+        unimplemented_function!("splash_set_stop_key")
     }
 
     pub fn splash_set_close_button(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_close_button")
+        unimplemented_function!("splash_set_close_button")
     }
 
     pub fn splash_set_stop_mouse(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function splash_set_stop_mouse")
+        unimplemented_function!("splash_set_stop_mouse")
     }
 
     pub fn splash_show_video(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function splash_show_video")
+        unimplemented_function!("splash_show_video")
     }
 
     pub fn splash_show_image(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function splash_show_image")
+        unimplemented_function!("splash_show_image")
     }
 
     pub fn splash_show_text(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function splash_show_text")
+        unimplemented_function!("splash_show_text")
     }
 
     pub fn splash_show_web(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function splash_show_web")
+        unimplemented_function!("splash_show_web")
     }
 
     pub fn show_image(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function show_image")
+        unimplemented_function!("show_image")
     }
 
     pub fn show_video(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function show_video")
+        unimplemented_function!("show_video")
     }
 
     pub fn show_text(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function show_text")
+        unimplemented_function!("show_text")
     }
 
     pub fn show_message(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function show_message")
+        unimplemented_function!("show_message")
     }
 
     pub fn show_question(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function show_question")
+        unimplemented_function!("show_question")
     }
 
     pub fn show_error(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -6710,211 +6641,211 @@ impl Game {
 
     pub fn show_info(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function show_info")
+        unimplemented_function!("show_info")
     }
 
     pub fn load_info(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function load_info")
+        unimplemented_function!("load_info")
     }
 
     pub fn highscore_show(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function highscore_show")
+        unimplemented_function!("highscore_show")
     }
 
     pub fn highscore_set_background(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function highscore_set_background")
+        unimplemented_function!("highscore_set_background")
     }
 
     pub fn highscore_set_border(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function highscore_set_border")
+        unimplemented_function!("highscore_set_border")
     }
 
     pub fn highscore_set_font(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function highscore_set_font")
+        unimplemented_function!("highscore_set_font")
     }
 
     pub fn highscore_set_strings(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function highscore_set_strings")
+        unimplemented_function!("highscore_set_strings")
     }
 
     pub fn highscore_set_colors(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function highscore_set_colors")
+        unimplemented_function!("highscore_set_colors")
     }
 
     pub fn highscore_show_ext(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 7
-        unimplemented!("Called unimplemented kernel function highscore_show_ext")
+        unimplemented_function!("highscore_show_ext")
     }
 
     pub fn highscore_clear(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function highscore_clear")
+        unimplemented_function!("highscore_clear")
     }
 
     pub fn highscore_add(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function highscore_add")
+        unimplemented_function!("highscore_add")
     }
 
     pub fn highscore_add_current(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function highscore_add_current")
+        unimplemented_function!("highscore_add_current")
     }
 
     pub fn highscore_value(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function highscore_value")
+        unimplemented_function!("highscore_value")
     }
 
     pub fn highscore_name(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function highscore_name")
+        unimplemented_function!("highscore_name")
     }
 
     pub fn draw_highscore(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function draw_highscore")
+        unimplemented_function!("draw_highscore")
     }
 
     pub fn show_message_ext(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function show_message_ext")
+        unimplemented_function!("show_message_ext")
     }
 
     pub fn message_background(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function message_background")
+        //unimplemented_function!("message_background")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_button(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function message_button")
+        //unimplemented_function!("message_button")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_alpha(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function message_alpha")
+        //unimplemented_function!("message_alpha")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_text_font(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        //unimplemented!("Called unimplemented kernel function message_text_font")
+        //unimplemented_function!("message_text_font")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_button_font(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        //unimplemented!("Called unimplemented kernel function message_button_font")
+        //unimplemented_function!("message_button_font")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_input_font(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        //unimplemented!("Called unimplemented kernel function message_input_font")
+        //unimplemented_function!("message_input_font")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_text_charset(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        //unimplemented!("Called unimplemented kernel function message_text_charset")
+        //unimplemented_function!("message_text_charset")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_mouse_color(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function message_mouse_color")
+        //unimplemented_function!("message_mouse_color")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_input_color(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function message_input_color")
+        //unimplemented_function!("message_input_color")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_position(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        //unimplemented!("Called unimplemented kernel function message_position")
+        //unimplemented_function!("message_position")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_size(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        //unimplemented!("Called unimplemented kernel function message_size")
+        //unimplemented_function!("message_size")
         // TODO
         Ok(Default::default())
     }
 
     pub fn message_caption(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        //unimplemented!("Called unimplemented kernel function message_caption")
+        //unimplemented_function!("message_caption")
         // TODO
         Ok(Default::default())
     }
 
     pub fn show_menu(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function show_menu")
+        unimplemented_function!("show_menu")
     }
 
     pub fn show_menu_pos(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function show_menu_pos")
+        unimplemented_function!("show_menu_pos")
     }
 
     pub fn get_integer(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_integer")
+        unimplemented_function!("get_integer")
     }
 
     pub fn get_string(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_string")
+        unimplemented_function!("get_string")
     }
 
     pub fn get_color(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function get_color")
+        unimplemented_function!("get_color")
     }
 
     pub fn get_open_filename(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_open_filename")
+        unimplemented_function!("get_open_filename")
     }
 
     pub fn get_save_filename(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_save_filename")
+        unimplemented_function!("get_save_filename")
     }
 
     pub fn get_directory(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function get_directory")
+        unimplemented_function!("get_directory")
     }
 
     pub fn get_directory_alt(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_directory_alt")
+        unimplemented_function!("get_directory_alt")
     }
 
     // NB: This function is constant because numlock state is tracked.
@@ -7038,98 +6969,98 @@ impl Game {
 
     pub fn joystick_exists(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_exists")
+        //unimplemented_function!("joystick_exists")
         // TODO
         Ok(gml::FALSE.into())
     }
 
     pub fn joystick_direction(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_direction")
+        //unimplemented_function!("joystick_direction")
         // TODO
         Ok(101.into())
     }
 
     pub fn joystick_name(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_name")
+        //unimplemented_function!("joystick_name")
         // TODO
         Ok("".into())
     }
 
     pub fn joystick_axes(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_axes")
+        //unimplemented_function!("joystick_axes")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_buttons(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_buttons")
+        //unimplemented_function!("joystick_buttons")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_has_pov(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_has_pov")
+        //unimplemented_function!("joystick_has_pov")
         // TODO
         Ok(gml::FALSE.into())
     }
 
     pub fn joystick_check_button(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        //unimplemented!("Called unimplemented kernel function joystick_check_button")
+        //unimplemented_function!("joystick_check_button")
         // TODO
         Ok(gml::FALSE.into())
     }
 
     pub fn joystick_xpos(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_xpos")
+        //unimplemented_function!("joystick_xpos")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_ypos(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_ypos")
+        //unimplemented_function!("joystick_ypos")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_zpos(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_zpos")
+        //unimplemented_function!("joystick_zpos")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_rpos(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_rpos")
+        //unimplemented_function!("joystick_rpos")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_upos(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_upos")
+        //unimplemented_function!("joystick_upos")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_vpos(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_vpos")
+        //unimplemented_function!("joystick_vpos")
         // TODO
         Ok(0.into())
     }
 
     pub fn joystick_pov(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        //unimplemented!("Called unimplemented kernel function joystick_pov")
+        //unimplemented_function!("joystick_pov")
         // TODO
         Ok((-1).into())
     }
@@ -7181,147 +7112,147 @@ impl Game {
 
     pub fn mouse_wait(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mouse_wait")
+        unimplemented_function!("mouse_wait")
     }
 
     pub fn mplay_init_ipx(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_init_ipx")
+        unimplemented_function!("mplay_init_ipx")
     }
 
     pub fn mplay_init_tcpip(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_init_tcpip")
+        unimplemented_function!("mplay_init_tcpip")
     }
 
     pub fn mplay_init_modem(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function mplay_init_modem")
+        unimplemented_function!("mplay_init_modem")
     }
 
     pub fn mplay_init_serial(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function mplay_init_serial")
+        unimplemented_function!("mplay_init_serial")
     }
 
     pub fn mplay_connect_status(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_connect_status")
+        unimplemented_function!("mplay_connect_status")
     }
 
     pub fn mplay_end(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_end")
+        unimplemented_function!("mplay_end")
     }
 
     pub fn mplay_session_mode(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_session_mode")
+        unimplemented_function!("mplay_session_mode")
     }
 
     pub fn mplay_session_create(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function mplay_session_create")
+        unimplemented_function!("mplay_session_create")
     }
 
     pub fn mplay_session_find(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_session_find")
+        unimplemented_function!("mplay_session_find")
     }
 
     pub fn mplay_session_name(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_session_name")
+        unimplemented_function!("mplay_session_name")
     }
 
     pub fn mplay_session_join(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function mplay_session_join")
+        unimplemented_function!("mplay_session_join")
     }
 
     pub fn mplay_session_status(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_session_status")
+        unimplemented_function!("mplay_session_status")
     }
 
     pub fn mplay_session_end(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_session_end")
+        unimplemented_function!("mplay_session_end")
     }
 
     pub fn mplay_player_find(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_player_find")
+        unimplemented_function!("mplay_player_find")
     }
 
     pub fn mplay_player_name(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_player_name")
+        unimplemented_function!("mplay_player_name")
     }
 
     pub fn mplay_player_id(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_player_id")
+        unimplemented_function!("mplay_player_id")
     }
 
     pub fn mplay_data_write(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function mplay_data_write")
+        unimplemented_function!("mplay_data_write")
     }
 
     pub fn mplay_data_read(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_data_read")
+        unimplemented_function!("mplay_data_read")
     }
 
     pub fn mplay_data_mode(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_data_mode")
+        unimplemented_function!("mplay_data_mode")
     }
 
     pub fn mplay_message_send(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function mplay_message_send")
+        unimplemented_function!("mplay_message_send")
     }
 
     pub fn mplay_message_send_guaranteed(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function mplay_message_send_guaranteed")
+        unimplemented_function!("mplay_message_send_guaranteed")
     }
 
     pub fn mplay_message_receive(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_message_receive")
+        unimplemented_function!("mplay_message_receive")
     }
 
     pub fn mplay_message_id(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_message_id")
+        unimplemented_function!("mplay_message_id")
     }
 
     pub fn mplay_message_value(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_message_value")
+        unimplemented_function!("mplay_message_value")
     }
 
     pub fn mplay_message_player(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_message_player")
+        unimplemented_function!("mplay_message_player")
     }
 
     pub fn mplay_message_name(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function mplay_message_name")
+        unimplemented_function!("mplay_message_name")
     }
 
     pub fn mplay_message_count(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_message_count")
+        unimplemented_function!("mplay_message_count")
     }
 
     pub fn mplay_message_clear(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function mplay_message_clear")
+        unimplemented_function!("mplay_message_clear")
     }
 
     pub fn mplay_ipaddress(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -7455,92 +7386,92 @@ impl Game {
 
     pub fn external_define0(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function external_define0")
+        unimplemented_function!("external_define0")
     }
 
     pub fn external_call0(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function external_call0")
+        unimplemented_function!("external_call0")
     }
 
     pub fn external_define1(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function external_define1")
+        unimplemented_function!("external_define1")
     }
 
     pub fn external_call1(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function external_call1")
+        unimplemented_function!("external_call1")
     }
 
     pub fn external_define2(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function external_define2")
+        unimplemented_function!("external_define2")
     }
 
     pub fn external_call2(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function external_call2")
+        unimplemented_function!("external_call2")
     }
 
     pub fn external_define3(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function external_define3")
+        unimplemented_function!("external_define3")
     }
 
     pub fn external_call3(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function external_call3")
+        unimplemented_function!("external_call3")
     }
 
     pub fn external_define4(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 7
-        unimplemented!("Called unimplemented kernel function external_define4")
+        unimplemented_function!("external_define4")
     }
 
     pub fn external_call4(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function external_call4")
+        unimplemented_function!("external_call4")
     }
 
     pub fn external_define5(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function external_define5")
+        unimplemented_function!("external_define5")
     }
 
     pub fn external_call5(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function external_call5")
+        unimplemented_function!("external_call5")
     }
 
     pub fn external_define6(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function external_define6")
+        unimplemented_function!("external_define6")
     }
 
     pub fn external_call6(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 7
-        unimplemented!("Called unimplemented kernel function external_call6")
+        unimplemented_function!("external_call6")
     }
 
     pub fn external_define7(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function external_define7")
+        unimplemented_function!("external_define7")
     }
 
     pub fn external_call7(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 8
-        unimplemented!("Called unimplemented kernel function external_call7")
+        unimplemented_function!("external_call7")
     }
 
     pub fn external_define8(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function external_define8")
+        unimplemented_function!("external_define8")
     }
 
     pub fn external_call8(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 9
-        unimplemented!("Called unimplemented kernel function external_call8")
+        unimplemented_function!("external_call8")
     }
 
     pub fn execute_string(&mut self, context: &mut Context, args: &[Value]) -> gml::Result<Value> {
@@ -7587,14 +7518,16 @@ impl Game {
         #[cfg(target_os = "windows")]
         {
             use ramen::platform::win32::WindowExt as _;
-            Ok((self.window.hwnd() as usize).into())
+            Ok(self.window.as_ref().map_or(0, |window| window.hwnd() as usize).into())
         }
         // TODO: Others! (They'll compile error here so it'll remind me)
     }
 
-    pub fn show_debug_message(&self, args: &[Value]) -> gml::Result<Value> {
+    pub fn show_debug_message(&mut self, args: &[Value]) -> gml::Result<Value> {
         let message = expect_args!(args, [any])?;
-        println!("{}", self.decode_str(message.repr().as_ref()));
+        let text = self.decode_str(message.repr().as_ref()).into_owned();
+        println!("{}", text);
+        self.game_log.push(text);
         Ok(Default::default())
     }
 
@@ -7751,17 +7684,17 @@ impl Game {
 
     pub fn clipboard_has_text(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function clipboard_has_text")
+        unimplemented_function!("clipboard_has_text")
     }
 
     pub fn clipboard_set_text(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function clipboard_set_text")
+        unimplemented_function!("clipboard_set_text")
     }
 
     pub fn clipboard_get_text(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function clipboard_get_text")
+        unimplemented_function!("clipboard_get_text")
     }
 
     pub fn date_current_datetime(&self, args: &[Value]) -> gml::Result<Value> {
@@ -7813,12 +7746,12 @@ impl Game {
 
     pub fn date_inc_year(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_inc_year")
+        unimplemented_function!("date_inc_year")
     }
 
     pub fn date_inc_month(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_inc_month")
+        unimplemented_function!("date_inc_month")
     }
 
     pub fn date_inc_week(args: &[Value]) -> gml::Result<Value> {
@@ -7912,97 +7845,97 @@ impl Game {
 
     pub fn date_year_span(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_year_span")
+        unimplemented_function!("date_year_span")
     }
 
     pub fn date_month_span(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_month_span")
+        unimplemented_function!("date_month_span")
     }
 
     pub fn date_week_span(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_week_span")
+        unimplemented_function!("date_week_span")
     }
 
     pub fn date_day_span(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_day_span")
+        unimplemented_function!("date_day_span")
     }
 
     pub fn date_hour_span(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_hour_span")
+        unimplemented_function!("date_hour_span")
     }
 
     pub fn date_minute_span(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_minute_span")
+        unimplemented_function!("date_minute_span")
     }
 
     pub fn date_second_span(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_second_span")
+        unimplemented_function!("date_second_span")
     }
 
     pub fn date_compare_datetime(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_compare_datetime")
+        unimplemented_function!("date_compare_datetime")
     }
 
     pub fn date_compare_date(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_compare_date")
+        unimplemented_function!("date_compare_date")
     }
 
     pub fn date_compare_time(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function date_compare_time")
+        unimplemented_function!("date_compare_time")
     }
 
     pub fn date_date_of(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_date_of")
+        unimplemented_function!("date_date_of")
     }
 
     pub fn date_time_of(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_time_of")
+        unimplemented_function!("date_time_of")
     }
 
     pub fn date_datetime_string(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_datetime_string")
+        unimplemented_function!("date_datetime_string")
     }
 
     pub fn date_date_string(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_date_string")
+        unimplemented_function!("date_date_string")
     }
 
     pub fn date_time_string(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_time_string")
+        unimplemented_function!("date_time_string")
     }
 
     pub fn date_days_in_month(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_days_in_month")
+        unimplemented_function!("date_days_in_month")
     }
 
     pub fn date_days_in_year(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_days_in_year")
+        unimplemented_function!("date_days_in_year")
     }
 
     pub fn date_leap_year(_args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_leap_year")
+        unimplemented_function!("date_leap_year")
     }
 
     pub fn date_is_today(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function date_is_today")
+        unimplemented_function!("date_is_today")
     }
 
     pub fn sprite_exists(&self, args: &[Value]) -> gml::Result<Value> {
@@ -8138,6 +8071,7 @@ impl Game {
     pub fn sprite_create_from_screen(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (x, y, width, height, transparency, smooth, origin_x, origin_y) =
             expect_args!(args, [int, int, int, int, int, bool, int, int])?;
+        self.check_readback("sprite_create_from_screen")?;
         let (removeback, fill_transparent) = match self.gm_version {
             Version::GameMaker8_0 => (transparency != 0, true),
             Version::GameMaker8_1 => (transparency == 1, transparency != 2),
@@ -8167,7 +8101,7 @@ impl Game {
         }];
         let sprite_id = self.assets.sprites.len();
         self.assets.sprites.push(Some(Box::new(asset::Sprite {
-            name: format!("__newsprite{}", sprite_id).into(),
+            name: self.compiler.register_runtime_asset("__newsprite", sprite_id),
             frames,
             bbox_left: colliders[0].bbox_left,
             bbox_right: colliders[0].bbox_right,
@@ -8186,6 +8120,7 @@ impl Game {
     pub fn sprite_add_from_screen(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (sprite_id, x, y, width, height, removeback, smooth) =
             expect_args!(args, [int, int, int, int, int, bool, bool])?;
+        self.check_readback("sprite_add_from_screen")?;
         if let Some(sprite) = self.assets.sprites.get_asset_mut(sprite_id) {
             // get image
             let x = x.max(0);
@@ -8237,6 +8172,7 @@ impl Game {
     pub fn sprite_create_from_surface(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (surf_id, x, y, width, height, transparency, smooth, origin_x, origin_y) =
             expect_args!(args, [int, int, int, int, int, int, bool, int, int])?;
+        self.check_readback("sprite_create_from_surface")?;
         if self.surface_target == Some(surf_id) {
             self.renderer.flush_queue();
         }
@@ -8267,7 +8203,7 @@ impl Game {
             }];
             let sprite_id = self.assets.sprites.len();
             self.assets.sprites.push(Some(Box::new(asset::Sprite {
-                name: format!("__newsprite{}", sprite_id).into(),
+                name: self.compiler.register_runtime_asset("__newsprite", sprite_id),
                 frames,
                 bbox_left: colliders[0].bbox_left,
                 bbox_right: colliders[0].bbox_right,
@@ -8292,6 +8228,7 @@ impl Game {
     pub fn sprite_add_from_surface(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (sprite_id, surf_id, x, y, width, height, removeback, smooth) =
             expect_args!(args, [int, int, int, int, int, int, bool, bool])?;
+        self.check_readback("sprite_add_from_surface")?;
         if let Some(sprite) = self.assets.sprites.get_asset_mut(sprite_id) {
             if let Some(surf) = self.surfaces.get_asset(surf_id) {
                 // get image
@@ -8381,7 +8318,7 @@ impl Game {
             .collect::<gml::Result<_>>()?;
         let sprite_id = self.assets.sprites.len();
         self.assets.sprites.push(Some(Box::new(asset::Sprite {
-            name: format!("__newsprite{}", sprite_id).into(),
+            name: self.compiler.register_runtime_asset("__newsprite", sprite_id),
             frames,
             bbox_left: colliders[0].bbox_left,
             bbox_right: colliders[0].bbox_right,
@@ -8402,7 +8339,9 @@ impl Game {
             expect_args!(args, [int, string, int, bool, bool, int, int])?;
         if let Some(sprite) = self.assets.sprites.get_asset_mut(sprite_id) {
             for frame in &sprite.frames {
-                self.renderer.delete_sprite(frame.atlas_ref);
+                if self.assets.shared_textures.release(frame.atlas_ref) {
+                    self.renderer.delete_sprite(frame.atlas_ref);
+                }
             }
             let imgnumb = imgnumb.max(1) as usize;
             let mut images = match file::load_animation(fname.as_ref(), imgnumb) {
@@ -8454,20 +8393,23 @@ impl Game {
 
     pub fn sprite_add_sprite(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function sprite_add_sprite")
+        unimplemented_function!("sprite_add_sprite")
     }
 
     pub fn sprite_replace_sprite(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function sprite_replace_sprite")
+        unimplemented_function!("sprite_replace_sprite")
     }
 
     pub fn sprite_delete(&mut self, args: &[Value]) -> gml::Result<Value> {
         let sprite_id = expect_args!(args, [int])?;
         if let Some(sprite) = self.assets.sprites.get_asset(sprite_id) {
             for frame in &sprite.frames {
-                self.renderer.delete_sprite(frame.atlas_ref);
+                if self.assets.shared_textures.release(frame.atlas_ref) {
+                    self.renderer.delete_sprite(frame.atlas_ref);
+                }
             }
+            self.compiler.unregister_runtime_asset(sprite.name.as_ref());
         } else {
             return Err(gml::Error::FunctionError("sprite_delete".into(), "Trying to delete non-existing sprite".into()))
         }
@@ -8477,7 +8419,7 @@ impl Game {
 
     pub fn sprite_duplicate(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function sprite_duplicate")
+        unimplemented_function!("sprite_duplicate")
     }
 
     pub fn sprite_assign(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -8485,7 +8427,9 @@ impl Game {
         if let Some(src) = self.assets.sprites.get_asset(src_id) {
             if let Some(sprite) = self.assets.sprites.get_asset(dst_id) {
                 for frame in &sprite.frames {
-                    self.renderer.delete_sprite(frame.atlas_ref);
+                    if self.assets.shared_textures.release(frame.atlas_ref) {
+                        self.renderer.delete_sprite(frame.atlas_ref);
+                    }
                 }
             }
             if dst_id >= 0 && self.assets.sprites.len() > dst_id as usize {
@@ -8528,7 +8472,7 @@ impl Game {
 
     pub fn sprite_merge(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function sprite_merge")
+        unimplemented_function!("sprite_merge")
     }
 
     pub fn sprite_save(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -8551,7 +8495,7 @@ impl Game {
 
     pub fn sprite_save_strip(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function sprite_save_strip")
+        unimplemented_function!("sprite_save_strip")
     }
 
     pub fn sprite_collision_mask(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -8684,6 +8628,7 @@ impl Game {
 
     pub fn background_create_from_screen(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (x, y, width, height, removeback, smooth) = expect_args!(args, [int, int, int, int, bool, bool])?;
+        self.check_readback("background_create_from_screen")?;
         let x = x.max(0);
         let y = y.max(0);
         let width = width.min(self.unscaled_width as i32 - x);
@@ -8694,7 +8639,7 @@ impl Game {
         asset::sprite::process_image(&mut image, removeback, smooth, true);
         let background_id = self.assets.backgrounds.len();
         self.assets.backgrounds.push(Some(Box::new(asset::Background {
-            name: format!("__newbackground{}", background_id).into(),
+            name: self.compiler.register_runtime_asset("__newbackground", background_id),
             width: width as _,
             height: height as _,
             atlas_ref: Some(
@@ -8709,6 +8654,7 @@ impl Game {
     pub fn background_create_from_surface(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (surf_id, x, y, width, height, removeback, smooth) =
             expect_args!(args, [int, int, int, int, int, bool, bool])?;
+        self.check_readback("background_create_from_surface")?;
         if self.surface_target == Some(surf_id) {
             self.renderer.flush_queue();
         }
@@ -8722,7 +8668,7 @@ impl Game {
             asset::sprite::process_image(&mut image, removeback, smooth, true);
             let background_id = self.assets.backgrounds.len();
             self.assets.backgrounds.push(Some(Box::new(asset::Background {
-                name: format!("__newbackground{}", background_id).into(),
+                name: self.compiler.register_runtime_asset("__newbackground", background_id),
                 width: width as _,
                 height: height as _,
                 atlas_ref: Some(
@@ -8744,7 +8690,7 @@ impl Game {
         let (w, h, col) = expect_args!(args, [int, int, int])?;
         let background_id = self.assets.backgrounds.len();
         self.assets.backgrounds.push(Some(Box::new(asset::Background {
-            name: format!("__newbackground{}", background_id).into(),
+            name: self.compiler.register_runtime_asset("__newbackground", background_id),
             width: w as _,
             height: h as _,
             atlas_ref: Some(
@@ -8758,7 +8704,7 @@ impl Game {
 
     pub fn background_create_gradient(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function background_create_gradient")
+        unimplemented_function!("background_create_gradient")
     }
 
     pub fn background_add(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -8779,7 +8725,7 @@ impl Game {
             .map_err(|e| gml::Error::FunctionError("background_add".into(), e.into()))?;
         let background_id = self.assets.backgrounds.len();
         self.assets.backgrounds.push(Some(Box::new(asset::Background {
-            name: format!("__newbackground{}", background_id).into(),
+            name: self.compiler.register_runtime_asset("__newbackground", background_id),
             width,
             height,
             atlas_ref: Some(atlas_ref),
@@ -8821,12 +8767,12 @@ impl Game {
 
     pub fn background_add_background(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function background_add_background")
+        unimplemented_function!("background_add_background")
     }
 
     pub fn background_replace_background(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function background_replace_background")
+        unimplemented_function!("background_replace_background")
     }
 
     pub fn background_delete(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -8835,6 +8781,7 @@ impl Game {
             if let Some(atlas_ref) = background.atlas_ref {
                 self.renderer.delete_sprite(atlas_ref);
             }
+            self.compiler.unregister_runtime_asset(background.name.as_ref());
         } else {
             return Err(gml::Error::FunctionError(
                 "background_delete".into(),
@@ -8857,7 +8804,7 @@ impl Game {
             let dst_id = self.assets.backgrounds.len();
             let (width, height) = (src.width, src.height);
             self.assets.backgrounds.push(Some(Box::new(asset::Background {
-                name: format!("__newbackground{}", dst_id).into(),
+                name: self.compiler.register_runtime_asset("__newbackground", dst_id),
                 width,
                 height,
                 atlas_ref,
@@ -8945,13 +8892,19 @@ impl Game {
     }
 
     pub fn sound_discard(&mut self, args: &[Value]) -> gml::Result<Value> {
-        // Dynamically un-preloads a sound, but we preload all sounds, so all we need to do is call sound_stop()
+        // Dynamically un-preloads a sound. Preloaded sounds stay loaded, but one with preload turned off gets
+        // decoded again the next time it's played, which the game can't tell apart from it staying decoded.
+        let sound_id = expect_args!(args, [int])?;
+        self.audio.discard(sound_id);
         self.sound_stop(args)
     }
 
     pub fn sound_restore(&mut self, args: &[Value]) -> gml::Result<Value> {
-        let _sound_id = expect_args!(args, [int])?;
-        // Dynamically preloads a sound, but we preload all sounds so this does nothing
+        let sound_id = expect_args!(args, [int])?;
+        // Dynamically preloads a sound, so one with preload turned off starts decoding in the background
+        if let Some(asset::sound::FileType::Lazy(handle)) = self.assets.sounds.get_asset(sound_id).map(|x| &x.handle) {
+            self.audio.prepare(handle);
+        }
         Ok(Default::default())
     }
 
@@ -8964,6 +8917,18 @@ impl Game {
         };
         let sound_id = self.assets.sounds.len() as i32;
         let handle = match path_buf.extension().and_then(std::ffi::OsStr::to_str) {
+            Some("mp3") if !preload => match self.audio.add_lazy_mp3(data, sound_id) {
+                Some(x) => asset::sound::FileType::Lazy(x),
+                None => return Ok((-1).into()),
+            },
+            Some("wav") if !preload => match self.audio.add_lazy_wav(data, sound_id, 1.0, kind == 2, kind >= 3) {
+                Some(x) => asset::sound::FileType::Lazy(x),
+                None => return Ok((-1).into()),
+            },
+            Some("mid") | Some("midi") => match self.audio.add_midi(data, sound_id as i32) {
+                Some(x) => asset::sound::FileType::Midi(x),
+                None => return Ok((-1).into()),
+            },
             Some("mp3") => match self.audio.add_mp3(data, sound_id as i32) {
                 Some(x) => asset::sound::FileType::Mp3(x),
                 None => return Ok((-1).into()),
@@ -8975,7 +8940,7 @@ impl Game {
             _ => return Ok((-1).into()),
         };
         self.assets.sounds.push(Some(Box::new(asset::Sound {
-            name: format!("__newsound{}", sound_id).into(),
+            name: self.compiler.register_runtime_asset("__newsound", sound_id as usize),
             handle,
             gml_kind: kind.into(),
             gml_preload: f64::from(u8::from(preload)).into(),
@@ -8997,6 +8962,20 @@ impl Game {
                     Err(_) => return Ok(0.into()),
                 };
                 sound.handle = match path_buf.extension().and_then(std::ffi::OsStr::to_str) {
+                    Some("mp3") if !preload => match self.audio.add_lazy_mp3(data, sound_id) {
+                        Some(x) => asset::sound::FileType::Lazy(x),
+                        None => return Ok(0.into()),
+                    },
+                    Some("wav") if !preload => {
+                        match self.audio.add_lazy_wav(data, sound_id, 1.0, kind == 2, kind >= 3) {
+                            Some(x) => asset::sound::FileType::Lazy(x),
+                            None => return Ok(0.into()),
+                        }
+                    },
+                    Some("mid") | Some("midi") => match self.audio.add_midi(data, sound_id as i32) {
+                        Some(x) => asset::sound::FileType::Midi(x),
+                        None => return Ok(0.into()),
+                    },
                     Some("mp3") => match self.audio.add_mp3(data, sound_id as i32) {
                         Some(x) => asset::sound::FileType::Mp3(x),
                         None => return Ok(0.into()),
@@ -9022,7 +9001,9 @@ impl Game {
     pub fn sound_delete(&mut self, args: &[Value]) -> gml::Result<Value> {
         let sound_id = expect_args!(args, [int])?;
         self.audio.stop_sound(sound_id);
-        if self.assets.sounds.get_asset(sound_id).is_some() {
+        self.audio.discard(sound_id);
+        if let Some(sound) = self.assets.sounds.get_asset(sound_id) {
+            self.compiler.unregister_runtime_asset(sound.name.as_ref());
             self.assets.sounds[sound_id as usize] = None;
         }
         Ok(Default::default())
@@ -9070,12 +9051,12 @@ impl Game {
 
     pub fn font_add(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function font_add")
+        unimplemented_function!("font_add")
     }
 
     pub fn font_replace(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 7
-        unimplemented!("Called unimplemented kernel function font_replace")
+        unimplemented_function!("font_replace")
     }
 
     pub fn font_add_sprite(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -9086,7 +9067,7 @@ impl Game {
             let first = first.clamp(0, 255) as _;
             let last = (first as usize + chars.len() - 1).min(255) as _;
             self.assets.fonts.push(Some(Box::new(asset::Font {
-                name: format!("__newfont{}", font_id).into(),
+                name: self.compiler.register_runtime_asset("__newfont", font_id),
                 sys_name: "".into(),
                 charset: 1,
                 size: 12,
@@ -9120,7 +9101,7 @@ impl Game {
                 font.bold = false;
                 font.italic = false;
                 font.first = first.clamp(0, 255) as _;
-                font.last = (first as usize + chars.len() - 1).min(255) as _;
+                font.last = (font.first as usize + chars.len() - 1).min(255) as _;
                 font.chars = chars;
                 font.own_graphics = false;
                 Ok(Default::default())
@@ -9134,7 +9115,7 @@ impl Game {
 
     pub fn font_delete(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function font_delete")
+        unimplemented_function!("font_delete")
     }
 
     pub fn script_exists(&self, args: &[Value]) -> gml::Result<Value> {
@@ -9162,7 +9143,7 @@ impl Game {
                     *dest = src.clone();
                 }
                 let mut new_context = Context::copy_with_args(context, new_args, args.len() - 1);
-                self.execute(&instructions, &mut new_context)?;
+                self.execute(&instructions, &mut new_context).map_err(|e| self.script_error(script_id, e))?;
                 Ok(new_context.return_value)
             } else {
                 Err(gml::Error::NonexistentAsset(asset::Type::Script, script_id))
@@ -9319,7 +9300,7 @@ impl Game {
         expect_args!(args, [])?;
         let path_id = self.assets.paths.len();
         self.assets.paths.push(Some(Box::new(asset::Path {
-            name: format!("__newpath{}", path_id).into(),
+            name: self.compiler.register_runtime_asset("__newpath", path_id),
             points: Vec::new(),
             control_nodes: Default::default(),
             length: Default::default(),
@@ -9341,7 +9322,7 @@ impl Game {
             .get_asset(src_id)
             .ok_or_else(|| gml::Error::NonexistentAsset(asset::Type::Path, src_id))?
             .clone();
-        path.name = format!("__newpath{}", dst_id).into();
+        path.name = self.compiler.register_runtime_asset("__newpath", dst_id as usize);
         self.assets.paths.push(Some(path));
         Ok(dst_id.into())
     }
@@ -9368,12 +9349,13 @@ impl Game {
 
     pub fn path_append(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function path_append")
+        unimplemented_function!("path_append")
     }
 
     pub fn path_delete(&mut self, args: &[Value]) -> gml::Result<Value> {
         let path_id = expect_args!(args, [int])?;
-        if self.assets.paths.get_asset(path_id).is_some() {
+        if let Some(path) = self.assets.paths.get_asset(path_id) {
+            self.compiler.unregister_runtime_asset(path.name.as_ref());
             self.assets.paths[path_id as usize] = None;
         }
         Ok(Default::default())
@@ -9390,7 +9372,7 @@ impl Game {
 
     pub fn path_insert_point(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function path_insert_point")
+        unimplemented_function!("path_insert_point")
     }
 
     pub fn path_change_point(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -9410,12 +9392,12 @@ impl Game {
 
     pub fn path_delete_point(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function path_delete_point")
+        unimplemented_function!("path_delete_point")
     }
 
     pub fn path_clear_points(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function path_clear_points")
+        unimplemented_function!("path_clear_points")
     }
 
     pub fn path_reverse(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -9472,7 +9454,7 @@ impl Game {
         Ok(Default::default())
     }
 
-    pub fn path_scale(&mut self, args: &[Value]) -> gml::Result<Value> {
+    pub fn path_rescale(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (id, xscale, yscale) = expect_args!(args, [int, real, real])?;
         if let Some(path) = self.assets.paths.get_asset_mut(id) {
             let (xcenter, ycenter) = path.center();
@@ -9511,7 +9493,7 @@ impl Game {
         expect_args!(args, [])?;
         let idx = self.assets.timelines.len();
         self.assets.timelines.push(Some(Box::new(asset::Timeline {
-            name: format!("__newtimeline{}", idx).into(),
+            name: self.compiler.register_runtime_asset("__newtimeline", idx),
             moments: Default::default(),
         })));
 
@@ -9520,7 +9502,8 @@ impl Game {
 
     pub fn timeline_delete(&mut self, args: &[Value]) -> gml::Result<Value> {
         let timeline = expect_args!(args, [int])?;
-        if self.assets.timelines.get_asset(timeline).is_some() {
+        if let Some(asset) = self.assets.timelines.get_asset(timeline) {
+            self.compiler.unregister_runtime_asset(asset.name.as_ref());
             self.assets.timelines[timeline as usize] = None;
             Ok(Default::default())
         } else {
@@ -9732,7 +9715,7 @@ impl Game {
         let id = self.assets.objects.len() as i32;
         let children = Default::default();
         let object = Box::new(asset::Object {
-            name: format!("__newobject{}", id).into(),
+            name: self.compiler.register_runtime_asset("__newobject", id as usize),
             solid: false,
             visible: true,
             persistent: false,
@@ -9752,7 +9735,7 @@ impl Game {
 
     pub fn object_delete(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function object_delete")
+        unimplemented_function!("object_delete")
     }
 
     pub fn object_event_clear(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -9829,7 +9812,7 @@ impl Game {
 
     pub fn room_set_code(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function room_set_code")
+        unimplemented_function!("room_set_code")
     }
 
     pub fn room_set_background_color(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -9919,7 +9902,7 @@ impl Game {
         expect_args!(args, [])?;
         let room_id = self.assets.rooms.len();
         self.assets.rooms.push(Some(Box::new(asset::Room {
-            name: format!("__newroom{}", room_id).into(),
+            name: self.compiler.register_runtime_asset("__newroom", room_id),
             caption: "".into(),
             width: 640,
             height: 480,
@@ -9982,7 +9965,7 @@ impl Game {
             .get_asset(src_id)
             .ok_or_else(|| gml::Error::NonexistentAsset(asset::Type::Room, src_id))?
             .clone();
-        room.name = format!("__newroom{}", dst_id).into();
+        room.name = self.compiler.register_runtime_asset("__newroom", dst_id as usize);
         self.assets.rooms.push(Some(room));
         Ok(dst_id.into())
     }
@@ -10040,17 +10023,17 @@ impl Game {
 
     pub fn room_tile_add(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 9
-        unimplemented!("Called unimplemented kernel function room_tile_add")
+        unimplemented_function!("room_tile_add")
     }
 
     pub fn room_tile_add_ext(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 12
-        unimplemented!("Called unimplemented kernel function room_tile_add_ext")
+        unimplemented_function!("room_tile_add_ext")
     }
 
     pub fn room_tile_clear(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function room_tile_clear")
+        unimplemented_function!("room_tile_clear")
     }
 
     pub fn part_type_create(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -11134,12 +11117,12 @@ impl Game {
 
     pub fn ds_queue_write(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function ds_queue_write")
+        unimplemented_function!("ds_queue_write")
     }
 
     pub fn ds_queue_read(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function ds_queue_read")
+        unimplemented_function!("ds_queue_read")
     }
 
     pub fn ds_list_create(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -11291,11 +11274,12 @@ impl Game {
         let (id, asc) = expect_args!(args, [int, bool])?;
         match self.lists.get_mut(id) {
             Some(list) => {
+                // sort_by is stable, so equal values stay in the same order whichever way it's sorted
                 let precision = self.ds_precision; // otherwise we get borrowing issues
                 if asc {
-                    list.sort_by(|x, y| ds::cmp(x, y, precision));
+                    list.sort_by(|x, y| ds::sort_cmp(x, y, precision));
                 } else {
-                    list.sort_by(|x, y| ds::cmp(y, x, precision));
+                    list.sort_by(|x, y| ds::sort_cmp(y, x, precision));
                 }
                 Ok(Default::default())
             },
@@ -11307,11 +11291,7 @@ impl Game {
         let id = expect_args!(args, [int])?;
         match self.lists.get_mut(id) {
             Some(list) => {
-                for _ in 1..list.len() {
-                    let id1 = self.rand.next_int(list.len() as u32 - 1);
-                    let id2 = self.rand.next_int(list.len() as u32 - 1);
-                    list.swap(id1 as usize, id2 as usize);
-                }
+                ds::shuffle(list, &mut self.rand);
                 Ok(Default::default())
             },
             None => {
@@ -11982,12 +11962,12 @@ impl Game {
 
     pub fn ds_grid_add_region(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function ds_grid_add_region")
+        unimplemented_function!("ds_grid_add_region")
     }
 
     pub fn ds_grid_multiply_region(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function ds_grid_multiply_region")
+        unimplemented_function!("ds_grid_multiply_region")
     }
 
     pub fn ds_grid_set_disk(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -12004,27 +11984,27 @@ impl Game {
 
     pub fn ds_grid_add_disk(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function ds_grid_add_disk")
+        unimplemented_function!("ds_grid_add_disk")
     }
 
     pub fn ds_grid_multiply_disk(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function ds_grid_multiply_disk")
+        unimplemented_function!("ds_grid_multiply_disk")
     }
 
     pub fn ds_grid_set_grid_region(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 8
-        unimplemented!("Called unimplemented kernel function ds_grid_set_grid_region")
+        unimplemented_function!("ds_grid_set_grid_region")
     }
 
     pub fn ds_grid_add_grid_region(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 8
-        unimplemented!("Called unimplemented kernel function ds_grid_add_grid_region")
+        unimplemented_function!("ds_grid_add_grid_region")
     }
 
     pub fn ds_grid_multiply_grid_region(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 8
-        unimplemented!("Called unimplemented kernel function ds_grid_multiply_grid_region")
+        unimplemented_function!("ds_grid_multiply_grid_region")
     }
 
     pub fn ds_grid_get(&self, args: &[Value]) -> gml::Result<Value> {
@@ -12094,22 +12074,22 @@ impl Game {
 
     pub fn ds_grid_get_disk_sum(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function ds_grid_get_disk_sum")
+        unimplemented_function!("ds_grid_get_disk_sum")
     }
 
     pub fn ds_grid_get_disk_max(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function ds_grid_get_disk_max")
+        unimplemented_function!("ds_grid_get_disk_max")
     }
 
     pub fn ds_grid_get_disk_min(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function ds_grid_get_disk_min")
+        unimplemented_function!("ds_grid_get_disk_min")
     }
 
     pub fn ds_grid_get_disk_mean(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function ds_grid_get_disk_mean")
+        unimplemented_function!("ds_grid_get_disk_mean")
     }
 
     pub fn ds_grid_value_exists(&self, args: &[Value]) -> gml::Result<Value> {
@@ -12191,7 +12171,7 @@ impl Game {
 
     pub fn ds_grid_shuffle(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function ds_grid_shuffle")
+        unimplemented_function!("ds_grid_shuffle")
     }
 
     pub fn ds_grid_write(&self, args: &[Value]) -> gml::Result<Value> {
@@ -12249,8 +12229,10 @@ impl Game {
             use asset::sound::FileType;
             let nanos = self.spoofed_time_nanos.unwrap_or_else(|| datetime::now_as_nanos());
             match &sound.handle {
+                FileType::Midi(handle) => self.audio.play_midi(handle, nanos),
                 FileType::Mp3(handle) => self.audio.play_mp3(handle, nanos),
                 FileType::Wav(handle) => self.audio.play_wav(handle, nanos),
+                FileType::Lazy(handle) => self.audio.play_lazy(handle, nanos),
                 FileType::None => (),
             }
             Ok(Default::default())
@@ -12263,9 +12245,12 @@ impl Game {
         let sound_id = expect_args!(args, [int])?;
         if let Some(sound) = self.assets.sounds.get_asset(sound_id) {
             use asset::sound::FileType;
+            let nanos = self.spoofed_time_nanos.unwrap_or_else(|| datetime::now_as_nanos());
             match &sound.handle {
-                FileType::Mp3(handle) => self.audio.loop_mp3(handle),
-                FileType::Wav(handle) => self.audio.loop_wav(handle),
+                FileType::Midi(handle) => self.audio.loop_midi(handle, nanos),
+                FileType::Mp3(handle) => self.audio.loop_mp3(handle, nanos),
+                FileType::Wav(handle) => self.audio.loop_wav(handle, nanos),
+                FileType::Lazy(handle) => self.audio.loop_lazy(handle, nanos),
                 FileType::None => (),
             }
             Ok(Default::default())
@@ -12294,10 +12279,11 @@ impl Game {
     pub fn sound_volume(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (sound_id, volume) = expect_args!(args, [int, real])?;
         if let Some(sound) = self.assets.sounds.get_asset(sound_id) {
-            // Deliberately written in a way that will produce an error when Kind::Midi is added
             use asset::sound::FileType;
             match &sound.handle {
                 FileType::Wav(handle) => handle.set_volume(volume.into()),
+                FileType::Lazy(handle) => handle.set_volume(volume.into()),
+                FileType::Midi(_) => (),
                 FileType::Mp3(_) => (),
                 FileType::None => (),
             }
@@ -12309,12 +12295,12 @@ impl Game {
 
     pub fn sound_fade(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function sound_fade")
+        unimplemented_function!("sound_fade")
     }
 
     pub fn sound_pan(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function sound_pan")
+        unimplemented_function!("sound_pan")
     }
 
     pub fn sound_background_tempo(&mut self, _args: &[Value]) -> gml::Result<Value> {
@@ -12331,162 +12317,162 @@ impl Game {
 
     pub fn sound_set_search_directory(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function sound_set_search_directory")
+        unimplemented_function!("sound_set_search_directory")
     }
 
     pub fn sound_effect_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function sound_effect_set")
+        unimplemented_function!("sound_effect_set")
     }
 
     pub fn sound_effect_chorus(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 8
-        unimplemented!("Called unimplemented kernel function sound_effect_chorus")
+        unimplemented_function!("sound_effect_chorus")
     }
 
     pub fn sound_effect_compressor(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 7
-        unimplemented!("Called unimplemented kernel function sound_effect_compressor")
+        unimplemented_function!("sound_effect_compressor")
     }
 
     pub fn sound_effect_echo(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 6
-        unimplemented!("Called unimplemented kernel function sound_effect_echo")
+        unimplemented_function!("sound_effect_echo")
     }
 
     pub fn sound_effect_flanger(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 8
-        unimplemented!("Called unimplemented kernel function sound_effect_flanger")
+        unimplemented_function!("sound_effect_flanger")
     }
 
     pub fn sound_effect_gargle(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function sound_effect_gargle")
+        unimplemented_function!("sound_effect_gargle")
     }
 
     pub fn sound_effect_equalizer(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function sound_effect_equalizer")
+        unimplemented_function!("sound_effect_equalizer")
     }
 
     pub fn sound_effect_reverb(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 5
-        unimplemented!("Called unimplemented kernel function sound_effect_reverb")
+        unimplemented_function!("sound_effect_reverb")
     }
 
     pub fn sound_3d_set_sound_position(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function sound_3d_set_sound_position")
+        unimplemented_function!("sound_3d_set_sound_position")
     }
 
     pub fn sound_3d_set_sound_velocity(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function sound_3d_set_sound_velocity")
+        unimplemented_function!("sound_3d_set_sound_velocity")
     }
 
     pub fn sound_3d_set_sound_distance(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function sound_3d_set_sound_distance")
+        unimplemented_function!("sound_3d_set_sound_distance")
     }
 
     pub fn sound_3d_set_sound_cone(&mut self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 7
-        unimplemented!("Called unimplemented kernel function sound_3d_set_sound_cone")
+        unimplemented_function!("sound_3d_set_sound_cone")
     }
 
     pub fn cd_init(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_init")
+        unimplemented_function!("cd_init")
     }
 
     pub fn cd_present(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_present")
+        unimplemented_function!("cd_present")
     }
 
     pub fn cd_number(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_number")
+        unimplemented_function!("cd_number")
     }
 
     pub fn cd_playing(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_playing")
+        unimplemented_function!("cd_playing")
     }
 
     pub fn cd_paused(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_paused")
+        unimplemented_function!("cd_paused")
     }
 
     pub fn cd_track(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_track")
+        unimplemented_function!("cd_track")
     }
 
     pub fn cd_length(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_length")
+        unimplemented_function!("cd_length")
     }
 
     pub fn cd_track_length(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function cd_track_length")
+        unimplemented_function!("cd_track_length")
     }
 
     pub fn cd_position(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_position")
+        unimplemented_function!("cd_position")
     }
 
     pub fn cd_track_position(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_track_position")
+        unimplemented_function!("cd_track_position")
     }
 
     pub fn cd_play(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function cd_play")
+        unimplemented_function!("cd_play")
     }
 
     pub fn cd_stop(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_stop")
+        unimplemented_function!("cd_stop")
     }
 
     pub fn cd_pause(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_pause")
+        unimplemented_function!("cd_pause")
     }
 
     pub fn cd_resume(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_resume")
+        unimplemented_function!("cd_resume")
     }
 
     pub fn cd_set_position(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function cd_set_position")
+        unimplemented_function!("cd_set_position")
     }
 
     pub fn cd_set_track_position(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function cd_set_track_position")
+        unimplemented_function!("cd_set_track_position")
     }
 
     pub fn cd_open_door(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_open_door")
+        unimplemented_function!("cd_open_door")
     }
 
     pub fn cd_close_door(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function cd_close_door")
+        unimplemented_function!("cd_close_door")
     }
 
     pub fn mci_command(&self, _args: &[Value]) -> gml::Result<Value> {
         // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function MCI_command")
+        unimplemented_function!("MCI_command")
     }
 
     pub fn d3d_start(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -13814,3 +13800,142 @@ impl Game {
         Ok(Default::default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::testing::{self, add_object, run, set_event};
+
+    #[test]
+    fn room_goto_errors() {
+        // the synthetic game only has the one room, so it's both the first and the last
+        let mut game = testing::synthetic_game();
+        let error = game.room_goto_next(&[]).unwrap_err();
+        assert!(matches!(error, gml::Error::NextRoomAfterLast));
+        assert_eq!(error.to_string(), "Moving to next room after the last room.");
+        let error = game.room_goto_previous(&[]).unwrap_err();
+        assert!(matches!(error, gml::Error::PreviousRoomBeforeFirst));
+        assert_eq!(error.to_string(), "Moving to previous room before the first room.");
+
+        // a room that doesn't exist fails straight away, rather than when the room would be loaded
+        for id in [-1, 1, 100] {
+            let error = game.room_goto(&[id.into()]).unwrap_err();
+            assert!(matches!(error, gml::Error::UnexistingRoom(x) if x == id));
+            assert_eq!(error.to_string(), format!("Unexisting room number: {}", id));
+        }
+        assert!(game.scene_change.is_none());
+        game.room_goto(&[0.into()]).unwrap();
+        assert!(matches!(game.scene_change, Some(SceneChange::Room(0))));
+    }
+
+    #[test]
+    fn instance_change_and_copy() {
+        let mut game = testing::synthetic_game();
+        game.assets.sprites.push(Some(Box::new(testing::sprite())));
+        let parent = add_object(&mut game, "obj_parent", -1);
+        let child = add_object(&mut game, "obj_child", parent);
+        let object = game.assets.objects[child as usize].as_mut().unwrap();
+        object.solid = true;
+        object.sprite_index = game.assets.sprites.len() as i32 - 1;
+        // the digits of score are a trace of which events ran, and what object_index was in each
+        set_event(&mut game, 0, gml::ev::DESTROY, 0, "score = score * 10 + 1");
+        set_event(&mut game, parent, gml::ev::CREATE, 0, "score = score * 10 + object_index");
+
+        // obj_counter's destroy event runs, then the create event obj_child inherits, which already sees obj_child
+        run(&mut game, &format!("with (0) {{ alarm[0] = 7; instance_change({}, true) }}", child));
+        assert_eq!(game.score, 10 + child);
+        let list = &game.room.instance_list;
+        assert_eq!((list.count(0), list.count(parent), list.count(child)), (0, 1, 1));
+        let handle = list.get_by_instid(100001).unwrap();
+        let instance = list.get(handle);
+        assert_eq!(instance.object_index.get(), child);
+        assert!(instance.solid.get());
+        assert_eq!(instance.sprite_index.get(), -1);
+        assert_eq!(instance.alarms.borrow().get(&0), Some(&7));
+
+        // a copy gets the next ID and everything else by value, so a data structure ID still refers to the same one
+        game.score = 0;
+        let copy_id = game.last_instance_id + 1;
+        run(&mut game, &format!("with ({}) {{ list = ds_list_create(); instance_copy(true) }}", child));
+        assert_eq!(game.score, child);
+        let list = &game.room.instance_list;
+        assert_eq!((list.count(parent), list.count(child)), (2, 2));
+        let copy = list.get(list.get_by_instid(copy_id).unwrap());
+        assert!(copy.is_active());
+        assert_eq!(copy.object_index.get(), child);
+        assert_eq!(copy.alarms.borrow().get(&0), Some(&7));
+        run(&mut game, &format!("with (100001) ds_list_add(list, 5); with ({}) score = ds_list_size(list)", copy_id));
+        assert_eq!(game.score, 1);
+    }
+
+    #[test]
+    fn placement_truth_table() {
+        let mut game = testing::synthetic_game();
+        game.assets.sprites.push(Some(Box::new(testing::sprite())));
+        let sprite = game.assets.sprites.len() as i32 - 1;
+        let wall = add_object(&mut game, "obj_wall", -1);
+        let marker = add_object(&mut game, "obj_marker", -1);
+        for &object in &[wall, marker] {
+            game.assets.objects[object as usize].as_mut().unwrap().sprite_index = sprite;
+        }
+        game.assets.objects[wall as usize].as_mut().unwrap().solid = true;
+        let wall_id = i32::from(game.instance_create(&[64.0.into(), 64.0.into(), wall.into()]).unwrap());
+        game.instance_create(&[128.0.into(), 64.0.into(), marker.into()]).unwrap();
+        let mover = game.room.instance_list.get_by_instid(100001).unwrap();
+        game.room.instance_list.get(mover).sprite_index.set(sprite);
+        let mut context = Context::with_single_instance(mover);
+
+        // The wall covers 64 to 79 both ways. The corners of the mover's bbox are rounded to even, then the pixels
+        // where they overlap are checked from the rounded position, so x = 48.5 only just misses.
+        let table = [
+            (47.5, true),
+            (48.4, true),
+            (48.5, true),
+            (48.6, false),
+            (49.5, false),
+            (78.5, false),
+            (79.4, false),
+            (79.5, true),
+            (80.5, true),
+        ];
+        for &(x, free) in &table {
+            let args = [x.into(), 64.0.into()];
+            assert_eq!(game.place_free(&mut context, &args).unwrap().is_truthy(), free, "place_free({}, 64)", x);
+            assert_eq!(game.place_empty(&mut context, &args).unwrap().is_truthy(), free, "place_empty({}, 64)", x);
+        }
+
+        // place_free only cares about solid instances, and none of them move the instance for good
+        let args = [120.0.into(), 64.0.into()];
+        assert!(game.place_free(&mut context, &args).unwrap().is_truthy());
+        assert!(!game.place_empty(&mut context, &args).unwrap().is_truthy());
+        assert!(game.place_meeting(&mut context, &[120.0.into(), 64.0.into(), marker.into()]).unwrap().is_truthy());
+        assert!(!game.place_meeting(&mut context, &[120.0.into(), 64.0.into(), wall.into()]).unwrap().is_truthy());
+        let instance = game.room.instance_list.get(mover);
+        assert_eq!((instance.x.get(), instance.y.get()), (Real::from(32.0), Real::from(64.0)));
+
+        // the position_* functions floor the point instead
+        let table = [
+            ((63.9, 70.0), false),
+            ((64.0, 70.0), true),
+            ((79.9, 70.0), true),
+            ((80.0, 70.0), false),
+            ((70.0, 63.5), false),
+            ((70.0, 79.5), true),
+        ];
+        for &((x, y), meeting) in &table {
+            let args = [x.into(), y.into(), wall.into()];
+            let result = game.position_meeting(&mut context, &args).unwrap().is_truthy();
+            assert_eq!(result, meeting, "position_meeting({}, {})", x, y);
+            let result = game.position_empty(&mut context, &args[..2]).unwrap().is_truthy();
+            assert_eq!(result, !meeting, "position_empty({}, {})", x, y);
+        }
+
+        game.position_change(&[79.9.into(), 70.0.into(), marker.into(), false.into()]).unwrap();
+        let list = &game.room.instance_list;
+        assert_eq!((list.count(wall), list.count(marker)), (0, 2));
+        game.position_destroy(&[130.0.into(), 64.0.into()]).unwrap();
+        assert_eq!(game.room.instance_list.count(marker), 1);
+        let changed = game.room.instance_list.get_by_instid(wall_id).unwrap();
+        assert_eq!(game.room.instance_list.get(changed).object_index.get(), marker);
+    }
+}
@@ -2,7 +2,7 @@ use crate::{
     game::Game,
     gml::{Function, InstanceVariable},
 };
-use phf::{phf_map, phf_ordered_map};
+use phf::{phf_map, phf_ordered_map, phf_set};
 
 pub mod constants {
     pub const ANSI_CHARSET: f64 = 0.0f64;
@@ -849,6 +849,51 @@ pub fn get_instance_variable_by_name(name: &[u8]) -> Option<&InstanceVariable> {
     std::str::from_utf8(name).ok().and_then(|n| INSTANCE_VARIABLES.iter().find(|(s, _)| *s == n).map(|(_, v)| v))
 }
 
+/// Names of the constants, variables and functions GM 8.1 added for compatibility with the HTML5 runner.
+/// The native runner only has stubs for these, and in GM 8.0 they don't exist at all - so they're ordinary
+/// field names there, and calling one of the functions is an unknown function error.
+pub const GM81_ONLY: phf::Set<&'static str> = phf_set! {
+    "browser_chrome",
+    "browser_firefox",
+    "browser_ie",
+    "browser_not_a_browser",
+    "browser_opera",
+    "browser_safari",
+    "browser_safari_mobile",
+    "browser_unknown",
+    "device_ios_ipad",
+    "device_ios_iphone",
+    "device_ios_iphone_retina",
+    "device_ios_unknown",
+    "os_android",
+    "os_ios",
+    "os_linux",
+    "os_macosx",
+    "os_psp",
+    "os_unknown",
+    "os_win32",
+    "os_win64",
+    "os_type",
+    "os_device",
+    "os_version",
+    "os_browser",
+    "browser_width",
+    "browser_height",
+    "display_aa",
+    "YoYo_GetPlatform",
+    "YoYo_GetDevice",
+    "YoYo_OpenURL",
+    "YoYo_OpenURL_ext",
+    "YoYo_OpenURL_full",
+    "YoYo_GetDomain",
+    "YoYo_GetTimer",
+    "YoYo_AddVirtualKey",
+    "YoYo_DeleteVirtualKey",
+    "YoYo_ShowVirtualKey",
+    "YoYo_HideVirtualKey",
+    "YoYo_EnableAlphaBlend",
+};
+
 /// Mappings of GM function names to callable pointers
 pub const FUNCTIONS: phf::OrderedMap<&'static str, Function> = phf_ordered_map! {
     // TODO: Use the macro to automatically infer the dependence on runtime of kernel
@@ -1596,7 +1641,7 @@ pub const FUNCTIONS: phf::OrderedMap<&'static str, Function> = phf_ordered_map!
     "execute_string" => Function::Runtime(Game::execute_string),
     "execute_file" => Function::Runtime(Game::execute_file),
     "window_handle" => Function::Constant(Game::window_handle),
-    "show_debug_message" => Function::Volatile(Game::show_debug_message),
+    "show_debug_message" => Function::Engine(Game::show_debug_message),
     "set_program_priority" => Function::Engine(Game::set_program_priority),
     "set_application_title" => Function::Pure(Game::set_application_title),
     "variable_global_exists" => Function::Constant(Game::variable_global_exists),
@@ -1772,7 +1817,7 @@ pub const FUNCTIONS: phf::OrderedMap<&'static str, Function> = phf_ordered_map!
     "path_mirror" => Function::Engine(Game::path_mirror),
     "path_flip" => Function::Engine(Game::path_flip),
     "path_rotate" => Function::Engine(Game::path_rotate),
-    "path_scale" => Function::Engine(Game::path_scale),
+    "path_rescale" => Function::Engine(Game::path_rescale),
     "path_shift" => Function::Engine(Game::path_shift),
     "timeline_name" => Function::Constant(Game::timeline_get_name),
     "timeline_exists" => Function::Constant(Game::timeline_exists),
@@ -2135,3 +2180,183 @@ pub const FUNCTIONS: phf::OrderedMap<&'static str, Function> = phf_ordered_map!
     "d3d_model_wall" => Function::Engine(Game::d3d_model_wall),
     "d3d_model_floor" => Function::Engine(Game::d3d_model_floor),
 };
+
+/// The number of arguments GM8 accepts for a builtin function, as (minimum, maximum).
+/// A call with any other number is rejected when it's compiled, before the function ever sees its arguments.
+/// Variadic functions go up to 16, the most arguments any call can have.
+/// Not every function is listed here yet - the rest still check their arguments themselves with `expect_args!`.
+pub const ARGUMENT_COUNTS: phf::Map<&'static str, (usize, usize)> = phf_map! {
+    "is_real" => (1, 1),
+    "is_string" => (1, 1),
+    "random" => (1, 1),
+    "random_range" => (2, 2),
+    "irandom" => (1, 1),
+    "irandom_range" => (2, 2),
+    "random_set_seed" => (1, 1),
+    "random_get_seed" => (0, 0),
+    "randomize" => (0, 0),
+    "abs" => (1, 1),
+    "round" => (1, 1),
+    "floor" => (1, 1),
+    "ceil" => (1, 1),
+    "sign" => (1, 1),
+    "frac" => (1, 1),
+    "sqrt" => (1, 1),
+    "sqr" => (1, 1),
+    "exp" => (1, 1),
+    "ln" => (1, 1),
+    "log2" => (1, 1),
+    "log10" => (1, 1),
+    "sin" => (1, 1),
+    "cos" => (1, 1),
+    "tan" => (1, 1),
+    "arcsin" => (1, 1),
+    "arccos" => (1, 1),
+    "arctan" => (1, 1),
+    "arctan2" => (2, 2),
+    "degtorad" => (1, 1),
+    "radtodeg" => (1, 1),
+    "power" => (2, 2),
+    "logn" => (2, 2),
+    "min" => (0, 16),
+    "max" => (0, 16),
+    "mean" => (0, 16),
+    "median" => (0, 16),
+    "choose" => (0, 16),
+    "real" => (1, 1),
+    "string" => (1, 1),
+    "string_format" => (3, 3),
+    "chr" => (1, 1),
+    "ord" => (1, 1),
+    "string_length" => (1, 1),
+    "string_byte_length" => (1, 1),
+    "string_byte_at" => (2, 2),
+    "string_pos" => (2, 2),
+    "string_copy" => (3, 3),
+    "string_char_at" => (2, 2),
+    "string_delete" => (3, 3),
+    "string_insert" => (3, 3),
+    "string_lower" => (1, 1),
+    "string_upper" => (1, 1),
+    "string_repeat" => (2, 2),
+    "string_letters" => (1, 1),
+    "string_digits" => (1, 1),
+    "string_lettersdigits" => (1, 1),
+    "string_replace" => (3, 3),
+    "string_replace_all" => (3, 3),
+    "string_count" => (2, 2),
+    "point_distance" => (4, 4),
+    "point_direction" => (4, 4),
+    "lengthdir_x" => (2, 2),
+    "lengthdir_y" => (2, 2),
+    "ds_set_precision" => (1, 1),
+    "ds_stack_create" => (0, 0),
+    "ds_stack_destroy" => (1, 1),
+    "ds_stack_clear" => (1, 1),
+    "ds_stack_copy" => (2, 2),
+    "ds_stack_size" => (1, 1),
+    "ds_stack_empty" => (1, 1),
+    "ds_stack_push" => (2, 2),
+    "ds_stack_pop" => (1, 1),
+    "ds_stack_top" => (1, 1),
+    "ds_stack_write" => (1, 1),
+    "ds_stack_read" => (2, 2),
+    "ds_queue_create" => (0, 0),
+    "ds_queue_destroy" => (1, 1),
+    "ds_queue_clear" => (1, 1),
+    "ds_queue_copy" => (2, 2),
+    "ds_queue_size" => (1, 1),
+    "ds_queue_empty" => (1, 1),
+    "ds_queue_enqueue" => (2, 2),
+    "ds_queue_dequeue" => (1, 1),
+    "ds_queue_head" => (1, 1),
+    "ds_queue_tail" => (1, 1),
+    "ds_queue_write" => (1, 1),
+    "ds_queue_read" => (2, 2),
+    "ds_list_create" => (0, 0),
+    "ds_list_destroy" => (1, 1),
+    "ds_list_clear" => (1, 1),
+    "ds_list_copy" => (2, 2),
+    "ds_list_size" => (1, 1),
+    "ds_list_empty" => (1, 1),
+    "ds_list_add" => (2, 2),
+    "ds_list_insert" => (3, 3),
+    "ds_list_replace" => (3, 3),
+    "ds_list_delete" => (2, 2),
+    "ds_list_find_index" => (2, 2),
+    "ds_list_find_value" => (2, 2),
+    "ds_list_sort" => (2, 2),
+    "ds_list_shuffle" => (1, 1),
+    "ds_list_write" => (1, 1),
+    "ds_list_read" => (2, 2),
+    "ds_map_create" => (0, 0),
+    "ds_map_destroy" => (1, 1),
+    "ds_map_clear" => (1, 1),
+    "ds_map_copy" => (2, 2),
+    "ds_map_size" => (1, 1),
+    "ds_map_empty" => (1, 1),
+    "ds_map_add" => (3, 3),
+    "ds_map_replace" => (3, 3),
+    "ds_map_delete" => (2, 2),
+    "ds_map_exists" => (2, 2),
+    "ds_map_find_value" => (2, 2),
+    "ds_map_find_previous" => (2, 2),
+    "ds_map_find_next" => (2, 2),
+    "ds_map_find_first" => (1, 1),
+    "ds_map_find_last" => (1, 1),
+    "ds_map_write" => (1, 1),
+    "ds_map_read" => (2, 2),
+    "ds_priority_create" => (0, 0),
+    "ds_priority_destroy" => (1, 1),
+    "ds_priority_clear" => (1, 1),
+    "ds_priority_copy" => (2, 2),
+    "ds_priority_size" => (1, 1),
+    "ds_priority_empty" => (1, 1),
+    "ds_priority_add" => (3, 3),
+    "ds_priority_change_priority" => (3, 3),
+    "ds_priority_find_priority" => (2, 2),
+    "ds_priority_delete_value" => (2, 2),
+    "ds_priority_delete_min" => (1, 1),
+    "ds_priority_find_min" => (1, 1),
+    "ds_priority_delete_max" => (1, 1),
+    "ds_priority_find_max" => (1, 1),
+    "ds_priority_write" => (1, 1),
+    "ds_priority_read" => (2, 2),
+    "ds_grid_create" => (2, 2),
+    "ds_grid_destroy" => (1, 1),
+    "ds_grid_copy" => (2, 2),
+    "ds_grid_resize" => (3, 3),
+    "ds_grid_width" => (1, 1),
+    "ds_grid_height" => (1, 1),
+    "ds_grid_clear" => (2, 2),
+    "ds_grid_set" => (4, 4),
+    "ds_grid_add" => (4, 4),
+    "ds_grid_multiply" => (4, 4),
+    "ds_grid_set_region" => (6, 6),
+    "ds_grid_add_region" => (6, 6),
+    "ds_grid_multiply_region" => (6, 6),
+    "ds_grid_set_disk" => (5, 5),
+    "ds_grid_add_disk" => (5, 5),
+    "ds_grid_multiply_disk" => (5, 5),
+    "ds_grid_set_grid_region" => (8, 8),
+    "ds_grid_add_grid_region" => (8, 8),
+    "ds_grid_multiply_grid_region" => (8, 8),
+    "ds_grid_get" => (3, 3),
+    "ds_grid_get_sum" => (5, 5),
+    "ds_grid_get_max" => (5, 5),
+    "ds_grid_get_min" => (5, 5),
+    "ds_grid_get_mean" => (5, 5),
+    "ds_grid_get_disk_sum" => (4, 4),
+    "ds_grid_get_disk_max" => (4, 4),
+    "ds_grid_get_disk_min" => (4, 4),
+    "ds_grid_get_disk_mean" => (4, 4),
+    "ds_grid_value_exists" => (6, 6),
+    "ds_grid_value_x" => (6, 6),
+    "ds_grid_value_y" => (6, 6),
+    "ds_grid_value_disk_exists" => (5, 5),
+    "ds_grid_value_disk_x" => (5, 5),
+    "ds_grid_value_disk_y" => (5, 5),
+    "ds_grid_shuffle" => (1, 1),
+    "ds_grid_write" => (1, 1),
+    "ds_grid_read" => (2, 2),
+};
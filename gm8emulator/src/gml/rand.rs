@@ -4,8 +4,37 @@
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
-pub struct Random(i32);
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Random(i32, #[serde(skip)] Option<Box<Log>>);
+
+/// Everywhere a random number gets drawn from, so that logs from two runs can be lined up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Site {
+    Random,
+    RandomRange,
+    Irandom,
+    IrandomRange,
+    Choose,
+    IfDice,
+    CreateObjectRandom,
+    MoveRandom,
+    MoveFixed,
+    ListShuffle,
+    ParticleEffect,
+    ParticleRange,
+    ParticleColour,
+    ParticleSubimage,
+    ParticleCount,
+    TempFolder,
+}
+
+/// A record of every number drawn, with the frame it was drawn on and where from.
+/// Frame 0 is everything before the first frame, such as Game Start.
+#[derive(Clone, Default)]
+pub struct Log {
+    frame: usize,
+    draws: Vec<(usize, Site, i32)>,
+}
 
 /// Increment value in the LCG algorithm.
 pub const INCREMENT: i32 = 1;
@@ -21,13 +50,13 @@ impl Random {
     /// Creates a new LCG with a random seed.
     #[inline]
     pub fn new() -> Self {
-        Self(rand_int())
+        Self(rand_int(), None)
     }
 
     /// Creates a new LCG with a given seed.
     #[inline]
     pub const fn with_seed(seed: i32) -> Self {
-        Self(seed)
+        Self(seed, None)
     }
 
     /// Equivalent to GML random_get_seed().
@@ -60,12 +89,37 @@ impl Random {
         self.0 = self.0.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
     }
 
+    /// Starts logging every number drawn. The log isn't kept in savestates.
+    pub fn start_log(&mut self) {
+        self.1 = Some(Default::default());
+    }
+
+    /// Moves the log on to the next frame, if there is one.
+    pub fn log_frame(&mut self) {
+        if let Some(log) = &mut self.1 {
+            log.frame += 1;
+        }
+    }
+
+    /// Takes the log, if there is one, and stops logging.
+    pub fn take_log(&mut self) -> Option<Log> {
+        self.1.take().map(|log| *log)
+    }
+
+    #[inline]
+    fn draw(&mut self, site: Site) {
+        self.cycle();
+        if let Some(log) = &mut self.1 {
+            log.draws.push((log.frame, site, self.0));
+        }
+    }
+
     /// Equivalent to GML random(n).
     ///
     /// Returns a random float between 0 and n (exclusive).
     #[inline]
-    pub fn next(&mut self, bound: f64) -> f64 {
-        self.cycle(); // cycle seed
+    pub fn next(&mut self, bound: f64, site: Site) -> f64 {
+        self.draw(site);
         (self.0 as u32 as f64) * f64::from_bits(INT_STEP) * bound
     }
 
@@ -76,17 +130,89 @@ impl Random {
     /// The input needs to be cast to unsigned because of weird UB with negative integers.
     /// The output can still be signed, if the input was a signed number (but cast to unsigned).
     #[inline]
-    pub fn next_int(&mut self, bound: u32) -> i32 {
-        self.cycle(); // cycle seed
+    pub fn next_int(&mut self, bound: u32, site: Site) -> i32 {
+        self.draw(site);
         let ls = (self.0 as u64) & 0xFFFF_FFFF;
         let lb = u64::from(bound.wrapping_add(1));
         ((ls.wrapping_mul(lb)) >> 32) as _
     }
 }
 
+impl PartialEq for Random {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Log {
+    /// Writes the log out with one draw per line, as "frame site seed".
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity(self.draws.len() * 24);
+        for (frame, site, seed) in self.draws.iter() {
+            text += &format!("{} {:?} {}\n", frame, site, seed);
+        }
+        text
+    }
+}
+
+/// Compares two logs written by `Log::to_text`, describing the first place they differ, if they do.
+pub fn first_difference(expected: &str, actual: &str) -> Option<String> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut draw = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => break None,
+            (Some(e), Some(a)) if e == a => draw += 1,
+            (Some(e), Some(a)) => break Some(format!("draw {}: expected \"{}\", got \"{}\"", draw, e, a)),
+            (Some(e), None) => break Some(format!("draw {}: expected \"{}\", but there were no more draws", draw, e)),
+            (None, Some(a)) => break Some(format!("draw {}: got \"{}\", but no more draws were expected", draw, a)),
+        }
+    }
+}
+
 // Makes a pseudorandom integer. Only used for seeding, such as in randomize().
 fn rand_int() -> i32 {
     let mut bytes: [u8; 4] = unsafe { std::mem::MaybeUninit::uninit().assume_init() };
     let _ = getrandom::getrandom(&mut bytes);
     i32::from_le_bytes(bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log() {
+        let mut expected = Random::with_seed(1);
+        let mut seeds = Vec::new();
+        for _ in 0..3 {
+            expected.cycle();
+            seeds.push(expected.seed());
+        }
+
+        let mut rand = Random::with_seed(1);
+        rand.next_int(5, Site::Irandom);
+        rand.start_log();
+        rand.next(1.0, Site::Random);
+        rand.log_frame();
+        rand.log_frame();
+        rand.next_int(3, Site::Choose);
+        let text = rand.take_log().unwrap().to_text();
+        assert_eq!(text, format!("0 Random {}\n2 Choose {}\n", seeds[1], seeds[2]));
+        assert!(rand.take_log().is_none());
+        assert!(rand == expected);
+    }
+
+    #[test]
+    fn compare_logs() {
+        let log = "0 Random 5\n1 Choose 7\n";
+        assert_eq!(first_difference(log, log), None);
+        assert_eq!(
+            first_difference(log, "0 Random 5\n1 MoveFixed 7\n").unwrap(),
+            "draw 2: expected \"1 Choose 7\", got \"1 MoveFixed 7\""
+        );
+        assert!(first_difference(log, "0 Random 5\n").unwrap().starts_with("draw 2: expected"));
+        assert!(first_difference("", log).unwrap().starts_with("draw 1: got"));
+    }
+}
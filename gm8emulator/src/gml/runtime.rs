@@ -1,14 +1,15 @@
 use crate::{
     asset,
-    game::{Game, GetAsset, SceneChange, Version},
+    game::{cmdline, Game, GetAsset, SceneChange, Version},
     gml::{
         self,
         datetime::DateTime,
         mappings::{self, constants as gml_constants},
         Context, InstanceVariable, Value,
     },
-    instance::Field,
+    instance::{Field, DEFAULT_ALARM},
     math::Real,
+    types::ID,
 };
 use gml_parser::token::Operator;
 use serde::{Deserialize, Serialize};
@@ -18,8 +19,6 @@ use std::{
     time,
 };
 
-const DEFAULT_ALARM: i32 = -1;
-
 /// A compiled runtime instruction. Generally represents a line of code.
 #[derive(Serialize, Deserialize)]
 pub enum Instruction {
@@ -161,8 +160,34 @@ pub enum Error {
     WrongArgumentCount(usize, usize),
     FunctionError(String, String),
     ReplayError(String),
-    BadDirectoryError(String),
     ExternalFunction(String, String),
+    NextRoomAfterLast,
+    PreviousRoomBeforeFirst,
+    UnexistingRoom(i32),
+
+    // Where an error happened, wrapped around it on the way out of each event and script
+    InEvent { object: String, event_type: usize, event_number: u32, error: Box<Error> },
+    InScript { script: String, error: Box<Error> },
+}
+
+impl Error {
+    /// Records that this error happened in the given object's event.
+    pub fn in_event(self, object: impl Into<String>, event_type: usize, event_number: u32) -> Self {
+        Self::InEvent { object: object.into(), event_type, event_number, error: Box::new(self) }
+    }
+
+    /// Records that this error happened in the given script.
+    pub fn in_script(self, script: impl Into<String>) -> Self {
+        Self::InScript { script: script.into(), error: Box::new(self) }
+    }
+
+    /// The error itself, without any of the events and scripts it happened in.
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::InEvent { error, .. } | Self::InScript { error, .. } => error.root(),
+            e => e,
+        }
+    }
 }
 
 impl std::error::Error for Error {}
@@ -204,11 +229,19 @@ impl Display for Error {
             },
             Self::UninitializedArgument(n) => write!(f, "uninitialized argument #{}", n),
             Self::TooManyArrayDimensions(n) => write!(f, "too many array dimensions ({})", n),
-            Self::WrongArgumentCount(exp, got) => write!(f, "wrong argument count (expected: {}, got: {})", exp, got),
             Self::FunctionError(fname, s) => write!(f, "{}: {}", fname, s),
             Self::ReplayError(s) => write!(f, "{}", s),
-            Self::BadDirectoryError(s) => write!(f, "cannot encode working directory {} with current encoding", s),
             Self::ExternalFunction(s, e) => write!(f, "failed to call external function \"{}\": {}", s, e),
+            // These are worded exactly like GM8's, since games sometimes show them to players.
+            // The expected and given argument counts are only in the Debug output.
+            Self::WrongArgumentCount(..) => write!(f, "Wrong number of arguments to function or script."),
+            Self::NextRoomAfterLast => write!(f, "Moving to next room after the last room."),
+            Self::PreviousRoomBeforeFirst => write!(f, "Moving to previous room before the first room."),
+            Self::UnexistingRoom(id) => write!(f, "Unexisting room number: {}", id),
+            Self::InEvent { object, event_type, event_number, error } => {
+                write!(f, "{}\nin object {} event {},{}", error, object, event_type, event_number)
+            },
+            Self::InScript { script, error } => write!(f, "{}\nin script {}", error, script),
         }
     }
 }
@@ -315,6 +348,14 @@ impl Game {
         mappings::FUNCTIONS.index(function_id).unwrap().1.invoke(self, context, args)
     }
 
+    /// Records that an error happened in the given script.
+    pub fn script_error(&self, script_id: ID, error: Error) -> Error {
+        match self.assets.scripts.get_asset(script_id) {
+            Some(script) => error.in_script(script.name.to_string()),
+            None => error,
+        }
+    }
+
     pub fn execute(&mut self, instructions: &[Instruction], context: &mut Context) -> gml::Result<ReturnType> {
         for instruction in instructions.iter() {
             match self.exec_instruction(instruction, context)? {
@@ -597,7 +638,7 @@ impl Game {
                     }
 
                     let mut new_context = Context::copy_with_args(context, arg_values, args.len());
-                    self.execute(&instructions, &mut new_context)?;
+                    self.execute(&instructions, &mut new_context).map_err(|e| self.script_error(*script_id as _, e))?;
                     Ok(new_context.return_value)
                 } else {
                     Err(Error::NonexistentAsset(asset::Type::Script, *script_id as i32))
@@ -989,20 +1030,12 @@ impl Game {
             InstanceVariable::Health => Ok(self.health.into()),
             InstanceVariable::GameId => Ok(self.game_id.into()),
             InstanceVariable::WorkingDirectory => {
-                // get cwd in WTF-8
-                let os_cwd = std::env::current_dir().unwrap();
-                // try to read as UTF-8
-                let mut cwd =
-                    os_cwd.to_str().ok_or(gml::Error::BadDirectoryError(os_cwd.to_string_lossy().into_owned()))?;
-                // trim if on windows
-                if cfg!(target_os = "windows") {
-                    cwd = cwd.trim_start_matches("\\\\?\\");
-                }
-                // try to encode with current encoding
                 // TODO: maybe try and get the short path name on windows?
-                self.encode_str_maybe(cwd)
-                    .map(|x| x.into_owned().into())
-                    .ok_or(gml::Error::BadDirectoryError(cwd.to_string()))
+                let encoding = match self.gm_version {
+                    Version::GameMaker8_0 => self.encoding,
+                    Version::GameMaker8_1 => encoding_rs::UTF_8,
+                };
+                Ok(gml::String::from(cmdline::encode_path(&std::env::current_dir().unwrap(), encoding)).into())
             },
             InstanceVariable::TempDirectory => Ok(self.temp_directory.clone().into()),
             InstanceVariable::ProgramDirectory => Ok(self.program_directory.clone().into()),
@@ -1137,7 +1170,10 @@ impl Game {
             InstanceVariable::MouseLastbutton => Ok(f64::from(self.input.mouse_lastbutton()).into()),
             InstanceVariable::KeyboardKey => Ok(f64::from(self.input.keyboard_key()).into()),
             InstanceVariable::KeyboardLastkey => Ok(f64::from(self.input.keyboard_lastkey()).into()),
-            InstanceVariable::KeyboardLastchar => todo!("keyboard_lastchar getter"),
+            InstanceVariable::KeyboardLastchar => match self.input.keyboard_lastchar() {
+                0 => Ok("".into()),
+                c => Ok(vec![c].into()),
+            },
             InstanceVariable::KeyboardString => todo!("keyboard_string getter"),
             InstanceVariable::CursorSprite => Ok(self.cursor_sprite.into()),
             InstanceVariable::ShowScore => Ok(self.score_capt_d.into()),
@@ -1173,7 +1209,7 @@ impl Game {
             InstanceVariable::EventObject => Ok(context.event_object.into()),
             InstanceVariable::EventAction => Ok(context.event_action.into()),
             InstanceVariable::SecureMode => Ok(gml::FALSE.into()),
-            InstanceVariable::DebugMode => Ok(gml::FALSE.into()),
+            InstanceVariable::DebugMode => Ok(self.debug_mode.into()),
             InstanceVariable::ErrorOccurred => Ok(self.error_occurred.into()),
             InstanceVariable::ErrorLast => Ok(self.error_last.clone().into()),
             InstanceVariable::GamemakerStandard => Ok(gml::TRUE.into()), // yeah!
@@ -1329,20 +1365,8 @@ impl Game {
             InstanceVariable::TransitionKind => self.transition_kind = value.into(),
             InstanceVariable::TransitionSteps => self.transition_steps = value.into(),
             InstanceVariable::Score => self.score = value.into(),
-            InstanceVariable::Lives => {
-                let old_lives = self.lives;
-                self.lives = value.into();
-                if old_lives > 0 && self.lives <= 0 {
-                    self.run_other_event(6)?;
-                }
-            },
-            InstanceVariable::Health => {
-                let old_health = self.health;
-                self.health = value.into();
-                if old_health > 0.into() && self.health <= 0.into() {
-                    self.run_other_event(9)?;
-                }
-            },
+            InstanceVariable::Lives => self.set_lives(value.into()),
+            InstanceVariable::Health => self.set_health(value.into()),
             InstanceVariable::RoomCaption => {
                 self.room.caption = value.into();
             },
@@ -1497,7 +1521,11 @@ impl Game {
                     self.input.set_keyboard_lastkey(vk);
                 }
             },
-            InstanceVariable::KeyboardLastchar => todo!("keyboard_lastchar setter"),
+            InstanceVariable::KeyboardLastchar => {
+                // only the first character is kept, and assigning "" clears it
+                let chars: &[u8] = (&value).into();
+                self.input.set_keyboard_lastchar(chars.first().copied().unwrap_or(0));
+            },
             InstanceVariable::KeyboardString => todo!("keyboard_string setter"),
             InstanceVariable::CursorSprite => self.cursor_sprite = value.round(),
             InstanceVariable::ShowScore => {
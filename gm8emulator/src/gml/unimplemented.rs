@@ -0,0 +1,85 @@
+//! Keeping track of kernel functions which haven't been implemented yet.
+//!
+//! Normally calling one of these panics. With counting switched on, they return 0 instead and the emulator
+//! keeps a tally, so playing through a game gives an idea of which missing functions matter most to it.
+//! The game itself will usually go wrong somewhere after the first one, so this is only for prioritising.
+
+use std::{collections::HashMap, sync::Mutex};
+
+static CALLS: Mutex<Calls> = Mutex::new(Calls(None));
+
+// How many times each unimplemented function has been called, if they're being counted
+struct Calls(Option<HashMap<&'static str, u64>>);
+
+impl Calls {
+    fn start(&mut self) {
+        self.0.get_or_insert_with(HashMap::new);
+    }
+
+    fn record(&mut self, name: &'static str) -> bool {
+        match self.0.as_mut() {
+            Some(calls) => {
+                *calls.entry(name).or_insert(0) += 1;
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn ranking(&self) -> Vec<(&'static str, u64)> {
+        let mut calls = self.0.iter().flatten().map(|(name, count)| (*name, *count)).collect::<Vec<_>>();
+        calls.sort_by(|(name1, count1), (name2, count2)| count2.cmp(count1).then(name1.cmp(name2)));
+        calls
+    }
+}
+
+/// Every kernel function which is still an `unimplemented_function!()` stub, in sorted order.
+/// This is generated by the build script from kernel.rs, so it can't go out of date.
+pub const FUNCTIONS: &[&str] = include!(concat!(env!("OUT_DIR"), "/unimplemented_functions.rs"));
+
+/// Checks whether a kernel function is unimplemented.
+pub fn is_unimplemented(name: &str) -> bool {
+    FUNCTIONS.binary_search(&name).is_ok()
+}
+
+/// Starts counting calls instead of panicking.
+pub fn start_counting() {
+    CALLS.lock().unwrap().start();
+}
+
+/// Records a call to an unimplemented function. Returns false if calls aren't being counted.
+pub fn record(name: &'static str) -> bool {
+    CALLS.lock().unwrap().record(name)
+}
+
+/// Every unimplemented function that's been called and how many times, most called first.
+pub fn ranking() -> Vec<(&'static str, u64)> {
+    CALLS.lock().unwrap().ranking()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranked_calls() {
+        // a tally of its own, since the global one is shared with anything else that runs an unimplemented function
+        let mut calls = Calls(None);
+        assert!(!calls.record("display_get_width"));
+        assert!(calls.ranking().is_empty());
+
+        calls.start();
+        for &name in &["ds_grid_shuffle", "display_get_width", "ds_grid_shuffle", "cd_init", "ds_grid_shuffle"] {
+            assert!(calls.record(name));
+        }
+        calls.record("cd_init");
+        assert_eq!(calls.ranking(), [("ds_grid_shuffle", 3), ("cd_init", 2), ("display_get_width", 1)]);
+    }
+
+    #[test]
+    fn generated_list() {
+        assert!(FUNCTIONS.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(is_unimplemented("splash_set_close_button"));
+        assert!(!is_unimplemented("instance_create"));
+    }
+}
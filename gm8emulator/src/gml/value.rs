@@ -365,8 +365,8 @@ impl Value {
 
     pub fn repr(&self) -> gml::String {
         match self {
-            Self::Real(r) if r.fract().into_inner() == 0.0 => format!("{:.0}", r).into(),
-            Self::Real(r) => format!("{:.2}", r).into(),
+            Self::Real(r) if r.fract().into_inner() == 0.0 => r.to_fixed(0).into(),
+            Self::Real(r) => r.to_fixed(2).into(),
             Self::Str(string) => string.clone(),
         }
     }
@@ -587,4 +587,12 @@ mod tests {
         let b = Value::Str("owo".to_string().into());
         let _ = a.add(b).unwrap();
     }
+
+    #[test]
+    fn repr() {
+        assert_eq!(Value::Real(Real::from(5.0)).repr().as_ref(), b"5");
+        assert_eq!(Value::Real(Real::from(0.125)).repr().as_ref(), b"0.13");
+        assert_eq!(Value::Real(Real::from(-1.005)).repr().as_ref(), b"-1.00");
+        assert_eq!(Value::Real(Real::from(1234.5)).repr().as_ref(), b"1234.50");
+    }
 }
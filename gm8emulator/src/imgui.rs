@@ -3,10 +3,15 @@
 // Note to self: ImGui's popup API is bugged and doesn't do anything, don't use it. Make your own.
 // Current hours wasted trying to use popup API in this file: 4
 
-use crate::types::Colour;
+use crate::{
+    locale::{tr, Script},
+    types::Colour,
+};
 use cimgui_sys as c;
 use std::{
+    ffi::CString,
     ops,
+    path::Path,
     ptr::{self, NonNull},
     slice,
 };
@@ -18,6 +23,9 @@ pub struct Context {
 
 pub struct Frame<'a>(&'a mut Context);
 
+/// The size in pixels of imgui's default font, ProggyClean.
+const DEFAULT_FONT_SIZE: f32 = 13.0;
+
 #[repr(transparent)]
 pub struct IO(c::ImGuiIO);
 
@@ -249,6 +257,30 @@ impl Frame<'_> {
         unsafe { cimgui_sys::igMenuItemBool(self.cstr(), std::ptr::null(), false, true) }
     }
 
+    pub fn selectable(&mut self, label: &str, selected: bool) -> bool {
+        self.cstr_store(label);
+        unsafe { c::igSelectableBool(self.cstr(), selected, 0, c::ImVec2 { x: 0.0, y: 0.0 }) }
+    }
+
+    pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+        self.cstr_store(label);
+        unsafe { c::igCheckbox(self.cstr(), value) }
+    }
+
+    /// Edits a nul-terminated string in place. Returns true when it's edited, or when Enter is pressed
+    /// if `enter_returns` is set.
+    pub fn input_text(&mut self, label: &str, buf: &mut [u8], enter_returns: bool) -> bool {
+        self.cstr_store(label);
+        let flags = if enter_returns { c::ImGuiInputTextFlags__ImGuiInputTextFlags_EnterReturnsTrue } else { 0 };
+        unsafe {
+            c::igInputText(self.cstr(), buf.as_mut_ptr().cast(), buf.len(), flags as _, None, std::ptr::null_mut())
+        }
+    }
+
+    pub fn same_line(&self) {
+        unsafe { c::igSameLine(0.0, -1.0) }
+    }
+
     pub fn callback<T>(
         &mut self,
         callback: unsafe extern "C" fn(*const c::ImDrawList, *const c::ImDrawCmd),
@@ -295,6 +327,54 @@ impl Frame<'_> {
         unsafe { c::igIsItemHovered(0) }
     }
 
+    pub fn item_active(&self) -> bool {
+        unsafe { c::igIsItemActive() }
+    }
+
+    /// How far the mouse has been dragged with the left button held since this was last called.
+    pub fn take_mouse_drag(&self) -> Vec2<f32> {
+        unsafe {
+            let mut delta = std::mem::MaybeUninit::uninit();
+            c::igGetMouseDragDelta(delta.as_mut_ptr(), 0, 0.0);
+            c::igResetMouseDragDelta(0);
+            delta.assume_init().into()
+        }
+    }
+
+    pub fn mouse_wheel(&self) -> f32 {
+        unsafe { (*c::igGetIO()).MouseWheel }
+    }
+
+    pub fn cursor_position(&self) -> Vec2<f32> {
+        unsafe {
+            let mut pos = std::mem::MaybeUninit::uninit();
+            c::igGetCursorScreenPos(pos.as_mut_ptr());
+            pos.assume_init().into()
+        }
+    }
+
+    pub fn available_size(&self) -> Vec2<f32> {
+        unsafe {
+            let mut size = std::mem::MaybeUninit::uninit();
+            c::igGetContentRegionAvail(size.as_mut_ptr());
+            size.assume_init().into()
+        }
+    }
+
+    pub fn push_clip_rect(&mut self, min: Vec2<f32>, max: Vec2<f32>) {
+        unsafe { c::igPushClipRect(min.into(), max.into(), true) };
+    }
+
+    pub fn pop_clip_rect(&mut self) {
+        unsafe { c::igPopClipRect() };
+    }
+
+    pub fn tooltip(&mut self, text: &str) {
+        unsafe { c::igBeginTooltip() };
+        self.text(text);
+        unsafe { c::igEndTooltip() };
+    }
+
     pub fn rect(&mut self, min: Vec2<f32>, max: Vec2<f32>, colour: Colour, alpha: u8) {
         unsafe {
             c::ImDrawList_AddRectFilled(
@@ -346,7 +426,8 @@ impl Frame<'_> {
                     0,
                     Vec2(0.5, 0.5).into(),
                 );
-                c::igBegin("Information\0".as_ptr() as _, std::ptr::null_mut(), 0b0001_0111_1110);
+                self.cstr_store(&format!("{}###Information", tr("window_information")));
+                c::igBegin(self.cstr(), std::ptr::null_mut(), 0b0001_0111_1110);
                 self.text(message);
                 c::igEnd();
                 true
@@ -360,6 +441,43 @@ impl Frame<'_> {
 }
 
 impl IO {
+    /// Adds imgui's default font with characters for the given scripts merged in from other font files.
+    /// This has to be called before font_data(), which is when the font atlas gets built.
+    /// Returns the files which couldn't be loaded.
+    pub fn load_fonts<'a>(&mut self, extra: &'a [(Script, &'a Path)]) -> Vec<&'a Path> {
+        let mut failed = Vec::new();
+        unsafe {
+            let atlas = self.0.Fonts;
+            c::ImFontAtlas_AddFontDefault(atlas, ptr::null());
+            for &(script, path) in extra {
+                let filename = match path.to_str().and_then(|x| CString::new(x).ok()) {
+                    Some(filename) if path.is_file() => filename,
+                    _ => {
+                        failed.push(path);
+                        continue
+                    },
+                };
+                let ranges = match script {
+                    Script::Japanese => c::ImFontAtlas_GetGlyphRangesJapanese(atlas),
+                    Script::Korean => c::ImFontAtlas_GetGlyphRangesKorean(atlas),
+                    Script::Chinese => c::ImFontAtlas_GetGlyphRangesChineseSimplifiedCommon(atlas),
+                    Script::Cyrillic => c::ImFontAtlas_GetGlyphRangesCyrillic(atlas),
+                    Script::Thai => c::ImFontAtlas_GetGlyphRangesThai(atlas),
+                };
+                // the config is copied by AddFontFromFileTTF, so it can be freed straight away
+                let config = c::ImFontConfig_ImFontConfig();
+                (*config).MergeMode = true;
+                let font =
+                    c::ImFontAtlas_AddFontFromFileTTF(atlas, filename.as_ptr(), DEFAULT_FONT_SIZE, config, ranges);
+                c::ImFontConfig_destroy(config);
+                if font.is_null() {
+                    failed.push(path);
+                }
+            }
+        }
+        failed
+    }
+
     pub fn font_data(&self) -> FontData<'_> {
         unsafe {
             let mut data: *mut u8 = ptr::null_mut();
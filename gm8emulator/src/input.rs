@@ -414,6 +414,44 @@ pub fn ramen2vk(x: ramen::event::Key) -> u8 {
     Button::try_from(x).map(|e| e as u8).unwrap_or(0)
 }
 
+/// Translates a key press into the character Windows would send in WM_CHAR, using the US keyboard layout.
+/// Replays only store key presses, so this has to be fixed rather than depending on the player's layout.
+fn vk2char(vk: u8, shift: bool, control: bool) -> Option<u8> {
+    const SHIFTED_DIGITS: &[u8; 10] = b")!@#$%^&*(";
+    Some(match vk {
+        b'A'..=b'Z' if control => vk - b'A' + 1,
+        b'A'..=b'Z' if shift => vk,
+        b'A'..=b'Z' => vk.to_ascii_lowercase(),
+        _ if control => return None,
+        b'0'..=b'9' if shift => SHIFTED_DIGITS[usize::from(vk - b'0')],
+        b'0'..=b'9' => vk,
+        0x60..=0x69 => vk - 0x60 + b'0',
+        0x08 | 0x09 | 0x0D | 0x1B | 0x20 => vk,
+        0x6A => b'*',
+        0x6B => b'+',
+        0x6D => b'-',
+        0x6E => b'.',
+        0x6F => b'/',
+        _ => {
+            let (normal, shifted) = match vk {
+                0xBA => (b';', b':'),
+                0xBB => (b'=', b'+'),
+                0xBC => (b',', b'<'),
+                0xBD => (b'-', b'_'),
+                0xBE => (b'.', b'>'),
+                0xBF => (b'/', b'?'),
+                0xC0 => (b'`', b'~'),
+                0xDB => (b'[', b'{'),
+                0xDC => (b'\\', b'|'),
+                0xDD => (b']', b'}'),
+                0xDE => (b'\'', b'"'),
+                _ => return None,
+            };
+            if shift { shifted } else { normal }
+        },
+    })
+}
+
 const fn make_is_direct_only() -> [bool; KEY_MAX] {
     let mut table = [false; KEY_MAX];
     let mut i = 0;
@@ -524,12 +562,50 @@ pub struct Input {
     // gamemaker weirdness
     key_current: u8,
     key_previous: u8,
+    key_lastchar: u8,
     mouse_current: i8,
     mouse_previous: i8,
     mouse_position_previous: (i32, i32),
     numlock_state: bool, // spoofed!
 }
 
+// Input as savestates stored it before key_lastchar
+#[derive(Deserialize)]
+pub struct InputV1 {
+    button_remap: ArraySerde<u8, KEY_MAX>,
+    button_state: ArraySerde<bool, KEY_MAX>,
+    button_state_press: ArraySerde<bool, KEY_MAX>,
+    button_state_release: ArraySerde<bool, KEY_MAX>,
+    mouse_position: (i32, i32),
+    mouse_wheel: (bool, bool),
+    key_current: u8,
+    key_previous: u8,
+    mouse_current: i8,
+    mouse_previous: i8,
+    mouse_position_previous: (i32, i32),
+    numlock_state: bool,
+}
+
+impl From<InputV1> for Input {
+    fn from(old: InputV1) -> Self {
+        Self {
+            button_remap: old.button_remap,
+            button_state: old.button_state,
+            button_state_press: old.button_state_press,
+            button_state_release: old.button_state_release,
+            mouse_position: old.mouse_position,
+            mouse_wheel: old.mouse_wheel,
+            key_current: old.key_current,
+            key_previous: old.key_previous,
+            key_lastchar: 0,
+            mouse_current: old.mouse_current,
+            mouse_previous: old.mouse_previous,
+            mouse_position_previous: old.mouse_position_previous,
+            numlock_state: old.numlock_state,
+        }
+    }
+}
+
 impl Input {
     pub const fn new() -> Self {
         Input {
@@ -541,6 +617,7 @@ impl Input {
             mouse_wheel: (false, false),
             key_current: 0,
             key_previous: 0,
+            key_lastchar: 0,
             mouse_current: 0,
             mouse_previous: 0,
             mouse_position_previous: (0, 0),
@@ -555,6 +632,11 @@ impl Input {
         if store_cur_prev {
             self.key_current = code;
             self.key_previous = code;
+            let shift = self.keyboard_check_direct(Button::Shift as u8);
+            let control = self.keyboard_check_direct(Button::Control as u8);
+            if let Some(c) = vk2char(code, shift, control) {
+                self.key_lastchar = c;
+            }
         }
     }
 
@@ -703,7 +785,7 @@ impl Input {
     pub fn keyboard_clear_all(&mut self) {
         self.key_current = 0;
         self.key_previous = 0;
-        // TODO: self.key_lastchar = 0;
+        self.key_lastchar = 0;
         self.button_state.iter_mut().for_each(|x| *x = false);
         self.button_state_press.iter_mut().for_each(|x| *x = false);
         self.button_state_release.iter_mut().for_each(|x| *x = false);
@@ -743,6 +825,12 @@ impl Input {
         self.key_previous
     }
 
+    /// The last character typed, or 0 if none has been typed since it was cleared.
+    #[inline]
+    pub fn keyboard_lastchar(&self) -> u8 {
+        self.key_lastchar
+    }
+
     #[inline]
     pub fn set_keyboard_key(&mut self, vk: u8) {
         self.key_current = vk;
@@ -753,6 +841,11 @@ impl Input {
         self.key_previous = vk;
     }
 
+    #[inline]
+    pub fn set_keyboard_lastchar(&mut self, c: u8) {
+        self.key_lastchar = c;
+    }
+
     fn mouse_check_button_internal_indirect(&self, state: &[bool; KEY_MAX], mb: i8) -> bool {
         match mb {
             MB_ANY => {
@@ -890,3 +983,67 @@ impl Input {
         *self = Self::new();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Button, Input, MouseButton};
+
+    #[test]
+    fn lastchar() {
+        let mut input = Input::new();
+        assert_eq!(input.keyboard_lastchar(), 0);
+        input.button_press(Button::A as u8, true);
+        assert_eq!(input.keyboard_lastchar(), b'a');
+
+        // modifiers update keyboard_lastkey, but they don't type anything
+        input.button_press(Button::LeftShift as u8, true);
+        assert_eq!(input.keyboard_lastkey(), Button::LeftShift as u8);
+        assert_eq!(input.keyboard_lastchar(), b'a');
+        input.button_press(Button::Alpha1 as u8, true);
+        assert_eq!(input.keyboard_lastchar(), b'!');
+        input.button_press(Button::Oem7 as u8, true);
+        assert_eq!(input.keyboard_lastchar(), b'"');
+        input.button_release(Button::LeftShift as u8, true);
+        input.button_press(Button::Keypad5 as u8, true);
+        assert_eq!(input.keyboard_lastchar(), b'5');
+
+        // releasing keys doesn't change anything
+        input.button_release(Button::Keypad5 as u8, true);
+        assert_eq!(input.keyboard_lastkey(), Button::Keypad5 as u8);
+        assert_eq!(input.keyboard_lastchar(), b'5');
+
+        input.keyboard_clear_all();
+        assert_eq!(input.keyboard_lastchar(), 0);
+    }
+
+    #[test]
+    fn writable_variables() {
+        let mut input = Input::new();
+        input.button_press(Button::A as u8, true);
+        input.set_keyboard_key(Button::B as u8);
+        input.set_keyboard_lastkey(0);
+        input.set_keyboard_lastchar(b'x');
+
+        // writing keyboard_key doesn't hold the key down, it only changes what keyboard_key reads
+        assert!(!input.keyboard_check(Button::B as u8));
+        assert!(input.keyboard_check(Button::A as u8));
+
+        // releasing a key only clears keyboard_key if it's still that key
+        input.button_release(Button::A as u8, true);
+        assert_eq!(input.keyboard_key(), Button::B as u8);
+        assert_eq!(input.keyboard_lastkey(), 0);
+        assert_eq!(input.keyboard_lastchar(), b'x');
+
+        // the mouse goes through button_press too, but it doesn't touch the keyboard variables
+        input.mouse_press(MouseButton::Left as i8, true);
+        assert_eq!(input.mouse_lastbutton(), MouseButton::Left as i8);
+        assert_eq!(input.keyboard_key(), Button::B as u8);
+        assert_eq!(input.keyboard_lastkey(), 0);
+        assert_eq!(input.keyboard_lastchar(), b'x');
+
+        // a key which is written to keyboard_key is cleared by releasing it, even if it wasn't pressed
+        input.set_keyboard_key(Button::C as u8);
+        input.button_release(Button::C as u8, true);
+        assert_eq!(input.keyboard_key(), 0);
+    }
+}
@@ -15,6 +15,9 @@ use std::{
 
 // Default in GameMaker 8
 const BBOX_DEFAULT: i32 = -100000;
+
+/// What an alarm which has never been set reads as.
+pub const DEFAULT_ALARM: i32 = -1;
 // Rust can't represent this many decimal places yet I think. In GM8 it's a TBYTE definition
 const PI: f64 = 3.1415926535897932380;
 
@@ -235,6 +238,12 @@ impl Instance {
         self.update_speed_direction()
     }
 
+    // Adds a motion to the current one as a vector, like motion_add
+    pub fn motion_add(&self, direction: Real, speed: Real) {
+        let direction = direction.to_radians();
+        self.set_hvspeed(self.hspeed.get() + direction.cos() * speed, self.vspeed.get() - direction.sin() * speed);
+    }
+
     // Sets hspeed and vspeed based on direction and speed
     fn update_hvspeed(&self) {
         let round_threshold = Real::from(0.0001); // The fudge-factor used by GM8
@@ -345,6 +354,45 @@ impl Instance {
     pub fn is_active(&self) -> bool {
         self.state.get() == InstanceState::Active
     }
+
+    /// Distance between this instance's bounding box and another's, or 0 if they overlap, as used by
+    /// distance_to_object. Both bounding boxes need to be up to date.
+    pub fn bbox_distance(&self, other: &Instance) -> f64 {
+        // Bounding boxes include their right and bottom edges, so ones right next to each other are 1 apart
+        let gap = |left1: i32, right1: i32, left2: i32, right2: i32| (left1 - right2).max(left2 - right1).max(0);
+        let x = gap(self.bbox_left.get(), self.bbox_right.get(), other.bbox_left.get(), other.bbox_right.get());
+        let y = gap(self.bbox_top.get(), self.bbox_bottom.get(), other.bbox_top.get(), other.bbox_bottom.get());
+        f64::from(x).hypot(f64::from(y))
+    }
+
+    /// Distance from this instance's bounding box to a point, or 0 if it's inside, as used by distance_to_point.
+    /// The bounding box needs to be up to date.
+    pub fn bbox_distance_to_point(&self, x: Real, y: Real) -> Real {
+        let gap = |p: Real, low: i32, high: i32| {
+            if p < Real::from(low) {
+                Real::from(low) - p
+            } else if p > Real::from(high) {
+                p - Real::from(high)
+            } else {
+                Real::from(0.0)
+            }
+        };
+        let x = gap(x, self.bbox_left.get(), self.bbox_right.get());
+        let y = gap(y, self.bbox_top.get(), self.bbox_bottom.get());
+        x.into_inner().hypot(y.into_inner()).into()
+    }
+}
+
+/// Steps an image_index forward by image_speed, given how many frames the sprite has (if it has a sprite at all).
+/// Returns the new index, and whether it went off either end of the animation, which is when
+/// the Animation End event runs. As in GM8 it only wraps around once per step, even if image_speed is huge.
+pub fn advance_image(image_index: Real, image_speed: Real, frame_count: Option<usize>) -> (Real, bool) {
+    let new_index = image_index + image_speed;
+    match frame_count.map(|x| x as f64) {
+        Some(count) if new_index.into_inner() >= count => (new_index - Real::from(count), true),
+        Some(count) if new_index.into_inner() < 0.0 => (new_index + Real::from(count), true),
+        _ => (new_index, false),
+    }
 }
 
 impl Field {
@@ -401,3 +449,95 @@ impl DummyFieldHolder {
         Self { fields: HashMap::new(), vars: HashMap::new() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{advance_image, Instance};
+    use crate::{asset::Sprite, game::testing::sprite, math::Real};
+
+    // Runs an animation for a number of steps, returning the final index and which steps it ended on
+    fn animate(speed: f64, frames: usize, steps: usize) -> (Real, Vec<usize>) {
+        let mut index = Real::from(0.0);
+        let mut ends = Vec::new();
+        for step in 1..=steps {
+            let (new_index, ended) = advance_image(index, Real::from(speed), Some(frames));
+            assert!(new_index.into_inner() >= 0.0 && new_index.into_inner() < frames as f64);
+            index = new_index;
+            if ended {
+                ends.push(step);
+            }
+        }
+        (index, ends)
+    }
+
+    #[test]
+    fn fractional_speed() {
+        // 0.3333 per step over 4 frames ends every 12.0012 steps, so the ends slowly drift
+        let (index, ends) = animate(0.3333, 4, 1000);
+        assert_eq!(ends.len(), 83);
+        assert_eq!(&ends[..3], &[13, 25, 37]);
+        assert_eq!(ends.last(), Some(&997));
+        assert!((index.into_inner() - 1.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn backwards() {
+        // going backwards wraps round to the last frame straight away, then every 16 steps
+        let (index, ends) = animate(-0.25, 4, 1000);
+        assert_eq!(ends.len(), 63);
+        assert!(ends.iter().enumerate().all(|(i, &step)| step == 1 + i * 16));
+        assert!((index.into_inner() - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_sprite() {
+        assert_eq!(advance_image(Real::from(5.0), Real::from(1.0), None), (Real::from(6.0), false));
+        assert_eq!(advance_image(Real::from(0.0), Real::from(-1.0), None), (Real::from(-1.0), false));
+        assert_eq!(advance_image(Real::from(1.0), Real::from(0.0), Some(2)), (Real::from(1.0), false));
+    }
+
+    fn placed(x: f64, y: f64, xscale: f64, mask: Option<&Sprite>) -> Instance {
+        let instance = Instance::new_dummy(None);
+        instance.x.set(Real::from(x));
+        instance.y.set(Real::from(y));
+        instance.image_xscale.set(Real::from(xscale));
+        instance.bbox_is_stale.set(true);
+        instance.update_bbox(mask);
+        instance
+    }
+
+    #[test]
+    fn bbox_distances() {
+        let sprite = sprite();
+        let this = placed(0.0, 0.0, 1.0, Some(&sprite));
+        let distance = |x, y, xscale, mask| this.bbox_distance(&placed(x, y, xscale, mask));
+
+        // overlapping
+        assert_eq!(distance(8.0, 8.0, 1.0, Some(&sprite)), 0.0);
+        assert_eq!(distance(15.0, 15.0, 1.0, Some(&sprite)), 0.0);
+        // side by side, which is 1 apart because bboxes include their right edge
+        assert_eq!(distance(16.0, 0.0, 1.0, Some(&sprite)), 1.0);
+        assert_eq!(distance(0.0, -16.0, 1.0, Some(&sprite)), 1.0);
+        // diagonal
+        assert_eq!(distance(18.0, 19.0, 1.0, Some(&sprite)), 5.0);
+        assert_eq!(distance(-20.0, -20.0, 1.0, Some(&sprite)), 5.0f64.hypot(5.0));
+        // mirrored, so its bbox is to the left of its origin
+        assert_eq!(distance(34.0, 0.0, -1.0, Some(&sprite)), 2.0);
+        assert_eq!(distance(18.0, 0.0, -1.0, Some(&sprite)), 0.0);
+        // no mask leaves the bbox where GM8 puts it, far off in the top left
+        assert_eq!(distance(0.0, 0.0, 1.0, None), 100000.0f64.hypot(100000.0));
+    }
+
+    #[test]
+    fn bbox_point_distances() {
+        let sprite = sprite();
+        let this = placed(0.0, 0.0, 1.0, Some(&sprite));
+        let distance = |x, y| this.bbox_distance_to_point(Real::from(x), Real::from(y)).into_inner();
+
+        assert_eq!(distance(8.0, 8.0), 0.0);
+        assert_eq!(distance(15.0, 0.0), 0.0);
+        assert_eq!(distance(15.5, 8.0), 0.5);
+        assert_eq!(distance(8.0, -2.25), 2.25);
+        assert_eq!(distance(-3.0, 19.0), 5.0);
+    }
+}
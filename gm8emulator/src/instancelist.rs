@@ -358,6 +358,8 @@ impl InstanceList {
         }
     }
 
+    /// Marks an instance as destroyed. It stays in the list, and its handle stays valid, until it's removed with
+    /// remove_with at the end of the step, but none of the iterators or lookups by id will find it in the meantime.
     pub fn mark_deleted(&mut self, handle: usize) {
         let instance = self.get(handle);
         if instance.state.get() != InstanceState::Deleted {
@@ -568,3 +570,198 @@ fn defrag_map(map: &HashMap<i32, Vec<usize>>, all_insts: &[usize]) -> HashMap<i3
 }
 
 // TODO: Maybe preallocating order/draw_order would increase perf - test this!
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asset::Object, math::Real};
+    use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+    // xorshift, so that the same "random" frames get checked every time
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    // Which instances a loop goes over: everything, or instances of an object with or without its children
+    #[derive(Clone, Copy, Debug)]
+    enum Loop {
+        All,
+        Object(ID),
+        Identity(ID),
+    }
+
+    enum Iter {
+        All(ILIterDrawOrder),
+        Object(ObjectIter),
+        Identity(IdentityIter),
+    }
+
+    impl Iter {
+        fn next(&mut self, list: &InstanceList) -> Option<usize> {
+            match self {
+                Iter::All(iter) => iter.next(list),
+                Iter::Object(iter) => iter.next(list),
+                Iter::Identity(iter) => iter.next(list),
+            }
+        }
+    }
+
+    struct Frame {
+        list: InstanceList,
+        objects: Vec<Object>,
+        // handles in the order their instances were created, and the other way around
+        handles: Vec<usize>,
+        created: HashMap<usize, usize>,
+        rng: Rng,
+    }
+
+    impl Frame {
+        // Objects 0 and 1 have no parent, 2 is a child of 0, and 3 is a child of 2
+        fn new(seed: u64) -> Self {
+            let parents: [&[ID]; 4] = [&[0], &[1], &[2, 0], &[3, 2, 0]];
+            let objects = parents
+                .iter()
+                .map(|parents| Object {
+                    name: "".into(),
+                    solid: false,
+                    visible: true,
+                    persistent: false,
+                    depth: 0,
+                    sprite_index: -1,
+                    mask_index: -1,
+                    parent_index: parents.get(1).copied().unwrap_or(-1),
+                    events: Default::default(),
+                    children: Default::default(),
+                    parents: Rc::new(RefCell::new(parents.iter().copied().collect())),
+                })
+                .collect();
+            let mut frame = Self {
+                list: InstanceList::new(),
+                objects,
+                handles: Vec::new(),
+                created: HashMap::new(),
+                rng: Rng(seed),
+            };
+            for _ in 0..10 {
+                frame.create();
+            }
+            frame
+        }
+
+        fn create(&mut self) {
+            let object_index = self.rng.below(self.objects.len()) as ID;
+            let id = 100001 + self.created.len() as ID;
+            let object = &self.objects[object_index as usize];
+            let handle = self.list.insert(Instance::new(id, Real::from(0), Real::from(0), object_index, object));
+            self.created.insert(handle, self.handles.len());
+            self.handles.push(handle);
+        }
+
+        fn matches(&self, kind: Loop, handle: usize) -> bool {
+            let instance = self.list.get(handle);
+            match kind {
+                Loop::All => true,
+                Loop::Object(object) => instance.object_index.get() == object,
+                Loop::Identity(object) => instance.parents.borrow().contains(&object),
+            }
+        }
+
+        fn random_loop(&mut self) -> Loop {
+            let object = self.rng.below(self.objects.len()) as ID;
+            match self.rng.below(3) {
+                0 => Loop::All,
+                1 => Loop::Object(object),
+                _ => Loop::Identity(object),
+            }
+        }
+
+        // Goes over some instances like an event or a with() does, creating and destroying instances and starting
+        // more loops from inside it at random
+        fn run(&mut self, kind: Loop, depth: usize) {
+            let created_before = self.created.len();
+            let mut iter = match kind {
+                Loop::All => Iter::All(self.list.iter_by_drawing()),
+                Loop::Object(object) => Iter::Object(self.list.iter_by_object(object)),
+                Loop::Identity(object) => Iter::Identity(self.list.iter_by_identity(object)),
+            };
+            let mut visited = HashSet::new();
+            while let Some(handle) = iter.next(&self.list) {
+                assert!(self.list.get(handle).is_active());
+                assert!(self.matches(kind, handle));
+                // only the draw order iterators are bounded to where the list ended when they started
+                if let Loop::All = kind {
+                    assert!(self.created[&handle] < created_before, "loop visited an instance created during it");
+                }
+                assert!(visited.insert(handle), "{:?} visited an instance twice", kind);
+                match self.rng.below(12) {
+                    0..=3 if self.handles.len() < 100 => self.create(),
+                    4 => self.list.mark_deleted(self.handles[self.rng.below(self.handles.len())]),
+                    5 if depth < 2 => {
+                        let kind = self.random_loop();
+                        self.run(kind, depth + 1);
+                    },
+                    _ => (),
+                }
+            }
+
+            // and everything which was there when the loop started, and still is, got visited
+            for &handle in &self.handles[..created_before] {
+                if self.list.get(handle).is_active() && self.matches(kind, handle) {
+                    assert!(visited.contains(&handle), "{:?} missed an instance", kind);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn creation_during_iteration() {
+        for seed in 1..100 {
+            let mut frame = Frame::new(seed);
+            // begin step, step, end step and so on, where instances created in one phase take part in the next
+            for _ in 0..6 {
+                let kind = frame.random_loop();
+                frame.run(kind, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn deleted_instances() {
+        let mut frame = Frame::new(7);
+        let (kept, deleted) = (frame.handles[0], frame.handles[1]);
+        let (kept_id, deleted_id) = (frame.list.get(kept).id.get(), frame.list.get(deleted).id.get());
+        frame.list.get(deleted).x.set(Real::from(12.0));
+        frame.list.mark_deleted(deleted);
+
+        // its variables can still be read through its handle, but nothing finds it any more
+        assert_eq!(f64::from(frame.list.get(deleted).x.get()), 12.0);
+        assert_eq!(frame.list.get_by_instid(deleted_id), None);
+        assert_eq!(frame.list.get_by_instid(kept_id), Some(kept));
+        let mut iter = frame.list.iter_by_drawing();
+        while let Some(handle) = iter.next(&frame.list) {
+            assert_ne!(handle, deleted);
+        }
+        let object = frame.list.get(deleted).object_index.get();
+        let mut iter = frame.list.iter_by_object(object);
+        while let Some(handle) = iter.next(&frame.list) {
+            assert_ne!(handle, deleted);
+        }
+        assert_eq!(frame.list.count_all_active(), 9);
+
+        // it's still marked as destroyed after a savestate, and is removed at the end of the step as usual
+        let bytes = bincode::serialize(&frame.list).unwrap();
+        let mut list: InstanceList = bincode::deserialize(&bytes).unwrap();
+        assert_eq!((list.count_all(), list.count_all_active()), (10, 9));
+        assert_eq!(list.get_by_instid(deleted_id), None);
+        list.remove_with(|instance| instance.state.get() == InstanceState::Deleted);
+        assert_eq!((list.count_all(), list.count_all_active()), (9, 9));
+        assert!(list.get_by_instid(kept_id).is_some());
+    }
+}
@@ -0,0 +1,283 @@
+//! Translations of the record mode UI.
+//!
+//! Each language is a TOML file in `locale/` named after its language code, with a string for each key.
+//! `{}` in a value is filled in by `trf`. Anything a language doesn't have is shown in English.
+//!
+//! Dear imgui has no bidirectional text support, so right-to-left languages would be shown backwards and aren't
+//! offered. Text is drawn as UTF-8 exactly as given, so characters from any other script show up as long as the
+//! UI font has them - see `scripts` and `font_file`.
+
+use encoding_rs::{
+    Encoding, BIG5, EUC_JP, EUC_KR, GB18030, GBK, IBM866, ISO_2022_JP, ISO_8859_5, KOI8_R, KOI8_U, SHIFT_JIS,
+    WINDOWS_1251, WINDOWS_874,
+};
+use std::{collections::HashMap, env, fmt::Display, path::PathBuf, sync::OnceLock};
+
+/// Every language the UI is available in, as its code and file. English comes first as it's the fallback.
+const LOCALES: &[(&str, &str)] =
+    &[("en", include_str!("../locale/en.toml")), ("ja", include_str!("../locale/ja.toml"))];
+
+struct Strings {
+    language: &'static str,
+    current: HashMap<String, String>,
+    english: HashMap<String, String>,
+}
+
+static STRINGS: OnceLock<Strings> = OnceLock::new();
+
+/// A writing system the UI font may need characters from, on top of the Latin ones imgui's default font has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Script {
+    Japanese,
+    Korean,
+    Chinese,
+    Cyrillic,
+    Thai,
+}
+
+/// Picks the UI language: `lang` if it's given, otherwise the system's language.
+/// If `lang` isn't available, English is used and an error message is returned.
+pub fn init(lang: Option<&str>) -> Result<(), String> {
+    let wanted = match lang {
+        Some(lang) => Some(normalize(lang)),
+        None => system_language().map(|x| normalize(&x)),
+    };
+    let locale = LOCALES.iter().find(|(code, _)| Some(*code) == wanted.as_deref()).unwrap_or(&LOCALES[0]);
+    STRINGS.get_or_init(|| load(locale));
+    match lang {
+        Some(lang) if locale.0 != wanted.unwrap_or_default() => {
+            let available = LOCALES.iter().map(|(code, _)| *code).collect::<Vec<_>>().join(", ");
+            Err(format!("the UI isn't available in '{}', using English (available: {})", lang, available))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// The code of the UI language, such as "en".
+pub fn language() -> &'static str {
+    strings().language
+}
+
+/// Looks up a string in the UI language, falling back to English and then to the key itself.
+pub fn tr(key: &'static str) -> &'static str {
+    let strings = strings();
+    strings.current.get(key).or_else(|| strings.english.get(key)).map(String::as_str).unwrap_or(key)
+}
+
+/// Like `tr`, with each `{}` in the string replaced by the next argument.
+pub fn trf(key: &'static str, args: &[&dyn Display]) -> String {
+    let mut args = args.iter();
+    let mut parts = tr(key).split("{}");
+    let mut out = String::from(parts.next().unwrap_or_default());
+    for part in parts {
+        if let Some(arg) = args.next() {
+            out += &arg.to_string();
+        }
+        out += part;
+    }
+    out
+}
+
+/// Which scripts the UI font needs for the UI language and for text in the game's code page.
+pub fn scripts(language: &str, encoding: &'static Encoding) -> Vec<Script> {
+    let from_language = match language {
+        "ja" => Some(Script::Japanese),
+        "ko" => Some(Script::Korean),
+        "zh" => Some(Script::Chinese),
+        "be" | "bg" | "kk" | "mk" | "ru" | "sr" | "uk" => Some(Script::Cyrillic),
+        "th" => Some(Script::Thai),
+        _ => None,
+    };
+    let code_pages = [
+        (SHIFT_JIS, Script::Japanese),
+        (EUC_JP, Script::Japanese),
+        (ISO_2022_JP, Script::Japanese),
+        (EUC_KR, Script::Korean),
+        (GBK, Script::Chinese),
+        (GB18030, Script::Chinese),
+        (BIG5, Script::Chinese),
+        (WINDOWS_1251, Script::Cyrillic),
+        (KOI8_R, Script::Cyrillic),
+        (KOI8_U, Script::Cyrillic),
+        (IBM866, Script::Cyrillic),
+        (ISO_8859_5, Script::Cyrillic),
+        (WINDOWS_874, Script::Thai),
+    ];
+    let from_encoding = code_pages.iter().find(|(e, _)| *e == encoding).map(|&(_, script)| script);
+    let mut scripts = from_language.into_iter().collect::<Vec<_>>();
+    if let Some(script) = from_encoding.filter(|x| !scripts.contains(x)) {
+        scripts.push(script);
+    }
+    scripts
+}
+
+/// Finds an installed font file with characters for the given script.
+pub fn font_file(script: Script) -> Option<PathBuf> {
+    const NOTO_CJK: &[&str] = &[
+        "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+        "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    ];
+    let (windows_fonts, other_fonts): (&[&str], &[&str]) = match script {
+        Script::Japanese => (&["meiryo.ttc", "msgothic.ttc", "YuGothM.ttc"], NOTO_CJK),
+        Script::Korean => (&["malgun.ttf", "gulim.ttc"], NOTO_CJK),
+        Script::Chinese => (&["msyh.ttc", "simsun.ttc"], NOTO_CJK),
+        Script::Cyrillic => (&["arial.ttf", "tahoma.ttf"], &[
+            "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+            "/usr/share/fonts/TTF/DejaVuSans.ttf",
+            "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+            "/System/Library/Fonts/Supplemental/Arial.ttf",
+        ]),
+        Script::Thai => (&["tahoma.ttf", "LeelawUI.ttf"], &[
+            "/usr/share/fonts/truetype/noto/NotoSansThai-Regular.ttf",
+            "/usr/share/fonts/noto/NotoSansThai-Regular.ttf",
+            "/usr/share/fonts/truetype/tlwg/Garuda.ttf",
+            "/System/Library/Fonts/Supplemental/Tahoma.ttf",
+        ]),
+    };
+    let windows_dir = env::var_os("WINDIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("C:\\Windows"));
+    windows_fonts
+        .iter()
+        .map(|name| windows_dir.join("Fonts").join(name))
+        .chain(other_fonts.iter().map(PathBuf::from))
+        .find(|path| path.is_file())
+}
+
+fn strings() -> &'static Strings {
+    STRINGS.get_or_init(|| load(&LOCALES[0]))
+}
+
+fn load(&(language, file): &(&'static str, &'static str)) -> Strings {
+    let parse = |language, file| parse(file).unwrap_or_else(|e| panic!("locale/{}.toml: {}", language, e));
+    Strings { language, current: parse(language, file), english: parse(LOCALES[0].0, LOCALES[0].1) }
+}
+
+/// Turns a locale name like "ja_JP.UTF-8" or "en-GB" into a language code like "ja" or "en".
+fn normalize(locale: &str) -> String {
+    locale.split(['_', '-', '.', '@']).next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+fn system_language() -> Option<String> {
+    let from_env =
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter().filter_map(|var| env::var(var).ok()).find(|x| !x.is_empty());
+    from_env.or_else(user_locale_name)
+}
+
+#[cfg(target_os = "windows")]
+fn user_locale_name() -> Option<String> {
+    use std::os::raw::c_int;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetUserDefaultLocaleName(name: *mut u16, len: c_int) -> c_int;
+    }
+    const LOCALE_NAME_MAX_LENGTH: usize = 85;
+
+    let mut name = [0u16; LOCALE_NAME_MAX_LENGTH];
+    let len = unsafe { GetUserDefaultLocaleName(name.as_mut_ptr(), name.len() as c_int) };
+    // the length includes the nul terminator, and is 0 if it failed
+    if len > 1 { Some(String::from_utf16_lossy(&name[..len as usize - 1])) } else { None }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn user_locale_name() -> Option<String> {
+    None
+}
+
+/// Parses a locale file, which has to be a flat table of strings.
+fn parse(file: &str) -> Result<HashMap<String, String>, String> {
+    // this goes through a Value because deserializing straight into a map doesn't notice duplicate keys
+    file.parse::<toml::Value>().and_then(|x| x.try_into()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    /// Every key passed to tr or trf in the emulator's source.
+    fn used_keys() -> Vec<String> {
+        fn visit(dir: &Path, keys: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    visit(&path, keys);
+                } else if path.extension().is_some_and(|x| x == "rs") {
+                    let source = fs::read_to_string(&path).unwrap();
+                    for call in ["tr(\"", "trf(\""].iter() {
+                        for (pos, _) in source.match_indices(call) {
+                            // skip things like str(" which only end in tr
+                            let before = source[..pos].chars().next_back().unwrap_or(' ');
+                            if before.is_alphanumeric() || before == '_' {
+                                continue
+                            }
+                            let rest = &source[pos + call.len()..];
+                            keys.push(rest[..rest.find('"').unwrap()].to_string());
+                        }
+                    }
+                }
+            }
+        }
+        let mut keys = Vec::new();
+        visit(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut keys);
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    #[test]
+    fn locales_have_every_key() {
+        let keys = used_keys();
+        assert!(keys.len() > 50, "only found {} keys", keys.len());
+        for (language, file) in LOCALES {
+            let strings = parse(file).unwrap_or_else(|e| panic!("{}: {}", language, e));
+            let missing = keys.iter().filter(|k| !strings.contains_key(k.as_str())).collect::<Vec<_>>();
+            assert!(missing.is_empty(), "missing from locale/{}.toml: {:?}", language, missing);
+        }
+    }
+
+    #[test]
+    fn translations_match_english() {
+        let english = parse(LOCALES[0].1).unwrap();
+        for (language, file) in &LOCALES[1..] {
+            for (key, value) in parse(file).unwrap() {
+                let original = english.get(&key).unwrap_or_else(|| panic!("{}: {} isn't in English", language, key));
+                assert_eq!(value.matches("{}").count(), original.matches("{}").count(), "{}: {}", language, key);
+            }
+        }
+    }
+
+    #[test]
+    fn parsing() {
+        let file = "# comment\n\na = \"x\"\n  b_2 = \"say \\\"hi\\\"\\n\\\\\" # trailing\nc=\"\"\nd = 'C:\\dir'\n";
+        let strings = parse(file).unwrap();
+        assert_eq!(strings["a"], "x");
+        assert_eq!(strings["b_2"], "say \"hi\"\n\\");
+        assert_eq!(strings["c"], "");
+        assert_eq!(strings["d"], "C:\\dir");
+        for bad in ["a = x", "a = \"x", "a = \"x\" y", "a.b = \"x\"", "= \"x\"", "a = 1", "a = \"1\"\na = \"2\""].iter() {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+        assert_eq!(normalize("ja_JP.UTF-8"), "ja");
+        assert_eq!(normalize("en-GB"), "en");
+        assert_eq!(normalize("C"), "c");
+    }
+
+    #[test]
+    fn fonts_for_game_text() {
+        assert_eq!(scripts("en", encoding_rs::WINDOWS_1252), []);
+        assert_eq!(scripts("en", SHIFT_JIS), [Script::Japanese]);
+        assert_eq!(scripts("ja", SHIFT_JIS), [Script::Japanese]);
+        assert_eq!(scripts("ja", WINDOWS_1251), [Script::Japanese, Script::Cyrillic]);
+        assert_eq!(scripts("ru", encoding_rs::UTF_8), [Script::Cyrillic]);
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(trf("control_frame", &[&12]), "Frame: 12");
+        // a key which isn't in a variable would be picked up by locales_have_every_key
+        let missing = "no_such_key";
+        assert_eq!(tr(missing), missing);
+    }
+}
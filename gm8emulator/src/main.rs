@@ -2,6 +2,9 @@
 
 mod action;
 mod asset;
+mod bundle;
+mod coverage;
+mod encoding;
 mod game;
 mod gml;
 mod handleman;
@@ -9,6 +12,7 @@ mod imgui;
 mod input;
 mod instance;
 mod instancelist;
+mod locale;
 mod math;
 mod render;
 mod tile;
@@ -16,17 +20,24 @@ mod types;
 mod util;
 
 use game::{
+    autosave::AutosaveConfig,
+    cmdline,
+    error::ExitReason,
     savestate::{self, SaveState},
     Game, PlayType, Replay,
 };
 use std::{
-    env, fs,
+    env,
+    ffi::OsString,
+    fs, io,
     path::{Path, PathBuf},
     process,
 };
 
 const EXIT_SUCCESS: i32 = 0;
 const EXIT_FAILURE: i32 = 1;
+const EXIT_LAUNCH_FAILURE: i32 = 2;
+const EXIT_RUNTIME_ERROR: i32 = 3;
 
 fn help(argv0: &str, opts: getopts::Options) {
     print!(
@@ -38,13 +49,63 @@ fn help(argv0: &str, opts: getopts::Options) {
     );
 }
 
+/// Writes a copy of this executable with the game and its config appended, so it runs the game by itself.
+fn write_bundle(output: &Path, game: &[u8], config: &bundle::Config) -> io::Result<()> {
+    let exe = fs::read(env::current_exe()?)?;
+    let mut file = fs::File::create(output)?;
+    bundle::write(&mut file, bundle::emulator(&exe)?, game, config)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
 fn main() {
     process::exit(xmain());
 }
 
 fn xmain() -> i32 {
-    let args: Vec<String> = env::args().collect();
-    let process = args[0].clone();
+    let args: Vec<OsString> = env::args_os().collect();
+    let process = args[0].to_string_lossy().into_owned();
+
+    // A bundled game runs with the options it was bundled with, and the whole command line goes to the game
+    let own_exe = env::current_exe().ok();
+    let mut bundle = match own_exe.as_ref().map(fs::File::open) {
+        Some(Ok(mut exe)) => match bundle::read(&mut exe) {
+            Ok(bundle) => bundle,
+            Err(e) => {
+                eprintln!("failed to read bundled game: {}", e);
+                return EXIT_FAILURE
+            },
+        },
+        _ => None,
+    };
+    let cli_args = match &bundle {
+        Some(bundle) => bundle.config.options.iter().map(OsString::from).collect(),
+        None => args[1..].to_vec(),
+    };
+
+    // getopts only accepts UTF-8, so anything else (most likely a Shift-JIS filename) is swapped for a placeholder
+    // while parsing and swapped back afterwards. Arguments can't contain NUL, so the placeholders can't clash.
+    let mut non_utf8 = Vec::new();
+    let utf8_args = cli_args
+        .iter()
+        .map(|arg| match arg.to_str() {
+            Some(arg) => arg.to_string(),
+            None => {
+                non_utf8.push(arg.clone());
+                format!("\0{}", non_utf8.len() - 1)
+            },
+        })
+        .collect::<Vec<_>>();
+    let restore_arg = |arg: &str| -> OsString {
+        match arg.strip_prefix('\0').and_then(|i| i.parse::<usize>().ok()).and_then(|i| non_utf8.get(i)) {
+            Some(arg) => arg.clone(),
+            None => arg.into(),
+        }
+    };
 
     let mut opts = getopts::Options::new();
     opts.optflag("h", "help", "prints this help message");
@@ -53,12 +114,58 @@ fn xmain() -> i32 {
     opts.optflag("v", "verbose", "enables verbose logging");
     opts.optflag("r", "realtime", "disables clock spoofing");
     opts.optflag("l", "no-framelimit", "disables the frame-limiter");
+    opts.optflag("", "headless", "play a replay (-f) with no window or audio, exiting when it ends");
+    opts.optflag("c", "capture-files", "capture the temp directory in savestates (record mode only)");
+    opts.optopt(
+        "",
+        "capture-save-dir",
+        "with --capture-files, also capture DIR, relative to the game's folder, where the game keeps its saves",
+        "DIR",
+    );
     opts.optopt("n", "project-name", "name of TAS project to create or load", "NAME");
     opts.optopt("f", "replay-file", "path to savestate file to replay", "FILE");
     opts.optopt("o", "output-file", "output savestate name in replay mode", "FILE.bin");
-    opts.optmulti("a", "game-arg", "argument to pass to the game", "ARG");
+    opts.optopt("", "export-replay-text", "write the replay given with -f out as text, then exit", "FILE.txt");
+    opts.optflag("", "replay-info", "print the frame count, rerecords and game of the replay given with -f, then exit");
+    opts.optmulti("a", "game-arg", "argument to pass to the game (split on spaces and unquoted like GM8 does)", "ARG");
+    opts.optopt(
+        "b",
+        "bundle",
+        "write a copy of the emulator which runs the game with these options, playing the replay given with -f first",
+        "OUTPUT",
+    );
+    opts.optopt("", "rng-log", "write every random number the game draws to FILE when it closes", "FILE");
+    opts.optopt("", "rng-compare", "check the random numbers drawn against a log written by --rng-log", "FILE");
+    opts.optopt("", "game-log", "also write the game's show_debug_message output to FILE", "FILE");
+    opts.optopt("", "game-log-size", "show_debug_message lines kept for the record mode console (default 1000)", "N");
+    opts.optflag("", "debug-mode", "run the game as if from GameMaker's debug mode, so debug_mode is true");
+    opts.optopt("", "dump-atlas", "write all texture pages and an index of them to DIR when the game closes", "DIR");
+    opts.optopt("", "dump-frames", "with -f, save the screen to DIR every N frames (default 60) as PNGs", "DIR[:N]");
+    opts.optflag("", "count-unimplemented", "carry on past unimplemented functions and list them when the game closes");
+    opts.optopt("", "autosave-interval", "seconds between autosaves when recording, 0 for never (default 60)", "SECS");
+    opts.optopt("", "autosave-rerecords", "re-records between autosaves, 0 for never (default 25)", "N");
+    opts.optopt("", "autosave-backups", "number of older autosaves to keep (default 5)", "N");
+    opts.optflag("", "coverage", "list the kernel functions the game calls which aren't fully supported, then exit");
+    opts.optopt("", "coverage-json", "with --coverage, also write the full report to FILE as JSON", "FILE");
+    opts.optopt("", "audio-memory", "evict long-unplayed sounds without preload once they take up MB megabytes", "MB");
+    opts.optopt("", "audio-device", "play sound on the output device called NAME instead of the default one", "NAME");
+    opts.optflag("", "frame-times", "draw a graph of how long recent frames took over the bottom left of the game");
+    opts.optflag("", "no-cull", "send sprites outside the view to the GPU anyway (for debugging the renderer)");
+    opts.optopt(
+        "",
+        "encoding",
+        "text encoding of GM8.0 games, like windows-1252, or auto to guess (default: the system's, or shift_jis \
+         when recording or replaying)",
+        "LABEL",
+    );
+    opts.optopt("", "lang", "language for the record mode UI, such as en or ja (default: the system language)", "LANG");
+    #[cfg(feature = "metrics")]
+    {
+        opts.optopt("", "metrics-port", "serve stats as JSON over HTTP on PORT (localhost only) or on IP:PORT", "PORT");
+        opts.optmulti("", "metrics-watch", "a GML expression to include in the metrics, such as global.deaths", "EXPR");
+    }
 
-    let matches = match opts.parse(&args[1..]) {
+    let matches = match opts.parse(&utf8_args) {
         Ok(matches) => matches,
         Err(fail) => {
             use getopts::Fail::*;
@@ -73,17 +180,92 @@ fn xmain() -> i32 {
         },
     };
 
-    if args.len() < 2 || matches.opt_present("h") {
+    if (args.len() < 2 && bundle.is_none()) || matches.opt_present("h") {
         help(&process, opts);
         return EXIT_SUCCESS
     }
 
+    if let Err(e) = locale::init(matches.opt_str("lang").as_deref()) {
+        eprintln!("{}", e);
+    }
+
     let strict = matches.opt_present("s");
     let multithread = !matches.opt_present("t");
     let spoof_time = !matches.opt_present("r");
-    let frame_limiter = !matches.opt_present("l");
+    let headless = matches.opt_present("headless");
+    let frame_limiter = !matches.opt_present("l") && !headless;
     let verbose = matches.opt_present("v");
+    let capture_files = matches.opt_present("c");
+    // relative to the game's folder, where the game itself would look for them
+    let capture_save_dir = matches.opt_str("capture-save-dir").map(PathBuf::from);
     let output_bin = matches.opt_str("o").map(PathBuf::from);
+    let bundle_output = matches.opt_str("b").map(PathBuf::from);
+    let dump_atlas = matches.opt_str("dump-atlas").map(PathBuf::from);
+    let dump_frames = match matches.opt_str("dump-frames").map(|x| game::framedump::parse_arg(&x)).transpose() {
+        Ok(dump_frames) => dump_frames,
+        Err(e) => {
+            eprintln!("invalid --dump-frames: {}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let count_unimplemented = matches.opt_present("count-unimplemented");
+    let no_cull = matches.opt_present("no-cull");
+    let coverage = matches.opt_present("coverage");
+    let coverage_json = matches.opt_str("coverage-json").map(PathBuf::from);
+    if count_unimplemented {
+        gml::unimplemented::start_counting();
+    }
+    let rng_log = matches.opt_str("rng-log").map(PathBuf::from);
+    let game_log = matches.opt_str("game-log").map(PathBuf::from);
+    let debug_mode = matches.opt_present("debug-mode");
+    let frame_times = matches.opt_present("frame-times");
+    let game_log_size = match matches.opt_get_default("game-log-size", game::gamelog::DEFAULT_CAPACITY) {
+        Ok(size) => size,
+        Err(e) => {
+            eprintln!("invalid --game-log-size: {}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let rng_compare = match matches.opt_str("rng-compare") {
+        Some(path) => match fs::read_to_string(&path) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                eprintln!("failed to read RNG log '{}': {}", path, e);
+                return EXIT_FAILURE
+            },
+        },
+        None => None,
+    };
+    let defaults = AutosaveConfig::default();
+    let autosave_config = match (
+        matches.opt_get_default("autosave-interval", defaults.interval),
+        matches.opt_get_default("autosave-rerecords", defaults.rerecords),
+        matches.opt_get_default("autosave-backups", defaults.backups),
+    ) {
+        (Ok(interval), Ok(rerecords), Ok(backups)) => AutosaveConfig { interval, rerecords, backups },
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            eprintln!("invalid autosave option: {}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let audio_memory = match matches.opt_get::<usize>("audio-memory") {
+        Ok(megabytes) => megabytes.map(|x| x * 1024 * 1024),
+        Err(e) => {
+            eprintln!("invalid audio memory budget: {}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let audio_device = matches.opt_str("audio-device");
+    #[cfg(feature = "metrics")]
+    let metrics_address = match matches.opt_str("metrics-port").map(|x| game::metrics::parse_address(&x)).transpose() {
+        Ok(address) => address,
+        Err(e) => {
+            eprintln!("invalid metrics port: {}", e);
+            return EXIT_FAILURE
+        },
+    };
+    #[cfg(feature = "metrics")]
+    let metrics_watches = matches.opt_strs("metrics-watch");
     let project_path = matches.opt_str("n").map(|name| {
         let mut p = env::current_dir().expect("std::env::current_dir() failed");
         p.push("projects");
@@ -99,32 +281,35 @@ fn xmain() -> i32 {
     }
 
     let temp_dir = project_path.as_ref().map(|proj_path| {
-        // attempt to find temp dir in project path
-        std::fs::read_dir(proj_path)
-            .ok()
-            .and_then(|iter| {
-                iter.filter_map(|x| x.ok())
-                    .find(|p| {
-                        p.metadata().ok().filter(|p| p.is_dir()).is_some()
-                            && p.file_name().to_str().filter(|s| s.starts_with("gm_ttt_")).is_some()
-                    })
-                    .map(|entry| entry.path())
-            })
-            // if we can't find one, make one
-            .unwrap_or_else(|| {
-                let mut random_int = [0u8; 4];
-                getrandom::getrandom(&mut random_int).expect("Couldn't generate a random number");
-                let path = [proj_path.clone(), format!("gm_ttt_{}", u32::from_le_bytes(random_int) % 100000).into()]
-                    .iter()
-                    .collect();
-                if let Err(e) = std::fs::create_dir_all(&path) {
-                    println!("Could not create temp folder: {}", e);
-                    println!("If this game uses the temp folder, it will most likely crash.");
-                }
-                path
-            })
+        game::tempdir::find_or_create_in_project(proj_path).unwrap_or_else(|e| {
+            println!("Could not create temp folder: {}", e);
+            println!("If this game uses the temp folder, it will most likely crash.");
+            proj_path.join(game::tempdir::PREFIX.to_string() + "0")
+        })
     });
     let can_clear_temp_dir = temp_dir.is_none();
+    let mut captured_dirs = temp_dir.iter().cloned().collect::<Vec<_>>();
+    if capture_files && project_path.is_none() {
+        eprintln!("warning: --capture-files has no effect outside of record mode");
+    }
+    if capture_save_dir.is_some() && !capture_files {
+        eprintln!("warning: --capture-save-dir has no effect without --capture-files");
+    }
+    if dump_atlas.is_some() && project_path.is_some() {
+        eprintln!("warning: --dump-atlas has no effect in record mode, use the Dump Atlas button instead");
+    }
+    if (rng_log.is_some() || rng_compare.is_some()) && project_path.is_some() {
+        eprintln!("warning: --rng-log and --rng-compare have no effect in record mode");
+    }
+    #[cfg(feature = "metrics")]
+    {
+        if metrics_address.is_some() && project_path.is_some() {
+            eprintln!("warning: --metrics-port has no effect in record mode");
+        }
+        if !metrics_watches.is_empty() && metrics_address.is_none() {
+            eprintln!("warning: --metrics-watch has no effect without --metrics-port");
+        }
+    }
     let replay = match matches
         .opt_str("f")
         .map(|filename| {
@@ -140,7 +325,12 @@ fn xmain() -> i32 {
                     Err(e) => Err(format!("couldn't load {:?}: {:?}", filepath, e)),
                 },
 
-                _ => Err("unknown filetype for -f, expected '.bin' or '.gmtas'".into()),
+                Some("txt") => match fs::read_to_string(&filepath) {
+                    Ok(text) => Replay::from_text(&text).map_err(|e| format!("couldn't load {:?}: {}", filepath, e)),
+                    Err(e) => Err(format!("couldn't load {:?}: {}", filepath, e)),
+                },
+
+                _ => Err("unknown filetype for -f, expected '.bin', '.gmtas' or '.txt'".into()),
             }
         })
         .transpose()
@@ -152,9 +342,95 @@ fn xmain() -> i32 {
         },
     };
 
+    if matches.opt_present("replay-info") {
+        let stats = match &replay {
+            Some(replay) => replay.stats(),
+            None => {
+                eprintln!("--replay-info needs a replay to look at with -f");
+                return EXIT_FAILURE
+            },
+        };
+        println!("frames: {}", stats.frame_count);
+        println!("rerecords: {}", stats.rerecords);
+        match stats.game {
+            Some(game) => println!("game: ID {}, gamedata hash {:016x}", game.game_id, game.gamedata_hash),
+            None => println!("game: unknown, the replay is from before this was saved"),
+        }
+        match (stats.first_input, stats.last_input) {
+            (Some(first), Some(last)) => println!("input: frames {} to {}", first, last),
+            _ => println!("input: none"),
+        }
+        return EXIT_SUCCESS
+    }
+
+    if let Some(path) = matches.opt_str("export-replay-text") {
+        let replay = match &replay {
+            Some(replay) => replay,
+            None => {
+                eprintln!("--export-replay-text needs a replay to export with -f");
+                return EXIT_FAILURE
+            },
+        };
+        return match fs::write(&path, replay.to_text()) {
+            Ok(()) => {
+                println!("wrote {} frames to '{}'", replay.frame_count(), path);
+                EXIT_SUCCESS
+            },
+            Err(e) => {
+                eprintln!("failed to write '{}': {}", path, e);
+                EXIT_FAILURE
+            },
+        }
+    }
+
+    // a bundle's intro is played like a replay, but the player takes over once it runs out
+    let intro = bundle.as_mut().and_then(|bundle| bundle.config.intro.take()).map(|bundle::Intro(intro)| intro);
+
+    // None means guessing from the game's text once it's loaded
+    let encoding_label = matches.opt_str("encoding");
+    let encoding = match encoding_label.as_deref() {
+        Some(label) if label.eq_ignore_ascii_case("auto") => None,
+        Some(label) => match encoding::from_label(label) {
+            Ok(encoding) => Some(encoding),
+            Err(e) => {
+                eprintln!("invalid --encoding: {}", e);
+                return EXIT_FAILURE
+            },
+        },
+        None if project_path.is_some() || replay.is_some() || intro.is_some() => Some(encoding::FALLBACK),
+        None => Some(encoding::system()),
+    };
+
+    if bundle_output.is_some() && (project_path.is_some() || output_bin.is_some()) {
+        eprintln!("--bundle can't be used with -n or -o");
+        return EXIT_FAILURE
+    }
+    if headless && ((replay.is_none() && intro.is_none()) || project_path.is_some()) {
+        eprintln!("--headless needs a replay to play with -f, and can't be used with -n");
+        return EXIT_FAILURE
+    }
+    if dump_frames.is_some() && (replay.is_none() || project_path.is_some()) {
+        eprintln!("--dump-frames needs a replay to play with -f, and can't be used with -n");
+        return EXIT_FAILURE
+    }
+    if dump_frames.is_some() && headless {
+        eprintln!("--dump-frames can't be used with --headless, since nothing is drawn");
+        return EXIT_FAILURE
+    }
+    if audio_device.is_some() && headless {
+        eprintln!("--audio-device can't be used with --headless, since nothing is heard");
+        return EXIT_FAILURE
+    }
+    if frame_times && headless {
+        eprintln!("--frame-times can't be used with --headless, since nothing is drawn");
+        return EXIT_FAILURE
+    }
+
     let input = {
-        if matches.free.len() == 1 {
-            &matches.free[0]
+        if let (Some(_), Some(exe)) = (&bundle, &own_exe) {
+            exe.clone().into_os_string()
+        } else if matches.free.len() == 1 {
+            restore_arg(&matches.free[0])
         } else if matches.free.len() > 1 {
             eprintln!("unexpected second input {}", matches.free[1]);
             return EXIT_FAILURE
@@ -164,24 +440,67 @@ fn xmain() -> i32 {
         }
     };
 
-    let mut game_args = matches.opt_strs("game-arg");
-    game_args.insert(0, input.to_string());
-    let game_args = game_args;
+    // a bundled game's command line has already been split up by the OS, so it's passed on as it is
+    let game_args = match &bundle {
+        Some(bundle) => bundle.config.game_args.iter().chain(&args[1..]).cloned().collect::<Vec<_>>(),
+        None => matches.opt_strs("game-arg").iter().flat_map(|arg| cmdline::split_os_str(&restore_arg(arg))).collect(),
+    };
 
     let file_path = Path::new(&input);
 
-    let mut file = match fs::read(file_path) {
+    let file = match bundle {
+        Some(bundle) => Ok(bundle.game),
+        None => fs::read(file_path),
+    };
+    let mut file = match file {
         Ok(data) => data,
         Err(err) => {
-            eprintln!("failed to open '{}': {}", input, err);
+            eprintln!("failed to open '{}': {}", file_path.display(), err);
             return EXIT_FAILURE
         },
     };
 
+    if let Some(output) = bundle_output {
+        let mut options = Vec::new();
+        let flags = [
+            ("-s", strict),
+            ("-t", !multithread),
+            ("-r", !spoof_time),
+            ("-l", !frame_limiter),
+            ("--headless", headless),
+        ];
+        for (opt, present) in flags.iter() {
+            if *present {
+                options.push(opt.to_string());
+            }
+        }
+        if let Some(label) = &encoding_label {
+            options.push("--encoding".into());
+            options.push(label.clone());
+        }
+        let config = bundle::Config { options, game_args, intro: replay.map(bundle::Intro) };
+        return match write_bundle(&output, &file, &config) {
+            Ok(()) => {
+                println!("wrote bundle to '{}'", output.display());
+                EXIT_SUCCESS
+            },
+            Err(err) => {
+                eprintln!("failed to write bundle '{}': {}", output.display(), err);
+                EXIT_FAILURE
+            },
+        }
+    }
+
     if verbose {
-        println!("loading '{}'...", input);
+        println!("loading '{}'...", file_path.display());
     }
 
+    // from_exe decrypts the game in place, so the manifest has to be read first
+    let dpi_aware = gm8exe::pe::Headers::parse(&file)
+        .ok()
+        .and_then(|pe| pe.manifest(&file).map(gm8exe::pe::dpi_aware))
+        .unwrap_or(false);
+
     #[rustfmt::skip]
     let assets = gm8exe::reader::from_exe(
         &mut file,                              // mut exe: AsRef<[u8]>
@@ -190,17 +509,33 @@ fn xmain() -> i32 {
         } else {
             None
         },
-        strict,                                 // strict: bool
-        multithread,                            // multithread: bool
+        gm8exe::reader::ReaderOptions {         // options: ReaderOptions
+            strict,
+            multithread,
+            keep_blobs: false,
+        },
     );
     let assets = match assets {
         Ok(assets) => assets,
         Err(err) => {
-            eprintln!("failed to load '{}' - {}", input, err);
+            eprintln!("failed to load '{}' - {}", file_path.display(), err);
             return EXIT_FAILURE
         },
     };
 
+    if coverage {
+        let gm81 = matches!(assets.version, gm8exe::GameVersion::GameMaker8_1);
+        let report = coverage::scan(&assets, |name| coverage::kernel_support(name, gm81));
+        print!("{}", report.to_table());
+        if let Some(path) = coverage_json {
+            if let Err(e) = fs::write(&path, report.to_json()) {
+                eprintln!("failed to write '{}' - {}", path.display(), e);
+                return EXIT_FAILURE
+            }
+        }
+        return EXIT_SUCCESS
+    }
+
     let absolute_path = match file_path.canonicalize() {
         Ok(p) => p,
         Err(e) => {
@@ -209,7 +544,27 @@ fn xmain() -> i32 {
         },
     };
 
-    let encoding = encoding_rs::SHIFT_JIS; // TODO: argument
+    // the exe and every project are left alone, even if they're in the captured save directory
+    let mut excluded_files = vec![absolute_path.clone()];
+    if let Some(dir) = &capture_save_dir {
+        captured_dirs.push(absolute_path.parent().unwrap_or(&absolute_path).join(dir));
+        excluded_files.extend(project_path.as_ref().and_then(|x| x.parent()).map(Path::to_path_buf));
+    }
+
+    // GM8.1 games are always UTF-8, so there's nothing to guess
+    let gm81 = matches!(assets.version, gm8exe::GameVersion::GameMaker8_1);
+    let encoding = encoding.unwrap_or_else(|| match encoding::guess(&assets).filter(|_| !gm81) {
+        Some(encoding) => {
+            println!("guessed the game's text encoding: {}", encoding.name());
+            encoding
+        },
+        None => {
+            if !gm81 {
+                println!("couldn't guess the game's text encoding, using {}", encoding::FALLBACK.name());
+            }
+            encoding::FALLBACK
+        },
+    });
 
     let play_type = if project_path.is_some() {
         PlayType::Record
@@ -219,50 +574,167 @@ fn xmain() -> i32 {
         PlayType::Normal
     };
 
-    let mut components =
-        match Game::launch(assets, absolute_path, game_args, temp_dir, encoding, frame_limiter, play_type) {
-            Ok(g) => g,
+    let mut components = match Game::launch(
+        assets,
+        absolute_path,
+        game_args,
+        temp_dir,
+        encoding,
+        frame_limiter,
+        play_type,
+        headless,
+        !dpi_aware,
+    ) {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("Failed to launch game: {}", e);
+            return EXIT_LAUNCH_FAILURE
+        },
+    };
+    if verbose {
+        let shared = &components.assets.shared_textures;
+        println!(
+            "{} sprite frames were identical to another and share its texture, saving {} KB",
+            shared.frames_saved,
+            shared.bytes_saved / 1024,
+        );
+        // This is easy to miss when comparing against real GM8, since it silently changes every room's setup
+        println!(
+            "room instances run {}",
+            if components.swap_creation_events {
+                "their create event, then their creation code (swap_creation_events)"
+            } else {
+                "their creation code, then their create event"
+            }
+        );
+    }
+    components.audio.set_memory_budget(audio_memory);
+    if let Some(name) = audio_device {
+        let devices = components.audio.devices();
+        if devices.contains(&name) {
+            components.audio.set_device(Some(name), 0);
+        } else {
+            eprintln!("warning: no audio output device called '{}', using the default one", name);
+            eprintln!("the output devices are: {}", devices.join(", "));
+        }
+    }
+    components.game_log = game::gamelog::GameLog::new(game_log_size);
+    if let Some(path) = &game_log {
+        if let Err(e) = components.game_log.tee_to(path) {
+            eprintln!("failed to create game log '{}': {}", path.display(), e);
+            return EXIT_FAILURE
+        }
+    }
+    components.debug_mode = debug_mode;
+    if frame_times {
+        components.frame_time_graph = Some(Default::default());
+    }
+    if no_cull {
+        components.renderer.set_offscreen_culling(false);
+    }
+    if let Some((dir, interval)) = dump_frames {
+        match game::framedump::FrameDumper::new(dir, interval) {
+            Ok(dumper) => {
+                println!("dumping every {} frames to '{}'", interval, dumper.dir().display());
+                components.frame_dumper = Some(dumper);
+            },
             Err(e) => {
-                eprintln!("Failed to launch game: {}", e);
+                eprintln!("failed to set up --dump-frames: {}", e);
                 return EXIT_FAILURE
             },
-        };
+        }
+    }
 
     let time_now = gml::datetime::now_as_nanos();
 
     if let Err(err) = if let Some(path) = project_path {
         components.spoofed_time_nanos = Some(time_now);
-        components.record(path);
-        Ok(())
+        if capture_files {
+            match game::filecapture::FileStore::new(&path, captured_dirs, excluded_files) {
+                Ok(store) => components.file_store = Some(store),
+                Err(e) => eprintln!("warning: couldn't set up file capture: {}", e),
+            }
+        }
+        components.record(path, autosave_config);
+        Ok(ExitReason::WindowClosed)
     } else {
-        // cache temp_dir and included files because the other functions take ownership
-        let temp_dir: Option<PathBuf> = if can_clear_temp_dir {
-            Some(components.decode_str(components.temp_directory.as_ref()).into_owned().into())
-        } else {
-            None
+        // cache temp_dir and included files because the other functions take ownership,
+        // and delete them when this goes out of scope, even if the game panics
+        let _cleanup = game::tempdir::Cleanup {
+            dir: if can_clear_temp_dir {
+                Some(components.decode_str(components.temp_directory.as_ref()).into_owned().into())
+            } else {
+                None
+            },
+            files: components
+                .included_files
+                .iter()
+                .filter(|i| i.remove_at_end)
+                .map(|i| PathBuf::from(components.decode_str(i.name.as_ref()).into_owned()))
+                .collect(),
         };
-        let files_to_delete = components
-            .included_files
-            .iter()
-            .filter(|i| i.remove_at_end)
-            .map(|i| PathBuf::from(components.decode_str(i.name.as_ref()).into_owned()))
-            .collect::<Vec<_>>();
-        let result = if let Some(replay) = replay {
+        if rng_log.is_some() || rng_compare.is_some() {
+            components.rand.start_log();
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(address) = metrics_address {
+            match components.start_metrics(address, &metrics_watches) {
+                Ok(address) => println!("serving metrics on http://{}/", address),
+                Err(e) => {
+                    eprintln!("failed to start the metrics server: {}", e);
+                    return EXIT_FAILURE
+                },
+            }
+        }
+        let result = if let Some(replay) = replay.or(intro) {
             components.replay(replay, output_bin)
         } else {
             components.spoofed_time_nanos = if spoof_time { Some(time_now) } else { None };
             components.run()
         };
-        for file in files_to_delete.into_iter() {
-            std::fs::remove_file(file).ok();
+        if let Some(log) = components.rand.take_log().map(|x| x.to_text()) {
+            if let Some(path) = &rng_log {
+                match fs::write(path, &log) {
+                    Ok(()) => println!("wrote RNG log to '{}'", path.display()),
+                    Err(e) => eprintln!("failed to write RNG log '{}': {}", path.display(), e),
+                }
+            }
+            if let Some(expected) = &rng_compare {
+                match gml::rand::first_difference(expected, &log) {
+                    Some(difference) => eprintln!("RNG diverged from the log at {}", difference),
+                    None => println!("RNG matched the log"),
+                }
+            }
         }
-        if let Some(temp_dir) = temp_dir {
-            std::fs::remove_dir_all(temp_dir).ok();
+        if let Some(dir) = &dump_atlas {
+            match components.dump_atlas(dir) {
+                Ok(count) => println!("wrote {} texture pages to '{}'", count, dir.display()),
+                Err(e) => eprintln!("failed to dump texture pages: {}", e),
+            }
+        }
+        if verbose {
+            let memory = components.audio_memory();
+            println!(
+                "audio memory: {} KB preloaded, {} KB of files without preload, {} KB decoded from them ({} sounds)",
+                memory.preloaded / 1024,
+                memory.compressed / 1024,
+                memory.decoded / 1024,
+                memory.decoded_count,
+            );
+        }
+        if count_unimplemented {
+            let calls = gml::unimplemented::ranking();
+            if calls.is_empty() {
+                println!("no unimplemented functions were called");
+            }
+            for (name, count) in calls {
+                println!("{:>8} {}", count, name);
+            }
         }
         result
     } {
         println!("Runtime error: {}", err);
-        EXIT_FAILURE
+        EXIT_RUNTIME_ERROR
     } else {
         EXIT_SUCCESS
     }
@@ -334,6 +334,106 @@ impl Real {
         Self(self.0.rem_euclid(other.0))
     }
 
+    /// Formats with a fixed number of decimal places like Delphi's Format does, which is what GM8 uses.
+    /// Only the first 18 significant digits are used, rounding is half away from zero, and the decimal separator
+    /// is always '.' regardless of the system locale. Numbers too big for that fall back to Delphi's general format.
+    pub fn to_fixed(self, decimals: usize) -> String {
+        const PRECISION: usize = 18;
+        if self.0.is_nan() {
+            return "NAN".into()
+        } else if self.0.is_infinite() {
+            return if self.0 > 0.0 { "INF".into() } else { "-INF".into() }
+        }
+
+        // Get the significant digits, and how many of them go before the decimal point
+        let scientific = format!("{:.*e}", PRECISION - 1, self.0.abs());
+        let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+        let mut digits = mantissa.bytes().filter(u8::is_ascii_digit).map(|c| c - b'0').collect::<Vec<_>>();
+        let mut int_digits = exponent[1..].parse::<i32>().unwrap() + 1;
+        let sign = if self.0 < 0.0 { "-" } else { "" };
+
+        if int_digits > PRECISION as i32 {
+            while digits.last() == Some(&0) {
+                digits.pop();
+            }
+            let mut text = format!("{}{}", sign, digits[0]);
+            if digits.len() > 1 {
+                text.push('.');
+                text.extend(digits[1..].iter().map(|d| char::from(b'0' + d)));
+            }
+            return format!("{}E{}", text, int_digits - 1)
+        }
+
+        let keep = int_digits + decimals as i32;
+        if keep < PRECISION as i32 {
+            let round_up = keep >= 0 && digits[keep as usize] >= 5;
+            digits.truncate(keep.max(0) as usize);
+            if round_up {
+                match digits.iter().rposition(|&d| d != 9) {
+                    Some(i) => {
+                        digits[i] += 1;
+                        digits[i + 1..].iter_mut().for_each(|d| *d = 0);
+                    },
+                    None => {
+                        digits.iter_mut().for_each(|d| *d = 0);
+                        digits.insert(0, 1);
+                        int_digits += 1;
+                    },
+                }
+            }
+        }
+
+        let digit = |i: i32| char::from(b'0' + if i >= 0 { digits.get(i as usize).copied().unwrap_or(0) } else { 0 });
+        let mut text = String::from(sign);
+        if int_digits > 0 {
+            text.extend((0..int_digits).map(digit));
+        } else {
+            text.push('0');
+        }
+        if decimals > 0 {
+            text.push('.');
+            text.extend((int_digits..int_digits + decimals as i32).map(digit));
+        }
+        text
+    }
+
+    /// Parses a number like Delphi's StrToFloat, which is what GM8 uses. The decimal separator is always '.',
+    /// and unlike Rust's parser, words like "inf" or "nan" aren't accepted.
+    pub fn parse(text: &str) -> Option<Self> {
+        let bytes = text.as_bytes();
+        let mut pos = 0;
+        let skip_digits = |pos: &mut usize| {
+            let start = *pos;
+            while bytes.get(*pos).filter(|c| c.is_ascii_digit()).is_some() {
+                *pos += 1;
+            }
+            *pos - start
+        };
+
+        if matches!(bytes.get(pos), Some(b'+' | b'-')) {
+            pos += 1;
+        }
+        let mut mantissa_digits = skip_digits(&mut pos);
+        if bytes.get(pos) == Some(&b'.') {
+            pos += 1;
+            mantissa_digits += skip_digits(&mut pos);
+        }
+        if mantissa_digits == 0 {
+            return None
+        }
+        if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+            pos += 1;
+            if matches!(bytes.get(pos), Some(b'+' | b'-')) {
+                pos += 1;
+            }
+            if skip_digits(&mut pos) == 0 {
+                return None
+            }
+        }
+
+        if pos == bytes.len() { text.parse::<f64>().ok().filter(|x| x.is_finite()).map(Self) } else { None }
+    }
+
     #[inline]
     pub fn cmp_nan_first(&self, other: &Self) -> Ordering {
         if self.0.is_nan() {
@@ -448,6 +548,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn to_fixed() {
+        assert_eq!(Real(3.0).to_fixed(0), "3");
+        assert_eq!(Real(3.14159).to_fixed(2), "3.14");
+        assert_eq!(Real(-3.14159).to_fixed(3), "-3.142");
+        // half away from zero, not to even like round()
+        assert_eq!(Real(0.5).to_fixed(0), "1");
+        assert_eq!(Real(2.5).to_fixed(0), "3");
+        assert_eq!(Real(-2.5).to_fixed(0), "-3");
+        assert_eq!(Real(0.125).to_fixed(2), "0.13");
+        assert_eq!(Real(0.05).to_fixed(1), "0.1");
+        assert_eq!(Real(9.999).to_fixed(2), "10.00");
+        assert_eq!(Real(0.004).to_fixed(2), "0.00");
+        assert_eq!(Real(-0.0).to_fixed(0), "0");
+        // 2.675 is really 2.67499999999999982236431605997495353221893310546875
+        assert_eq!(Real(2.675).to_fixed(2), "2.67");
+        // digits past the 18th are zero, not the exact binary expansion
+        assert_eq!(Real(123.1).to_fixed(18), "123.099999999999994000");
+        assert_eq!(Real(0.1).to_fixed(18), "0.100000000000000006");
+        assert_eq!(Real(123456789012345678.0).to_fixed(0), "123456789012345680");
+        assert_eq!(Real(1e20).to_fixed(2), "1E20");
+        assert_eq!(Real(-(2f64.powi(70))).to_fixed(0), "-1.1805916207174113E21");
+        assert_eq!(Real(f64::INFINITY).to_fixed(2), "INF");
+        assert_eq!(Real(f64::NEG_INFINITY).to_fixed(2), "-INF");
+        assert_eq!(Real(f64::NAN).to_fixed(2), "NAN");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Real::parse("3"), Some(Real(3.0)));
+        assert_eq!(Real::parse("-3.25"), Some(Real(-3.25)));
+        assert_eq!(Real::parse("+.5"), Some(Real(0.5)));
+        assert_eq!(Real::parse("5."), Some(Real(5.0)));
+        assert_eq!(Real::parse("1.5e3"), Some(Real(1500.0)));
+        assert_eq!(Real::parse("1E-2"), Some(Real(0.01)));
+        // the decimal separator is always a dot
+        assert_eq!(Real::parse("1,5"), None);
+        assert_eq!(Real::parse("inf"), None);
+        assert_eq!(Real::parse("NaN"), None);
+        assert_eq!(Real::parse("."), None);
+        assert_eq!(Real::parse("1e"), None);
+        assert_eq!(Real::parse("1e999"), None);
+        assert_eq!(Real::parse(""), None);
+    }
+
     #[test]
     fn sin() {
         assert_eq!(Real(PI / 2.0).sin(), Real(1.0));
@@ -1,13 +1,15 @@
 //! Game rendering functionality
 
 pub mod atlas;
+mod headless;
 mod opengl;
 
 use crate::types::Colour;
 use atlas::{AtlasRect, AtlasRef};
+use image::RgbaImage;
 use ramen::window::Window;
 use serde::{Deserialize, Serialize};
-use std::any::Any;
+use std::{any::Any, convert::TryFrom};
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Scaling {
@@ -20,7 +22,53 @@ pub enum Scaling {
     Full,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl Scaling {
+    /// Where a framebuffer of the given size gets drawn in a window of the given size, as (x, y, width, height).
+    /// Anything outside of it is black bars.
+    pub fn region(self, (fb_width, fb_height): (i32, i32), (window_width, window_height): (i32, i32)) -> [i32; 4] {
+        match self {
+            Scaling::Fixed(scale) => {
+                let w = (f64::from(fb_width) * scale) as i32;
+                let h = (f64::from(fb_height) * scale) as i32;
+                [(window_width - w) / 2, (window_height - h) / 2, w, h]
+            },
+            Scaling::Aspect(_) => {
+                if fb_width > 0 && fb_height > 0 {
+                    let fixed_width = window_height * fb_width / fb_height;
+                    if fixed_width < window_width {
+                        // window is too wide
+                        [(window_width - fixed_width) / 2, 0, fixed_width, window_height]
+                    } else {
+                        // window is too tall
+                        let fixed_height = window_width * fb_height / fb_width;
+                        [0, (window_height - fixed_height) / 2, window_width, fixed_height]
+                    }
+                } else {
+                    // can never be too careful
+                    [0, 0, fb_width, fb_height]
+                }
+            },
+            Scaling::Full => [0, 0, window_width, window_height],
+        }
+    }
+
+    /// Converts a point in the window to the framebuffer pixel which is drawn there, undoing `region` exactly.
+    /// Points on the black bars end up outside of the framebuffer.
+    pub fn window_to_screen(self, (x, y): (i32, i32), fb_size: (i32, i32), window_size: (i32, i32)) -> (i32, i32) {
+        let [w_x, w_y, w_w, w_h] = self.region(fb_size, window_size);
+        if w_w <= 0 || w_h <= 0 {
+            return (x, y)
+        }
+        let unscale = |pos: i32, offset: i32, size: i32, fb: i32| {
+            (i64::from(pos - offset) * i64::from(fb)).div_euclid(i64::from(size)) as i32
+        };
+        (unscale(x, w_x, w_w, fb_size.0), unscale(y, w_y, w_h, fb_size.1))
+    }
+}
+
+/// A texture read back from the GPU, for savestates. Its pixels are stored as a PNG when it's serialized.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "StoredTexture")]
 pub struct SavedTexture {
     width: i32,
     height: i32,
@@ -28,6 +76,106 @@ pub struct SavedTexture {
     zbuf: Option<Box<[f32]>>,
 }
 
+/// How a SavedTexture is serialized, with `pixels` as a PNG.
+#[derive(Serialize, Deserialize)]
+struct TextureData<P, Z> {
+    width: i32,
+    height: i32,
+    pixels: P,
+    zbuf: Z,
+}
+
+impl Serialize for SavedTexture {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pixels =
+            encode_png(self.width as u32, self.height as u32, &self.pixels).map_err(serde::ser::Error::custom)?;
+        TextureData { width: self.width, height: self.height, pixels, zbuf: &self.zbuf }.serialize(serializer)
+    }
+}
+
+type StoredTexture = TextureData<Box<[u8]>, Option<Box<[f32]>>>;
+
+impl TryFrom<StoredTexture> for SavedTexture {
+    type Error = String;
+
+    fn try_from(data: StoredTexture) -> Result<Self, Self::Error> {
+        let TextureData { width, height, pixels, zbuf } = data;
+        let pixels = decode_png(pixels, width as u32, height as u32)?;
+        Ok(Self { width, height, pixels, zbuf })
+    }
+}
+
+// A SavedTexture as savestates stored it before its pixels were stored as a PNG
+#[derive(Deserialize)]
+pub struct SavedTextureV1 {
+    width: i32,
+    height: i32,
+    pixels: Box<[u8]>,
+    zbuf: Option<Box<[f32]>>,
+}
+
+impl From<SavedTextureV1> for SavedTexture {
+    fn from(old: SavedTextureV1) -> Self {
+        let SavedTextureV1 { width, height, pixels, zbuf } = old;
+        Self { width, height, pixels, zbuf }
+    }
+}
+
+impl SavedTexture {
+    pub fn new(width: i32, height: i32, pixels: Box<[u8]>, zbuf: Option<Box<[f32]>>) -> Self {
+        Self { width, height, pixels, zbuf }
+    }
+
+    /// The texture's pixels and depth buffer.
+    pub fn into_parts(self) -> (Box<[u8]>, Option<Box<[f32]>>) {
+        (self.pixels, self.zbuf)
+    }
+}
+
+/// Compresses RGBA pixels into a PNG. Savestates store pixels this way, since what games draw tends to compress well.
+/// This happens every time a savestate is made, and the whole savestate gets compressed again afterwards, so the
+/// fastest compression level is used.
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Result<Box<[u8]>, String> {
+    use image::png::{CompressionType, FilterType, PngEncoder};
+    // a PNG can't be empty, so an empty image is stored as nothing at all
+    if width == 0 || height == 0 {
+        return Ok(Box::new([]))
+    }
+    let mut png = Vec::new();
+    PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::Sub)
+        .encode(rgba, width, height, image::ColorType::Rgba8)
+        .map_err(|e| e.to_string())?;
+    Ok(png.into_boxed_slice())
+}
+
+/// Reverses `encode_png`, checking the image is the expected size.
+fn decode_png(data: Box<[u8]>, width: u32, height: u32) -> Result<Box<[u8]>, String> {
+    if width == 0 || height == 0 {
+        return if data.is_empty() {
+            Ok(data)
+        } else {
+            Err(format!("expected nothing for a {}x{} image", width, height))
+        }
+    }
+    let image = image::load_from_memory_with_format(&data, image::ImageFormat::Png).map_err(|e| e.to_string())?;
+    let image = image.into_rgba8();
+    if image.dimensions() != (width, height) {
+        return Err(format!("expected a {}x{} image, got {}x{}", width, height, image.width(), image.height()))
+    }
+    Ok(image.into_raw().into_boxed_slice())
+}
+
+/// A solid rectangle drawn over the window when presenting, such as a bar of the frame time graph. It's in window
+/// pixels from the bottom left, and never ends up in anything the game can read back.
+#[derive(Clone, Copy, Debug)]
+pub struct OverlayRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    pub colour: Colour,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Fog {
     pub colour: i32,
@@ -173,8 +321,16 @@ pub struct VertexBuffer {
     tris: Vec<Vertex>,
 }
 
-pub struct Renderer(Box<dyn RendererTrait>);
+/// The backend, and how many frames have been presented with it.
+pub struct Renderer(Box<dyn RendererTrait>, u64);
 
+/// A rendering backend.
+///
+/// Textures, surfaces and the framebuffer all hold straight (not premultiplied) alpha, as in GM8's D3D8 renderer.
+/// Pixel data is stored and read back exactly as it's given, and blend factors are passed straight to the backend,
+/// so blend modes set with draw_set_blend_mode_ext() combine colours the same way GM8 does. The downside is that
+/// interpolation picks up the colour of transparent pixels, so if that matters it has to be fixed before uploading,
+/// which is what `asset::sprite::process_image` does for removed backgrounds.
 pub trait RendererTrait {
     fn as_any(&self) -> &dyn Any;
     fn max_texture_size(&self) -> u32;
@@ -199,6 +355,10 @@ pub trait RendererTrait {
     fn get_vsync(&self) -> bool;
     fn wait_vsync(&self);
 
+    /// Sets how many vertical blanks presenting a frame waits for, with 0 meaning vsync is off.
+    fn set_swap_interval(&self, interval: u32);
+    fn get_swap_interval(&self) -> u32;
+
     fn get_rect(&self, id: AtlasRef) -> Option<&AtlasRect>;
 
     fn draw_sprite(&mut self, tex: AtlasRef, x: f64, y: f64, xs: f64, ys: f64, ang: f64, col: i32, alpha: f64) {
@@ -302,6 +462,8 @@ pub trait RendererTrait {
     );
     fn flush_queue(&mut self);
     fn present(&mut self, window_width: u32, window_height: u32, scaling: Scaling);
+    /// Sets the rectangles `present` draws over the window, replacing the previous ones.
+    fn set_overlay(&mut self, rects: Vec<OverlayRect>);
     fn draw_stored(&mut self, target_x: i32, target_y: i32, width: u32, height: u32);
     fn stored_size(&self) -> (u32, u32);
     fn finish(&mut self, window_width: u32, window_height: u32, clear_colour: Colour);
@@ -319,6 +481,8 @@ pub trait RendererTrait {
     fn set_blend_mode(&mut self, src: BlendType, dst: BlendType);
     fn get_pixel_interpolation(&self) -> bool;
     fn set_pixel_interpolation(&mut self, lerping: bool);
+    fn get_offscreen_culling(&self) -> bool;
+    fn set_offscreen_culling(&mut self, cull: bool);
     fn get_texture_repeat(&self) -> bool;
     fn set_texture_repeat(&mut self, repeat: bool);
 
@@ -328,6 +492,8 @@ pub trait RendererTrait {
     fn set_stored(&mut self, rgba: Box<[u8]>, zbuf: Box<[f32]>, fb_w: u32, fb_h: u32);
 
     fn dump_dynamic_textures(&self) -> Vec<Option<SavedTexture>>;
+    // Reads back every texture page, not just dynamic ones, for debugging. Freed pages are None.
+    fn dump_atlas_pages(&self) -> Vec<Option<SavedTexture>>;
     fn upload_dynamic_textures(&mut self, textures: &[Option<SavedTexture>]);
 
     fn create_sprite_colour(&mut self, width: i32, height: i32, col: Colour) -> Result<AtlasRef, String>;
@@ -349,7 +515,10 @@ pub trait RendererTrait {
     fn set_zbuf_trashed(&mut self, trashed: bool);
     fn get_zbuf_trashed(&self) -> bool;
 
+    /// Gets the GML texture id for a texture, as returned by sprite_get_texture() and the like.
+    /// The id stays the same for as long as the texture exists, wherever it ends up in the atlases.
     fn get_texture_id(&mut self, atl_ref: AtlasRef) -> i32;
+    /// Looks up a GML texture id, returning None if the texture it belonged to has been deleted.
     fn get_texture_from_id(&self, id: i32) -> Option<AtlasRef>;
 
     fn get_texture_rects(&self) -> Vec<Option<AtlasRect>>;
@@ -534,11 +703,21 @@ impl Default for RendererOptions {
     }
 }
 
+/// Which renderer to use.
+pub enum Backend<'a> {
+    /// Draws to the given window with OpenGL.
+    OpenGL(&'a Window),
+    /// Keeps textures and surfaces in memory without drawing anything, for when there's no window.
+    Headless,
+}
+
 impl Renderer {
-    pub fn new(backend: (), options: &RendererOptions, window: &Window, clear_colour: Colour) -> Result<Self, String> {
-        Ok(Self(Box::new(match backend {
-            () => opengl::RendererImpl::new(options, window, clear_colour)?,
-        })))
+    pub fn new(backend: Backend, options: &RendererOptions, clear_colour: Colour) -> Result<Self, String> {
+        let backend: Box<dyn RendererTrait> = match backend {
+            Backend::OpenGL(window) => Box::new(opengl::RendererImpl::new(options, window, clear_colour)?),
+            Backend::Headless => Box::new(headless::RendererImpl::new(options, clear_colour)),
+        };
+        Ok(Self(backend, 0))
     }
 
     pub fn max_texture_size(&self) -> u32 {
@@ -580,6 +759,14 @@ impl Renderer {
         self.0.wait_vsync()
     }
 
+    pub fn set_swap_interval(&self, interval: u32) {
+        self.0.set_swap_interval(interval)
+    }
+
+    pub fn get_swap_interval(&self) -> u32 {
+        self.0.get_swap_interval()
+    }
+
     pub fn draw_sprite(
         &mut self,
         texture: AtlasRef,
@@ -883,6 +1070,18 @@ impl Renderer {
         self.0.upload_dynamic_textures(textures)
     }
 
+    pub fn dump_atlas_pages(&self) -> Vec<Option<RgbaImage>> {
+        self.0
+            .dump_atlas_pages()
+            .into_iter()
+            .map(|t| t.and_then(|t| RgbaImage::from_raw(t.width as _, t.height as _, t.pixels.into())))
+            .collect()
+    }
+
+    pub fn get_rect(&self, atlas_ref: AtlasRef) -> Option<&AtlasRect> {
+        self.0.get_rect(atlas_ref)
+    }
+
     pub fn create_sprite_colour(&mut self, width: i32, height: i32, col: Colour) -> Result<AtlasRef, String> {
         self.0.create_sprite_colour(width, height, col)
     }
@@ -961,6 +1160,16 @@ impl Renderer {
         self.0.set_pixel_interpolation(lerping)
     }
 
+    /// Whether sprites which land entirely outside the current view are left out of the vertex batch.
+    /// This only affects what gets sent to the GPU - draw events and everything else still run as normal.
+    pub fn get_offscreen_culling(&self) -> bool {
+        self.0.get_offscreen_culling()
+    }
+
+    pub fn set_offscreen_culling(&mut self, cull: bool) {
+        self.0.set_offscreen_culling(cull)
+    }
+
     pub fn get_texture_repeat(&self) -> bool {
         self.0.get_texture_repeat()
     }
@@ -1082,9 +1291,19 @@ impl Renderer {
     }
 
     pub fn present(&mut self, window_width: u32, window_height: u32, scaling: Scaling) {
+        self.1 += 1;
         self.0.present(window_width, window_height, scaling)
     }
 
+    /// How many frames have been presented so far, not counting the ones `finish` shows.
+    pub fn present_count(&self) -> u64 {
+        self.1
+    }
+
+    pub fn set_overlay(&mut self, rects: Vec<OverlayRect>) {
+        self.0.set_overlay(rects)
+    }
+
     pub fn draw_stored(&mut self, target_x: i32, target_y: i32, width: u32, height: u32) {
         self.0.draw_stored(target_x, target_y, width, height)
     }
@@ -1199,3 +1418,102 @@ fn mat4mult(m1: [f32; 16], m2: [f32; 16]) -> [f32; 16] {
         (m1[12] * m2[3]) + (m1[13] * m2[7]) + (m1[14] * m2[11]) + (m1[15] * m2[15]),
     ]
 }
+
+/// Splits a BGR colour into RGBA floats between 0 and 1, as they're given to the GPU.
+fn split_colour(rgb: i32, alpha: f64) -> [f32; 4] {
+    [
+        ((rgb & 0xFF) as f32) / 255.0,
+        (((rgb >> 8) & 0xFF) as f32) / 255.0,
+        (((rgb >> 16) & 0xFF) as f32) / 255.0,
+        alpha.max(0.0).min(1.0) as f32,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{atlas::AtlasRect, decode_png, encode_png, PrimitiveBuilder, PrimitiveType, SavedTexture, Scaling};
+
+    #[test]
+    fn window_to_screen_full() {
+        // an odd window size, so some framebuffer pixels are drawn wider than others
+        let (fb, window) = ((320, 240), (641, 481));
+        let mut last = -1;
+        for x in 0..window.0 {
+            let (screen_x, _) = Scaling::Full.window_to_screen((x, 0), fb, window);
+            assert!(screen_x == last || screen_x == last + 1);
+            last = screen_x;
+        }
+        assert_eq!(last, fb.0 - 1);
+        assert_eq!(Scaling::Full.window_to_screen((640, 480), fb, window), (319, 239));
+    }
+
+    #[test]
+    fn window_to_screen_black_bars() {
+        // drawn 800 pixels wide with 100 pixel bars either side
+        let (fb, window) = ((400, 300), (1000, 600));
+        let aspect = Scaling::Aspect(-1.0);
+        assert_eq!(aspect.region(fb, window), [100, 0, 800, 600]);
+        assert_eq!(aspect.window_to_screen((100, 0), fb, window), (0, 0));
+        assert_eq!(aspect.window_to_screen((99, 1), fb, window), (-1, 0));
+        assert_eq!(aspect.window_to_screen((899, 599), fb, window), (399, 299));
+        assert_eq!(aspect.window_to_screen((900, 300), fb, window), (400, 150));
+
+        // fixed scaling is centred in a window that's too big for it
+        let fixed = Scaling::Fixed(2.0);
+        assert_eq!(fixed.window_to_screen((100, 0), fb, window), (0, 0));
+        assert_eq!(fixed.window_to_screen((101, 1), fb, window), (0, 0));
+        assert_eq!(fixed.window_to_screen((102, 2), fb, window), (1, 1));
+        assert_eq!(Scaling::Fixed(1.0).window_to_screen((5, 5), fb, fb), (5, 5));
+    }
+
+    #[test]
+    fn primitive_texture_coords() {
+        // a sprite texture somewhere in the middle of texture page 2
+        let rect = AtlasRect { atlas_id: 2, x: 16, y: 32, w: 8, h: 4, origin_x: 0.0, origin_y: 0.0 };
+        let mut prim = PrimitiveBuilder::new(rect, PrimitiveType::TriStrip);
+        for &(x, y) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            prim.push_vertex([x * 100.0, y * 100.0, 0.0], [x, y], [1.0; 4], [0.0; 3]);
+        }
+        assert_eq!(prim.get_atlas_id(), 2);
+        assert_eq!(prim.get_vertices().len(), 6);
+
+        // the shader maps texture coordinates from 0 to 1 onto the sprite's region of the page, not the whole page
+        let mut corners = prim
+            .get_vertices()
+            .iter()
+            .map(|v| {
+                let [x, y, w, h] = v.atlas_xywh;
+                (x + v.tex_coord[0] * w, y + v.tex_coord[1] * h)
+            })
+            .collect::<Vec<_>>();
+        corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        corners.dedup();
+        assert_eq!(corners, [(16.0, 32.0), (16.0, 36.0), (24.0, 32.0), (24.0, 36.0)]);
+    }
+
+    #[test]
+    fn saved_texture_png() {
+        // a sprite-like texture: a filled square on a transparent background
+        let (width, height) = (64, 48);
+        let pixels = (0..width * height)
+            .flat_map(|i| if i % width < 32 && i / width < 32 { [255, 128, 0, 255] } else { [0, 0, 0, 0] })
+            .collect::<Box<[u8]>>();
+        let zbuf = Some(vec![0.5; (width * height) as usize].into_boxed_slice());
+        let texture = SavedTexture { width, height, pixels: pixels.clone(), zbuf: zbuf.clone() };
+        let serialized = bincode::serialize(&texture).unwrap();
+        let raw = bincode::serialize(&(width, height, &pixels, &zbuf)).unwrap();
+        assert!(serialized.len() < raw.len() - pixels.len() / 2);
+        let read: SavedTexture = bincode::deserialize(&serialized).unwrap();
+        assert_eq!((read.width, read.height, &read.pixels, &read.zbuf), (width, height, &pixels, &zbuf));
+
+        // raw pixels aren't a PNG, and a texture which is the wrong size can't be saved
+        assert!(bincode::deserialize::<SavedTexture>(&raw).is_err());
+        let wrong_size = SavedTexture { width: 1, height: 1, pixels: vec![0; 3].into(), zbuf: None };
+        assert!(bincode::serialize(&wrong_size).is_err());
+
+        let png = encode_png(2, 1, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(decode_png(png.clone(), 2, 1).unwrap()[..], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(decode_png(png, 1, 2).is_err());
+        assert_eq!(decode_png(encode_png(0, 5, &[]).unwrap(), 0, 5).unwrap().len(), 0);
+    }
+}
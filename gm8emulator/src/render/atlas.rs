@@ -1,5 +1,9 @@
 use rect_packer::DensePacker;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
 
 #[inline]
 fn next_pow2(n: i32) -> i32 {
@@ -10,11 +14,42 @@ pub struct AtlasBuilder {
     max_size: i32,
     packers: Vec<DensePacker>,
     textures: Vec<(AtlasRect, Box<[u8]>)>,
+    // textures added with texture_shared, by a hash of their size, origin and pixels
+    shareable: HashMap<u64, Vec<AtlasRef>>,
 }
 
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub struct AtlasRef(pub i32);
 
+/// Keeps track of textures which were packed once for several identical sprite frames,
+/// so that deleting or replacing one of the frames leaves the texture alone for the rest.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SharedTextures {
+    // how many frames are using each shared texture, only while it's more than one
+    owners: HashMap<i32, u32>,
+
+    /// How many frames were given another frame's texture, and how many bytes of texture space that saved.
+    pub frames_saved: usize,
+    pub bytes_saved: usize,
+}
+
+impl SharedTextures {
+    /// Gives up one frame's use of a texture. Returns true if nothing else is using it, so it can be deleted.
+    pub fn release(&mut self, atlas_ref: AtlasRef) -> bool {
+        match self.owners.get_mut(&atlas_ref.0) {
+            Some(count) if *count > 2 => {
+                *count -= 1;
+                false
+            },
+            Some(_) => {
+                self.owners.remove(&atlas_ref.0);
+                false
+            },
+            None => true,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 #[repr(C)]
 pub struct AtlasRect {
@@ -30,10 +65,63 @@ pub struct AtlasRect {
     pub(super) origin_y: f32,
 }
 
+impl AtlasRect {
+    /// The texture page this is on.
+    pub fn page(&self) -> u32 {
+        self.atlas_id
+    }
+
+    /// Where this is on its texture page, as (x, y, width, height) in pixels.
+    pub fn bounds(&self) -> (i32, i32, i32, i32) {
+        (self.x, self.y, self.w, self.h)
+    }
+}
+
 impl AtlasBuilder {
     pub fn new(max_size: i32) -> Self {
         assert_eq!(max_size, next_pow2(max_size));
-        AtlasBuilder { max_size, packers: Vec::new(), textures: Vec::new() }
+        AtlasBuilder { max_size, packers: Vec::new(), textures: Vec::new(), shareable: HashMap::new() }
+    }
+
+    /// Like `texture`, but if an identical texture has already been added this way, that one is returned
+    /// instead of packing another copy, and `shared` is told it has another owner.
+    pub fn texture_shared(
+        &mut self,
+        width: i32,
+        height: i32,
+        origin_x: i32,
+        origin_y: i32,
+        data: Box<[u8]>,
+        shared: &mut SharedTextures,
+    ) -> Option<AtlasRef> {
+        if width <= 0 || height <= 0 {
+            return Some(AtlasRef(-1))
+        }
+        let mut hasher = DefaultHasher::new();
+        (width, height, origin_x, origin_y, &data).hash(&mut hasher);
+        let hash = hasher.finish();
+        let (origin_x_f, origin_y_f) = (origin_x as f32 / width as f32, origin_y as f32 / height as f32);
+        let textures = &self.textures;
+        let existing = self.shareable.get(&hash).and_then(|refs| {
+            refs.iter().copied().find(|r| {
+                let (rect, pixels) = &textures[r.0 as usize];
+                (rect.w, rect.h, rect.origin_x, rect.origin_y) == (width, height, origin_x_f, origin_y_f)
+                    && *pixels == data
+            })
+        });
+        match existing {
+            Some(atlas_ref) => {
+                *shared.owners.entry(atlas_ref.0).or_insert(1) += 1;
+                shared.frames_saved += 1;
+                shared.bytes_saved += data.len();
+                Some(atlas_ref)
+            },
+            None => {
+                let atlas_ref = self.texture(width, height, origin_x, origin_y, data)?;
+                self.shareable.entry(hash).or_default().push(atlas_ref);
+                Some(atlas_ref)
+            },
+        }
     }
 
     pub fn texture(
@@ -110,3 +198,50 @@ impl AtlasBuilder {
         (self.packers, self.textures)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_bounds() {
+        let mut builder = AtlasBuilder::new(1024);
+        let a = builder.texture(30, 20, 0, 0, vec![0; 30 * 20 * 4].into()).unwrap();
+        let b = builder.texture(17, 9, 4, 4, vec![0; 17 * 9 * 4].into()).unwrap();
+        assert_eq!(builder.texture(0, 5, 0, 0, Box::new([])).unwrap().0, -1);
+        let (_, textures) = builder.into_inner();
+        let (rect_a, rect_b) = (&textures[a.0 as usize].0, &textures[b.0 as usize].0);
+        assert_eq!((rect_a.page(), rect_b.page()), (0, 0));
+        let ((_, _, w_a, h_a), (_, _, w_b, h_b)) = (rect_a.bounds(), rect_b.bounds());
+        assert_eq!((w_a, h_a, w_b, h_b), (30, 20, 17, 9));
+    }
+
+    #[test]
+    fn shared_frames() {
+        let mut builder = AtlasBuilder::new(1024);
+        let mut shared = SharedTextures::default();
+        let frame = |colour: u8| vec![colour; 8 * 8 * 4].into_boxed_slice();
+        // two sprites, one with an idle animation padded out with copies of its first frame
+        let idle = [frame(1), frame(2), frame(1), frame(1)]
+            .iter()
+            .map(|f| builder.texture_shared(8, 8, 4, 4, f.clone(), &mut shared).unwrap())
+            .collect::<Vec<_>>();
+        let menu = builder.texture_shared(8, 8, 4, 4, frame(1), &mut shared).unwrap();
+        // a different origin would draw differently, so it can't share
+        let offset = builder.texture_shared(8, 8, 0, 0, frame(1), &mut shared).unwrap();
+
+        // only what's in the builder gets uploaded
+        assert_eq!(builder.textures.len(), 3);
+        assert!(idle[2..].iter().chain(Some(&menu)).all(|r| r.0 == idle[0].0));
+        assert_ne!(idle[1].0, idle[0].0);
+        assert_ne!(offset.0, idle[0].0);
+        assert_eq!((shared.frames_saved, shared.bytes_saved), (3, 3 * 8 * 8 * 4));
+
+        // replacing the idle sprite lets go of its frames, but the texture has to stay for the menu sprite
+        for atlas_ref in &idle {
+            assert_eq!(shared.release(*atlas_ref), atlas_ref.0 == idle[1].0);
+        }
+        assert!(shared.release(menu));
+        assert!(shared.release(offset));
+    }
+}
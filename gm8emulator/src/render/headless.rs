@@ -0,0 +1,1017 @@
+//! A renderer which doesn't draw anything, for playing replays on machines with no display or graphics driver.
+//!
+//! Textures, surfaces and the framebuffer are all kept in memory, so everything the game can find out about them
+//! still works: their sizes and ids, pixels which were uploaded, cleared or copied between surfaces, and saving
+//! them in savestates. Drawing only updates whatever state it would have set, and never changes any pixels, so
+//! the GML functions which read pixels back from the screen or a surface fail rather than return them.
+//! Pixels are RGBA with the top row first, which is what the OpenGL renderer reads back too.
+
+use crate::{
+    render::{
+        atlas::{AtlasBuilder, AtlasRect, AtlasRef},
+        mat4mult, split_colour, BlendType, Fog, Light, OverlayRect, PrimitiveBuilder, PrimitiveShape, PrimitiveType,
+        RendererOptions, RendererTrait, SavedTexture, Scaling, VertexBuffer,
+    },
+    types::Colour,
+};
+use std::{any::Any, cell::Cell, convert::TryInto};
+
+/// The biggest texture page the atlases get packed into. Real drivers usually allow at least this much.
+const MAX_TEXTURE_SIZE: u32 = 8192;
+
+#[rustfmt::skip]
+const IDENTITY_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// Passes a colour through the same conversion the OpenGL renderer stores it with, so getters return the same.
+fn gpu_colour(colour: i32) -> i32 {
+    let c = split_colour(colour, 1.0);
+    u32::from(Colour::from((f64::from(c[0]), c[1].into(), c[2].into()))) as i32
+}
+
+fn colour_bytes(colour: Colour, alpha: f64) -> [u8; 4] {
+    let byte = |x: f64| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [byte(colour.r), byte(colour.g), byte(colour.b), byte(alpha)]
+}
+
+/// A texture page, surface or framebuffer.
+#[derive(Clone)]
+struct Page {
+    width: i32,
+    height: i32,
+    pixels: Vec<u8>,
+    zbuf: Option<Vec<f32>>,
+}
+
+impl Page {
+    fn new(width: i32, height: i32, has_zbuffer: bool) -> Self {
+        let len = width.max(0) as usize * height.max(0) as usize;
+        Self { width, height, pixels: vec![0; len * 4], zbuf: if has_zbuffer { Some(vec![1.0; len]) } else { None } }
+    }
+
+    fn from_saved(texture: &SavedTexture) -> Self {
+        Self {
+            width: texture.width,
+            height: texture.height,
+            pixels: texture.pixels.to_vec(),
+            zbuf: texture.zbuf.as_ref().map(|x| x.to_vec()),
+        }
+    }
+
+    fn to_saved(&self, with_zbuf: bool) -> SavedTexture {
+        SavedTexture {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.clone().into_boxed_slice(),
+            zbuf: self.zbuf.clone().filter(|_| with_zbuf).map(Vec::into_boxed_slice),
+        }
+    }
+
+    /// Clips an area to the page, returning the offset of what's left and its size, or None if there's nothing left.
+    fn clip(&self, x: i32, y: i32, w: i32, h: i32) -> Option<(i32, i32, usize, usize)> {
+        let (left, top) = (x.max(0), y.max(0));
+        let (right, bottom) = (x.saturating_add(w).min(self.width), y.saturating_add(h).min(self.height));
+        if right > left && bottom > top {
+            Some((left, top, (right - left) as usize, (bottom - top) as usize))
+        } else {
+            None
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    fn fill(&mut self, [x, y, w, h]: [i32; 4], colour: Option<[u8; 4]>, depth: Option<f32>) {
+        if let Some((x, y, w, h)) = self.clip(x, y, w, h) {
+            for row in y..y + h as i32 {
+                let start = self.index(x, row);
+                if let Some(colour) = colour {
+                    for pixel in self.pixels[start * 4..(start + w) * 4].chunks_exact_mut(4) {
+                        pixel.copy_from_slice(&colour);
+                    }
+                }
+                if let (Some(zbuf), Some(depth)) = (self.zbuf.as_mut(), depth) {
+                    zbuf[start..start + w].iter_mut().for_each(|z| *z = depth);
+                }
+            }
+        }
+    }
+
+    /// Reads an area of pixels. Any of it which is off the page comes back as zeroes.
+    fn read(&self, x: i32, y: i32, w: i32, h: i32) -> Box<[u8]> {
+        let (width, height) = (w.max(0) as usize, h.max(0) as usize);
+        let mut data = vec![0; width * height * 4];
+        if let Some((left, top, clip_w, clip_h)) = self.clip(x, y, w, h) {
+            for row in 0..clip_h {
+                let src = self.index(left, top + row as i32) * 4;
+                let dst = ((top - y) as usize + row) * width * 4 + (left - x) as usize * 4;
+                data[dst..dst + clip_w * 4].copy_from_slice(&self.pixels[src..src + clip_w * 4]);
+            }
+        }
+        data.into_boxed_slice()
+    }
+
+    /// Writes an area of pixels which is `w` pixels wide. Any of it which is off the page is left out.
+    fn write(&mut self, x: i32, y: i32, w: i32, data: &[u8]) {
+        let h = if w > 0 { (data.len() / (w as usize * 4)) as i32 } else { 0 };
+        if let Some((left, top, clip_w, clip_h)) = self.clip(x, y, w, h) {
+            for row in 0..clip_h {
+                let src = ((top - y) as usize + row) * w as usize * 4 + (left - x) as usize * 4;
+                let dst = self.index(left, top + row as i32) * 4;
+                self.pixels[dst..dst + clip_w * 4].copy_from_slice(&data[src..src + clip_w * 4]);
+            }
+        }
+    }
+}
+
+pub struct RendererImpl {
+    pages: Vec<Option<Page>>,
+    texture_rects: Vec<Option<AtlasRect>>,
+    stock_texture_count: usize,
+    stock_atlas_count: u32,
+    framebuffer: Page,
+    stored_framebuffer: Option<Page>,
+    // the page a surface being drawn to is on, or None for the framebuffer
+    target: Option<u32>,
+    // the part of the target which clearing affects, like the scissor box in the OpenGL renderer
+    clip: [i32; 4],
+    zbuf_trashed: bool,
+    white_pixel: AtlasRect,
+    swap_interval: Cell<u32>,
+
+    model_matrix: [f32; 16],
+    alpha_blending: bool,
+    blend_mode: (BlendType, BlendType),
+    interpolate_pixels: bool,
+    texture_repeat: bool,
+    depth_test: bool,
+    write_depth: bool,
+    culling: bool,
+    fog: Option<Fog>,
+    gouraud: bool,
+    lighting: bool,
+    ambient_colour: i32,
+    lights: [(bool, Light); 8],
+    circle_precision: i32,
+    using_3d: bool,
+    perspective: bool,
+    depth: f32,
+    cull_offscreen: bool,
+    primitive_2d: PrimitiveBuilder,
+    primitive_3d: PrimitiveBuilder,
+}
+
+impl RendererImpl {
+    pub fn new(options: &RendererOptions, clear_colour: Colour) -> Self {
+        let (width, height) = (options.size.0 as i32, options.size.1 as i32);
+        let mut renderer = Self {
+            pages: Vec::new(),
+            texture_rects: Vec::new(),
+            stock_texture_count: 0,
+            stock_atlas_count: 0,
+            framebuffer: Page::new(width, height, true),
+            stored_framebuffer: None,
+            target: None,
+            clip: [0, 0, width, height],
+            zbuf_trashed: false,
+            white_pixel: Default::default(),
+            swap_interval: Cell::new(if options.vsync { 1 } else { 0 }),
+
+            model_matrix: IDENTITY_MATRIX,
+            alpha_blending: true,
+            blend_mode: (BlendType::SrcAlpha, BlendType::InvSrcAlpha),
+            interpolate_pixels: options.interpolate_pixels,
+            texture_repeat: false,
+            depth_test: false,
+            write_depth: false,
+            culling: false,
+            fog: None,
+            gouraud: true,
+            lighting: false,
+            ambient_colour: 0,
+            lights: [(false, Light::Directional { direction: [0.0; 3], colour: 0 }); 8],
+            circle_precision: 24,
+            using_3d: false,
+            perspective: false,
+            depth: 0.0,
+            cull_offscreen: true,
+            primitive_2d: PrimitiveBuilder::new(Default::default(), PrimitiveType::PointList),
+            primitive_3d: PrimitiveBuilder::new(Default::default(), PrimitiveType::PointList),
+        };
+        renderer.setup_frame(clear_colour);
+        renderer
+    }
+
+    fn setup_frame(&mut self, clear_colour: Colour) {
+        let (width, height) = (self.framebuffer.width, self.framebuffer.height);
+        self.set_view(0, 0, width, height, 0.0, 0, 0, width, height);
+        self.clear_view(clear_colour, 1.0);
+    }
+
+    fn get_rect_mut(&mut self, id: AtlasRef) -> Option<&mut AtlasRect> {
+        id.0.try_into()
+            .ok()
+            .and_then(move |id: usize| self.texture_rects.get_mut(id))
+            .and_then(|o: &mut Option<AtlasRect>| o.as_mut())
+    }
+
+    fn page(&self, atlas_id: u32) -> Option<&Page> {
+        self.pages.get(atlas_id as usize).and_then(Option::as_ref)
+    }
+
+    fn target_page(&mut self) -> Option<&mut Page> {
+        match self.target {
+            Some(atlas_id) => self.pages.get_mut(atlas_id as usize).and_then(Option::as_mut),
+            None => Some(&mut self.framebuffer),
+        }
+    }
+
+    fn clear(&mut self, colour: Option<[u8; 4]>, depth: Option<f32>) {
+        // the framebuffer's z-buffer isn't attached while it's trashed
+        let depth = depth.filter(|_| self.target.is_some() || !self.zbuf_trashed);
+        let clip = self.clip;
+        if let Some(page) = self.target_page() {
+            page.fill(clip, colour, depth);
+        }
+    }
+}
+
+impl RendererTrait for RendererImpl {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn max_texture_size(&self) -> u32 {
+        MAX_TEXTURE_SIZE
+    }
+
+    fn push_atlases(&mut self, mut atl: AtlasBuilder) -> Result<(), String> {
+        assert!(self.pages.is_empty(), "atlases should be initialized only once");
+        let white_pixel_ref =
+            atl.texture(1, 1, 0, 0, Box::new([0xFF, 0xFF, 0xFF, 0xFF])).ok_or("Couldn't pack white_pixel")?;
+        self.reset_primitive_2d(PrimitiveType::PointList, None);
+        self.reset_primitive_3d(PrimitiveType::PointList, None);
+
+        let (packers, sprites) = atl.into_inner();
+        self.white_pixel = sprites[white_pixel_ref.0 as usize].0;
+
+        let mut pages = packers
+            .iter()
+            .map(|packer| {
+                let (width, height) = packer.size();
+                Page::new(width, height, false)
+            })
+            .collect::<Vec<_>>();
+        for (rect, pixels) in &sprites {
+            // atlas pixels are BGRA, like they're stored in the game
+            let mut rgba = pixels.to_vec();
+            rgba.chunks_exact_mut(4).for_each(|x| x.swap(0, 2));
+            pages[rect.atlas_id as usize].write(rect.x, rect.y, rect.w, &rgba);
+        }
+
+        self.stock_atlas_count = pages.len() as u32 + 2; // same as the OpenGL renderer, so savestates match
+        self.pages = pages.into_iter().map(Some).collect();
+        self.texture_rects = sprites.into_iter().map(|(rect, _)| Some(rect)).collect();
+        self.stock_texture_count = self.texture_rects.len();
+        Ok(())
+    }
+
+    fn upload_sprite(
+        &mut self,
+        data: Box<[u8]>,
+        width: i32,
+        height: i32,
+        origin_x: i32,
+        origin_y: i32,
+    ) -> Result<AtlasRef, String> {
+        let atlas_ref = self.create_surface(width, height, false)?;
+        if let Some(rect) = self.get_rect_mut(atlas_ref) {
+            rect.origin_x = origin_x as f32 / width as f32;
+            rect.origin_y = origin_y as f32 / height as f32;
+            let rect = *rect;
+            if let Some(Some(page)) = self.pages.get_mut(rect.atlas_id as usize) {
+                page.write(0, 0, width, &data);
+            }
+        }
+        Ok(atlas_ref)
+    }
+
+    fn duplicate_sprite(&mut self, atlas_ref: AtlasRef) -> Result<AtlasRef, String> {
+        match self.get_rect(atlas_ref).copied() {
+            Some(rect) => {
+                let pixels = self.dump_sprite(atlas_ref);
+                let sprite = self.create_surface(rect.w, rect.h, false)?;
+                let new_rect = self.get_rect_mut(sprite).unwrap();
+                new_rect.origin_x = rect.origin_x;
+                new_rect.origin_y = rect.origin_y;
+                let atlas_id = new_rect.atlas_id;
+                if let Some(Some(page)) = self.pages.get_mut(atlas_id as usize) {
+                    page.write(0, 0, rect.w, &pixels);
+                }
+                Ok(sprite)
+            },
+            None => Ok(AtlasRef(-1)),
+        }
+    }
+
+    fn delete_sprite(&mut self, atlas_ref: AtlasRef) {
+        // this only deletes sprites created with upload_sprite
+        if let Some(rect) = atlas_ref
+            .0
+            .try_into()
+            .ok()
+            .and_then(|id: usize| self.texture_rects.get_mut(id))
+            .and_then(|o: &mut Option<AtlasRect>| o.take())
+        {
+            if rect.atlas_id >= self.stock_atlas_count {
+                self.pages[rect.atlas_id as usize] = None;
+                if self.target == Some(rect.atlas_id) {
+                    self.target = None;
+                }
+            }
+        }
+    }
+
+    fn set_vsync(&self, vsync: bool) {
+        self.swap_interval.set(if vsync { 1 } else { 0 });
+    }
+
+    fn get_vsync(&self) -> bool {
+        self.swap_interval.get() != 0
+    }
+
+    fn wait_vsync(&self) {}
+
+    fn set_swap_interval(&self, interval: u32) {
+        self.swap_interval.set(interval);
+    }
+
+    fn get_swap_interval(&self) -> u32 {
+        self.swap_interval.get()
+    }
+
+    fn create_sprite_colour(&mut self, width: i32, height: i32, col: Colour) -> Result<AtlasRef, String> {
+        let atlas_ref = self.create_surface(width, height, false)?;
+        if let Some(rect) = self.get_rect(atlas_ref).copied() {
+            if let Some(Some(page)) = self.pages.get_mut(rect.atlas_id as usize) {
+                page.fill([0, 0, width, height], Some(colour_bytes(col, 1.0)), None);
+            }
+        }
+        Ok(atlas_ref)
+    }
+
+    fn create_surface(&mut self, width: i32, height: i32, has_zbuffer: bool) -> Result<AtlasRef, String> {
+        let page = Page::new(width, height, has_zbuffer);
+        let atlas_id = match self.pages.iter().position(|x| x.is_none()) {
+            Some(id) => {
+                self.pages[id] = Some(page);
+                id as u32
+            },
+            None => {
+                self.pages.push(Some(page));
+                self.pages.len() as u32 - 1
+            },
+        };
+        let id = self.texture_rects.len() as i32;
+        self.texture_rects.push(Some(AtlasRect {
+            atlas_id,
+            x: 0,
+            y: 0,
+            w: width,
+            h: height,
+            origin_x: 0.0,
+            origin_y: 0.0,
+        }));
+        Ok(AtlasRef(id))
+    }
+
+    fn set_target(&mut self, atlas_ref: AtlasRef) {
+        if let Some(rect) = self.get_rect(atlas_ref).copied().filter(|x| self.page(x.atlas_id).is_some()) {
+            let AtlasRect { x, y, w, h, .. } = rect;
+            self.target = Some(rect.atlas_id);
+            self.clip = [x, y, w, h];
+            self.set_view(x, y, w, h, 0.0, x, y, w, h);
+        }
+    }
+
+    fn reset_target(&mut self) {
+        self.target = None;
+        let (width, height) = (self.framebuffer.width, self.framebuffer.height);
+        self.clip = [0, 0, width, height];
+        self.set_view(0, 0, width, height, 0.0, 0, 0, width, height);
+    }
+
+    fn copy_surface(
+        &mut self,
+        dest: AtlasRef,
+        dest_x: i32,
+        dest_y: i32,
+        src: AtlasRef,
+        src_x: i32,
+        src_y: i32,
+        width: i32,
+        height: i32,
+    ) {
+        let (src_rect, dest_rect) = match (self.get_rect(src), self.get_rect(dest)) {
+            (Some(src), Some(dest)) => (*src, *dest),
+            _ => return,
+        };
+        // only the part which is inside both surfaces gets copied
+        let (left, top) = (src_x.max(0).max(src_x - dest_x), src_y.max(0).max(src_y - dest_y));
+        let right = (src_x + width).min(src_rect.w).min(src_x - dest_x + dest_rect.w);
+        let bottom = (src_y + height).min(src_rect.h).min(src_y - dest_y + dest_rect.h);
+        if right > left && bottom > top {
+            let pixels = match self.page(src_rect.atlas_id) {
+                Some(page) => page.read(src_rect.x + left, src_rect.y + top, right - left, bottom - top),
+                None => return,
+            };
+            if let Some(Some(page)) = self.pages.get_mut(dest_rect.atlas_id as usize) {
+                let (x, y) = (dest_rect.x + dest_x + left - src_x, dest_rect.y + dest_y + top - src_y);
+                page.write(x, y, right - left, &pixels);
+            }
+        }
+    }
+
+    fn set_zbuf_trashed(&mut self, trashed: bool) {
+        self.zbuf_trashed = trashed;
+    }
+
+    fn get_zbuf_trashed(&self) -> bool {
+        self.zbuf_trashed
+    }
+
+    fn resize_framebuffer(&mut self, width: u32, height: u32, store: bool) {
+        let mut framebuffer = Page::new(width as i32, height as i32, true);
+        let old = &self.framebuffer;
+        let (copy_width, copy_height) = (framebuffer.width.min(old.width), framebuffer.height.min(old.height));
+        framebuffer.write(0, 0, copy_width, &old.read(0, 0, copy_width, copy_height));
+        if let (Some(new_zbuf), Some(old_zbuf)) = (framebuffer.zbuf.as_mut(), old.zbuf.as_ref()) {
+            for y in 0..copy_height as usize {
+                let (new_row, old_row) = (y * width as usize, y * old.width as usize);
+                new_zbuf[new_row..new_row + copy_width as usize]
+                    .copy_from_slice(&old_zbuf[old_row..old_row + copy_width as usize]);
+            }
+        }
+        let old = std::mem::replace(&mut self.framebuffer, framebuffer);
+        if store {
+            self.stored_framebuffer = Some(old);
+        }
+    }
+
+    fn get_texture_id(&mut self, atl_ref: AtlasRef) -> i32 {
+        atl_ref.0
+    }
+
+    fn get_texture_from_id(&self, id: i32) -> Option<AtlasRef> {
+        Some(AtlasRef(id)).filter(|&atlas_ref| self.get_rect(atlas_ref).is_some())
+    }
+
+    fn get_texture_rects(&self) -> Vec<Option<AtlasRect>> {
+        self.texture_rects[self.stock_texture_count..].to_vec()
+    }
+
+    fn set_texture_rects(&mut self, rects: &[Option<AtlasRect>]) {
+        self.texture_rects.truncate(self.stock_texture_count);
+        self.texture_rects.extend_from_slice(rects);
+    }
+
+    fn dump_sprite_part(&self, atlas_ref: AtlasRef, part_x: i32, part_y: i32, part_w: i32, part_h: i32) -> Box<[u8]> {
+        match self.get_rect(atlas_ref).and_then(|rect| Some((rect, self.page(rect.atlas_id)?))) {
+            Some((rect, page)) => page.read(rect.x + part_x, rect.y + part_y, part_w, part_h),
+            None => Box::new([]),
+        }
+    }
+
+    fn get_pixels(&self, x: i32, y: i32, w: i32, h: i32) -> Box<[u8]> {
+        self.framebuffer.read(x, y, w, h)
+    }
+
+    fn stored_pixels(&self) -> Box<[u8]> {
+        self.stored_framebuffer.as_ref().unwrap_or(&self.framebuffer).pixels.clone().into_boxed_slice()
+    }
+
+    fn stored_zbuffer(&self) -> Box<[f32]> {
+        let framebuffer = self.stored_framebuffer.as_ref().unwrap_or(&self.framebuffer);
+        framebuffer.zbuf.clone().unwrap_or_default().into_boxed_slice()
+    }
+
+    fn set_stored(&mut self, rgba: Box<[u8]>, zbuf: Box<[f32]>, fb_w: u32, fb_h: u32) {
+        self.stored_framebuffer =
+            Some(Page { width: fb_w as i32, height: fb_h as i32, pixels: rgba.into(), zbuf: Some(zbuf.into()) });
+    }
+
+    fn dump_dynamic_textures(&self) -> Vec<Option<SavedTexture>> {
+        let pages = self.pages.iter().skip(self.stock_atlas_count as usize);
+        pages.map(|page| page.as_ref().map(|x| x.to_saved(true))).collect()
+    }
+
+    fn dump_atlas_pages(&self) -> Vec<Option<SavedTexture>> {
+        self.pages.iter().map(|page| page.as_ref().map(|x| x.to_saved(false))).collect()
+    }
+
+    fn upload_dynamic_textures(&mut self, textures: &[Option<SavedTexture>]) {
+        self.pages.truncate(self.stock_atlas_count as usize);
+        self.pages.resize(self.stock_atlas_count as usize, None);
+        self.pages.extend(textures.iter().map(|texture| texture.as_ref().map(Page::from_saved)));
+        if self.target.is_some_and(|x| self.page(x).is_none()) {
+            self.target = None;
+        }
+    }
+
+    fn get_rect(&self, id: AtlasRef) -> Option<&AtlasRect> {
+        id.0.try_into()
+            .ok()
+            .and_then(|id: usize| self.texture_rects.get(id))
+            .and_then(|o: &Option<AtlasRect>| o.as_ref())
+    }
+
+    fn draw_sprite_general(
+        &mut self,
+        texture: AtlasRef,
+        _part_x: f64,
+        _part_y: f64,
+        _part_w: f64,
+        _part_h: f64,
+        _x: f64,
+        _y: f64,
+        _xscale: f64,
+        _yscale: f64,
+        _angle: f64,
+        _col1: i32,
+        _col2: i32,
+        _col3: i32,
+        _col4: i32,
+        _alpha: f64,
+        _use_origin: bool,
+    ) {
+        // drawing a sprite turns off texture repeat, which the game can see
+        if self.get_rect(texture).is_some() {
+            self.set_texture_repeat(false);
+        }
+    }
+
+    fn draw_rectangle(&mut self, _x1: f64, _y1: f64, _x2: f64, _y2: f64, _colour: i32, _alpha: f64) {}
+
+    fn draw_rectangle_outline(&mut self, _x1: f64, _y1: f64, _x2: f64, _y2: f64, _colour: i32, _alpha: f64) {}
+
+    fn draw_rectangle_gradient(
+        &mut self,
+        _x1: f64,
+        _y1: f64,
+        _x2: f64,
+        _y2: f64,
+        _c1: i32,
+        _c2: i32,
+        _c3: i32,
+        _c4: i32,
+        _alpha: f64,
+        _outline: bool,
+    ) {
+    }
+
+    fn draw_point(&mut self, _x: f64, _y: f64, _colour: i32, _alpha: f64) {}
+
+    fn draw_line(
+        &mut self,
+        _x1: f64,
+        _y1: f64,
+        _x2: f64,
+        _y2: f64,
+        _width: Option<f64>,
+        _c1: i32,
+        _c2: i32,
+        _alpha: f64,
+    ) {
+    }
+
+    fn draw_triangle(
+        &mut self,
+        _x1: f64,
+        _y1: f64,
+        _x2: f64,
+        _y2: f64,
+        _x3: f64,
+        _y3: f64,
+        _c1: i32,
+        _c2: i32,
+        _c3: i32,
+        _alpha: f64,
+        _outline: bool,
+    ) {
+    }
+
+    fn draw_ellipse(
+        &mut self,
+        _x: f64,
+        _y: f64,
+        _rad_x: f64,
+        _rad_y: f64,
+        _c1: i32,
+        _c2: i32,
+        _alpha: f64,
+        _outline: bool,
+    ) {
+    }
+
+    fn draw_roundrect(
+        &mut self,
+        _x1: f64,
+        _y1: f64,
+        _x2: f64,
+        _y2: f64,
+        _c1: i32,
+        _c2: i32,
+        _alpha: f64,
+        _outline: bool,
+    ) {
+    }
+
+    fn set_circle_precision(&mut self, prec: i32) {
+        self.circle_precision = (prec.clamp(4, 64) >> 2) << 2;
+    }
+
+    fn get_circle_precision(&self) -> i32 {
+        self.circle_precision
+    }
+
+    fn reset_primitive_2d(&mut self, ptype: PrimitiveType, atlas_ref: Option<AtlasRef>) {
+        self.primitive_2d = PrimitiveBuilder::new(
+            atlas_ref.and_then(|ar| self.get_rect(ar).copied()).unwrap_or(self.white_pixel),
+            ptype,
+        );
+    }
+
+    fn vertex_2d(&mut self, x: f64, y: f64, xtex: f64, ytex: f64, col: i32, alpha: f64) {
+        self.primitive_2d.push_vertex(
+            [x as f32, y as f32, self.depth],
+            [xtex as f32, ytex as f32],
+            split_colour(col, alpha),
+            [0.0, 0.0, 0.0],
+        );
+    }
+
+    fn draw_primitive_2d(&mut self) {}
+
+    fn get_primitive_2d(&self) -> PrimitiveBuilder {
+        self.primitive_2d.clone()
+    }
+
+    fn set_primitive_2d(&mut self, prim: PrimitiveBuilder) {
+        self.primitive_2d = prim;
+    }
+
+    fn reset_primitive_3d(&mut self, ptype: PrimitiveType, atlas_ref: Option<AtlasRef>) {
+        self.primitive_3d = PrimitiveBuilder::new(
+            atlas_ref.and_then(|ar| self.get_rect(ar).copied()).unwrap_or(self.white_pixel),
+            ptype,
+        );
+    }
+
+    fn vertex_3d(
+        &mut self,
+        x: f64,
+        y: f64,
+        z: f64,
+        nx: f64,
+        ny: f64,
+        nz: f64,
+        xtex: f64,
+        ytex: f64,
+        col: i32,
+        alpha: f64,
+    ) {
+        self.primitive_3d.push_vertex(
+            [x as f32, y as f32, z as f32],
+            [xtex as f32, ytex as f32],
+            split_colour(col, alpha),
+            [nx as f32, ny as f32, nz as f32],
+        );
+    }
+
+    fn draw_primitive_3d(&mut self) {}
+
+    fn get_primitive_3d(&self) -> PrimitiveBuilder {
+        self.primitive_3d.clone()
+    }
+
+    fn set_primitive_3d(&mut self, prim: PrimitiveBuilder) {
+        self.primitive_3d = prim;
+    }
+
+    fn extend_buffers(&self, buf: &mut VertexBuffer) {
+        let verts = self.primitive_3d.get_vertices();
+        match self.primitive_3d.get_shape() {
+            PrimitiveShape::Point => buf.points.extend_from_slice(verts),
+            PrimitiveShape::Line => buf.lines.extend_from_slice(&verts[..verts.len() / 2 * 2]),
+            PrimitiveShape::Triangle => buf.tris.extend_from_slice(&verts[..verts.len() / 3 * 3]),
+        }
+    }
+
+    fn draw_buffers(&mut self, _atlas_ref: Option<AtlasRef>, _buf: &VertexBuffer) {}
+
+    fn get_alpha_blending(&self) -> bool {
+        self.alpha_blending
+    }
+
+    fn set_alpha_blending(&mut self, alphablend: bool) {
+        self.alpha_blending = alphablend;
+    }
+
+    fn get_blend_mode(&self) -> (BlendType, BlendType) {
+        self.blend_mode
+    }
+
+    fn set_blend_mode(&mut self, src: BlendType, dst: BlendType) {
+        self.blend_mode = (src, dst);
+    }
+
+    fn get_pixel_interpolation(&self) -> bool {
+        self.interpolate_pixels
+    }
+
+    fn set_pixel_interpolation(&mut self, lerping: bool) {
+        self.interpolate_pixels = lerping;
+    }
+
+    fn get_offscreen_culling(&self) -> bool {
+        self.cull_offscreen
+    }
+
+    fn set_offscreen_culling(&mut self, cull: bool) {
+        self.cull_offscreen = cull;
+    }
+
+    fn get_texture_repeat(&self) -> bool {
+        self.texture_repeat
+    }
+
+    fn set_texture_repeat(&mut self, repeat: bool) {
+        self.texture_repeat = repeat;
+    }
+
+    fn flush_queue(&mut self) {}
+
+    fn set_view_matrix(&mut self, _view: [f32; 16]) {}
+
+    fn set_viewproj_matrix(&mut self, _view: [f32; 16], _proj: [f32; 16]) {}
+
+    fn get_model_matrix(&self) -> [f32; 16] {
+        self.model_matrix
+    }
+
+    fn set_model_matrix(&mut self, model: [f32; 16]) {
+        self.model_matrix = model;
+    }
+
+    fn mult_model_matrix(&mut self, model: [f32; 16]) {
+        self.model_matrix = mat4mult(self.model_matrix, model);
+    }
+
+    fn set_projection_ortho(&mut self, _x: f64, _y: f64, _w: f64, _h: f64, _angle: f64) {}
+
+    fn set_projection_perspective(&mut self, _x: f64, _y: f64, _w: f64, _h: f64, _angle: f64) {}
+
+    fn set_view(
+        &mut self,
+        _src_x: i32,
+        _src_y: i32,
+        _src_w: i32,
+        _src_h: i32,
+        _src_angle: f64,
+        port_x: i32,
+        port_y: i32,
+        port_w: i32,
+        port_h: i32,
+    ) {
+        // as in DX8, the viewport only changes while drawing to the framebuffer
+        if self.target.is_none() && port_x >= 0 && port_y >= 0 && port_w >= 0 && port_h >= 0 {
+            self.clip = [port_x, port_y, port_w, port_h];
+        }
+    }
+
+    fn clear_view(&mut self, colour: Colour, alpha: f64) {
+        self.clear(Some(colour_bytes(colour, alpha)), Some(1.0));
+    }
+
+    fn clear_view_no_zbuf(&mut self, colour: Colour, alpha: f64) {
+        self.clear(Some(colour_bytes(colour, alpha)), None);
+    }
+
+    fn clear_zbuf(&mut self) {
+        if self.using_3d {
+            self.clear(None, Some(1.0));
+        }
+    }
+
+    fn get_3d(&self) -> bool {
+        self.using_3d
+    }
+
+    fn set_3d(&mut self, use_3d: bool) {
+        self.using_3d = use_3d;
+        self.set_depth_test(use_3d);
+        self.set_perspective(use_3d);
+    }
+
+    fn get_depth(&self) -> f32 {
+        self.depth
+    }
+
+    fn set_depth(&mut self, depth: f32) {
+        self.depth = if self.using_3d { depth.clamp(-16000.0, 16000.0) } else { 0.0 };
+    }
+
+    fn get_depth_test(&self) -> bool {
+        self.depth_test
+    }
+
+    fn set_depth_test(&mut self, depth_test: bool) {
+        self.depth_test = depth_test && self.using_3d;
+    }
+
+    fn get_write_depth(&self) -> bool {
+        self.write_depth
+    }
+
+    fn set_write_depth(&mut self, write_depth: bool) {
+        self.write_depth = write_depth;
+    }
+
+    fn get_culling(&self) -> bool {
+        self.culling
+    }
+
+    fn set_culling(&mut self, culling: bool) {
+        self.culling = culling;
+    }
+
+    fn get_perspective(&self) -> bool {
+        self.perspective
+    }
+
+    fn set_perspective(&mut self, perspective: bool) {
+        self.perspective = perspective;
+    }
+
+    fn get_fog(&self) -> Option<Fog> {
+        self.fog.clone()
+    }
+
+    fn set_fog(&mut self, fog: Option<Fog>) {
+        self.fog = fog.map(|fog| Fog { colour: gpu_colour(fog.colour), ..fog });
+    }
+
+    fn get_gouraud(&self) -> bool {
+        self.gouraud
+    }
+
+    fn set_gouraud(&mut self, gouraud: bool) {
+        self.gouraud = gouraud;
+    }
+
+    fn get_lighting_enabled(&self) -> bool {
+        self.lighting
+    }
+
+    fn set_lighting_enabled(&mut self, enabled: bool) {
+        self.lighting = enabled;
+    }
+
+    fn get_ambient_colour(&self) -> i32 {
+        self.ambient_colour
+    }
+
+    fn set_ambient_colour(&mut self, colour: i32) {
+        self.ambient_colour = gpu_colour(colour);
+    }
+
+    fn get_lights(&self) -> [(bool, Light); 8] {
+        self.lights
+    }
+
+    fn set_lights(&mut self, lights: [(bool, Light); 8]) {
+        lights.iter().enumerate().for_each(|(i, &(enabled, light))| {
+            self.set_light_enabled(i, enabled);
+            self.set_light(i, light);
+        })
+    }
+
+    fn set_light_enabled(&mut self, id: usize, enabled: bool) {
+        self.lights[id].0 = enabled;
+    }
+
+    fn set_light(&mut self, id: usize, light: Light) {
+        self.lights[id].1 = match light {
+            Light::Directional { direction, colour } => Light::Directional { direction, colour: gpu_colour(colour) },
+            Light::Point { position, range, colour } => Light::Point { position, range, colour: gpu_colour(colour) },
+        };
+    }
+
+    fn present(&mut self, _window_width: u32, _window_height: u32, _scaling: Scaling) {}
+
+    fn set_overlay(&mut self, _rects: Vec<OverlayRect>) {}
+
+    fn draw_stored(&mut self, _x: i32, _y: i32, _w: u32, _h: u32) {}
+
+    fn stored_size(&self) -> (u32, u32) {
+        let framebuffer = self.stored_framebuffer.as_ref().unwrap_or(&self.framebuffer);
+        (framebuffer.width as u32, framebuffer.height as u32)
+    }
+
+    fn finish(&mut self, window_width: u32, window_height: u32, clear_colour: Colour) {
+        self.present(window_width, window_height, Scaling::Fixed(1.0));
+        self.setup_frame(clear_colour)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer() -> RendererImpl {
+        let options = RendererOptions { size: (4, 3), ..Default::default() };
+        let mut renderer = RendererImpl::new(&options, Colour::new(1.0, 0.0, 0.0));
+        let mut atlases = AtlasBuilder::new(64);
+        atlases.texture(2, 1, 0, 0, Box::new([1, 2, 3, 4, 5, 6, 7, 8])).unwrap();
+        renderer.push_atlases(atlases).unwrap();
+        renderer
+    }
+
+    #[test]
+    fn textures_and_surfaces() {
+        let mut renderer = renderer();
+        // the game's textures are stored as BGRA and read back as RGBA
+        assert_eq!(&*renderer.dump_sprite(AtlasRef(0)), &[3, 2, 1, 4, 7, 6, 5, 8]);
+        assert_eq!(&*renderer.get_pixels(3, 2, 2, 1), &[255, 0, 0, 255, 0, 0, 0, 0]);
+
+        let sprite = renderer.upload_sprite(Box::new([9; 2 * 2 * 4]), 2, 2, 1, 0).unwrap();
+        let copy = renderer.duplicate_sprite(sprite).unwrap();
+        assert_eq!(renderer.get_rect(copy).unwrap().origin_x, 0.5);
+        assert_eq!(&*renderer.dump_sprite(copy), &[9; 16]);
+
+        // clearing a surface only affects that surface, and drawing doesn't change anything
+        let surface = renderer.create_surface(3, 3, true).unwrap();
+        renderer.set_target(surface);
+        renderer.clear_view(Colour::new(0.0, 0.0, 1.0), 0.5);
+        renderer.draw_rectangle(0.0, 0.0, 10.0, 10.0, 0xFFFFFF, 1.0);
+        renderer.reset_target();
+        assert_eq!(&*renderer.dump_sprite_part(surface, 2, 2, 1, 1), &[0, 0, 255, 128]);
+        assert_eq!(&*renderer.get_pixels(0, 0, 1, 1), &[255, 0, 0, 255]);
+
+        // copying is clipped to both surfaces
+        renderer.copy_surface(copy, 1, 1, surface, 0, 0, 3, 3);
+        assert_eq!(&*renderer.dump_sprite(copy), &[9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 0, 0, 255, 128]);
+
+        // only textures made after loading are saved, and they come back as they were
+        let saved = renderer.dump_dynamic_textures();
+        let rects = renderer.get_texture_rects();
+        renderer.delete_sprite(copy);
+        assert!(renderer.get_rect(copy).is_none());
+        renderer.upload_dynamic_textures(&saved);
+        renderer.set_texture_rects(&rects);
+        assert_eq!(&*renderer.dump_sprite_part(copy, 1, 1, 1, 1), &[0, 0, 255, 128]);
+    }
+
+    #[test]
+    fn framebuffer() {
+        let mut renderer = renderer();
+        renderer.set_view(0, 0, 4, 3, 0.0, 1, 1, 2, 1);
+        renderer.clear_view(Colour::new(0.0, 1.0, 0.0), 1.0);
+        assert_eq!(&*renderer.get_pixels(0, 1, 4, 1), &[
+            255, 0, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 255, 0, 0, 255
+        ]);
+
+        renderer.resize_framebuffer(2, 2, true);
+        assert_eq!(renderer.stored_size(), (4, 3));
+        assert_eq!(&*renderer.get_pixels(1, 1, 1, 1), &[0, 255, 0, 255]);
+        renderer.finish(2, 2, Colour::new(0.0, 0.0, 0.0));
+        assert_eq!(&*renderer.get_pixels(1, 1, 1, 1), &[0, 0, 0, 255]);
+        assert_eq!(renderer.stored_pixels().len(), 4 * 3 * 4);
+        assert_eq!(renderer.stored_zbuffer().len(), 4 * 3);
+    }
+
+    #[test]
+    fn state() {
+        let mut renderer = renderer();
+        renderer.set_depth(100.0);
+        assert_eq!(renderer.get_depth(), 0.0);
+        renderer.set_3d(true);
+        renderer.set_depth(20000.0);
+        assert_eq!((renderer.get_depth(), renderer.get_depth_test()), (16000.0, true));
+        renderer.set_circle_precision(30);
+        assert_eq!(renderer.get_circle_precision(), 28);
+        renderer.set_texture_repeat(true);
+        renderer.draw_sprite(AtlasRef(0), 0.0, 0.0, 1.0, 1.0, 0.0, 0, 1.0);
+        assert!(!renderer.get_texture_repeat());
+        renderer.set_ambient_colour(0x7F000080);
+        assert_eq!(renderer.get_ambient_colour(), gpu_colour(0x80));
+        renderer.reset_primitive_2d(PrimitiveType::TriList, None);
+        renderer.vertex_2d(1.0, 2.0, 0.0, 0.0, 0, 1.0);
+        assert_eq!(renderer.get_primitive_2d().get_vertices().len(), 1);
+    }
+}
@@ -1,8 +1,8 @@
 use crate::{
     render::{
         atlas::{AtlasBuilder, AtlasRect, AtlasRef},
-        mat4mult, BlendType, Fog, Light, PrimitiveBuilder, PrimitiveShape, PrimitiveType, RendererOptions,
-        RendererTrait, SavedTexture, Scaling, Vertex, VertexBuffer,
+        mat4mult, split_colour, BlendType, Fog, Light, OverlayRect, PrimitiveBuilder, PrimitiveShape, PrimitiveType,
+        RendererOptions, RendererTrait, SavedTexture, Scaling, Vertex, VertexBuffer,
     },
     types::Colour,
 };
@@ -185,8 +185,11 @@ pub struct RendererImpl {
     using_3d: bool,
     perspective: bool,
     depth: f32,
+    cull_offscreen: bool,
+    cull_rect: Option<[f64; 4]>,
     primitive_2d: PrimitiveBuilder,
     primitive_3d: PrimitiveBuilder,
+    overlay: Vec<OverlayRect>,
 
     loc_gm81_normalize: GLint, // uniform bool gm81_normalize
     loc_tex: GLint,            // uniform sampler2D tex
@@ -216,6 +219,29 @@ unsafe fn shader_info_log(gl: &gl::Gl, name: &str, id: GLuint) -> String {
     )
 }
 
+#[rustfmt::skip]
+const IDENTITY_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// Checks whether the bounding box of some points lies entirely outside an area (left, top, right, bottom).
+/// Anything this returns true for would be clipped away completely by an orthographic projection of that area.
+fn outside_area(points: &[[f32; 3]], area: [f64; 4]) -> bool {
+    let [left, top, right, bottom] = area;
+    let (mut min_x, mut min_y, mut max_x, mut max_y) =
+        (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for [x, y, _] in points.iter().copied() {
+        min_x = min_x.min(x.into());
+        min_y = min_y.min(y.into());
+        max_x = max_x.max(x.into());
+        max_y = max_y.max(y.into());
+    }
+    max_x < left || min_x > right || max_y < top || min_y > bottom
+}
+
 fn make_view_matrix(x: f64, y: f64, z: f64, w: f64, h: f64, angle: f64) -> [f32; 16] {
     // Note: sin is negated because it's the same as negating the angle, which is how GM8 does view angles
     let angle = angle.to_radians();
@@ -249,15 +275,6 @@ fn make_view_matrix(x: f64, y: f64, z: f64, w: f64, h: f64, angle: f64) -> [f32;
     view_matrix
 }
 
-fn split_colour(rgb: i32, alpha: f64) -> [f32; 4] {
-    [
-        ((rgb & 0xFF) as f32) / 255.0,
-        (((rgb >> 8) & 0xFF) as f32) / 255.0,
-        (((rgb >> 16) & 0xFF) as f32) / 255.0,
-        alpha.max(0.0).min(1.0) as f32,
-    ]
-}
-
 // TODO: probably put this in render.rs instead
 impl VertexBuffer {
     pub fn swap_colour(&mut self, old: (i32, f64), new: (i32, f64)) {
@@ -570,8 +587,11 @@ impl RendererImpl {
                 using_3d: false,
                 perspective: false,
                 depth: 0.0,
+                cull_offscreen: true,
+                cull_rect: None,
                 primitive_2d: PrimitiveBuilder::new(Default::default(), PrimitiveType::PointList),
                 primitive_3d: PrimitiveBuilder::new(Default::default(), PrimitiveType::PointList),
+                overlay: Vec::new(),
 
                 loc_gm81_normalize: gl.GetUniformLocation(program, b"gm81_normalize\0".as_ptr().cast()),
                 loc_tex: gl.GetUniformLocation(program, b"tex\0".as_ptr().cast()),
@@ -604,6 +624,60 @@ impl RendererImpl {
         }
     }
 
+    // Reads back every texture page from first_atlas onwards, and their z-buffers if with_zbuf is set
+    fn dump_textures(&self, first_atlas: usize, with_zbuf: bool) -> Vec<Option<SavedTexture>> {
+        unsafe {
+            // store previous
+            let mut prev_tex2d = 0;
+            self.gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut prev_tex2d);
+
+            let mut textures = Vec::with_capacity(self.texture_ids.len() - first_atlas);
+            for (tex_id, zbuf_id) in self
+                .texture_ids
+                .iter()
+                .copied()
+                .zip(self.zbuf_ids.iter().copied())
+                .skip(first_atlas)
+            {
+                textures.push(match tex_id {
+                    Some(tex_id) => {
+                        self.gl.BindTexture(gl::TEXTURE_2D, tex_id);
+                        let mut width = 0;
+                        let mut height = 0;
+                        self.gl.GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_WIDTH, &mut width);
+                        self.gl.GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_HEIGHT, &mut height);
+                        let len = (width * height) as usize;
+                        let mut pixels: Vec<u8> = Vec::with_capacity(len * 4);
+                        pixels.set_len(len * 4);
+                        self.gl.GetTexImage(gl::TEXTURE_2D, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr().cast());
+                        let zbuf = if let Some(zbuf_id) = zbuf_id.filter(|_| with_zbuf) {
+                            self.gl.BindTexture(gl::TEXTURE_2D, zbuf_id);
+                            let mut zbuf: Vec<f32> = Vec::with_capacity(len);
+                            zbuf.set_len(len);
+                            self.gl.GetTexImage(
+                                gl::TEXTURE_2D,
+                                0,
+                                gl::DEPTH_COMPONENT,
+                                gl::FLOAT,
+                                zbuf.as_mut_ptr().cast(),
+                            );
+                            Some(zbuf.into_boxed_slice())
+                        } else {
+                            None
+                        };
+                        Some(SavedTexture { width, height, pixels: pixels.into_boxed_slice(), zbuf })
+                    },
+                    None => None,
+                });
+            }
+
+            self.gl.BindTexture(gl::TEXTURE_2D, prev_tex2d as _);
+            assert_eq!(self.gl.GetError(), 0);
+
+            textures
+        }
+    }
+
     fn setup_frame(&mut self, clear_colour: Colour) {
         unsafe {
             // get framebuffer size
@@ -1033,6 +1107,14 @@ impl RendererTrait for RendererImpl {
         unsafe { self.imp.wait_vsync() }
     }
 
+    fn set_swap_interval(&self, interval: u32) {
+        unsafe { self.imp.set_swap_interval(interval) };
+    }
+
+    fn get_swap_interval(&self) -> u32 {
+        unsafe { self.imp.get_swap_interval() }
+    }
+
     fn create_sprite_colour(&mut self, width: i32, height: i32, col: Colour) -> Result<AtlasRef, String> {
         let atlas_ref = self.create_surface(width, height, false)?;
         if let Some(rect) = self.get_rect(atlas_ref) {
@@ -1394,7 +1476,8 @@ impl RendererTrait for RendererImpl {
     }
 
     fn get_texture_from_id(&self, id: i32) -> Option<AtlasRef> {
-        Some(AtlasRef(id))
+        // texture_rects slots are never reused, so a stale id can't end up pointing at some other texture
+        Some(AtlasRef(id)).filter(|&atlas_ref| self.get_rect(atlas_ref).is_some())
     }
 
     fn get_texture_rects(&self) -> Vec<Option<AtlasRect>> {
@@ -1544,56 +1627,11 @@ impl RendererTrait for RendererImpl {
     }
 
     fn dump_dynamic_textures(&self) -> Vec<Option<SavedTexture>> {
-        unsafe {
-            // store previous
-            let mut prev_tex2d = 0;
-            self.gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut prev_tex2d);
-
-            let mut textures = Vec::with_capacity(self.texture_ids.len() - self.stock_atlas_count as usize);
-            for (tex_id, zbuf_id) in self
-                .texture_ids
-                .iter()
-                .copied()
-                .zip(self.zbuf_ids.iter().copied())
-                .skip(self.stock_atlas_count as usize)
-            {
-                textures.push(match tex_id {
-                    Some(tex_id) => {
-                        self.gl.BindTexture(gl::TEXTURE_2D, tex_id);
-                        let mut width = 0;
-                        let mut height = 0;
-                        self.gl.GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_WIDTH, &mut width);
-                        self.gl.GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_HEIGHT, &mut height);
-                        let len = (width * height) as usize;
-                        let mut pixels: Vec<u8> = Vec::with_capacity(len * 4);
-                        pixels.set_len(len * 4);
-                        self.gl.GetTexImage(gl::TEXTURE_2D, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr().cast());
-                        let zbuf = if let Some(zbuf_id) = zbuf_id {
-                            self.gl.BindTexture(gl::TEXTURE_2D, zbuf_id);
-                            let mut zbuf: Vec<f32> = Vec::with_capacity(len);
-                            zbuf.set_len(len);
-                            self.gl.GetTexImage(
-                                gl::TEXTURE_2D,
-                                0,
-                                gl::DEPTH_COMPONENT,
-                                gl::FLOAT,
-                                zbuf.as_mut_ptr().cast(),
-                            );
-                            Some(zbuf.into_boxed_slice())
-                        } else {
-                            None
-                        };
-                        Some(SavedTexture { width, height, pixels: pixels.into_boxed_slice(), zbuf })
-                    },
-                    None => None,
-                });
-            }
-
-            self.gl.BindTexture(gl::TEXTURE_2D, prev_tex2d as _);
-            assert_eq!(self.gl.GetError(), 0);
+        self.dump_textures(self.stock_atlas_count as usize, true)
+    }
 
-            textures
-        }
+    fn dump_atlas_pages(&self) -> Vec<Option<SavedTexture>> {
+        self.dump_textures(0, false)
     }
 
     fn upload_dynamic_textures(&mut self, textures: &[Option<SavedTexture>]) {
@@ -1755,13 +1793,25 @@ impl RendererTrait for RendererImpl {
             [(x + xoff * angle_cos - yoff * angle_sin) as f32, (y + yoff * angle_cos + xoff * angle_sin) as f32, depth]
         };
 
+        let corners = [rotate(left, top), rotate(right, top), rotate(right, bottom), rotate(left, bottom)];
+
+        // skip batching sprites that can't end up on screen - only the vertices are dropped, the caller has
+        // already done everything else drawing does, so this can't change game state
+        if self.cull_offscreen && self.next_render_state.model_matrix == IDENTITY_MATRIX {
+            if let Some(area) = self.cull_rect {
+                if outside_area(&corners, area) {
+                    return
+                }
+            }
+        }
+
         // push the vertices
         self.push_primitive(
             PrimitiveBuilder::new(atlas_ref, PrimitiveType::TriFan)
-                .push_vertex(rotate(left, top), [tex_left, tex_top], split_colour(col1, alpha), normal)
-                .push_vertex(rotate(right, top), [tex_right, tex_top], split_colour(col2, alpha), normal)
-                .push_vertex(rotate(right, bottom), [tex_right, tex_bottom], split_colour(col3, alpha), normal)
-                .push_vertex(rotate(left, bottom), [tex_left, tex_bottom], split_colour(col4, alpha), normal),
+                .push_vertex(corners[0], [tex_left, tex_top], split_colour(col1, alpha), normal)
+                .push_vertex(corners[1], [tex_right, tex_top], split_colour(col2, alpha), normal)
+                .push_vertex(corners[2], [tex_right, tex_bottom], split_colour(col3, alpha), normal)
+                .push_vertex(corners[3], [tex_left, tex_bottom], split_colour(col4, alpha), normal),
         );
     }
 
@@ -2062,6 +2112,14 @@ impl RendererTrait for RendererImpl {
         self.render_state_updated = true;
     }
 
+    fn get_offscreen_culling(&self) -> bool {
+        self.cull_offscreen
+    }
+
+    fn set_offscreen_culling(&mut self, cull: bool) {
+        self.cull_offscreen = cull;
+    }
+
     fn get_texture_repeat(&self) -> bool {
         self.next_render_state.texture_repeat.into()
     }
@@ -2082,11 +2140,14 @@ impl RendererTrait for RendererImpl {
     }
 
     fn set_view_matrix(&mut self, view: [f32; 16]) {
+        // arbitrary matrices could be looking anywhere, so don't cull with them
+        self.cull_rect = None;
         self.next_render_state.view_matrix = view;
         self.render_state_updated = true;
     }
 
     fn set_viewproj_matrix(&mut self, view: [f32; 16], proj: [f32; 16]) {
+        self.cull_rect = None;
         self.next_render_state.view_matrix = view;
         self.next_render_state.proj_matrix = proj;
         self.render_state_updated = true;
@@ -2119,6 +2180,10 @@ impl RendererTrait for RendererImpl {
         };
 
         self.set_viewproj_matrix(make_view_matrix(x, y, -16000.0, w, h, angle), proj_matrix);
+        // rotated views are rare enough that it's not worth working out their bounds
+        if angle == 0.0 && w > 0.0 && h > 0.0 {
+            self.cull_rect = Some([x, y, x + w, y + h]);
+        }
     }
 
     fn set_projection_perspective(&mut self, x: f64, y: f64, w: f64, h: f64, angle: f64) {
@@ -2375,30 +2440,7 @@ impl RendererTrait for RendererImpl {
             let (window_width, window_height) = (window_width as i32, window_height as i32);
 
             // Scaling
-            let (w_x, w_y, w_w, w_h) = match scaling {
-                Scaling::Fixed(scale) => {
-                    let w = (f64::from(fb_width) * scale) as i32;
-                    let h = (f64::from(fb_height) * scale) as i32;
-                    ((window_width - w) / 2, (window_height - h) / 2, w, h)
-                },
-                Scaling::Aspect(_) => {
-                    if fb_width > 0 && fb_height > 0 {
-                        let fixed_width = window_height * fb_width / fb_height;
-                        if fixed_width < window_width {
-                            // window is too wide
-                            ((window_width - fixed_width) / 2, 0, fixed_width, window_height)
-                        } else {
-                            // window is too tall
-                            let fixed_height = window_width * fb_height / fb_width;
-                            (0, (window_height - fixed_height) / 2, window_width, fixed_height)
-                        }
-                    } else {
-                        // can never be too careful
-                        (0, 0, fb_width, fb_height)
-                    }
-                },
-                Scaling::Full => (0, 0, window_width, window_height),
-            };
+            let [w_x, w_y, w_w, w_h] = scaling.region((fb_width, fb_height), (window_width, window_height));
 
             // On Intel, glBlitFrameBuffer just does nothing if the scissor box is too big, which it
             // very well could be. So just disable the scissor test for now.
@@ -2425,6 +2467,20 @@ impl RendererTrait for RendererImpl {
                 gl::COLOR_BUFFER_BIT,
                 if self.next_render_state.interpolate_pixels.into() { gl::LINEAR } else { gl::NEAREST },
             );
+
+            // Draw the overlay on top, by clearing a scissor box for each rectangle
+            if !self.overlay.is_empty() {
+                let mut scissor_old = [0; 4];
+                self.gl.GetIntegerv(gl::SCISSOR_BOX, scissor_old.as_mut_ptr());
+                self.gl.Enable(gl::SCISSOR_TEST);
+                for rect in &self.overlay {
+                    self.gl.Scissor(rect.x, rect.y, rect.w, rect.h);
+                    self.gl.ClearColor(rect.colour.r as f32, rect.colour.g as f32, rect.colour.b as f32, 1.0);
+                    self.gl.Clear(gl::COLOR_BUFFER_BIT);
+                }
+                let [x, y, w, h] = scissor_old;
+                self.gl.Scissor(x, y, w, h);
+            }
             self.gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, fb_old as u32);
 
             self.gl.Enable(gl::SCISSOR_TEST);
@@ -2442,6 +2498,10 @@ impl RendererTrait for RendererImpl {
         }
     }
 
+    fn set_overlay(&mut self, rects: Vec<OverlayRect>) {
+        self.overlay = rects;
+    }
+
     fn draw_stored(&mut self, x: i32, y: i32, w: u32, h: u32) {
         if w == 0 || h == 0 {
             return
@@ -2512,3 +2572,27 @@ impl RendererTrait for RendererImpl {
         self.setup_frame(clear_colour)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offscreen_sprites() {
+        let view = [0.0, 0.0, 800.0, 608.0];
+        let quad = |x: f32, y: f32| [[x, y, 0.0], [x + 32.0, y, 0.0], [x + 32.0, y + 32.0, 0.0], [x, y + 32.0, 0.0]];
+        assert!(!outside_area(&quad(100.0, 100.0), view));
+        // partly on screen, or touching the edge
+        assert!(!outside_area(&quad(-16.0, 300.0), view));
+        assert!(!outside_area(&quad(800.0, 300.0), view));
+        assert!(!outside_area(&quad(300.0, -32.0), view));
+        // fully outside on each side
+        assert!(outside_area(&quad(-33.0, 300.0), view));
+        assert!(outside_area(&quad(801.0, 300.0), view));
+        assert!(outside_area(&quad(300.0, -40.0), view));
+        assert!(outside_area(&quad(300.0, 640.0), view));
+        // a rotated sprite whose bounds cross the view even though its corners don't
+        let diamond = [[400.0, -100.0, 0.0], [900.0, 300.0, 0.0], [400.0, 700.0, 0.0], [-100.0, 300.0, 0.0]];
+        assert!(!outside_area(&diamond, view));
+    }
+}
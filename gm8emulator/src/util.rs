@@ -1,3 +1,5 @@
+use crate::math::Real;
+
 /// Converts BGRA pixeldata to RGBA pixeldata in-place.
 pub fn bgra2rgba(data: &mut [u8]) {
     assert_eq!(data.len() % 4, 0);
@@ -23,9 +25,31 @@ pub fn rotate_around_center(x: &mut f64, y: &mut f64, sin: f64, cos: f64) {
     *y = y_new;
 }
 
+/// The points GM8 checks along a line for precise collisions: one for each pixel along the line's longer axis,
+/// including both ends, so the line can't slip between pixels of a mask however steep it is.
+pub fn line_points(x1: i32, y1: i32, x2: i32, y2: i32) -> impl Iterator<Item = (Real, Real)> {
+    let iter_vert = (x2 - x1).abs() < (y2 - y1).abs();
+    // Make sure we're going left to right, or top to bottom if iterating vertically
+    let (x1, y1, x2, y2) =
+        if (iter_vert && y2 < y1) || (!iter_vert && x2 < x1) { (x2, y2, x1, y1) } else { (x1, y1, x2, y2) };
+    let point_count = if iter_vert { y2 - y1 } else { x2 - x1 } + 1;
+    (0..point_count).map(move |i| {
+        // Avoid dividing by zero
+        if point_count == 1 {
+            (Real::from(x1), Real::from(y1))
+        } else if iter_vert {
+            let slope = Real::from(x2 - x1) / Real::from(y2 - y1);
+            (Real::from(x1) + Real::from(i) * slope, Real::from(y1 + i))
+        } else {
+            let slope = Real::from(y2 - y1) / Real::from(x2 - x1);
+            (Real::from(x1 + i), Real::from(y1) + Real::from(i) * slope)
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{bgra2rgba, rgba2bgra};
+    use super::{bgra2rgba, line_points, rgba2bgra};
 
     #[test]
     fn bgra_rgba() {
@@ -38,4 +62,21 @@ mod tests {
         rgba2bgra(&mut cool_pixels);
         assert_eq!(cool_pixels, bgra_pixels);
     }
+
+    #[test]
+    fn line_sampling() {
+        let points = |x1, y1, x2, y2| {
+            line_points(x1, y1, x2, y2).map(|(x, y)| (x.into_inner(), y.into_inner())).collect::<Vec<_>>()
+        };
+        assert_eq!(points(3, 4, 3, 4), [(3.0, 4.0)]);
+        assert_eq!(points(0, 0, 4, 2), [(0.0, 0.0), (1.0, 0.5), (2.0, 1.0), (3.0, 1.5), (4.0, 2.0)]);
+        assert_eq!(points(0, 0, 1, 3), [(0.0, 0.0), (1.0 / 3.0, 1.0), (2.0 / 3.0, 2.0), (1.0, 3.0)]);
+
+        // Lines going up or left get the same points as the other way round
+        for &(x1, y1, x2, y2) in &[(0, 0, 4, 2), (0, 0, 1, 3), (5, -5, 5, 5), (-2, 7, 9, 7), (0, 0, 6, 6)] {
+            assert_eq!(points(x2, y2, x1, y1), points(x1, y1, x2, y2));
+        }
+        assert_eq!(points(5, 5, 5, -5).len(), 11);
+        assert_eq!(points(-3, 0, -9, 1).len(), 7);
+    }
 }
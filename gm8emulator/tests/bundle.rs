@@ -0,0 +1,34 @@
+//! Bundles a game with a short intro replay, then runs the bundle headless, which plays the intro and exits.
+
+use std::{env, fs, process::Command};
+
+#[test]
+fn headless_intro() {
+    let dir = env::temp_dir().join(format!("gm8emulator-bundle-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let exe = dir.join("synthetic.exe");
+    fs::write(&exe, conformance::synthetic::exe()).unwrap();
+    let intro = dir.join("intro.txt");
+    let header = "opengmk replay 1\nstart_time 0\nstart_seed 0\nrerecords 0\n";
+    fs::write(&intro, header.to_string() + &"0 0\n".repeat(60)).unwrap();
+    let bundle = dir.join(if cfg!(windows) { "bundled.exe" } else { "bundled" });
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gm8emulator"))
+        .arg(&exe)
+        .arg("-f")
+        .arg(&intro)
+        .args(["--headless", "-a", "two \"words each\""])
+        .arg("--bundle")
+        .arg(&bundle)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(fs::metadata(&bundle).unwrap().len() > fs::metadata(&exe).unwrap().len());
+
+    // the bundle's own command line goes to the game, so options it doesn't know about aren't an error
+    let output = Command::new(&bundle).args(["--not-an-option", "with spaces"]).output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!stdout.contains("Usage:"), "the bundle wasn't found:\n{}", stdout);
+}
@@ -0,0 +1,89 @@
+//! Plays a replay headless with the metrics server running, and reads the metrics while it's going.
+
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn get(address: &str) -> Value {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    serde_json::from_str(&response[response.find("\r\n\r\n").unwrap() + 4..]).unwrap()
+}
+
+#[test]
+fn headless_replay() {
+    let dir = env::temp_dir().join(format!("gm8emulator-metrics-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let exe = dir.join("synthetic.exe");
+    fs::write(&exe, conformance::synthetic::exe()).unwrap();
+
+    // far more frames than it takes to read the metrics, since the emulator gets stopped after that
+    let replay = dir.join("idle.txt");
+    let header = "opengmk replay 1\nstart_time 0\nstart_seed 0\nrerecords 0\n";
+    fs::write(&replay, header.to_string() + &"0 0\n".repeat(1_000_000)).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gm8emulator"))
+        .arg(&exe)
+        .arg("-f")
+        .arg(&replay)
+        .args(["--headless", "--metrics-port", "0"])
+        .args(["--metrics-watch", "instance_count", "--metrics-watch", "room_caption"])
+        .args(["--metrics-watch", "obj_counter.nope"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let address = stdout
+        .by_ref()
+        .map(Result::unwrap)
+        .find_map(|line| Some(line.strip_prefix("serving metrics on http://")?.trim_end_matches('/').to_string()))
+        .expect("the emulator didn't say where the metrics are");
+    // keep reading so the emulator never blocks on a full pipe
+    thread::spawn(move || stdout.for_each(drop));
+
+    // wait for the first frame, then check the frame number goes up as the replay plays
+    let start = Instant::now();
+    let first = loop {
+        let metrics = get(&address);
+        if metrics["frame"].as_u64().unwrap() > 0 {
+            break metrics
+        }
+        assert!(start.elapsed() < Duration::from_secs(30), "no frames were run");
+        thread::sleep(Duration::from_millis(1));
+    };
+    let second = loop {
+        let metrics = get(&address);
+        if metrics["frame"].as_u64().unwrap() > first["frame"].as_u64().unwrap() {
+            break metrics
+        }
+        assert!(start.elapsed() < Duration::from_secs(30), "the frame number stopped at {}", first["frame"]);
+        thread::sleep(Duration::from_millis(1));
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = fs::remove_dir_all(&dir);
+
+    for metrics in &[first, second] {
+        let mut keys = metrics.as_object().unwrap().keys().map(String::as_str).collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, ["errors", "fps", "frame", "room", "watches"]);
+        assert!(metrics["fps"].is_u64());
+        assert_eq!(metrics["room"], "rm_start");
+        assert_eq!(
+            metrics["watches"],
+            json!({ "instance_count": 1.0, "room_caption": "synthetic", "obj_counter.nope": null })
+        );
+        let errors = metrics["errors"].as_object().unwrap();
+        assert_eq!(errors.keys().collect::<Vec<_>>(), ["obj_counter.nope"]);
+        assert!(errors["obj_counter.nope"].is_string());
+    }
+}
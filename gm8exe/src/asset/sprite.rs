@@ -11,6 +11,9 @@ pub const VERSION: u32 = 800;
 pub const VERSION_COLLISION: u32 = 800;
 pub const VERSION_FRAME: u32 = 800;
 
+/// The sprite version late GM8.1 builds use, which also stores the collision shape.
+pub const VERSION_SHAPE: u32 = 810;
+
 pub struct Sprite {
     /// The asset name present in GML and the editor.
     pub name: PascalString,
@@ -30,6 +33,10 @@ pub struct Sprite {
 
     /// Whether each individual frame has its own collision map.
     pub per_frame_colliders: bool,
+
+    /// The collision shape picked in the editor: 0 = precise, 1 = rectangle, 2 = disk, 3 = diamond.
+    /// Only late GM8.1 builds store this, and only for sprites with frames.
+    pub collision_shape: Option<u32>,
 }
 
 pub struct Frame {
@@ -67,13 +74,13 @@ impl Asset for Sprite {
 
         let version = reader.read_u32::<LE>()?;
         if strict {
-            assert_ver_multiple(version, &[VERSION, 810])?;
+            assert_ver_multiple(version, &[VERSION, VERSION_SHAPE])?;
         }
 
         let origin_x = reader.read_i32::<LE>()?;
         let origin_y = reader.read_i32::<LE>()?;
         let frame_count = reader.read_u32::<LE>()?;
-        let (frames, colliders, per_frame_colliders) = if frame_count != 0 {
+        let (frames, colliders, per_frame_colliders, collision_shape) = if frame_count != 0 {
             let frames = (0..frame_count)
                 .map(|_| {
                     let version = reader.read_u32::<LE>()?;
@@ -113,26 +120,24 @@ impl Asset for Sprite {
                 Ok(CollisionMap { width, height, bbox_left, bbox_right, bbox_top, bbox_bottom, data })
             }
 
-            if version == 810 {
-                reader.read_u32::<LE>()?; // collision shape, unused
-            }
+            let collision_shape = if version == VERSION_SHAPE { Some(reader.read_u32::<LE>()?) } else { None };
             let per_frame_colliders = reader.read_u32::<LE>()? != 0;
             let colliders: Vec<CollisionMap> = if per_frame_colliders {
                 (0..frame_count).map(|_| read_collision(&mut reader, strict)).collect::<Result<_, _>>()?
             } else {
                 vec![read_collision(&mut reader, strict)?]
             };
-            (frames, colliders, per_frame_colliders)
+            (frames, colliders, per_frame_colliders, collision_shape)
         } else {
-            (Vec::new(), Vec::new(), false)
+            (Vec::new(), Vec::new(), false, None)
         };
 
-        Ok(Sprite { name, origin_x, origin_y, frames, colliders, per_frame_colliders })
+        Ok(Sprite { name, origin_x, origin_y, frames, colliders, per_frame_colliders, collision_shape })
     }
 
     fn serialize_exe(&self, mut writer: impl io::Write, _version: GameVersion) -> io::Result<()> {
         writer.write_pas_string(&self.name)?;
-        writer.write_u32::<LE>(VERSION)?;
+        writer.write_u32::<LE>(if self.collision_shape.is_some() { VERSION_SHAPE } else { VERSION })?;
         writer.write_i32::<LE>(self.origin_x)?;
         writer.write_i32::<LE>(self.origin_y)?;
         if !self.frames.is_empty() {
@@ -145,6 +150,9 @@ impl Asset for Sprite {
                 let pixeldata = frame.data.clone();
                 writer.write_all(&pixeldata)?;
             }
+            if let Some(shape) = self.collision_shape {
+                writer.write_u32::<LE>(shape)?;
+            }
             writer.write_u32::<LE>(self.per_frame_colliders.into())?;
             for collider in self.colliders.iter() {
                 writer.write_u32::<LE>(VERSION_COLLISION)?;
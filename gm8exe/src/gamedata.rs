@@ -5,6 +5,21 @@ pub mod gm81;
 use crate::{reader::ReaderError, upx, GameVersion};
 use std::io::{self, Seek, SeekFrom};
 
+/// Identifies the game version of gamedata which has been cut out of its exe, so it starts with the header.
+/// Also removes any version-specific encryptions.
+pub fn find_standalone<F>(data: &mut io::Cursor<&mut [u8]>, logger: Option<F>) -> Result<GameVersion, ReaderError>
+where
+    F: Copy + Fn(&str),
+{
+    if gm80::check_standalone(data, logger)? {
+        Ok(GameVersion::GameMaker8_0)
+    } else if gm81::check_standalone(data, logger)? {
+        Ok(GameVersion::GameMaker8_1)
+    } else {
+        Err(ReaderError::UnknownFormat)
+    }
+}
+
 /// Identifies the game version and start of gamedata header, given a data cursor.
 /// Also removes any version-specific encryptions.
 pub fn find<F>(
@@ -122,3 +137,32 @@ where
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::find_standalone;
+    use crate::GameVersion;
+    use std::io;
+
+    #[test]
+    fn standalone_headers() {
+        let mut gm80 = vec![0u8; 64];
+        gm80[0..4].copy_from_slice(&1234321u32.to_le_bytes());
+        gm80[4..8].copy_from_slice(&800u32.to_le_bytes());
+        let mut data = io::Cursor::new(gm80.as_mut_slice());
+        assert!(matches!(find_standalone(&mut data, None::<fn(&str)>), Ok(GameVersion::GameMaker8_0)));
+        assert_eq!(data.position(), 16);
+
+        // the magic number is split across two dwords, with the xor key in the other bytes (zero here)
+        let mut gm81 = vec![0u8; 1024];
+        gm81[0..4].copy_from_slice(&(0xF7140067u32 & 0xFF00FF00).to_le_bytes());
+        gm81[4..8].copy_from_slice(&(0xF7140067u32 & 0x00FF00FF).to_le_bytes());
+        let mut data = io::Cursor::new(gm81.as_mut_slice());
+        assert!(matches!(find_standalone(&mut data, None::<fn(&str)>), Ok(GameVersion::GameMaker8_1)));
+        assert_eq!(data.position(), 36);
+
+        let mut exe = b"MZ\x90\0\x03\0\0\0\x04\0\0\0\xFF\xFF\0\0".to_vec();
+        let mut data = io::Cursor::new(exe.as_mut_slice());
+        assert!(find_standalone(&mut data, None::<fn(&str)>).is_err());
+    }
+}
@@ -1,9 +1,14 @@
 use byteorder::{ReadBytesExt, LE};
+use flate2::bufread::ZlibDecoder;
 use std::{
     cmp::max,
+    convert::TryInto,
     io::{self, Read, Seek, SeekFrom},
 };
 
+/// How far apart the runner looks for the gamedata header, starting from the position stored in the exe.
+const HEADER_STEP: usize = 10000;
+
 /// Check if this is a standard gm8.0 game by looking for the loading sequence
 /// If so, sets the cursor to the start of the gamedata.
 pub fn check<F>(exe: &mut io::Cursor<&mut [u8]>, logger: Option<F>) -> io::Result<bool>
@@ -97,8 +102,8 @@ where
                     let header1 = match exe.read_u32::<LE>() {
                         Ok(h) => h,
                         _ => {
-                            log!(logger, "Passed end of stream looking for GM8.0 header, so quitting");
-                            return Ok(false)
+                            log!(logger, "Passed end of stream looking for GM8.0 header");
+                            return use_rewritten_header(exe, header_start as usize, logger)
                         },
                     };
                     if header1 == n {
@@ -112,7 +117,7 @@ where
                             header1
                         );
                         // Skip ahead 10000 bytes in the file and try again - this is what the GM8 runner does
-                        exe.seek(SeekFrom::Current(10000 - 4))?;
+                        exe.seek(SeekFrom::Current(HEADER_STEP as i64 - 4))?;
                     }
                 }
             },
@@ -125,7 +130,7 @@ where
                 let header2 = exe.read_u32::<LE>()?;
                 if header2 != n {
                     log!(logger, "Failed to read GM8.0 header: expected version {}, got {}", n, header2);
-                    return Ok(false)
+                    return use_rewritten_header(exe, header_start as usize, logger)
                 }
             },
             None => {
@@ -140,6 +145,68 @@ where
     }
 }
 
+/// Check if this is standalone GM8.0 gamedata, which starts with the default header magic numbers.
+/// If so, sets the cursor to the start of the gamedata.
+pub fn check_standalone<F>(data: &mut io::Cursor<&mut [u8]>, logger: Option<F>) -> io::Result<bool>
+where
+    F: Copy + Fn(&str),
+{
+    log!(logger, "Checking for standalone GM8.0 gamedata...");
+    if data.get_ref().len() < 16 {
+        return Ok(false)
+    }
+    data.set_position(0);
+    if data.read_u32::<LE>()? == 1234321 && data.read_u32::<LE>()? == 800 {
+        data.seek(SeekFrom::Current(8))?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Some protectors rewrite the magic numbers in the gamedata header, but leave the data after it alone.
+/// This looks for the header in the same places the runner would, and picks the first one which is followed by
+/// a settings chunk that inflates completely, checksum and all. If one is found, the cursor is left after the header.
+fn use_rewritten_header<F>(exe: &mut io::Cursor<&mut [u8]>, header_start: usize, logger: Option<F>) -> io::Result<bool>
+where
+    F: Copy + Fn(&str),
+{
+    match find_rewritten_header(exe.get_ref(), header_start) {
+        Some(pos) => {
+            log!(logger, "GM8.0 header at 0x{:X} has rewritten magic numbers, but a settings chunk follows", pos);
+            exe.set_position(pos as u64 + 16);
+            Ok(true)
+        },
+        None => {
+            log!(logger, "No settings chunk after any of the places the GM8.0 header could be, so quitting");
+            Ok(false)
+        },
+    }
+}
+
+/// Finds the first place the runner would look for the header which has a valid settings chunk after it.
+fn find_rewritten_header(data: &[u8], header_start: usize) -> Option<usize> {
+    (header_start..data.len()).step_by(HEADER_STEP).find(|pos| settings_chunk_at(data, pos + 16))
+}
+
+fn settings_chunk_at(data: &[u8], pos: usize) -> bool {
+    let len = match data.get(pos..pos + 4) {
+        Some(len) => u32::from_le_bytes(len.try_into().unwrap()) as usize,
+        None => return false,
+    };
+    let chunk = match data.get(pos + 4..).and_then(|data| data.get(..len)) {
+        Some(chunk) if chunk.len() >= 2 => chunk,
+        _ => return false,
+    };
+    // zlib header: deflate with at most a 32K window, a valid check value and no preset dictionary
+    let (cmf, flg) = (chunk[0], chunk[1]);
+    if cmf & 0x0F != 8 || cmf >> 4 > 7 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 || flg & 0x20 != 0 {
+        return false
+    }
+    let mut settings = Vec::new();
+    ZlibDecoder::new(chunk).read_to_end(&mut settings).is_ok() && !settings.is_empty()
+}
+
 /// Removes GameMaker 8.0 protection in-place.
 pub fn decrypt<F>(data: &mut io::Cursor<&mut [u8]>, logger: Option<F>) -> io::Result<()>
 where
@@ -184,3 +251,72 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check, find_rewritten_header, HEADER_STEP};
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::{self, Write};
+
+    const HEADER_START: usize = 0x150000;
+
+    // An exe with an intact GM8.0 loading sequence, with `header` written some steps past where the header should be
+    // and a settings chunk after it. The rest of the space the runner searches is filled with noise.
+    fn exe(steps: usize, header: [u32; 2], seed: u32) -> Vec<u8> {
+        let mut exe = noise(HEADER_START + HEADER_STEP * 4, seed);
+        exe[0xA49BE..0xA49CB].copy_from_slice(&[0x8B, 0x45, 0xF4, 0xE8, 0x2A, 0xBD, 0xFD, 0xFF, 0x3D, 0x91, 0xD5, 0x12, 0]);
+        exe[0xA49CB..0xA49D1].copy_from_slice(&[0x0F, 0x85, 0x18, 0x01, 0x00, 0x00]);
+        exe[0xA49E2..0xA49EE].copy_from_slice(&[0x8B, 0xC6, 0xE8, 0x07, 0xBD, 0xFD, 0xFF, 0x3D, 0x20, 0x03, 0, 0]);
+        exe[0xA49EE..0xA49F4].copy_from_slice(&[0x0F, 0x85, 0xF5, 0x00, 0x00, 0x00]);
+        exe[0x144AC0..0x144AC4].copy_from_slice(&(HEADER_START as u32).to_le_bytes());
+
+        let mut settings = ZlibEncoder::new(Vec::new(), Compression::default());
+        settings.write_all(&[1; 200]).unwrap();
+        let settings = settings.finish().unwrap();
+        let pos = HEADER_START + HEADER_STEP * steps;
+        exe[pos..pos + 4].copy_from_slice(&header[0].to_le_bytes());
+        exe[pos + 4..pos + 8].copy_from_slice(&header[1].to_le_bytes());
+        exe[pos + 16..pos + 20].copy_from_slice(&(settings.len() as u32).to_le_bytes());
+        exe[pos + 20..pos + 20 + settings.len()].copy_from_slice(&settings);
+        exe
+    }
+
+    fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn header_position(mut exe: Vec<u8>) -> Option<usize> {
+        let mut cursor = io::Cursor::new(&mut exe[..]);
+        match check(&mut cursor, None::<fn(&str)>).unwrap() {
+            true => Some(cursor.position() as usize - 16),
+            false => None,
+        }
+    }
+
+    #[test]
+    fn rewritten_magic() {
+        assert_eq!(header_position(exe(2, [1234321, 800], 1)), Some(HEADER_START + HEADER_STEP * 2));
+        assert_eq!(header_position(exe(2, [0xDEADBEEF, 0xCAFEF00D], 1)), Some(HEADER_START + HEADER_STEP * 2));
+        // the magic number's right but the version isn't
+        assert_eq!(header_position(exe(3, [1234321, 801], 2)), Some(HEADER_START + HEADER_STEP * 3));
+    }
+
+    #[test]
+    fn noise_isnt_a_header() {
+        for seed in 0..8 {
+            let data = noise(HEADER_STEP * 500, seed);
+            assert_eq!(find_rewritten_header(&data, 0), None);
+            for offset in 0..64 {
+                assert_eq!(find_rewritten_header(&data, offset * 7), None);
+            }
+        }
+        let mut exe = exe(1, [0, 0], 3);
+        exe.truncate(HEADER_START + HEADER_STEP);
+        assert_eq!(header_position(exe), None);
+    }
+}
@@ -108,6 +108,28 @@ where
     }
 }
 
+/// Check if this is standalone GM8.1 gamedata, which starts with the default header.
+/// If so, removes gm81 encryption and sets the cursor to the start of the gamedata.
+pub fn check_standalone<F>(data: &mut io::Cursor<&mut [u8]>, logger: Option<F>) -> io::Result<bool>
+where
+    F: Copy + Fn(&str),
+{
+    log!(logger, "Checking for standalone GM8.1 gamedata...");
+    if data.get_ref().len() < 8 {
+        return Ok(false)
+    }
+    data.set_position(0);
+    let d1 = data.read_u32::<LE>()?;
+    let d2 = data.read_u32::<LE>()?;
+    if (d1 & 0xFF00FF00) | (d2 & 0x00FF00FF) == 0xF7140067 {
+        decrypt(data, logger, XorMethod::Normal)?;
+        data.seek(SeekFrom::Current(20))?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
 /// Seeks for a GM81-style magic value from its current position.
 /// Returns the associated xor value within the magic if it was found; returns None otherwise.
 /// On success, the cursor will have been advanced just past the eight bytes from which the value was parsed.
@@ -153,7 +175,7 @@ where
         rvalue
     };
 
-    let sudalv_magic_point = (data.position() - 12) as u32;
+    let sudalv_magic_point = data.position().saturating_sub(12) as u32;
     let hash_key = format!("_MJD{}#RWK", data.read_u32::<LE>()?);
     let hash_key_utf16: Vec<u8> = hash_key.bytes().flat_map(|c| once(c).chain(once(0))).collect();
 
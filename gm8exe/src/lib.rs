@@ -21,9 +21,11 @@ macro_rules! log {
 pub mod asset;
 pub mod def;
 pub mod gamedata;
+pub mod pe;
 pub mod reader;
 pub mod rsrc;
 pub mod settings;
+pub mod sha256;
 pub mod upx;
 
 mod colour;
@@ -51,15 +53,102 @@ pub struct GameAssets {
 
     pub dx_dll: Vec<u8>,
     pub ico_file_raw: Option<Vec<u8>>,
+    pub exe_metadata: Option<ExeMetadata>,
     pub help_dialog: GameHelpDialog,
     pub last_instance_id: i32,
     pub last_tile_id: i32,
     pub library_init_strings: Vec<PascalString>,
     pub room_order: Vec<i32>,
+    /// Whatever comes after the gamedata in the file. GameMaker never puts anything there, but installers and
+    /// other tools sometimes do. In GM8.1 and antidec-protected games the gamedata is encrypted right up to the end
+    /// of the file, so this has been decrypted along with it and won't match what's in the file.
+    pub trailing_data: Option<Box<[u8]>>,
 
     pub settings: Settings,
     pub game_id: u32,
     pub guid: [u32; 4],
+    /// A 64-bit FNV-1a hash of the gamedata, from just after its header to the end of the room order. Unlike a hash
+    /// of the whole file, this stays the same if only the exe's icon or other resources are changed.
+    pub gamedata_hash: u64,
+
+    /// The compressed data each asset was read from, if the game was read with `ReaderOptions::keep_blobs`.
+    pub blobs: Option<AssetBlobs>,
+}
+
+/// What the exe says about itself in its resources, besides its icon.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExeMetadata {
+    pub version_info: Option<pe::VersionInfo>,
+    /// The side-by-side manifest, an XML document.
+    pub manifest: Option<Vec<u8>>,
+}
+
+impl ExeMetadata {
+    /// The product name from the version info, which GM8 fills in from the "Executable Information" settings.
+    pub fn product_name(&self) -> Option<&str> {
+        self.version_info.as_ref()?.string("ProductName").filter(|x| !x.is_empty())
+    }
+}
+
+/// The lists of assets which are stored as separate compressed blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Trigger,
+    Sound,
+    Sprite,
+    Background,
+    Path,
+    Script,
+    Font,
+    Timeline,
+    Object,
+    Room,
+    IncludedFile,
+}
+
+impl AssetKind {
+    /// Every kind, in the order they're stored in the exe.
+    pub const ALL: [AssetKind; 11] = [
+        AssetKind::Trigger,
+        AssetKind::Sound,
+        AssetKind::Sprite,
+        AssetKind::Background,
+        AssetKind::Path,
+        AssetKind::Script,
+        AssetKind::Font,
+        AssetKind::Timeline,
+        AssetKind::Object,
+        AssetKind::Room,
+        AssetKind::IncludedFile,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AssetKind::Trigger => "trigger",
+            AssetKind::Sound => "sound",
+            AssetKind::Sprite => "sprite",
+            AssetKind::Background => "background",
+            AssetKind::Path => "path",
+            AssetKind::Script => "script",
+            AssetKind::Font => "font",
+            AssetKind::Timeline => "timeline",
+            AssetKind::Object => "object",
+            AssetKind::Room => "room",
+            AssetKind::IncludedFile => "included_file",
+        }
+    }
+}
+
+/// Every asset's compressed data exactly as it was in the exe (after decryption), indexed by AssetKind.
+/// Deleted assets are included, since they're still stored.
+pub type AssetBlobs = [Vec<Box<[u8]>>; 11];
+
+impl GameAssets {
+    /// The compressed data an asset was read from. This is None if the game wasn't read with
+    /// `ReaderOptions::keep_blobs`, or if there's no such asset.
+    pub fn asset_blob(&self, kind: AssetKind, index: usize) -> Option<&[u8]> {
+        self.blobs.as_ref()?[kind as usize].get(index).map(|x| x.as_ref())
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
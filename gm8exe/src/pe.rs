@@ -0,0 +1,695 @@
+//! The parts of a Windows PE file (an exe) which are needed besides the game data: its section table,
+//! and the icon, version info and manifest in its resources.
+//!
+//! Format reference: https://docs.microsoft.com/en-us/windows/win32/debug/pe-format
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    io::{self, Cursor, Read, Seek, SeekFrom},
+};
+
+/// The machine type of 32-bit x86 exes, which all GameMaker 8 games are.
+pub const MACHINE_I386: u16 = 0x14C;
+
+/// Resource type IDs: https://docs.microsoft.com/en-us/windows/win32/menurc/resource-types
+pub const RT_ICON: u32 = 3;
+pub const RT_GROUP_ICON: u32 = 14;
+pub const RT_VERSION: u32 = 16;
+pub const RT_MANIFEST: u32 = 24;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The file doesn't start with the MZ and PE signatures, so it isn't an exe.
+    InvalidHeader,
+    IO(io::Error),
+}
+impl std::error::Error for Error {}
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidHeader => write!(f, "invalid exe header"),
+            Error::IO(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::IO(err)
+    }
+}
+
+/// A section header
+/// Just read this: https://docs.microsoft.com/en-us/windows/win32/debug/pe-format#section-table-section-headers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    /// Padded with NULs, so ".rsrc" is stored as b".rsrc\0\0\0".
+    pub name: [u8; 8],
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub disk_size: u32,
+    pub disk_address: u32,
+}
+
+/// One resource of some type. Resources can have a copy for each language, but almost never have more than one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resource {
+    /// The resource's ID, or None if it has a name instead.
+    pub id: Option<u32>,
+    pub languages: Vec<ResourceData>,
+}
+
+/// Where one language's copy of a resource is, as an address in the loaded exe's memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceData {
+    pub language: u32,
+    pub rva: u32,
+    pub size: u32,
+}
+
+/// The parts of an exe's version resource which Windows shows in its properties window.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// The numeric file version as major, minor, build, revision, or None if the resource doesn't have one.
+    pub file_version: Option<[u16; 4]>,
+    /// Every string in the first string table, like ("ProductName", "My Game"), in the order they're stored.
+    pub strings: Vec<(String, String)>,
+}
+
+impl VersionInfo {
+    /// Reads a VS_VERSIONINFO resource.
+    /// Format reference: https://docs.microsoft.com/en-us/windows/win32/menurc/vs-versioninfo
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let root = VersionBlock::parse(data)?.0;
+        if root.key != "VS_VERSION_INFO" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a version resource"))
+        }
+
+        // VS_FIXEDFILEINFO, which starts with a signature and the version of the struct itself
+        let mut file_version = None;
+        if root.value.len() >= 16 && root.value[0..4] == 0xFEEF04BDu32.to_le_bytes() {
+            let mut value = Cursor::new(&root.value[8..16]);
+            let (ms, ls) = (value.read_u32::<LE>()?, value.read_u32::<LE>()?);
+            file_version = Some([(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16]);
+        }
+
+        // StringFileInfo holds one StringTable per language, each of which holds the strings themselves
+        let mut strings = Vec::new();
+        let mut children = root.children;
+        while !children.is_empty() {
+            let (child, rest) = VersionBlock::parse(children)?;
+            children = rest;
+            if child.key == "StringFileInfo" && !child.children.is_empty() {
+                let mut entries = VersionBlock::parse(child.children)?.0.children;
+                while !entries.is_empty() {
+                    let (entry, rest) = VersionBlock::parse(entries)?;
+                    entries = rest;
+                    strings.push((entry.key, utf16(entry.value).0));
+                }
+                break
+            }
+        }
+
+        Ok(Self { file_version, strings })
+    }
+
+    /// Looks up a string by its key, like "ProductName", "CompanyName" or "FileVersion".
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.strings.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+// One block of a version resource. They all have the same layout: a header, a NUL-terminated UTF-16 key,
+// a value, and then child blocks, each part aligned to four bytes.
+struct VersionBlock<'a> {
+    key: String,
+    value: &'a [u8],
+    children: &'a [u8],
+}
+
+impl<'a> VersionBlock<'a> {
+    // Reads the block at the start of `data`, also returning whatever comes after it.
+    fn parse(data: &'a [u8]) -> io::Result<(Self, &'a [u8])> {
+        let mut header = Cursor::new(data);
+        let length = usize::from(header.read_u16::<LE>()?);
+        let value_length = usize::from(header.read_u16::<LE>()?);
+        let is_text = header.read_u16::<LE>()? == 1;
+        let block = match data.get(..length) {
+            Some(block) if length >= 6 => block,
+            _ => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        };
+        let (key, key_end) = utf16(&block[6..]);
+        let value_start = align4(6 + key_end).min(length);
+        // text lengths are in UTF-16 characters, but some tools write them in bytes, so don't trust them too much
+        let value_end = (value_start + if is_text { value_length * 2 } else { value_length }).min(length);
+        let children_start = align4(value_end).min(length);
+        let rest = data.get(align4(length)..).unwrap_or_default();
+        Ok((Self { key, value: &block[value_start..value_end], children: &block[children_start..] }, rest))
+    }
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+// Reads a NUL-terminated UTF-16 string, returning it and how many bytes it took up including the NUL.
+fn utf16(data: &[u8]) -> (String, usize) {
+    let units = data.chunks_exact(2).map(|x| u16::from_le_bytes([x[0], x[1]])).take_while(|&x| x != 0);
+    let units = units.collect::<Vec<_>>();
+    (String::from_utf16_lossy(&units), (units.len() * 2 + 2).min(data.len()))
+}
+
+/// The headers of an exe.
+#[derive(Clone, Debug)]
+pub struct Headers {
+    pub machine: u16,
+    pub sections: Vec<Section>,
+}
+
+impl Headers {
+    /// Reads the headers and section table of an exe.
+    pub fn parse(exe: &[u8]) -> Result<Self, Error> {
+        let mut data = Cursor::new(exe);
+
+        // Windows EXE must always start with "MZ"
+        if exe.get(0..2) != Some(b"MZ") {
+            return Err(Error::InvalidHeader)
+        }
+        // Dword at 0x3C indicates the start of the PE header
+        data.set_position(0x3C);
+        let pe_header_loc = data.read_u32::<LE>()? as usize;
+        // PE header must begin with PE\0\0, then the machine type
+        match exe.get(pe_header_loc..pe_header_loc.saturating_add(6)) {
+            Some([b'P', b'E', 0, 0, _, _]) => (),
+            _ => return Err(Error::InvalidHeader),
+        }
+        data.set_position((pe_header_loc + 4) as u64);
+        let machine = data.read_u16::<LE>()?;
+        let section_count = data.read_u16::<LE>()?;
+        // Read length of optional header
+        data.seek(SeekFrom::Current(12))?;
+        let optional_len = data.read_u16::<LE>()?;
+        // Skip over PE characteristics (2 bytes) + optional header
+        data.seek(SeekFrom::Current(i64::from(optional_len) + 2))?;
+
+        let mut sections = Vec::with_capacity(section_count.into());
+        for _ in 0..section_count {
+            let mut name = [0u8; 8];
+            data.read_exact(&mut name)?;
+            let virtual_size = data.read_u32::<LE>()?;
+            let virtual_address = data.read_u32::<LE>()?;
+            let disk_size = data.read_u32::<LE>()?;
+            let disk_address = data.read_u32::<LE>()?;
+            data.seek(SeekFrom::Current(16))?;
+            sections.push(Section { name, virtual_size, virtual_address, disk_size, disk_address });
+        }
+
+        Ok(Self { machine, sections })
+    }
+
+    /// Finds a section by its name, without the padding. If more than one has that name, the last one is used.
+    pub fn section(&self, name: &[u8]) -> Option<&Section> {
+        self.sections.iter().rev().find(|x| x.name.split(|&c| c == 0).next() == Some(name))
+    }
+
+    /// Gets some bytes from the file by their location in the loaded exe's memory.
+    pub fn virtual_bytes<'a>(&self, exe: &'a [u8], rva: u32, size: u32) -> Option<&'a [u8]> {
+        let (rva, size) = (u64::from(rva), u64::from(size));
+        // The data can't run up to the very end of the section, which is wrong, but it's how icons have always
+        // been read so changing it would change decompiler output.
+        let section = self.sections.iter().find(|x| {
+            let start = u64::from(x.virtual_address);
+            rva >= start && rva + size < start + u64::from(x.virtual_size)
+        })?;
+        let start = u64::from(section.disk_address) + (rva - u64::from(section.virtual_address));
+        exe.get(usize::try_from(start).ok()?..usize::try_from(start + size).ok()?)
+    }
+
+    /// Lists the resources of a type, or returns None if the exe has no resources of that type.
+    /// Anything which can't be read, like an entry which points outside the file, is left out.
+    pub fn resources(&self, exe: &[u8], kind: u32) -> Option<Vec<Resource>> {
+        self.resources_at(exe, self.section(b".rsrc")?.disk_address, kind)
+    }
+
+    // Like `resources`, but with the resource tree starting at the given file offset instead of at .rsrc.
+    fn resources_at(&self, exe: &[u8], rsrc_base: u32, kind: u32) -> Option<Vec<Resource>> {
+        let base = u64::from(rsrc_base);
+        let mut data = Cursor::new(exe);
+
+        // The resource tree always has three levels: type, then name or ID, then language.
+        // Named types are ignored, since none of the types this is used for have names.
+        let (_, type_dir) = directory(&mut data, base, 0).into_iter().find(|x| x.0 == Some(kind))?;
+
+        let mut resources = Vec::new();
+        for (id, offset) in directory(&mut data, base, subdirectory(type_dir)?) {
+            let offset = match subdirectory(offset) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            let languages = directory(&mut data, base, offset)
+                .into_iter()
+                .filter_map(|(language, offset)| {
+                    // the bottom of the tree, so this has to point at the data rather than another directory
+                    if subdirectory(offset).is_some() {
+                        return None
+                    }
+                    data.set_position(base + u64::from(offset));
+                    let rva = data.read_u32::<LE>().ok()?;
+                    let size = data.read_u32::<LE>().ok()?;
+                    Some(ResourceData { language: language?, rva, size })
+                })
+                .collect();
+            resources.push(Resource { id, languages });
+        }
+        Some(resources)
+    }
+
+    /// Finds the icon group which will be used for the window icon.
+    /// Returns an entire rebuilt .ico file, or None if there isn't one associated with this exe.
+    pub fn icon(&self, exe: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        match self.section(b".rsrc") {
+            Some(rsrc) => self.icon_at(exe, rsrc.disk_address),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `icon`, but with the resource tree starting at the given file offset instead of at .rsrc.
+    pub(crate) fn icon_at(&self, exe: &[u8], rsrc_base: u32) -> io::Result<Option<Vec<u8>>> {
+        let icons = match self.resources_at(exe, rsrc_base, RT_ICON) {
+            // no icons, even if there's a group which refers to some
+            Some(icons) if icons.iter().all(|x| x.id.is_none()) => return Ok(None),
+            Some(icons) => icons,
+            None => Vec::new(),
+        };
+        // id, rva, size
+        let icons: Vec<(u32, u32, u32)> = icons
+            .iter()
+            .filter_map(|x| Some((x.id?, x.languages.first()?)))
+            .map(|(id, x)| (id, x.rva, x.size))
+            .collect();
+
+        let group = match self.resources_at(exe, rsrc_base, RT_GROUP_ICON).and_then(|x| x.first()?.languages.first().copied()) {
+            Some(group) => group,
+            None => return Ok(None),
+        };
+        let v = match self.virtual_bytes(exe, group.rva, group.size) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        // Read the ico header
+        let mut ico_header = Cursor::new(v);
+        ico_header.seek(SeekFrom::Current(4))?;
+        let image_count = usize::from(ico_header.read_u16::<LE>()?);
+
+        let raw_header_size = 6 + (image_count * 16);
+        let mut raw_file: Vec<u8> = Vec::with_capacity(raw_header_size);
+        let mut raw_file_body: Vec<u8> = Vec::new();
+        raw_file.extend_from_slice(&v[0..6]);
+        for _ in 0..image_count {
+            // Copy data to raw file header
+            let pos = ico_header.position() as usize;
+            let entry = v.get(pos..pos + 12).ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            raw_file.extend_from_slice(entry);
+            raw_file.write_u32::<LE>((raw_header_size + raw_file_body.len()) as u32)?;
+
+            // Skip over the ICO file header
+            // This contains width, height, bpp etc - but these are allowed to be wrong, so we ignore them
+            ico_header.seek(SeekFrom::Current(12))?;
+            let ordinal = u32::from(ico_header.read_u16::<LE>()?);
+
+            // Match this ordinal name with an icon resource
+            if let Some(icon) = icons.iter().find(|x| x.0 == ordinal && x.2 >= 40) {
+                if let Some(v) = self.virtual_bytes(exe, icon.1, icon.2) {
+                    raw_file_body.extend_from_slice(v);
+                }
+            }
+        }
+        raw_file.append(&mut raw_file_body);
+        Ok(Some(raw_file))
+    }
+
+    /// Reads the exe's version info, or returns None if it doesn't have any.
+    /// Returns an error if the resource is there but cut short or otherwise broken.
+    pub fn version_info(&self, exe: &[u8]) -> io::Result<Option<VersionInfo>> {
+        let version = match self.resources(exe, RT_VERSION).and_then(|x| x.first()?.languages.first().copied()) {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        match self.virtual_bytes(exe, version.rva, version.size) {
+            Some(data) => VersionInfo::parse(data).map(Some),
+            None => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    /// Gets the exe's side-by-side manifest, an XML document which says things like whether it's DPI-aware.
+    pub fn manifest<'a>(&self, exe: &'a [u8]) -> Option<&'a [u8]> {
+        let manifest = *self.resources(exe, RT_MANIFEST)?.first()?.languages.first()?;
+        self.virtual_bytes(exe, manifest.rva, manifest.size)
+    }
+}
+
+// Reads the entries of a resource directory as (ID or None if it's named, offset),
+// stopping early if it runs past the end of the file.
+fn directory(data: &mut Cursor<&[u8]>, base: u64, offset: u32) -> Vec<(Option<u32>, u32)> {
+    data.set_position(base + u64::from(offset) + 12);
+    let (name_count, id_count) = match (data.read_u16::<LE>(), data.read_u16::<LE>()) {
+        (Ok(name_count), Ok(id_count)) => (u32::from(name_count), u32::from(id_count)),
+        _ => return Vec::new(),
+    };
+    let mut entries = Vec::new();
+    for i in 0..name_count + id_count {
+        match (data.read_u32::<LE>(), data.read_u32::<LE>()) {
+            (Ok(id), Ok(offset)) => entries.push((if i < name_count { None } else { Some(id) }, offset)),
+            _ => break,
+        }
+    }
+    entries
+}
+
+// Offsets to another directory have the high bit set, and offsets to a resource's data don't.
+fn subdirectory(offset: u32) -> Option<u32> {
+    if offset & 0x8000_0000 != 0 { Some(offset & 0x7FFF_FFFF) } else { None }
+}
+
+/// Whether a manifest says the program is DPI-aware, meaning Windows won't scale its window up on high DPI displays.
+/// The newer dpiAwareness element takes priority over dpiAware, as it does on Windows 10.
+pub fn dpi_aware(manifest: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(manifest);
+    if let Some(values) = element(&text, "dpiAwareness") {
+        // Windows uses the first value in the list it recognises
+        for value in values.split(',').map(|x| x.trim().to_ascii_lowercase()) {
+            match value.as_str() {
+                "unaware" => return false,
+                "system" | "permonitor" | "permonitorv2" => return true,
+                _ => (),
+            }
+        }
+    }
+    match element(&text, "dpiAware") {
+        Some(value) => matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "true/pm" | "per monitor"),
+        None => false,
+    }
+}
+
+// Gets the text inside the first element with this name, ignoring any namespace prefix.
+fn element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = text;
+    while let Some(pos) = rest.find('<') {
+        rest = &rest[pos + 1..];
+        let tag = &rest[..rest.find(|c: char| c == '>' || c == '/' || c.is_whitespace())?];
+        if tag.rsplit(':').next() == Some(name) {
+            let open = rest.find('>')?;
+            if rest[..open].ends_with('/') {
+                return Some("")
+            }
+            let inner = &rest[open + 1..];
+            return Some(&inner[..inner.find('<')?])
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        dpi_aware, Error, Headers, Resource, ResourceData, Section, VersionInfo, RT_GROUP_ICON, RT_ICON, RT_MANIFEST,
+        RT_VERSION,
+    };
+    use crate::{reader::PESection, rsrc::find_icons};
+    use std::io::Cursor;
+
+    const MANIFEST: &[u8] = b"<assembly><application><windowsSettings><dpiAware>true</dpiAware>\
+        </windowsSettings></application></assembly>";
+
+    // Builds an exe with one section, .rsrc, which is loaded at 0x1000 and holds `rsrc`.
+    fn exe(rsrc: &[u8]) -> Vec<u8> {
+        let mut exe = vec![0u8; 0x200];
+        exe[0..2].copy_from_slice(b"MZ");
+        exe[0x3C..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        exe[0x80..0x88].copy_from_slice(b"PE\0\0\x4C\x01\x01\x00");
+        // no optional header, so the section table starts right after the file header
+        exe[0x98..0xA0].copy_from_slice(b".rsrc\0\0\0");
+        for (i, x) in [rsrc.len() as u32 + 0x100, 0x1000, rsrc.len() as u32, 0x200].iter().enumerate() {
+            exe[0xA0 + i * 4..0xA4 + i * 4].copy_from_slice(&x.to_le_bytes());
+        }
+        exe.extend_from_slice(rsrc);
+        exe
+    }
+
+    fn put(rsrc: &mut Vec<u8>, at: usize, bytes: &[u8]) {
+        if rsrc.len() < at + bytes.len() {
+            rsrc.resize(at + bytes.len(), 0);
+        }
+        rsrc[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // A resource directory with some named entries followed by some with IDs.
+    fn dir(names: u16, entries: &[(u32, u32)]) -> Vec<u8> {
+        let mut dir = vec![0u8; 12];
+        dir.extend_from_slice(&names.to_le_bytes());
+        dir.extend_from_slice(&(entries.len() as u16 - names).to_le_bytes());
+        for (id, offset) in entries {
+            dir.extend_from_slice(&id.to_le_bytes());
+            dir.extend_from_slice(&offset.to_le_bytes());
+        }
+        dir
+    }
+
+    fn data_entry(offset: u32, size: u32) -> Vec<u8> {
+        [0x1000 + offset, size, 0, 0].iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    // Two icons, a group which lists them in the opposite order, and a manifest.
+    fn rsrc() -> Vec<u8> {
+        let mut rsrc = Vec::new();
+        let sub = 0x8000_0000;
+        put(
+            &mut rsrc,
+            0x000,
+            &dir(0, &[(RT_ICON, sub | 0x30), (RT_GROUP_ICON, sub | 0x60), (RT_MANIFEST, sub | 0x90)]),
+        );
+        put(&mut rsrc, 0x030, &dir(0, &[(1, sub | 0xC0), (2, sub | 0xD8)]));
+        put(&mut rsrc, 0x060, &dir(0, &[(101, sub | 0xF0)]));
+        put(&mut rsrc, 0x090, &dir(0, &[(1, sub | 0x108)]));
+        put(&mut rsrc, 0x0C0, &dir(0, &[(1033, 0x120)]));
+        put(&mut rsrc, 0x0D8, &dir(0, &[(1033, 0x130)]));
+        put(&mut rsrc, 0x0F0, &dir(0, &[(1033, 0x140)]));
+        put(&mut rsrc, 0x108, &dir(0, &[(1033, 0x150)]));
+        put(&mut rsrc, 0x120, &data_entry(0x200, 40));
+        put(&mut rsrc, 0x130, &data_entry(0x228, 48));
+        put(&mut rsrc, 0x140, &data_entry(0x260, 34));
+        put(&mut rsrc, 0x150, &data_entry(0x290, MANIFEST.len() as u32));
+        put(&mut rsrc, 0x200, &[1; 40]);
+        put(&mut rsrc, 0x228, &[2; 48]);
+        put(&mut rsrc, 0x260, &[0, 0, 1, 0, 2, 0]);
+        put(&mut rsrc, 0x266, &[32, 32, 0, 0, 1, 0, 32, 0, 48, 0, 0, 0, 2, 0]);
+        put(&mut rsrc, 0x274, &[16, 16, 0, 0, 1, 0, 32, 0, 40, 0, 0, 0, 1, 0]);
+        put(&mut rsrc, 0x290, MANIFEST);
+        rsrc
+    }
+
+    #[test]
+    fn parses_sections() {
+        let exe = exe(&[0; 16]);
+        let headers = Headers::parse(&exe).unwrap();
+        assert_eq!(headers.machine, 0x14C);
+        assert_eq!(headers.sections, [Section {
+            name: *b".rsrc\0\0\0",
+            virtual_size: 0x110,
+            virtual_address: 0x1000,
+            disk_size: 16,
+            disk_address: 0x200,
+        }]);
+        assert_eq!(headers.section(b".rsrc"), Some(&headers.sections[0]));
+        assert_eq!(headers.section(b".rsr"), None);
+        assert_eq!(headers.virtual_bytes(&exe, 0x1004, 8), Some(&[0u8; 8][..]));
+        assert_eq!(headers.virtual_bytes(&exe, 0x2000, 8), None);
+
+        let mut not_mz = exe.clone();
+        not_mz[0] = b'X';
+        assert!(matches!(Headers::parse(&not_mz), Err(Error::InvalidHeader)));
+        let mut not_pe = exe.clone();
+        not_pe[0x81] = b'X';
+        assert!(matches!(Headers::parse(&not_pe), Err(Error::InvalidHeader)));
+        let mut far_pe = exe.clone();
+        far_pe[0x3C..0x40].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(Headers::parse(&far_pe), Err(Error::InvalidHeader)));
+        assert!(matches!(Headers::parse(&exe[..0xA8]), Err(Error::IO(_))));
+        assert!(matches!(Headers::parse(b"MZ"), Err(Error::IO(_))));
+    }
+
+    #[test]
+    fn reads_resources() {
+        let exe = exe(&rsrc());
+        let headers = Headers::parse(&exe).unwrap();
+        let icon =
+            |id, rva, size| Resource { id: Some(id), languages: vec![ResourceData { language: 1033, rva, size }] };
+        assert_eq!(headers.resources(&exe, RT_ICON), Some(vec![icon(1, 0x1200, 40), icon(2, 0x1228, 48)]));
+        assert_eq!(headers.resources(&exe, 5), None);
+        assert_eq!(headers.manifest(&exe), Some(MANIFEST));
+
+        let mut ico = vec![0, 0, 1, 0, 2, 0];
+        ico.extend_from_slice(&[32, 32, 0, 0, 1, 0, 32, 0, 48, 0, 0, 0, 38, 0, 0, 0]);
+        ico.extend_from_slice(&[16, 16, 0, 0, 1, 0, 32, 0, 40, 0, 0, 0, 86, 0, 0, 0]);
+        ico.extend_from_slice(&[2; 48]);
+        ico.extend_from_slice(&[1; 40]);
+        assert_eq!(headers.icon(&exe).unwrap(), Some(ico));
+
+        // no resources at all
+        let exe = self::exe(&[]);
+        let headers = Headers::parse(&exe).unwrap();
+        assert_eq!(headers.icon(&exe).unwrap(), None);
+        assert_eq!(headers.manifest(&exe), None);
+    }
+
+    #[test]
+    fn old_find_icons() {
+        let mut exe = exe(&rsrc());
+        let headers = Headers::parse(&exe).unwrap();
+        let icon = headers.icon(&exe).unwrap();
+        assert!(icon.is_some());
+        let sections = headers.sections.iter().map(PESection::from).collect::<Vec<_>>();
+        let mut data = Cursor::new(exe.as_mut_slice());
+        data.set_position(0x200);
+        assert_eq!(find_icons(&mut data, &sections).unwrap(), icon);
+    }
+
+    #[test]
+    fn bad_resource_trees() {
+        let sub = 0x8000_0000;
+
+        // RT_ICON pointing back at the top of the tree
+        let mut tree = rsrc();
+        put(&mut tree, 0x10, &[RT_ICON as u8, 0, 0, 0, 0, 0, 0, 0x80]);
+        let exe = self::exe(&tree);
+        let headers = Headers::parse(&exe).unwrap();
+        let icons = headers.resources(&exe, RT_ICON).unwrap();
+        let ids = icons.iter().map(|x| x.id).collect::<Vec<_>>();
+        assert_eq!(ids, [Some(RT_ICON), Some(RT_GROUP_ICON), Some(RT_MANIFEST)]);
+        assert!(icons.iter().all(|x| x.languages.is_empty()));
+
+        // an icon whose data entry is past the end of the file, and a group directory which is too
+        let mut tree = rsrc();
+        put(&mut tree, 0xC0, &dir(0, &[(1033, 0xFFFF)]));
+        put(&mut tree, 0x00, &dir(0, &[(RT_ICON, sub | 0x30), (RT_GROUP_ICON, sub | 0xFFFF)]));
+        let exe = self::exe(&tree);
+        let headers = Headers::parse(&exe).unwrap();
+        assert!(headers.resources(&exe, RT_ICON).unwrap()[0].languages.is_empty());
+        assert_eq!(headers.resources(&exe, RT_GROUP_ICON), Some(vec![]));
+        assert_eq!(headers.icon(&exe).unwrap(), None);
+
+        // a directory claiming more entries than there are, and a data entry where a directory should be
+        let mut tree = rsrc();
+        put(&mut tree, 0x0E, &[0xFF, 0xFF]);
+        put(&mut tree, 0x44, &0xC0u32.to_le_bytes());
+        let exe = self::exe(&tree);
+        let headers = Headers::parse(&exe).unwrap();
+        assert_eq!(headers.resources(&exe, RT_ICON).unwrap().iter().map(|x| x.id).collect::<Vec<_>>(), [Some(2)]);
+        assert_eq!(headers.manifest(&exe), Some(MANIFEST));
+    }
+
+    // A version resource block, padded to four bytes. Text values are given without their NUL.
+    fn block(key: &str, value: &[u8], is_text: bool, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut block = vec![0u8; 6];
+        block.extend(key.encode_utf16().chain(Some(0)).flat_map(|x| x.to_le_bytes()));
+        block.resize((block.len() + 3) & !3, 0);
+        block.extend_from_slice(value);
+        if is_text {
+            block.extend_from_slice(&[0, 0]);
+        }
+        for child in children {
+            block.resize((block.len() + 3) & !3, 0);
+            block.extend_from_slice(child);
+        }
+        let value_length = if is_text { value.len() / 2 + 1 } else { value.len() };
+        let length = block.len() as u16;
+        block[0..2].copy_from_slice(&length.to_le_bytes());
+        block[2..4].copy_from_slice(&(value_length as u16).to_le_bytes());
+        block[4..6].copy_from_slice(&u16::from(is_text).to_le_bytes());
+        block.resize((block.len() + 3) & !3, 0);
+        block
+    }
+
+    fn text(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    fn version_info() -> Vec<u8> {
+        let mut fixed = Vec::new();
+        for x in &[0xFEEF04BDu32, 0x10000, 0x0001_0002, 0x0003_0004, 0x0001_0002, 0x0003_0004] {
+            fixed.extend_from_slice(&x.to_le_bytes());
+        }
+        fixed.resize(52, 0);
+        let strings = [
+            block("CompanyName", &text("Someone"), true, &[]),
+            block("ProductName", &text("Some Game"), true, &[]),
+            block("FileVersion", &text("1.2.3.4"), true, &[]),
+            block("Comments", &[], true, &[]),
+        ];
+        let table = block("040904E4", &[], false, &strings);
+        let translation = block("Translation", &[0x09, 0x04, 0xE4, 0x04], false, &[]);
+        block("VS_VERSION_INFO", &fixed, false, &[
+            block("VarFileInfo", &[], true, &[translation]),
+            block("StringFileInfo", &[], true, &[table]),
+        ])
+    }
+
+    #[test]
+    fn reads_version_info() {
+        let info = VersionInfo::parse(&version_info()).unwrap();
+        assert_eq!(info.file_version, Some([1, 2, 3, 4]));
+        assert_eq!(info.string("ProductName"), Some("Some Game"));
+        assert_eq!(info.string("CompanyName"), Some("Someone"));
+        assert_eq!(info.string("FileVersion"), Some("1.2.3.4"));
+        assert_eq!(info.string("Comments"), Some(""));
+        assert_eq!(info.string("LegalCopyright"), None);
+
+        // found through the resource tree, next to the manifest
+        let sub = 0x8000_0000;
+        let mut tree = Vec::new();
+        put(&mut tree, 0x00, &dir(0, &[(RT_VERSION, sub | 0x20)]));
+        put(&mut tree, 0x20, &dir(0, &[(1, sub | 0x40)]));
+        put(&mut tree, 0x40, &dir(0, &[(1033, 0x60)]));
+        put(&mut tree, 0x60, &data_entry(0x100, version_info().len() as u32));
+        put(&mut tree, 0x100, &version_info());
+        let exe = exe(&tree);
+        let headers = Headers::parse(&exe).unwrap();
+        assert_eq!(headers.version_info(&exe).unwrap(), Some(info));
+        let exe = self::exe(&rsrc());
+        assert_eq!(Headers::parse(&exe).unwrap().version_info(&exe).unwrap(), None);
+    }
+
+    #[test]
+    fn bad_version_info() {
+        let data = version_info();
+        // cut short, or not a version resource at all
+        assert!(VersionInfo::parse(&data[..data.len() / 2]).is_err());
+        assert!(VersionInfo::parse(&data[..4]).is_err());
+        assert!(VersionInfo::parse(&block("VS_VERSION_INFX", &[], false, &[])).is_err());
+
+        // no fixed info or strings, and a text length given in bytes rather than characters
+        let info = VersionInfo::parse(&block("VS_VERSION_INFO", &[], false, &[])).unwrap();
+        assert_eq!(info, VersionInfo::default());
+        let mut string = block("ProductName", &text("Game"), true, &[]);
+        string[2] = 10;
+        let table = block("040904E4", &[], false, &[string]);
+        let data = block("VS_VERSION_INFO", &[], false, &[block("StringFileInfo", &[], true, &[table])]);
+        assert_eq!(VersionInfo::parse(&data).unwrap().string("ProductName"), Some("Game"));
+    }
+
+    #[test]
+    fn manifest_dpi_awareness() {
+        let settings = |x: &str| format!("<assembly><asmv3:windowsSettings>{}</asmv3:windowsSettings></assembly>", x);
+        assert!(dpi_aware(MANIFEST));
+        let namespaced = "<dpiAware xmlns=\"http://schemas.microsoft.com/SMI/2005/WindowsSettings\">True/PM</dpiAware>";
+        assert!(dpi_aware(settings(namespaced).as_bytes()));
+        assert!(dpi_aware(settings("<ws2:dpiAwareness>bogus, PerMonitorV2</ws2:dpiAwareness>").as_bytes()));
+        assert!(!dpi_aware(settings("<dpiAware>true</dpiAware><dpiAwareness>unaware</dpiAwareness>").as_bytes()));
+        assert!(!dpi_aware(settings("<dpiAware>false</dpiAware>").as_bytes()));
+        assert!(!dpi_aware(settings("<dpiAware/>").as_bytes()));
+        assert!(!dpi_aware(b"<assembly><dpiAwareness"));
+        assert!(!dpi_aware(b""));
+    }
+}
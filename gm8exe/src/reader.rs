@@ -1,17 +1,20 @@
 use crate::{
     asset::*,
     gamedata::{self, gm80},
-    rsrc,
+    pe,
     settings::{GameHelpDialog, Settings},
-    AssetList, GameAssets, GameVersion,
+    AssetBlobs, AssetKind, AssetList, ExeMetadata, GameAssets, GameVersion,
 };
 use byteorder::{ReadBytesExt, LE};
 use flate2::bufread::ZlibDecoder;
+#[cfg(feature = "multithread")]
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::{
     fmt::{self, Display},
     io::{self, Read, Seek, SeekFrom},
 };
+#[cfg(feature = "multithread")]
+use std::{panic, sync::mpsc, thread};
 
 #[derive(Debug)]
 pub enum ReaderError {
@@ -49,6 +52,15 @@ macro_rules! from_err {
 from_err!(ReaderError, Error, AssetError);
 from_err!(ReaderError, io::Error, IO);
 
+impl From<pe::Error> for ReaderError {
+    fn from(err: pe::Error) -> Self {
+        match err {
+            pe::Error::InvalidHeader => ReaderError::InvalidExeHeader,
+            pe::Error::IO(err) => ReaderError::IO(err),
+        }
+    }
+}
+
 /// Helper function for inflating zlib data.
 pub(crate) fn inflate<I>(data: &I) -> ZlibDecoder<&[u8]>
 where
@@ -59,6 +71,7 @@ where
 
 /// A windows PE Section header
 /// Just read this: https://docs.microsoft.com/en-us/windows/win32/debug/pe-format#section-table-section-headers
+/// This is `pe::Section` without the name, and is kept for code which was written before that existed.
 pub struct PESection {
     pub virtual_size: u32,
     pub virtual_address: u32,
@@ -66,87 +79,103 @@ pub struct PESection {
     pub disk_address: u32,
 }
 
-pub fn from_exe<I, F>(mut exe: I, logger: Option<F>, strict: bool, multithread: bool) -> Result<GameAssets, ReaderError>
+impl From<&pe::Section> for PESection {
+    fn from(section: &pe::Section) -> Self {
+        let pe::Section { virtual_size, virtual_address, disk_size, disk_address, .. } = *section;
+        Self { virtual_size, virtual_address, disk_size, disk_address }
+    }
+}
+
+/// Options for reading a game with `from_exe`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ReaderOptions {
+    /// Fail if any asset has an unexpected version, instead of trying to read it anyway.
+    pub strict: bool,
+
+    /// Decompress and read assets on multiple threads. Without the `multithread` feature this does nothing.
+    pub multithread: bool,
+
+    /// Keep a copy of each asset's compressed data in `GameAssets::blobs`, for identifying games by their content.
+    /// When this is off, nothing is copied.
+    pub keep_blobs: bool,
+}
+
+/// How far the reader has got, as reported to the callback given to `from_exe_with_callback`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReaderProgress {
+    /// Reading the settings, and everything else that comes before the first list of assets.
+    Settings,
+
+    /// `done` out of `total` assets of this kind have been read. This is reported once with `done` as 0 when the
+    /// list is found, and then again after each asset, so on multiple threads they may not finish in order.
+    Assets { kind: AssetKind, done: usize, total: usize },
+
+    /// Reading the help dialog, library initialization code and room order, which are all quick.
+    Finishing,
+}
+
+pub fn from_exe<I, F>(exe: I, logger: Option<F>, options: ReaderOptions) -> Result<GameAssets, ReaderError>
+where
+    F: Copy + Fn(&str),
+    I: AsRef<[u8]> + AsMut<[u8]>,
+{
+    from_exe_with_callback(exe, logger, options, |_| ())
+}
+
+/// Like `from_exe`, but calls `progress` as each section of the gamedata is read, for showing a progress bar.
+/// This is always called on the calling thread, even when reading on multiple threads.
+pub fn from_exe_with_callback<I, F, P>(
+    mut exe: I,
+    logger: Option<F>,
+    options: ReaderOptions,
+    mut progress: P,
+) -> Result<GameAssets, ReaderError>
 where
     F: Copy + Fn(&str),
     I: AsRef<[u8]> + AsMut<[u8]>,
+    P: FnMut(ReaderProgress),
 {
     let exe = exe.as_mut();
 
     // comfy wrapper for byteorder I/O
     let mut exe = io::Cursor::new(exe);
 
-    // verify executable header
-    // Windows EXE must always start with "MZ"
-    if exe.get_ref().get(0..2).unwrap_or(b"XX") != b"MZ" {
+    // verify executable header, which must be for i386
+    let pe = pe::Headers::parse(exe.get_ref())?;
+    if pe.machine != pe::MACHINE_I386 {
         return Err(ReaderError::InvalidExeHeader)
     }
-    // Dword at 0x3C indicates the start of the PE header
-    exe.set_position(0x3C);
-    let pe_header_loc = exe.read_u32::<LE>()? as usize;
-    // PE header must begin with PE\0\0, then 0x14C which means i386.
-    match exe.get_ref().get(pe_header_loc..(pe_header_loc + 6)) {
-        Some(b"PE\0\0\x4C\x01") => (),
-        _ => return Err(ReaderError::InvalidExeHeader),
-    }
-    // Read number of sections
-    exe.set_position((pe_header_loc + 6) as u64);
-    let section_count = exe.read_u16::<LE>()?;
-    // Read length of optional header
-    exe.seek(SeekFrom::Current(12))?;
-    let optional_len = exe.read_u16::<LE>()?;
-    // Skip over PE characteristics (2 bytes) + optional header
-    exe.seek(SeekFrom::Current((optional_len as i64) + 2))?;
-
-    // Read all sections, noting these 3 values from certain sections if they exist
+
+    // Note these 2 values from the UPX sections if they exist
     let mut upx0_virtual_len: Option<u32> = None;
     let mut upx1_data: Option<(u32, u32)> = None; // virtual size, position on disk
-    let mut rsrc_location: Option<u32> = None;
-
-    let mut sections: Vec<PESection> = Vec::with_capacity(section_count as usize);
-
-    for _ in 0..section_count {
-        let mut sect_name = [0u8; 8];
-        exe.read_exact(&mut sect_name)?;
-
-        let virtual_size = exe.read_u32::<LE>()?;
-        let virtual_address = exe.read_u32::<LE>()?;
-        let disk_size = exe.read_u32::<LE>()?;
-        let disk_address = exe.read_u32::<LE>()?;
-        exe.seek(SeekFrom::Current(16))?;
-
-        // See if this is a section we want to do something with
-        match sect_name {
-            [0x55, 0x50, 0x58, 0x30, 0x00, 0x00, 0x00, 0x00] => {
-                // UPX0 section
-                upx0_virtual_len = Some(virtual_size);
-                log!(logger, "UPX0 section found, virtual len: {}", virtual_size);
-            },
-            [0x55, 0x50, 0x58, 0x31, 0x00, 0x00, 0x00, 0x00] => {
-                // UPX1 section
-                upx1_data = Some((virtual_size, disk_address));
-                log!(logger, "UPX1 section found, virtual len: {}", virtual_size);
+
+    for section in &pe.sections {
+        match &section.name {
+            b"UPX0\0\0\0\0" => {
+                upx0_virtual_len = Some(section.virtual_size);
+                log!(logger, "UPX0 section found, virtual len: {}", section.virtual_size);
             },
-            [0x2E, 0x72, 0x73, 0x72, 0x63, 0x00, 0x00, 0x00] => {
-                // .rsrc section
-                log!(logger, "Found .rsrc section beginning at {}", disk_address);
-                rsrc_location = Some(disk_address);
+            b"UPX1\0\0\0\0" => {
+                upx1_data = Some((section.virtual_size, section.disk_address));
+                log!(logger, "UPX1 section found, virtual len: {}", section.virtual_size);
             },
+            b".rsrc\0\0\0" => log!(logger, "Found .rsrc section beginning at {}", section.disk_address),
             _ => {},
         }
-        sections.push(PESection { virtual_size, virtual_address, disk_size, disk_address })
     }
 
-    let ico_file_raw = rsrc_location
-        .map(|x| {
-            let temp_pos = exe.position();
-            exe.set_position(u64::from(x));
-            let ico = rsrc::find_icons(&mut exe, &sections);
-            exe.set_position(temp_pos);
-            ico
-        })
-        .transpose()?
-        .flatten();
+    let ico_file_raw = pe.icon(exe.get_ref())?;
+    let version_info = match pe.version_info(exe.get_ref()) {
+        Ok(version_info) => version_info,
+        Err(e) if !options.strict => {
+            log!(logger, "Ignoring broken version info: {}", e);
+            None
+        },
+        Err(e) => return Err(e.into()),
+    };
+    let manifest = pe.manifest(exe.get_ref()).map(|x| x.to_vec());
+    let exe_metadata = Some(ExeMetadata { version_info, manifest });
 
     // Decide if UPX is in use based on PE section names
     // This is None if there is no UPX, obviously, otherwise it's (max_size, offset_on_disk)
@@ -158,6 +187,54 @@ where
     // Identify the game version in use and locate the gamedata header
     let game_ver = gamedata::find(&mut exe, logger, upx_data)?;
 
+    read_gamedata(exe, game_ver, ico_file_raw, exe_metadata, logger, options, &mut progress)
+}
+
+/// Reads a game from gamedata which has been cut out of its exe (or dumped from memory), so that it starts with the
+/// gamedata header instead of a PE header. There are no resources to read, so `ico_file_raw` and `exe_metadata`
+/// are always None.
+pub fn from_gamedata<I, F>(data: I, logger: Option<F>, options: ReaderOptions) -> Result<GameAssets, ReaderError>
+where
+    F: Copy + Fn(&str),
+    I: AsRef<[u8]> + AsMut<[u8]>,
+{
+    from_gamedata_with_callback(data, logger, options, |_| ())
+}
+
+/// Like `from_gamedata`, but calls `progress` the same way as `from_exe_with_callback`.
+pub fn from_gamedata_with_callback<I, F, P>(
+    mut data: I,
+    logger: Option<F>,
+    options: ReaderOptions,
+    mut progress: P,
+) -> Result<GameAssets, ReaderError>
+where
+    F: Copy + Fn(&str),
+    I: AsRef<[u8]> + AsMut<[u8]>,
+    P: FnMut(ReaderProgress),
+{
+    let mut data = io::Cursor::new(data.as_mut());
+    let game_ver = gamedata::find_standalone(&mut data, logger)?;
+    read_gamedata(data, game_ver, None, None, logger, options, &mut progress)
+}
+
+/// Reads everything after the gamedata header, which `exe` must be positioned just past.
+fn read_gamedata<F>(
+    mut exe: io::Cursor<&mut [u8]>,
+    game_ver: GameVersion,
+    ico_file_raw: Option<Vec<u8>>,
+    exe_metadata: Option<ExeMetadata>,
+    logger: Option<F>,
+    options: ReaderOptions,
+    progress: &mut dyn FnMut(ReaderProgress),
+) -> Result<GameAssets, ReaderError>
+where
+    F: Copy + Fn(&str),
+{
+    let ReaderOptions { strict, multithread, keep_blobs } = options;
+    progress(ReaderProgress::Settings);
+    let start = exe.position() as usize;
+
     // little helper thing
     macro_rules! assert_ver {
         ($name: literal, $expect: expr, $ver: expr) => {{
@@ -433,6 +510,9 @@ where
         src: &mut io::Cursor<&[u8]>,
         deserializer: F,
         multithread: bool,
+        kind: AssetKind,
+        blobs: &mut Option<AssetBlobs>,
+        progress: &mut dyn FnMut(ReaderProgress),
     ) -> Result<AssetList<T>, ReaderError>
     where
         T: Send,
@@ -454,11 +534,44 @@ where
             }
         };
 
+        let refs = get_asset_refs(src)?;
+        if let Some(blobs) = blob_list(blobs, kind) {
+            blobs.extend(refs.iter().map(|&data| Box::from(data)));
+        }
+        let total = refs.len();
+        progress(ReaderProgress::Assets { kind, done: 0, total });
+        #[cfg(feature = "multithread")]
         if multithread {
-            get_asset_refs(src)?.par_iter().copied().map(to_asset).collect::<Result<Vec<_>, ReaderError>>()
-        } else {
-            get_asset_refs(src)?.iter().copied().map(to_asset).collect::<Result<Vec<_>, ReaderError>>()
+            // `progress` can only be called from this thread, so the workers send a message for each asset they finish
+            let (tx, rx) = mpsc::channel();
+            return thread::scope(|s| {
+                let worker = s.spawn(move || {
+                    refs.par_iter()
+                        .copied()
+                        .map(|data| {
+                            let asset = to_asset(data);
+                            let _ = tx.send(());
+                            asset
+                        })
+                        .collect::<Result<Vec<_>, ReaderError>>()
+                });
+                for (i, ()) in rx.iter().enumerate() {
+                    progress(ReaderProgress::Assets { kind, done: i + 1, total });
+                }
+                worker.join().unwrap_or_else(|e| panic::resume_unwind(e))
+            })
         }
+        #[cfg(not(feature = "multithread"))]
+        let _ = multithread;
+        refs.iter()
+            .copied()
+            .enumerate()
+            .map(|(i, data)| {
+                let asset = to_asset(data);
+                progress(ReaderProgress::Assets { kind, done: i + 1, total });
+                asset
+            })
+            .collect::<Result<Vec<_>, ReaderError>>()
     }
 
     #[inline]
@@ -467,11 +580,20 @@ where
         version: GameVersion,
         strict: bool,
         multithread: bool,
+        kind: AssetKind,
+        blobs: &mut Option<AssetBlobs>,
+        progress: &mut dyn FnMut(ReaderProgress),
     ) -> Result<AssetList<T>, ReaderError>
     where
         T: Asset + Send,
     {
-        get_assets(src, |data| <T as Asset>::deserialize_exe(data, version, strict), multithread)
+        let deserializer = |data: ZlibDecoder<&[u8]>| <T as Asset>::deserialize_exe(data, version, strict);
+        get_assets(src, deserializer, multithread, kind, blobs, progress)
+    }
+
+    let mut blobs: Option<AssetBlobs> = if keep_blobs { Some(Default::default()) } else { None };
+    fn blob_list(blobs: &mut Option<AssetBlobs>, kind: AssetKind) -> Option<&mut Vec<Box<[u8]>>> {
+        blobs.as_mut().map(|x| &mut x[kind as usize])
     }
 
     assert_ver!("extensions header", 700, exe.read_u32::<LE>()?)?;
@@ -490,7 +612,8 @@ where
 
     // Triggers
     assert_ver!("triggers header", 800, exe.read_u32::<LE>()?)?;
-    let triggers: AssetList<Trigger> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let triggers: AssetList<Trigger> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Trigger, &mut blobs, progress)?;
     if logger.is_some() {
         triggers.iter().flatten().for_each(|trigger| {
             log!(
@@ -516,7 +639,8 @@ where
 
     // Sounds
     assert_ver!("sounds header", 800, exe.read_u32::<LE>()?)?;
-    let sounds: AssetList<Sound> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let sounds: AssetList<Sound> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Sound, &mut blobs, progress)?;
     if logger.is_some() {
         sounds.iter().flatten().for_each(|sound| {
             log!(logger, " + Added sound '{}' ({})", sound.name, sound.source);
@@ -525,7 +649,8 @@ where
 
     // Sprites
     assert_ver!("sprites header", 800, exe.read_u32::<LE>()?)?;
-    let sprites: AssetList<Sprite> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let sprites: AssetList<Sprite> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Sprite, &mut blobs, progress)?;
     if logger.is_some() {
         sprites.iter().flatten().for_each(|sprite| {
             let framecount = sprite.frames.len();
@@ -547,7 +672,8 @@ where
 
     // Backgrounds
     assert_ver!("backgrounds header", 800, exe.read_u32::<LE>()?)?;
-    let backgrounds: AssetList<Background> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let backgrounds: AssetList<Background> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Background, &mut blobs, progress)?;
     if logger.is_some() {
         backgrounds.iter().flatten().for_each(|background| {
             log!(logger, " + Added background '{}' ({}x{})", background.name, background.width, background.height);
@@ -556,7 +682,8 @@ where
 
     // Paths
     assert_ver!("paths header", 800, exe.read_u32::<LE>()?)?;
-    let paths: AssetList<Path> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let paths: AssetList<Path> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Path, &mut blobs, progress)?;
     if logger.is_some() {
         use crate::asset::path::ConnectionKind;
 
@@ -579,7 +706,8 @@ where
 
     // Scripts
     assert_ver!("scripts header", 800, exe.read_u32::<LE>()?)?;
-    let scripts: AssetList<Script> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let scripts: AssetList<Script> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Script, &mut blobs, progress)?;
     if logger.is_some() {
         scripts.iter().flatten().for_each(|script| {
             log!(logger, " + Added script '{}'", script.name);
@@ -588,7 +716,8 @@ where
 
     // Fonts
     assert_ver!("fonts header", 800, exe.read_u32::<LE>()?)?;
-    let fonts: AssetList<Font> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let fonts: AssetList<Font> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Font, &mut blobs, progress)?;
     if logger.is_some() {
         fonts.iter().flatten().for_each(|font| {
             log!(
@@ -605,7 +734,8 @@ where
 
     // Timelines
     assert_ver!("timelines header", 800, exe.read_u32::<LE>()?)?;
-    let timelines: AssetList<Timeline> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let timelines: AssetList<Timeline> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Timeline, &mut blobs, progress)?;
     if logger.is_some() {
         timelines.iter().flatten().for_each(|timeline| {
             log!(logger, " + Added timeline '{}' (moments: {})", timeline.name, timeline.moments.len());
@@ -614,7 +744,8 @@ where
 
     // Objects
     assert_ver!("objects header", 800, exe.read_u32::<LE>()?)?;
-    let objects: AssetList<Object> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let objects: AssetList<Object> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Object, &mut blobs, progress)?;
     if logger.is_some() {
         objects.iter().flatten().for_each(|object| {
             log!(
@@ -631,7 +762,8 @@ where
 
     // Rooms
     assert_ver!("rooms header", 800, exe.read_u32::<LE>()?)?;
-    let rooms: AssetList<Room> = get_assets_ex(&mut exe, game_ver, strict, multithread)?;
+    let rooms: AssetList<Room> =
+        get_assets_ex(&mut exe, game_ver, strict, multithread, AssetKind::Room, &mut blobs, progress)?;
     if logger.is_some() {
         rooms.iter().flatten().for_each(|room| {
             log!(
@@ -652,12 +784,21 @@ where
     // Included Files
     assert_ver!("included files header", 800, exe.read_u32::<LE>()?)?;
     // TODO: how was this different from the others? why is it not using get_assets?
-    let included_files = get_asset_refs(&mut exe)?
+    let included_file_refs = get_asset_refs(&mut exe)?;
+    if let Some(blobs) = blob_list(&mut blobs, AssetKind::IncludedFile) {
+        blobs.extend(included_file_refs.iter().map(|&data| Box::from(data)));
+    }
+    let total = included_file_refs.len();
+    progress(ReaderProgress::Assets { kind: AssetKind::IncludedFile, done: 0, total });
+    let included_files = included_file_refs
         .iter()
-        .map(|chunk| {
+        .enumerate()
+        .map(|(i, chunk)| {
             // AssetDataError -> ReaderError
             let data = inflate(chunk);
-            IncludedFile::deserialize_exe(data, game_ver, strict).map_err(ReaderError::from)
+            let file = IncludedFile::deserialize_exe(data, game_ver, strict).map_err(ReaderError::from);
+            progress(ReaderProgress::Assets { kind: AssetKind::IncludedFile, done: i + 1, total });
+            file
         })
         .collect::<Result<Vec<_>, _>>()?;
     if logger.is_some() {
@@ -679,6 +820,7 @@ where
     }
 
     // Help Dialog
+    progress(ReaderProgress::Finishing);
     assert_ver!("help dialog", 800, exe.read_u32::<LE>()?)?;
     let help_dialog = {
         let len = exe.read_u32::<LE>()? as usize;
@@ -725,6 +867,15 @@ where
         room_order
     };
 
+    // the room order is the last thing GameMaker writes, so anything after it was added by something else
+    let end = exe.position() as usize;
+    let trailing_data = exe.get_ref().get(end..).filter(|x| !x.is_empty()).map(Box::<[u8]>::from);
+    if let Some(data) = &trailing_data {
+        log!(logger, " + Found {} bytes of trailing data after the gamedata", data.len());
+    }
+    let gamedata_hash = fnv1a(&exe.get_ref()[start..end]);
+    log!(logger, " + Gamedata hash: {:016x}", gamedata_hash);
+
     Ok(GameAssets {
         extensions,
         sprites,
@@ -742,15 +893,25 @@ where
 
         dx_dll,
         ico_file_raw,
+        exe_metadata,
         version: game_ver,
         help_dialog,
         last_instance_id,
         last_tile_id,
         library_init_strings,
         room_order,
+        trailing_data,
 
         settings,
         game_id,
         guid,
+        gamedata_hash,
+
+        blobs,
     })
 }
+
+/// 64-bit FNV-1a, which is quick and needs no dependencies. It only has to tell games apart, not resist tampering.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF29CE484222325, |hash, b| (hash ^ u64::from(*b)).wrapping_mul(0x100000001B3))
+}
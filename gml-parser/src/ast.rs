@@ -1,5 +1,5 @@
 use crate::{
-    lexer::Lexer,
+    lexer::{line_at, Lexer},
     token::{Keyword, Operator, Separator, Token},
 };
 
@@ -120,11 +120,20 @@ pub struct WhileExpr<'a> {
 #[derive(Debug)]
 pub struct Error {
     pub message: String,
+
+    /// The line the error was found on, starting from 1, if known.
+    pub line: Option<usize>,
 }
 
 impl Error {
     pub fn new(message: String) -> Self {
-        Error { message }
+        Error { message, line: None }
+    }
+
+    /// Sets the line the error was found on, unless it's already known.
+    fn at_line(mut self, line: usize) -> Self {
+        self.line.get_or_insert(line);
+        self
     }
 }
 
@@ -251,7 +260,9 @@ impl<'a> IntoIterator for AST<'a> {
 
 impl<'a> AST<'a> {
     pub fn new(source: &'a [u8]) -> Result<Self, Error> {
-        let mut lex = Lexer::new(source).peekable();
+        let lexer = Lexer::new(source);
+        let furthest = lexer.furthest();
+        let mut lex = lexer.peekable();
         let mut expressions = Vec::new();
 
         loop {
@@ -259,7 +270,7 @@ impl<'a> AST<'a> {
             match AST::read_line(&mut lex) {
                 Ok(Some(expr)) => expressions.push(expr),
                 Ok(None) => break,
-                Err(e) => return Err(e),
+                Err(e) => return Err(e.at_line(line_at(source, furthest.get()))),
             }
         }
 
@@ -267,8 +278,15 @@ impl<'a> AST<'a> {
     }
 
     pub fn expression(source: &'a [u8]) -> Result<Expr<'a>, Error> {
-        let mut lex = Lexer::new(source).peekable();
-        if lex.peek().is_some() { AST::read_binary_tree(&mut lex, None, false) } else { Ok(Expr::LiteralReal(0.0)) }
+        let lexer = Lexer::new(source);
+        let furthest = lexer.furthest();
+        let mut lex = lexer.peekable();
+        let result = if lex.peek().is_some() {
+            AST::read_binary_tree(&mut lex, None, false)
+        } else {
+            Ok(Expr::LiteralReal(0.0))
+        };
+        result.map_err(|e| e.at_line(line_at(source, furthest.get())))
     }
 
     fn read_line(lex: &mut Peekable<Lexer<'a>>) -> Result<Option<Expr<'a>>, Error> {
@@ -505,9 +523,11 @@ impl<'a> AST<'a> {
             _ => return Err(Error::new(format!("Invalid token at beginning of expression: {:?}", token))),
         };
 
-        // skip over trailing semicolons
-        while lex.peek() == Some(&Token::Separator(Separator::Semicolon)) {
-            lex.next();
+        // skip over trailing semicolons, unless there's an error - reading past it would move where it's reported
+        if ret.is_ok() {
+            while lex.peek() == Some(&Token::Separator(Separator::Semicolon)) {
+                lex.next();
+            }
         }
 
         ret
@@ -801,6 +821,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn error_lines() {
+        let line = |src: &str| AST::new(src.as_bytes()).unwrap_err().line;
+        assert_eq!(line("a = );"), Some(1));
+        assert_eq!(line("a = 1;\r\n\r\nb = );\r\nc = 2;"), Some(3));
+        assert_eq!(line("if (a) {\r\n    b = );\r\n}"), Some(2));
+        assert_eq!(line("/* comment\r\n */ var 1;"), Some(2));
+        assert_eq!(AST::expression(b"1 +\r\n)").unwrap_err().line, Some(2));
+    }
+
     #[test]
     fn nothing() {
         // Empty string
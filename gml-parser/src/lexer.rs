@@ -1,7 +1,9 @@
 use crate::token::{Keyword, Operator, Separator, Token};
 
 use std::{
+    cell::Cell,
     iter::{Copied, Enumerate, Peekable},
+    rc::Rc,
     slice,
     slice::SliceIndex,
     str, u64,
@@ -12,7 +14,8 @@ pub struct Lexer<'a> {
     /// GML source code to return references to.
     src: &'a [u8],
 
-    line_hint: usize,
+    /// Where the furthest token read so far started in the source code, shared with any clones.
+    furthest: Rc<Cell<usize>>,
 
     /// Iterator over the source code as raw bytes.
     iter: Peekable<Enumerate<Copied<slice::Iter<'a, u8>>>>,
@@ -21,38 +24,42 @@ pub struct Lexer<'a> {
 impl<'a> Lexer<'a> {
     /// Creates a new Lexer over GML source code.
     pub fn new(src: &'a [u8]) -> Self {
-        Lexer { src, line_hint: 1, iter: src.iter().copied().enumerate().peekable() }
+        Lexer { src, furthest: Rc::default(), iter: src.iter().copied().enumerate().peekable() }
     }
 
-    /// Returns the current line number in the source code.
+    /// Returns the line number, starting from 1, of the furthest token read by this Lexer or any of its clones.
     pub fn line(&self) -> usize {
-        self.line_hint
+        line_at(self.src, self.furthest.get())
+    }
+
+    /// Returns a handle to where the furthest token read so far started, which stays shared with any clones.
+    /// The parser only looks ahead when it has to, so this is the best guess at where a syntax error is.
+    pub fn furthest(&self) -> Rc<Cell<usize>> {
+        Rc::clone(&self.furthest)
     }
 
     /// Fast-forwards the internal iterator to the next token, skipping over whitespace.
-    /// Returns how many lines (LF) were skipped in the process.
-    fn fast_forward(&mut self) -> usize {
-        let mut lines_skipped: usize = 0;
-        loop {
-            match self.iter.peek() {
-                Some(&(_, ch)) if ch <= b' ' => {
-                    if ch == b'\n' {
-                        lines_skipped += 1;
-                    }
-                    self.iter.next();
-                },
-                _ => break lines_skipped,
+    fn fast_forward(&mut self) {
+        while let Some(&(_, ch)) = self.iter.peek() {
+            if ch > b' ' {
+                break
             }
+            self.iter.next();
         }
     }
 }
 
+/// Returns the line number, starting from 1, of a position in some source code.
+pub fn line_at(src: &[u8], pos: usize) -> usize {
+    src[..pos].iter().filter(|&&c| c == b'\n').count() + 1
+}
+
 impl<'a> Iterator for Lexer<'a> {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // locate next token
-        self.line_hint = self.fast_forward();
+        self.fast_forward();
 
         /// Helper function to reconstruct our byte slices to a string easily.
         /// This is fine since we operate on something that is a &str in a first place,
@@ -63,6 +70,7 @@ impl<'a> Iterator for Lexer<'a> {
         }
 
         let head = *self.iter.peek()?;
+        self.furthest.set(self.furthest.get().max(head.0));
 
         #[allow(clippy::match_overlapping_arm)] // quotes overlap with the catch-all ASCII
         Some(match head.1 {